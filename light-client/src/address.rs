@@ -0,0 +1,32 @@
+//! Recipient addresses decoded per chain, so a payment is matched on what
+//! an address means rather than how it is spelled: an ETH address by its
+//! 20 bytes whatever the hex case, a BTC address by the output script it
+//! pays, a SOL address by its 32-byte key. A malformed address is
+//! `InvalidAddress` rather than a mismatch.
+
+use crate::btc_tx;
+use crate::*;
+
+/// `0x` followed by 40 hex digits in any case (EIP-55 checksums are not
+/// enforced, only the bytes compared).
+pub(crate) fn eth(address: &str) -> Result<[u8; 20], VerifyError> {
+    let digits = address.strip_prefix("0x").ok_or(VerifyError::InvalidAddress)?;
+    if digits.len() != 40 {
+        return Err(VerifyError::InvalidAddress);
+    }
+    let bytes = hex::decode(digits).map_err(|_| VerifyError::InvalidAddress)?;
+    bytes.try_into().map_err(|_| VerifyError::InvalidAddress)
+}
+
+/// The output script of a base58check P2PKH/P2SH or bech32/bech32m
+/// address.
+pub(crate) fn btc(address: &str) -> Result<Vec<u8>, VerifyError> {
+    btc_tx::address_script(address).ok_or(VerifyError::InvalidAddress)
+}
+
+/// A base58 ed25519 public key.
+pub(crate) fn sol(address: &str) -> Result<[u8; 32], VerifyError> {
+    btc_tx::base58_decode(address)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(VerifyError::InvalidAddress)
+}
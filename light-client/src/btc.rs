@@ -0,0 +1,436 @@
+//! Bitcoin SPV. The owner anchors the header chain at a checkpoint on a
+//! difficulty-period boundary; from there anyone extends it with raw
+//! 80-byte headers, each checked for:
+//!
+//! - linkage: `prev_blockhash` is the previous header's hash;
+//! - difficulty: `bits` equals the previous header's, except on a
+//!   2016-block boundary, where it must be the mainnet retarget of the
+//!   period that just ended;
+//! - proof-of-work: the double-SHA256 hash is at most the `bits` target.
+//!
+//! Every stored header carries the chain work accumulated since the
+//! checkpoint, and a batch that forks off the stored chain replaces it
+//! only if it ends with more work than the current tip. The tip is the
+//! BTC finalized height; depth on top of it is the caller's confirmation
+//! requirement.
+//!
+//! A payment proof is the raw transaction, its position in the block and
+//! its Merkle branch. The transaction must hash to the expected txid and
+//! fold up to the stored merkle root at the claimed height; only then are
+//! its outputs read: one paying the recipient's script exactly the
+//! amount in sats, and an `OP_RETURN` output carrying the memo.
+
+use crate::btc_tx;
+use crate::eth::parse_hex;
+use crate::finality::log_rollback;
+use crate::u256::U256;
+use crate::*;
+
+/// Blocks per difficulty period.
+pub const RETARGET_INTERVAL: u64 = 2016;
+/// Two weeks, the time a period is meant to take.
+const TARGET_TIMESPAN: u64 = 14 * 24 * 60 * 60;
+/// Headers accepted by one `submit_btc_headers` call.
+pub const MAX_HEADERS_PER_CALL: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct BtcHeader {
+    /// Double-SHA256 of the header, in internal (little-endian) order.
+    pub hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    pub bits: u32,
+    /// Work of this header and its ancestors down to the checkpoint.
+    pub chain_work: u128,
+}
+
+/// Heights of the checkpoint and of the best stored header.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct BtcChain {
+    pub anchor_height: u64,
+    pub tip_height: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BtcTip {
+    pub height: u64,
+    /// Block hash as block explorers show it (byte-reversed).
+    pub hash: String,
+    /// Work since the checkpoint.
+    pub chain_work: U128,
+}
+
+/// A BTC payment or transition proof in the JSON format. Byte fields are
+/// hex; branch hashes are in internal order, as they are hashed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BtcPaymentProof {
+    /// Raw transaction, with or without witness data.
+    pub tx: String,
+    /// Position of the transaction in its block.
+    pub tx_index: u32,
+    /// Sibling hashes from the transaction up to the merkle root.
+    pub merkle_branch: Vec<String>,
+    pub block_height: u64,
+}
+
+/// A BTC payment proof in the binary format (`codec.rs`);
+/// `BtcPaymentProof` decodes to this.
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub struct BtcTxProof {
+    /// Raw transaction, with or without witness data.
+    pub tx: Vec<u8>,
+    /// Position of the transaction in its block.
+    pub tx_index: u32,
+    /// Sibling hashes from the transaction up to the merkle root.
+    pub merkle_branch: Vec<[u8; 32]>,
+    pub block_height: u64,
+}
+
+impl BtcPaymentProof {
+    /// The binary form, `None` if a hex field does not decode.
+    pub(crate) fn decode(self) -> Option<BtcTxProof> {
+        Some(BtcTxProof {
+            tx: parse_hex(&self.tx)?,
+            tx_index: self.tx_index,
+            merkle_branch: self
+                .merkle_branch
+                .iter()
+                .map(|node| parse_hex(node).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()))
+                .collect::<Option<_>>()?,
+            block_height: self.block_height,
+        })
+    }
+}
+
+/// Why a BTC proof was rejected, with the block height it claims (0 if it
+/// could not be read).
+#[derive(Debug, PartialEq)]
+pub(crate) struct BtcProofError {
+    pub block_height: u64,
+    pub reason: VerifyError,
+}
+
+struct RawHeader {
+    hash: [u8; 32],
+    prev_blockhash: [u8; 32],
+    merkle_root: [u8; 32],
+    time: u32,
+    bits: u32,
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Owner anchors the header chain at a trusted block. The height must
+    /// start a difficulty period, so every later retarget can be checked,
+    /// and must be above the current tip, which leaves the previous chain
+    /// unreachable.
+    pub fn set_btc_checkpoint(&mut self, height: u64, header: String) {
+        self.assert_owner();
+        assert!(
+            height.is_multiple_of(RETARGET_INTERVAL),
+            "Checkpoint must start a difficulty period"
+        );
+        if let Some(chain) = &self.btc_chain {
+            assert!(height > chain.tip_height, "Checkpoint must be above the current tip");
+        }
+        let raw = parse_hex(&header)
+            .and_then(|bytes| parse_header(&bytes))
+            .unwrap_or_else(|| env::panic_str("Header must be 80 bytes of hex"));
+        assert!(meets_target(&raw.hash, raw.bits), "Checkpoint does not meet its proof-of-work target");
+        let header = BtcHeader {
+            hash: raw.hash,
+            merkle_root: raw.merkle_root,
+            time: raw.time,
+            bits: raw.bits,
+            chain_work: block_work(raw.bits),
+        };
+        self.store_btc_header(height, &header);
+        self.set_btc_tip(BtcChain { anchor_height: height, tip_height: height });
+    }
+
+    /// Extend (or reorganize) the stored chain with consecutive raw
+    /// headers, the first building on any stored header at or above the
+    /// checkpoint. Returns the new tip height.
+    pub fn submit_btc_headers(&mut self, headers: Vec<String>) -> u64 {
+        let chain = self
+            .btc_chain
+            .clone()
+            .unwrap_or_else(|| env::panic_str("BTC checkpoint is not set"));
+        assert!(
+            !headers.is_empty() && headers.len() <= MAX_HEADERS_PER_CALL,
+            "Submit between 1 and {} headers",
+            MAX_HEADERS_PER_CALL
+        );
+        let raw: Vec<RawHeader> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                parse_hex(header)
+                    .and_then(|bytes| parse_header(&bytes))
+                    .unwrap_or_else(|| env::panic_str(&format!("Header {} is not 80 bytes of hex", i)))
+            })
+            .collect();
+
+        let parent_height = self
+            .btc_heights
+            .get(&raw[0].prev_blockhash)
+            .filter(|height| *height >= chain.anchor_height && *height <= chain.tip_height)
+            .unwrap_or_else(|| env::panic_str("First header does not build on a stored header"));
+        let mut parent = self.btc_headers.get(&parent_height).unwrap();
+        let mut added: Vec<BtcHeader> = Vec::with_capacity(raw.len());
+        for (i, header) in raw.iter().enumerate() {
+            let height = parent_height + 1 + i as u64;
+            assert!(
+                header.prev_blockhash == parent.hash,
+                "Header at height {} does not link to its parent",
+                height
+            );
+            let expected_bits = if height.is_multiple_of(RETARGET_INTERVAL) {
+                let first = height - RETARGET_INTERVAL;
+                let first_time = if first > parent_height {
+                    added[(first - parent_height - 1) as usize].time
+                } else {
+                    self.btc_headers.get(&first).unwrap().time
+                };
+                next_bits(parent.bits, first_time, parent.time)
+            } else {
+                parent.bits
+            };
+            assert!(
+                header.bits == expected_bits,
+                "Header at height {} has bits {:#010x}, expected {:#010x}",
+                height,
+                header.bits,
+                expected_bits
+            );
+            assert!(
+                meets_target(&header.hash, header.bits),
+                "Header at height {} does not meet its proof-of-work target",
+                height
+            );
+            parent = BtcHeader {
+                hash: header.hash,
+                merkle_root: header.merkle_root,
+                time: header.time,
+                bits: header.bits,
+                chain_work: parent.chain_work.saturating_add(block_work(header.bits)),
+            };
+            added.push(parent.clone());
+        }
+
+        let tip = self.btc_headers.get(&chain.tip_height).unwrap();
+        assert!(parent.chain_work > tip.chain_work, "Headers do not add work to the best chain");
+        let new_tip = parent_height + added.len() as u64;
+        for (i, header) in added.iter().enumerate() {
+            self.store_btc_header(parent_height + 1 + i as u64, header);
+        }
+        for height in new_tip + 1..=chain.tip_height {
+            if let Some(stale) = self.btc_headers.get(&height) {
+                self.btc_headers.remove(&height);
+                self.btc_heights.remove(&stale.hash);
+            }
+        }
+        if parent_height < chain.tip_height {
+            log_rollback(&chain_key(&ChainType::BTC), chain.tip_height, parent_height, "header reorg");
+        }
+        self.set_btc_tip(BtcChain { anchor_height: chain.anchor_height, tip_height: new_tip });
+        new_tip
+    }
+
+    pub fn get_btc_tip(&self) -> Option<BtcTip> {
+        let chain = self.btc_chain.as_ref()?;
+        let header = self.btc_headers.get(&chain.tip_height)?;
+        Some(BtcTip {
+            height: chain.tip_height,
+            hash: display_hash(&header.hash),
+            chain_work: U128(header.chain_work),
+        })
+    }
+
+    /// Hash of the best-chain block at `height`, as block explorers show it.
+    pub fn get_btc_block_hash(&self, height: u64) -> Option<String> {
+        let chain = self.btc_chain.as_ref()?;
+        if height < chain.anchor_height || height > chain.tip_height {
+            return None;
+        }
+        self.btc_headers.get(&height).map(|header| display_hash(&header.hash))
+    }
+}
+
+impl LightClient {
+    fn store_btc_header(&mut self, height: u64, header: &BtcHeader) {
+        if let Some(replaced) = self.btc_headers.insert(&height, header) {
+            self.btc_heights.remove(&replaced.hash);
+        }
+        self.btc_heights.insert(&header.hash, &height);
+    }
+
+    fn set_btc_tip(&mut self, chain: BtcChain) {
+        let tip = self.btc_headers.get(&chain.tip_height).unwrap();
+        self.finalized.insert(
+            &chain_key(&ChainType::BTC),
+            &FinalizedBlock { height: chain.tip_height, block_hash: display_hash(&tip.hash) },
+        );
+        env::log_str(&format!("BTC tip is now height {}", chain.tip_height));
+        self.btc_chain = Some(chain);
+    }
+
+    /// `verify_btc_payment` plus the confirmations check, logging why a
+    /// proof was rejected. Stored blocks are all at or below the tip, so
+    /// with the default of one confirmation any stored block passes.
+    pub(crate) fn btc_verdict(&self, proof: &BtcTxProof, expected: &ExpectedPayment) -> VerificationResult {
+        let finalized_height = self.get_finalized_height(ChainType::BTC);
+        let (result, block_height) = match self.verify_btc_payment(proof, expected) {
+            Ok(block_height) if !self.has_confirmations(&ChainType::BTC, block_height, finalized_height) => {
+                let error = VerifyError::NotFinalized { proof_height: block_height, finalized: finalized_height };
+                (Err(error), block_height)
+            }
+            Ok(block_height) => (self.check_proof_age(&ChainType::BTC, block_height, finalized_height), block_height),
+            Err(error) => (Err(error.reason), error.block_height),
+        };
+        if let Err(error) = result {
+            env::log_str(&format!("BTC proof rejected: {}", error));
+        }
+        VerificationResult { outcome: result.into(), block_height, finalized_height }
+    }
+
+    /// Check a `BtcTxProof` against `expected`. Returns the proven block
+    /// height.
+    pub(crate) fn verify_btc_payment(
+        &self,
+        proof: &BtcTxProof,
+        expected: &ExpectedPayment,
+    ) -> Result<u64, BtcProofError> {
+        let fail = |reason: VerifyError| BtcProofError { block_height: proof.block_height, reason };
+        self.check_checkpoint(&ChainType::BTC, proof.block_height).map_err(fail)?;
+
+        // BTC has no registered tokens.
+        if !expected.asset.eq_ignore_ascii_case("BTC") {
+            return Err(fail(VerifyError::UnregisteredToken));
+        }
+        let chain = self.btc_chain.as_ref().ok_or(fail(VerifyError::UnknownBlock))?;
+        if proof.block_height < chain.anchor_height || proof.block_height > chain.tip_height {
+            return Err(fail(VerifyError::UnknownBlock));
+        }
+        let block = self.btc_headers.get(&proof.block_height).ok_or(fail(VerifyError::UnknownBlock))?;
+        if self
+            .stored_header_hash(&ChainType::BTC, proof.block_height)
+            .is_some_and(|hash| hash != display_hash(&block.hash))
+        {
+            return Err(fail(VerifyError::HeaderMismatch));
+        }
+
+        // A 64-byte transaction could pass for an inner Merkle node.
+        if proof.tx.len() == 64 {
+            return Err(fail(VerifyError::InclusionProofInvalid));
+        }
+        let tx = btc_tx::decode(&proof.tx).ok_or(fail(VerifyError::DeserializeFailed))?;
+        let mut expected_txid = parse_hex(expected.tx_hash).ok_or(fail(VerifyError::TxHashMismatch))?;
+        expected_txid.reverse();
+        if tx.txid.as_slice() != expected_txid.as_slice() {
+            return Err(fail(VerifyError::TxHashMismatch));
+        }
+        if merkle_root(tx.txid, proof.tx_index, &proof.merkle_branch) != Some(block.merkle_root) {
+            return Err(fail(VerifyError::InclusionProofInvalid));
+        }
+
+        let script = address::btc(expected.recipient).map_err(fail)?;
+        let mut paid = tx.outputs.iter().filter(|output| output.script == script).peekable();
+        if paid.peek().is_none() {
+            return Err(fail(VerifyError::RecipientMismatch));
+        }
+        if !paid.any(|output| output.value as u128 == expected.amount) {
+            return Err(fail(VerifyError::AmountMismatch));
+        }
+        if !tx
+            .outputs
+            .iter()
+            .any(|output| btc_tx::op_return_data(&output.script) == Some(expected.memo.as_bytes()))
+        {
+            return Err(fail(VerifyError::MemoMismatch));
+        }
+        Ok(proof.block_height)
+    }
+}
+
+pub(crate) fn sha256d(bytes: &[u8]) -> [u8; 32] {
+    env::sha256_array(env::sha256_array(bytes))
+}
+
+fn display_hash(hash: &[u8; 32]) -> String {
+    let mut reversed = *hash;
+    reversed.reverse();
+    hex::encode(reversed)
+}
+
+/// Display-order hash of a raw 80-byte header.
+pub(crate) fn header_display_hash(bytes: &[u8]) -> Option<String> {
+    parse_header(bytes).map(|raw| display_hash(&raw.hash))
+}
+
+fn parse_header(bytes: &[u8]) -> Option<RawHeader> {
+    if bytes.len() != 80 {
+        return None;
+    }
+    let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    Some(RawHeader {
+        hash: sha256d(bytes),
+        prev_blockhash: bytes[4..36].try_into().unwrap(),
+        merkle_root: bytes[36..68].try_into().unwrap(),
+        time: word(68),
+        bits: word(72),
+    })
+}
+
+pub(crate) fn meets_target(hash: &[u8; 32], bits: u32) -> bool {
+    match U256::from_compact(bits) {
+        Some(target) if target != U256::ZERO => U256::from_le_bytes(hash) <= target,
+        _ => false,
+    }
+}
+
+/// Expected work to find a header at `bits`: `2^256 / (target + 1)`.
+pub(crate) fn block_work(bits: u32) -> u128 {
+    let Some(target) = U256::from_compact(bits).filter(|target| *target != U256::ZERO) else {
+        return 0;
+    };
+    let work = target.not().div(&target.add(&U256::from_u64(1))).add(&U256::from_u64(1));
+    work.to_u128().unwrap_or(u128::MAX)
+}
+
+/// Mainnet retarget: the last period's target scaled by how long it took
+/// against two weeks, the factor clamped to [1/4, 4] and the result to
+/// the proof-of-work limit.
+pub(crate) fn next_bits(bits: u32, first_time: u32, last_time: u32) -> u32 {
+    let timespan = (last_time as i64 - first_time as i64)
+        .clamp(TARGET_TIMESPAN as i64 / 4, TARGET_TIMESPAN as i64 * 4) as u64;
+    let pow_limit = U256::MAX.shr(32);
+    let target = U256::from_compact(bits)
+        .and_then(|target| target.checked_mul_u64(timespan))
+        .map(|scaled| scaled.div_u64(TARGET_TIMESPAN))
+        .unwrap_or(pow_limit);
+    target.min(pow_limit).to_compact()
+}
+
+/// Fold `txid` up a Merkle branch; `index` picks the side at each level
+/// and must not point past the branch.
+pub(crate) fn merkle_root(txid: [u8; 32], index: u32, branch: &[[u8; 32]]) -> Option<[u8; 32]> {
+    if branch.len() > 32 || index.checked_shr(branch.len() as u32).unwrap_or(0) != 0 {
+        return None;
+    }
+    let mut node = txid;
+    for (level, sibling) in branch.iter().enumerate() {
+        let mut pair = [0u8; 64];
+        if index >> level & 1 == 1 {
+            pair[..32].copy_from_slice(sibling);
+            pair[32..].copy_from_slice(&node);
+        } else {
+            pair[..32].copy_from_slice(&node);
+            pair[32..].copy_from_slice(sibling);
+        }
+        node = sha256d(&pair);
+    }
+    Some(node)
+}
@@ -0,0 +1,231 @@
+//! Bitcoin transaction decoding and the output scripts of addresses.
+//! Transactions may carry witness data; the txid is computed without it.
+//! Addresses: base58check P2PKH/P2SH and bech32/bech32m segwit, mainnet
+//! and testnet.
+
+use crate::btc::sha256d;
+
+pub(crate) struct Transaction {
+    /// In internal order, as Merkle branches hash it.
+    pub txid: [u8; 32],
+    pub outputs: Vec<Output>,
+}
+
+pub(crate) struct Output {
+    /// Sats.
+    pub value: u64,
+    pub script: Vec<u8>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let slice = self.bytes.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn int(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.take(len)?;
+        Some(bytes.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        match self.int(1)? {
+            0xfd => self.int(2),
+            0xfe => self.int(4),
+            0xff => self.int(8),
+            small => Some(small),
+        }
+    }
+
+    fn var_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.varint()?).ok()?;
+        self.take(len)
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Option<Transaction> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let version = reader.take(4)?;
+    let segwit = bytes.get(4..6) == Some(&[0x00, 0x01]);
+    if segwit {
+        reader.take(2)?;
+    }
+    let body_start = reader.pos;
+    let inputs = reader.varint()?;
+    if inputs == 0 {
+        return None;
+    }
+    for _ in 0..inputs {
+        reader.take(36)?;
+        reader.var_bytes()?;
+        reader.take(4)?;
+    }
+    let mut outputs = Vec::new();
+    for _ in 0..reader.varint()? {
+        let value = reader.int(8)?;
+        let script = reader.var_bytes()?.to_vec();
+        outputs.push(Output { value, script });
+    }
+    let body_end = reader.pos;
+    if segwit {
+        for _ in 0..inputs {
+            for _ in 0..reader.varint()? {
+                reader.var_bytes()?;
+            }
+        }
+    }
+    let lock_time = reader.take(4)?;
+    if reader.pos != bytes.len() {
+        return None;
+    }
+    let stripped = [version, &bytes[body_start..body_end], lock_time].concat();
+    Some(Transaction { txid: sha256d(&stripped), outputs })
+}
+
+/// Data of an `OP_RETURN <push>` script.
+pub(crate) fn op_return_data(script: &[u8]) -> Option<&[u8]> {
+    let (&op, rest) = script.split_first()?;
+    if op != 0x6a {
+        return None;
+    }
+    let (&push, rest) = rest.split_first()?;
+    let (len, data) = match push {
+        0x01..=0x4b => (push as usize, rest),
+        0x4c => (*rest.first()? as usize, &rest[1..]),
+        0x4d => (u16::from_le_bytes(rest.get(..2)?.try_into().ok()?) as usize, &rest[2..]),
+        _ => return None,
+    };
+    (data.len() == len).then_some(data)
+}
+
+/// The output script an address pays to.
+pub(crate) fn address_script(address: &str) -> Option<Vec<u8>> {
+    let lower = address.to_ascii_lowercase();
+    if ["bc1", "tb1", "bcrt1"].iter().any(|hrp| lower.starts_with(hrp)) {
+        return segwit_script(address);
+    }
+    let decoded = base58_decode(address)?;
+    if decoded.len() != 25 || sha256d(&decoded[..21])[..4] != decoded[21..] {
+        return None;
+    }
+    let hash = &decoded[1..21];
+    match decoded[0] {
+        0x00 | 0x6f => Some([&[0x76, 0xa9, 0x14], hash, &[0x88, 0xac]].concat()),
+        0x05 | 0xc4 => Some([&[0xa9, 0x14], hash, &[0x87]].concat()),
+        _ => None,
+    }
+}
+
+const BASE58: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub(crate) fn base58_decode(value: &str) -> Option<Vec<u8>> {
+    let mut number: Vec<u8> = Vec::new();
+    for c in value.bytes() {
+        let mut carry = BASE58.iter().position(|b| *b == c)? as u32;
+        for byte in number.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            number.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = value.bytes().take_while(|c| *c == b'1').count();
+    Some([vec![0; zeros], number].concat())
+}
+
+pub(crate) fn base58_encode(bytes: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in bytes {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    let encoded = digits.iter().rev().map(|digit| BASE58[*digit as usize] as char);
+    std::iter::repeat_n('1', zeros).chain(encoded).collect()
+}
+
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+    values.iter().fold(1u32, |chk, value| {
+        let top = chk >> 25;
+        let chk = ((chk & 0x01ff_ffff) << 5) ^ *value as u32;
+        (0..5).filter(|i| top >> i & 1 == 1).fold(chk, |chk, i| chk ^ GEN[i])
+    })
+}
+
+/// BIP173 (witness v0) or BIP350 (v1+) address to its witness script.
+fn segwit_script(address: &str) -> Option<Vec<u8>> {
+    if address.to_ascii_lowercase() != address && address.to_ascii_uppercase() != address {
+        return None;
+    }
+    let address = address.to_ascii_lowercase();
+    let (hrp, data) = address.rsplit_once('1')?;
+    if data.len() < 7 {
+        return None;
+    }
+    let values = data
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|b| *b == c).map(|v| v as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    let expanded: Vec<u8> = hrp
+        .bytes()
+        .map(|c| c >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|c| c & 31))
+        .chain(values.iter().copied())
+        .collect();
+    let checksum = bech32_polymod(&expanded);
+
+    let version = values[0];
+    let program = convert_5_to_8(&values[1..values.len() - 6])?;
+    let valid = match version {
+        0 => checksum == BECH32_CONST && (program.len() == 20 || program.len() == 32),
+        1..=16 => checksum == BECH32M_CONST && (2..=40).contains(&program.len()),
+        _ => false,
+    };
+    if !valid {
+        return None;
+    }
+    let opcode = if version == 0 { 0 } else { 0x50 + version };
+    Some([&[opcode, program.len() as u8], program.as_slice()].concat())
+}
+
+/// Regroup 5-bit values into bytes, rejecting non-zero padding.
+fn convert_5_to_8(values: &[u8]) -> Option<Vec<u8>> {
+    let (mut acc, mut bits) = (0u32, 0u32);
+    let mut out = Vec::new();
+    for value in values {
+        acc = ((acc << 5) | *value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || (acc << (8 - bits)) & 0xff != 0 {
+        return None;
+    }
+    Some(out)
+}
@@ -0,0 +1,86 @@
+//! Per-chain verification settings: how many confirmations a proven
+//! block needs (the block itself counts as one, so the default of 1 only
+//! asks that it be finalized), how far below the finalized height a
+//! proven block may be (no limit unless the owner sets one), and whether
+//! the chain is paused, in which case every proof for it is invalid.
+
+use crate::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ChainConfig {
+    pub finalized_height: u64,
+    pub required_confirmations: u64,
+    pub max_proof_age_blocks: Option<u64>,
+    pub paused: bool,
+}
+
+#[near_bindgen]
+impl LightClient {
+    pub fn set_required_confirmations(&mut self, chain_type: ChainType, confirmations: u64) {
+        self.assert_owner();
+        assert!(confirmations > 0, "Required confirmations must be at least 1");
+        self.required_confirmations.insert(&chain_key(&chain_type), &confirmations);
+    }
+
+    /// Owner caps how many blocks a proven block may sit below the
+    /// finalized height; `None` lifts the cap.
+    pub fn set_max_proof_age(&mut self, chain_type: ChainType, max_age_blocks: Option<u64>) {
+        self.assert_owner();
+        let key = chain_key(&chain_type);
+        match max_age_blocks {
+            Some(max_age) => self.max_proof_age_blocks.insert(&key, &max_age),
+            None => self.max_proof_age_blocks.remove(&key),
+        };
+    }
+
+    pub fn set_chain_paused(&mut self, chain_type: ChainType, paused: bool) {
+        self.assert_owner();
+        let key = chain_key(&chain_type);
+        if paused {
+            self.paused_chains.insert(&key);
+        } else {
+            self.paused_chains.remove(&key);
+        }
+        env::log_str(&format!("CHAIN_PAUSED:chain={},paused={}", key, paused));
+    }
+
+    pub fn get_chain_config(&self, chain_type: ChainType) -> ChainConfig {
+        ChainConfig {
+            finalized_height: self.get_finalized_height(chain_type.clone()),
+            required_confirmations: self.get_required_confirmations(&chain_type),
+            max_proof_age_blocks: self.max_proof_age_blocks.get(&chain_key(&chain_type)),
+            paused: self.paused_chains.contains(&chain_key(&chain_type)),
+        }
+    }
+}
+
+impl LightClient {
+    fn get_required_confirmations(&self, chain_type: &ChainType) -> u64 {
+        self.required_confirmations.get(&chain_key(chain_type)).unwrap_or(1)
+    }
+
+    /// `finalized_height - block_height + 1` reaches the chain's required
+    /// confirmations; a block above the finalized height has none.
+    pub(crate) fn has_confirmations(&self, chain_type: &ChainType, block_height: u64, finalized_height: u64) -> bool {
+        let confirmations = (finalized_height + 1).saturating_sub(block_height);
+        confirmations >= self.get_required_confirmations(chain_type)
+    }
+
+    /// `ProofTooOld` when `finalized_height - block_height` exceeds the
+    /// chain's max proof age.
+    pub(crate) fn check_proof_age(&self, chain_type: &ChainType, block_height: u64, finalized_height: u64) -> Result<(), VerifyError> {
+        match self.max_proof_age_blocks.get(&chain_key(chain_type)) {
+            Some(max_age) if finalized_height.saturating_sub(block_height) > max_age => Err(VerifyError::ProofTooOld {
+                proof_height: block_height,
+                finalized: finalized_height,
+                max_age,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn is_chain_paused(&self, chain_type: &ChainType) -> bool {
+        self.paused_chains.contains(&chain_key(chain_type))
+    }
+}
@@ -0,0 +1,103 @@
+//! Trusted starting points for the header store. The owner sets each
+//! chain's checkpoint once; from then on submitted headers must build
+//! forward from it, one stored parent at a time, and proofs below it are
+//! refused. Starting over takes `reset_chain`, which unlinks the chain's
+//! headers and logs a `chain_reset` event.
+
+use near_sdk::serde_json::json;
+
+use crate::headers::canonical_hash;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Checkpoint {
+    pub height: u64,
+    pub block_hash: String,
+    /// Chain-specific data for the block: if given, the RLP header for
+    /// ETH or the 80-byte header for BTC, checked against `block_hash`.
+    pub extra: Vec<u8>,
+    pub set_at: u64,
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Owner sets `chain_type`'s checkpoint, once. It becomes the first
+    /// stored header.
+    pub fn init_checkpoint(&mut self, chain_type: ChainType, height: u64, block_hash: String, extra: Vec<u8>) {
+        self.assert_owner();
+        let chain = chain_key(&chain_type);
+        assert!(
+            self.checkpoints.get(&chain).is_none(),
+            "Checkpoint already initialized for {}",
+            chain
+        );
+        let block_hash = canonical_hash(&chain_type, &block_hash)
+            .unwrap_or_else(|| env::panic_str("Block hash must be 32 bytes"));
+        if !extra.is_empty() {
+            assert!(
+                checkpoint_header_matches(&chain_type, height, &block_hash, &extra),
+                "Checkpoint data does not match the block"
+            );
+        }
+
+        let checkpoint = Checkpoint { height, block_hash, extra, set_at: env::block_timestamp() };
+        self.checkpoints.insert(&chain, &checkpoint);
+        self.start_headers(&chain, &checkpoint);
+        env::log_str(&format!(
+            "CHECKPOINT_INITIALIZED:chain={},height={},hash={}",
+            chain, height, checkpoint.block_hash
+        ));
+    }
+
+    pub fn get_checkpoint(&self, chain_type: ChainType) -> Option<Checkpoint> {
+        self.checkpoints.get(&chain_key(&chain_type))
+    }
+
+    /// Owner drops `chain_type`'s checkpoint and every header built on
+    /// it, so `init_checkpoint` can be called again. Finalized heights
+    /// and BTC SPV headers are untouched.
+    pub fn reset_chain(&mut self, chain_type: ChainType, reason: String) {
+        self.assert_owner();
+        let chain = chain_key(&chain_type);
+        let checkpoint = self
+            .checkpoints
+            .remove(&chain)
+            .unwrap_or_else(|| env::panic_str("No checkpoint to reset"));
+        let latest_height = self.clear_headers(&chain);
+        let event = json!({
+            "standard": "light-client",
+            "version": "1.0.0",
+            "event": "chain_reset",
+            "data": [{
+                "chain": chain,
+                "checkpoint_height": checkpoint.height,
+                "latest_height": latest_height,
+                "reason": reason,
+            }],
+        });
+        env::log_str(&format!("EVENT_JSON:{}", event));
+    }
+}
+
+impl LightClient {
+    /// `BelowCheckpoint` for a proof under the chain's checkpoint.
+    pub(crate) fn check_checkpoint(&self, chain_type: &ChainType, proof_height: u64) -> Result<(), VerifyError> {
+        match self.checkpoints.get(&chain_key(chain_type)) {
+            Some(checkpoint) if proof_height < checkpoint.height => {
+                Err(VerifyError::BelowCheckpoint { proof_height, checkpoint: checkpoint.height })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn checkpoint_header_matches(chain_type: &ChainType, height: u64, block_hash: &str, extra: &[u8]) -> bool {
+    match chain_type {
+        ChainType::ETH => {
+            eth::header_number(extra) == Some(height) && hex::encode(env::keccak256_array(extra)) == block_hash
+        }
+        ChainType::BTC => btc::header_display_hash(extra).as_deref() == Some(block_hash),
+        ChainType::SOL => true,
+    }
+}
@@ -0,0 +1,79 @@
+//! The `proof_data` envelope. Its first byte names the codec of the rest:
+//!
+//! - `0`, JSON: the chain's `EthPaymentProof`, `BtcPaymentProof` or
+//!   `SolPaymentProof`, byte fields in hex;
+//! - `1`, borsh: a `PaymentProofV1`, whose variant must be one of the
+//!   proof's chain.
+//!
+//! Both decode to the same binary structures, which are all the verifiers
+//! see. Anything else, an empty `proof_data` included, is unparsable.
+//! ETH log proofs for the prover (`eth_prover.rs`) have no JSON form.
+//!
+//! With the `proof-codec` feature, `encode_json` and `encode_borsh` build
+//! `proof_data` from the same structures.
+
+use crate::*;
+
+pub const JSON_CODEC: u8 = 0;
+pub const BORSH_CODEC: u8 = 1;
+
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub enum PaymentProofV1 {
+    Eth(EthReceiptProof),
+    EthLog(EthLogProof),
+    Btc(BtcTxProof),
+    Sol(SolTxProof),
+}
+
+impl PaymentProofV1 {
+    fn chain_type(&self) -> ChainType {
+        match self {
+            PaymentProofV1::Eth(_) | PaymentProofV1::EthLog(_) => ChainType::ETH,
+            PaymentProofV1::Btc(_) => ChainType::BTC,
+            PaymentProofV1::Sol(_) => ChainType::SOL,
+        }
+    }
+}
+
+/// The proof in `proof_data`, `None` if it does not decode as a proof of
+/// `chain_type`.
+pub(crate) fn decode(chain_type: &ChainType, proof_data: &[u8]) -> Option<PaymentProofV1> {
+    let (codec, body) = proof_data.split_first()?;
+    match *codec {
+        JSON_CODEC => match chain_type {
+            ChainType::ETH => near_sdk::serde_json::from_slice::<EthPaymentProof>(body)
+                .ok()?
+                .decode()
+                .map(PaymentProofV1::Eth),
+            ChainType::BTC => near_sdk::serde_json::from_slice::<BtcPaymentProof>(body)
+                .ok()?
+                .decode()
+                .map(PaymentProofV1::Btc),
+            ChainType::SOL => near_sdk::serde_json::from_slice::<SolPaymentProof>(body)
+                .ok()?
+                .decode()
+                .map(PaymentProofV1::Sol),
+        },
+        BORSH_CODEC => PaymentProofV1::try_from_slice(body)
+            .ok()
+            .filter(|proof| proof.chain_type() == *chain_type),
+        _ => None,
+    }
+}
+
+/// `proof_data` carrying `proof` (an `EthPaymentProof`, `BtcPaymentProof`
+/// or `SolPaymentProof`) as JSON.
+#[cfg(any(test, feature = "proof-codec"))]
+pub fn encode_json<T: Serialize>(proof: &T) -> Vec<u8> {
+    let mut proof_data = vec![JSON_CODEC];
+    proof_data.extend(near_sdk::serde_json::to_vec(proof).expect("Proofs serialize to JSON"));
+    proof_data
+}
+
+/// `proof_data` carrying `proof` in borsh.
+#[cfg(any(test, feature = "proof-codec"))]
+pub fn encode_borsh(proof: &PaymentProofV1) -> Vec<u8> {
+    let mut proof_data = vec![BORSH_CODEC];
+    proof_data.extend(borsh::to_vec(proof).expect("Proofs serialize to borsh"));
+    proof_data
+}
@@ -0,0 +1,156 @@
+//! Replay protection. `verify_payment_proof` records nothing, so one proof
+//! verifies any number of times; `consume_payment_proof` runs the same
+//! checks and, on success, records `(chain, tx_hash, memo)` so the proof
+//! is rejected from then on. Only consumer contracts the owner registers
+//! may consume. An ETH log proof has no memo to bind, so it is recorded
+//! under its log id alone and spends once whatever memo
+//! it is presented with.
+
+use near_sdk::PromiseError;
+
+use crate::codec;
+use crate::eth_prover;
+use crate::*;
+
+#[near_bindgen]
+impl LightClient {
+    pub fn add_consumer(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.consumers.insert(&account_id);
+    }
+
+    pub fn remove_consumer(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.consumers.remove(&account_id);
+    }
+
+    pub fn is_consumer(&self, account_id: AccountId) -> bool {
+        self.consumers.contains(&account_id)
+    }
+
+    /// `verify_payment_proof`, recording the proof as consumed when it is
+    /// valid with at least `min_confirmations` on top of it. A proof
+    /// already consumed is invalid, reporting the height it was consumed
+    /// at. An under-confirmed proof comes back valid but unconsumed, so
+    /// the caller's own confirmation check rejects it and it can be
+    /// presented again later.
+    pub fn consume_payment_proof(
+        &mut self,
+        chain_type: ChainType,
+        proof_data: Vec<u8>,
+        expected_recipient: String,
+        expected_asset: String,
+        expected_amount: U128,
+        expected_memo: String,
+        expected_tx_hash: String,
+        expected_asset_kind: Option<AssetKind>,
+        min_confirmations: u64,
+    ) -> PromiseOrValue<VerificationResult> {
+        assert!(
+            self.consumers.contains(&env::predecessor_account_id()),
+            "Caller is not a registered consumer"
+        );
+        let proof = codec::decode(&chain_type, &proof_data);
+        let memo = match proof {
+            Some(PaymentProofV1::EthLog(_)) => "",
+            _ => &expected_memo,
+        };
+        let key = consumed_key(&chain_type, &expected_tx_hash, memo);
+        if let Some(result) = self.already_consumed(&chain_type, &key, &expected_tx_hash) {
+            return PromiseOrValue::Value(result);
+        }
+        let expected = ExpectedPayment {
+            recipient: &expected_recipient,
+            asset: &expected_asset,
+            amount: expected_amount.0,
+            memo: &expected_memo,
+            tx_hash: &expected_tx_hash,
+            asset_kind: expected_asset_kind.as_ref(),
+        };
+        match self.payment_verdict(&chain_type, proof, &expected) {
+            Verdict::Final(result) => {
+                self.record_consumed(&key, &expected_tx_hash, &result, min_confirmations, env::predecessor_account_id());
+                PromiseOrValue::Value(result)
+            }
+            Verdict::Pending(call, pending) => call
+                .then(Self::ext(env::current_account_id()).with_static_gas(eth_prover::CALLBACK_GAS).on_eth_log_consumed(
+                    pending.block_height,
+                    pending.finalized_height,
+                    key,
+                    expected_tx_hash,
+                    min_confirmations,
+                    env::predecessor_account_id(),
+                ))
+                .into(),
+        }
+    }
+
+    /// The prover's answer on a proof `consumer` is consuming, recorded as
+    /// in `consume_payment_proof`. Consumption is checked again: another
+    /// call may have spent the proof while the prover was answering.
+    #[private]
+    pub fn on_eth_log_consumed(
+        &mut self,
+        #[callback_result] verified: Result<bool, PromiseError>,
+        block_height: u64,
+        finalized_height: u64,
+        key: String,
+        tx_hash: String,
+        min_confirmations: u64,
+        consumer: AccountId,
+    ) -> VerificationResult {
+        if let Some(result) = self.already_consumed(&ChainType::ETH, &key, &tx_hash) {
+            return result;
+        }
+        let result = eth_prover::prover_verdict(verified, block_height, finalized_height);
+        self.record_consumed(&key, &tx_hash, &result, min_confirmations, consumer);
+        result
+    }
+
+    /// Height a consumed proof was included at, `None` if not consumed.
+    pub fn get_consumed_proof(&self, chain_type: ChainType, tx_hash: String, memo: String) -> Option<u64> {
+        self.consumed.get(&consumed_key(&chain_type, &tx_hash, &memo))
+    }
+}
+
+impl LightClient {
+    /// The verdict on a proof already consumed under `key`, reporting the
+    /// height it was consumed at.
+    fn already_consumed(&self, chain_type: &ChainType, key: &str, tx_hash: &str) -> Option<VerificationResult> {
+        let block_height = self.consumed.get(&key.to_string())?;
+        env::log_str(&format!("Proof for tx {} already consumed", tx_hash));
+        Some(VerificationResult {
+            outcome: VerifyOutcome::Invalid(VerifyError::AlreadyConsumed),
+            block_height,
+            finalized_height: self.get_finalized_height(chain_type.clone()),
+        })
+    }
+
+    fn record_consumed(
+        &mut self,
+        key: &str,
+        tx_hash: &str,
+        result: &VerificationResult,
+        min_confirmations: u64,
+        consumer: AccountId,
+    ) {
+        if result.is_valid() && result.finalized_height.saturating_sub(result.block_height) >= min_confirmations {
+            self.consumed.insert(&key.to_string(), &result.block_height);
+            env::log_str(&format!(
+                "Consumed proof for tx {} at height {} by {}",
+                tx_hash, result.block_height, consumer
+            ));
+        }
+    }
+}
+
+/// ETH and BTC hashes are hex, accepted with or without `0x` and in
+/// either case, so they are normalized before keying; SOL signatures are
+/// base58 and kept as given.
+fn consumed_key(chain_type: &ChainType, tx_hash: &str, memo: &str) -> String {
+    let tx_hash = match chain_type {
+        ChainType::ETH | ChainType::BTC => tx_hash.strip_prefix("0x").unwrap_or(tx_hash).to_lowercase(),
+        ChainType::SOL => tx_hash.to_string(),
+    };
+    format!("{}:{}:{}", chain_key(chain_type), tx_hash, memo)
+}
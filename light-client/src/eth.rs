@@ -0,0 +1,458 @@
+//! Ethereum payment proofs checked against block headers the owner has
+//! stored. A proof carries the RLP block header and, at one index of that
+//! block, the transaction and its receipt with their trie proofs:
+//!
+//! - the header must hash to the submitted header's hash for its number
+//!   (`headers.rs`), or else to the stored block hash;
+//! - the transaction and receipt must sit under that index in the
+//!   header's `transactionsRoot` and `receiptsRoot`;
+//! - the transaction hashes to the expected `tx_hash` and its receipt
+//!   reports success.
+//!
+//! What was paid is read from the proven data, never from the proof's
+//! own claims. Native ETH: the transaction's `to` and `value`, with the
+//! memo as its whole calldata. ERC-20s (registered with `register_token`):
+//! a `Transfer` log of the token contract in the receipt, with the memo
+//! appended to the `transfer(to, amount)` calldata sent to the token.
+//! The proof names its `AssetKind`, which must be the one expected: the
+//! caller's `expected_asset_kind` when given, otherwise the registered
+//! token (or native for `ETH`).
+//!
+//! An ETH proof can instead be a single `Transfer` log whose inclusion an
+//! `eth_prover` vouches for; see `eth_prover.rs`.
+
+use crate::address;
+use crate::mpt;
+use crate::rlp::{self, Item};
+use crate::*;
+
+/// `keccak256("Transfer(address,address,uint256)")`.
+const TRANSFER_TOPIC: [u8; 32] = [
+    0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa, 0x95, 0x2b,
+    0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
+];
+
+/// `transfer(address,uint256)` selector.
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// Calldata length of `transfer(address,uint256)` before the memo.
+const TRANSFER_CALLDATA_LEN: usize = 4 + 32 + 32;
+
+/// An ETH payment or transition proof in the JSON format. Byte fields are
+/// hex, with or without `0x`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EthPaymentProof {
+    /// RLP-encoded block header.
+    pub header: String,
+    /// Position of the transaction (and its receipt) in the block.
+    pub receipt_index: u64,
+    /// Receipt as stored in the receipts trie (typed receipts keep their
+    /// type byte).
+    pub receipt: String,
+    /// Receipts trie nodes from the root to the receipt.
+    pub receipt_proof: Vec<String>,
+    /// Signed transaction as stored in the transactions trie.
+    pub transaction: String,
+    /// Transactions trie nodes from the root to the transaction.
+    pub transaction_proof: Vec<String>,
+    /// Whether the payment is native ETH or an ERC-20 transfer.
+    pub asset_kind: AssetKind,
+}
+
+/// An ETH payment or transition proof in the binary format
+/// (`codec.rs`); `EthPaymentProof` decodes to this.
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub struct EthReceiptProof {
+    /// RLP-encoded block header.
+    pub header: Vec<u8>,
+    /// Position of the transaction (and its receipt) in the block.
+    pub receipt_index: u64,
+    /// Receipt as stored in the receipts trie.
+    pub receipt: Vec<u8>,
+    /// Receipts trie nodes from the root to the receipt.
+    pub receipt_proof: Vec<Vec<u8>>,
+    /// Signed transaction as stored in the transactions trie.
+    pub transaction: Vec<u8>,
+    /// Transactions trie nodes from the root to the transaction.
+    pub transaction_proof: Vec<Vec<u8>>,
+    /// The ERC-20 contract paid in, `None` for native ETH.
+    pub token: Option<[u8; 20]>,
+}
+
+impl EthPaymentProof {
+    /// The binary form, `None` if a byte field is not hex.
+    pub(crate) fn decode(self) -> Option<EthReceiptProof> {
+        Some(EthReceiptProof {
+            header: parse_hex(&self.header)?,
+            receipt_index: self.receipt_index,
+            receipt: parse_hex(&self.receipt)?,
+            receipt_proof: parse_hex_list(&self.receipt_proof)?,
+            transaction: parse_hex(&self.transaction)?,
+            transaction_proof: parse_hex_list(&self.transaction_proof)?,
+            token: match &self.asset_kind {
+                AssetKind::Native => None,
+                AssetKind::Erc20 { contract } => Some(parse_address(contract)?),
+            },
+        })
+    }
+}
+
+/// What the caller expects the proven transaction to have paid.
+pub(crate) struct ExpectedPayment<'a> {
+    pub recipient: &'a str,
+    pub asset: &'a str,
+    pub amount: u128,
+    pub memo: &'a str,
+    pub tx_hash: &'a str,
+    /// ETH only; `None` leaves the kind to the token registry.
+    pub asset_kind: Option<&'a AssetKind>,
+}
+
+/// Why an ETH proof was rejected, with the block height the header
+/// claims (0 if it could not be read).
+#[derive(Debug, PartialEq)]
+pub(crate) struct EthProofError {
+    pub block_height: u64,
+    pub reason: VerifyError,
+}
+
+struct Header {
+    parent_hash: [u8; 32],
+    number: u64,
+    transactions_root: [u8; 32],
+    receipts_root: [u8; 32],
+}
+
+struct Transaction<'a> {
+    to: &'a [u8],
+    value: u128,
+    data: &'a [u8],
+}
+
+#[derive(PartialEq)]
+struct Log<'a> {
+    address: &'a [u8],
+    topics: Vec<&'a [u8]>,
+    data: &'a [u8],
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Owner stores the hash of a finalized ETH block so proofs against it
+    /// can be checked.
+    pub fn set_eth_block_hash(&mut self, block_height: u64, block_hash: String) {
+        self.assert_owner();
+        let hash = parse_hex(&block_hash)
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .unwrap_or_else(|| env::panic_str("Block hash must be 32 bytes of hex"));
+        self.eth_block_hashes.insert(&block_height, &hash);
+    }
+
+    pub fn get_eth_block_hash(&self, block_height: u64) -> Option<String> {
+        self.eth_block_hashes
+            .get(&block_height)
+            .map(|hash| format!("0x{}", hex::encode(hash)))
+    }
+
+    /// `register_token` on ETH with `Some`, `unregister_token` with `None`.
+    pub fn set_eth_token(&mut self, asset: String, token: Option<String>) {
+        match token {
+            Some(token) => self.register_token(ChainType::ETH, asset, token),
+            None => self.unregister_token(ChainType::ETH, asset),
+        }
+    }
+
+    pub fn get_eth_token(&self, asset: String) -> Option<String> {
+        self.get_token(ChainType::ETH, asset)
+    }
+}
+
+impl LightClient {
+    /// `verify_eth_payment` plus the confirmations check, logging why a proof
+    /// was rejected.
+    pub(crate) fn eth_verdict(&self, proof: &EthReceiptProof, expected: &ExpectedPayment) -> VerificationResult {
+        self.eth_finality_verdict(self.verify_eth_payment(proof, expected))
+    }
+
+    /// The verdict on a proven ETH block height: it needs the chain's
+    /// confirmations and must not be too old.
+    pub(crate) fn eth_finality_verdict(&self, verified: Result<u64, EthProofError>) -> VerificationResult {
+        let finalized_height = self.get_finalized_height(ChainType::ETH);
+        let (result, block_height) = match verified {
+            Ok(block_height)
+                if finalized_height == 0 || !self.has_confirmations(&ChainType::ETH, block_height, finalized_height) =>
+            {
+                let error = VerifyError::NotFinalized { proof_height: block_height, finalized: finalized_height };
+                (Err(error), block_height)
+            }
+            Ok(block_height) => (self.check_proof_age(&ChainType::ETH, block_height, finalized_height), block_height),
+            Err(error) => (Err(error.reason), error.block_height),
+        };
+        if let Err(error) = result {
+            env::log_str(&format!("ETH proof rejected: {}", error));
+        }
+        VerificationResult { outcome: result.into(), block_height, finalized_height }
+    }
+
+    /// Check an `EthReceiptProof` against `expected`. Returns the proven
+    /// block height; finality is the caller's check.
+    pub(crate) fn verify_eth_payment(
+        &self,
+        proof: &EthReceiptProof,
+        expected: &ExpectedPayment,
+    ) -> Result<u64, EthProofError> {
+        let header = self.proven_header(&proof.header)?;
+        let fail = |reason: VerifyError| EthProofError { block_height: header.number, reason };
+
+        let key = rlp::encode_uint(proof.receipt_index);
+        if mpt::verify_proof(&header.transactions_root, &key, &proof.transaction_proof).as_ref()
+            != Some(&proof.transaction)
+        {
+            return Err(fail(VerifyError::InclusionProofInvalid));
+        }
+        if mpt::verify_proof(&header.receipts_root, &key, &proof.receipt_proof).as_ref() != Some(&proof.receipt) {
+            return Err(fail(VerifyError::InclusionProofInvalid));
+        }
+
+        let tx_hash = parse_hex(expected.tx_hash).ok_or(fail(VerifyError::TxHashMismatch))?;
+        if env::keccak256_array(&proof.transaction).as_slice() != tx_hash.as_slice() {
+            return Err(fail(VerifyError::TxHashMismatch));
+        }
+        let tx = decode_transaction(&proof.transaction).ok_or(fail(VerifyError::DeserializeFailed))?;
+        let logs = decode_successful_receipt(&proof.receipt).ok_or(fail(VerifyError::TransactionFailed))?;
+        let recipient = address::eth(expected.recipient).map_err(fail)?;
+        let memo = expected.memo.as_bytes();
+
+        let token = self.expected_token(expected).map_err(fail)?;
+        if proof.token != token {
+            return Err(fail(VerifyError::AssetMismatch));
+        }
+
+        let Some(token) = token else {
+            if tx.to != recipient {
+                return Err(fail(VerifyError::RecipientMismatch));
+            }
+            if tx.value != expected.amount {
+                return Err(fail(VerifyError::AmountMismatch));
+            }
+            if tx.data != memo {
+                return Err(fail(VerifyError::MemoMismatch));
+            }
+            return Ok(header.number);
+        };
+        let transfers: Vec<&Log> = logs.iter().filter(|log| is_transfer_to(log, &token, &recipient)).collect();
+        if transfers.is_empty() {
+            return Err(fail(VerifyError::RecipientMismatch));
+        }
+        if !transfers.iter().any(|log| uint256(log.data) == Some(expected.amount)) {
+            return Err(fail(VerifyError::AmountMismatch));
+        }
+        let calldata_memo = (tx.to == token
+            && tx.data.len() >= TRANSFER_CALLDATA_LEN
+            && tx.data[..4] == TRANSFER_SELECTOR)
+            .then(|| &tx.data[TRANSFER_CALLDATA_LEN..]);
+        if calldata_memo != Some(memo) {
+            return Err(fail(VerifyError::MemoMismatch));
+        }
+        Ok(header.number)
+    }
+
+    /// Check an `EthLogProof` against `expected`, all but the inclusion of
+    /// its receipt in the block, which is the prover's call. Returns the
+    /// proven block height; finality is the caller's check.
+    pub(crate) fn verify_eth_log(&self, proof: &EthLogProof, expected: &ExpectedPayment) -> Result<u64, EthProofError> {
+        let header = self.proven_header(&proof.header_data)?;
+        let fail = |reason: VerifyError| EthProofError { block_height: header.number, reason };
+
+        let block_hash = env::keccak256_array(&proof.header_data);
+        let log_id = log_id(&block_hash, proof.receipt_index, proof.log_index);
+        if parse_hex(expected.tx_hash).as_deref() != Some(log_id.as_slice()) {
+            return Err(fail(VerifyError::TxHashMismatch));
+        }
+        let logs = decode_successful_receipt(&proof.receipt_data).ok_or(fail(VerifyError::TransactionFailed))?;
+        let log = rlp::decode_exact(&proof.log_entry_data)
+            .and_then(|item| decode_log(&item))
+            .ok_or(fail(VerifyError::DeserializeFailed))?;
+        if usize::try_from(proof.log_index).ok().and_then(|index| logs.get(index)) != Some(&log) {
+            return Err(fail(VerifyError::InclusionProofInvalid));
+        }
+
+        let recipient = address::eth(expected.recipient).map_err(fail)?;
+        // A native payment leaves no log to prove.
+        let token = self.expected_token(expected).map_err(fail)?.ok_or(fail(VerifyError::AssetMismatch))?;
+        if log.address != token {
+            return Err(fail(VerifyError::AssetMismatch));
+        }
+        if !is_transfer_to(&log, &token, &recipient) {
+            return Err(fail(VerifyError::RecipientMismatch));
+        }
+        if uint256(log.data) != Some(expected.amount) {
+            return Err(fail(VerifyError::AmountMismatch));
+        }
+        Ok(header.number)
+    }
+
+    /// The decoded `header_rlp`, if it hashes to the submitted header for
+    /// its number, or else to the stored block hash, and is not below the
+    /// checkpoint.
+    fn proven_header(&self, header_rlp: &[u8]) -> Result<Header, EthProofError> {
+        let header = decode_header(header_rlp)
+            .ok_or(EthProofError { block_height: 0, reason: VerifyError::DeserializeFailed })?;
+        let fail = |reason: VerifyError| EthProofError { block_height: header.number, reason };
+        self.check_checkpoint(&ChainType::ETH, header.number).map_err(fail)?;
+
+        let stored = match self.stored_header_hash(&ChainType::ETH, header.number) {
+            Some(hash) => hash,
+            None => hex::encode(self.eth_block_hashes.get(&header.number).ok_or(fail(VerifyError::UnknownBlock))?),
+        };
+        if hex::encode(env::keccak256_array(header_rlp)) != stored {
+            return Err(fail(VerifyError::HeaderMismatch));
+        }
+        Ok(header)
+    }
+
+    /// The ERC-20 contract `expected` pays in, or `None` for native ETH. An
+    /// expected contract must be the one registered for the asset.
+    fn expected_token(&self, expected: &ExpectedPayment) -> Result<Option<[u8; 20]>, VerifyError> {
+        let registered = self.eth_tokens.get(&expected.asset.to_uppercase());
+        match expected.asset_kind {
+            None if expected.asset.eq_ignore_ascii_case("ETH") => Ok(None),
+            None => registered.map(Some).ok_or(VerifyError::UnregisteredToken),
+            Some(AssetKind::Native) if registered.is_some() => Err(VerifyError::AssetMismatch),
+            Some(AssetKind::Native) => Ok(None),
+            Some(AssetKind::Erc20 { .. }) if expected.asset.eq_ignore_ascii_case("ETH") => Err(VerifyError::AssetMismatch),
+            Some(AssetKind::Erc20 { contract }) => {
+                let registered = registered.ok_or(VerifyError::UnregisteredToken)?;
+                if parse_address(contract) != Some(registered) {
+                    return Err(VerifyError::AssetMismatch);
+                }
+                Ok(Some(registered))
+            }
+        }
+    }
+}
+
+/// The id an `EthLogProof` stands in for a tx hash with:
+/// `keccak256(block_hash ++ receipt_index ++ log_index)`, the indexes as
+/// 8-byte big-endian.
+pub(crate) fn log_id(block_hash: &[u8; 32], receipt_index: u64, log_index: u64) -> [u8; 32] {
+    let mut preimage = block_hash.to_vec();
+    preimage.extend_from_slice(&receipt_index.to_be_bytes());
+    preimage.extend_from_slice(&log_index.to_be_bytes());
+    env::keccak256_array(&preimage)
+}
+
+/// An ERC-20 `Transfer` by `token` to `recipient`.
+fn is_transfer_to(log: &Log, token: &[u8; 20], recipient: &[u8; 20]) -> bool {
+    log.address == token && log.topics.len() == 3 && log.topics[0] == TRANSFER_TOPIC && log.topics[2][12..] == recipient[..]
+}
+
+/// Whether `headers` (RLP, hex) lead from the finalized block `from` to
+/// the block `to_hash` at `to_height`, each header the parent of the
+/// next.
+pub(crate) fn headers_link(from: &FinalizedBlock, to_height: u64, to_hash: [u8; 32], headers: &[String]) -> bool {
+    let Some(mut parent) = parse_hex(&from.block_hash).and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+        return false;
+    };
+    let mut number = from.height;
+    for header in headers {
+        let Some(encoded) = parse_hex(header) else {
+            return false;
+        };
+        match decode_header(&encoded) {
+            Some(decoded) if decoded.parent_hash == parent && decoded.number == number + 1 => {}
+            _ => return false,
+        }
+        parent = env::keccak256_array(&encoded);
+        number += 1;
+    }
+    number == to_height && parent == to_hash
+}
+
+pub(crate) fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    hex::decode(value.strip_prefix("0x").unwrap_or(value)).ok()
+}
+
+fn parse_hex_list(values: &[String]) -> Option<Vec<Vec<u8>>> {
+    values.iter().map(|value| parse_hex(value)).collect()
+}
+
+pub(crate) fn parse_address(value: &str) -> Option<[u8; 20]> {
+    parse_hex(value)?.try_into().ok()
+}
+
+fn hash32(item: &Item) -> Option<[u8; 32]> {
+    item.bytes()?.try_into().ok()
+}
+
+/// A 32-byte big-endian word, if it fits in a `u128`.
+fn uint256(word: &[u8]) -> Option<u128> {
+    if word.len() != 32 || word[..16].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(word[16..].iter().fold(0u128, |acc, b| (acc << 8) | *b as u128))
+}
+
+/// Block number of an RLP header.
+pub(crate) fn header_number(encoded: &[u8]) -> Option<u64> {
+    decode_header(encoded).map(|header| header.number)
+}
+
+fn decode_header(encoded: &[u8]) -> Option<Header> {
+    let fields = rlp::decode_exact(encoded)?.list()?;
+    if fields.len() < 15 {
+        return None;
+    }
+    Some(Header {
+        parent_hash: hash32(&fields[0])?,
+        number: u64::try_from(fields[8].uint()?).ok()?,
+        transactions_root: hash32(&fields[4])?,
+        receipts_root: hash32(&fields[5])?,
+    })
+}
+
+/// Legacy, EIP-2930, EIP-1559 and EIP-4844 transactions.
+fn decode_transaction(encoded: &[u8]) -> Option<Transaction<'_>> {
+    let (to, value, data, body) = match encoded.first()? {
+        0x01 => (4, 5, 6, &encoded[1..]),
+        0x02 | 0x03 => (5, 6, 7, &encoded[1..]),
+        0xc0..=0xff => (3, 4, 5, encoded),
+        _ => return None,
+    };
+    let fields = rlp::decode_exact(body)?.list()?;
+    if fields.len() <= data {
+        return None;
+    }
+    Some(Transaction {
+        to: fields[to].bytes()?,
+        value: fields[value].uint()?,
+        data: fields[data].bytes()?,
+    })
+}
+
+/// Logs of a receipt whose status is success.
+fn decode_successful_receipt(encoded: &[u8]) -> Option<Vec<Log<'_>>> {
+    let body = match encoded.first()? {
+        0x01..=0x7f => &encoded[1..],
+        _ => encoded,
+    };
+    let fields = rlp::decode_exact(body)?.list()?;
+    if fields.len() != 4 || fields[0].bytes()? != [1] {
+        return None;
+    }
+    fields[3].list()?.iter().map(decode_log).collect()
+}
+
+fn decode_log<'a>(log: &Item<'a>) -> Option<Log<'a>> {
+    let parts = log.list()?;
+    if parts.len() != 3 {
+        return None;
+    }
+    let topics = parts[1]
+        .list()?
+        .iter()
+        .map(|topic| topic.bytes().filter(|t| t.len() == 32))
+        .collect::<Option<Vec<_>>>()?;
+    Some(Log { address: parts[0].bytes()?, topics, data: parts[2].bytes()? })
+}
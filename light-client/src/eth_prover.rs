@@ -0,0 +1,154 @@
+//! ETH proofs checked by an external, Rainbow-bridge-style prover, which
+//! the owner sets as `eth_prover`. Such a proof is an `EthLogProof` (only
+//! in borsh, see `codec.rs`) and the prover's `verify_log_entry` decides
+//! whether its receipt is in the block; with no prover set it cannot be
+//! proven. Everything else is still checked here first
+//! (`eth::verify_eth_log`): the header is a stored one, the log is the
+//! receipt's `log_index`th and the receipt succeeded, the log is a
+//! `Transfer` of the expected token to the recipient for the amount, and
+//! finality. Only a proof that passes all of that costs a prover call.
+//!
+//! A receipt names neither its transaction nor the calldata, so:
+//!
+//! - the expected tx hash is the log's id, `eth::log_id`, which consumers
+//!   key replay protection on like a tx hash;
+//! - the memo is not checked;
+//! - native ETH, which leaves no log, cannot be proven.
+//!
+//! The verdict then comes back through a callback, so the ETH methods
+//! return a promise rather than a value.
+
+use near_sdk::{ext_contract, Gas, Promise, PromiseError};
+
+use crate::*;
+
+/// For the prover's `verify_log_entry`.
+pub const PROVER_GAS: Gas = Gas::from_tgas(40);
+
+/// For the callback reading the prover's answer.
+pub const CALLBACK_GAS: Gas = Gas::from_tgas(15);
+
+/// An ETH log proof: what the prover's `verify_log_entry` takes.
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub struct EthLogProof {
+    /// Position of the log in its receipt.
+    pub log_index: u64,
+    /// RLP-encoded log.
+    pub log_entry_data: Vec<u8>,
+    /// Position of the receipt in the block.
+    pub receipt_index: u64,
+    /// Receipt as stored in the receipts trie.
+    pub receipt_data: Vec<u8>,
+    /// RLP-encoded block header.
+    pub header_data: Vec<u8>,
+    /// Receipts trie nodes from the root to the receipt.
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// A payment proof's verdict, or for an ETH proof with a prover set, the
+/// prover call still to be answered and the verdict so far, whose heights
+/// the callback reports.
+pub(crate) enum Verdict {
+    Final(VerificationResult),
+    Pending(Promise, VerificationResult),
+}
+
+#[ext_contract(ext_eth_prover)]
+pub trait EthProver {
+    fn verify_log_entry(
+        &self,
+        log_index: u64,
+        log_entry_data: Vec<u8>,
+        receipt_index: u64,
+        receipt_data: Vec<u8>,
+        header_data: Vec<u8>,
+        proof: Vec<Vec<u8>>,
+        skip_bridge_call: bool,
+    ) -> bool;
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Owner sets the prover ETH log proofs go to; `None` rejects them.
+    pub fn set_eth_prover(&mut self, prover: Option<AccountId>) {
+        self.assert_owner();
+        env::log_str(&format!(
+            "ETH_PROVER_SET:prover={}",
+            prover.as_ref().map_or("none", |prover| prover.as_str())
+        ));
+        self.eth_prover = prover;
+    }
+
+    pub fn get_eth_prover(&self) -> Option<AccountId> {
+        self.eth_prover.clone()
+    }
+
+    /// The prover's answer on a proof that passed the local checks at
+    /// `block_height`, with `finalized_height` as of the call.
+    #[private]
+    pub fn on_eth_log_verified(
+        &self,
+        #[callback_result] verified: Result<bool, PromiseError>,
+        block_height: u64,
+        finalized_height: u64,
+    ) -> VerificationResult {
+        prover_verdict(verified, block_height, finalized_height)
+    }
+
+    /// `on_eth_log_verified` for `verify_transition_proof`.
+    #[private]
+    pub fn on_eth_transition_log_verified(
+        &self,
+        #[callback_result] verified: Result<bool, PromiseError>,
+        block_height: u64,
+        finalized_height: u64,
+    ) -> VerifyOutcome {
+        prover_verdict(verified, block_height, finalized_height).outcome
+    }
+}
+
+impl LightClient {
+    /// Checks an `EthLogProof` locally and, if it passes, asks the prover
+    /// about it.
+    pub(crate) fn prove_eth_log(&self, proof: EthLogProof, expected: &ExpectedPayment) -> Verdict {
+        let verdict = self.eth_finality_verdict(self.verify_eth_log(&proof, expected));
+        if !verdict.is_valid() {
+            return Verdict::Final(verdict);
+        }
+        match self.eth_prover.clone() {
+            Some(prover) => {
+                let call = ext_eth_prover::ext(prover).with_static_gas(PROVER_GAS).verify_log_entry(
+                    proof.log_index,
+                    proof.log_entry_data,
+                    proof.receipt_index,
+                    proof.receipt_data,
+                    proof.header_data,
+                    proof.proof,
+                    // The header was matched against stored ones above.
+                    true,
+                );
+                Verdict::Pending(call, verdict)
+            }
+            // Nothing here can show the receipt is in the block.
+            None => Verdict::Final(prover_verdict(Ok(false), verdict.block_height, verdict.finalized_height)),
+        }
+    }
+}
+
+/// `Ok(false)` is a receipt not in the block; a failed prover call
+/// rejects the proof too, as it cannot be told apart from a bad one.
+pub(crate) fn prover_verdict(
+    verified: Result<bool, PromiseError>,
+    block_height: u64,
+    finalized_height: u64,
+) -> VerificationResult {
+    let result = match verified {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(VerifyError::InclusionProofInvalid),
+        Err(_) => Err(VerifyError::ProverFailed),
+    };
+    if let Err(error) = result {
+        env::log_str(&format!("ETH proof rejected: {}", error));
+    }
+    VerificationResult { outcome: result.into(), block_height, finalized_height }
+}
@@ -0,0 +1,123 @@
+//! Finalized blocks per chain. Each chain's finalized `(height,
+//! block_hash)` only moves forward, by at most `max_height_step` unless
+//! the move comes with a header proof linking the two blocks. The one
+//! way back is `rollback_finalized`: the owner returns a chain to a
+//! height it was finalized at before, at most `max_rollback_depth`
+//! blocks down, and a `finalized_rollback` event tells consumers which
+//! heights are no longer final. Only the blocks within that depth are
+//! kept for it.
+
+use near_sdk::serde_json::json;
+
+use crate::*;
+
+pub const DEFAULT_MAX_ROLLBACK_DEPTH: u64 = 64;
+/// Highest `max_rollback_depth` the owner may set: a rollback removes the
+/// ETH block hash of every height it undoes, all in one call.
+pub const MAX_ROLLBACK_DEPTH_LIMIT: u64 = 256;
+pub const DEFAULT_MAX_HEIGHT_STEP: u64 = 100_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FinalizedBlock {
+    pub height: u64,
+    pub block_hash: String,
+}
+
+#[near_bindgen]
+impl LightClient {
+    pub fn get_finalized_block(&self, chain_type: ChainType) -> Option<FinalizedBlock> {
+        self.finalized.get(&chain_key(&chain_type))
+    }
+
+    pub fn set_max_rollback_depth(&mut self, depth: u64) {
+        self.assert_owner();
+        assert!(
+            depth <= MAX_ROLLBACK_DEPTH_LIMIT,
+            "Max rollback depth cannot exceed {}",
+            MAX_ROLLBACK_DEPTH_LIMIT
+        );
+        self.max_rollback_depth = depth;
+    }
+
+    pub fn get_max_rollback_depth(&self) -> u64 {
+        self.max_rollback_depth
+    }
+
+    pub fn set_max_height_step(&mut self, step: u64) {
+        self.assert_owner();
+        assert!(step > 0, "Max height step must be positive");
+        self.max_height_step = step;
+    }
+
+    pub fn get_max_height_step(&self) -> u64 {
+        self.max_height_step
+    }
+
+    /// Owner moves a chain back to `to_height`, which must have been
+    /// finalized before, after a reorg past finality. ETH block hashes
+    /// above it are dropped and pending reports cleared. BTC reorganizes
+    /// through its header chain instead.
+    pub fn rollback_finalized(&mut self, chain_type: ChainType, to_height: u64, reason: String) {
+        self.assert_owner();
+        assert!(chain_type != ChainType::BTC, "BTC height follows submitted headers");
+        let key = chain_key(&chain_type);
+        let current = self
+            .finalized
+            .get(&key)
+            .unwrap_or_else(|| env::panic_str("Chain has no finalized height"));
+        assert!(to_height < current.height, "Rollback must go below the finalized height");
+        assert!(
+            current.height - to_height <= self.max_rollback_depth,
+            "Rollback of {} blocks exceeds the max depth of {}",
+            current.height - to_height,
+            self.max_rollback_depth
+        );
+        let mut recent = self.recent_finalized.get(&key).unwrap_or_default();
+        let restored = recent
+            .iter()
+            .position(|block| block.height == to_height)
+            .unwrap_or_else(|| env::panic_str(&format!("Height {} was never finalized", to_height)));
+        recent.truncate(restored + 1);
+        self.recent_finalized.insert(&key, &recent);
+
+        if chain_type == ChainType::ETH {
+            for height in to_height + 1..=current.height {
+                self.eth_block_hashes.remove(&height);
+            }
+        }
+        self.height_reports.remove(&key);
+        self.finalized.insert(&key, &recent[restored]);
+        log_rollback(&key, current.height, to_height, &reason);
+    }
+}
+
+impl LightClient {
+    /// Record `(height, block_hash)` as the chain's finalized block, and
+    /// forget the blocks a rollback can no longer reach.
+    pub(crate) fn advance_finalized(&mut self, key: &String, height: u64, block_hash: String) {
+        let block = FinalizedBlock { height, block_hash };
+        let mut recent = self.recent_finalized.get(key).unwrap_or_default();
+        recent.retain(|kept| kept.height + self.max_rollback_depth >= height);
+        recent.push(block.clone());
+        self.recent_finalized.insert(key, &recent);
+        self.finalized.insert(key, &block);
+    }
+}
+
+/// `finalized_rollback` event: heights above `to_height`, up to
+/// `from_height`, are no longer final.
+pub(crate) fn log_rollback(chain: &str, from_height: u64, to_height: u64, reason: &str) {
+    let event = json!({
+        "standard": "light-client",
+        "version": "1.0.0",
+        "event": "finalized_rollback",
+        "data": [{
+            "chain": chain,
+            "from_height": from_height,
+            "to_height": to_height,
+            "reason": reason,
+        }],
+    });
+    env::log_str(&format!("EVENT_JSON:{}", event));
+}
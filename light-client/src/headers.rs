@@ -0,0 +1,205 @@
+//! Block headers submitted by updaters, per chain and height. A chain's
+//! headers start at its checkpoint (`checkpoint.rs`) and each must link
+//! to the stored one below it. Only the last
+//! `header_retention` heights below a chain's latest header are kept:
+//! older ones read as absent and are deleted a few at a time as new
+//! headers come in.
+//!
+//! Hashes are 32 bytes: hex for ETH and BTC (BTC in the usual reversed
+//! display order), base58 for SOL. Proofs that commit to a block hash
+//! (ETH headers, BTC SPV blocks) must match the stored header at their
+//! height when there is one.
+
+use crate::btc_tx;
+use crate::eth::parse_hex;
+use crate::*;
+
+/// Keep the last 50,000 heights per chain.
+pub const DEFAULT_HEADER_RETENTION: u64 = 50_000;
+
+/// Expired heights deleted per `submit_header`.
+const MAX_PRUNED_PER_SUBMIT: u64 = 32;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeaderRecord {
+    pub height: u64,
+    pub block_hash: String,
+    /// Empty for the checkpoint.
+    pub parent_hash: String,
+    pub submitted_by: AccountId,
+    /// Block timestamp (ns) of the submission.
+    pub submitted_at: u64,
+}
+
+/// Lowest height not yet pruned, and the latest height stored.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct HeaderRange {
+    pub oldest: u64,
+    pub latest: u64,
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// An updater stores a block header on top of a stored parent. Heights
+    /// at or below the chain's finalized height are fixed once stored;
+    /// above it, a header can be replaced (a reorg), dropping the headers
+    /// above it.
+    pub fn submit_header(&mut self, chain_type: ChainType, height: u64, block_hash: String, parent_hash: String) {
+        assert!(
+            self.updaters.contains(&env::predecessor_account_id()),
+            "Only updaters can submit headers"
+        );
+        let chain = chain_key(&chain_type);
+        let block_hash = canonical_hash(&chain_type, &block_hash)
+            .unwrap_or_else(|| env::panic_str("Block hash must be 32 bytes"));
+        let parent_hash = canonical_hash(&chain_type, &parent_hash)
+            .unwrap_or_else(|| env::panic_str("Parent hash must be 32 bytes"));
+        let checkpoint = self
+            .checkpoints
+            .get(&chain)
+            .unwrap_or_else(|| env::panic_str(&format!("No checkpoint for {}", chain)));
+        assert!(
+            height > checkpoint.height,
+            "Header must be above the checkpoint at {}",
+            checkpoint.height
+        );
+        let mut range = self.header_ranges.get(&chain).unwrap();
+        assert!(
+            !self.is_expired(&range, height),
+            "Height {} is below the retention window",
+            height
+        );
+
+        if let Some(existing) = self.get_header(chain_type.clone(), height) {
+            if existing.block_hash == block_hash && existing.parent_hash == parent_hash {
+                return;
+            }
+            assert!(
+                height > self.get_finalized_height(chain_type.clone()),
+                "Header at finalized height {} cannot be replaced",
+                height
+            );
+        }
+        let parent = self
+            .get_header(chain_type.clone(), height - 1)
+            .unwrap_or_else(|| env::panic_str(&format!("No stored parent at {}", height - 1)));
+        assert_eq!(
+            parent.block_hash, parent_hash,
+            "Parent hash does not match stored header at {}",
+            parent.height
+        );
+
+        let record = HeaderRecord {
+            height,
+            block_hash,
+            parent_hash,
+            submitted_by: env::predecessor_account_id(),
+            submitted_at: env::block_timestamp(),
+        };
+        self.headers.insert(&header_key(&chain, height), &record);
+        // The new header is the tip: appended, or replacing one whose
+        // descendants no longer link to it.
+        range.latest = height;
+        self.prune_headers(&chain, &mut range);
+        self.header_ranges.insert(&chain, &range);
+        env::log_str(&format!(
+            "HEADER_SUBMITTED:chain={},height={},hash={}",
+            chain, height, record.block_hash
+        ));
+    }
+
+    /// Stored header at `height`, unless it has fallen out of the
+    /// retention window or off the chain.
+    pub fn get_header(&self, chain_type: ChainType, height: u64) -> Option<HeaderRecord> {
+        let chain = chain_key(&chain_type);
+        let range = self.header_ranges.get(&chain)?;
+        if height < range.oldest || height > range.latest || self.is_expired(&range, height) {
+            return None;
+        }
+        self.headers.get(&header_key(&chain, height))
+    }
+
+    pub fn get_latest_header(&self, chain_type: ChainType) -> Option<HeaderRecord> {
+        let chain = chain_key(&chain_type);
+        let range = self.header_ranges.get(&chain)?;
+        self.headers.get(&header_key(&chain, range.latest))
+    }
+
+    /// Owner sets how many heights below the latest header are kept.
+    /// Shrinking it prunes lazily.
+    pub fn set_header_retention(&mut self, heights: u64) {
+        self.assert_owner();
+        assert!(heights > 0, "Retention must keep at least one height");
+        self.header_retention = heights;
+    }
+
+    pub fn get_header_retention(&self) -> u64 {
+        self.header_retention
+    }
+}
+
+impl LightClient {
+    /// Stores `checkpoint` as the chain's only header.
+    pub(crate) fn start_headers(&mut self, chain: &str, checkpoint: &Checkpoint) {
+        let record = HeaderRecord {
+            height: checkpoint.height,
+            block_hash: checkpoint.block_hash.clone(),
+            parent_hash: String::new(),
+            submitted_by: env::predecessor_account_id(),
+            submitted_at: checkpoint.set_at,
+        };
+        self.headers.insert(&header_key(chain, checkpoint.height), &record);
+        self.header_ranges.insert(
+            &chain.to_string(),
+            &HeaderRange { oldest: checkpoint.height, latest: checkpoint.height },
+        );
+    }
+
+    /// Unlinks all of `chain`'s headers and returns the latest height.
+    /// Records are left for later headers to overwrite: deleting up to
+    /// `header_retention` of them would not fit in one call.
+    pub(crate) fn clear_headers(&mut self, chain: &str) -> u64 {
+        self.header_ranges
+            .remove(&chain.to_string())
+            .map_or(0, |range| range.latest)
+    }
+
+    /// Hash of the stored header at `height`, in the same form
+    /// `submit_header` stores it.
+    pub(crate) fn stored_header_hash(&self, chain_type: &ChainType, height: u64) -> Option<String> {
+        self.get_header(chain_type.clone(), height).map(|header| header.block_hash)
+    }
+
+    fn is_expired(&self, range: &HeaderRange, height: u64) -> bool {
+        height.saturating_add(self.header_retention) <= range.latest
+    }
+
+    /// Deletes up to `MAX_PRUNED_PER_SUBMIT` expired heights from the
+    /// bottom of `range`.
+    fn prune_headers(&mut self, chain: &str, range: &mut HeaderRange) {
+        let mut pruned = 0;
+        while pruned < MAX_PRUNED_PER_SUBMIT && self.is_expired(range, range.oldest) {
+            self.headers.remove(&header_key(chain, range.oldest));
+            range.oldest += 1;
+            pruned += 1;
+        }
+    }
+}
+
+fn header_key(chain: &str, height: u64) -> String {
+    format!("{}:{}", chain, height)
+}
+
+/// Lower-case hex without `0x` for ETH and BTC, base58 as given for SOL.
+pub(crate) fn canonical_hash(chain_type: &ChainType, hash: &str) -> Option<String> {
+    match chain_type {
+        ChainType::ETH | ChainType::BTC => {
+            let bytes = parse_hex(hash).filter(|bytes| bytes.len() == 32)?;
+            Some(hex::encode(bytes))
+        }
+        ChainType::SOL => btc_tx::base58_decode(hash)
+            .filter(|bytes| bytes.len() == 32)
+            .map(|_| hash.to_string()),
+    }
+}
@@ -0,0 +1,152 @@
+//! Dedicated height updaters. Besides the updater quorum (`quorum.rs`),
+//! the owner can give a chain one updater account, the hot key that
+//! pushes its finalized height on its own, so the owner key stays
+//! offline for configuration. That account sets heights for its chain
+//! only, and each update must come at least the chain's
+//! `min_interval_ns` after the last and move the height by at most its
+//! `max_step` (by default the global `max_height_step`). The owner can
+//! set any chain's height past both limits. Heights still only move
+//! forward, and BTC still follows its header chain.
+//!
+//! The updater moves the height only: the ETH block hash it sends is not
+//! stored for proofs, so a stolen hot key cannot vouch for a forged
+//! header. Proofs still need a hash from quorum, the owner, or a submitted
+//! header.
+
+use crate::eth::parse_hex;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeightUpdateLimits {
+    /// Least time (ns) between two updates by the chain's updater.
+    pub min_interval_ns: u64,
+    /// Most blocks one update by the chain's updater moves the height.
+    pub max_step: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeightUpdater {
+    pub chain_type: ChainType,
+    pub account_id: AccountId,
+    pub limits: HeightUpdateLimits,
+    /// Block timestamp (ns) of the chain's last direct update, if any.
+    pub last_update_at: Option<u64>,
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Owner assigns (or rotates) the chain's height updater; `None`
+    /// removes it.
+    pub fn set_height_updater(&mut self, chain_type: ChainType, account_id: Option<AccountId>) {
+        self.assert_owner();
+        assert!(chain_type != ChainType::BTC, "BTC height follows submitted headers");
+        let key = chain_key(&chain_type);
+        env::log_str(&format!(
+            "HEIGHT_UPDATER_SET:chain={},updater={}",
+            key,
+            account_id.as_ref().map_or("none", |account_id| account_id.as_str())
+        ));
+        match account_id {
+            Some(account_id) => self.height_updaters.insert(&key, &account_id),
+            None => self.height_updaters.remove(&key),
+        };
+    }
+
+    pub fn set_height_update_limits(&mut self, chain_type: ChainType, min_interval_ns: u64, max_step: u64) {
+        self.assert_owner();
+        assert!(max_step > 0, "Max height step must be positive");
+        self.height_update_limits
+            .insert(&chain_key(&chain_type), &HeightUpdateLimits { min_interval_ns, max_step });
+    }
+
+    /// The chain's updater, or the owner, sets its finalized height to
+    /// `height` with `block_hash` (32 bytes of hex for ETH). Only the
+    /// owner's ETH hash is also stored for proofs.
+    pub fn set_finalized_height(&mut self, chain_type: ChainType, height: u64, block_hash: String) {
+        let caller = env::predecessor_account_id();
+        let key = chain_key(&chain_type);
+        let is_owner = caller == self.owner_id;
+        assert!(
+            is_owner || self.height_updaters.get(&key).as_ref() == Some(&caller),
+            "Only the chain's height updater can set its height"
+        );
+        assert!(chain_type != ChainType::BTC, "BTC height follows submitted headers");
+        let eth_hash = (chain_type == ChainType::ETH).then(|| {
+            parse_hex(&block_hash)
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .unwrap_or_else(|| env::panic_str("Block hash must be 32 bytes of hex"))
+        });
+        let current = self.finalized.get(&key).map(|block| block.height);
+        if let Some(current) = current {
+            assert!(height > current, "Height must be above the finalized height");
+        }
+
+        let now = env::block_timestamp();
+        if !is_owner {
+            assert!(
+                !self.frozen_chains.contains(&key),
+                "{} height is frozen by conflicting reports",
+                key
+            );
+            let limits = self.get_height_update_limits(chain_type.clone());
+            if let Some(last) = self.last_height_updates.get(&key) {
+                assert!(
+                    now.saturating_sub(last) >= limits.min_interval_ns,
+                    "Height updated {} ns ago, under the min interval of {} ns",
+                    now.saturating_sub(last),
+                    limits.min_interval_ns
+                );
+            }
+            if let Some(current) = current {
+                assert!(
+                    height - current <= limits.max_step,
+                    "Height is {} blocks past the finalized height, over the max step of {}",
+                    height - current,
+                    limits.max_step
+                );
+            }
+        }
+
+        self.advance_finalized(&key, height, block_hash.clone());
+        if let Some(hash) = eth_hash.filter(|_| is_owner) {
+            self.eth_block_hashes.insert(&height, &hash);
+        }
+        if let Some(mut reports) = self.height_reports.get(&key) {
+            reports.retain(|report| report.height > height);
+            self.height_reports.insert(&key, &reports);
+        }
+        self.last_height_updates.insert(&key, &now);
+        env::log_str(&format!(
+            "HEIGHT_FINALIZED:chain={},height={},block_hash={},set_by={}",
+            key, height, block_hash, caller
+        ));
+    }
+
+    pub fn get_height_updater(&self, chain_type: ChainType) -> Option<AccountId> {
+        self.height_updaters.get(&chain_key(&chain_type))
+    }
+
+    /// Every assigned height updater, with its chain's limits.
+    pub fn get_height_updaters(&self) -> Vec<HeightUpdater> {
+        [ChainType::ETH, ChainType::SOL]
+            .into_iter()
+            .filter_map(|chain_type| {
+                let key = chain_key(&chain_type);
+                Some(HeightUpdater {
+                    account_id: self.height_updaters.get(&key)?,
+                    limits: self.get_height_update_limits(chain_type.clone()),
+                    last_update_at: self.last_height_updates.get(&key),
+                    chain_type,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_height_update_limits(&self, chain_type: ChainType) -> HeightUpdateLimits {
+        self.height_update_limits
+            .get(&chain_key(&chain_type))
+            .unwrap_or(HeightUpdateLimits { min_interval_ns: 0, max_step: self.max_height_step })
+    }
+}
@@ -0,0 +1,89 @@
+//! Interim inclusion check for SOL proofs, until SOL slots are verified
+//! on their own. The leaf is `sha256(tx_hash)`; each `inclusion_proof`
+//! entry is a 32-byte sibling and the side it sits on (`"L:<hex>"` or
+//! `"R:<hex>"` in JSON), and the fold `sha256(left || right)` must reach
+//! the root an updater registered for the block.
+
+use crate::eth::parse_hex;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub enum Sibling {
+    Left([u8; 32]),
+    Right([u8; 32]),
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// An updater registers the Merkle root of a block's transactions.
+    /// ETH and BTC proofs are checked against their own headers instead.
+    pub fn set_merkle_root(&mut self, chain_type: ChainType, block_height: u64, root: String) {
+        assert!(
+            self.updaters.contains(&env::predecessor_account_id()),
+            "Only updaters can set Merkle roots"
+        );
+        assert!(
+            chain_type == ChainType::SOL,
+            "{:?} proofs are verified against block headers",
+            chain_type
+        );
+        let root = parse_hex(&root)
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .unwrap_or_else(|| env::panic_str("Root must be 32 bytes of hex"));
+        self.merkle_roots.insert(&(chain_key(&chain_type), block_height), &root);
+    }
+
+    pub fn get_merkle_root(&self, chain_type: ChainType, block_height: u64) -> Option<String> {
+        self.merkle_roots
+            .get(&(chain_key(&chain_type), block_height))
+            .map(hex::encode)
+    }
+}
+
+impl LightClient {
+    /// Whether `inclusion_proof` folds `tx_hash` up to the registered
+    /// root of `block_height`.
+    pub(crate) fn check_inclusion(
+        &self,
+        chain_type: &ChainType,
+        block_height: u64,
+        tx_hash: &str,
+        inclusion_proof: &[Sibling],
+    ) -> Result<(), VerifyError> {
+        if inclusion_proof.is_empty() {
+            return Err(VerifyError::EmptyInclusionProof);
+        }
+        let root = self
+            .merkle_roots
+            .get(&(chain_key(chain_type), block_height))
+            .ok_or(VerifyError::UnknownBlock)?;
+        if fold(tx_hash, inclusion_proof) != root {
+            return Err(VerifyError::InclusionProofInvalid);
+        }
+        Ok(())
+    }
+}
+
+/// Root the proof leads to from `tx_hash`.
+pub(crate) fn fold(tx_hash: &str, inclusion_proof: &[Sibling]) -> [u8; 32] {
+    inclusion_proof
+        .iter()
+        .fold(env::sha256_array(tx_hash.as_bytes()), |node, sibling| {
+            let pair = match sibling {
+                Sibling::Left(hash) => [*hash, node].concat(),
+                Sibling::Right(hash) => [node, *hash].concat(),
+            };
+            env::sha256_array(&pair)
+        })
+}
+
+/// A JSON `"L:<hex>"` or `"R:<hex>"` entry, `None` if malformed.
+pub(crate) fn parse_sibling(entry: &str) -> Option<Sibling> {
+    let (side, hash) = entry.split_once(':')?;
+    let hash = <[u8; 32]>::try_from(hex::decode(hash).ok()?).ok()?;
+    match side {
+        "L" => Some(Sibling::Left(hash)),
+        "R" => Some(Sibling::Right(hash)),
+        _ => None,
+    }
+}
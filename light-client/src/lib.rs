@@ -1,9 +1,53 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedSet};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::state::ContractState;
-use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault};
+use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, PromiseOrValue};
+
+mod address;
+mod btc;
+mod btc_tx;
+mod chain_config;
+mod checkpoint;
+mod codec;
+mod consume;
+mod eth;
+mod eth_prover;
+mod finality;
+mod headers;
+mod height_updater;
+mod inclusion;
+mod migration;
+mod mpt;
+mod outcome;
+mod quorum;
+mod rlp;
+mod sol;
+mod sol_tx;
+mod tokens;
+mod u256;
+
+pub use btc::{BtcPaymentProof, BtcTip, BtcTxProof};
+pub use chain_config::ChainConfig;
+pub use checkpoint::Checkpoint;
+pub use codec::{PaymentProofV1, BORSH_CODEC, JSON_CODEC};
+#[cfg(feature = "proof-codec")]
+pub use codec::{encode_borsh, encode_json};
+use btc::{BtcChain, BtcHeader};
+pub use eth::{EthPaymentProof, EthReceiptProof};
+pub use eth_prover::{EthLogProof, EthProver};
+pub use finality::FinalizedBlock;
+pub use headers::HeaderRecord;
+pub use height_updater::{HeightUpdateLimits, HeightUpdater};
+pub use inclusion::Sibling;
+use headers::HeaderRange;
+pub use outcome::{VerifyError, VerifyOutcome};
+pub use quorum::HeightReport;
+pub use sol::{SolPaymentProof, SolTxProof};
+pub use tokens::RegisteredToken;
+use eth::ExpectedPayment;
+use eth_prover::Verdict;
 
 #[derive(
     BorshDeserialize,
@@ -21,50 +65,157 @@ pub enum ChainType {
     SOL,
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+/// What an ETH payment moves: the chain's native coin, or an ERC-20 whose
+/// `contract` is a 20-byte hex address (compared case-insensitively).
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum AssetKind {
+    Native,
+    Erc20 { contract: String },
+}
+
+/// Outcome of `verify_payment_proof`. `block_height` is the height the proof
+/// claims (0 if it could not be parsed); `finalized_height` is the chain's
+/// finalized height at verification time.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
-pub struct PaymentProof {
-    pub chain_type: ChainType,
-    pub tx_hash: String,
-    pub recipient: String,
-    pub asset: String,
-    pub amount: U128,
-    pub memo: String,
+pub struct VerificationResult {
+    pub outcome: VerifyOutcome,
     pub block_height: u64,
-    pub inclusion_proof: Vec<String>,
+    pub finalized_height: u64,
+}
+
+impl VerificationResult {
+    pub fn is_valid(&self) -> bool {
+        self.outcome.is_valid()
+    }
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct LightClient {
     pub owner_id: AccountId,
-    pub finalized_heights: LookupMap<String, u64>,
+    /// Finalized block per chain, set by updater quorum (`quorum.rs`) or,
+    /// for BTC, the header chain; see `finality.rs`.
+    pub finalized: LookupMap<String, FinalizedBlock>,
+    /// Blocks each chain was finalized at within `max_rollback_depth` of
+    /// its current height, oldest first: where a rollback may return to.
+    pub recent_finalized: LookupMap<String, Vec<FinalizedBlock>>,
+    pub max_rollback_depth: u64,
+    pub max_height_step: u64,
+    /// Hashes of finalized ETH blocks by number, see `eth.rs`.
+    pub eth_block_hashes: LookupMap<u64, [u8; 32]>,
+    /// ERC-20 contract of each ETH-chain asset symbol other than `ETH`.
+    pub eth_tokens: LookupMap<String, [u8; 20]>,
+    /// Contract ETH proofs are delegated to, see `eth_prover.rs`.
+    pub eth_prover: Option<AccountId>,
+    /// SPL mint of each SOL-chain asset symbol other than `SOL`.
+    pub sol_mints: LookupMap<String, [u8; 32]>,
+    /// Registered symbols per chain, sorted; see `tokens.rs`.
+    pub token_symbols: LookupMap<String, Vec<String>>,
+    /// BTC header chain, see `btc.rs`: headers by height, heights by hash,
+    /// and the checkpoint and tip heights once anchored.
+    pub btc_headers: LookupMap<u64, BtcHeader>,
+    pub btc_heights: LookupMap<[u8; 32], u64>,
+    pub btc_chain: Option<BtcChain>,
+    /// Proofs spent through `consume_payment_proof`, keyed by chain, tx
+    /// hash and memo, with their block height; see `consume.rs`.
+    pub consumed: LookupMap<String, u64>,
+    /// Contracts allowed to consume proofs.
+    pub consumers: LookupSet<AccountId>,
+    /// Accounts whose agreeing reports move finalized heights.
+    pub updaters: UnorderedSet<AccountId>,
+    pub updater_threshold: u32,
+    pub report_window_ns: u64,
+    /// Pending reports per chain, and chains frozen by a conflict.
+    pub height_reports: LookupMap<String, Vec<HeightReport>>,
+    pub frozen_chains: LookupSet<String>,
+    /// Each chain's dedicated height updater, its limits and the time of
+    /// its last update; see `height_updater.rs`.
+    pub height_updaters: LookupMap<String, AccountId>,
+    pub height_update_limits: LookupMap<String, HeightUpdateLimits>,
+    pub last_height_updates: LookupMap<String, u64>,
+    /// Confirmations a proven block needs per chain (default 1), and
+    /// paused chains; see `chain_config.rs`.
+    pub required_confirmations: LookupMap<String, u64>,
+    pub max_proof_age_blocks: LookupMap<String, u64>,
+    pub paused_chains: LookupSet<String>,
+    /// Transaction roots of SOL blocks for `inclusion_proof`s, see
+    /// `inclusion.rs`.
+    pub merkle_roots: LookupMap<(String, u64), [u8; 32]>,
+    /// Updater-submitted headers keyed `chain:height`, each chain's stored
+    /// range, and how many heights are kept; see `headers.rs`.
+    pub headers: LookupMap<String, HeaderRecord>,
+    pub header_ranges: LookupMap<String, HeaderRange>,
+    pub header_retention: u64,
+    /// Each chain's trusted checkpoint; see `checkpoint.rs`.
+    pub checkpoints: LookupMap<String, Checkpoint>,
 }
 
 impl ContractState for LightClient {}
 
+/// Layout version of the `LightClient` state this code reads and writes.
+/// Bump it, and add a variant to `VersionedLightClient`, whenever a field
+/// of a released layout changes.
+pub const STATE_VERSION: u8 = 1;
+
 #[near_bindgen]
 impl LightClient {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
+        migration::write_state_version();
         Self {
             owner_id,
-            finalized_heights: LookupMap::new(b"h"),
+            finalized: LookupMap::new(b"h"),
+            recent_finalized: LookupMap::new(b"g"),
+            max_rollback_depth: finality::DEFAULT_MAX_ROLLBACK_DEPTH,
+            max_height_step: finality::DEFAULT_MAX_HEIGHT_STEP,
+            eth_block_hashes: LookupMap::new(b"b"),
+            eth_tokens: LookupMap::new(b"t"),
+            eth_prover: None,
+            sol_mints: LookupMap::new(b"l"),
+            token_symbols: LookupMap::new(b"w"),
+            btc_headers: LookupMap::new(b"c"),
+            btc_heights: LookupMap::new(b"d"),
+            btc_chain: None,
+            consumed: LookupMap::new(b"u"),
+            consumers: LookupSet::new(b"k"),
+            updaters: UnorderedSet::new(b"p"),
+            updater_threshold: 1,
+            report_window_ns: quorum::DEFAULT_REPORT_WINDOW_NS,
+            height_reports: LookupMap::new(b"r"),
+            frozen_chains: LookupSet::new(b"f"),
+            height_updaters: LookupMap::new(b"j"),
+            height_update_limits: LookupMap::new(b"q"),
+            last_height_updates: LookupMap::new(b"v"),
+            required_confirmations: LookupMap::new(b"n"),
+            max_proof_age_blocks: LookupMap::new(b"o"),
+            paused_chains: LookupSet::new(b"s"),
+            merkle_roots: LookupMap::new(b"m"),
+            headers: LookupMap::new(b"e"),
+            header_ranges: LookupMap::new(b"i"),
+            header_retention: headers::DEFAULT_HEADER_RETENTION,
+            checkpoints: LookupMap::new(b"a"),
         }
     }
 
-    pub fn set_finalized_height(&mut self, chain_type: ChainType, finalized_height: u64) {
-        self.assert_owner();
-        self.finalized_heights
-            .insert(&chain_key(&chain_type), &finalized_height);
-    }
-
     pub fn get_finalized_height(&self, chain_type: ChainType) -> u64 {
-        self.finalized_heights
+        self.finalized
             .get(&chain_key(&chain_type))
+            .map(|block| block.height)
             .unwrap_or(0)
     }
 
+    /// Check a payment proof. Besides the verdict, reports the height the
+    /// proof claims and the chain's finalized height, so the caller can
+    /// require confirmations on top of finality. `proof_data` is JSON or
+    /// borsh behind a codec byte (`codec.rs`). ETH receipt proofs are
+    /// checked against stored block hashes, BTC proofs against the SPV
+    /// header chain, SOL proofs read from the signed transaction against
+    /// registered roots. `expected_asset_kind` only applies to ETH;
+    /// without it the kind follows from `register_token`. An ETH log proof
+    /// goes to the `eth_prover`, and the verdict comes from a callback
+    /// once the prover has answered.
     pub fn verify_payment_proof(
         &self,
         chain_type: ChainType,
@@ -73,114 +224,2995 @@ impl LightClient {
         expected_asset: String,
         expected_amount: U128,
         expected_memo: String,
-    ) -> bool {
-        let proof: PaymentProof = match near_sdk::serde_json::from_slice(&proof_data) {
-            Ok(value) => value,
-            Err(_) => return false,
+        expected_tx_hash: String,
+        expected_asset_kind: Option<AssetKind>,
+    ) -> PromiseOrValue<VerificationResult> {
+        let expected = ExpectedPayment {
+            recipient: &expected_recipient,
+            asset: &expected_asset,
+            amount: expected_amount.0,
+            memo: &expected_memo,
+            tx_hash: &expected_tx_hash,
+            asset_kind: expected_asset_kind.as_ref(),
+        };
+        match self.payment_verdict(&chain_type, codec::decode(&chain_type, &proof_data), &expected) {
+            Verdict::Final(result) => PromiseOrValue::Value(result),
+            Verdict::Pending(call, pending) => call
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(eth_prover::CALLBACK_GAS)
+                        .on_eth_log_verified(pending.block_height, pending.finalized_height),
+                )
+                .into(),
+        }
+    }
+
+    /// Same checks as `verify_payment_proof`, for the maker's transition
+    /// payment.
+    pub fn verify_transition_proof(
+        &self,
+        chain_type: ChainType,
+        proof_data: Vec<u8>,
+        expected_recipient: String,
+        expected_asset: String,
+        expected_amount: U128,
+        expected_memo: String,
+        expected_tx_hash: String,
+    ) -> PromiseOrValue<VerifyOutcome> {
+        let expected = ExpectedPayment {
+            recipient: &expected_recipient,
+            asset: &expected_asset,
+            amount: expected_amount.0,
+            memo: &expected_memo,
+            tx_hash: &expected_tx_hash,
+            asset_kind: None,
         };
+        match self.payment_verdict(&chain_type, codec::decode(&chain_type, &proof_data), &expected) {
+            Verdict::Final(result) => PromiseOrValue::Value(result.outcome),
+            Verdict::Pending(call, pending) => call
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(eth_prover::CALLBACK_GAS)
+                        .on_eth_transition_log_verified(pending.block_height, pending.finalized_height),
+                )
+                .into(),
+        }
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Only owner can update the light client"
+        );
+    }
+}
 
-        if proof.chain_type != chain_type {
-            return false;
+impl LightClient {
+    pub(crate) fn payment_verdict(
+        &self,
+        chain_type: &ChainType,
+        proof: Option<PaymentProofV1>,
+        expected: &ExpectedPayment,
+    ) -> Verdict {
+        if self.is_chain_paused(chain_type) {
+            env::log_str(&format!("{:?} proofs are paused", chain_type));
+            return Verdict::Final(VerificationResult {
+                outcome: VerifyOutcome::Invalid(VerifyError::ChainPaused),
+                block_height: 0,
+                finalized_height: self.get_finalized_height(chain_type.clone()),
+            });
         }
-        if proof.recipient != expected_recipient {
-            return false;
+        Verdict::Final(match proof {
+            Some(PaymentProofV1::Eth(proof)) => self.eth_verdict(&proof, expected),
+            Some(PaymentProofV1::EthLog(proof)) => return self.prove_eth_log(proof, expected),
+            Some(PaymentProofV1::Btc(proof)) => self.btc_verdict(&proof, expected),
+            Some(PaymentProofV1::Sol(proof)) => self.sol_verdict(&proof, expected),
+            None => {
+                env::log_str(&format!("{:?} proof rejected: {}", chain_type, VerifyError::DeserializeFailed));
+                VerificationResult {
+                    outcome: VerifyOutcome::Invalid(VerifyError::DeserializeFailed),
+                    block_height: 0,
+                    finalized_height: self.get_finalized_height(chain_type.clone()),
+                }
+            }
+        })
+    }
+}
+
+fn chain_key(chain_type: &ChainType) -> String {
+    match chain_type {
+        ChainType::BTC => "BTC".to_string(),
+        ChainType::ETH => "ETH".to_string(),
+        ChainType::SOL => "SOL".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    /// The value of a call answered without a prover.
+    trait Settled<T> {
+        fn settled(self) -> T;
+    }
+
+    impl<T> Settled<T> for PromiseOrValue<T> {
+        fn settled(self) -> T {
+            match self {
+                PromiseOrValue::Value(value) => value,
+                PromiseOrValue::Promise(_) => panic!("Expected a value, not a promise"),
+            }
         }
-        if !proof.asset.eq_ignore_ascii_case(&expected_asset) {
-            return false;
+    }
+
+    fn setup(finalized_height: u64) -> LightClient {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        finalize(&mut client, ChainType::SOL, finalized_height, "sol-block");
+        for block_height in 90..=100 {
+            client.set_merkle_root(ChainType::SOL, block_height, hex::encode(sol_root()));
         }
-        if proof.amount.0 != expected_amount.0 {
-            return false;
+        client
+    }
+
+    /// Root of a two-leaf tree: `sha256(SOL_TX_HASH)` and the sibling
+    /// `[9; 32]`.
+    fn sol_root() -> [u8; 32] {
+        env::sha256_array([env::sha256_array(SOL_TX_HASH.as_bytes()), [9; 32]].concat())
+    }
+
+    /// The owner, as the only updater, reports `height`.
+    fn finalize(client: &mut LightClient, chain_type: ChainType, height: u64, block_hash: &str) {
+        client.add_updater(accounts(0));
+        client.report_finalized_height(chain_type, height, block_hash.to_string());
+    }
+
+    /// Base58 of `[7; 32]`.
+    const SOL_RECIPIENT: &str = "US517G5965aydkZ46HS38QLi7UQiSojurfbQfKCELFx";
+
+    /// A signed legacy transaction from the key `[1; 32]`: a System
+    /// Program transfer of 5 lamports to `SOL_RECIPIENT` and a Memo
+    /// program instruction carrying "memo".
+    const SOL_TX: &str = concat!(
+        "010da1f6503ac7182ffc71b982063e1ac5bbf864dee0ef439e4c9d096cd7d0653df182e16fc2f97380b6463f981433e4",
+        "72d98e8eb5407b1a46e03419bbd74b5000010002048a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf37488",
+        "01b40f6f5c07070707070707070707070707070707070707070707070707070707070707070000000000000000000000",
+        "000000000000000000000000000000000000000000054a535a992921064d24e87160da387c7c35b5ddbc92bb81e41fa8",
+        "404105448d030303030303030303030303030303030303030303030303030303030303030302020200010c0200000005",
+        "000000000000000300046d656d6f",
+    );
+    const SOL_TX_HASH: &str = "GotrJZDygjj3ihY99NN7j99e3VFsVTEin4Rj8xZvTPchodzjZ6xbKhK89k13MMK2PUyXntmeKRCnc1nxdxBrEcP";
+
+    fn proof(block_height: u64) -> Vec<u8> {
+        codec::encode_json(&SolPaymentProof {
+            transaction: SOL_TX.to_string(),
+            block_height,
+            inclusion_proof: vec![format!("R:{}", hex::encode([9; 32]))],
+        })
+    }
+
+    fn verify(client: &LightClient, proof_data: Vec<u8>) -> VerificationResult {
+        client.verify_payment_proof(
+            ChainType::SOL,
+            proof_data,
+            SOL_RECIPIENT.to_string(),
+            "SOL".to_string(),
+            U128(5),
+            "memo".to_string(),
+            SOL_TX_HASH.to_string(),
+            None,
+        ).settled()
+    }
+
+    #[test]
+    fn test_payment_proof_reports_heights() {
+        let client = setup(100);
+        assert_eq!(
+            verify(&client, proof(100)),
+            VerificationResult { outcome: VerifyOutcome::Valid, block_height: 100, finalized_height: 100 }
+        );
+        assert_eq!(
+            verify(&client, proof(94)),
+            VerificationResult { outcome: VerifyOutcome::Valid, block_height: 94, finalized_height: 100 }
+        );
+    }
+
+    #[test]
+    fn test_payment_proof_above_finalized_height_is_invalid() {
+        let mut client = setup(100);
+        client.set_merkle_root(ChainType::SOL, 101, hex::encode(sol_root()));
+        assert_eq!(
+            verify(&client, proof(101)),
+            VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::NotFinalized { proof_height: 101, finalized: 100 }), block_height: 101, finalized_height: 100 }
+        );
+    }
+
+    #[test]
+    fn test_unparsable_payment_proof_is_invalid() {
+        let client = setup(100);
+        assert_eq!(
+            verify(&client, [&[codec::JSON_CODEC][..], b"not json"].concat()),
+            VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::DeserializeFailed), block_height: 0, finalized_height: 100 }
+        );
+    }
+
+    // ------------------------------------------------------------------------
+    // ETH proofs, against a block built here: the tries are real, the
+    // transactions are unsigned stand-ins (only their hashes matter).
+    // ------------------------------------------------------------------------
+
+    const HEIGHT: u64 = 1_000;
+    const RECIPIENT: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+    const TOKEN: &str = "0x2222222222222222222222222222222222222222";
+    const MEMO: &str = "sub:7";
+
+    fn enc_bytes(bytes: &[u8]) -> Vec<u8> {
+        match bytes {
+            [b] if *b < 0x80 => vec![*b],
+            _ => [enc_len(0x80, bytes.len()), bytes.to_vec()].concat(),
         }
-        if proof.memo != expected_memo {
-            return false;
+    }
+
+    fn enc_list(items: &[Vec<u8>]) -> Vec<u8> {
+        let payload = items.concat();
+        [enc_len(0xc0, payload.len()), payload].concat()
+    }
+
+    fn enc_len(offset: u8, len: usize) -> Vec<u8> {
+        if len < 56 {
+            return vec![offset + len as u8];
         }
-        if proof.inclusion_proof.is_empty() {
-            return false;
+        let len_bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        [vec![offset + 55 + len_bytes.len() as u8], len_bytes].concat()
+    }
+
+    fn enc_uint(value: u128) -> Vec<u8> {
+        let bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        enc_bytes(&bytes)
+    }
+
+    fn word(value: u128) -> Vec<u8> {
+        [vec![0u8; 16], value.to_be_bytes().to_vec()].concat()
+    }
+
+    fn addr(value: &str) -> Vec<u8> {
+        hex::decode(value.trim_start_matches("0x")).unwrap()
+    }
+
+    fn keccak(bytes: &[u8]) -> Vec<u8> {
+        env::keccak256(bytes)
+    }
+
+    fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+        let flag = if leaf { 2 } else { 0 } + (nibbles.len() % 2) as u8;
+        let mut all = vec![flag];
+        if nibbles.len().is_multiple_of(2) {
+            all.push(0);
         }
+        all.extend_from_slice(nibbles);
+        all.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect()
+    }
 
-        let finalized_height = self.get_finalized_height(proof.chain_type.clone());
-        if finalized_height == 0 {
-            return false;
+    fn node_ref(node: Vec<u8>) -> Vec<u8> {
+        if node.len() < 32 {
+            node
+        } else {
+            enc_bytes(&keccak(&node))
         }
-        if proof.block_height > finalized_height {
-            return false;
+    }
+
+    /// Encoded trie node over `items` (keys as nibbles), pushing the nodes
+    /// on the way to `target` into `path` leaf first.
+    fn trie_node(items: &[(Vec<u8>, Vec<u8>)], target: Option<&[u8]>, path: &mut Vec<Vec<u8>>) -> Vec<u8> {
+        let node = if items.len() == 1 {
+            enc_list(&[enc_bytes(&hex_prefix(&items[0].0, true)), enc_bytes(&items[0].1)])
+        } else {
+            let first = &items[0].0;
+            let shared = (0..first.len())
+                .take_while(|&i| items.iter().all(|(key, _)| key.len() > i && key[i] == first[i]))
+                .count();
+            if shared > 0 {
+                let rest: Vec<_> = items.iter().map(|(key, value)| (key[shared..].to_vec(), value.clone())).collect();
+                let target = target.filter(|t| t.starts_with(&first[..shared])).map(|t| &t[shared..]);
+                let child = trie_node(&rest, target, path);
+                enc_list(&[enc_bytes(&hex_prefix(&first[..shared], false)), node_ref(child)])
+            } else {
+                let mut slots: Vec<Vec<u8>> = (0..16u8)
+                    .map(|nibble| {
+                        let rest: Vec<_> = items
+                            .iter()
+                            .filter(|(key, _)| key.first() == Some(&nibble))
+                            .map(|(key, value)| (key[1..].to_vec(), value.clone()))
+                            .collect();
+                        if rest.is_empty() {
+                            return vec![0x80];
+                        }
+                        let target = target.filter(|t| t.first() == Some(&nibble)).map(|t| &t[1..]);
+                        node_ref(trie_node(&rest, target, path))
+                    })
+                    .collect();
+                let value = items.iter().find(|(key, _)| key.is_empty()).map_or(vec![], |(_, v)| v.clone());
+                slots.push(enc_bytes(&value));
+                enc_list(&slots)
+            }
+        };
+        if target.is_some() {
+            path.push(node.clone());
         }
+        node
+    }
 
-        // TODO: Replace with real on-chain light client cryptographic verification:
-        // - ETH: header sync + receipt trie inclusion proof.
-        // - SOL: slot commitment sync + transaction inclusion proof.
-        env::log_str(&format!(
-            "Verified proof skeleton for {:?} tx {} at height {} (<= finalized {})",
-            proof.chain_type, proof.tx_hash, proof.block_height, finalized_height
-        ));
-        true
+    /// Root of the trie keyed by `rlp(index)` over `values`, and the proof
+    /// for `index`.
+    fn trie(values: &[Vec<u8>], index: u64) -> ([u8; 32], Vec<String>) {
+        let nibbles = |key: Vec<u8>| key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect::<Vec<u8>>();
+        let items: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (nibbles(rlp::encode_uint(i as u64)), value.clone()))
+            .collect();
+        let mut path = Vec::new();
+        let root = trie_node(&items, Some(&nibbles(rlp::encode_uint(index))), &mut path);
+        path.reverse();
+        let proof = path
+            .into_iter()
+            .enumerate()
+            .filter(|(i, node)| *i == 0 || node.len() >= 32)
+            .map(|(_, node)| hex::encode(node))
+            .collect();
+        (env::keccak256_array(&root), proof)
     }
 
-    pub fn verify_transition_proof(
-        &self,
-        chain_type: ChainType,
-        proof_data: Vec<u8>,
-        expected_recipient: String,
-        expected_asset: String,
-        expected_amount: U128,
-        expected_memo: String,
-        expected_tx_hash: String,
-    ) -> bool {
-        let proof: PaymentProof = match near_sdk::serde_json::from_slice(&proof_data) {
-            Ok(value) => value,
-            Err(_) => return false,
+    fn eip1559_tx(nonce: u128, to: &[u8], value: u128, data: &[u8]) -> Vec<u8> {
+        let fields = [
+            enc_uint(1),
+            enc_uint(nonce),
+            enc_uint(1),
+            enc_uint(100),
+            enc_uint(21_000),
+            enc_bytes(to),
+            enc_uint(value),
+            enc_bytes(data),
+            enc_list(&[]),
+            enc_uint(1),
+            enc_bytes(&[7; 32]),
+            enc_bytes(&[9; 32]),
+        ];
+        [vec![0x02], enc_list(&fields)].concat()
+    }
+
+    fn receipt(success: bool, logs: &[Vec<u8>]) -> Vec<u8> {
+        let status = if success { enc_uint(1) } else { enc_bytes(&[]) };
+        let fields = [status, enc_uint(21_000), enc_bytes(&[0; 256]), enc_list(logs)];
+        [vec![0x02], enc_list(&fields)].concat()
+    }
+
+    fn transfer_log(token: &str, to: &str, amount: u128) -> Vec<u8> {
+        let topics = [
+            enc_bytes(&keccak(b"Transfer(address,address,uint256)")),
+            enc_bytes(&[[0u8; 12].to_vec(), addr("0x3333333333333333333333333333333333333333")].concat()),
+            enc_bytes(&[[0u8; 12].to_vec(), addr(to)].concat()),
+        ];
+        enc_list(&[enc_bytes(&addr(token)), enc_list(&topics), enc_bytes(&word(amount))])
+    }
+
+    fn transfer_calldata(to: &str, amount: u128, memo: &str) -> Vec<u8> {
+        let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+        data.extend([[0u8; 12].to_vec(), addr(to)].concat());
+        data.extend(word(amount));
+        data.extend(memo.as_bytes());
+        data
+    }
+
+    fn header(transactions_root: [u8; 32], receipts_root: [u8; 32], number: u64) -> Vec<u8> {
+        child_header(&[0; 32], transactions_root, receipts_root, number)
+    }
+
+    fn child_header(parent_hash: &[u8], transactions_root: [u8; 32], receipts_root: [u8; 32], number: u64) -> Vec<u8> {
+        enc_list(&[
+            enc_bytes(parent_hash),
+            enc_bytes(&[0; 32]),
+            enc_bytes(&[0; 20]),
+            enc_bytes(&[0; 32]),
+            enc_bytes(&transactions_root),
+            enc_bytes(&receipts_root),
+            enc_bytes(&[0; 256]),
+            enc_uint(0),
+            enc_uint(number as u128),
+            enc_uint(30_000_000),
+            enc_uint(63_000),
+            enc_uint(1_700_000_000),
+            enc_bytes(&[]),
+            enc_bytes(&[0; 32]),
+            enc_bytes(&[0; 8]),
+            enc_uint(7),
+        ])
+    }
+
+    /// A block of three transactions: 0 pays 500 wei of native ETH, 1 pays
+    /// 42 of the token, 2 is an unrelated failed call.
+    struct Block {
+        txs: Vec<Vec<u8>>,
+        receipts: Vec<Vec<u8>>,
+    }
+
+    impl Block {
+        fn new() -> Self {
+            let txs = vec![
+                eip1559_tx(0, &addr(RECIPIENT), 500, MEMO.as_bytes()),
+                eip1559_tx(1, &addr(TOKEN), 0, &transfer_calldata(RECIPIENT, 42, MEMO)),
+                eip1559_tx(2, &addr(TOKEN), 0, &[]),
+            ];
+            let receipts = vec![
+                receipt(true, &[]),
+                receipt(true, &[transfer_log(TOKEN, RECIPIENT, 42)]),
+                receipt(false, &[]),
+            ];
+            Block { txs, receipts }
+        }
+
+        fn header(&self) -> Vec<u8> {
+            let (tx_root, _) = trie(&self.txs, 0);
+            let (receipt_root, _) = trie(&self.receipts, 0);
+            header(tx_root, receipt_root, HEIGHT)
+        }
+
+        fn proof(&self, index: u64) -> EthPaymentProof {
+            let (_, transaction_proof) = trie(&self.txs, index);
+            let (_, receipt_proof) = trie(&self.receipts, index);
+            EthPaymentProof {
+                header: format!("0x{}", hex::encode(self.header())),
+                receipt_index: index,
+                receipt: hex::encode(&self.receipts[index as usize]),
+                receipt_proof,
+                transaction: hex::encode(&self.txs[index as usize]),
+                transaction_proof,
+                asset_kind: match index {
+                    0 => AssetKind::Native,
+                    _ => AssetKind::Erc20 { contract: TOKEN.to_string() },
+                },
+            }
+        }
+
+        fn tx_hash(&self, index: usize) -> String {
+            format!("0x{}", hex::encode(keccak(&self.txs[index])))
+        }
+    }
+
+    /// Light client finalized at `HEIGHT` that knows `block` and the token.
+    fn eth_setup(block: &Block) -> LightClient {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        finalize(&mut client, ChainType::ETH, HEIGHT, &hex::encode(keccak(&block.header())));
+        client.set_eth_token("usdc".to_string(), Some(TOKEN.to_string()));
+        client
+    }
+
+    fn verify_eth(client: &LightClient, proof: &EthPaymentProof, asset: &str, amount: u128, tx_hash: &str) -> VerificationResult {
+        client.verify_payment_proof(
+            ChainType::ETH,
+            codec::encode_json(proof),
+            RECIPIENT.to_string(),
+            asset.to_string(),
+            U128(amount),
+            MEMO.to_string(),
+            tx_hash.to_string(),
+            None,
+        ).settled()
+    }
+
+    fn proven(block_height: u64) -> VerificationResult {
+        VerificationResult { outcome: VerifyOutcome::Valid, block_height, finalized_height: HEIGHT }
+    }
+
+    fn rejected(block_height: u64, error: VerifyError) -> VerificationResult {
+        VerificationResult { outcome: VerifyOutcome::Invalid(error), block_height, finalized_height: HEIGHT }
+    }
+
+    #[test]
+    fn test_eth_native_payment_is_proven() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        assert_eq!(verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0)), proven(HEIGHT));
+    }
+
+    #[test]
+    fn test_eth_token_payment_is_proven_from_transfer_log() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        assert_eq!(verify_eth(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1)), proven(HEIGHT));
+        assert_eq!(client.get_eth_token("USDC".to_string()), Some(TOKEN.to_string()));
+    }
+
+    #[test]
+    fn test_eth_payment_fields_come_from_the_proven_data() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let proof = block.proof(1);
+        assert_eq!(verify_eth(&client, &proof, "USDC", 41, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::AmountMismatch));
+        assert_eq!(verify_eth(&client, &proof, "ETH", 42, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::AssetMismatch));
+        assert_eq!(verify_eth(&client, &block.proof(0), "ETH", 501, &block.tx_hash(0)), rejected(HEIGHT, VerifyError::AmountMismatch));
+        let other_recipient = client.verify_payment_proof(
+            ChainType::ETH,
+            codec::encode_json(&proof),
+            TOKEN.to_string(),
+            "USDC".to_string(),
+            U128(42),
+            MEMO.to_string(),
+            block.tx_hash(1),
+            None,
+        ).settled();
+        assert!(!other_recipient.is_valid());
+        let other_memo = client.verify_payment_proof(
+            ChainType::ETH,
+            codec::encode_json(&proof),
+            RECIPIENT.to_string(),
+            "USDC".to_string(),
+            U128(42),
+            "sub:8".to_string(),
+            block.tx_hash(1),
+            None,
+        ).settled();
+        assert!(!other_memo.is_valid());
+    }
+
+    #[test]
+    fn test_eth_proof_is_bound_to_its_tx_hash() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        assert_eq!(verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::TxHashMismatch));
+    }
+
+    #[test]
+    fn test_eth_failed_receipt_is_rejected() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        assert_eq!(verify_eth(&client, &block.proof(2), "USDC", 0, &block.tx_hash(2)), rejected(HEIGHT, VerifyError::TransactionFailed));
+    }
+
+    #[test]
+    fn test_eth_receipt_must_be_in_the_trie() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        // Receipt of another index under this index's proof
+        let mut proof = block.proof(0);
+        proof.receipt = block.proof(1).receipt;
+        assert_eq!(verify_eth(&client, &proof, "ETH", 500, &block.tx_hash(0)), rejected(HEIGHT, VerifyError::InclusionProofInvalid));
+
+        // A tampered trie node
+        let mut proof = block.proof(0);
+        let last = proof.receipt_proof.last_mut().unwrap();
+        *last = last.replacen("01", "02", 1);
+        assert_eq!(verify_eth(&client, &proof, "ETH", 500, &block.tx_hash(0)), rejected(HEIGHT, VerifyError::InclusionProofInvalid));
+
+        // Another index's proof nodes
+        let mut proof = block.proof(0);
+        proof.receipt_proof = block.proof(1).receipt_proof;
+        assert_eq!(verify_eth(&client, &proof, "ETH", 500, &block.tx_hash(0)), rejected(HEIGHT, VerifyError::InclusionProofInvalid));
+    }
+
+    #[test]
+    fn test_eth_header_must_match_stored_hash() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let mut other = Block::new();
+        other.txs[0] = eip1559_tx(0, &addr(RECIPIENT), 5_000, MEMO.as_bytes());
+        assert_eq!(verify_eth(&client, &other.proof(0), "ETH", 5_000, &other.tx_hash(0)), rejected(HEIGHT, VerifyError::HeaderMismatch));
+    }
+
+    #[test]
+    fn test_eth_unknown_or_unfinalized_block_is_rejected() {
+        let block = Block::new();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        finalize(&mut client, ChainType::ETH, HEIGHT - 1, &hex::encode([1; 32]));
+        client.set_eth_block_hash(HEIGHT, hex::encode(keccak(&block.header())));
+        assert_eq!(
+            verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0)),
+            VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::NotFinalized { proof_height: HEIGHT, finalized: HEIGHT - 1 }), block_height: HEIGHT, finalized_height: HEIGHT - 1 }
+        );
+
+        finalize(&mut client, ChainType::ETH, HEIGHT, &hex::encode(keccak(&block.header())));
+
+        // Same tries under a header for a block whose hash was never stored
+        let mut proof = block.proof(0);
+        let (tx_root, _) = trie(&block.txs, 0);
+        let (receipt_root, _) = trie(&block.receipts, 0);
+        proof.header = hex::encode(header(tx_root, receipt_root, HEIGHT - 5));
+        assert_eq!(verify_eth(&client, &proof, "ETH", 500, &block.tx_hash(0)), rejected(HEIGHT - 5, VerifyError::UnknownBlock));
+    }
+
+    #[test]
+    fn test_eth_unmapped_token_is_rejected() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.set_eth_token("USDC".to_string(), None);
+        assert_eq!(verify_eth(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::UnregisteredToken));
+    }
+
+    #[test]
+    fn test_eth_legacy_json_proof_is_rejected() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let legacy = codec::encode_json(&near_sdk::serde_json::json!({
+            "chain_type": "ETH",
+            "tx_hash": block.tx_hash(0),
+            "recipient": RECIPIENT,
+            "asset": "ETH",
+            "amount": "500",
+            "memo": MEMO,
+            "block_height": HEIGHT,
+            "inclusion_proof": ["leaf"],
+        }));
+        let result = client.verify_payment_proof(
+            ChainType::ETH,
+            legacy,
+            RECIPIENT.to_string(),
+            "ETH".to_string(),
+            U128(500),
+            MEMO.to_string(),
+            block.tx_hash(0),
+            None,
+        ).settled();
+        assert_eq!(result, rejected(0, VerifyError::DeserializeFailed));
+    }
+
+    #[test]
+    fn test_eth_transition_proof_uses_the_same_checks() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let transition = |amount: u128| {
+            client.verify_transition_proof(
+                ChainType::ETH,
+                codec::encode_json(&block.proof(0)),
+                RECIPIENT.to_string(),
+                "ETH".to_string(),
+                U128(amount),
+                MEMO.to_string(),
+                block.tx_hash(0),
+            ).settled()
         };
+        assert_eq!(transition(500), VerifyOutcome::Valid);
+        assert_eq!(transition(499), VerifyOutcome::Invalid(VerifyError::AmountMismatch));
+    }
 
-        if proof.chain_type != chain_type {
-            return false;
+    #[test]
+    fn test_mpt_walks_nodes_embedded_in_their_parent() {
+        // Values this small keep every node under 32 bytes
+        let values: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i + 1]).collect();
+        for index in 0..3u64 {
+            let (root, proof) = trie(&values, index);
+            let proof: Vec<Vec<u8>> = proof.iter().map(|node| hex::decode(node).unwrap()).collect();
+            assert_eq!(proof.len(), 1);
+            assert_eq!(
+                mpt::verify_proof(&root, &rlp::encode_uint(index), &proof),
+                Some(values[index as usize].clone())
+            );
         }
-        if proof.tx_hash != expected_tx_hash {
-            return false;
+        let (root, proof) = trie(&values, 0);
+        let proof: Vec<Vec<u8>> = proof.iter().map(|node| hex::decode(node).unwrap()).collect();
+        assert_eq!(mpt::verify_proof(&root, &rlp::encode_uint(5), &proof), None);
+    }
+
+    #[test]
+    fn test_rlp_rejects_truncated_input() {
+        assert_eq!(rlp::encode_uint(0), vec![0x80]);
+        assert_eq!(rlp::encode_uint(0x7f), vec![0x7f]);
+        assert_eq!(rlp::encode_uint(0x80), vec![0x81, 0x80]);
+        assert!(rlp::decode(&[0x83, 1, 2]).is_none());
+        assert!(rlp::decode(&[0xf8]).is_none());
+        assert!(rlp::decode_exact(&[0x01, 0x02]).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_set_eth_block_hash_owner_only() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_eth_block_hash(HEIGHT, hex::encode([0u8; 32]));
+    }
+
+    // ------------------------------------------------------------------------
+    // BTC SPV. Mainnet blocks 0-2 and the genesis coinbase are real; the
+    // payment blocks are mined here at a trivial difficulty anchored by an
+    // owner checkpoint.
+    // ------------------------------------------------------------------------
+
+    const GENESIS: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    const MAINNET_1: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+    const MAINNET_2: &str = "010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61";
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    const EASY_BITS: u32 = 0x207f_ffff;
+    /// BIP173 test vector; pays `0014751e76e8199196d454941c45d1b3a323f1433bd6`.
+    const BTC_RECIPIENT: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const SATS: u128 = 50_000;
+
+    fn btc_client(checkpoint: &str) -> LightClient {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        client.set_btc_checkpoint(0, checkpoint.to_string());
+        client
+    }
+
+    fn mine(prev: [u8; 32], merkle_root: [u8; 32], time: u32) -> Vec<u8> {
+        (0u32..)
+            .map(|nonce| {
+                [&1u32.to_le_bytes()[..], &prev, &merkle_root, &time.to_le_bytes(), &EASY_BITS.to_le_bytes(), &nonce.to_le_bytes()]
+                    .concat()
+            })
+            .find(|header| btc::meets_target(&btc::sha256d(header), EASY_BITS))
+            .unwrap()
+    }
+
+    /// Headers mined on top of `prev`, one per merkle root.
+    fn mine_chain(prev: &[u8], roots: &[[u8; 32]], time: u32) -> Vec<Vec<u8>> {
+        let mut prev = btc::sha256d(prev);
+        roots
+            .iter()
+            .enumerate()
+            .map(|(i, root)| {
+                let header = mine(prev, *root, time + 600 * i as u32);
+                prev = btc::sha256d(&header);
+                header
+            })
+            .collect()
+    }
+
+    fn hexes(headers: &[Vec<u8>]) -> Vec<String> {
+        headers.iter().map(hex::encode).collect()
+    }
+
+    /// One-input transaction with the given outputs, optionally with a
+    /// witness.
+    fn btc_transaction(seed: u8, outputs: &[(u64, Vec<u8>)], witness: bool) -> Vec<u8> {
+        let mut tx = 2u32.to_le_bytes().to_vec();
+        if witness {
+            tx.extend([0x00, 0x01]);
         }
-        if proof.recipient != expected_recipient {
-            return false;
+        tx.push(1);
+        tx.extend([seed; 32]);
+        tx.extend(0u32.to_le_bytes());
+        tx.push(0);
+        tx.extend(u32::MAX.to_le_bytes());
+        tx.push(outputs.len() as u8);
+        for (value, script) in outputs {
+            tx.extend(value.to_le_bytes());
+            tx.push(script.len() as u8);
+            tx.extend(script);
         }
-        if !proof.asset.eq_ignore_ascii_case(&expected_asset) {
-            return false;
+        if witness {
+            tx.extend([0x02, 0x03, 0xaa, 0xbb, 0xcc, 0x01, 0xdd]);
         }
-        if proof.amount.0 != expected_amount.0 {
-            return false;
+        tx.extend(0u32.to_le_bytes());
+        tx
+    }
+
+    fn op_return(memo: &str) -> Vec<u8> {
+        [&[0x6a, memo.len() as u8], memo.as_bytes()].concat()
+    }
+
+    fn txid(tx: &[u8]) -> [u8; 32] {
+        btc_tx::decode(tx).unwrap().txid
+    }
+
+    fn display(hash: [u8; 32]) -> String {
+        let mut reversed = hash;
+        reversed.reverse();
+        hex::encode(reversed)
+    }
+
+    /// Merkle root of `txids` and the branch of the one at `index`.
+    fn btc_tree(txids: &[[u8; 32]], index: usize) -> ([u8; 32], Vec<String>) {
+        let (mut level, mut index, mut branch) = (txids.to_vec(), index, vec![]);
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                level.push(*level.last().unwrap());
+            }
+            branch.push(hex::encode(level[index ^ 1]));
+            level = level.chunks(2).map(|pair| btc::sha256d(&[pair[0], pair[1]].concat())).collect();
+            index /= 2;
         }
-        if proof.memo != expected_memo {
-            return false;
+        (level[0], branch)
+    }
+
+    /// Checkpoint at 0, block 1 holding `txs`, then two empty blocks.
+    struct BtcFixture {
+        client: LightClient,
+        checkpoint: Vec<u8>,
+        headers: Vec<Vec<u8>>,
+        txs: Vec<Vec<u8>>,
+    }
+
+    impl BtcFixture {
+        fn new() -> Self {
+            let payment = vec![
+                (1_000, vec![0x51]),
+                (SATS as u64, hex::decode("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap()),
+                (0, op_return(MEMO)),
+            ];
+            let txs = vec![
+                btc_transaction(1, &[(625_000_000, vec![0x51])], false),
+                btc_transaction(2, &[(7, vec![0x51])], false),
+                btc_transaction(3, &payment, true),
+                btc_transaction(4, &[(9, vec![0x51])], false),
+            ];
+            let txids: Vec<[u8; 32]> = txs.iter().map(|tx| txid(tx)).collect();
+            let checkpoint = mine([0; 32], [0; 32], 1_700_000_000);
+            let headers = mine_chain(&checkpoint, &[btc_tree(&txids, 0).0, [1; 32], [2; 32]], 1_700_000_600);
+            let mut client = btc_client(&hex::encode(&checkpoint));
+            assert_eq!(client.submit_btc_headers(hexes(&headers)), 3);
+            BtcFixture { client, checkpoint, headers, txs }
         }
-        if proof.inclusion_proof.is_empty() {
-            return false;
+
+        fn proof(&self, index: usize) -> BtcPaymentProof {
+            let txids: Vec<[u8; 32]> = self.txs.iter().map(|tx| txid(tx)).collect();
+            BtcPaymentProof {
+                tx: hex::encode(&self.txs[index]),
+                tx_index: index as u32,
+                merkle_branch: btc_tree(&txids, index).1,
+                block_height: 1,
+            }
         }
 
-        let finalized_height = self.get_finalized_height(proof.chain_type.clone());
-        if finalized_height == 0 {
-            return false;
+        fn verify(&self, proof: &BtcPaymentProof, recipient: &str, amount: u128, memo: &str) -> VerificationResult {
+            self.client.verify_payment_proof(
+                ChainType::BTC,
+                codec::encode_json(proof),
+                recipient.to_string(),
+                "BTC".to_string(),
+                U128(amount),
+                memo.to_string(),
+                display(txid(&hex::decode(&proof.tx).unwrap())),
+                None,
+            ).settled()
         }
-        if proof.block_height > finalized_height {
-            return false;
+    }
+
+    fn btc_rejected(block_height: u64, error: VerifyError) -> VerificationResult {
+        VerificationResult { outcome: VerifyOutcome::Invalid(error), block_height, finalized_height: 3 }
+    }
+
+    #[test]
+    fn test_btc_mainnet_headers_extend_the_genesis_checkpoint() {
+        let mut client = btc_client(GENESIS);
+        assert_eq!(client.submit_btc_headers(vec![MAINNET_1.to_string(), MAINNET_2.to_string()]), 2);
+        assert_eq!(
+            client.get_btc_block_hash(0).unwrap(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert_eq!(
+            client.get_btc_block_hash(1).unwrap(),
+            "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048"
+        );
+        assert_eq!(
+            client.get_btc_tip(),
+            Some(BtcTip {
+                height: 2,
+                hash: "000000006a625f06636b8bb6ac7b960a8d03705d1ace08b1a19da3fdcc99ddbd".to_string(),
+                // 0x100010001 per block at the initial difficulty
+                chain_work: U128(3 * 0x1_0001_0001),
+            })
+        );
+        assert_eq!(client.get_finalized_height(ChainType::BTC), 2);
+        assert_eq!(client.get_btc_block_hash(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Header at height 1 does not meet its proof-of-work target")]
+    fn test_btc_header_without_proof_of_work_is_rejected() {
+        let mut client = btc_client(GENESIS);
+        let mut header = hex::decode(MAINNET_1).unwrap();
+        header[79] ^= 1;
+        client.submit_btc_headers(vec![hex::encode(header)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "First header does not build on a stored header")]
+    fn test_btc_header_must_build_on_the_stored_chain() {
+        let mut client = btc_client(GENESIS);
+        client.submit_btc_headers(vec![MAINNET_2.to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Header at height 2 does not link to its parent")]
+    fn test_btc_batch_headers_must_link() {
+        let mut client = btc_client(GENESIS);
+        client.submit_btc_headers(vec![MAINNET_1.to_string(), MAINNET_1.to_string()]);
+    }
+
+    #[test]
+    fn test_btc_genesis_coinbase_folds_to_the_genesis_merkle_root() {
+        let tx = btc_tx::decode(&hex::decode(GENESIS_COINBASE).unwrap()).unwrap();
+        assert_eq!(display(tx.txid), "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        let header = hex::decode(GENESIS).unwrap();
+        assert_eq!(btc::merkle_root(tx.txid, 0, &[]).unwrap(), header[36..68]);
+        assert_eq!(btc::merkle_root(tx.txid, 1, &[]), None);
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(tx.outputs[0].value, 5_000_000_000);
+    }
+
+    #[test]
+    fn test_btc_retarget_matches_bitcoin_core_vectors() {
+        // Bitcoin Core's pow_tests: periods ending at 32255, 2015 (clamped
+        // to the limit), 68543 (quarter clamp) and 48383 (4x clamp).
+        assert_eq!(btc::next_bits(0x1d00ffff, 1261130161, 1262152739), 0x1d00d86a);
+        assert_eq!(btc::next_bits(0x1d00ffff, 1231006505, 1233061996), 0x1d00ffff);
+        assert_eq!(btc::next_bits(0x1c05a3f4, 1279008237, 1279297671), 0x1c0168fd);
+        assert_eq!(btc::next_bits(0x1c387f6f, 1263163443, 1269211443), 0x1d00e1fd);
+    }
+
+    #[test]
+    #[should_panic(expected = "Header at height 2016 has bits 0x207fffff, expected 0x1d00ffff")]
+    fn test_btc_retarget_boundary_requires_the_new_bits() {
+        let checkpoint = mine([0; 32], [0; 32], 1_700_000_000);
+        let mut client = btc_client(&hex::encode(&checkpoint));
+        let headers = mine_chain(&checkpoint, &[[0; 32]; 2016], 1_700_000_600);
+        // A fresh context per call, as each would be its own transaction
+        let call = || testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        for batch in headers[..2015].chunks(btc::MAX_HEADERS_PER_CALL) {
+            call();
+            client.submit_btc_headers(hexes(batch));
         }
+        assert_eq!(client.get_btc_tip().unwrap().height, 2015);
+        // The easy target is above the limit the retarget clamps to
+        call();
+        client.submit_btc_headers(hexes(&headers[2015..]));
+    }
 
-        env::log_str(&format!(
-            "Verified transition skeleton for {:?} tx {} at height {}",
-            proof.chain_type, proof.tx_hash, proof.block_height
-        ));
-        true
+    #[test]
+    #[should_panic(expected = "Header at height 1 has bits 0x1d00ffff, expected 0x207fffff")]
+    fn test_btc_header_must_keep_the_period_bits() {
+        let checkpoint = mine([0; 32], [0; 32], 1_700_000_000);
+        let mut client = btc_client(&hex::encode(&checkpoint));
+        let mut header = mine_chain(&checkpoint, &[[0; 32]], 1_700_000_600).remove(0);
+        header[72..76].copy_from_slice(&0x1d00ffffu32.to_le_bytes());
+        client.submit_btc_headers(vec![hex::encode(header)]);
     }
 
-    fn assert_owner(&self) {
+    #[test]
+    fn test_btc_reorg_to_the_chain_with_more_work() {
+        let mut fixture = BtcFixture::new();
+        let old_hash = fixture.client.get_btc_block_hash(2).unwrap();
+        let fork = mine_chain(&fixture.headers[0], &[[7; 32], [8; 32], [9; 32]], 1_700_001_000);
+        assert_eq!(fixture.client.submit_btc_headers(hexes(&fork)), 4);
+        assert_eq!(fixture.client.get_btc_block_hash(2).unwrap(), display(btc::sha256d(&fork[0])));
+        assert_ne!(fixture.client.get_btc_block_hash(2).unwrap(), old_hash);
+        assert_eq!(fixture.client.get_finalized_height(ChainType::BTC), 4);
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"finalized_rollback\"") && log.contains("\"to_height\":1")));
+        // Block 1 is shared, so its payment still proves
+        assert!(fixture.verify(&fixture.proof(2), BTC_RECIPIENT, SATS, MEMO).is_valid());
+    }
+
+    #[test]
+    #[should_panic(expected = "Headers do not add work to the best chain")]
+    fn test_btc_fork_without_more_work_is_rejected() {
+        let mut fixture = BtcFixture::new();
+        let fork = mine_chain(&fixture.headers[0], &[[7; 32], [8; 32]], 1_700_001_000);
+        fixture.client.submit_btc_headers(hexes(&fork));
+    }
+
+    #[test]
+    fn test_btc_payment_is_proven_by_merkle_branch() {
+        let fixture = BtcFixture::new();
         assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "Only owner can update finalized heights"
+            fixture.verify(&fixture.proof(2), BTC_RECIPIENT, SATS, MEMO),
+            VerificationResult { outcome: VerifyOutcome::Valid, block_height: 1, finalized_height: 3 }
         );
+        let transition = fixture.client.verify_transition_proof(
+            ChainType::BTC,
+            codec::encode_json(&fixture.proof(2)),
+            BTC_RECIPIENT.to_string(),
+            "BTC".to_string(),
+            U128(SATS),
+            MEMO.to_string(),
+            display(txid(&fixture.txs[2])),
+        ).settled();
+        assert!(transition.is_valid());
     }
-}
 
-fn chain_key(chain_type: &ChainType) -> String {
-    match chain_type {
-        ChainType::BTC => "BTC".to_string(),
-        ChainType::ETH => "ETH".to_string(),
-        ChainType::SOL => "SOL".to_string(),
+    #[test]
+    fn test_btc_payment_outputs_must_match() {
+        let fixture = BtcFixture::new();
+        let proof = fixture.proof(2);
+        assert_eq!(fixture.verify(&proof, BTC_RECIPIENT, SATS + 1, MEMO), btc_rejected(1, VerifyError::AmountMismatch));
+        assert_eq!(fixture.verify(&proof, BTC_RECIPIENT, SATS, "sub:8"), btc_rejected(1, VerifyError::MemoMismatch));
+        assert_eq!(fixture.verify(&proof, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", SATS, MEMO), btc_rejected(1, VerifyError::RecipientMismatch));
+        // A transaction in the block that does not pay
+        assert_eq!(fixture.verify(&fixture.proof(1), BTC_RECIPIENT, SATS, MEMO), btc_rejected(1, VerifyError::RecipientMismatch));
+    }
+
+    #[test]
+    fn test_btc_payment_must_be_in_the_claimed_block() {
+        let fixture = BtcFixture::new();
+        let mut wrong_index = fixture.proof(2);
+        wrong_index.tx_index = 3;
+        assert_eq!(fixture.verify(&wrong_index, BTC_RECIPIENT, SATS, MEMO), btc_rejected(1, VerifyError::InclusionProofInvalid));
+
+        let mut wrong_block = fixture.proof(2);
+        wrong_block.block_height = 2;
+        assert_eq!(fixture.verify(&wrong_block, BTC_RECIPIENT, SATS, MEMO), btc_rejected(2, VerifyError::InclusionProofInvalid));
+
+        let mut unknown_block = fixture.proof(2);
+        unknown_block.block_height = 4;
+        assert_eq!(fixture.verify(&unknown_block, BTC_RECIPIENT, SATS, MEMO), btc_rejected(4, VerifyError::UnknownBlock));
+
+        let mut short_branch = fixture.proof(2);
+        short_branch.merkle_branch.pop();
+        assert_eq!(fixture.verify(&short_branch, BTC_RECIPIENT, SATS, MEMO), btc_rejected(1, VerifyError::InclusionProofInvalid));
+    }
+
+    #[test]
+    fn test_btc_proof_is_bound_to_its_txid() {
+        let fixture = BtcFixture::new();
+        let result = fixture.client.verify_payment_proof(
+            ChainType::BTC,
+            codec::encode_json(&fixture.proof(2)),
+            BTC_RECIPIENT.to_string(),
+            "BTC".to_string(),
+            U128(SATS),
+            MEMO.to_string(),
+            display(txid(&fixture.txs[1])),
+            None,
+        ).settled();
+        assert_eq!(result, btc_rejected(1, VerifyError::TxHashMismatch));
+    }
+
+    #[test]
+    fn test_btc_txid_ignores_witness_data() {
+        let outputs = [(5, vec![0x51])];
+        assert_eq!(
+            txid(&btc_transaction(9, &outputs, true)),
+            txid(&btc_transaction(9, &outputs, false))
+        );
+        let mut trailing = btc_transaction(9, &outputs, false);
+        trailing.push(0);
+        assert!(btc_tx::decode(&trailing).is_none());
+    }
+
+    #[test]
+    fn test_btc_address_scripts() {
+        let script = |address: &str| btc_tx::address_script(address).map(hex::encode);
+        assert_eq!(script(BTC_RECIPIENT).unwrap(), "0014751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(
+            script("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4").unwrap(),
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+        // BIP350 taproot-length v1 vector
+        assert_eq!(
+            script("bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y").unwrap(),
+            "5128751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+        assert_eq!(
+            script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap(),
+            "76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac"
+        );
+        // Bad checksum, mixed case, v0 with a bech32m checksum
+        assert_eq!(script("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"), None);
+        assert_eq!(script("bc1qw508d6qejxtdg4y5r3zarvary0c5xW7kv8f3t4"), None);
+        assert_eq!(script("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh"), None);
+        assert_eq!(script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"), None);
+    }
+
+    #[test]
+    fn test_btc_proofs_need_a_checkpoint() {
+        let client = setup(100);
+        let result = client.verify_payment_proof(
+            ChainType::BTC,
+            codec::encode_json(&BtcPaymentProof {
+                tx: GENESIS_COINBASE.to_string(),
+                tx_index: 0,
+                merkle_branch: vec![],
+                block_height: 0,
+            }),
+            BTC_RECIPIENT.to_string(),
+            "BTC".to_string(),
+            U128(SATS),
+            MEMO.to_string(),
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+            None,
+        ).settled();
+        assert_eq!(result, VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::UnknownBlock), block_height: 0, finalized_height: 0 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Checkpoint must start a difficulty period")]
+    fn test_btc_checkpoint_must_start_a_period() {
+        let mut client = setup(100);
+        client.set_btc_checkpoint(2015, GENESIS.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Checkpoint must be above the current tip")]
+    fn test_btc_checkpoint_cannot_rewind_the_chain() {
+        let mut fixture = BtcFixture::new();
+        fixture.client.set_btc_checkpoint(0, hex::encode(&fixture.checkpoint));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_btc_checkpoint_owner_only() {
+        let mut client = setup(100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_btc_checkpoint(0, GENESIS.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "BTC height follows submitted headers")]
+    fn test_btc_finalized_height_is_not_reported() {
+        let mut client = setup(100);
+        client.report_finalized_height(ChainType::BTC, 5, "btc-block".to_string());
+    }
+
+    // ------------------------------------------------------------------------
+    // Consuming proofs
+    // ------------------------------------------------------------------------
+
+    fn consumer_setup() -> LightClient {
+        let mut client = setup(100);
+        client.add_consumer(accounts(2));
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        client
+    }
+
+    fn consume(client: &mut LightClient, proof_data: Vec<u8>, tx_hash: &str, min_confirmations: u64) -> VerificationResult {
+        client.consume_payment_proof(
+            ChainType::SOL,
+            proof_data,
+            SOL_RECIPIENT.to_string(),
+            "SOL".to_string(),
+            U128(5),
+            "memo".to_string(),
+            tx_hash.to_string(),
+            None,
+            min_confirmations,
+        ).settled()
+    }
+
+    #[test]
+    fn test_consumed_proof_is_rejected_on_replay() {
+        let mut client = consumer_setup();
+        assert_eq!(
+            consume(&mut client, proof(94), SOL_TX_HASH, 6),
+            VerificationResult { outcome: VerifyOutcome::Valid, block_height: 94, finalized_height: 100 }
+        );
+        assert_eq!(client.get_consumed_proof(ChainType::SOL, SOL_TX_HASH.to_string(), "memo".to_string()), Some(94));
+        assert_eq!(
+            consume(&mut client, proof(94), SOL_TX_HASH, 6),
+            VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::AlreadyConsumed), block_height: 94, finalized_height: 100 }
+        );
+        // The view still verifies without consuming
+        assert!(verify(&client, proof(94)).is_valid());
+    }
+
+    #[test]
+    fn test_invalid_or_unconfirmed_proof_is_not_consumed() {
+        let mut client = consumer_setup();
+        assert!(!consume(&mut client, proof(94), "other", 0).is_valid());
+        // Valid, but short of the caller's confirmations: reported, not spent
+        assert_eq!(
+            consume(&mut client, proof(98), SOL_TX_HASH, 6),
+            VerificationResult { outcome: VerifyOutcome::Valid, block_height: 98, finalized_height: 100 }
+        );
+        assert_eq!(client.get_consumed_proof(ChainType::SOL, SOL_TX_HASH.to_string(), "memo".to_string()), None);
+        assert!(consume(&mut client, proof(98), SOL_TX_HASH, 2).is_valid());
+        assert!(!consume(&mut client, proof(98), SOL_TX_HASH, 2).is_valid());
+    }
+
+    #[test]
+    fn test_consumed_hex_hashes_are_normalized() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.add_consumer(accounts(2));
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        let proof_data = codec::encode_json(&block.proof(0));
+        let mut consume_eth = |tx_hash: String| {
+            client
+                .consume_payment_proof(
+                    ChainType::ETH,
+                    proof_data.clone(),
+                    RECIPIENT.to_string(),
+                    "ETH".to_string(),
+                    U128(500),
+                    MEMO.to_string(),
+                    tx_hash,
+                    None,
+                    0,
+                ).settled()
+                .is_valid()
+        };
+        let tx_hash = block.tx_hash(0);
+        assert!(consume_eth(tx_hash.clone()));
+        assert!(!consume_eth(tx_hash.trim_start_matches("0x").to_uppercase()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Caller is not a registered consumer")]
+    fn test_unregistered_consumer_is_rejected() {
+        let mut client = consumer_setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(3)).build());
+        consume(&mut client, proof(94), SOL_TX_HASH, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Caller is not a registered consumer")]
+    fn test_removed_consumer_is_rejected() {
+        let mut client = consumer_setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        client.remove_consumer(accounts(2));
+        assert!(!client.is_consumer(accounts(2)));
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        consume(&mut client, proof(94), SOL_TX_HASH, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_add_consumer_owner_only() {
+        let mut client = consumer_setup();
+        client.add_consumer(accounts(3));
+    }
+
+    // ------------------------------------------------------------------------
+    // Finalized heights by updater quorum
+    // ------------------------------------------------------------------------
+
+    const T0: u64 = 1_000_000_000_000;
+
+    fn act_as(account: usize, timestamp: u64) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(account))
+            .block_timestamp(timestamp)
+            .build());
+    }
+
+    /// Updaters 1, 2 and 3 with a threshold of 2.
+    fn quorum_setup() -> LightClient {
+        act_as(0, T0);
+        let mut client = LightClient::new(accounts(0));
+        for updater in 1..=3 {
+            client.add_updater(accounts(updater));
+        }
+        client.set_updater_threshold(2);
+        client
+    }
+
+    fn report(client: &mut LightClient, updater: usize, timestamp: u64, height: u64, block_hash: &str) {
+        act_as(updater, timestamp);
+        client.report_finalized_height(ChainType::SOL, height, block_hash.to_string());
+    }
+
+    #[test]
+    fn test_height_advances_once_quorum_agrees() {
+        let mut client = quorum_setup();
+        report(&mut client, 1, T0, 100, "h100");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 0);
+        // Reporting again replaces the updater's own report
+        report(&mut client, 1, T0 + 1, 100, "h100");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 0);
+        assert_eq!(client.get_height_reports(ChainType::SOL).len(), 1);
+
+        report(&mut client, 2, T0 + 2, 100, "h100");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 100);
+        assert!(client.get_height_reports(ChainType::SOL).is_empty());
+        assert_eq!(client.get_updater_threshold(), 2);
+        assert_eq!(client.get_updaters().len(), 3);
+    }
+
+    #[test]
+    fn test_eth_quorum_stores_the_block_hash() {
+        let mut client = quorum_setup();
+        let hash = format!("0x{}", hex::encode([7u8; 32]));
+        for updater in 1..=2 {
+            act_as(updater, T0);
+            client.report_finalized_height(ChainType::ETH, HEIGHT, hash.clone());
+        }
+        assert_eq!(client.get_finalized_height(ChainType::ETH), HEIGHT);
+        assert_eq!(client.get_eth_block_hash(HEIGHT), Some(hash));
+    }
+
+    #[test]
+    fn test_conflicting_hashes_freeze_the_chain_until_resolved() {
+        let mut client = quorum_setup();
+        report(&mut client, 1, T0, 100, "h100");
+        report(&mut client, 2, T0, 100, "forked");
+        assert!(client.is_height_frozen(ChainType::SOL));
+        assert!(near_sdk::test_utils::get_logs()[0].starts_with("HEIGHT_CONFLICT:chain=SOL,height=100"));
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 0);
+
+        act_as(0, T0);
+        client.resolve_height_conflict(ChainType::SOL);
+        assert!(!client.is_height_frozen(ChainType::SOL));
+        assert!(client.get_height_reports(ChainType::SOL).is_empty());
+        report(&mut client, 1, T0, 100, "h100");
+        report(&mut client, 3, T0, 100, "h100");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "SOL height is frozen by conflicting reports")]
+    fn test_frozen_chain_takes_no_reports() {
+        let mut client = quorum_setup();
+        report(&mut client, 1, T0, 100, "h100");
+        report(&mut client, 2, T0, 100, "forked");
+        report(&mut client, 3, T0, 101, "h101");
+    }
+
+    #[test]
+    fn test_stale_reports_expire() {
+        let mut client = quorum_setup();
+        report(&mut client, 1, T0, 100, "forked");
+        let late = T0 + quorum::DEFAULT_REPORT_WINDOW_NS + 1;
+        // Updater 1's report has expired, so this is no conflict
+        report(&mut client, 2, late, 100, "h100");
+        assert!(!client.is_height_frozen(ChainType::SOL));
+        let reports = client.get_height_reports(ChainType::SOL);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].updater, accounts(2));
+        report(&mut client, 3, late, 100, "h100");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 100);
+    }
+
+    #[test]
+    fn test_removed_updater_reports_stop_counting() {
+        let mut client = quorum_setup();
+        report(&mut client, 1, T0, 100, "h100");
+        act_as(0, T0);
+        client.remove_updater(accounts(1));
+        report(&mut client, 2, T0, 100, "h100");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Height must be above the finalized height")]
+    fn test_reports_must_advance_the_height() {
+        let mut client = quorum_setup();
+        report(&mut client, 1, T0, 100, "h100");
+        report(&mut client, 2, T0, 100, "h100");
+        report(&mut client, 3, T0, 100, "h100");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only updaters can report heights")]
+    fn test_only_updaters_report() {
+        let mut client = quorum_setup();
+        report(&mut client, 4, T0, 100, "h100");
+    }
+
+    #[test]
+    #[should_panic(expected = "Threshold must be between 1 and the number of updaters")]
+    fn test_threshold_cannot_exceed_updaters() {
+        let mut client = quorum_setup();
+        client.set_updater_threshold(4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Removing would leave fewer updaters than the threshold")]
+    fn test_remove_updater_keeps_the_threshold_reachable() {
+        let mut client = quorum_setup();
+        client.set_updater_threshold(3);
+        client.remove_updater(accounts(1));
+    }
+
+    // ------------------------------------------------------------------------
+    // Finality: monotonic heights, bounded steps and rollbacks
+    // ------------------------------------------------------------------------
+
+    #[test]
+    fn test_finalized_block_keeps_its_hash() {
+        let client = setup(100);
+        assert_eq!(
+            client.get_finalized_block(ChainType::SOL),
+            Some(FinalizedBlock { height: 100, block_hash: "sol-block".to_string() })
+        );
+        assert_eq!(client.get_finalized_block(ChainType::ETH), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Height must be above the finalized height")]
+    fn test_backward_report_is_rejected() {
+        let mut client = setup(100);
+        client.report_finalized_height(ChainType::SOL, 90, "sol-90".to_string());
+    }
+
+    #[test]
+    fn test_bounded_rollback_returns_to_a_finalized_block() {
+        let mut client = setup(100);
+        client.report_finalized_height(ChainType::SOL, 110, "sol-110".to_string());
+        client.rollback_finalized(ChainType::SOL, 100, "reorg at 104".to_string());
+        assert_eq!(
+            client.get_finalized_block(ChainType::SOL),
+            Some(FinalizedBlock { height: 100, block_hash: "sol-block".to_string() })
+        );
+        let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(
+            near_sdk::test_utils::get_logs().last().unwrap().strip_prefix("EVENT_JSON:").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(event["event"], "finalized_rollback");
+        assert_eq!(event["data"][0]["chain"], "SOL");
+        assert_eq!(event["data"][0]["from_height"], 110);
+        assert_eq!(event["data"][0]["to_height"], 100);
+        assert_eq!(event["data"][0]["reason"], "reorg at 104");
+        // Updaters carry on from the restored height
+        client.report_finalized_height(ChainType::SOL, 105, "sol-105".to_string());
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 105);
+    }
+
+    #[test]
+    fn test_eth_rollback_drops_block_hashes_above_it() {
+        let mut client = setup(100);
+        finalize(&mut client, ChainType::ETH, HEIGHT, &hex::encode([1; 32]));
+        finalize(&mut client, ChainType::ETH, HEIGHT + 5, &hex::encode([2; 32]));
+        client.rollback_finalized(ChainType::ETH, HEIGHT, "reorg".to_string());
+        assert_eq!(client.get_eth_block_hash(HEIGHT + 5), None);
+        assert_eq!(client.get_eth_block_hash(HEIGHT), Some(format!("0x{}", hex::encode([1; 32]))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Rollback of 10 blocks exceeds the max depth of 5")]
+    fn test_rollback_is_limited_to_the_max_depth() {
+        let mut client = setup(100);
+        client.set_max_rollback_depth(5);
+        client.report_finalized_height(ChainType::SOL, 110, "sol-110".to_string());
+        client.rollback_finalized(ChainType::SOL, 100, "reorg".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Height 105 was never finalized")]
+    fn test_rollback_target_must_have_been_finalized() {
+        let mut client = setup(100);
+        client.report_finalized_height(ChainType::SOL, 110, "sol-110".to_string());
+        client.rollback_finalized(ChainType::SOL, 105, "reorg".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_rollback_owner_only() {
+        let mut client = setup(100);
+        client.report_finalized_height(ChainType::SOL, 110, "sol-110".to_string());
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.rollback_finalized(ChainType::SOL, 100, "reorg".to_string());
+    }
+
+    #[test]
+    fn test_blocks_below_the_rollback_depth_are_pruned() {
+        let mut client = setup(100);
+        client.set_max_rollback_depth(5);
+        client.report_finalized_height(ChainType::SOL, 103, "sol-103".to_string());
+        client.report_finalized_height(ChainType::SOL, 106, "sol-106".to_string());
+        let heights: Vec<u64> = client.recent_finalized.get(&"SOL".to_string()).unwrap().iter().map(|block| block.height).collect();
+        assert_eq!(heights, vec![103, 106]);
+
+        client.rollback_finalized(ChainType::SOL, 103, "reorg".to_string());
+        assert_eq!(client.recent_finalized.get(&"SOL".to_string()).unwrap().len(), 1);
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 103);
+    }
+
+    #[test]
+    #[should_panic(expected = "Max rollback depth cannot exceed 256")]
+    fn test_max_rollback_depth_is_capped() {
+        let mut client = setup(100);
+        client.set_max_rollback_depth(finality::MAX_ROLLBACK_DEPTH_LIMIT);
+        client.set_max_rollback_depth(finality::MAX_ROLLBACK_DEPTH_LIMIT + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Height is 11 blocks past the finalized height, over the max step of 10, without linking headers")]
+    fn test_oversized_jump_is_rejected() {
+        let mut client = setup(100);
+        client.set_max_height_step(10);
+        client.report_finalized_height(ChainType::SOL, 111, "sol-111".to_string());
+    }
+
+    /// Headers for heights 11 to 13, each the child of the one before,
+    /// above `parent` at 10.
+    fn header_chain(parent: &[u8]) -> Vec<Vec<u8>> {
+        let mut parent = parent.to_vec();
+        (11..=13u64)
+            .map(|number| {
+                let header = child_header(&keccak(&parent), [0; 32], [0; 32], number);
+                parent = header.clone();
+                header
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_eth_jump_with_linking_headers_is_accepted() {
+        let base = header([0; 32], [0; 32], 10);
+        let headers = header_chain(&base);
+        let mut client = setup(100);
+        client.set_max_height_step(2);
+        finalize(&mut client, ChainType::ETH, 10, &hex::encode(keccak(&base)));
+        client.report_finalized_height_with_headers(
+            ChainType::ETH,
+            13,
+            hex::encode(keccak(&headers[2])),
+            headers.iter().map(hex::encode).collect(),
+        );
+        assert_eq!(client.get_finalized_height(ChainType::ETH), 13);
+    }
+
+    #[test]
+    #[should_panic(expected = "over the max step of 2, without linking headers")]
+    fn test_eth_jump_with_broken_headers_is_rejected() {
+        let base = header([0; 32], [0; 32], 10);
+        let headers = header_chain(&base);
+        let mut client = setup(100);
+        client.set_max_height_step(2);
+        finalize(&mut client, ChainType::ETH, 10, &hex::encode(keccak(&base)));
+        client.report_finalized_height_with_headers(
+            ChainType::ETH,
+            13,
+            hex::encode(keccak(&headers[2])),
+            vec![hex::encode(&headers[0]), hex::encode(&headers[2])],
+        );
+    }
+
+    // ------------------------------------------------------------------------
+    // Required confirmations and paused chains
+    // ------------------------------------------------------------------------
+
+    #[test]
+    fn test_sol_proof_needs_exactly_the_required_confirmations() {
+        let mut client = setup(100);
+        client.set_required_confirmations(ChainType::SOL, 6);
+        // 100 - 95 + 1 = 6
+        assert!(verify(&client, proof(95)).is_valid());
+        assert!(!verify(&client, proof(96)).is_valid());
+        let transition = |block_height: u64| {
+            client.verify_transition_proof(
+                ChainType::SOL,
+                proof(block_height),
+                SOL_RECIPIENT.to_string(),
+                "SOL".to_string(),
+                U128(5),
+                "memo".to_string(),
+                SOL_TX_HASH.to_string(),
+            ).settled()
+        };
+        assert_eq!(transition(95), VerifyOutcome::Valid);
+        assert_eq!(
+            transition(96),
+            VerifyOutcome::Invalid(VerifyError::NotFinalized { proof_height: 96, finalized: 100 })
+        );
+    }
+
+    #[test]
+    fn test_eth_proof_needs_exactly_the_required_confirmations() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.set_required_confirmations(ChainType::ETH, 3);
+        let valid = |client: &LightClient| verify_eth(client, &block.proof(0), "ETH", 500, &block.tx_hash(0)).is_valid();
+        assert!(!valid(&client));
+        client.report_finalized_height(ChainType::ETH, HEIGHT + 1, hex::encode([1; 32]));
+        assert!(!valid(&client));
+        client.report_finalized_height(ChainType::ETH, HEIGHT + 2, hex::encode([2; 32]));
+        assert!(valid(&client));
+    }
+
+    #[test]
+    fn test_btc_proof_needs_exactly_the_required_confirmations() {
+        let mut fixture = BtcFixture::new();
+        // Block 1 under a tip of 3 has 3 confirmations
+        fixture.client.set_required_confirmations(ChainType::BTC, 3);
+        assert!(fixture.verify(&fixture.proof(2), BTC_RECIPIENT, SATS, MEMO).is_valid());
+        fixture.client.set_required_confirmations(ChainType::BTC, 4);
+        assert_eq!(fixture.verify(&fixture.proof(2), BTC_RECIPIENT, SATS, MEMO), btc_rejected(1, VerifyError::NotFinalized { proof_height: 1, finalized: 3 }));
+    }
+
+    #[test]
+    fn test_paused_chain_rejects_every_proof() {
+        let mut client = setup(100);
+        client.set_required_confirmations(ChainType::SOL, 4);
+        client.set_chain_paused(ChainType::SOL, true);
+        assert_eq!(
+            client.get_chain_config(ChainType::SOL),
+            ChainConfig { finalized_height: 100, required_confirmations: 4, max_proof_age_blocks: None, paused: true }
+        );
+        assert_eq!(
+            verify(&client, proof(90)),
+            VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::ChainPaused), block_height: 0, finalized_height: 100 }
+        );
+        client.set_chain_paused(ChainType::SOL, false);
+        assert!(verify(&client, proof(90)).is_valid());
+        assert_eq!(
+            client.get_chain_config(ChainType::ETH),
+            ChainConfig { finalized_height: 0, required_confirmations: 1, max_proof_age_blocks: None, paused: false }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Required confirmations must be at least 1")]
+    fn test_required_confirmations_must_be_positive() {
+        let mut client = setup(100);
+        client.set_required_confirmations(ChainType::SOL, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_set_chain_paused_owner_only() {
+        let mut client = setup(100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_chain_paused(ChainType::SOL, true);
+    }
+
+    #[test]
+    fn test_proof_age_is_unlimited_by_default() {
+        let client = setup(100);
+        assert_eq!(client.get_chain_config(ChainType::SOL).max_proof_age_blocks, None);
+        assert!(verify(&client, proof(90)).is_valid());
+    }
+
+    #[test]
+    fn test_sol_proof_age_boundary() {
+        let mut client = setup(100);
+        client.set_max_proof_age(ChainType::SOL, Some(5));
+        assert_eq!(client.get_chain_config(ChainType::SOL).max_proof_age_blocks, Some(5));
+        // 100 - 95 = 5
+        assert!(verify(&client, proof(95)).is_valid());
+        assert_eq!(
+            verify(&client, proof(94)).outcome,
+            VerifyOutcome::Invalid(VerifyError::ProofTooOld { proof_height: 94, finalized: 100, max_age: 5 })
+        );
+        client.set_max_proof_age(ChainType::SOL, None);
+        assert!(verify(&client, proof(94)).is_valid());
+    }
+
+    #[test]
+    fn test_eth_proof_age_boundary() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.set_max_proof_age(ChainType::ETH, Some(1));
+        let verdict = |client: &LightClient| verify_eth(client, &block.proof(0), "ETH", 500, &block.tx_hash(0)).outcome;
+        client.report_finalized_height(ChainType::ETH, HEIGHT + 1, hex::encode([1; 32]));
+        assert_eq!(verdict(&client), VerifyOutcome::Valid);
+        client.report_finalized_height(ChainType::ETH, HEIGHT + 2, hex::encode([2; 32]));
+        assert_eq!(
+            verdict(&client),
+            VerifyOutcome::Invalid(VerifyError::ProofTooOld { proof_height: HEIGHT, finalized: HEIGHT + 2, max_age: 1 })
+        );
+    }
+
+    #[test]
+    fn test_btc_proof_age_boundary() {
+        let mut fixture = BtcFixture::new();
+        // Block 1 under a tip of 3 is 2 blocks old
+        fixture.client.set_max_proof_age(ChainType::BTC, Some(2));
+        assert!(fixture.verify(&fixture.proof(2), BTC_RECIPIENT, SATS, MEMO).is_valid());
+        fixture.client.set_max_proof_age(ChainType::BTC, Some(1));
+        assert_eq!(
+            fixture.verify(&fixture.proof(2), BTC_RECIPIENT, SATS, MEMO),
+            btc_rejected(1, VerifyError::ProofTooOld { proof_height: 1, finalized: 3, max_age: 1 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_set_max_proof_age_owner_only() {
+        let mut client = setup(100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_max_proof_age(ChainType::SOL, Some(5));
+    }
+
+    // ------------------------------------------------------------------------
+    // Inclusion proofs of SOL proofs
+    // ------------------------------------------------------------------------
+
+    fn leaf(tx_hash: &str) -> [u8; 32] {
+        env::sha256_array(tx_hash.as_bytes())
+    }
+
+    fn node(left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        env::sha256_array([left, right].concat())
+    }
+
+    /// Root of the tree over leaves a, b, c, d.
+    fn four_leaf_root() -> [u8; 32] {
+        node(node(leaf("a"), leaf("b")), node(leaf("c"), leaf("d")))
+    }
+
+    #[test]
+    fn test_inclusion_proof_folds_left_and_right() {
+        testing_env!(VMContextBuilder::new().build());
+        let (ab, cd) = (node(leaf("a"), leaf("b")), node(leaf("c"), leaf("d")));
+        let entry = |side: &str, hash: [u8; 32]| inclusion::parse_sibling(&format!("{}:{}", side, hex::encode(hash))).unwrap();
+        assert_eq!(
+            inclusion::fold("a", &[entry("R", leaf("b")), entry("R", cd)]),
+            four_leaf_root()
+        );
+        assert_eq!(
+            inclusion::fold("c", &[entry("R", leaf("d")), entry("L", ab)]),
+            four_leaf_root()
+        );
+        assert_eq!(
+            inclusion::fold("d", &[entry("L", leaf("c")), entry("L", ab)]),
+            four_leaf_root()
+        );
+        // Wrong side, tampered sibling
+        assert_ne!(
+            inclusion::fold("a", &[entry("L", leaf("b")), entry("R", cd)]),
+            four_leaf_root()
+        );
+        let mut tampered = cd;
+        tampered[0] ^= 1;
+        assert_ne!(
+            inclusion::fold("a", &[entry("R", leaf("b")), entry("R", tampered)]),
+            four_leaf_root()
+        );
+    }
+
+    #[test]
+    fn test_malformed_inclusion_entries_fail() {
+        testing_env!(VMContextBuilder::new().build());
+        let sibling = hex::encode(leaf("b"));
+        for entry in [
+            format!("X:{}", sibling),
+            format!("l:{}", sibling),
+            sibling.clone(),
+            format!("R:{}", &sibling[..62]),
+            format!("R:{}00", sibling),
+            format!("R:zz{}", &sibling[2..]),
+            "lol".to_string(),
+        ] {
+            assert_eq!(inclusion::parse_sibling(&entry), None, "{}", entry);
+        }
+    }
+
+    #[test]
+    fn test_sol_proof_must_reach_the_registered_root() {
+        let client = setup(100);
+        let mut payment: SolPaymentProof = near_sdk::serde_json::from_slice(&proof(95)[1..]).unwrap();
+        assert!(verify(&client, codec::encode_json(&payment)).is_valid());
+
+        payment.inclusion_proof = vec!["lol".to_string()];
+        assert!(!verify(&client, codec::encode_json(&payment)).is_valid());
+        payment.inclusion_proof = vec![format!("L:{}", hex::encode([9; 32]))];
+        assert!(!verify(&client, codec::encode_json(&payment)).is_valid());
+        payment.inclusion_proof = vec![];
+        assert!(!verify(&client, codec::encode_json(&payment)).is_valid());
+        // No root registered for the block
+        assert!(!verify(&client, proof(89)).is_valid());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only updaters can set Merkle roots")]
+    fn test_set_merkle_root_updaters_only() {
+        let mut client = setup(100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_merkle_root(ChainType::SOL, 100, hex::encode(sol_root()));
+    }
+
+    #[test]
+    #[should_panic(expected = "ETH proofs are verified against block headers")]
+    fn test_set_merkle_root_is_sol_only() {
+        let mut client = setup(100);
+        client.set_merkle_root(ChainType::ETH, 100, hex::encode(sol_root()));
+    }
+
+    // ------------------------------------------------------------------------
+    // Native vs ERC-20 asset kinds
+    // ------------------------------------------------------------------------
+
+    fn verify_eth_kind(
+        client: &LightClient,
+        proof: &EthPaymentProof,
+        asset: &str,
+        amount: u128,
+        tx_hash: &str,
+        kind: AssetKind,
+    ) -> VerificationResult {
+        client.verify_payment_proof(
+            ChainType::ETH,
+            codec::encode_json(proof),
+            RECIPIENT.to_string(),
+            asset.to_string(),
+            U128(amount),
+            MEMO.to_string(),
+            tx_hash.to_string(),
+            Some(kind),
+        ).settled()
+    }
+
+    fn erc20(contract: &str) -> AssetKind {
+        AssetKind::Erc20 { contract: contract.to_string() }
+    }
+
+    #[test]
+    fn test_eth_expected_asset_kind_is_proven() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let (native, token) = (block.proof(0), block.proof(1));
+        assert_eq!(verify_eth_kind(&client, &native, "ETH", 500, &block.tx_hash(0), AssetKind::Native), proven(HEIGHT));
+        assert_eq!(verify_eth_kind(&client, &token, "USDC", 42, &block.tx_hash(1), erc20(TOKEN)), proven(HEIGHT));
+        // Contracts compare as addresses, whatever the case
+        assert_eq!(
+            verify_eth_kind(&client, &token, "USDC", 42, &block.tx_hash(1), erc20(&TOKEN.to_uppercase().replace("0X", "0x"))),
+            proven(HEIGHT)
+        );
+    }
+
+    #[test]
+    fn test_eth_expected_contract_needs_registered_token() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.set_eth_token("USDC".to_string(), None);
+        assert_eq!(verify_eth_kind(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1), erc20(TOKEN)), rejected(HEIGHT, VerifyError::UnregisteredToken));
+    }
+
+    #[test]
+    fn test_eth_native_vs_erc20_mismatch_is_rejected() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        // Token payment where native ETH is expected, and the reverse
+        assert_eq!(verify_eth_kind(&client, &block.proof(1), "ETH", 42, &block.tx_hash(1), AssetKind::Native), rejected(HEIGHT, VerifyError::AssetMismatch));
+        assert_eq!(verify_eth_kind(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0), erc20(TOKEN)), rejected(HEIGHT, VerifyError::AssetMismatch));
+
+        // The proof's declared kind must match too
+        let mut native = block.proof(0);
+        native.asset_kind = erc20(TOKEN);
+        assert_eq!(verify_eth(&client, &native, "ETH", 500, &block.tx_hash(0)), rejected(HEIGHT, VerifyError::AssetMismatch));
+        let mut token = block.proof(1);
+        token.asset_kind = AssetKind::Native;
+        assert_eq!(verify_eth(&client, &token, "USDC", 42, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::AssetMismatch));
+
+        // An asset registered as a token cannot be expected as native
+        assert_eq!(verify_eth_kind(&client, &block.proof(0), "USDC", 500, &block.tx_hash(0), AssetKind::Native), rejected(HEIGHT, VerifyError::AssetMismatch));
+    }
+
+    #[test]
+    fn test_eth_token_contract_mismatch_is_rejected() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        let other = "0x4444444444444444444444444444444444444444";
+        let token = block.proof(1);
+        // Expected contract disagrees with the registry
+        assert_eq!(verify_eth_kind(&client, &token, "USDC", 42, &block.tx_hash(1), erc20(other)), rejected(HEIGHT, VerifyError::AssetMismatch));
+        // Proof declares another contract
+        let mut declared = block.proof(1);
+        declared.asset_kind = erc20(other);
+        assert_eq!(verify_eth_kind(&client, &declared, "USDC", 42, &block.tx_hash(1), erc20(TOKEN)), rejected(HEIGHT, VerifyError::AssetMismatch));
+        assert_eq!(verify_eth_kind(&client, &token, "USDC", 42, &block.tx_hash(1), erc20("0x1234")), rejected(HEIGHT, VerifyError::AssetMismatch));
+
+        // Unregistered asset expected at another contract
+        client.set_eth_token("USDC".to_string(), None);
+        assert_eq!(verify_eth_kind(&client, &declared, "USDC", 42, &block.tx_hash(1), erc20(other)), rejected(HEIGHT, VerifyError::UnregisteredToken));
+    }
+
+    // ------------------------------------------------------------------------
+    // Structured verification errors
+    // ------------------------------------------------------------------------
+
+    /// Outcome of `proof(95)` after `tamper`.
+    fn sol_outcome(client: &LightClient, tamper: impl FnOnce(&mut SolPaymentProof)) -> VerifyOutcome {
+        let mut payment: SolPaymentProof = near_sdk::serde_json::from_slice(&proof(95)[1..]).unwrap();
+        tamper(&mut payment);
+        verify(client, codec::encode_json(&payment)).outcome
+    }
+
+    /// Outcome of `proof(95)` checked against other expectations.
+    fn sol_expecting(client: &LightClient, recipient: &str, asset: &str, amount: u128, memo: &str, tx_hash: &str) -> VerifyOutcome {
+        client
+            .verify_payment_proof(
+                ChainType::SOL,
+                proof(95),
+                recipient.to_string(),
+                asset.to_string(),
+                U128(amount),
+                memo.to_string(),
+                tx_hash.to_string(),
+                None,
+            ).settled()
+            .outcome
+    }
+
+    #[test]
+    fn test_each_malformed_sol_proof_reports_its_error() {
+        let mut client = setup(100);
+        let invalid = VerifyOutcome::Invalid;
+        let expecting = |recipient: &str, asset: &str, amount: u128, memo: &str| {
+            sol_expecting(&client, recipient, asset, amount, memo, SOL_TX_HASH)
+        };
+        assert_eq!(sol_outcome(&client, |_| {}), VerifyOutcome::Valid);
+        assert_eq!(verify(&client, vec![codec::JSON_CODEC, b'{']).outcome, invalid(VerifyError::DeserializeFailed));
+        assert_eq!(sol_outcome(&client, |p| p.transaction = "zz".to_string()), invalid(VerifyError::DeserializeFailed));
+        assert_eq!(
+            sol_outcome(&client, |p| p.transaction.truncate(p.transaction.len() - 2)),
+            invalid(VerifyError::DeserializeFailed)
+        );
+        assert_eq!(
+            sol_expecting(&client, SOL_RECIPIENT, "SOL", 5, "memo", SOL_SPL_TX_HASH),
+            invalid(VerifyError::TxHashMismatch)
+        );
+        // 6 lamports instead of 5 under the original signature
+        let forged = SOL_TX.replace("0c0200000005", "0c0200000006");
+        assert_eq!(sol_outcome(&client, |p| p.transaction = forged), invalid(VerifyError::InvalidSignature));
+        assert_eq!(expecting(SOL_TOKEN_ACCOUNT, "SOL", 5, "memo"), invalid(VerifyError::RecipientMismatch));
+        assert_eq!(expecting("other", "SOL", 5, "memo"), invalid(VerifyError::InvalidAddress));
+        assert_eq!(expecting(SOL_RECIPIENT, "USDC", 5, "memo"), invalid(VerifyError::UnregisteredToken));
+        assert_eq!(expecting(SOL_RECIPIENT, "SOL", 6, "memo"), invalid(VerifyError::AmountMismatch));
+        assert_eq!(expecting(SOL_RECIPIENT, "SOL", 5, "sub:8"), invalid(VerifyError::MemoMismatch));
+        assert_eq!(sol_outcome(&client, |p| p.inclusion_proof.clear()), invalid(VerifyError::EmptyInclusionProof));
+        assert_eq!(
+            sol_outcome(&client, |p| p.inclusion_proof = vec![format!("L:{}", hex::encode([9; 32]))]),
+            invalid(VerifyError::InclusionProofInvalid)
+        );
+        assert_eq!(sol_outcome(&client, |p| p.block_height = 89), invalid(VerifyError::UnknownBlock));
+
+        client.set_required_confirmations(ChainType::SOL, 10);
+        assert_eq!(
+            sol_outcome(&client, |_| {}),
+            invalid(VerifyError::NotFinalized { proof_height: 95, finalized: 100 })
+        );
+        client.set_chain_paused(ChainType::SOL, true);
+        assert_eq!(sol_outcome(&client, |_| {}), invalid(VerifyError::ChainPaused));
+    }
+
+    #[test]
+    fn test_eth_native_recipient_mismatch_is_reported() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let result = client.verify_payment_proof(
+            ChainType::ETH,
+            codec::encode_json(&block.proof(0)),
+            TOKEN.to_string(),
+            "ETH".to_string(),
+            U128(500),
+            MEMO.to_string(),
+            block.tx_hash(0),
+            None,
+        ).settled();
+        assert_eq!(result, rejected(HEIGHT, VerifyError::RecipientMismatch));
+    }
+
+    #[test]
+    fn test_verify_outcome_json() {
+        let json = |outcome: VerifyOutcome| near_sdk::serde_json::to_string(&outcome).unwrap();
+        assert_eq!(json(VerifyOutcome::Valid), r#""Valid""#);
+        assert_eq!(json(VerifyOutcome::Invalid(VerifyError::MemoMismatch)), r#"{"Invalid":"MemoMismatch"}"#);
+        assert_eq!(
+            json(VerifyOutcome::Invalid(VerifyError::NotFinalized { proof_height: 7, finalized: 5 })),
+            r#"{"Invalid":{"NotFinalized":{"proof_height":7,"finalized":5}}}"#
+        );
+    }
+
+    // ------------------------------------------------------------------------
+    // Recipient normalization per chain
+    // ------------------------------------------------------------------------
+
+    #[test]
+    fn test_eth_recipient_matches_in_any_hex_case() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let verify_to = |recipient: &str| {
+            client.verify_payment_proof(
+                ChainType::ETH,
+                codec::encode_json(&block.proof(0)),
+                recipient.to_string(),
+                "ETH".to_string(),
+                U128(500),
+                MEMO.to_string(),
+                block.tx_hash(0),
+                None,
+            ).settled()
+        };
+        // EIP-55 checksummed and upper-case spellings of `RECIPIENT`
+        assert_eq!(verify_to("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"), proven(HEIGHT));
+        assert_eq!(verify_to("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED"), proven(HEIGHT));
+        assert_eq!(verify_to(RECIPIENT), proven(HEIGHT));
+
+        let invalid = rejected(HEIGHT, VerifyError::InvalidAddress);
+        assert_eq!(verify_to("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"), invalid);
+        assert_eq!(verify_to("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea"), invalid);
+        assert_eq!(verify_to("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaedff"), invalid);
+        assert_eq!(verify_to("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beazz"), invalid);
+    }
+
+    #[test]
+    fn test_btc_recipient_matches_canonically() {
+        let fixture = BtcFixture::new();
+        let proof = fixture.proof(2);
+        // Bech32 may be all upper-case, never mixed
+        assert!(fixture.verify(&proof, &BTC_RECIPIENT.to_uppercase(), SATS, MEMO).is_valid());
+        let mixed = format!("BC1Q{}", &BTC_RECIPIENT[4..]);
+        assert_eq!(fixture.verify(&proof, &mixed, SATS, MEMO), btc_rejected(1, VerifyError::InvalidAddress));
+        // Broken bech32 and base58check checksums
+        let bad_bech32 = format!("{}5", &BTC_RECIPIENT[..BTC_RECIPIENT.len() - 1]);
+        assert_eq!(fixture.verify(&proof, &bad_bech32, SATS, MEMO), btc_rejected(1, VerifyError::InvalidAddress));
+        assert_eq!(
+            fixture.verify(&proof, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3", SATS, MEMO),
+            btc_rejected(1, VerifyError::InvalidAddress)
+        );
+        // A valid address that is not the one paid
+        assert_eq!(
+            fixture.verify(&proof, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", SATS, MEMO),
+            btc_rejected(1, VerifyError::RecipientMismatch)
+        );
+    }
+
+    #[test]
+    fn test_sol_recipient_must_be_a_base58_key() {
+        let client = setup(100);
+        let invalid = VerifyOutcome::Invalid(VerifyError::InvalidAddress);
+        // Hex of the same key, and base58 of the wrong length
+        let expecting = |recipient: &str| sol_expecting(&client, recipient, "SOL", 5, "memo", SOL_TX_HASH);
+        assert_eq!(expecting(&hex::encode([7; 32])), invalid);
+        assert_eq!(expecting(&SOL_RECIPIENT[1..]), invalid);
+        assert_eq!(expecting(&format!("{}0", SOL_RECIPIENT)), invalid);
+        assert_eq!(expecting(SOL_RECIPIENT), VerifyOutcome::Valid);
+    }
+
+    #[test]
+    fn test_address_decoding() {
+        assert_eq!(address::eth("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"), address::eth(RECIPIENT));
+        assert_eq!(address::sol(SOL_RECIPIENT), Ok([7; 32]));
+        assert_eq!(address::sol("11111111111111111111111111111111"), Ok([0; 32]));
+        assert_eq!(address::btc(BTC_RECIPIENT), address::btc(&BTC_RECIPIENT.to_uppercase()));
+        assert_eq!(address::btc("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"), Err(VerifyError::InvalidAddress));
+    }
+
+    // ------------------------------------------------------------------------
+    // Submitted headers: linkage, retention and proof cross-checks
+    // ------------------------------------------------------------------------
+
+    fn h(byte: u8) -> String {
+        format!("0x{}", hex::encode([byte; 32]))
+    }
+
+    /// Owner as the only updater, with the ETH checkpoint at height 0.
+    fn header_client() -> LightClient {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        client.add_updater(accounts(0));
+        client.init_checkpoint(ChainType::ETH, 0, h(0), vec![]);
+        client
+    }
+
+    fn submit_run(client: &mut LightClient, heights: std::ops::RangeInclusive<u8>) {
+        for height in heights {
+            client.submit_header(ChainType::ETH, height as u64, h(height), h(height - 1));
+        }
+    }
+
+    #[test]
+    fn test_submitted_headers_are_looked_up_by_height() {
+        let mut client = header_client();
+        assert_eq!(client.get_latest_header(ChainType::ETH).unwrap().height, 0);
+        client.submit_header(ChainType::ETH, 1, h(1), h(0));
+        client.submit_header(ChainType::ETH, 2, h(2).to_uppercase().replace("0X", "0x"), h(1));
+        let header = client.get_header(ChainType::ETH, 2).unwrap();
+        assert_eq!(header.block_hash, hex::encode([2; 32]));
+        assert_eq!(header.parent_hash, hex::encode([1; 32]));
+        assert_eq!(header.submitted_by, accounts(0));
+        assert_eq!(client.get_latest_header(ChainType::ETH), Some(header));
+        assert_eq!(client.get_header(ChainType::ETH, 0).unwrap().parent_hash, "");
+        assert_eq!(client.get_header(ChainType::ETH, 3), None);
+        assert_eq!(client.get_header(ChainType::BTC, 2), None);
+
+        client.init_checkpoint(ChainType::SOL, 4, "11111111111111111111111111111111".to_string(), vec![]);
+        client.submit_header(ChainType::SOL, 5, SOL_RECIPIENT.to_string(), "11111111111111111111111111111111".to_string());
+        assert_eq!(client.get_latest_header(ChainType::SOL).unwrap().block_hash, SOL_RECIPIENT);
+    }
+
+    #[test]
+    #[should_panic(expected = "Parent hash does not match stored header at 1")]
+    fn test_header_must_link_to_stored_parent() {
+        let mut client = header_client();
+        client.submit_header(ChainType::ETH, 1, h(1), h(0));
+        client.submit_header(ChainType::ETH, 2, h(2), h(9));
+    }
+
+    #[test]
+    #[should_panic(expected = "No stored parent at 4")]
+    fn test_header_needs_a_stored_parent() {
+        let mut client = header_client();
+        client.submit_header(ChainType::ETH, 1, h(1), h(0));
+        client.submit_header(ChainType::ETH, 5, h(5), h(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only updaters can submit headers")]
+    fn test_only_updaters_submit_headers() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        client.submit_header(ChainType::ETH, 10, h(1), h(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Block hash must be 32 bytes")]
+    fn test_header_hash_must_be_32_bytes() {
+        let mut client = header_client();
+        client.submit_header(ChainType::ETH, 1, "0x1234".to_string(), h(0));
+    }
+
+    #[test]
+    fn test_unfinalized_header_replacement_drops_descendants() {
+        let mut client = header_client();
+        submit_run(&mut client, 1..=3);
+        client.submit_header(ChainType::ETH, 2, h(7), h(1));
+        assert_eq!(client.get_header(ChainType::ETH, 2).unwrap().block_hash, hex::encode([7; 32]));
+        assert_eq!(client.get_header(ChainType::ETH, 3), None);
+        assert_eq!(client.get_latest_header(ChainType::ETH).unwrap().height, 2);
+        client.submit_header(ChainType::ETH, 3, h(8), h(7));
+        assert_eq!(client.get_header(ChainType::ETH, 3).unwrap().block_hash, hex::encode([8; 32]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Header at finalized height 1 cannot be replaced")]
+    fn test_finalized_header_cannot_be_replaced() {
+        let mut client = header_client();
+        client.submit_header(ChainType::ETH, 1, h(1), h(0));
+        client.report_finalized_height(ChainType::ETH, 1, hex::encode([1; 32]));
+        client.submit_header(ChainType::ETH, 1, h(1), h(0));
+        client.submit_header(ChainType::ETH, 1, h(3), h(0));
+    }
+
+    #[test]
+    fn test_headers_outside_retention_are_pruned() {
+        let mut client = header_client();
+        client.set_header_retention(3);
+        submit_run(&mut client, 1..=6);
+        for height in 0..=3 {
+            assert_eq!(client.get_header(ChainType::ETH, height), None);
+            assert_eq!(client.headers.get(&format!("ETH:{}", height)), None);
+        }
+        assert!(client.get_header(ChainType::ETH, 4).is_some());
+        assert_eq!(client.get_latest_header(ChainType::ETH).unwrap().height, 6);
+        // Other chains keep their own window
+        client.init_checkpoint(ChainType::BTC, 0, h(0), vec![]);
+        client.submit_header(ChainType::BTC, 1, h(1), h(0));
+        assert!(client.get_header(ChainType::BTC, 0).is_some());
+    }
+
+    #[test]
+    fn test_header_pruning_is_bounded_per_submit() {
+        let mut client = header_client();
+        submit_run(&mut client, 1..=40);
+        client.set_header_retention(1);
+        client.submit_header(ChainType::ETH, 41, h(41), h(40));
+        // Expired headers read as absent at once but leave storage 32 at a time
+        assert_eq!(client.get_header(ChainType::ETH, 33), None);
+        assert_eq!(client.headers.get(&"ETH:31".to_string()), None);
+        assert!(client.headers.get(&"ETH:32".to_string()).is_some());
+        client.submit_header(ChainType::ETH, 42, h(42), h(41));
+        assert_eq!(client.headers.get(&"ETH:41".to_string()), None);
+        assert!(client.get_header(ChainType::ETH, 42).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Height 2 is below the retention window")]
+    fn test_header_below_retention_is_refused() {
+        let mut client = header_client();
+        client.set_header_retention(3);
+        submit_run(&mut client, 1..=5);
+        client.submit_header(ChainType::ETH, 2, h(2), h(1));
+    }
+
+    #[test]
+    fn test_eth_proof_prefers_submitted_header() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        let hash = hex::encode(keccak(&block.header()));
+        client.init_checkpoint(ChainType::ETH, HEIGHT, hash, block.header());
+        assert_eq!(verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0)), proven(HEIGHT));
+
+        // Without a block hash for the height, the submitted header suffices
+        client.eth_block_hashes.remove(&HEIGHT);
+        assert_eq!(verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0)), proven(HEIGHT));
+
+        // and a different submitted header wins over a matching block hash
+        client.eth_block_hashes.insert(&HEIGHT, &keccak(&block.header()).try_into().unwrap());
+        client.headers.insert(
+            &format!("ETH:{}", HEIGHT),
+            &HeaderRecord { block_hash: hex::encode([5; 32]), ..client.get_header(ChainType::ETH, HEIGHT).unwrap() },
+        );
+        assert_eq!(
+            verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0)),
+            rejected(HEIGHT, VerifyError::HeaderMismatch)
+        );
+    }
+
+    #[test]
+    fn test_btc_proof_checks_submitted_header() {
+        let mut fixture = BtcFixture::new();
+        fixture.client.add_updater(accounts(0));
+        let checkpoint_hash = display(btc::sha256d(&fixture.checkpoint));
+        fixture.client.init_checkpoint(ChainType::BTC, 0, checkpoint_hash.clone(), fixture.checkpoint.clone());
+        let proof = fixture.proof(2);
+        fixture.client.submit_header(ChainType::BTC, 1, display(btc::sha256d(&fixture.headers[0])), checkpoint_hash);
+        assert!(fixture.verify(&proof, BTC_RECIPIENT, SATS, MEMO).is_valid());
+
+        // Block 1 is finalized, so a conflicting header is planted directly
+        let header = fixture.client.get_header(ChainType::BTC, 1).unwrap();
+        fixture.client.headers.insert(&"BTC:1".to_string(), &HeaderRecord { block_hash: hex::encode([5; 32]), ..header });
+        assert_eq!(
+            fixture.verify(&proof, BTC_RECIPIENT, SATS, MEMO),
+            btc_rejected(1, VerifyError::HeaderMismatch)
+        );
+    }
+
+    // ------------------------------------------------------------------------
+    // Trusted checkpoints
+    // ------------------------------------------------------------------------
+
+    #[test]
+    #[should_panic(expected = "Checkpoint already initialized for ETH")]
+    fn test_checkpoint_is_set_once() {
+        let mut client = header_client();
+        client.init_checkpoint(ChainType::ETH, 5, h(5), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_only_owner_sets_checkpoint() {
+        let mut client = header_client();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.init_checkpoint(ChainType::SOL, 5, "11111111111111111111111111111111".to_string(), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "No checkpoint for BTC")]
+    fn test_headers_need_a_checkpoint() {
+        let mut client = header_client();
+        client.submit_header(ChainType::BTC, 1, h(1), h(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Header must be above the checkpoint at 0")]
+    fn test_headers_build_forward_from_checkpoint() {
+        let mut client = header_client();
+        client.submit_header(ChainType::ETH, 1, h(1), h(0));
+        assert_eq!(
+            client.get_checkpoint(ChainType::ETH).map(|checkpoint| checkpoint.block_hash),
+            Some(hex::encode([0; 32]))
+        );
+        client.submit_header(ChainType::ETH, 0, h(3), h(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Checkpoint data does not match the block")]
+    fn test_checkpoint_data_must_match_the_block() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.init_checkpoint(ChainType::ETH, HEIGHT + 1, hex::encode(keccak(&block.header())), block.header());
+    }
+
+    #[test]
+    fn test_proof_below_checkpoint_is_rejected() {
+        let mut client = setup(100);
+        assert!(verify(&client, proof(95)).is_valid());
+        client.init_checkpoint(ChainType::SOL, 96, "11111111111111111111111111111111".to_string(), vec![]);
+        assert_eq!(
+            verify(&client, proof(95)).outcome,
+            VerifyOutcome::Invalid(VerifyError::BelowCheckpoint { proof_height: 95, checkpoint: 96 })
+        );
+        assert!(verify(&client, proof(96)).is_valid());
+    }
+
+    #[test]
+    fn test_eth_proof_below_checkpoint_is_rejected() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        client.init_checkpoint(ChainType::ETH, HEIGHT + 1, h(1), vec![]);
+        assert_eq!(
+            verify_eth(&client, &block.proof(0), "ETH", 500, &block.tx_hash(0)),
+            rejected(HEIGHT, VerifyError::BelowCheckpoint { proof_height: HEIGHT, checkpoint: HEIGHT + 1 })
+        );
+    }
+
+    #[test]
+    fn test_reset_chain_allows_a_new_checkpoint() {
+        let mut client = header_client();
+        submit_run(&mut client, 1..=2);
+        client.reset_chain(ChainType::ETH, "bad checkpoint".to_string());
+        let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(
+            near_sdk::test_utils::get_logs().last().unwrap().strip_prefix("EVENT_JSON:").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(event["event"], "chain_reset");
+        assert_eq!(event["data"][0]["chain"], "ETH");
+        assert_eq!(event["data"][0]["checkpoint_height"], 0);
+        assert_eq!(event["data"][0]["latest_height"], 2);
+        assert_eq!(event["data"][0]["reason"], "bad checkpoint");
+        assert_eq!(client.get_checkpoint(ChainType::ETH), None);
+        assert_eq!(client.get_header(ChainType::ETH, 1), None);
+        assert_eq!(client.get_latest_header(ChainType::ETH), None);
+
+        // Earlier headers stay unreachable from the new checkpoint
+        client.init_checkpoint(ChainType::ETH, 1, h(9), vec![]);
+        assert_eq!(client.get_header(ChainType::ETH, 2), None);
+        client.submit_header(ChainType::ETH, 2, h(10), h(9));
+        assert_eq!(client.get_latest_header(ChainType::ETH).unwrap().block_hash, hex::encode([10; 32]));
+    }
+
+    // ------------------------------------------------------------------------
+    // SOL transaction parsing
+    // ------------------------------------------------------------------------
+
+    /// A signed v0 transaction from the key `[1; 32]`: an SPL
+    /// `TransferChecked` of 42,000,000 of the mint `[6; 32]` (6 decimals)
+    /// from the token account `[4; 32]` to `SOL_TOKEN_ACCOUNT`, and a Memo
+    /// program instruction carrying "memo".
+    const SOL_SPL_TX: &str = concat!(
+        "01241566ad505e31f9ba543661222db4e4979ccabb1a825ced1ea660d6db0b27513b35731f6f9cc74775b833a6559126",
+        "f9ba9927ba6443aa2216d206aa2f99470f80010003068a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf374",
+        "8801b40f6f5c040404040404040404040404040404040404040404040404040404040404040408080808080808080808",
+        "080808080808080808080808080808080808080808080606060606060606060606060606060606060606060606060606",
+        "06060606060606ddf6e1d765a193d9cbe146ceeb79ac1cb485ed5f5b37913a8cf5857eff00a9054a535a992921064d24",
+        "e87160da387c7c35b5ddbc92bb81e41fa8404105448d0303030303030303030303030303030303030303030303030303",
+        "030303030303020404010302000a0c80de80020000000006050100046d656d6f00",
+    );
+    const SOL_SPL_TX_HASH: &str = "iqt1LhoxmC54bX142qXNRAdLTDw5Y62EbJL4RfUndC26QXhwJW7QYHbGRvoRHyvUb7DbHKvDpG9A6u7m9KZGeZ4";
+    /// Base58 of `[6; 32]`.
+    const SOL_MINT: &str = "QWmroo4YnnMqYW3cnxWkFdaTxGD3P7vMSzwMHGbUzwF";
+    /// Base58 of `[8; 32]`.
+    const SOL_TOKEN_ACCOUNT: &str = "YMN9Qj5jPNp7j14VPcML1B6xGgcPWVZUGLFU3Mnyfaf";
+
+    #[test]
+    fn test_sol_transaction_decodes_transfer_and_memo() {
+        let bytes = hex::decode(SOL_TX).unwrap();
+        let tx = sol_tx::decode(&bytes).unwrap();
+        assert_eq!(btc_tx::base58_encode(&tx.signatures[0]), SOL_TX_HASH);
+        assert_eq!(btc_tx::base58_encode(&tx.fee_payer), "AKnL4NNf3DGWZJS6cPknBuEGnVsV4A4m5tgebLHaRSZ9");
+        assert_eq!(tx.transfers.len(), 1);
+        assert_eq!((tx.transfers[0].mint, tx.transfers[0].destination, tx.transfers[0].amount), (None, [7; 32], 5));
+        assert_eq!(tx.memos, vec![b"memo".as_slice()]);
+        assert!(env::ed25519_verify(&tx.signatures[0], tx.message, &tx.fee_payer));
+
+        let bytes = hex::decode(SOL_SPL_TX).unwrap();
+        let tx = sol_tx::decode(&bytes).unwrap();
+        assert_eq!(btc_tx::base58_encode(&tx.signatures[0]), SOL_SPL_TX_HASH);
+        assert_eq!(
+            (tx.transfers[0].mint, tx.transfers[0].destination, tx.transfers[0].amount),
+            (Some([6; 32]), [8; 32], 42_000_000)
+        );
+        assert_eq!(tx.memos, vec![b"memo".as_slice()]);
+    }
+
+    #[test]
+    fn test_malformed_sol_transactions_do_not_decode() {
+        let bytes = hex::decode(SOL_TX).unwrap();
+        // Trailing bytes, and a second signature the header does not ask for
+        assert!(sol_tx::decode(&[bytes.as_slice(), &[0]].concat()).is_none());
+        let two_signatures = [&[2u8][..], &bytes[1..65], &bytes[1..]].concat();
+        assert!(sol_tx::decode(&two_signatures).is_none());
+        // Unsupported message version 1
+        let v1 = [&bytes[..65], &[0x81], &bytes[65..]].concat();
+        assert!(sol_tx::decode(&v1).is_none());
+        assert!(sol_tx::decode(&[]).is_none());
+    }
+
+    #[test]
+    fn test_base58_round_trip() {
+        for bytes in [vec![0, 0, 1, 2], vec![255; 32], vec![]] {
+            assert_eq!(btc_tx::base58_decode(&btc_tx::base58_encode(&bytes)), Some(bytes));
+        }
+        assert_eq!(btc_tx::base58_encode(&[7; 32]), SOL_RECIPIENT);
+    }
+
+    /// `setup(100)` with the SPL transaction's root registered at 97.
+    fn spl_setup() -> (LightClient, Vec<u8>) {
+        let mut client = setup(100);
+        let root = env::sha256_array([env::sha256_array(SOL_SPL_TX_HASH.as_bytes()), [9; 32]].concat());
+        client.set_merkle_root(ChainType::SOL, 97, hex::encode(root));
+        let proof = codec::encode_json(&SolPaymentProof {
+            transaction: SOL_SPL_TX.to_string(),
+            block_height: 97,
+            inclusion_proof: vec![format!("R:{}", hex::encode([9; 32]))],
+        });
+        (client, proof)
+    }
+
+    fn verify_spl(client: &LightClient, proof: &[u8], asset: &str, amount: u128) -> VerifyOutcome {
+        client
+            .verify_payment_proof(
+                ChainType::SOL,
+                proof.to_vec(),
+                SOL_TOKEN_ACCOUNT.to_string(),
+                asset.to_string(),
+                U128(amount),
+                "memo".to_string(),
+                SOL_SPL_TX_HASH.to_string(),
+                None,
+            ).settled()
+            .outcome
+    }
+
+    #[test]
+    fn test_sol_spl_transfer_is_proven_against_registered_mint() {
+        let (mut client, proof) = spl_setup();
+        let invalid = VerifyOutcome::Invalid;
+        assert_eq!(verify_spl(&client, &proof, "USDC", 42_000_000), invalid(VerifyError::UnregisteredToken));
+        client.set_sol_mint("usdc".to_string(), Some(SOL_MINT.to_string()));
+        assert_eq!(client.get_sol_mint("USDC".to_string()), Some(SOL_MINT.to_string()));
+        assert_eq!(verify_spl(&client, &proof, "USDC", 42_000_000), VerifyOutcome::Valid);
+        assert_eq!(verify_spl(&client, &proof, "USDC", 42), invalid(VerifyError::AmountMismatch));
+        // Tokens are not lamports, and another registered mint is not this one
+        assert_eq!(verify_spl(&client, &proof, "SOL", 42_000_000), invalid(VerifyError::AssetMismatch));
+        client.set_sol_mint("USDC".to_string(), Some(SOL_RECIPIENT.to_string()));
+        assert_eq!(verify_spl(&client, &proof, "USDC", 42_000_000), invalid(VerifyError::AssetMismatch));
+        client.set_sol_mint("USDC".to_string(), None);
+        assert_eq!(client.get_sol_mint("USDC".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Mint must be a base58 32-byte key")]
+    fn test_sol_mint_must_be_a_key() {
+        let mut client = setup(100);
+        client.set_sol_mint("USDC".to_string(), Some(hex::encode([6; 32])));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_set_sol_mint_owner_only() {
+        let mut client = setup(100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_sol_mint("USDC".to_string(), Some(SOL_MINT.to_string()));
+    }
+
+    // ------------------------------------------------------------------
+    // ETH prover
+    // ------------------------------------------------------------------
+
+    fn prover() -> AccountId {
+        accounts(3)
+    }
+
+    /// The token transfer of `Block` as an `EthLogProof`. The prover is
+    /// mocked, so `proof` is left empty.
+    fn log_proof(block: &Block) -> EthLogProof {
+        EthLogProof {
+            log_index: 0,
+            log_entry_data: transfer_log(TOKEN, RECIPIENT, 42),
+            receipt_index: 1,
+            receipt_data: block.receipts[1].clone(),
+            header_data: block.header(),
+            proof: vec![],
+        }
+    }
+
+    fn log_id(block: &Block, receipt_index: u64, log_index: u64) -> String {
+        let block_hash: [u8; 32] = keccak(&block.header()).try_into().unwrap();
+        format!("0x{}", hex::encode(eth::log_id(&block_hash, receipt_index, log_index)))
+    }
+
+    fn prover_setup(block: &Block) -> LightClient {
+        let mut client = eth_setup(block);
+        client.set_eth_prover(Some(prover()));
+        client
+    }
+
+    fn verify_log(client: &LightClient, proof: &EthLogProof, asset: &str, amount: u128, tx_hash: &str) -> PromiseOrValue<VerificationResult> {
+        client.verify_payment_proof(
+            ChainType::ETH,
+            codec::encode_borsh(&PaymentProofV1::EthLog(proof.clone())),
+            RECIPIENT.to_string(),
+            asset.to_string(),
+            U128(amount),
+            MEMO.to_string(),
+            tx_hash.to_string(),
+            None,
+        )
+    }
+
+    /// Receiver, method and JSON arguments of each function call made.
+    fn created_calls() -> Vec<(AccountId, String, near_sdk::serde_json::Value)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                receipt.actions.into_iter().filter_map(move |action| match action {
+                    near_sdk::mock::MockAction::FunctionCallWeight { method_name, args, .. } => Some((
+                        receipt.receiver_id.clone(),
+                        String::from_utf8(method_name).unwrap(),
+                        near_sdk::serde_json::from_slice(&args).unwrap(),
+                    )),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_eth_log_proof_is_sent_to_the_prover() {
+        let block = Block::new();
+        let client = prover_setup(&block);
+        assert_eq!(client.get_eth_prover(), Some(prover()));
+        let proof = log_proof(&block);
+        let result = verify_log(&client, &proof, "USDC", 42, &log_id(&block, 1, 0));
+        assert!(matches!(result, PromiseOrValue::Promise(_)));
+        // Scheduled once dropped, as when returned
+        drop(result);
+
+        let calls = created_calls();
+        assert_eq!(calls.len(), 2);
+        let (receiver, method, args) = &calls[0];
+        assert_eq!((receiver, method.as_str()), (&prover(), "verify_log_entry"));
+        assert_eq!(args["receipt_index"], 1);
+        assert_eq!(args["header_data"], near_sdk::serde_json::json!(block.header()));
+        assert_eq!(args["skip_bridge_call"], true);
+        let (receiver, method, args) = &calls[1];
+        assert_eq!((receiver, method.as_str()), (&env::current_account_id(), "on_eth_log_verified"));
+        assert_eq!(args["block_height"], HEIGHT);
+        assert_eq!(args["finalized_height"], HEIGHT);
+    }
+
+    #[test]
+    fn test_eth_prover_answer_is_the_verdict() {
+        let block = Block::new();
+        let client = prover_setup(&block);
+        assert_eq!(client.on_eth_log_verified(Ok(true), HEIGHT, HEIGHT), proven(HEIGHT));
+        assert_eq!(
+            client.on_eth_log_verified(Ok(false), HEIGHT, HEIGHT),
+            rejected(HEIGHT, VerifyError::InclusionProofInvalid)
+        );
+        assert_eq!(
+            client.on_eth_log_verified(Err(near_sdk::PromiseError::Failed), HEIGHT, HEIGHT),
+            rejected(HEIGHT, VerifyError::ProverFailed)
+        );
+        assert_eq!(
+            client.on_eth_transition_log_verified(Ok(false), HEIGHT, HEIGHT),
+            VerifyOutcome::Invalid(VerifyError::InclusionProofInvalid)
+        );
+    }
+
+    #[test]
+    fn test_eth_log_proof_failing_local_checks_skips_the_prover() {
+        let block = Block::new();
+        let client = prover_setup(&block);
+        let proof = log_proof(&block);
+        let id = log_id(&block, 1, 0);
+        let settled = |result: PromiseOrValue<VerificationResult>| result.settled();
+        // The tx hash stands for this log only
+        let other_log = log_id(&block, 1, 1);
+        assert_eq!(settled(verify_log(&client, &proof, "USDC", 42, &other_log)), rejected(HEIGHT, VerifyError::TxHashMismatch));
+        assert_eq!(settled(verify_log(&client, &proof, "USDC", 42, &block.tx_hash(1))), rejected(HEIGHT, VerifyError::TxHashMismatch));
+        assert_eq!(settled(verify_log(&client, &proof, "USDC", 41, &id)), rejected(HEIGHT, VerifyError::AmountMismatch));
+        // Native payments leave no log
+        assert_eq!(settled(verify_log(&client, &proof, "ETH", 42, &id)), rejected(HEIGHT, VerifyError::AssetMismatch));
+
+        let mut forged = log_proof(&block);
+        forged.log_entry_data = transfer_log(TOKEN, RECIPIENT, 4_200);
+        assert_eq!(settled(verify_log(&client, &forged, "USDC", 4_200, &id)), rejected(HEIGHT, VerifyError::InclusionProofInvalid));
+        let mut failed = log_proof(&block);
+        failed.receipt_data = receipt(false, &[transfer_log(TOKEN, RECIPIENT, 42)]);
+        assert_eq!(settled(verify_log(&client, &failed, "USDC", 42, &id)), rejected(HEIGHT, VerifyError::TransactionFailed));
+        let mut unknown = log_proof(&block);
+        unknown.header_data = header([1; 32], [2; 32], HEIGHT);
+        assert_eq!(settled(verify_log(&client, &unknown, "USDC", 42, &id)), rejected(HEIGHT, VerifyError::HeaderMismatch));
+
+        // Receipt proofs are still checked here
+        let json = codec::encode_json(&block.proof(1));
+        let result = client
+            .verify_payment_proof(ChainType::ETH, json, RECIPIENT.to_string(), "USDC".to_string(), U128(42), MEMO.to_string(), block.tx_hash(1), None)
+            .settled();
+        assert_eq!(result, proven(HEIGHT));
+        assert!(created_calls().is_empty());
+    }
+
+    #[test]
+    fn test_eth_log_proof_without_a_prover_is_invalid() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let result = verify_log(&client, &log_proof(&block), "USDC", 42, &log_id(&block, 1, 0)).settled();
+        assert_eq!(result, rejected(HEIGHT, VerifyError::InclusionProofInvalid));
+        assert!(created_calls().is_empty());
+    }
+
+    #[test]
+    fn test_eth_log_proof_is_consumed_once_whatever_the_memo() {
+        let block = Block::new();
+        let mut client = prover_setup(&block);
+        client.add_consumer(accounts(1));
+        testing_env!(VMContextBuilder::new().current_account_id(accounts(0)).predecessor_account_id(accounts(1)).build());
+        let proof = codec::encode_borsh(&PaymentProofV1::EthLog(log_proof(&block)));
+        let id = log_id(&block, 1, 0);
+        let consume = |client: &mut LightClient, memo: &str| {
+            client.consume_payment_proof(
+                ChainType::ETH,
+                proof.clone(),
+                RECIPIENT.to_string(),
+                "USDC".to_string(),
+                U128(42),
+                memo.to_string(),
+                id.clone(),
+                None,
+                0,
+            )
+        };
+        assert!(matches!(consume(&mut client, MEMO), PromiseOrValue::Promise(_)));
+        let (_, method, args) = created_calls().pop().unwrap();
+        assert_eq!(method, "on_eth_log_consumed");
+        assert_eq!(args["consumer"], accounts(1).to_string());
+
+        testing_env!(VMContextBuilder::new().current_account_id(accounts(0)).predecessor_account_id(accounts(0)).build());
+        let key = args["key"].as_str().unwrap().to_string();
+        let result = client.on_eth_log_consumed(Ok(true), HEIGHT, HEIGHT, key.clone(), id.clone(), 0, accounts(1));
+        assert_eq!(result, proven(HEIGHT));
+        assert_eq!(client.get_consumed_proof(ChainType::ETH, id.clone(), String::new()), Some(HEIGHT));
+        // A second answer for the same proof finds it spent
+        let again = client.on_eth_log_consumed(Ok(true), HEIGHT, HEIGHT, key, id.clone(), 0, accounts(1));
+        assert_eq!(again, rejected(HEIGHT, VerifyError::AlreadyConsumed));
+
+        testing_env!(VMContextBuilder::new().current_account_id(accounts(0)).predecessor_account_id(accounts(1)).build());
+        let replay = consume(&mut client, "another memo").settled();
+        assert_eq!(replay, rejected(HEIGHT, VerifyError::AlreadyConsumed));
+    }
+
+    #[test]
+    fn test_eth_prover_rejection_consumes_nothing() {
+        let block = Block::new();
+        let mut client = prover_setup(&block);
+        let id = log_id(&block, 1, 0);
+        let key = format!("ETH:{}:", &id[2..]);
+        let result = client.on_eth_log_consumed(Ok(false), HEIGHT, HEIGHT, key, id.clone(), 0, accounts(1));
+        assert_eq!(result, rejected(HEIGHT, VerifyError::InclusionProofInvalid));
+        assert_eq!(client.get_consumed_proof(ChainType::ETH, id, String::new()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_set_eth_prover_owner_only() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.set_eth_prover(Some(prover()));
+    }
+
+    // ------------------------------------------------------------------
+    // Proof codecs
+    // ------------------------------------------------------------------
+
+    #[test]
+    fn test_eth_proof_round_trips_both_codecs() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let binary = PaymentProofV1::Eth(block.proof(1).decode().unwrap());
+        let json = codec::encode_json(&block.proof(1));
+        let borsh = codec::encode_borsh(&binary);
+        assert_eq!((json[0], borsh[0]), (codec::JSON_CODEC, codec::BORSH_CODEC));
+        assert_eq!(codec::decode(&ChainType::ETH, &json), Some(binary.clone()));
+        assert_eq!(codec::decode(&ChainType::ETH, &borsh), Some(binary));
+
+        let verify = |proof_data: Vec<u8>| {
+            client
+                .verify_payment_proof(ChainType::ETH, proof_data, RECIPIENT.to_string(), "USDC".to_string(), U128(42), MEMO.to_string(), block.tx_hash(1), None)
+                .settled()
+        };
+        assert_eq!(verify(json), proven(HEIGHT));
+        assert_eq!(verify(borsh), proven(HEIGHT));
+    }
+
+    #[test]
+    fn test_btc_and_sol_proofs_round_trip_both_codecs() {
+        let fixture = BtcFixture::new();
+        let binary = PaymentProofV1::Btc(fixture.proof(2).decode().unwrap());
+        assert_eq!(codec::decode(&ChainType::BTC, &codec::encode_json(&fixture.proof(2))), Some(binary.clone()));
+        let borsh = codec::encode_borsh(&binary);
+        assert_eq!(codec::decode(&ChainType::BTC, &borsh), Some(binary));
+        let transition = fixture.client.verify_transition_proof(
+            ChainType::BTC,
+            borsh,
+            BTC_RECIPIENT.to_string(),
+            "BTC".to_string(),
+            U128(SATS),
+            MEMO.to_string(),
+            display(txid(&fixture.txs[2])),
+        ).settled();
+        assert!(transition.is_valid());
+
+        let client = setup(100);
+        let binary = codec::decode(&ChainType::SOL, &proof(95)).unwrap();
+        let PaymentProofV1::Sol(sol) = &binary else {
+            panic!("Expected a SOL proof, got {:?}", binary);
+        };
+        assert_eq!(sol.inclusion_proof, vec![Sibling::Right([9; 32])]);
+        let borsh = codec::encode_borsh(&binary);
+        assert_eq!(codec::decode(&ChainType::SOL, &borsh), Some(binary));
+        assert!(verify(&client, borsh).is_valid());
+    }
+
+    #[test]
+    fn test_unknown_codec_byte_is_unparsable() {
+        let client = setup(100);
+        let unparsable =
+            VerificationResult { outcome: VerifyOutcome::Invalid(VerifyError::DeserializeFailed), block_height: 0, finalized_height: 100 };
+        let body = &proof(95)[1..];
+        for codec in [2u8, 0x7f, 0xff, b'{'] {
+            assert_eq!(verify(&client, [&[codec][..], body].concat()), unparsable, "codec {}", codec);
+        }
+        // The legacy JSON without its codec byte
+        assert_eq!(verify(&client, body.to_vec()), unparsable);
+        assert_eq!(verify(&client, vec![]), unparsable);
+        assert_eq!(verify(&client, vec![codec::BORSH_CODEC]), unparsable);
+        // JSON where borsh is named, and the other way round
+        assert_eq!(verify(&client, [&[codec::BORSH_CODEC][..], body].concat()), unparsable);
+        let borsh = codec::encode_borsh(&codec::decode(&ChainType::SOL, &proof(95)).unwrap());
+        assert_eq!(verify(&client, [&[codec::JSON_CODEC][..], &borsh[1..]].concat()), unparsable);
+    }
+
+    #[test]
+    fn test_borsh_proof_of_another_chain_is_unparsable() {
+        let block = Block::new();
+        let client = eth_setup(&block);
+        let sol = codec::decode(&ChainType::SOL, &proof(95)).unwrap();
+        let result = client
+            .verify_payment_proof(ChainType::ETH, codec::encode_borsh(&sol), RECIPIENT.to_string(), "ETH".to_string(), U128(500), MEMO.to_string(), block.tx_hash(0), None)
+            .settled();
+        assert_eq!(result, rejected(0, VerifyError::DeserializeFailed));
+        let eth = PaymentProofV1::Eth(block.proof(0).decode().unwrap());
+        assert_eq!(codec::decode(&ChainType::BTC, &codec::encode_borsh(&eth)), None);
+        assert_eq!(codec::decode(&ChainType::SOL, &codec::encode_borsh(&eth)), None);
+    }
+
+    // ------------------------------------------------------------------
+    // Height updaters
+    // ------------------------------------------------------------------
+
+    /// A client with accounts(2) as the ETH height updater, ten blocks per
+    /// update at most a minute apart, and no finalized height yet.
+    fn height_updater_setup() -> LightClient {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        client.set_height_updater(ChainType::ETH, Some(accounts(2)));
+        client.set_height_update_limits(ChainType::ETH, 60_000_000_000, 10);
+        client
+    }
+
+    fn set_height_as(client: &mut LightClient, account_id: AccountId, timestamp: u64, chain_type: ChainType, height: u64) {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(account_id).block_timestamp(timestamp).build());
+        client.set_finalized_height(chain_type, height, hex::encode([height as u8; 32]));
+    }
+
+    #[test]
+    fn test_height_updater_sets_its_chain_height() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::ETH, 100);
+        set_height_as(&mut client, accounts(2), 60_000_000_000, ChainType::ETH, 110);
+        assert_eq!(client.get_finalized_height(ChainType::ETH), 110);
+        // The updater's hash is not kept for proofs
+        assert_eq!(client.get_eth_block_hash(110), None);
+        assert_eq!(client.get_finalized_block(ChainType::ETH).unwrap().block_hash, hex::encode([110; 32]));
+        assert_eq!(
+            client.get_height_updaters(),
+            vec![HeightUpdater {
+                chain_type: ChainType::ETH,
+                account_id: accounts(2),
+                limits: HeightUpdateLimits { min_interval_ns: 60_000_000_000, max_step: 10 },
+                last_update_at: Some(60_000_000_000),
+            }]
+        );
+        assert_eq!(client.get_height_updater(ChainType::SOL), None);
+        // Not one of the quorum updaters
+        assert!(client.get_updaters().is_empty());
+    }
+
+    #[test]
+    fn test_height_updater_hash_cannot_prove_payments() {
+        let block = Block::new();
+        let mut client = height_updater_setup();
+        client.set_eth_token("USDC".to_string(), Some(TOKEN.to_string()));
+        // A hot key vouching for the header
+        let hash = hex::encode(keccak(&block.header()));
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        client.set_finalized_height(ChainType::ETH, HEIGHT, hash.clone());
+        assert_eq!(client.get_eth_block_hash(HEIGHT), None);
+        assert_eq!(verify_eth(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::UnknownBlock));
+
+        // The owner's hash is stored
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        client.set_eth_block_hash(HEIGHT, hash);
+        assert_eq!(verify_eth(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1)), proven(HEIGHT));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the chain's height updater can set its height")]
+    fn test_height_updater_is_limited_to_its_chain() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::SOL, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the chain's height updater can set its height")]
+    fn test_rotated_out_height_updater_is_rejected() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::ETH, 100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        client.set_height_updater(ChainType::ETH, Some(accounts(3)));
+        assert_eq!(client.get_height_updater(ChainType::ETH), Some(accounts(3)));
+        set_height_as(&mut client, accounts(3), 60_000_000_000, ChainType::ETH, 101);
+        set_height_as(&mut client, accounts(2), 120_000_000_000, ChainType::ETH, 102);
+    }
+
+    #[test]
+    #[should_panic(expected = "under the min interval of 60000000000 ns")]
+    fn test_height_updates_respect_the_min_interval() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::ETH, 100);
+        set_height_as(&mut client, accounts(2), 59_999_999_999, ChainType::ETH, 101);
+    }
+
+    #[test]
+    #[should_panic(expected = "Height is 11 blocks past the finalized height, over the max step of 10")]
+    fn test_height_updates_respect_the_max_step() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::ETH, 100);
+        set_height_as(&mut client, accounts(2), 60_000_000_000, ChainType::ETH, 111);
+    }
+
+    #[test]
+    fn test_owner_overrides_height_update_limits() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::ETH, 100);
+        set_height_as(&mut client, accounts(0), 1, ChainType::ETH, 200);
+        assert_eq!(client.get_finalized_height(ChainType::ETH), 200);
+        // Chains without an updater too
+        set_height_as(&mut client, accounts(0), 2, ChainType::SOL, 50);
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 50);
+        // The updater's interval runs from the owner's update
+        set_height_as(&mut client, accounts(2), 60_000_000_001, ChainType::ETH, 210);
+        assert_eq!(client.get_finalized_height(ChainType::ETH), 210);
+    }
+
+    #[test]
+    fn test_height_update_limits_default_to_the_max_height_step() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut client = LightClient::new(accounts(0));
+        client.set_max_height_step(500);
+        assert_eq!(
+            client.get_height_update_limits(ChainType::SOL),
+            HeightUpdateLimits { min_interval_ns: 0, max_step: 500 }
+        );
+        client.set_height_updater(ChainType::SOL, Some(accounts(2)));
+        client.set_height_updater(ChainType::SOL, None);
+        assert!(client.get_height_updaters().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Height must be above the finalized height")]
+    fn test_height_updater_cannot_move_height_back() {
+        let mut client = height_updater_setup();
+        set_height_as(&mut client, accounts(2), 0, ChainType::ETH, 100);
+        set_height_as(&mut client, accounts(2), 60_000_000_000, ChainType::ETH, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_set_height_updater_owner_only() {
+        let mut client = height_updater_setup();
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(2)).build());
+        client.set_height_updater(ChainType::ETH, Some(accounts(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "BTC height follows submitted headers")]
+    fn test_btc_has_no_height_updater() {
+        let mut client = height_updater_setup();
+        client.set_height_updater(ChainType::BTC, Some(accounts(2)));
+    }
+
+    // ------------------------------------------------------------------
+    // Token registry
+    // ------------------------------------------------------------------
+
+    /// Mainnet USDC, in its checksummed casing.
+    const REAL_USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    #[test]
+    fn test_registered_tokens_are_listed_per_chain() {
+        let mut client = setup(100);
+        client.register_token(ChainType::ETH, "usdc".to_string(), REAL_USDC.to_string());
+        client.register_token(ChainType::ETH, "DAI".to_string(), TOKEN.to_string());
+        // Hex casing does not make another token
+        client.register_token(ChainType::ETH, "USDC".to_string(), REAL_USDC.to_uppercase().replacen("0X", "0x", 1));
+        client.register_token(ChainType::SOL, "USDC".to_string(), SOL_MINT.to_string());
+        let token = |symbol: &str, canonical_id: &str| RegisteredToken { symbol: symbol.to_string(), canonical_id: canonical_id.to_string() };
+        assert_eq!(
+            client.get_registered_tokens(ChainType::ETH),
+            vec![token("DAI", TOKEN), token("USDC", &REAL_USDC.to_lowercase())]
+        );
+        assert_eq!(client.get_registered_tokens(ChainType::SOL), vec![token("USDC", SOL_MINT)]);
+        assert!(client.get_registered_tokens(ChainType::BTC).is_empty());
+        assert_eq!(client.get_token(ChainType::ETH, "Usdc".to_string()), Some(REAL_USDC.to_lowercase()));
+        // The chain-specific setters are the same registry
+        assert_eq!(client.get_eth_token("DAI".to_string()), Some(TOKEN.to_string()));
+
+        client.register_token(ChainType::ETH, "DAI".to_string(), REAL_USDC.to_string());
+        client.unregister_token(ChainType::ETH, "usdc".to_string());
+        assert_eq!(client.get_registered_tokens(ChainType::ETH), vec![token("DAI", &REAL_USDC.to_lowercase())]);
+        client.set_sol_mint("USDC".to_string(), None);
+        assert!(client.get_registered_tokens(ChainType::SOL).is_empty());
+    }
+
+    #[test]
+    fn test_spoofed_eth_token_is_rejected() {
+        let block = Block::new();
+        let mut client = eth_setup(&block);
+        // `Block` pays in `TOKEN`, a look-alike of the registered USDC
+        client.register_token(ChainType::ETH, "USDC".to_string(), REAL_USDC.to_lowercase());
+        assert_eq!(verify_eth(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::AssetMismatch));
+        assert_eq!(
+            verify_eth_kind(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1), erc20(TOKEN)),
+            rejected(HEIGHT, VerifyError::AssetMismatch)
+        );
+        // Under a symbol of its own, it is unregistered
+        assert_eq!(verify_eth(&client, &block.proof(1), "USDC.E", 42, &block.tx_hash(1)), rejected(HEIGHT, VerifyError::UnregisteredToken));
+
+        client.register_token(ChainType::ETH, "USDC".to_string(), TOKEN.to_string());
+        assert_eq!(
+            verify_eth_kind(&client, &block.proof(1), "USDC", 42, &block.tx_hash(1), erc20(TOKEN)),
+            proven(HEIGHT)
+        );
+    }
+
+    #[test]
+    fn test_spoofed_sol_mint_is_rejected() {
+        let (mut client, proof) = spl_setup();
+        // Another key in the mint's place
+        client.register_token(ChainType::SOL, "USDC".to_string(), SOL_RECIPIENT.to_string());
+        assert_eq!(verify_spl(&client, &proof, "USDC", 42_000_000), VerifyOutcome::Invalid(VerifyError::AssetMismatch));
+        assert_eq!(verify_spl(&client, &proof, "USDT", 42_000_000), VerifyOutcome::Invalid(VerifyError::UnregisteredToken));
+        client.register_token(ChainType::SOL, "USDC".to_string(), SOL_MINT.to_string());
+        assert_eq!(verify_spl(&client, &proof, "USDC", 42_000_000), VerifyOutcome::Valid);
+    }
+
+    #[test]
+    #[should_panic(expected = "ETH is the chain's native asset")]
+    fn test_native_symbol_cannot_be_registered() {
+        let mut client = setup(100);
+        client.register_token(ChainType::ETH, "eth".to_string(), TOKEN.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "BTC has no tokens")]
+    fn test_btc_tokens_cannot_be_registered() {
+        let mut client = setup(100);
+        client.register_token(ChainType::BTC, "WBTC".to_string(), TOKEN.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can update the light client")]
+    fn test_register_token_owner_only() {
+        let mut client = setup(100);
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(1)).build());
+        client.register_token(ChainType::ETH, "USDC".to_string(), REAL_USDC.to_string());
+    }
+
+    // ------------------------------------------------------------------
+    // State migration
+    // ------------------------------------------------------------------
+
+    #[test]
+    fn test_migrate_from_v0_layout() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(accounts(0)).build());
+        let mut finalized_heights = LookupMap::new(b"h");
+        finalized_heights.insert(&"SOL".to_string(), &100u64);
+        finalized_heights.insert(&"ETH".to_string(), &HEIGHT);
+        env::state_write(&migration::LightClientV0 { owner_id: accounts(0), finalized_heights });
+
+        let mut client = LightClient::migrate();
+        assert_eq!(client.get_state_version(), STATE_VERSION);
+        assert_eq!(client.owner_id, accounts(0));
+        assert_eq!(client.get_finalized_block(ChainType::SOL), Some(FinalizedBlock { height: 100, block_hash: String::new() }));
+        assert_eq!(client.get_finalized_height(ChainType::ETH), HEIGHT);
+        assert_eq!(client.get_finalized_height(ChainType::BTC), 0);
+        assert_eq!(client.get_max_rollback_depth(), finality::DEFAULT_MAX_ROLLBACK_DEPTH);
+
+        // Quorum reports carry on from the migrated height
+        finalize(&mut client, ChainType::SOL, 110, "sol-110");
+        assert_eq!(client.get_finalized_height(ChainType::SOL), 110);
+    }
+
+    #[test]
+    fn test_migrate_current_layout_keeps_state() {
+        let client = setup(100);
+        assert_eq!(client.get_state_version(), STATE_VERSION);
+        env::state_write(&client);
+        let client = LightClient::migrate();
+        assert_eq!(client.get_finalized_block(ChainType::SOL), Some(FinalizedBlock { height: 100, block_hash: "sol-block".to_string() }));
     }
 }
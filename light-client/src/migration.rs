@@ -0,0 +1,90 @@
+//! State migration from earlier deployed layouts.
+//!
+//! The layout of the root state is recorded under its own storage key (see
+//! `STATE_VERSION`), and `migrate` upgrades from whichever version it finds.
+//!
+//! `LightClientV0` is the layout that shipped with nothing but a finalized
+//! height per chain. Every field added since starts out as `new` sets it.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct LightClientV0 {
+    pub owner_id: AccountId,
+    pub finalized_heights: LookupMap<String, u64>,
+}
+
+/// Storage key holding the layout version of the root state. State written
+/// before versioning has no such key and is always the V0 layout.
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// Root state in any layout `migrate` knows how to upgrade.
+pub(crate) enum VersionedLightClient {
+    V0(Box<LightClientV0>),
+    /// The current layout (`STATE_VERSION` 1).
+    V1(Box<LightClient>),
+}
+
+impl VersionedLightClient {
+    /// Read the root state using the layout its version key names.
+    fn read() -> Self {
+        match stored_state_version() {
+            0 => Self::V0(Box::new(env::state_read().expect("No V0 state to migrate"))),
+            1 => Self::V1(Box::new(env::state_read().expect("No V1 state to migrate"))),
+            version => env::panic_str(&format!("Unknown state version {}", version)),
+        }
+    }
+}
+
+impl From<VersionedLightClient> for LightClient {
+    fn from(state: VersionedLightClient) -> Self {
+        match state {
+            VersionedLightClient::V0(old) => LightClient::from(*old),
+            VersionedLightClient::V1(current) => *current,
+        }
+    }
+}
+
+impl From<LightClientV0> for LightClient {
+    /// V0 kept bare heights under the prefix `finalized` now uses, so each
+    /// is moved into a `FinalizedBlock`. No hash was recorded: the migrated
+    /// block is no rollback target, and a jump past the max height step
+    /// cannot be linked to it by headers until the next quorum report.
+    fn from(old: LightClientV0) -> Self {
+        let mut heights = old.finalized_heights;
+        let mut client = LightClient::new(old.owner_id);
+        for chain_type in [ChainType::BTC, ChainType::ETH, ChainType::SOL] {
+            let key = chain_key(&chain_type);
+            if let Some(height) = heights.remove(&key) {
+                client.finalized.insert(&key, &FinalizedBlock { height, block_hash: String::new() });
+            }
+        }
+        client
+    }
+}
+
+fn stored_state_version() -> u8 {
+    env::storage_read(STATE_VERSION_KEY).map_or(0, |bytes| bytes[0])
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Upgrade the stored state to the current layout, whichever version it
+    /// was written in. Already-current state is returned unchanged, so
+    /// calling it again after an upgrade is harmless.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let client = LightClient::from(VersionedLightClient::read());
+        write_state_version();
+        client
+    }
+
+    pub fn get_state_version(&self) -> u8 {
+        stored_state_version()
+    }
+}
@@ -0,0 +1,89 @@
+//! Inclusion proofs against an Ethereum Merkle-Patricia trie root, as
+//! returned by `eth_getProof`-style tooling: the nodes from the root down
+//! to the one holding the value.
+
+use crate::rlp::{self, Item};
+use near_sdk::env;
+
+/// Where the walk goes next: a node referenced by hash (taken from the
+/// proof) or one embedded in its parent because it is under 32 bytes.
+enum NodeRef<'a> {
+    Hash(&'a [u8]),
+    Inline(&'a [u8]),
+}
+
+fn child_ref<'a>(item: &Item<'a>) -> Option<NodeRef<'a>> {
+    if item.is_list {
+        return Some(NodeRef::Inline(item.raw));
+    }
+    match item.payload.len() {
+        32 => Some(NodeRef::Hash(item.payload)),
+        _ => None,
+    }
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Hex-prefix encoded path of a leaf or extension node: its nibbles and
+/// whether it is a leaf.
+fn decode_path(encoded: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let all = nibbles(encoded);
+    let flag = *all.first()?;
+    if flag > 3 {
+        return None;
+    }
+    let skip = if flag & 1 == 1 { 1 } else { 2 };
+    if all.len() < skip {
+        return None;
+    }
+    Some((all[skip..].to_vec(), flag & 2 == 2))
+}
+
+/// Value stored under `key` in the trie with `root`, if `proof` shows it.
+/// Every proof node must be used, and each must hash to the reference its
+/// parent (or the root) holds.
+pub(crate) fn verify_proof(root: &[u8; 32], key: &[u8], proof: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let path = nibbles(key);
+    let mut consumed = 0;
+    let mut proof_nodes = proof.iter();
+    let mut next = NodeRef::Hash(root);
+    loop {
+        let encoded: &[u8] = match next {
+            NodeRef::Hash(hash) => {
+                let node = proof_nodes.next()?;
+                if env::keccak256_array(node).as_slice() != hash {
+                    return None;
+                }
+                node
+            }
+            NodeRef::Inline(raw) => raw,
+        };
+        let items = rlp::decode_exact(encoded)?.list()?;
+        match items.len() {
+            17 => {
+                if consumed == path.len() {
+                    let value = items[16].bytes()?;
+                    return (!value.is_empty() && proof_nodes.next().is_none()).then(|| value.to_vec());
+                }
+                next = child_ref(&items[path[consumed] as usize])?;
+                consumed += 1;
+            }
+            2 => {
+                let (node_path, is_leaf) = decode_path(items[0].bytes()?)?;
+                let rest = &path[consumed..];
+                if is_leaf {
+                    let value = items[1].bytes()?;
+                    return (rest == node_path.as_slice() && proof_nodes.next().is_none()).then(|| value.to_vec());
+                }
+                if !rest.starts_with(&node_path) {
+                    return None;
+                }
+                consumed += node_path.len();
+                next = child_ref(&items[1])?;
+            }
+            _ => return None,
+        }
+    }
+}
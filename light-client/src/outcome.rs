@@ -0,0 +1,106 @@
+//! Why a proof was accepted or not. Every chain's checks reduce their
+//! failures to one `VerifyError`, so callers (and relayers reading the
+//! result) see the concrete reason rather than a bare `false`.
+
+use std::fmt;
+
+use crate::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VerifyOutcome {
+    Valid,
+    Invalid(VerifyError),
+}
+
+impl VerifyOutcome {
+    pub fn is_valid(&self) -> bool {
+        *self == VerifyOutcome::Valid
+    }
+}
+
+impl From<Result<(), VerifyError>> for VerifyOutcome {
+    fn from(result: Result<(), VerifyError>) -> Self {
+        match result {
+            Ok(()) => VerifyOutcome::Valid,
+            Err(error) => VerifyOutcome::Invalid(error),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VerifyError {
+    /// `proof_data` (or one of its hex fields) could not be decoded.
+    DeserializeFailed,
+    /// An expected or proven recipient that is not a valid address on the
+    /// chain.
+    InvalidAddress,
+    RecipientMismatch,
+    /// Wrong asset, or the wrong native / ERC-20 kind or token contract.
+    AssetMismatch,
+    /// An expected asset with no `register_token` entry for the chain.
+    UnregisteredToken,
+    AmountMismatch,
+    MemoMismatch,
+    TxHashMismatch,
+    /// A SOL transaction whose fee payer signature does not verify.
+    InvalidSignature,
+    /// The proof's block is not finalized with the chain's required
+    /// confirmations.
+    NotFinalized { proof_height: u64, finalized: u64 },
+    /// The proof's block is more than the chain's max proof age below the
+    /// finalized height.
+    ProofTooOld { proof_height: u64, finalized: u64, max_age: u64 },
+    /// The proof's block is below the chain's trusted checkpoint.
+    BelowCheckpoint { proof_height: u64, checkpoint: u64 },
+    EmptyInclusionProof,
+    /// The transaction (or receipt) does not prove into the block.
+    InclusionProofInvalid,
+    /// The ETH prover call failed, so its answer is unknown.
+    ProverFailed,
+    /// No stored header, hash or root for the proof's block.
+    UnknownBlock,
+    /// An ETH header or BTC block that does not match the stored block
+    /// hash or submitted header for its height.
+    HeaderMismatch,
+    /// The ETH receipt reports a reverted transaction.
+    TransactionFailed,
+    ChainPaused,
+    /// `consume_payment_proof` already spent this proof.
+    AlreadyConsumed,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use VerifyError::*;
+        match self {
+            DeserializeFailed => write!(f, "unparsable proof"),
+            InvalidAddress => write!(f, "invalid address"),
+            RecipientMismatch => write!(f, "recipient mismatch"),
+            AssetMismatch => write!(f, "asset mismatch"),
+            UnregisteredToken => write!(f, "token not registered"),
+            AmountMismatch => write!(f, "amount mismatch"),
+            MemoMismatch => write!(f, "memo mismatch"),
+            TxHashMismatch => write!(f, "tx hash mismatch"),
+            InvalidSignature => write!(f, "invalid signature"),
+            NotFinalized { proof_height, finalized } => {
+                write!(f, "block {} not finalized (finalized {})", proof_height, finalized)
+            }
+            ProofTooOld { proof_height, finalized, max_age } => {
+                write!(f, "block {} older than {} blocks below finalized {}", proof_height, max_age, finalized)
+            }
+            BelowCheckpoint { proof_height, checkpoint } => {
+                write!(f, "block {} below checkpoint {}", proof_height, checkpoint)
+            }
+            EmptyInclusionProof => write!(f, "empty inclusion proof"),
+            InclusionProofInvalid => write!(f, "transaction not in block"),
+            ProverFailed => write!(f, "prover call failed"),
+            UnknownBlock => write!(f, "unknown block"),
+            HeaderMismatch => write!(f, "header does not match stored block hash"),
+            TransactionFailed => write!(f, "transaction failed"),
+            ChainPaused => write!(f, "chain paused"),
+            AlreadyConsumed => write!(f, "proof already consumed"),
+        }
+    }
+}
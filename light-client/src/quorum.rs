@@ -0,0 +1,194 @@
+//! Finalized heights by updater quorum. Owner-managed updaters report
+//! `(height, block_hash)` per chain; the chain's finalized height moves
+//! to a height once `updater_threshold` distinct updaters have reported
+//! the same hash for it within the report window. An ETH height that
+//! reaches quorum also stores its block hash for proofs.
+//!
+//! Heights only move forward, by at most `max_height_step` unless an ETH
+//! report carries the headers in between (see `finality.rs`).
+//!
+//! Two fresh reports with different hashes at one height freeze the
+//! chain: no further reports are taken until the owner resolves it. BTC
+//! is not reported; its height follows the SPV header chain.
+
+use crate::eth::{self, parse_hex};
+use crate::*;
+
+/// Ten minutes.
+pub const DEFAULT_REPORT_WINDOW_NS: u64 = 600_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeightReport {
+    pub updater: AccountId,
+    pub height: u64,
+    pub block_hash: String,
+    /// Block timestamp (ns) of the report.
+    pub reported_at: u64,
+}
+
+#[near_bindgen]
+impl LightClient {
+    pub fn add_updater(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.updaters.insert(&account_id);
+    }
+
+    pub fn remove_updater(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        if self.updaters.remove(&account_id) {
+            assert!(
+                self.updaters.len() >= self.updater_threshold as u64,
+                "Removing would leave fewer updaters than the threshold"
+            );
+        }
+    }
+
+    pub fn set_updater_threshold(&mut self, threshold: u32) {
+        self.assert_owner();
+        assert!(
+            threshold > 0 && threshold as u64 <= self.updaters.len(),
+            "Threshold must be between 1 and the number of updaters"
+        );
+        self.updater_threshold = threshold;
+    }
+
+    /// How long a report counts towards quorum.
+    pub fn set_report_window(&mut self, window_ns: u64) {
+        self.assert_owner();
+        assert!(window_ns > 0, "Report window must be positive");
+        self.report_window_ns = window_ns;
+    }
+
+    /// Owner clears a chain's reports after a conflict and lets updaters
+    /// report again.
+    pub fn resolve_height_conflict(&mut self, chain_type: ChainType) {
+        self.assert_owner();
+        let key = chain_key(&chain_type);
+        self.frozen_chains.remove(&key);
+        self.height_reports.remove(&key);
+        env::log_str(&format!("HEIGHT_CONFLICT_RESOLVED:chain={}", key));
+    }
+
+    /// An updater reports `height` as finalized with `block_hash` (32
+    /// bytes of hex for ETH). A later report replaces the updater's
+    /// earlier one. The height may be at most `max_height_step` above the
+    /// finalized one.
+    pub fn report_finalized_height(&mut self, chain_type: ChainType, height: u64, block_hash: String) {
+        self.report_height(chain_type, height, block_hash, None);
+    }
+
+    /// `report_finalized_height` for a jump past `max_height_step`: ETH
+    /// only, with the RLP headers from the one above the finalized block
+    /// up to the reported block, each the parent of the next.
+    pub fn report_finalized_height_with_headers(
+        &mut self,
+        chain_type: ChainType,
+        height: u64,
+        block_hash: String,
+        headers: Vec<String>,
+    ) {
+        self.report_height(chain_type, height, block_hash, Some(headers));
+    }
+
+    pub fn get_updaters(&self) -> Vec<AccountId> {
+        self.updaters.to_vec()
+    }
+
+    pub fn get_updater_threshold(&self) -> u32 {
+        self.updater_threshold
+    }
+
+    pub fn get_report_window(&self) -> u64 {
+        self.report_window_ns
+    }
+
+    /// Reports on `chain_type` that still count towards quorum.
+    pub fn get_height_reports(&self, chain_type: ChainType) -> Vec<HeightReport> {
+        self.fresh_reports(&chain_key(&chain_type))
+    }
+
+    pub fn is_height_frozen(&self, chain_type: ChainType) -> bool {
+        self.frozen_chains.contains(&chain_key(&chain_type))
+    }
+}
+
+impl LightClient {
+    fn report_height(&mut self, chain_type: ChainType, height: u64, block_hash: String, headers: Option<Vec<String>>) {
+        let updater = env::predecessor_account_id();
+        assert!(self.updaters.contains(&updater), "Only updaters can report heights");
+        assert!(chain_type != ChainType::BTC, "BTC height follows submitted headers");
+        let key = chain_key(&chain_type);
+        assert!(
+            !self.frozen_chains.contains(&key),
+            "{} height is frozen by conflicting reports",
+            key
+        );
+        let eth_hash = (chain_type == ChainType::ETH).then(|| {
+            parse_hex(&block_hash)
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .unwrap_or_else(|| env::panic_str("Block hash must be 32 bytes of hex"))
+        });
+        if let Some(current) = self.finalized.get(&key) {
+            assert!(height > current.height, "Height must be above the finalized height");
+            let step = height - current.height;
+            if step > self.max_height_step {
+                let linked = match (eth_hash, &headers) {
+                    (Some(hash), Some(headers)) => eth::headers_link(&current, height, hash, headers),
+                    _ => false,
+                };
+                assert!(
+                    linked,
+                    "Height is {} blocks past the finalized height, over the max step of {}, without linking headers",
+                    step,
+                    self.max_height_step
+                );
+            }
+        }
+
+        let mut reports = self.fresh_reports(&key);
+        reports.retain(|report| report.updater != updater);
+        if let Some(conflict) = reports
+            .iter()
+            .find(|report| report.height == height && report.block_hash != block_hash)
+        {
+            env::log_str(&format!(
+                "HEIGHT_CONFLICT:chain={},height={},block_hash={},reported_by={},conflicting_hash={},conflicting_reporter={}",
+                key, height, block_hash, updater, conflict.block_hash, conflict.updater
+            ));
+            self.frozen_chains.insert(&key);
+        }
+        reports.push(HeightReport {
+            updater,
+            height,
+            block_hash: block_hash.clone(),
+            reported_at: env::block_timestamp(),
+        });
+        let agreeing = reports
+            .iter()
+            .filter(|report| report.height == height && report.block_hash == block_hash)
+            .count();
+        if !self.frozen_chains.contains(&key) && agreeing >= self.updater_threshold as usize {
+            self.advance_finalized(&key, height, block_hash.clone());
+            if let Some(hash) = eth_hash {
+                self.eth_block_hashes.insert(&height, &hash);
+            }
+            reports.retain(|report| report.height > height);
+            env::log_str(&format!(
+                "HEIGHT_FINALIZED:chain={},height={},block_hash={}",
+                key, height, block_hash
+            ));
+        }
+        self.height_reports.insert(&key, &reports);
+    }
+
+    /// Stored reports still inside the window from current updaters.
+    fn fresh_reports(&self, key: &String) -> Vec<HeightReport> {
+        let now = env::block_timestamp();
+        let mut reports = self.height_reports.get(key).unwrap_or_default();
+        reports.retain(|report| {
+            now.saturating_sub(report.reported_at) <= self.report_window_ns && self.updaters.contains(&report.updater)
+        });
+        reports
+    }
+}
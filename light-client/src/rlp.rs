@@ -0,0 +1,89 @@
+//! Just enough RLP to read Ethereum headers, receipts, transactions and trie
+//! nodes. Decoding never panics: malformed input yields `None`, so a bad
+//! proof is reported as invalid instead of aborting the call.
+
+/// One decoded item. `raw` is the full encoding including its header,
+/// which trie nodes embedded in their parent are hashed and walked by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Item<'a> {
+    pub is_list: bool,
+    pub payload: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+impl<'a> Item<'a> {
+    pub fn bytes(&self) -> Option<&'a [u8]> {
+        (!self.is_list).then_some(self.payload)
+    }
+
+    pub fn list(&self) -> Option<Vec<Item<'a>>> {
+        if !self.is_list {
+            return None;
+        }
+        let mut items = Vec::new();
+        let mut rest = self.payload;
+        while !rest.is_empty() {
+            let (item, next) = decode(rest)?;
+            items.push(item);
+            rest = next;
+        }
+        Some(items)
+    }
+
+    /// Big-endian unsigned integer of at most 16 bytes.
+    pub fn uint(&self) -> Option<u128> {
+        let bytes = self.bytes()?;
+        if bytes.len() > 16 || bytes.first() == Some(&0) {
+            return None;
+        }
+        Some(bytes.iter().fold(0u128, |acc, b| (acc << 8) | *b as u128))
+    }
+}
+
+/// First item of `buf` and whatever follows it.
+pub(crate) fn decode(buf: &[u8]) -> Option<(Item<'_>, &[u8])> {
+    let (&prefix, rest) = buf.split_first()?;
+    let (is_list, header_len, len) = match prefix {
+        0x00..=0x7f => {
+            return Some((Item { is_list: false, payload: &buf[..1], raw: &buf[..1] }, rest));
+        }
+        0x80..=0xb7 => (false, 0, (prefix - 0x80) as usize),
+        0xb8..=0xbf => (false, (prefix - 0xb7) as usize, 0),
+        0xc0..=0xf7 => (true, 0, (prefix - 0xc0) as usize),
+        0xf8..=0xff => (true, (prefix - 0xf7) as usize, 0),
+    };
+    let (len, rest) = if header_len == 0 {
+        (len, rest)
+    } else {
+        if rest.len() < header_len || header_len > 4 {
+            return None;
+        }
+        let len = rest[..header_len].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, &rest[header_len..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let consumed = buf.len() - rest.len() + len;
+    let item = Item { is_list, payload: &rest[..len], raw: &buf[..consumed] };
+    Some((item, &rest[len..]))
+}
+
+/// `buf` holding exactly one item.
+pub(crate) fn decode_exact(buf: &[u8]) -> Option<Item<'_>> {
+    match decode(buf)? {
+        (item, []) => Some(item),
+        _ => None,
+    }
+}
+
+/// RLP encoding of `value` as an integer, used for receipt and transaction
+/// trie keys.
+pub(crate) fn encode_uint(value: u64) -> Vec<u8> {
+    let bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    match bytes.as_slice() {
+        [] => vec![0x80],
+        [b] if *b < 0x80 => vec![*b],
+        _ => [vec![0x80 + bytes.len() as u8], bytes].concat(),
+    }
+}
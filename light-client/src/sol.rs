@@ -0,0 +1,140 @@
+//! SOL payment proofs. The payment is read from the serialized
+//! transaction itself: the fee payer's signature must verify over the
+//! message and be the expected tx hash, and the message must hold a
+//! System Program transfer of exactly the amount to the recipient (or an
+//! SPL `TransferChecked` of the asset's registered mint to the recipient
+//! token account) plus a Memo program instruction carrying the memo.
+//! The slot is anchored only by the updater-registered Merkle root of
+//! its signatures and the reported finalized height (`inclusion.rs`).
+
+use crate::btc_tx::base58_decode;
+use crate::eth::parse_hex;
+use crate::inclusion::{self, Sibling};
+use crate::sol_tx::Transfer;
+use crate::*;
+
+/// A SOL payment proof in the JSON format.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolPaymentProof {
+    /// Hex of the serialized transaction, signatures then message.
+    pub transaction: String,
+    /// The slot that holds the transaction.
+    pub block_height: u64,
+    /// `"L:<hex>"` / `"R:<hex>"` siblings from `sha256(tx_hash)` up to the
+    /// slot's registered root, see `inclusion.rs`.
+    pub inclusion_proof: Vec<String>,
+}
+
+/// A SOL payment proof in the binary format (`codec.rs`);
+/// `SolPaymentProof` decodes to this.
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub struct SolTxProof {
+    /// The serialized transaction, signatures then message.
+    pub transaction: Vec<u8>,
+    /// The slot that holds the transaction.
+    pub block_height: u64,
+    /// Siblings from `sha256(tx_hash)` up to the slot's registered root.
+    pub inclusion_proof: Vec<Sibling>,
+}
+
+impl SolPaymentProof {
+    /// The binary form, `None` if the transaction is not hex or an
+    /// inclusion entry is malformed.
+    pub(crate) fn decode(self) -> Option<SolTxProof> {
+        Some(SolTxProof {
+            transaction: parse_hex(&self.transaction)?,
+            block_height: self.block_height,
+            inclusion_proof: self
+                .inclusion_proof
+                .iter()
+                .map(|entry| inclusion::parse_sibling(entry))
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// `register_token` on SOL with `Some`, `unregister_token` with `None`.
+    pub fn set_sol_mint(&mut self, asset: String, mint: Option<String>) {
+        match mint {
+            Some(mint) => self.register_token(ChainType::SOL, asset, mint),
+            None => self.unregister_token(ChainType::SOL, asset),
+        }
+    }
+
+    pub fn get_sol_mint(&self, asset: String) -> Option<String> {
+        self.get_token(ChainType::SOL, asset)
+    }
+}
+
+impl LightClient {
+    /// `check_sol_payment`, logging why a proof was rejected.
+    pub(crate) fn sol_verdict(&self, proof: &SolTxProof, expected: &ExpectedPayment) -> VerificationResult {
+        let finalized_height = self.get_finalized_height(ChainType::SOL);
+        let verdict = |block_height: u64, result: Result<(), VerifyError>| {
+            if let Err(error) = result {
+                env::log_str(&format!("SOL proof rejected: {}", error));
+            }
+            VerificationResult { outcome: result.into(), block_height, finalized_height }
+        };
+        // TODO: Anchor slots cryptographically (bank hash / vote
+        // verification) rather than by roots and heights from updaters.
+        verdict(proof.block_height, self.check_sol_payment(proof, expected, finalized_height))
+    }
+
+    fn check_sol_payment(
+        &self,
+        proof: &SolTxProof,
+        expected: &ExpectedPayment,
+        finalized_height: u64,
+    ) -> Result<(), VerifyError> {
+        self.check_checkpoint(&ChainType::SOL, proof.block_height)?;
+        let tx = sol_tx::decode(&proof.transaction).ok_or(VerifyError::DeserializeFailed)?;
+        let signature = tx.signatures[0];
+        if base58_decode(expected.tx_hash).as_deref() != Some(&signature[..]) {
+            return Err(VerifyError::TxHashMismatch);
+        }
+        if !env::ed25519_verify(&signature, tx.message, &tx.fee_payer) {
+            return Err(VerifyError::InvalidSignature);
+        }
+
+        let recipient = address::sol(expected.recipient)?;
+        let mint = self.expected_mint(expected)?;
+        let paid: Vec<&Transfer> = tx.transfers.iter().filter(|transfer| transfer.destination == recipient).collect();
+        if paid.is_empty() {
+            return Err(VerifyError::RecipientMismatch);
+        }
+        if !paid.iter().any(|transfer| transfer.mint == mint) {
+            return Err(VerifyError::AssetMismatch);
+        }
+        if !paid
+            .iter()
+            .any(|transfer| transfer.mint == mint && u128::from(transfer.amount) == expected.amount)
+        {
+            return Err(VerifyError::AmountMismatch);
+        }
+        if !tx.memos.contains(&expected.memo.as_bytes()) {
+            return Err(VerifyError::MemoMismatch);
+        }
+
+        self.check_inclusion(&ChainType::SOL, proof.block_height, expected.tx_hash, &proof.inclusion_proof)?;
+        if finalized_height == 0 || !self.has_confirmations(&ChainType::SOL, proof.block_height, finalized_height) {
+            return Err(VerifyError::NotFinalized { proof_height: proof.block_height, finalized: finalized_height });
+        }
+        self.check_proof_age(&ChainType::SOL, proof.block_height, finalized_height)?;
+        Ok(())
+    }
+
+    /// `None` for lamports, else the registered mint of the asset.
+    fn expected_mint(&self, expected: &ExpectedPayment) -> Result<Option<[u8; 32]>, VerifyError> {
+        if expected.asset.eq_ignore_ascii_case("SOL") {
+            return Ok(None);
+        }
+        self.sol_mints
+            .get(&expected.asset.to_uppercase())
+            .map(Some)
+            .ok_or(VerifyError::UnregisteredToken)
+    }
+}
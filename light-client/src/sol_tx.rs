@@ -0,0 +1,170 @@
+//! Solana transaction decoding: the signatures and the legacy or v0
+//! message they sign, and from its instructions the payments a proof can
+//! show. Those are System Program transfers, SPL Token `TransferChecked`s
+//! (plain `Transfer` names no mint) and Memo program data. Accounts
+//! loaded from v0 address lookup tables are not known here, so an
+//! instruction that names one is skipped.
+
+/// `11111111111111111111111111111111`.
+const SYSTEM_PROGRAM: [u8; 32] = [0; 32];
+
+/// `TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA` and Token-2022,
+/// `TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb`.
+const TOKEN_PROGRAMS: [[u8; 32]; 2] = [
+    [
+        6, 221, 246, 225, 215, 101, 161, 147, 217, 203, 225, 70, 206, 235, 121, 172, 28, 180, 133, 237, 95, 91, 55,
+        145, 58, 140, 245, 133, 126, 255, 0, 169,
+    ],
+    [
+        6, 221, 246, 225, 238, 117, 143, 222, 24, 66, 93, 188, 228, 108, 205, 218, 182, 26, 252, 77, 131, 185, 13, 39,
+        254, 189, 249, 40, 216, 161, 139, 252,
+    ],
+];
+
+/// `MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr` and the older
+/// `Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo`.
+const MEMO_PROGRAMS: [[u8; 32]; 2] = [
+    [
+        5, 74, 83, 90, 153, 41, 33, 6, 77, 36, 232, 113, 96, 218, 56, 124, 124, 53, 181, 221, 188, 146, 187, 129, 228,
+        31, 168, 64, 65, 5, 68, 141,
+    ],
+    [
+        5, 74, 83, 80, 248, 93, 200, 130, 214, 20, 165, 86, 114, 120, 138, 41, 109, 223, 30, 171, 171, 208, 166, 6,
+        120, 136, 73, 50, 244, 238, 246, 160,
+    ],
+];
+
+const SYSTEM_TRANSFER: [u8; 4] = [2, 0, 0, 0];
+const TOKEN_TRANSFER_CHECKED: u8 = 12;
+
+pub(crate) struct Transaction<'a> {
+    /// The first is the transaction's id, made by the fee payer.
+    pub signatures: Vec<[u8; 64]>,
+    /// The signed bytes.
+    pub message: &'a [u8],
+    pub fee_payer: [u8; 32],
+    pub transfers: Vec<Transfer>,
+    pub memos: Vec<&'a [u8]>,
+}
+
+pub(crate) struct Transfer {
+    /// `None` for lamports, else the SPL mint.
+    pub mint: Option<[u8; 32]>,
+    /// The receiving wallet for lamports, the receiving token account for
+    /// SPL tokens.
+    pub destination: [u8; 32],
+    pub amount: u64,
+}
+
+struct Instruction<'a> {
+    program: [u8; 32],
+    accounts: &'a [u8],
+    data: &'a [u8],
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let slice = self.bytes.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    /// "Compact-u16": seven bits a byte, low first, at most three bytes.
+    fn compact_len(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        for shift in [0, 7, 14] {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return u16::try_from(value).ok().map(usize::from);
+            }
+        }
+        None
+    }
+
+    fn compact_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.compact_len()?;
+        self.take(len)
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Option<Transaction<'_>> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let signatures = (0..reader.compact_len()?)
+        .map(|_| reader.array::<64>())
+        .collect::<Option<Vec<_>>>()?;
+    let message = &bytes[reader.pos..];
+
+    let mut reader = Reader { bytes: message, pos: 0 };
+    let versioned = message.first()? & 0x80 != 0;
+    if versioned && reader.byte()? != 0x80 {
+        return None;
+    }
+    let required_signatures = reader.byte()?;
+    reader.take(2)?;
+    if required_signatures == 0 || usize::from(required_signatures) != signatures.len() {
+        return None;
+    }
+    let keys = (0..reader.compact_len()?)
+        .map(|_| reader.array::<32>())
+        .collect::<Option<Vec<_>>>()?;
+    reader.take(32)?;
+    let instructions = (0..reader.compact_len()?)
+        .map(|_| {
+            let program = *keys.get(usize::from(reader.byte()?))?;
+            let accounts = reader.compact_bytes()?;
+            let data = reader.compact_bytes()?;
+            Some(Instruction { program, accounts, data })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if versioned {
+        for _ in 0..reader.compact_len()? {
+            reader.take(32)?;
+            reader.compact_bytes()?;
+            reader.compact_bytes()?;
+        }
+    }
+    if reader.pos != message.len() {
+        return None;
+    }
+
+    let account = |instruction: &Instruction, position: usize| {
+        keys.get(usize::from(*instruction.accounts.get(position)?)).copied()
+    };
+    let mut transfers = Vec::new();
+    let mut memos = Vec::new();
+    for instruction in &instructions {
+        let data = instruction.data;
+        if instruction.program == SYSTEM_PROGRAM && data.len() == 12 && data[..4] == SYSTEM_TRANSFER {
+            if let Some(destination) = account(instruction, 1) {
+                let amount = u64::from_le_bytes(data[4..].try_into().unwrap());
+                transfers.push(Transfer { mint: None, destination, amount });
+            }
+        } else if TOKEN_PROGRAMS.contains(&instruction.program)
+            && data.len() == 10
+            && data[0] == TOKEN_TRANSFER_CHECKED
+        {
+            if let (Some(mint), Some(destination)) = (account(instruction, 1), account(instruction, 2)) {
+                let amount = u64::from_le_bytes(data[1..9].try_into().unwrap());
+                transfers.push(Transfer { mint: Some(mint), destination, amount });
+            }
+        } else if MEMO_PROGRAMS.contains(&instruction.program) {
+            memos.push(data);
+        }
+    }
+    Some(Transaction { signatures, message, fee_payer: *keys.first()?, transfers, memos })
+}
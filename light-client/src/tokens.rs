@@ -0,0 +1,119 @@
+//! Token registry: the on-chain identifier each asset symbol stands for,
+//! per chain. An ETH symbol maps to its ERC-20 contract, a SOL symbol to
+//! its SPL mint; BTC has only its native coin. Symbols are compared
+//! uppercased and identifiers as bytes, so `0xA0b8..` and `0xa0b8..` are
+//! one contract. The chain's native symbol (`ETH`, `SOL`) needs no entry,
+//! and any other symbol without one is `UnregisteredToken`, so a proof of
+//! a look-alike token is never read as the real one.
+
+use crate::btc_tx::base58_encode;
+use crate::eth::parse_address;
+use crate::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RegisteredToken {
+    pub symbol: String,
+    /// `0x`-prefixed lowercase hex for ETH, base58 for SOL.
+    pub canonical_id: String,
+}
+
+#[near_bindgen]
+impl LightClient {
+    /// Owner maps `symbol` on `chain_type` to `canonical_id`: an ERC-20
+    /// contract (hex) on ETH, an SPL mint (base58) on SOL. Registering a
+    /// symbol again replaces its identifier.
+    pub fn register_token(&mut self, chain_type: ChainType, symbol: String, canonical_id: String) {
+        self.assert_owner();
+        let symbol = symbol.to_uppercase();
+        assert!(!symbol.is_empty(), "Token symbol must not be empty");
+        assert!(
+            symbol != native_symbol(&chain_type),
+            "{} is the chain's native asset",
+            symbol
+        );
+        match chain_type {
+            ChainType::ETH => {
+                let address =
+                    parse_address(&canonical_id).unwrap_or_else(|| env::panic_str("Token must be a 20-byte hex address"));
+                self.eth_tokens.insert(&symbol, &address);
+            }
+            ChainType::SOL => {
+                let mint =
+                    address::sol(&canonical_id).unwrap_or_else(|_| env::panic_str("Mint must be a base58 32-byte key"));
+                self.sol_mints.insert(&symbol, &mint);
+            }
+            ChainType::BTC => env::panic_str("BTC has no tokens"),
+        }
+        let key = chain_key(&chain_type);
+        let mut symbols = self.token_symbols.get(&key).unwrap_or_default();
+        if let Err(position) = symbols.binary_search(&symbol) {
+            symbols.insert(position, symbol.clone());
+            self.token_symbols.insert(&key, &symbols);
+        }
+        env::log_str(&format!(
+            "TOKEN_REGISTERED:chain={},symbol={},id={}",
+            key,
+            symbol,
+            self.canonical_id(&chain_type, &symbol).unwrap()
+        ));
+    }
+
+    pub fn unregister_token(&mut self, chain_type: ChainType, symbol: String) {
+        self.assert_owner();
+        let symbol = symbol.to_uppercase();
+        match chain_type {
+            ChainType::ETH => {
+                self.eth_tokens.remove(&symbol);
+            }
+            ChainType::SOL => {
+                self.sol_mints.remove(&symbol);
+            }
+            ChainType::BTC => {}
+        }
+        let key = chain_key(&chain_type);
+        let mut symbols = self.token_symbols.get(&key).unwrap_or_default();
+        if let Ok(position) = symbols.binary_search(&symbol) {
+            symbols.remove(position);
+            self.token_symbols.insert(&key, &symbols);
+            env::log_str(&format!("TOKEN_UNREGISTERED:chain={},symbol={}", key, symbol));
+        }
+    }
+
+    pub fn get_token(&self, chain_type: ChainType, symbol: String) -> Option<String> {
+        self.canonical_id(&chain_type, &symbol.to_uppercase())
+    }
+
+    /// The chain's registered tokens, by symbol.
+    pub fn get_registered_tokens(&self, chain_type: ChainType) -> Vec<RegisteredToken> {
+        self.token_symbols
+            .get(&chain_key(&chain_type))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|symbol| {
+                let canonical_id = self.canonical_id(&chain_type, &symbol)?;
+                Some(RegisteredToken { symbol, canonical_id })
+            })
+            .collect()
+    }
+}
+
+impl LightClient {
+    fn canonical_id(&self, chain_type: &ChainType, symbol: &str) -> Option<String> {
+        let symbol = symbol.to_string();
+        match chain_type {
+            ChainType::ETH => self.eth_tokens.get(&symbol).map(|address| format!("0x{}", hex::encode(address))),
+            ChainType::SOL => self.sol_mints.get(&symbol).map(|mint| base58_encode(&mint)),
+            ChainType::BTC => None,
+        }
+    }
+}
+
+/// The symbol of the chain's own coin.
+pub(crate) fn native_symbol(chain_type: &ChainType) -> &'static str {
+    match chain_type {
+        ChainType::BTC => "BTC",
+        ChainType::ETH => "ETH",
+        ChainType::SOL => "SOL",
+    }
+}
@@ -0,0 +1,176 @@
+//! The 256-bit arithmetic Bitcoin difficulty needs: compact `bits`
+//! targets, retargeting and chain work. Limbs are little-endian.
+
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct U256([u64; 4]);
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+
+    /// A block hash as the little-endian number proof-of-work compares.
+    pub fn from_le_bytes(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+        }
+        U256(limbs)
+    }
+
+    /// Target encoded by `bits`; `None` for negative or overflowing
+    /// encodings, which no valid header uses.
+    pub fn from_compact(bits: u32) -> Option<Self> {
+        let exponent = bits >> 24;
+        let mantissa = bits & 0x007f_ffff;
+        if bits & 0x0080_0000 != 0 && mantissa != 0 {
+            return None;
+        }
+        if exponent <= 3 {
+            return Some(U256::from_u64((mantissa >> (8 * (3 - exponent))) as u64));
+        }
+        let shift = 8 * (exponent - 3);
+        let value = U256::from_u64(mantissa as u64);
+        if mantissa != 0 && shift + (32 - mantissa.leading_zeros()) > 256 {
+            return None;
+        }
+        Some(value.shl(shift))
+    }
+
+    /// Compact encoding, rounding down as Bitcoin Core does.
+    pub fn to_compact(self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            self.shr(8 * (size - 3)).low_u64() as u32
+        };
+        if compact & 0x0080_0000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+        compact | (size << 24)
+    }
+
+    /// Number of significant bits.
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// `None` if it does not fit.
+    pub fn to_u128(self) -> Option<u128> {
+        (self.0[2] == 0 && self.0[3] == 0).then(|| ((self.0[1] as u128) << 64) | self.0[0] as u128)
+    }
+
+    pub fn shl(&self, shift: u32) -> Self {
+        let mut out = [0u64; 4];
+        let (limbs, bits) = ((shift / 64) as usize, shift % 64);
+        for i in (limbs..4).rev() {
+            out[i] = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                out[i] |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        U256(out)
+    }
+
+    pub fn shr(&self, shift: u32) -> Self {
+        let mut out = [0u64; 4];
+        let (limbs, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in out.iter_mut().enumerate().take(4usize.saturating_sub(limbs)) {
+            *limb = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                *limb |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+        U256(out)
+    }
+
+    pub fn not(&self) -> Self {
+        U256(self.0.map(|limb| !limb))
+    }
+
+    /// Wrapping addition.
+    pub fn add(&self, other: &Self) -> Self {
+        let mut out = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        U256(out)
+    }
+
+    /// Wrapping subtraction.
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.not().add(&U256::from_u64(1)))
+    }
+
+    /// `None` on overflow.
+    pub fn checked_mul_u64(&self, factor: u64) -> Option<Self> {
+        let mut out = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * factor as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        (carry == 0).then_some(U256(out))
+    }
+
+    pub fn div_u64(&self, divisor: u64) -> Self {
+        let mut out = [0u64; 4];
+        let mut rem = 0u128;
+        for i in (0..4).rev() {
+            let current = (rem << 64) | self.0[i] as u128;
+            out[i] = (current / divisor as u128) as u64;
+            rem = current % divisor as u128;
+        }
+        U256(out)
+    }
+
+    /// Shift-and-subtract long division; `divisor` must be non-zero.
+    pub fn div(&self, divisor: &Self) -> Self {
+        let mut quotient = U256::ZERO;
+        let mut rem = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            rem = rem.shl(1);
+            if self.0[(bit / 64) as usize] >> (bit % 64) & 1 == 1 {
+                rem.0[0] |= 1;
+            }
+            if rem >= *divisor {
+                rem = rem.sub(divisor);
+                quotient.0[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        quotient
+    }
+}
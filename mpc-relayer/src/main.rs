@@ -1,23 +1,34 @@
 //! MPC Relayer — Off-chain service that polls the orderbook contract for open
 //! intents and automatically submits batch matches when symmetric counter-intents
 //! are found. Uses NEAR CLI under the hood to sign and broadcast transactions.
+//!
+//! Each match names its transition: the chain, treasury path, the maker's
+//! registered receive address and, for native ETH, the unsigned EIP-1559
+//! transaction whose keccak256 hash MPC signs. Other transitions are not
+//! built yet, so pairs that need them are skipped.
 
 use anyhow::{anyhow, bail, Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashSet;
+use sha3::{Digest, Keccak256};
 use std::env;
 use tokio::process::Command;
 use tokio::time::{sleep, Duration};
 
 const DEFAULT_NETWORK: &str = "testnet";
 const DEFAULT_RPC_URL: &str = "https://rpc.testnet.near.org";
+const DEFAULT_MAX_FEE_GWEI: u128 = 20;
+const MAX_PRIORITY_FEE_PER_GAS: u128 = 1_000_000_000;
+const ETH_TRANSFER_GAS: u128 = 21_000;
 
 /// An order intent from the orderbook contract.
 #[derive(Debug, Deserialize, Clone)]
 struct Intent {
+    #[serde(deserialize_with = "de_u64_from_str")]
     id: u64,
     maker: String,
     src_asset: String,
@@ -31,12 +42,37 @@ struct Intent {
     status: String,
 }
 
-/// Parameters for a single match in a batch_match_intents call.
+/// Parameters for a single match in a batch_match_intents call; the
+/// fields of the contract's `MatchParams`.
 #[derive(Debug, Serialize)]
 struct MatchParam {
     intent_id: String,
     fill_amount: String,
     get_amount: String,
+    /// Hash MPC signs: keccak256 of `unsigned_tx` for ETH.
+    payload: [u8; 32],
+    /// The contract's treasury path on `transition_chain_type`.
+    path: String,
+    transition_chain_type: String,
+    /// The maker's registered receive address on `transition_chain_type`.
+    recipient: String,
+    /// The intent's src_asset, which the transition moves.
+    transition_asset: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unsigned_tx: Option<Vec<u8>>,
+}
+
+/// The parts of the contract's `AssetInfo` the relayer reads.
+#[derive(Debug, Deserialize)]
+struct AssetInfo {
+    chain_type: String,
+    token_contract: Option<String>,
+}
+
+/// The parts of the contract's `ContractConfig` the relayer reads.
+#[derive(Debug, Deserialize)]
+struct ContractConfig {
+    eth_chain_id: u64,
 }
 
 /// NEAR RPC JSON-RPC response envelope.
@@ -62,6 +98,10 @@ struct Config {
     poll_seconds: u64,
     asset_a: String,
     asset_b: String,
+    /// Nonce of the treasury's next ETH transaction; ETH transitions are
+    /// only built when it is given.
+    eth_nonce: Option<u64>,
+    max_fee_per_gas: u128,
 }
 
 #[tokio::main]
@@ -74,16 +114,35 @@ async fn main() -> Result<()> {
         config.contract_id, config.relayer_id, config.network, config.asset_a, config.asset_b
     );
 
+    let eth_chain_id = view::<ContractConfig>(&config, "get_config", json!({})).await?.eth_chain_id;
+    let mut eth_nonce = config.eth_nonce;
     loop {
         let intents = fetch_open_intents(&config).await?;
         println!("Current open intents: {}", intents.len());
 
-        let matches = build_mirror_matches(&intents, &config.asset_a, &config.asset_b);
+        let mut matches = Vec::new();
+        for (i, j) in find_mirror_pairs(&intents, &config.asset_a, &config.asset_b) {
+            let (i_fill, j_fill) = (remaining(i), remaining(j));
+            // A skipped pair must not use up a nonce
+            let nonce_before = eth_nonce;
+            let i_match = build_match(&config, eth_chain_id, &mut eth_nonce, i, i_fill, j_fill).await?;
+            let j_match = build_match(&config, eth_chain_id, &mut eth_nonce, j, j_fill, i_fill).await?;
+            match (i_match, j_match) {
+                (Some(i_match), Some(j_match)) => matches.extend([i_match, j_match]),
+                _ => {
+                    eth_nonce = nonce_before;
+                    println!("Skipping #{} <=> #{}: a transition cannot be built", i.id, j.id);
+                }
+            }
+        }
         if matches.is_empty() {
             println!("No matchable {}<->{} counter-intents found", config.asset_a, config.asset_b);
         } else {
             println!("Found {} matches, submitting batch to chain", matches.len());
-            submit_batch_match(&config, &matches).await?;
+            let sub_ids = submit_batch_match(&config, &matches).await?;
+            for (m, sub_id) in matches.iter().zip(&sub_ids) {
+                println!("Intent #{} -> sub-intent #{}", m.intent_id, sub_id);
+            }
         }
 
         if config.once {
@@ -100,7 +159,7 @@ fn parse_args() -> Result<Config> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 3 {
         bail!(
-            "Usage: cargo run -- <CONTRACT_ID> <RELAYER_ID> [NETWORK] [--once] [--poll-seconds N] [--asset-a SOL] [--asset-b ETH]"
+            "Usage: cargo run -- <CONTRACT_ID> <RELAYER_ID> [NETWORK] [--once] [--poll-seconds N] [--asset-a SOL] [--asset-b ETH] [--eth-nonce N] [--max-fee-gwei N]"
         );
     }
 
@@ -114,6 +173,8 @@ fn parse_args() -> Result<Config> {
     let mut poll_seconds: u64 = 6;
     let mut asset_a = "SOL".to_string();
     let mut asset_b = "ETH".to_string();
+    let mut eth_nonce = None;
+    let mut max_fee_gwei = DEFAULT_MAX_FEE_GWEI;
 
     let mut i = 3;
     while i < args.len() {
@@ -140,6 +201,20 @@ fn parse_args() -> Result<Config> {
                     .ok_or_else(|| anyhow!("--asset-b requires a value"))?
                     .to_uppercase();
             }
+            "--eth-nonce" => {
+                i += 1;
+                let v = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("--eth-nonce requires a value"))?;
+                eth_nonce = Some(v.parse().context("Failed to parse ETH nonce")?);
+            }
+            "--max-fee-gwei" => {
+                i += 1;
+                let v = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("--max-fee-gwei requires a value"))?;
+                max_fee_gwei = v.parse().context("Failed to parse max fee")?;
+            }
             value if value.starts_with("--") => {
                 bail!("Unknown argument: {}", value);
             }
@@ -165,6 +240,8 @@ fn parse_args() -> Result<Config> {
         poll_seconds,
         asset_a,
         asset_b,
+        eth_nonce,
+        max_fee_per_gas: max_fee_gwei * 1_000_000_000,
     })
 }
 
@@ -174,6 +251,11 @@ async fn fetch_open_intents(config: &Config) -> Result<Vec<Intent>> {
         "from_index": "0",
         "limit": 200u64
     });
+    view(config, "get_open_intents", args).await
+}
+
+/// Call a view method of the orderbook contract via NEAR RPC.
+async fn view<T: DeserializeOwned>(config: &Config, method_name: &str, args: serde_json::Value) -> Result<T> {
     let args_base64 = STANDARD.encode(serde_json::to_vec(&args)?);
 
     let req = json!({
@@ -184,7 +266,7 @@ async fn fetch_open_intents(config: &Config) -> Result<Vec<Intent>> {
             "request_type": "call_function",
             "finality": "final",
             "account_id": config.contract_id,
-            "method_name": "get_open_intents",
+            "method_name": method_name,
             "args_base64": args_base64
         }
     });
@@ -207,15 +289,13 @@ async fn fetch_open_intents(config: &Config) -> Result<Vec<Intent>> {
         .result
         .ok_or_else(|| anyhow!("RPC response missing 'result' field"))?;
     let json_text = String::from_utf8(result.result).context("result is not valid UTF-8")?;
-    let intents: Vec<Intent> =
-        serde_json::from_str(&json_text).context("Failed to parse get_open_intents response")?;
-    Ok(intents)
+    serde_json::from_str(&json_text).with_context(|| format!("Failed to parse {} response", method_name))
 }
 
-/// Find symmetric counter-intents for the asset pair and build MatchParam entries.
-fn build_mirror_matches(intents: &[Intent], asset_a: &str, asset_b: &str) -> Vec<MatchParam> {
+/// Find symmetric counter-intents for the asset pair.
+fn find_mirror_pairs<'a>(intents: &'a [Intent], asset_a: &str, asset_b: &str) -> Vec<(&'a Intent, &'a Intent)> {
     let mut used: HashSet<u64> = HashSet::new();
-    let mut out: Vec<MatchParam> = Vec::new();
+    let mut out = Vec::new();
 
     for i in intents {
         if used.contains(&i.id) || !is_open(i) {
@@ -240,37 +320,25 @@ fn build_mirror_matches(intents: &[Intent], asset_a: &str, asset_b: &str) -> Vec
             }
 
             // Current strategy: exact mirror match. Two intents are matched only when their remaining amounts are perfectly symmetric.
-            let i_remain = i.src_amount.saturating_sub(i.filled_amount);
-            let j_remain = j.src_amount.saturating_sub(j.filled_amount);
-            let i_need = i.dst_amount;
-            let j_need = j.dst_amount;
-
-            let exact_mirror = i_remain == j_need && j_remain == i_need;
+            let exact_mirror = remaining(i) == j.dst_amount && remaining(j) == i.dst_amount;
             if !exact_mirror {
                 continue;
             }
 
-            out.push(MatchParam {
-                intent_id: i.id.to_string(),
-                fill_amount: i_remain.to_string(),
-                get_amount: j_remain.to_string(),
-            });
-            out.push(MatchParam {
-                intent_id: j.id.to_string(),
-                fill_amount: j_remain.to_string(),
-                get_amount: i_remain.to_string(),
-            });
+            out.push((i, j));
             used.insert(i.id);
             used.insert(j.id);
 
             println!(
-                "Match found: #{}({} {} -> {} {}) <=> #{}({} {} -> {} {})",
+                "Match found: #{} by {}({} {} -> {} {}) <=> #{} by {}({} {} -> {} {})",
                 i.id,
+                i.maker,
                 i.src_amount,
                 i.src_asset,
                 i.dst_amount,
                 i.dst_asset,
                 j.id,
+                j.maker,
                 j.src_amount,
                 j.src_asset,
                 j.dst_amount,
@@ -283,6 +351,107 @@ fn build_mirror_matches(intents: &[Intent], asset_a: &str, asset_b: &str) -> Vec
     out
 }
 
+/// Unfilled part of the intent's src_amount.
+fn remaining(intent: &Intent) -> u128 {
+    intent.src_amount.saturating_sub(intent.filled_amount)
+}
+
+/// Build the match filling `fill` of `intent` for `get`. Its transition
+/// pays `fill` of the intent's src_asset to the maker's receive address on
+/// that asset's chain. `None` if the relayer cannot build that transition.
+async fn build_match(
+    config: &Config,
+    eth_chain_id: u64,
+    eth_nonce: &mut Option<u64>,
+    intent: &Intent,
+    fill: u128,
+    get: u128,
+) -> Result<Option<MatchParam>> {
+    let asset: AssetInfo = view::<Option<AssetInfo>>(config, "get_asset", json!({ "symbol": intent.src_asset }))
+        .await?
+        .ok_or_else(|| anyhow!("Asset {} is not registered", intent.src_asset))?;
+    let chain = asset.chain_type;
+    let Some(path) = view::<Option<String>>(config, "get_treasury_path", json!({ "chain_type": chain })).await? else {
+        println!("Intent #{}: no {} treasury path", intent.id, chain);
+        return Ok(None);
+    };
+    let receive_args = json!({ "account_id": intent.maker, "chain_type": chain });
+    let Some(recipient) = view::<Option<String>>(config, "get_receive_address", receive_args).await? else {
+        println!("Intent #{}: {} has no {} receive address", intent.id, intent.maker, chain);
+        return Ok(None);
+    };
+    if chain != "ETH" || asset.token_contract.is_some() {
+        println!("Intent #{}: cannot build a {} transition on {}", intent.id, intent.src_asset, chain);
+        return Ok(None);
+    }
+    let Some(nonce) = eth_nonce.as_mut() else {
+        println!("Intent #{}: pass --eth-nonce to build ETH transitions", intent.id);
+        return Ok(None);
+    };
+    let unsigned_tx = eth_transfer_tx(eth_chain_id, *nonce, config.max_fee_per_gas, &recipient, fill)?;
+    *nonce += 1;
+    Ok(Some(MatchParam {
+        intent_id: intent.id.to_string(),
+        fill_amount: fill.to_string(),
+        get_amount: get.to_string(),
+        payload: Keccak256::digest(&unsigned_tx).into(),
+        path,
+        transition_chain_type: chain,
+        recipient,
+        transition_asset: intent.src_asset.clone(),
+        unsigned_tx: Some(unsigned_tx),
+    }))
+}
+
+/// Unsigned EIP-1559 transaction sending `value` wei to `to`:
+/// `0x02 || rlp([chain_id, nonce, max_priority_fee, max_fee, gas, to, value, data, access_list])`.
+fn eth_transfer_tx(chain_id: u64, nonce: u64, max_fee_per_gas: u128, to: &str, value: u128) -> Result<Vec<u8>> {
+    let to = hex::decode(to.trim_start_matches("0x")).context("Invalid ETH recipient")?;
+    if to.len() != 20 {
+        bail!("ETH recipient must be 20 bytes");
+    }
+    let fields = [
+        rlp_uint(chain_id as u128),
+        rlp_uint(nonce as u128),
+        rlp_uint(MAX_PRIORITY_FEE_PER_GAS),
+        rlp_uint(max_fee_per_gas),
+        rlp_uint(ETH_TRANSFER_GAS),
+        rlp_bytes(&to),
+        rlp_uint(value),
+        rlp_bytes(&[]),
+        rlp_header(0xc0, 0),
+    ]
+    .concat();
+    let mut tx = vec![0x02];
+    tx.extend(rlp_header(0xc0, fields.len()));
+    tx.extend(fields);
+    Ok(tx)
+}
+
+fn rlp_header(offset: u8, len: usize) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    let mut out = vec![offset + 55 + len_bytes.len() as u8];
+    out.extend(len_bytes);
+    out
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut out = rlp_header(0x80, bytes.len());
+    out.extend(bytes);
+    out
+}
+
+fn rlp_uint(value: u128) -> Vec<u8> {
+    let bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    rlp_bytes(&bytes)
+}
+
 /// True if the intent is still open for matching.
 fn is_open(intent: &Intent) -> bool {
     intent.status == "Open"
@@ -294,7 +463,9 @@ fn is_opposite_pair(a: &Intent, b: &Intent) -> bool {
 }
 
 /// Submit batch match via NEAR CLI (sign-with-keychain, send).
-async fn submit_batch_match(config: &Config, matches: &[MatchParam]) -> Result<()> {
+/// Submit a batch match and return the sub-intent ids the contract created,
+/// in the same order as `matches`.
+async fn submit_batch_match(config: &Config, matches: &[MatchParam]) -> Result<Vec<u64>> {
     if matches.len() < 2 {
         bail!("batch_match_intents requires at least 2 match items");
     }
@@ -337,7 +508,40 @@ async fn submit_batch_match(config: &Config, matches: &[MatchParam]) -> Result<(
     }
 
     println!("Batch match submitted successfully.\n{}", stdout);
-    Ok(())
+    let sub_ids = parse_sub_intent_ids(&stdout)?;
+    if sub_ids.len() != matches.len() {
+        bail!(
+            "Expected {} sub-intent ids from batch_match_intents, got {}",
+            matches.len(),
+            sub_ids.len()
+        );
+    }
+    Ok(sub_ids)
+}
+
+/// Extract the `Vec<U64>` return value from near CLI output. The CLI prints
+/// the JSON return value after its own status lines.
+fn parse_sub_intent_ids(stdout: &str) -> Result<Vec<u64>> {
+    let start = stdout.rfind('[').ok_or_else(|| anyhow!("No return value in near CLI output"))?;
+    let end = stdout[start..]
+        .find(']')
+        .map(|i| start + i + 1)
+        .ok_or_else(|| anyhow!("Unterminated return value in near CLI output"))?;
+    let ids: Vec<String> = serde_json::from_str(&stdout[start..end])
+        .context("Failed to parse batch_match_intents return value")?;
+    ids.iter()
+        .map(|id| id.parse::<u64>().context("Invalid sub-intent id"))
+        .collect()
+}
+
+/// Deserialize a `U64` id, which the contract sends as a JSON string.
+fn de_u64_from_str<'de, D>(deserializer: D) -> std::result::Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse::<u64>()
+        .map_err(|e| serde::de::Error::custom(format!("u64 parse error: {e}")))
 }
 
 /// Deserialize u128 from either a JSON string or number.
@@ -0,0 +1,159 @@
+//! Owner-managed asset registry. Symbols are stored upper-cased; every
+//! entry point that takes an asset resolves it here, so "eth" and "ETH"
+//! share one balance and unknown or disabled assets are refused.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetInfo {
+    /// Chain the asset is deposited from and withdrawn to.
+    pub chain_type: ChainType,
+    pub decimals: u8,
+    /// Smallest amount `verify_mpc_deposit` will credit.
+    pub min_deposit: u128,
+    pub enabled: bool,
+    /// Smallest `src_amount` an intent selling this asset may have, and
+    /// smallest batch fill of one unless it takes the whole remainder.
+    pub min_intent_size: u128,
+    /// ERC-20 contract of an ETH-chain token, lower-case `0x` hex; `None`
+    /// for the chain's native coin.
+    pub token_contract: Option<String>,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Add an asset, or replace an existing entry's settings. Newly
+    /// registered assets start enabled; a replaced entry keeps its
+    /// `min_intent_size` and `token_contract`.
+    pub fn register_asset(&mut self, symbol: String, chain_type: ChainType, decimals: u8, min_deposit: U128) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can manage assets");
+        Self::assert_valid_asset(&symbol);
+        let symbol = symbol.to_uppercase();
+        let existing = self.assets.get(&symbol);
+        let info = AssetInfo {
+            chain_type,
+            decimals,
+            min_deposit: min_deposit.0,
+            enabled: true,
+            min_intent_size: existing.as_ref().map_or(0, |info| info.min_intent_size),
+            token_contract: existing.and_then(|info| info.token_contract),
+        };
+        self.assets.insert(&symbol, &info);
+    }
+
+    pub fn set_asset_enabled(&mut self, symbol: String, enabled: bool) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can manage assets");
+        let symbol = symbol.to_uppercase();
+        let mut info = self
+            .assets
+            .get(&symbol)
+            .unwrap_or_else(|| env::panic_str(&format!("Unknown asset {}", symbol)));
+        info.enabled = enabled;
+        self.assets.insert(&symbol, &info);
+        env::log_str(&format!("ASSET_ENABLED:symbol={},enabled={}", symbol, enabled));
+    }
+
+    /// Applies to intents created and fills matched after the change;
+    /// intents already on the book keep their size.
+    pub fn set_min_intent_size(&mut self, symbol: String, min_intent_size: U128) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can manage assets");
+        let symbol = symbol.to_uppercase();
+        let mut info = self
+            .assets
+            .get(&symbol)
+            .unwrap_or_else(|| env::panic_str(&format!("Unknown asset {}", symbol)));
+        info.min_intent_size = min_intent_size.0;
+        self.assets.insert(&symbol, &info);
+        env::log_str(&format!("ASSET_MIN_INTENT_SIZE:symbol={},min={}", symbol, min_intent_size.0));
+    }
+
+    /// ERC-20 contract the light client checks ETH payments of `symbol`
+    /// against; `None` makes it a native ETH payment.
+    pub fn set_asset_token_contract(&mut self, symbol: String, token_contract: Option<String>) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can manage assets");
+        let symbol = symbol.to_uppercase();
+        let mut info = self
+            .assets
+            .get(&symbol)
+            .unwrap_or_else(|| env::panic_str(&format!("Unknown asset {}", symbol)));
+        assert_eq!(info.chain_type, ChainType::ETH, "Token contracts are for ETH assets");
+        let token_contract = token_contract.map(|contract| {
+            let hex = contract.strip_prefix("0x").unwrap_or(&contract);
+            assert!(
+                hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_hexdigit()),
+                "Token contract must be a 20-byte hex address"
+            );
+            format!("0x{}", hex.to_lowercase())
+        });
+        env::log_str(&format!(
+            "ASSET_TOKEN_CONTRACT:symbol={},contract={}",
+            symbol,
+            token_contract.as_deref().unwrap_or("native")
+        ));
+        info.token_contract = token_contract;
+        self.assets.insert(&symbol, &info);
+    }
+
+    /// 0 for unknown assets.
+    pub fn get_min_intent_size(&self, symbol: String) -> U128 {
+        U128(self.min_intent_size(&symbol.to_uppercase()))
+    }
+
+    pub fn get_asset(&self, symbol: String) -> Option<AssetInfo> {
+        self.assets.get(&symbol.to_uppercase())
+    }
+
+    pub fn get_registered_assets(&self, from_index: u64, limit: u64) -> Vec<(String, AssetInfo)> {
+        self.assets
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+}
+
+impl Orderbook {
+    /// Registry key for `asset`, panicking if it is unknown or disabled.
+    pub(crate) fn resolve_asset(&self, asset: &str) -> (String, AssetInfo) {
+        self.lookup_asset(asset).unwrap_or_else(|error| error.panic())
+    }
+
+    /// Kind the light client must find in an ETH payment of `symbol`;
+    /// `None` for other chains and unregistered assets.
+    pub(crate) fn expected_asset_kind(&self, symbol: &str) -> Option<AssetKind> {
+        let info = self.assets.get(&symbol.to_uppercase())?;
+        if info.chain_type != ChainType::ETH {
+            return None;
+        }
+        Some(match info.token_contract {
+            Some(contract) => AssetKind::Erc20 { contract },
+            None => AssetKind::Native,
+        })
+    }
+
+    pub(crate) fn min_intent_size(&self, symbol: &str) -> u128 {
+        self.assets.get(&symbol.to_string()).map_or(0, |info| info.min_intent_size)
+    }
+
+    /// `src_amount` of a new intent selling `symbol` against its minimum.
+    pub(crate) fn check_min_intent_size(&self, symbol: &str, amount: u128) -> Result<(), OrderbookError> {
+        let min = self.min_intent_size(symbol);
+        if amount < min {
+            return Err(OrderbookError::BelowMinIntentSize { asset: symbol.to_string(), amount, min });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn lookup_asset(&self, asset: &str) -> Result<(String, AssetInfo), OrderbookError> {
+        let symbol = asset.to_uppercase();
+        let info = self
+            .assets
+            .get(&symbol)
+            .ok_or_else(|| OrderbookError::UnknownAsset { asset: asset.to_string() })?;
+        if !info.enabled {
+            return Err(OrderbookError::AssetDisabled { asset: symbol });
+        }
+        Ok((symbol, info))
+    }
+}
@@ -0,0 +1,139 @@
+//! One record per executed batch match, so the sub-intents a batch created
+//! can be grouped back together after the fact.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchRecord {
+    #[serde(with = "crate::u64_json")]
+    pub id: u64,
+    pub solver: AccountId,
+    pub sub_intent_ids: Vec<U64>,
+    /// Block timestamp (ns) of the match.
+    pub timestamp: u64,
+    /// NEAR (yocto) the solver attached: bonds, signing deposits and any
+    /// remainder that was sent back.
+    pub attached_deposit: U128,
+}
+
+/// Surplus a batch left over, held for its solver until every sub-intent
+/// the batch created is delivered. Unwinding any of them forfeits it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeldSurplus {
+    pub solver: AccountId,
+    pub amounts: Vec<(String, U128)>,
+    /// Sub-intents of the batch not delivered yet.
+    #[serde(with = "crate::u64_json")]
+    pub outstanding: u64,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn get_batch(&self, batch_id: U64) -> Option<BatchRecord> {
+        self.batches.get(&(batch_id.0))
+    }
+
+    /// Batches `solver` executed, oldest first.
+    pub fn get_batches_by_solver(&self, solver: AccountId, from_index: U128, limit: u64) -> Vec<BatchRecord> {
+        let Some(ids) = self.batches_by_solver.get(&solver) else {
+            return vec![];
+        };
+        let from_index = from_index.0 as u64;
+        (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
+            .filter_map(|index| self.batches.get(&ids.get(index).unwrap()))
+            .collect()
+    }
+
+    /// Surplus still held for the batch's solver, if any.
+    pub fn get_held_surplus(&self, batch_id: U64) -> Option<HeldSurplus> {
+        self.held_surplus.get(&(batch_id.0))
+    }
+}
+
+impl Orderbook {
+    /// Id the next executed batch will get; its sub-intents are stamped
+    /// with it before `record_batch` stores the record.
+    pub(crate) fn take_batch_id(&mut self) -> u64 {
+        let id = self.next_batch_id;
+        self.next_batch_id += 1;
+        id
+    }
+
+    pub(crate) fn record_batch(&mut self, id: u64, solver: &AccountId, sub_ids: &[u64], attached: u128) {
+        let record = BatchRecord {
+            id,
+            solver: solver.clone(),
+            sub_intent_ids: sub_ids.iter().copied().map(U64).collect(),
+            timestamp: env::block_timestamp(),
+            attached_deposit: U128(attached),
+        };
+        self.batches.insert(&id, &record);
+        let mut ids = self
+            .batches_by_solver
+            .get(solver)
+            .unwrap_or_else(|| Vector::new(format!("N{}", solver).as_bytes()));
+        ids.push(&id);
+        self.batches_by_solver.insert(solver, &ids);
+        env::log_str(&format!("BATCH_RECORDED:batch_id={},solver={},sub_intents={}", id, solver, sub_ids.len()));
+    }
+
+    /// Hold a batch's surplus until its `outstanding` sub-intents are
+    /// delivered.
+    pub(crate) fn hold_surplus(&mut self, batch_id: u64, solver: &AccountId, surplus: &[(String, u128)], outstanding: u64) {
+        if surplus.is_empty() {
+            return;
+        }
+        let held = HeldSurplus {
+            solver: solver.clone(),
+            amounts: surplus.iter().map(|(asset, amount)| (asset.clone(), U128(*amount))).collect(),
+            outstanding,
+        };
+        self.held_surplus.insert(&batch_id, &held);
+        for (asset, amount) in surplus {
+            env::log_str(&format!(
+                "SOLVER_SURPLUS_HELD:batch_id={},solver={},asset={},amount={}",
+                batch_id, solver, asset, amount
+            ));
+        }
+    }
+
+    /// Count `sub` as delivered; the last delivery of its batch credits the
+    /// held surplus to the solver.
+    pub(crate) fn settle_surplus(&mut self, sub: &SubIntent) {
+        let Some(batch_id) = sub.batch_id else {
+            return;
+        };
+        let Some(mut held) = self.held_surplus.get(&batch_id) else {
+            return;
+        };
+        held.outstanding -= 1;
+        if held.outstanding > 0 {
+            self.held_surplus.insert(&batch_id, &held);
+            return;
+        }
+        self.held_surplus.remove(&batch_id);
+        for (asset, amount) in &held.amounts {
+            self.internal_credit(&held.solver, asset, amount.0, BalanceChangeReason::SolverSurplus, None);
+            env::log_str(&format!(
+                "SOLVER_SURPLUS:solver={},asset={},amount={}",
+                held.solver, asset, amount.0
+            ));
+        }
+    }
+
+    /// Drop the surplus of `sub`'s batch: the unwound fill goes back to its
+    /// maker, so the batch no longer leaves anything over.
+    pub(crate) fn forfeit_surplus(&mut self, sub: &SubIntent) {
+        let Some(batch_id) = sub.batch_id else {
+            return;
+        };
+        if self.held_surplus.remove(&batch_id).is_some() {
+            env::log_str(&format!(
+                "SOLVER_SURPLUS_FORFEITED:batch_id={},sub_intent_id={}",
+                batch_id, sub.id
+            ));
+        }
+    }
+}
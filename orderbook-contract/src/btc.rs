@@ -0,0 +1,304 @@
+//! Minimal parsing of unsigned Bitcoin withdrawals so `withdraw` can check
+//! what MPC is asked to sign instead of trusting a caller-supplied hash.
+//!
+//! A withdrawal is a BIP174 PSBT (version 0) spending one P2WPKH input.
+//! Its `PSBT_IN_WITNESS_UTXO` supplies the input's value and script; the
+//! BIP143 sighash commits to both, so a PSBT that misstates them gets a
+//! signature the network rejects. Only `SIGHASH_ALL` is signed.
+
+use near_sdk::env;
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const SIGHASH_ALL: u32 = 1;
+
+/// The fields of an unsigned withdrawal transaction it is checked against.
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnsignedBtcTx {
+    /// Sats of the spent output.
+    pub input_value: u64,
+    /// Script of the spent output, `OP_0 <20-byte key hash>`.
+    pub input_script: Vec<u8>,
+    pub outputs: Vec<Output>,
+    /// BIP143 sighash of the input; what MPC signs.
+    pub sighash: [u8; 32],
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Output {
+    /// Sats.
+    pub value: u64,
+    pub script: Vec<u8>,
+}
+
+/// A transaction without witness data, as `PSBT_GLOBAL_UNSIGNED_TX` holds it.
+pub(crate) struct Transaction<'a> {
+    version: &'a [u8],
+    /// `(outpoint, sequence)` of each input.
+    inputs: Vec<(&'a [u8], &'a [u8])>,
+    /// The serialized outputs, without their count.
+    raw_outputs: &'a [u8],
+    outputs: Vec<Output>,
+    lock_time: &'a [u8],
+}
+
+/// One key-value pair of a PSBT map.
+type PsbtEntry<'a> = (&'a [u8], &'a [u8]);
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).ok_or("truncated")?;
+        let slice = self.bytes.get(self.pos..end).ok_or("truncated")?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn int(&mut self, len: usize) -> Result<u64, String> {
+        let bytes = self.take(len)?;
+        Ok(bytes.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        match self.int(1)? {
+            0xfd => self.int(2),
+            0xfe => self.int(4),
+            0xff => self.int(8),
+            small => Ok(small),
+        }
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = usize::try_from(self.varint()?).map_err(|_| "length out of range")?;
+        self.take(len)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+/// Decode a transaction in its pre-segwit serialization with empty input
+/// scripts.
+pub(crate) fn decode_unsigned(bytes: &[u8]) -> Result<Transaction<'_>, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let version = reader.take(4)?;
+    let mut inputs = Vec::new();
+    for _ in 0..reader.varint()? {
+        let outpoint = reader.take(36)?;
+        if !reader.var_bytes()?.is_empty() {
+            return Err("input scripts must be empty".to_string());
+        }
+        inputs.push((outpoint, reader.take(4)?));
+    }
+    if inputs.is_empty() {
+        return Err("no inputs".to_string());
+    }
+    let count = reader.varint()?;
+    let outputs_start = reader.pos;
+    let mut outputs = Vec::new();
+    for _ in 0..count {
+        let value = reader.int(8)?;
+        let script = reader.var_bytes()?.to_vec();
+        outputs.push(Output { value, script });
+    }
+    let raw_outputs = &bytes[outputs_start..reader.pos];
+    let lock_time = reader.take(4)?;
+    if !reader.is_empty() {
+        return Err("trailing bytes after the transaction".to_string());
+    }
+    Ok(Transaction { version, inputs, raw_outputs, outputs, lock_time })
+}
+
+/// BIP143 `SIGHASH_ALL` sighash of input `index` of `tx`, spending `value`
+/// sats from the P2WPKH output of `key_hash`.
+pub(crate) fn bip143_sighash(tx: &Transaction, index: usize, key_hash: &[u8; 20], value: u64) -> [u8; 32] {
+    let prevouts: Vec<u8> = tx.inputs.iter().flat_map(|(outpoint, _)| outpoint.iter().copied()).collect();
+    let sequences: Vec<u8> = tx.inputs.iter().flat_map(|(_, sequence)| sequence.iter().copied()).collect();
+    let (outpoint, sequence) = tx.inputs[index];
+    let preimage = [
+        tx.version,
+        &sha256d(&prevouts),
+        &sha256d(&sequences),
+        outpoint,
+        &[0x19, 0x76, 0xa9, 0x14],
+        key_hash,
+        &[0x88, 0xac],
+        &value.to_le_bytes(),
+        sequence,
+        &sha256d(tx.raw_outputs),
+        tx.lock_time,
+        &SIGHASH_ALL.to_le_bytes(),
+    ]
+    .concat();
+    sha256d(&preimage)
+}
+
+/// Parse a serialized PSBT spending a single P2WPKH input. The error says
+/// what is malformed.
+pub(crate) fn parse_withdrawal_psbt(psbt: &[u8]) -> Result<UnsignedBtcTx, String> {
+    let mut reader = Reader { bytes: psbt, pos: 0 };
+    if reader.take(PSBT_MAGIC.len())? != PSBT_MAGIC {
+        return Err("not a PSBT".to_string());
+    }
+    let mut unsigned_tx = None;
+    while let Some((key, value)) = psbt_entry(&mut reader)? {
+        if key == [PSBT_GLOBAL_UNSIGNED_TX] {
+            unsigned_tx = Some(value);
+        }
+    }
+    let tx = decode_unsigned(unsigned_tx.ok_or("no unsigned transaction")?)?;
+    if tx.inputs.len() != 1 {
+        return Err("expected a single input".to_string());
+    }
+    let mut witness_utxo = None;
+    while let Some((key, value)) = psbt_entry(&mut reader)? {
+        if key == [PSBT_IN_WITNESS_UTXO] {
+            witness_utxo = Some(value);
+        }
+    }
+    for _ in &tx.outputs {
+        while psbt_entry(&mut reader)?.is_some() {}
+    }
+    if !reader.is_empty() {
+        return Err("trailing bytes after the PSBT".to_string());
+    }
+
+    let mut utxo = Reader { bytes: witness_utxo.ok_or("input has no witness UTXO")?, pos: 0 };
+    let input_value = utxo.int(8)?;
+    let input_script = utxo.var_bytes()?.to_vec();
+    if !utxo.is_empty() {
+        return Err("malformed witness UTXO".to_string());
+    }
+    let key_hash: [u8; 20] = match input_script.as_slice() {
+        [0x00, 0x14, hash @ ..] => hash.try_into().map_err(|_| "input is not P2WPKH")?,
+        _ => return Err("input is not P2WPKH".to_string()),
+    };
+    let sighash = bip143_sighash(&tx, 0, &key_hash, input_value);
+    Ok(UnsignedBtcTx { input_value, input_script, outputs: tx.outputs, sighash })
+}
+
+/// Next key-value pair of the current PSBT map, or `None` at its separator.
+fn psbt_entry<'a>(reader: &mut Reader<'a>) -> Result<Option<PsbtEntry<'a>>, String> {
+    let key = reader.var_bytes()?;
+    if key.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((key, reader.var_bytes()?)))
+}
+
+pub(crate) fn sha256d(bytes: &[u8]) -> [u8; 32] {
+    env::sha256_array(env::sha256_array(bytes))
+}
+
+/// The output script an address pays to: base58check P2PKH/P2SH or
+/// bech32/bech32m segwit, mainnet and testnet.
+pub(crate) fn address_script(address: &str) -> Option<Vec<u8>> {
+    let lower = address.to_ascii_lowercase();
+    if ["bc1", "tb1", "bcrt1"].iter().any(|hrp| lower.starts_with(hrp)) {
+        return segwit_script(address);
+    }
+    let decoded = base58_decode(address)?;
+    if decoded.len() != 25 || sha256d(&decoded[..21])[..4] != decoded[21..] {
+        return None;
+    }
+    let hash = &decoded[1..21];
+    match decoded[0] {
+        0x00 | 0x6f => Some([&[0x76, 0xa9, 0x14], hash, &[0x88, 0xac]].concat()),
+        0x05 | 0xc4 => Some([&[0xa9, 0x14], hash, &[0x87]].concat()),
+        _ => None,
+    }
+}
+
+const BASE58: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub(crate) fn base58_decode(value: &str) -> Option<Vec<u8>> {
+    let mut number: Vec<u8> = Vec::new();
+    for c in value.bytes() {
+        let mut carry = BASE58.iter().position(|b| *b == c)? as u32;
+        for byte in number.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            number.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = value.bytes().take_while(|c| *c == b'1').count();
+    Some([vec![0; zeros], number].concat())
+}
+
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+    values.iter().fold(1u32, |chk, value| {
+        let top = chk >> 25;
+        let chk = ((chk & 0x01ff_ffff) << 5) ^ *value as u32;
+        (0..5).filter(|i| top >> i & 1 == 1).fold(chk, |chk, i| chk ^ GEN[i])
+    })
+}
+
+/// BIP173 (witness v0) or BIP350 (v1+) address to its witness script.
+fn segwit_script(address: &str) -> Option<Vec<u8>> {
+    if address.to_ascii_lowercase() != address && address.to_ascii_uppercase() != address {
+        return None;
+    }
+    let address = address.to_ascii_lowercase();
+    let (hrp, data) = address.rsplit_once('1')?;
+    if data.len() < 7 {
+        return None;
+    }
+    let values = data
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|b| *b == c).map(|v| v as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    let expanded: Vec<u8> = hrp
+        .bytes()
+        .map(|c| c >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|c| c & 31))
+        .chain(values.iter().copied())
+        .collect();
+    let checksum = bech32_polymod(&expanded);
+
+    let version = values[0];
+    let program = convert_5_to_8(&values[1..values.len() - 6])?;
+    let valid = match version {
+        0 => checksum == BECH32_CONST && (program.len() == 20 || program.len() == 32),
+        1..=16 => checksum == BECH32M_CONST && (2..=40).contains(&program.len()),
+        _ => false,
+    };
+    if !valid {
+        return None;
+    }
+    let opcode = if version == 0 { 0 } else { 0x50 + version };
+    Some([&[opcode, program.len() as u8], program.as_slice()].concat())
+}
+
+/// Regroup 5-bit values into bytes, rejecting non-zero padding.
+fn convert_5_to_8(values: &[u8]) -> Option<Vec<u8>> {
+    let (mut acc, mut bits) = (0u32, 0u32);
+    let mut out = Vec::new();
+    for value in values {
+        acc = ((acc << 5) | *value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || (acc << (8 - bits)) & 0xff != 0 {
+        return None;
+    }
+    Some(out)
+}
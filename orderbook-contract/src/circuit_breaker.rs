@@ -0,0 +1,111 @@
+//! Matched-volume circuit breaker. Matched supply is summed per asset over
+//! windows of `volume_window_blocks` blocks; a batch that would take an
+//! asset past its owner-set cap is turned away with a
+//! `circuit_breaker_tripped` event instead of being matched.
+
+use crate::ledger::log_event;
+use crate::*;
+
+/// Default length of a volume window: about an hour of blocks.
+pub const DEFAULT_VOLUME_WINDOW_BLOCKS: u64 = 3_600;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VolumeWindow {
+    /// First block height of the window.
+    pub window_start: u64,
+    /// `fill_amount` matched in the window, summed over batches.
+    pub volume: U128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VolumeWindowView {
+    pub asset: String,
+    pub window_start: u64,
+    /// First block height of the next window.
+    pub window_end: u64,
+    pub volume: U128,
+    pub cap: Option<U128>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CircuitBreakerEvent {
+    pub solver: AccountId,
+    pub asset: String,
+    pub cap: U128,
+    /// Volume already matched in the window.
+    pub volume: U128,
+    /// What the rejected batch would have added.
+    pub batch_volume: U128,
+    pub window_start: u64,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Cap the volume of `asset` matched per window; `None` clears the cap.
+    pub fn set_volume_cap(&mut self, asset: String, cap: Option<U128>) {
+        self.schedule_config_change(ConfigChange::VolumeCap(asset.to_uppercase(), cap));
+    }
+
+    pub fn set_volume_window_blocks(&mut self, blocks: u64) {
+        assert!(blocks > 0, "Volume window must be at least one block");
+        self.schedule_config_change(ConfigChange::VolumeWindowBlocks(blocks));
+    }
+
+    /// Volume of `asset` matched in the current window and its cap.
+    pub fn get_volume_window(&self, asset: String) -> VolumeWindowView {
+        let asset = asset.to_uppercase();
+        let window = self.current_volume_window(&asset);
+        VolumeWindowView {
+            window_end: window.window_start + self.volume_window_blocks,
+            window_start: window.window_start,
+            volume: window.volume,
+            cap: self.volume_caps.get(&asset).map(U128),
+            asset,
+        }
+    }
+}
+
+impl Orderbook {
+    fn current_volume_window(&self, asset: &str) -> VolumeWindow {
+        let height = env::block_height();
+        let window_start = height - height % self.volume_window_blocks;
+        self.matched_volume
+            .get(&asset.to_string())
+            .filter(|window| window.window_start == window_start)
+            .unwrap_or(VolumeWindow { window_start, volume: U128(0) })
+    }
+
+    /// Add a batch's per-asset supply to the current windows. If any asset
+    /// would go over its cap, logs `circuit_breaker_tripped` for it, records
+    /// nothing and returns false.
+    pub(crate) fn record_matched_volume(&mut self, solver: &AccountId, net: &[AssetNet]) -> bool {
+        let mut windows = Vec::new();
+        for entry in net {
+            let mut window = self.current_volume_window(&entry.asset);
+            let volume = window.volume.0.saturating_add(entry.supply.0);
+            if let Some(cap) = self.volume_caps.get(&entry.asset).filter(|cap| volume > *cap) {
+                log_event(
+                    "circuit_breaker_tripped",
+                    CircuitBreakerEvent {
+                        solver: solver.clone(),
+                        asset: entry.asset.clone(),
+                        cap: U128(cap),
+                        volume: window.volume,
+                        batch_volume: entry.supply,
+                        window_start: window.window_start,
+                    },
+                );
+                return false;
+            }
+            window.volume = U128(volume);
+            windows.push((entry.asset.clone(), window));
+        }
+        for (asset, window) in windows {
+            self.matched_volume.insert(&asset, &window);
+        }
+        true
+    }
+}
@@ -0,0 +1,174 @@
+//! Garbage collection of finished records. Anyone may remove intents,
+//! sub-intents and withdrawals that are final and past the retention period; a small
+//! tombstone keeps their outcome queryable, and the storage they freed is
+//! paid to the caller.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RecordKind {
+    Intent,
+    SubIntent,
+    Withdrawal,
+}
+
+/// Final status of a removed record, serialized as the bare status name.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde", untagged)]
+pub enum RecordStatus {
+    Intent(IntentStatus),
+    SubIntent(SubIntentStatus),
+    Withdrawal(WithdrawalStatus),
+}
+
+/// What is left of a record after `cleanup`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Tombstone {
+    pub status: RecordStatus,
+    pub closed_at: Option<u64>,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Remove the given records, leaving a tombstone for each. Panics if one
+    /// is not final or still within `record_retention_ns`; ids that do not
+    /// exist (never created or already cleaned) are skipped. The freed
+    /// storage cost is transferred to the caller, taken from the deposit of
+    /// the account that paid for the record. Returns how many were removed.
+    pub fn cleanup(&mut self, ids: Vec<U64>, kind: RecordKind) -> u32 {
+        let mut removed = 0;
+        let mut freed_bytes = 0;
+        for id in ids {
+            let id = id.0;
+            let initial_usage = env::storage_usage();
+            let payer = match kind {
+                RecordKind::Intent => match self.intents.get(&id) {
+                    Some(intent) => self.remove_intent_record(intent),
+                    None => continue,
+                },
+                RecordKind::SubIntent => match self.sub_intents.get(&id) {
+                    Some(sub) => self.remove_sub_intent_record(sub),
+                    None => continue,
+                },
+                RecordKind::Withdrawal => match self.withdrawals.get(&id) {
+                    Some(record) => self.remove_withdrawal_record(record),
+                    None => continue,
+                },
+            };
+            let freed = initial_usage.saturating_sub(env::storage_usage());
+            if let Some(payer) = payer {
+                self.release_storage(&payer, freed);
+            }
+            freed_bytes += freed;
+            removed += 1;
+        }
+
+        let refund = freed_bytes as u128 * env::storage_byte_cost().as_yoctonear();
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id())
+                .transfer(NearToken::from_yoctonear(refund))
+                .detach();
+        }
+        env::log_str(&format!("RECORDS_CLEANED:kind={:?},count={},refund={}", kind, removed, refund));
+        removed
+    }
+
+    pub fn set_record_retention(&mut self, retention_ns: u64) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set record retention"
+        );
+        self.record_retention_ns = retention_ns;
+    }
+
+    pub fn get_tombstone(&self, kind: RecordKind, id: U64) -> Option<Tombstone> {
+        self.tombstones.get(&(kind, id.0))
+    }
+}
+
+impl Orderbook {
+    /// A `Filled` intent has no close time of its own; it becomes removable
+    /// once every sub-intent has been cleaned up, each past its retention.
+    fn remove_intent_record(&mut self, intent: Intent) -> Option<AccountId> {
+        assert!(
+            intent.status.is_final() || intent.status == IntentStatus::Filled,
+            "Intent {} is not final",
+            intent.id
+        );
+        assert!(
+            self.sub_intents_by_parent
+                .get(&intent.id)
+                .is_none_or(|ids| ids.iter().all(|id| self.sub_intents.get(&id).is_none())),
+            "Intent {} still has sub-intents",
+            intent.id
+        );
+        self.assert_retention_passed(intent.closed_at, intent.id);
+
+        self.intents.remove(&intent.id);
+        if let Some(mut ids) = self.sub_intents_by_parent.remove(&intent.id) {
+            ids.clear();
+        }
+        if let Some(mut fills) = self.intent_fills.remove(&intent.id) {
+            fills.clear();
+        }
+        self.tombstones.insert(
+            &(RecordKind::Intent, intent.id),
+            &Tombstone {
+                status: RecordStatus::Intent(intent.status),
+                closed_at: intent.closed_at,
+            },
+        );
+        Some(intent.maker)
+    }
+
+    /// Takes were paid for by the taker; batch fills by the contract.
+    fn remove_sub_intent_record(&mut self, sub: SubIntent) -> Option<AccountId> {
+        assert!(sub.status.is_final(), "Sub-Intent {} is not final", sub.id);
+        assert!(!self.holds_escrow(&sub), "Sub-Intent {} still holds escrow", sub.id);
+        self.assert_retention_passed(sub.closed_at, sub.id);
+
+        self.remove_sub_intent(&sub);
+        self.transition_expectations.remove(&sub.id);
+        self.settlement_requests.remove(&sub.id);
+        self.settlement_proposals.remove(&sub.id);
+        self.signatures.remove(&SignTarget::SubIntent(sub.id));
+        self.tombstones.insert(
+            &(RecordKind::SubIntent, sub.id),
+            &Tombstone {
+                status: RecordStatus::SubIntent(sub.status),
+                closed_at: sub.closed_at,
+            },
+        );
+        sub.expires_at.map(|_| sub.taker)
+    }
+
+    /// Withdrawal records are paid for by the contract.
+    fn remove_withdrawal_record(&mut self, record: WithdrawalRecord) -> Option<AccountId> {
+        assert!(record.status.is_final(), "Withdrawal {} is not final", record.id);
+        self.assert_retention_passed(record.closed_at, record.id);
+
+        self.withdrawals.remove(&record.id);
+        self.signatures.remove(&SignTarget::Withdrawal(record.id));
+        self.tombstones.insert(
+            &(RecordKind::Withdrawal, record.id),
+            &Tombstone {
+                status: RecordStatus::Withdrawal(record.status),
+                closed_at: record.closed_at,
+            },
+        );
+        None
+    }
+
+    fn assert_retention_passed(&self, closed_at: Option<u64>, id: u64) {
+        if let Some(closed_at) = closed_at {
+            assert!(
+                env::block_timestamp() >= closed_at + self.record_retention_ns,
+                "Record {} is within its retention period",
+                id
+            );
+        }
+    }
+}
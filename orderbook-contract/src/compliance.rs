@@ -0,0 +1,41 @@
+//! Owner-managed blocklist. A blocked account cannot deposit, open or take
+//! intents, be matched as maker or solver, or withdraw. Refunds of work
+//! already in flight (failed signatures, cancellations) still reach it.
+
+use crate::*;
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn block_account(&mut self, account: AccountId) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can block accounts");
+        if self.blocked_accounts.insert(&account) {
+            env::log_str(&format!("ACCOUNT_BLOCKED:account={}", account));
+        }
+    }
+
+    pub fn unblock_account(&mut self, account: AccountId) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can block accounts");
+        if self.blocked_accounts.remove(&account) {
+            env::log_str(&format!("ACCOUNT_UNBLOCKED:account={}", account));
+        }
+    }
+
+    pub fn is_blocked(&self, account: AccountId) -> bool {
+        self.blocked_accounts.contains(&account)
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn assert_not_blocked(&self, account: &AccountId) {
+        if let Err(error) = self.check_not_blocked(account) {
+            error.panic();
+        }
+    }
+
+    pub(crate) fn check_not_blocked(&self, account: &AccountId) -> Result<(), OrderbookError> {
+        if self.blocked_accounts.contains(account) {
+            return Err(OrderbookError::AccountBlocked { account: account.clone() });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Owner-managed external contract addresses, per-chain signers, treasury
+//! derivation paths and deposit confirmation depths. Changes
+//! can be held behind a delay (in blocks) so users get a window to react
+//! before they take effect.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ConfigChange {
+    MpcContract(AccountId),
+    /// Route one chain to its own signer; `None` falls back to `mpc_contract`.
+    ChainSigner(ChainType, Option<AccountId>),
+    LightClientContract(AccountId),
+    /// Chain id ETH withdrawal transactions must be signed for.
+    EthChainId(u64),
+    /// Protocol fee in basis points, at most `MAX_FEE_BPS`.
+    FeeBps(u16),
+    FeeCollector(AccountId),
+    /// Referrers' share of the protocol fee in basis points.
+    ReferralBps(u16),
+    /// Part of the protocol fee paid back to makers, at most `fee_bps`.
+    MakerRebateBps(u16),
+    DelayBlocks(u64),
+    /// Derivation path transitions on one chain are signed under; `None`
+    /// removes it, which blocks matching on that chain.
+    TreasuryPath(ChainType, Option<String>),
+    /// Per-user, per-window withdrawal cap for one asset; `None` removes it.
+    WithdrawalLimit(String, Option<U128>),
+    /// Matched volume cap per window for one asset; `None` clears it.
+    VolumeCap(String, Option<U128>),
+    VolumeWindowBlocks(u64),
+    /// Confirmations a deposit on one chain needs past the light client's
+    /// proof; 0 removes the requirement.
+    MinConfirmations(ChainType, u64),
+    InputLimits(InputLimits),
+    /// Cap on `gas_limit * max_fee_per_gas` of an ETH withdrawal, in wei.
+    MaxWithdrawalGasFee(U128),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingConfigChange {
+    pub change: ConfigChange,
+    /// Block height from which `apply_config_changes` may apply it.
+    pub effective_height: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractConfig {
+    pub owner: AccountId,
+    pub mpc_contract: AccountId,
+    pub light_client_contract: AccountId,
+    pub eth_chain_id: u64,
+    pub max_withdrawal_gas_fee: U128,
+    pub fee_bps: u16,
+    pub fee_collector: AccountId,
+    pub referral_bps: u16,
+    pub config_delay_blocks: u64,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn set_mpc_contract(&mut self, account_id: AccountId) {
+        self.schedule_config_change(ConfigChange::MpcContract(account_id));
+    }
+
+    pub fn set_chain_signer(&mut self, chain_type: ChainType, account_id: Option<AccountId>) {
+        self.schedule_config_change(ConfigChange::ChainSigner(chain_type, account_id));
+    }
+
+    pub fn set_light_client_contract(&mut self, account_id: AccountId) {
+        self.schedule_config_change(ConfigChange::LightClientContract(account_id));
+    }
+
+    pub fn set_eth_chain_id(&mut self, chain_id: u64) {
+        self.schedule_config_change(ConfigChange::EthChainId(chain_id));
+    }
+
+    pub fn set_max_withdrawal_gas_fee(&mut self, max_fee: U128) {
+        self.schedule_config_change(ConfigChange::MaxWithdrawalGasFee(max_fee));
+    }
+
+    pub fn set_fee_bps(&mut self, fee_bps: u16) {
+        assert!(fee_bps <= MAX_FEE_BPS, "Fee exceeds maximum of {} bps", MAX_FEE_BPS);
+        assert!(fee_bps >= self.maker_rebate_bps, "Fee is below the maker rebate of {} bps", self.maker_rebate_bps);
+        self.schedule_config_change(ConfigChange::FeeBps(fee_bps));
+    }
+
+    pub fn set_fee_collector(&mut self, account_id: AccountId) {
+        self.schedule_config_change(ConfigChange::FeeCollector(account_id));
+    }
+
+    /// Applies to matches made after the change.
+    pub fn set_referral_bps(&mut self, referral_bps: u16) {
+        assert!(referral_bps <= 10_000, "Referral share exceeds 10000 bps");
+        self.schedule_config_change(ConfigChange::ReferralBps(referral_bps));
+    }
+
+    /// Applies to matches made after the change.
+    pub fn set_maker_rebate_bps(&mut self, maker_rebate_bps: u16) {
+        assert!(maker_rebate_bps <= self.fee_bps, "Maker rebate exceeds fee of {} bps", self.fee_bps);
+        self.schedule_config_change(ConfigChange::MakerRebateBps(maker_rebate_bps));
+    }
+
+    pub fn set_treasury_path(&mut self, chain_type: ChainType, path: Option<String>) {
+        assert!(path.as_ref().is_none_or(|p| !p.is_empty()), "Treasury path cannot be empty");
+        self.schedule_config_change(ConfigChange::TreasuryPath(chain_type, path));
+    }
+
+    pub fn set_min_confirmations(&mut self, chain_type: ChainType, confirmations: u64) {
+        self.schedule_config_change(ConfigChange::MinConfirmations(chain_type, confirmations));
+    }
+
+    /// Raising the delay applies at once; lowering it waits out the current
+    /// delay so it can't be used to skip a pending change's window.
+    pub fn set_config_delay(&mut self, blocks: u64) {
+        self.schedule_config_change(ConfigChange::DelayBlocks(blocks));
+    }
+
+    /// Apply every scheduled change whose delay has passed. Returns how many
+    /// were applied.
+    pub fn apply_config_changes(&mut self) -> u32 {
+        let height = env::block_height();
+        let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_config_changes)
+            .into_iter()
+            .partition(|p| p.effective_height <= height);
+        self.pending_config_changes = waiting;
+        for pending in &due {
+            self.apply_config_change(pending.change.clone());
+        }
+        due.len() as u32
+    }
+
+    pub fn cancel_config_changes(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can change config");
+        self.pending_config_changes.clear();
+        env::log_str("CONFIG_CHANGES_CANCELLED");
+    }
+
+    pub fn get_config(&self) -> ContractConfig {
+        ContractConfig {
+            owner: self.owner.clone(),
+            mpc_contract: self.mpc_contract.clone(),
+            light_client_contract: self.light_client_contract.clone(),
+            eth_chain_id: self.eth_chain_id,
+            max_withdrawal_gas_fee: U128(self.max_withdrawal_gas_fee),
+            fee_bps: self.fee_bps,
+            fee_collector: self.fee_collector.clone(),
+            referral_bps: self.referral_bps,
+            config_delay_blocks: self.config_delay_blocks,
+        }
+    }
+
+    pub fn get_chain_signer(&self, chain_type: ChainType) -> AccountId {
+        self.signer_for(&chain_type)
+    }
+
+    pub fn get_treasury_path(&self, chain_type: ChainType) -> Option<String> {
+        self.treasury_paths.get(&chain_type)
+    }
+
+    pub fn get_min_confirmations(&self, chain_type: ChainType) -> u64 {
+        self.min_confirmations.get(&chain_type).unwrap_or(0)
+    }
+
+    pub fn get_pending_config_changes(&self) -> Vec<PendingConfigChange> {
+        self.pending_config_changes.clone()
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn schedule_config_change(&mut self, change: ConfigChange) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can change config");
+        let immediate = match change {
+            ConfigChange::DelayBlocks(blocks) => blocks >= self.config_delay_blocks,
+            _ => self.config_delay_blocks == 0,
+        };
+        if immediate {
+            self.apply_config_change(change);
+            return;
+        }
+        let effective_height = env::block_height() + self.config_delay_blocks;
+        env::log_str(&format!(
+            "CONFIG_CHANGE_SCHEDULED:change={:?},effective_height={}",
+            change, effective_height
+        ));
+        self.pending_config_changes.push(PendingConfigChange { change, effective_height });
+    }
+
+    fn apply_config_change(&mut self, change: ConfigChange) {
+        match &change {
+            ConfigChange::MpcContract(account_id) => self.mpc_contract = account_id.clone(),
+            ConfigChange::ChainSigner(chain_type, Some(account_id)) => {
+                self.chain_signers.insert(chain_type, account_id);
+            }
+            ConfigChange::ChainSigner(chain_type, None) => {
+                self.chain_signers.remove(chain_type);
+            }
+            ConfigChange::LightClientContract(account_id) => {
+                self.light_client_contract = account_id.clone()
+            }
+            ConfigChange::EthChainId(chain_id) => self.eth_chain_id = *chain_id,
+            ConfigChange::FeeBps(fee_bps) => self.fee_bps = *fee_bps,
+            ConfigChange::FeeCollector(account_id) => self.fee_collector = account_id.clone(),
+            ConfigChange::ReferralBps(referral_bps) => self.referral_bps = *referral_bps,
+            ConfigChange::MakerRebateBps(maker_rebate_bps) => self.maker_rebate_bps = *maker_rebate_bps,
+            ConfigChange::DelayBlocks(blocks) => self.config_delay_blocks = *blocks,
+            ConfigChange::TreasuryPath(chain_type, Some(path)) => {
+                self.treasury_paths.insert(chain_type, path);
+            }
+            ConfigChange::TreasuryPath(chain_type, None) => {
+                self.treasury_paths.remove(chain_type);
+            }
+            ConfigChange::WithdrawalLimit(asset, Some(limit)) => {
+                self.withdrawal_limits.insert(asset, &limit.0);
+            }
+            ConfigChange::WithdrawalLimit(asset, None) => {
+                self.withdrawal_limits.remove(asset);
+            }
+            ConfigChange::VolumeCap(asset, Some(cap)) => {
+                self.volume_caps.insert(asset, &cap.0);
+            }
+            ConfigChange::VolumeCap(asset, None) => {
+                self.volume_caps.remove(asset);
+            }
+            ConfigChange::VolumeWindowBlocks(blocks) => self.volume_window_blocks = *blocks,
+            ConfigChange::MinConfirmations(chain_type, 0) => {
+                self.min_confirmations.remove(chain_type);
+            }
+            ConfigChange::MinConfirmations(chain_type, confirmations) => {
+                self.min_confirmations.insert(chain_type, confirmations);
+            }
+            ConfigChange::InputLimits(limits) => self.input_limits = *limits,
+            ConfigChange::MaxWithdrawalGasFee(max_fee) => self.max_withdrawal_gas_fee = max_fee.0,
+        }
+        env::log_str(&format!("CONFIG_CHANGED:{:?}", change));
+    }
+    /// `InsufficientConfirmations` unless the proven deposit sits at least
+    /// `min_confirmations` blocks below the finalized height of `chain_type`.
+    pub(crate) fn check_confirmations(
+        &self,
+        chain_type: &ChainType,
+        verification: &VerificationResult,
+    ) -> Result<(), OrderbookError> {
+        let required = self.get_min_confirmations(chain_type.clone());
+        let confirmations = verification.finalized_height.saturating_sub(verification.block_height);
+        if confirmations < required {
+            return Err(OrderbookError::InsufficientConfirmations {
+                chain_type: chain_type.clone(),
+                block_height: verification.block_height,
+                confirmations,
+                required,
+            });
+        }
+        Ok(())
+    }
+}
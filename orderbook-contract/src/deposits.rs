@@ -0,0 +1,126 @@
+//! Durable deposit history. Every credit through `deposit_for` or a proven
+//! MPC deposit is stored as a `DepositRecord` with its own id and indexed
+//! per user, so a missing deposit can be checked on-chain.
+
+use crate::ledger::log_event;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DepositSource {
+    /// Proven through `verify_mpc_deposit`.
+    Mpc,
+    /// Owner credit through `deposit_for`.
+    Admin,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositRecord {
+    #[serde(with = "crate::u64_json")]
+    pub id: u64,
+    pub user: AccountId,
+    pub source: DepositSource,
+    /// Chain of the asset; for MPC deposits, the chain the proof came from.
+    pub chain_type: ChainType,
+    pub asset: String,
+    pub amount: U128,
+    /// External transaction and MPC address paid; `None` for admin credits.
+    pub tx_hash: Option<String>,
+    pub recipient: Option<String>,
+    /// Block timestamp (ns) of the credit.
+    pub timestamp: u64,
+}
+
+/// Result of `on_mpc_deposit_verified`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositOutcome {
+    pub credited: bool,
+    /// Why the deposit was not credited.
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositRejectedEvent {
+    pub user: AccountId,
+    pub chain_type: ChainType,
+    pub asset: String,
+    pub amount: U128,
+    pub tx_hash: String,
+    pub reason: String,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn get_deposit(&self, deposit_id: U64) -> Option<DepositRecord> {
+        self.deposits.get(&(deposit_id.0))
+    }
+
+    /// A user's deposits, oldest first.
+    pub fn get_deposits_by_user(&self, user: AccountId, from_index: U128, limit: u64) -> Vec<DepositRecord> {
+        let Some(ids) = self.deposits_by_user.get(&user) else {
+            return vec![];
+        };
+        let from_index = from_index.0 as u64;
+        (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
+            .filter_map(|index| self.deposits.get(&ids.get(index).unwrap()))
+            .collect()
+    }
+}
+
+impl Orderbook {
+    /// Log `deposit_rejected` for a proven deposit that is not credited.
+    pub(crate) fn reject_deposit(
+        user: AccountId,
+        chain_type: ChainType,
+        asset: String,
+        amount: U128,
+        tx_hash: String,
+        error: OrderbookError,
+    ) -> DepositOutcome {
+        let reason = error.to_string();
+        log_event(
+            "deposit_rejected",
+            DepositRejectedEvent { user, chain_type, asset, amount, tx_hash, reason: reason.clone() },
+        );
+        DepositOutcome { credited: false, reason: Some(reason) }
+    }
+
+    /// Store a credited deposit and return its id.
+    pub(crate) fn record_deposit(
+        &mut self,
+        user: &AccountId,
+        source: DepositSource,
+        chain_type: ChainType,
+        asset: &str,
+        amount: u128,
+        tx_hash: Option<String>,
+        recipient: Option<String>,
+    ) -> u64 {
+        let id = self.next_deposit_id;
+        self.next_deposit_id += 1;
+        self.deposits.insert(
+            &id,
+            &DepositRecord {
+                id,
+                user: user.clone(),
+                source,
+                chain_type,
+                asset: asset.to_string(),
+                amount: U128(amount),
+                tx_hash,
+                recipient,
+                timestamp: env::block_timestamp(),
+            },
+        );
+        let mut ids = self
+            .deposits_by_user
+            .get(user)
+            .unwrap_or_else(|| Vector::new(format!("F{}", user).as_bytes()));
+        ids.push(&id);
+        self.deposits_by_user.insert(user, &ids);
+        id
+    }
+}
@@ -0,0 +1,145 @@
+//! Order-book depth per market direction. Open intents are indexed by
+//! `src/dst` pair as they open and close, sorted by limit price, so a
+//! depth query only walks the intents currently resting on that side of
+//! the book, best level first.
+
+use crate::*;
+use std::cmp::Ordering;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepthLevel {
+    /// Limit price as `dst_amount / src_amount`, reduced to lowest terms.
+    pub price_num: U128,
+    pub price_den: U128,
+    /// Unfilled `src_asset` across the level's intents.
+    pub total_remaining: U128,
+    pub intent_count: u32,
+}
+
+/// An open intent's place on its side of the book: least `dst_asset`
+/// asked per unit of `src_asset` first, oldest first within a price. The
+/// price is kept in lowest terms, so equal prices compare equal.
+#[derive(BorshDeserialize, BorshSerialize, Clone, PartialEq, Eq, Debug)]
+pub struct BookEntry {
+    pub price_num: u128,
+    pub price_den: u128,
+    pub intent_id: u64,
+}
+
+impl BookEntry {
+    pub(crate) fn of(intent: &Intent) -> Self {
+        let divisor = gcd(intent.dst_amount, intent.src_amount);
+        Self {
+            price_num: intent.dst_amount / divisor,
+            price_den: intent.src_amount / divisor,
+            intent_id: intent.id,
+        }
+    }
+}
+
+impl Ord for BookEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_ratio(self.price_num, self.price_den, other.price_num, other.price_den)
+            .then(self.intent_id.cmp(&other.intent_id))
+    }
+}
+
+impl PartialOrd for BookEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Open, unexpired intents selling `src_asset` for `dst_asset`,
+    /// grouped by limit price. Cheapest level (least `dst_asset` asked per
+    /// unit of `src_asset`) first, at most `levels` entries.
+    pub fn get_depth(&self, src_asset: String, dst_asset: String, levels: u32) -> Vec<DepthLevel> {
+        let key = Self::book_key(&src_asset.to_uppercase(), &dst_asset.to_uppercase());
+        let Some(entries) = self.open_intents_by_pair.get(&key) else {
+            return Vec::new();
+        };
+        let now = env::block_timestamp();
+        let mut book: Vec<DepthLevel> = Vec::new();
+        for (entry, ()) in entries.iter() {
+            let intent = self.intents.get(&entry.intent_id).expect("Intent not found");
+            if intent.is_expired(now) {
+                continue;
+            }
+            let remaining = intent.src_amount - intent.filled_amount;
+            if let Some(level) = book
+                .last_mut()
+                .filter(|level| level.price_num.0 == entry.price_num && level.price_den.0 == entry.price_den)
+            {
+                level.total_remaining.0 += remaining;
+                level.intent_count += 1;
+                continue;
+            }
+            if book.len() == levels as usize {
+                break;
+            }
+            book.push(DepthLevel {
+                price_num: U128(entry.price_num),
+                price_den: U128(entry.price_den),
+                total_remaining: U128(remaining),
+                intent_count: 1,
+            });
+        }
+        book
+    }
+}
+
+impl Orderbook {
+    /// Key for one direction of a market, e.g. "SOL>ETH" for intents
+    /// selling SOL for ETH.
+    pub(crate) fn book_key(src_asset: &str, dst_asset: &str) -> String {
+        format!("{}>{}", src_asset, dst_asset)
+    }
+
+    pub(crate) fn index_open_intent(&mut self, intent: &Intent) {
+        let key = Self::book_key(&intent.src_asset, &intent.dst_asset);
+        let mut entries = self
+            .open_intents_by_pair
+            .get(&key)
+            .unwrap_or_else(|| TreeMap::new(format!("A{}", key).as_bytes()));
+        entries.insert(&BookEntry::of(intent), &());
+        self.open_intents_by_pair.insert(&key, &entries);
+    }
+
+    pub(crate) fn unindex_open_intent(&mut self, intent: &Intent) {
+        let key = Self::book_key(&intent.src_asset, &intent.dst_asset);
+        if let Some(mut entries) = self.open_intents_by_pair.get(&key) {
+            entries.remove(&BookEntry::of(intent));
+            if entries.is_empty() {
+                self.open_intents_by_pair.remove(&key);
+            } else {
+                self.open_intents_by_pair.insert(&key, &entries);
+            }
+        }
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Compares `a / b` with `c / d` (denominators non-zero) without
+/// multiplying, so full-range u128 amounts cannot overflow.
+pub(crate) fn cmp_ratio(a: u128, b: u128, c: u128, d: u128) -> Ordering {
+    match (a / b).cmp(&(c / d)) {
+        Ordering::Equal => {}
+        other => return other,
+    }
+    match (a % b, c % d) {
+        (0, 0) => Ordering::Equal,
+        (0, _) => Ordering::Less,
+        (_, 0) => Ordering::Greater,
+        // a'/b vs c'/d with both below one: compare the reciprocals, reversed.
+        (ra, rc) => cmp_ratio(d, rc, b, ra),
+    }
+}
@@ -0,0 +1,295 @@
+//! Derivation paths and MPC-derived deposit addresses. Chain signatures derive each signing key
+//! additively from the MPC root key: `child = root + epsilon * G`, where
+//! `epsilon` hashes the requesting contract and the path. The point
+//! arithmetic is done with the `ecrecover` host function: recovering a
+//! signature `(r = root.x, s = r)` over the hash `-r * epsilon` yields
+//! exactly `root + epsilon * G`.
+
+use crate::*;
+use sha3::{Digest, Sha3_256};
+
+const EPSILON_DERIVATION_PREFIX: &str = "near-mpc-recovery v0.1.0 epsilon derivation:";
+
+/// secp256k1 group order, big-endian.
+const CURVE_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 0xba, 0xae, 0xdc,
+    0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Path the deposit key of `user` on `chain_type` is derived under. The
+/// relayer signs deposit sweeps under the same path.
+pub fn deposit_path(user: &AccountId, chain_type: &ChainType) -> String {
+    format!("deposit/{}/{:?}", user, chain_type)
+}
+
+/// The only path `user`'s withdrawals on `chain_type` may be signed under.
+pub fn user_path(user: &AccountId, chain_type: &ChainType) -> String {
+    format!("user/{}/{:?}", user, chain_type)
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Owner sets the MPC root public key deposit addresses on `chain_type`
+    /// are derived from, as compressed or uncompressed secp256k1 hex.
+    pub fn set_mpc_root_key(&mut self, chain_type: ChainType, pubkey_hex: String) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can set MPC root keys");
+        Self::assert_secp256k1_chain(&chain_type);
+        let key = parse_public_key(&pubkey_hex).unwrap_or_else(|| env::panic_str("Invalid secp256k1 public key"));
+        self.mpc_root_keys.insert(&chain_type, &key);
+        env::log_str(&format!("MPC_ROOT_KEY_SET:chain={:?},key={}", chain_type, pubkey_hex));
+    }
+
+    /// Uncompressed hex (`04...`) of the root key set for `chain_type`.
+    pub fn get_mpc_root_key(&self, chain_type: ChainType) -> Option<String> {
+        self.mpc_root_keys.get(&chain_type).map(|key| key_hex(&key))
+    }
+
+    /// The address MPC controls for `user` on `chain_type`: the key derived
+    /// for this contract under `deposit_path`, as an ETH address or a BTC
+    /// P2WPKH address.
+    pub fn derive_deposit_address(&self, user: AccountId, chain_type: ChainType) -> String {
+        Self::assert_secp256k1_chain(&chain_type);
+        let root = self
+            .mpc_root_keys
+            .get(&chain_type)
+            .unwrap_or_else(|| env::panic_str(&format!("No MPC root key set for {:?}", chain_type)));
+        let epsilon = derive_epsilon(&env::current_account_id(), &deposit_path(&user, &chain_type));
+        let child = derive_child_key(&root, &epsilon).unwrap_or_else(|| env::panic_str("Key derivation failed"));
+        match chain_type {
+            ChainType::ETH => eth_address(&child),
+            ChainType::BTC => btc_p2wpkh_address(&child),
+            ChainType::SOL => unreachable!(),
+        }
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn check_user_path(user: &AccountId, chain_type: &ChainType, path: &str) -> Result<(), OrderbookError> {
+        let expected = user_path(user, chain_type);
+        if path != expected {
+            return Err(OrderbookError::WrongWithdrawalPath { expected, got: path.to_string() });
+        }
+        Ok(())
+    }
+
+    /// Transitions are signed under the owner-configured treasury path of
+    /// their chain and nothing else.
+    pub(crate) fn check_treasury_path(&self, chain_type: &ChainType, path: &str) -> Result<(), OrderbookError> {
+        match self.treasury_paths.get(chain_type) {
+            None => Err(OrderbookError::NoTreasuryPath { chain_type: chain_type.clone() }),
+            Some(expected) if expected != path => Err(OrderbookError::NotTreasuryPath {
+                chain_type: chain_type.clone(),
+                path: path.to_string(),
+            }),
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn assert_secp256k1_chain(chain_type: &ChainType) {
+        if *chain_type == ChainType::SOL {
+            env::panic_str("SOL deposit addresses need Ed25519 key derivation, which is not supported yet");
+        }
+    }
+
+    /// Recover the key a secp256k1 `signature` over `payload` was made
+    /// with. When `chain_type` has a root key, it must be the key MPC derives
+    /// for this contract under `path`. Returns the recovered key as
+    /// uncompressed hex (`None` for Ed25519 or, without a root key, when
+    /// nothing can be recovered).
+    pub(crate) fn check_signature(
+        &self,
+        chain_type: &ChainType,
+        payload: &[u8],
+        path: &str,
+        signature: &SignResult,
+    ) -> Result<Option<String>, String> {
+        let SignResult::Secp256k1 { big_r, s, recovery_id } = signature else {
+            return Ok(None);
+        };
+        let payload: &[u8; 32] = payload.try_into().map_err(|_| "Secp256k1 payload must be 32 bytes")?;
+        let recovered = recover_signer(payload, &big_r.affine_point, &s.scalar, *recovery_id);
+        let Some(root) = self.mpc_root_keys.get(chain_type) else {
+            return Ok(recovered.map(|key| key_hex(&key)));
+        };
+        let recovered = recovered.ok_or("Signature does not recover to a public key")?;
+        let expected = derive_child_key(&root, &derive_epsilon(&env::current_account_id(), path))
+            .ok_or("Key derivation failed")?;
+        if recovered != expected {
+            return Err(format!(
+                "Signature recovers to {}, not the key derived for path {}",
+                key_hex(&recovered),
+                path
+            ));
+        }
+        Ok(Some(key_hex(&recovered)))
+    }
+}
+
+/// Signer of `payload` given MPC's `big_r` (compressed point hex) and `s`
+/// (scalar hex).
+fn recover_signer(payload: &[u8; 32], big_r: &str, s: &str, recovery_id: u8) -> Option<[u8; 64]> {
+    let big_r = hex::decode(big_r).ok()?;
+    let s = hex::decode(s).ok()?;
+    if big_r.len() != 33 || !matches!(big_r[0], 0x02 | 0x03) || s.len() != 32 {
+        return None;
+    }
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&big_r[1..]);
+    signature[32..].copy_from_slice(&s);
+    env::ecrecover(payload, &signature, recovery_id, false)
+}
+
+fn key_hex(key: &[u8; 64]) -> String {
+    format!("04{}", hex::encode(key))
+}
+
+/// `epsilon = sha3_256(prefix || "{predecessor},{path}")`, reduced mod n.
+pub(crate) fn derive_epsilon(predecessor: &AccountId, path: &str) -> [u8; 32] {
+    let hash: [u8; 32] = Sha3_256::digest(format!("{}{},{}", EPSILON_DERIVATION_PREFIX, predecessor, path)).into();
+    reduce(hash)
+}
+
+/// `root + epsilon * G` for a root key given as `x || y`.
+pub(crate) fn derive_child_key(root: &[u8; 64], epsilon: &[u8; 32]) -> Option<[u8; 64]> {
+    let r: [u8; 32] = root[..32].try_into().unwrap();
+    let parity = root[63] & 1;
+    let hash = neg_mod(mul_mod(&r, epsilon));
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&r);
+    signature[32..].copy_from_slice(&r);
+    env::ecrecover(&hash, &signature, parity, false)
+}
+
+/// Parse a compressed (`02`/`03`) or uncompressed (`04`) key, checking it is
+/// on the curve. Returns `x || y`.
+pub(crate) fn parse_public_key(pubkey_hex: &str) -> Option<[u8; 64]> {
+    let bytes = hex::decode(pubkey_hex.strip_prefix("0x").unwrap_or(pubkey_hex)).ok()?;
+    let (x, parity) = match (bytes.len(), *bytes.first()?) {
+        (33, 0x02 | 0x03) => (&bytes[1..], bytes[0] & 1),
+        (65, 0x04) => (&bytes[1..33], bytes[64] & 1),
+        _ => return None,
+    };
+    let mut root = [0u8; 64];
+    root[..32].copy_from_slice(x);
+    root[63] = parity;
+    // With a zero epsilon the "derivation" only lifts x onto the curve.
+    let point = derive_child_key(&root, &[0u8; 32])?;
+    (bytes.len() == 33 || point[..] == bytes[1..]).then_some(point)
+}
+
+pub(crate) fn eth_address(key: &[u8; 64]) -> String {
+    format!("0x{}", hex::encode(&env::keccak256_array(key)[12..]))
+}
+
+pub(crate) fn btc_p2wpkh_address(key: &[u8; 64]) -> String {
+    let mut compressed = vec![0x02 | (key[63] & 1)];
+    compressed.extend_from_slice(&key[..32]);
+    let program = env::ripemd160_array(env::sha256_array(&compressed));
+
+    // Witness version 0, then the program regrouped into 5-bit words
+    let mut data = vec![0u8];
+    let (mut acc, mut bits) = (0u32, 0);
+    for byte in program {
+        acc = (acc << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            data.push(((acc >> bits) & 31) as u8);
+        }
+    }
+    if bits > 0 {
+        data.push(((acc << (5 - bits)) & 31) as u8);
+    }
+    let checksum = bech32_checksum("bc", &data);
+    let mut address = "bc1".to_string();
+    address.extend(data.iter().chain(&checksum).map(|&d| BECH32_CHARSET[d as usize] as char));
+    address
+}
+
+fn bech32_checksum(hrp: &str, data: &[u8]) -> [u8; 6] {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 31));
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0; 6]);
+    let mut chk = 1u32;
+    for value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ value as u32;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk ^= 1;
+    let mut checksum = [0u8; 6];
+    for (i, c) in checksum.iter_mut().enumerate() {
+        *c = ((chk >> (5 * (5 - i))) & 31) as u8;
+    }
+    checksum
+}
+
+// ---- Scalar arithmetic mod n on big-endian 256-bit integers ----
+
+/// `a - b` mod 2^256.
+fn wrapping_sub(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let diff = a[i] as i16 - b[i] as i16 - borrow;
+        borrow = (diff < 0) as i16;
+        out[i] = diff.rem_euclid(256) as u8;
+    }
+    out
+}
+
+/// Reduce a value below 2^256 (so below 2n) mod n.
+fn reduce(a: [u8; 32]) -> [u8; 32] {
+    if a >= CURVE_ORDER {
+        wrapping_sub(&a, &CURVE_ORDER)
+    } else {
+        a
+    }
+}
+
+fn add_mod(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut sum = [0u8; 32];
+    let mut carry = 0u16;
+    for i in (0..32).rev() {
+        let s = a[i] as u16 + b[i] as u16 + carry;
+        sum[i] = s as u8;
+        carry = s >> 8;
+    }
+    // Past 2^256 the wrapped subtraction still lands on the right residue
+    if carry == 1 || sum >= CURVE_ORDER {
+        wrapping_sub(&sum, &CURVE_ORDER)
+    } else {
+        sum
+    }
+}
+
+fn mul_mod(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let a = reduce(*a);
+    let mut acc = [0u8; 32];
+    for byte in b {
+        for bit in (0..8).rev() {
+            acc = add_mod(&acc, &acc);
+            if (byte >> bit) & 1 == 1 {
+                acc = add_mod(&acc, &a);
+            }
+        }
+    }
+    acc
+}
+
+fn neg_mod(a: [u8; 32]) -> [u8; 32] {
+    if a == [0u8; 32] {
+        a
+    } else {
+        wrapping_sub(&CURVE_ORDER, &a)
+    }
+}
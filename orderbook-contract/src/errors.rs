@@ -0,0 +1,226 @@
+//! Typed failures of the mutating entry points. Methods marked
+//! `#[handle_result]` return `Result<_, OrderbookError>`; the runtime panics
+//! with the `Display` text of an `Err`, so the transaction still fails and
+//! every state change it made is rolled back, while clients can key retries
+//! off the message prefix instead of free-form strings.
+//!
+//! Helpers shared with entry points that still panic come in pairs: a
+//! `check_*` returning the error and an `assert_*` panicking with it.
+
+use crate::*;
+use std::fmt;
+
+#[derive(FunctionError, Clone, PartialEq, Debug)]
+pub enum OrderbookError {
+    ContractPaused,
+    AccountBlocked { account: AccountId },
+    PairPaused { pair: String },
+    UnknownAsset { asset: String },
+    AssetDisabled { asset: String },
+    /// Malformed `make_intent` arguments.
+    InvalidIntent { reason: String },
+    SelfReferral,
+    ExpiryInPast,
+    OpenIntentLimit { limit: u64 },
+    InsufficientBalance { asset: String, have: u128, need: u128 },
+    StorageNotRegistered { account: AccountId },
+    InsufficientStorage { account: AccountId },
+    IntentNotFound { id: u64 },
+    IntentNotOpen { id: u64 },
+    IntentFilled { id: u64 },
+    IntentExpired { id: u64 },
+    /// Some of its sub-intents are still being signed or proven.
+    IntentSettling { id: u64 },
+    TakerNotAllowed { taker: AccountId, intent_id: u64 },
+    AllOrNothing { intent_id: u64, required: u128 },
+    BelowMinFill { intent_id: u64, amount: u128, min: u128 },
+    BelowMinIntentSize { asset: String, amount: u128, min: u128 },
+    ExceedsRemaining { intent_id: u64 },
+    PriceMismatch { intent_id: u64, get_amount: u128 },
+    PriceOverflow { intent_id: u64 },
+    AmountOverflow { asset: String },
+    TooFewMatches,
+    TooManyMatches { max: usize },
+    DuplicateIntent { id: u64 },
+    InsufficientSupply { asset: String, deficit: u128 },
+    SolverNotRegistered { account: AccountId },
+    InsufficientDeposit { required: u128, attached: u128 },
+    InvalidDestination { chain_type: ChainType, destination: String },
+    RecipientMismatch { recipient: String, chain_type: ChainType, maker: AccountId },
+    NoReceiveAddress { maker: AccountId, chain_type: ChainType },
+    InputTooLong { field: String, len: usize, max: u32 },
+    TransitionAssetMismatch { intent_id: u64, expected: String, got: String },
+    TransitionTxTooLarge { intent_id: u64, len: usize, max: usize },
+    /// The payload of an ETH match is not the signing hash of its
+    /// `unsigned_tx`, or the match has none.
+    TransitionPayloadMismatch { intent_id: u64 },
+    /// The ETH transition transaction does not pay the fill.
+    InvalidTransitionTx { reason: String },
+    /// Transitions on this chain have no transaction the contract can
+    /// check the payload against, so they are not signed.
+    UnsupportedTransitionChain { chain_type: ChainType },
+    NoTreasuryPath { chain_type: ChainType },
+    NotTreasuryPath { chain_type: ChainType, path: String },
+    WrongWithdrawalPath { expected: String, got: String },
+    UnsupportedWithdrawal { asset: String, chain_type: ChainType },
+    /// The withdrawal transaction does not use the user's next nonce.
+    WrongWithdrawalNonce { expected: u64, got: u64 },
+    WithdrawalGasFeeTooHigh { max: u128 },
+    /// The unsigned withdrawal transaction does not pay the withdrawal.
+    InvalidTransaction { reason: String },
+    WithdrawalLimitExceeded { asset: String, remaining: u128, resets_at: u64 },
+    SubIntentNotFound { id: u64 },
+    SubIntentNotTaken { id: u64 },
+    SubIntentExpired { id: u64 },
+    NoSettlementRequest { id: u64 },
+    /// The request was backfilled by the V0 migration and no transaction
+    /// has been approved for it yet.
+    SettlementPayloadUnset { id: u64 },
+    NoEscrow { id: u64 },
+    EscrowTimeoutNotReached { id: u64, claimable_after: u64 },
+    MemoMismatch { expected: String, got: String },
+    PaymentConsumed { tx_hash: String },
+    NotAuthorized { account: AccountId, action: String },
+    /// `reason` is `None` when the light client call itself failed.
+    DepositProofInvalid { reason: Option<VerifyError> },
+    DepositConsumed { tx_hash: String },
+    InsufficientConfirmations { chain_type: ChainType, block_height: u64, confirmations: u64, required: u64 },
+}
+
+impl OrderbookError {
+    pub(crate) fn invalid_tx(reason: &str) -> Self {
+        OrderbookError::InvalidTransaction { reason: reason.to_string() }
+    }
+}
+
+impl fmt::Display for OrderbookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use OrderbookError::*;
+        match self {
+            ContractPaused => write!(f, "Contract is paused"),
+            AccountBlocked { account } => write!(f, "Account {} is blocked", account),
+            PairPaused { pair } => write!(f, "Pair {} is paused", pair),
+            UnknownAsset { asset } => write!(f, "Unknown asset {}", asset),
+            AssetDisabled { asset } => write!(f, "Asset {} is disabled", asset),
+            InvalidIntent { reason } => write!(f, "Invalid intent: {}", reason),
+            SelfReferral => write!(f, "Maker cannot refer their own intent"),
+            ExpiryInPast => write!(f, "Expiry must be in the future"),
+            OpenIntentLimit { limit } => write!(f, "Open intent limit of {} reached", limit),
+            InsufficientBalance { asset, have, need } => {
+                write!(f, "Insufficient {} balance: have {}, need {}", asset, have, need)
+            }
+            StorageNotRegistered { account } => write!(f, "Storage not registered for {}", account),
+            InsufficientStorage { account } => write!(f, "Insufficient storage balance for {}", account),
+            IntentNotFound { id } => write!(f, "Intent {} not found", id),
+            IntentNotOpen { id } => write!(f, "Intent {} not open", id),
+            IntentFilled { id } => write!(f, "Intent {} already filled", id),
+            IntentExpired { id } => write!(f, "Intent {} expired", id),
+            IntentSettling { id } => write!(f, "Intent {} has sub-intents still settling", id),
+            TakerNotAllowed { taker, intent_id } => {
+                write!(f, "{} is not allowed to fill Intent {}", taker, intent_id)
+            }
+            AllOrNothing { intent_id, required } => {
+                write!(f, "Intent {} is all-or-nothing: fill must be {}", intent_id, required)
+            }
+            BelowMinFill { intent_id, amount, min } => {
+                write!(f, "Fill {} below minimum {} for Intent {}", amount, min, intent_id)
+            }
+            BelowMinIntentSize { asset, amount, min } => {
+                write!(f, "Intent size {} below minimum {} for {}", amount, min, asset)
+            }
+            ExceedsRemaining { intent_id } => {
+                write!(f, "Fill amount exceeds remaining balance for Intent {}", intent_id)
+            }
+            PriceMismatch { intent_id, get_amount } => {
+                write!(f, "Price mismatch for Intent {}: Get {} < Required", intent_id, get_amount)
+            }
+            PriceOverflow { intent_id } => write!(f, "Price check overflow for Intent {}", intent_id),
+            AmountOverflow { asset } => write!(f, "Amount overflow for asset {}", asset),
+            TooFewMatches => write!(f, "At least 2 intents required"),
+            TooManyMatches { max } => write!(f, "Max {} intents per batch (gas limit)", max),
+            DuplicateIntent { id } => write!(f, "Duplicate Intent {} in batch", id),
+            InsufficientSupply { asset, deficit } => {
+                write!(f, "Insufficient supply for asset {}: deficit {}", asset, deficit)
+            }
+            SolverNotRegistered { account } => write!(f, "{} is not a registered solver", account),
+            InsufficientDeposit { required, attached } => {
+                write!(f, "Attach at least {} yoctoNEAR, got {}", required, attached)
+            }
+            InvalidDestination { chain_type, destination } => {
+                write!(f, "Invalid {:?} destination {}", chain_type, destination)
+            }
+            RecipientMismatch { recipient, chain_type, maker } => write!(
+                f,
+                "Recipient {} does not match the {:?} receive address of {}",
+                recipient, chain_type, maker
+            ),
+            InputTooLong { field, len, max } => write!(f, "{} is {} bytes, max {}", field, len, max),
+            NoReceiveAddress { maker, chain_type } => {
+                write!(f, "Maker {} has no {:?} receive address", maker, chain_type)
+            }
+            TransitionAssetMismatch { intent_id, expected, got } => write!(
+                f,
+                "Transition asset {} does not match intent {} src_asset {}",
+                got, intent_id, expected
+            ),
+            TransitionTxTooLarge { intent_id, len, max } => {
+                write!(f, "Transition tx of intent {} is {} bytes, max {}", intent_id, len, max)
+            }
+            TransitionPayloadMismatch { intent_id } => {
+                write!(f, "Payload of intent {} is not the keccak256 hash of its transition tx", intent_id)
+            }
+            InvalidTransitionTx { reason } => write!(f, "Invalid transition transaction: {}", reason),
+            UnsupportedTransitionChain { chain_type } => {
+                write!(f, "Transitions on {:?} are not supported; only ETH transitions can be checked", chain_type)
+            }
+            NoTreasuryPath { chain_type } => write!(f, "No treasury path configured for {:?}", chain_type),
+            NotTreasuryPath { chain_type, path } => {
+                write!(f, "Path {} is not the {:?} treasury path", path, chain_type)
+            }
+            WrongWithdrawalPath { expected, got } => {
+                write!(f, "Withdrawal path must be {}, got {}", expected, got)
+            }
+            UnsupportedWithdrawal { asset, chain_type } => {
+                write!(
+                    f,
+                    "Withdrawals of {} on {:?} are not supported; only native ETH, BTC and SOL and ERC-20 tokens with a registered contract can be withdrawn",
+                    asset, chain_type
+                )
+            }
+            WrongWithdrawalNonce { expected, got } => {
+                write!(f, "Withdrawal transaction nonce must be {}, got {}", expected, got)
+            }
+            WithdrawalGasFeeTooHigh { max } => {
+                write!(f, "Withdrawal gas fee (gas_limit * max_fee_per_gas) exceeds the cap of {} wei", max)
+            }
+            InvalidTransaction { reason } => write!(f, "Invalid withdrawal transaction: {}", reason),
+            WithdrawalLimitExceeded { asset, remaining, resets_at } => write!(
+                f,
+                "Withdrawal limit exceeded for {}: {} remaining until {}",
+                asset, remaining, resets_at
+            ),
+            SubIntentNotFound { id } => write!(f, "Sub-Intent {} not found", id),
+            SubIntentNotTaken { id } => write!(f, "Sub-Intent {} is not in Taken state", id),
+            SubIntentExpired { id } => write!(f, "Sub-Intent {} expired", id),
+            NoSettlementRequest { id } => write!(f, "No settlement request for Sub-Intent {}", id),
+            SettlementPayloadUnset { id } => {
+                write!(f, "Settlement request for Sub-Intent {} has no approved transaction yet", id)
+            }
+            NoEscrow { id } => write!(f, "No escrow for Sub-Intent {}", id),
+            EscrowTimeoutNotReached { id, claimable_after } => {
+                write!(f, "Escrow timeout of Sub-Intent {} not reached until {}", id, claimable_after)
+            }
+            MemoMismatch { expected, got } => write!(f, "memo mismatch: expected {}, got {}", expected, got),
+            PaymentConsumed { tx_hash } => write!(f, "Payment tx {} already used", tx_hash),
+            NotAuthorized { account, action } => write!(f, "{} is not authorized to {}", account, action),
+            DepositProofInvalid { reason: None } => write!(f, "MPC deposit proof invalid"),
+            DepositProofInvalid { reason: Some(reason) } => write!(f, "MPC deposit proof invalid: {:?}", reason),
+            DepositConsumed { tx_hash } => write!(f, "Deposit tx {} already credited", tx_hash),
+            InsufficientConfirmations { chain_type, block_height, confirmations, required } => write!(
+                f,
+                "Deposit at height {} has {} confirmations on {:?}, {} required",
+                block_height, confirmations, chain_type, required
+            ),
+        }
+    }
+}
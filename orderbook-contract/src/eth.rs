@@ -0,0 +1,152 @@
+//! Minimal parsing of unsigned Ethereum transactions so `withdraw` and
+//! settlement repricing can check what MPC is asked to sign instead of
+//! trusting a caller-supplied hash.
+//!
+//! Only EIP-1559 (type 2) transactions are accepted:
+//! `0x02 || rlp([chain_id, nonce, max_priority_fee, max_fee, gas, to, value, data, access_list])`.
+
+use near_sdk::env;
+
+const EIP1559_TX_TYPE: u8 = 0x02;
+
+/// The fields of an unsigned transaction that a withdrawal is checked against.
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnsignedEthTx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u64,
+    pub to: [u8; 20],
+    pub value: u128,
+    pub data: Vec<u8>,
+    /// RLP payload of the access list, compared as-is.
+    pub access_list: Vec<u8>,
+    /// keccak256 of the full serialized transaction; what MPC signs.
+    pub signing_hash: [u8; 32],
+}
+
+/// One decoded RLP item: `(is_list, payload)`.
+type RlpItem<'a> = (bool, &'a [u8]);
+
+/// Decode the RLP item at the start of `buf`; returns it and the remaining
+/// input.
+fn rlp_item(buf: &[u8]) -> Result<(RlpItem<'_>, &[u8]), String> {
+    let (&prefix, rest) = buf.split_first().ok_or("truncated RLP item")?;
+    let (is_list, header_len, len) = match prefix {
+        0x00..=0x7f => return Ok(((false, &buf[..1]), rest)),
+        0x80..=0xb7 => (false, 0, (prefix - 0x80) as usize),
+        0xb8..=0xbf => (false, (prefix - 0xb7) as usize, 0),
+        0xc0..=0xf7 => (true, 0, (prefix - 0xc0) as usize),
+        0xf8..=0xff => (true, (prefix - 0xf7) as usize, 0),
+    };
+    let (len, rest) = if header_len == 0 {
+        (len, rest)
+    } else {
+        if rest.len() < header_len || header_len > 4 {
+            return Err("bad RLP length".to_string());
+        }
+        let len = rest[..header_len].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, &rest[header_len..])
+    };
+    if rest.len() < len {
+        return Err("truncated RLP item".to_string());
+    }
+    Ok(((is_list, &rest[..len]), &rest[len..]))
+}
+
+fn rlp_list(buf: &[u8]) -> Result<Vec<RlpItem<'_>>, String> {
+    let mut items = Vec::new();
+    let mut rest = buf;
+    while !rest.is_empty() {
+        let (item, next) = rlp_item(rest)?;
+        items.push(item);
+        rest = next;
+    }
+    Ok(items)
+}
+
+fn rlp_bytes<'a>(item: RlpItem<'a>, field: &str) -> Result<&'a [u8], String> {
+    if item.0 {
+        return Err(format!("{} must be a byte string", field));
+    }
+    Ok(item.1)
+}
+
+fn rlp_uint(item: RlpItem<'_>, field: &str, max_bytes: usize) -> Result<u128, String> {
+    let bytes = rlp_bytes(item, field)?;
+    if bytes.len() > max_bytes {
+        return Err(format!("{} out of range", field));
+    }
+    if bytes.first() == Some(&0) {
+        return Err(format!("{} has leading zeros", field));
+    }
+    Ok(bytes.iter().fold(0u128, |acc, b| (acc << 8) | *b as u128))
+}
+
+/// Parse a serialized unsigned EIP-1559 transaction. The error says what
+/// is malformed.
+pub(crate) fn parse_unsigned_eip1559(tx: &[u8]) -> Result<UnsignedEthTx, String> {
+    let (&tx_type, body) = tx.split_first().ok_or("empty")?;
+    if tx_type != EIP1559_TX_TYPE {
+        return Err("only EIP-1559 (type 2) transactions are supported".to_string());
+    }
+    let ((is_list, payload), trailing) = rlp_item(body)?;
+    if !is_list || !trailing.is_empty() {
+        return Err("expected a single RLP list".to_string());
+    }
+    let fields = rlp_list(payload)?;
+    if fields.len() != 9 {
+        return Err("expected 9 fields".to_string());
+    }
+    let to: [u8; 20] = rlp_bytes(fields[5], "to")?
+        .try_into()
+        .map_err(|_| "to must be a 20-byte address")?;
+    let access_list = match fields[8] {
+        (true, list) => list.to_vec(),
+        (false, _) => return Err("access_list must be a list".to_string()),
+    };
+    Ok(UnsignedEthTx {
+        chain_id: rlp_uint(fields[0], "chain_id", 8)? as u64,
+        nonce: rlp_uint(fields[1], "nonce", 8)? as u64,
+        max_priority_fee_per_gas: rlp_uint(fields[2], "max_priority_fee_per_gas", 16)?,
+        max_fee_per_gas: rlp_uint(fields[3], "max_fee_per_gas", 16)?,
+        gas_limit: rlp_uint(fields[4], "gas_limit", 8)? as u64,
+        to,
+        value: rlp_uint(fields[6], "value", 16)?,
+        data: rlp_bytes(fields[7], "data")?.to_vec(),
+        access_list,
+        signing_hash: env::keccak256_array(tx),
+    })
+}
+
+impl UnsignedEthTx {
+    /// True if `other` is this transaction with, at most, different gas
+    /// fields.
+    pub(crate) fn differs_only_in_gas(&self, other: &UnsignedEthTx) -> bool {
+        (self.chain_id, self.nonce, self.to, self.value, &self.data, &self.access_list)
+            == (other.chain_id, other.nonce, other.to, other.value, &other.data, &other.access_list)
+    }
+}
+
+/// Length of `erc20_transfer_data`: the selector and two words.
+pub(crate) const ERC20_TRANSFER_DATA_LEN: usize = 4 + 32 + 32;
+
+/// Calldata of an ERC-20 `transfer(to, amount)`.
+pub(crate) fn erc20_transfer_data(to: &[u8; 20], amount: u128) -> Vec<u8> {
+    let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+    data.extend([0u8; 12]);
+    data.extend(to);
+    data.extend([0u8; 16]);
+    data.extend(amount.to_be_bytes());
+    data
+}
+
+/// Parse a `0x`-prefixed (or bare) 20-byte hex address.
+pub(crate) fn parse_address(address: &str) -> [u8; 20] {
+    let hex_str = address.strip_prefix("0x").unwrap_or(address);
+    hex::decode(hex_str)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or_else(|| env::panic_str(&format!("Invalid ETH address {}", address)))
+}
@@ -0,0 +1,68 @@
+//! Size caps on caller-supplied bytes and strings that are forwarded in
+//! promises or kept in state. Entry points check them before changing
+//! anything, so an oversized argument costs the caller only the rejection.
+
+use crate::*;
+
+pub const DEFAULT_MAX_PROOF_DATA_LEN: u32 = 16 * 1024;
+pub const DEFAULT_MAX_PATH_LEN: u32 = 128;
+/// Recipients, destinations, memos and transaction hashes.
+pub const DEFAULT_MAX_FIELD_LEN: u32 = 256;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InputLimits {
+    pub max_proof_data_len: u32,
+    pub max_path_len: u32,
+    pub max_field_len: u32,
+}
+
+impl Default for InputLimits {
+    fn default() -> Self {
+        InputLimits {
+            max_proof_data_len: DEFAULT_MAX_PROOF_DATA_LEN,
+            max_path_len: DEFAULT_MAX_PATH_LEN,
+            max_field_len: DEFAULT_MAX_FIELD_LEN,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Owner changes the input caps, after the config delay.
+    pub fn set_input_limits(&mut self, limits: InputLimits) {
+        assert!(
+            limits.max_proof_data_len > 0 && limits.max_path_len > 0 && limits.max_field_len > 0,
+            "Input limits must be positive"
+        );
+        self.schedule_config_change(ConfigChange::InputLimits(limits));
+    }
+
+    pub fn get_input_limits(&self) -> InputLimits {
+        self.input_limits
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn check_proof_data(&self, proof_data: &[u8]) -> Result<(), OrderbookError> {
+        Self::check_input_len("proof_data", proof_data.len(), self.input_limits.max_proof_data_len)
+    }
+
+    pub(crate) fn check_path_len(&self, path: &str) -> Result<(), OrderbookError> {
+        Self::check_input_len("path", path.len(), self.input_limits.max_path_len)
+    }
+
+    /// Each `(name, value)` against `max_field_len`.
+    pub(crate) fn check_fields(&self, fields: &[(&str, &str)]) -> Result<(), OrderbookError> {
+        fields
+            .iter()
+            .try_for_each(|(name, value)| Self::check_input_len(name, value.len(), self.input_limits.max_field_len))
+    }
+
+    fn check_input_len(field: &str, len: usize, max: u32) -> Result<(), OrderbookError> {
+        if len > max as usize {
+            return Err(OrderbookError::InputTooLong { field: field.to_string(), len, max });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Single entry point for balance mutations. Every credit and debit goes
+//! through `internal_credit` / `internal_debit`, which log a NEP-297
+//! `balance_changed` event carrying the running balance, so an indexer can
+//! rebuild every user's ledger from logs alone.
+
+use crate::*;
+use near_sdk::json_types::I128;
+
+pub const EVENT_STANDARD: &str = "orderbook";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// Why a balance moved. `related_id` on the event is an intent id for the
+/// `Intent*` reasons, a withdrawal id for the `Withdrawal*` ones and a
+/// sub-intent id for the rest.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum BalanceChangeReason {
+    /// Owner credit through `deposit_for`.
+    Deposit,
+    /// External deposit proven through `verify_mpc_deposit`.
+    MpcDeposit,
+    /// Maker's source amount locked by `make_intent`.
+    IntentLocked,
+    /// Unfilled remainder returned on cancel, expiry or force-cancel.
+    IntentRefund,
+    /// A fill returned to the maker after its parent intent closed.
+    FillUnwound,
+    /// Taker's counter-asset locked by `take_intent`.
+    TakerEscrowLocked,
+    TakerEscrowRefund,
+    /// Taker's counter-asset handed to the maker.
+    TakerEscrowReleased,
+    /// Maker's batch proceeds released once the transition is proven.
+    EscrowReleased,
+    ProtocolFee,
+    ReferralFee,
+    /// Maker's share of the protocol fee on a batch fill.
+    MakerRebate,
+    /// Supply a batch's limits leave over, credited to its solver.
+    SolverSurplus,
+    Withdrawal,
+    /// Withdrawal returned after its signature failed.
+    WithdrawalRefund,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BalanceChangedEvent {
+    pub user: AccountId,
+    pub asset: String,
+    pub delta: I128,
+    pub reason: BalanceChangeReason,
+    pub related_id: Option<U64>,
+    pub balance: U128,
+}
+
+impl Orderbook {
+    pub(crate) fn internal_credit(
+        &mut self,
+        user: &AccountId,
+        asset: &str,
+        amount: u128,
+        reason: BalanceChangeReason,
+        related_id: Option<u64>,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        let mut bals = self.balances.get(user).unwrap_or_else(|| {
+            UnorderedMap::new(format!("b{}", user).as_bytes())
+        });
+        let balance = bals.get(&asset.to_string()).unwrap_or(0) + amount;
+        bals.insert(&asset.to_string(), &balance);
+        self.balances.insert(user, &bals);
+        Self::emit_balance_changed(user, asset, amount as i128, reason, related_id, balance);
+    }
+
+    /// `InsufficientBalance` unless `user` holds at least `need` of `asset`.
+    pub(crate) fn check_balance(&self, user: &AccountId, asset: &str, need: u128) -> Result<(), OrderbookError> {
+        let have = self
+            .balances
+            .get(user)
+            .and_then(|bals| bals.get(&asset.to_string()))
+            .unwrap_or(0);
+        if have < need {
+            return Err(OrderbookError::InsufficientBalance { asset: asset.to_string(), have, need });
+        }
+        Ok(())
+    }
+
+    /// Callers run `check_balance` first so they can fail with a typed
+    /// error; the checks here only guard the invariant.
+    pub(crate) fn internal_debit(
+        &mut self,
+        user: &AccountId,
+        asset: &str,
+        amount: u128,
+        reason: BalanceChangeReason,
+        related_id: Option<u64>,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        let mut bals = self.balances.get(user).expect("User not found");
+        let balance = bals
+            .get(&asset.to_string())
+            .unwrap_or(0)
+            .checked_sub(amount)
+            .expect("Insufficient balance");
+        bals.insert(&asset.to_string(), &balance);
+        self.balances.insert(user, &bals);
+        Self::emit_balance_changed(user, asset, -(amount as i128), reason, related_id, balance);
+    }
+
+    fn emit_balance_changed(
+        user: &AccountId,
+        asset: &str,
+        delta: i128,
+        reason: BalanceChangeReason,
+        related_id: Option<u64>,
+        balance: u128,
+    ) {
+        log_event(
+            "balance_changed",
+            BalanceChangedEvent {
+                user: user.clone(),
+                asset: asset.to_string(),
+                delta: I128(delta),
+                reason,
+                related_id: related_id.map(U64),
+                balance: U128(balance),
+            },
+        );
+    }
+}
+
+/// Log a NEP-297 event of this contract with a single `data` entry.
+pub(crate) fn log_event<T: Serialize>(event: &str, data: T) {
+    let event = near_sdk::serde_json::json!({
+        "standard": EVENT_STANDARD,
+        "version": EVENT_VERSION,
+        "event": event,
+        "data": [data],
+    });
+    env::log_str(&format!("EVENT_JSON:{}", event));
+}
@@ -1,30 +1,200 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
-use near_sdk::{env, near_bindgen, AccountId, NearToken, PanicOnDefault, Promise, Gas, PromiseError, ext_contract};
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, FunctionError, NearToken, PanicOnDefault, Promise, PromiseOrValue, Gas, PromiseError, ext_contract};
+use near_sdk::json_types::{U128, U64};
 use near_sdk::state::ContractState;
 use near_sdk::serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use hex;
+use std::collections::HashSet;
+
+/// Serde adapter for the `u64` ids of stored records: JSON carries them as
+/// `U64` strings, like every id argument, while borsh keeps the plain `u64`
+/// so no state migration is needed.
+pub(crate) mod u64_json {
+    use near_sdk::json_types::U64;
+    use near_sdk::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        U64(*id).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        U64::deserialize(deserializer).map(|id| id.0)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(id: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+            id.map(U64).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+            Option::<U64>::deserialize(deserializer).map(|id| id.map(|id| id.0))
+        }
+    }
+}
+
+/// Signature scheme requested from the MPC signer. Each scheme lives in its
+/// own MPC key domain.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SignatureScheme {
+    #[default]
+    Secp256k1,
+    Ed25519,
+}
+
+impl SignatureScheme {
+    /// Default scheme for transfers on `chain_type` (Solana uses Ed25519).
+    pub fn for_chain(chain_type: &ChainType) -> Self {
+        match chain_type {
+            ChainType::SOL => SignatureScheme::Ed25519,
+            ChainType::BTC | ChainType::ETH => SignatureScheme::Secp256k1,
+        }
+    }
+
+    fn domain_id(&self) -> u64 {
+        match self {
+            SignatureScheme::Secp256k1 => 0,
+            SignatureScheme::Ed25519 => 1,
+        }
+    }
+}
+
+/// Hex-encoded payload, tagged with how the signer should treat it.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SignPayload {
+    Ecdsa(String),
+    Eddsa(String),
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SignRequest {
-    pub payload: [u8; 32],
+    pub payload_v2: SignPayload,
     pub path: String,
-    pub key_version: u32,
+    pub domain_id: u64,
+}
+
+impl SignRequest {
+    pub fn new(payload: &[u8], path: String, scheme: SignatureScheme) -> Self {
+        let payload_v2 = match scheme {
+            SignatureScheme::Secp256k1 => SignPayload::Ecdsa(hex::encode(payload)),
+            SignatureScheme::Ed25519 => SignPayload::Eddsa(hex::encode(payload)),
+        };
+        SignRequest {
+            payload_v2,
+            path,
+            domain_id: scheme.domain_id(),
+        }
+    }
+}
+
+/// Which record an MPC sign request was issued for. Sub-intents and
+/// withdrawals have independent id spaces, so the callback needs both.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SignTarget {
+    SubIntent(#[serde(with = "u64_json")] u64),
+    Withdrawal(#[serde(with = "u64_json")] u64),
+}
+
+impl SignTarget {
+    pub fn id(&self) -> u64 {
+        match self {
+            SignTarget::SubIntent(id) | SignTarget::Withdrawal(id) => *id,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SignatureEvent {
-    pub sub_intent_id: u64,
+    pub sub_intent_id: U64,
+    pub target: SignTarget,
     pub chain_type: ChainType,
     pub payload: String, // Hex string
-    pub big_r: String,
-    pub s: String,
-    pub recovery_id: u8,
+    pub scheme: SignatureScheme,
+    /// Secp256k1 only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub big_r: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_id: Option<u8>,
+    /// Ed25519 only: 64-byte signature, hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Secp256k1 only: the key the signature recovers to, uncompressed hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
     pub transition_memo: String,
+    /// External-chain recipient of the signed transaction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Asset the signed transaction moves.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    /// Sub-intents only: the unsigned transition transaction from the match, hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unsigned_tx: Option<String>,
+    /// Batch-matched sub-intents only: the batch that created it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<U64>,
+}
+
+/// Why an `on_signed` callback did not yield a usable signature.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum SignFailureReason {
+    /// The MPC `sign` call failed or returned nothing.
+    PromiseFailed,
+    /// MPC answered, but the signature did not verify.
+    InvalidSignature,
+}
+
+/// Data of the `sub_intent_sign_failed` event.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SubIntentSignFailedEvent {
+    pub sub_intent_id: U64,
+    pub chain_type: ChainType,
+    pub reason: SignFailureReason,
+}
+
+/// Data of the `signed_after_cancel` event: MPC signed a sub-intent that
+/// had left `Verifying` (force-cancelled or slashed) while the call was in
+/// flight. The signature is dropped, so relayers must not broadcast it.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SignedAfterCancelEvent {
+    pub sub_intent_id: U64,
+    pub chain_type: ChainType,
+    /// Status when the signature arrived; `None` if the sub-intent is gone.
+    pub status: Option<SubIntentStatus>,
+}
+
+/// Data of the `withdrawal_sign_failed` event.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawalSignFailedEvent {
+    pub wd_id: U64,
+    pub user: AccountId,
+    pub asset: String,
+    pub amount: U128,
+    pub reason: SignFailureReason,
+}
+
+/// Signature kept on-chain so a relayer that missed the `EVENT_JSON` log
+/// can re-fetch it and rebroadcast.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StoredSignature {
+    pub target: SignTarget,
+    pub chain_type: ChainType,
+    pub payload: String, // Hex string
+    pub signature: SignResult,
 }
 
 #[ext_contract(ext_signer)]
@@ -32,6 +202,59 @@ pub trait MultiChainSigner {
     fn sign(&mut self, request: SignRequest) -> Promise;
 }
 
+/// What the light client's `verify_payment_proof` returns.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VerificationResult {
+    pub outcome: VerifyOutcome,
+    /// Height the proof claims the payment was included at.
+    pub block_height: u64,
+    pub finalized_height: u64,
+}
+
+/// The light client's verdict on a proof, with the reason it was refused.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VerifyOutcome {
+    Valid,
+    Invalid(VerifyError),
+}
+
+/// Mirrors the light client's `VerifyError`; only logged and reported here.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VerifyError {
+    DeserializeFailed,
+    InvalidAddress,
+    RecipientMismatch,
+    AssetMismatch,
+    UnregisteredToken,
+    AmountMismatch,
+    MemoMismatch,
+    TxHashMismatch,
+    InvalidSignature,
+    NotFinalized { proof_height: u64, finalized: u64 },
+    ProofTooOld { proof_height: u64, finalized: u64, max_age: u64 },
+    BelowCheckpoint { proof_height: u64, checkpoint: u64 },
+    EmptyInclusionProof,
+    InclusionProofInvalid,
+    ProverFailed,
+    UnknownBlock,
+    HeaderMismatch,
+    TransactionFailed,
+    ChainPaused,
+    AlreadyConsumed,
+}
+
+/// What the light client expects an ETH payment to move, from the asset
+/// registry's `token_contract`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum AssetKind {
+    Native,
+    Erc20 { contract: String },
+}
+
 #[ext_contract(ext_light_client)]
 pub trait LightClient {
     fn verify_payment_proof(
@@ -42,7 +265,21 @@ pub trait LightClient {
         expected_asset: String,
         expected_amount: U128,
         expected_memo: String,
-    ) -> bool;
+        expected_tx_hash: String,
+        expected_asset_kind: Option<AssetKind>,
+    ) -> PromiseOrValue<VerificationResult>;
+    fn consume_payment_proof(
+        &mut self,
+        chain_type: ChainType,
+        proof_data: Vec<u8>,
+        expected_recipient: String,
+        expected_asset: String,
+        expected_amount: U128,
+        expected_memo: String,
+        expected_tx_hash: String,
+        expected_asset_kind: Option<AssetKind>,
+        min_confirmations: u64,
+    ) -> PromiseOrValue<VerificationResult>;
     fn verify_transition_proof(
         &self,
         chain_type: ChainType,
@@ -52,7 +289,7 @@ pub trait LightClient {
         expected_amount: U128,
         expected_memo: String,
         expected_tx_hash: String,
-    ) -> bool;
+    ) -> PromiseOrValue<VerifyOutcome>;
 }
 
 #[ext_contract(ext_self)]
@@ -60,65 +297,223 @@ pub trait SelfContract {
     fn on_mpc_deposit_verified(
         &mut self,
         user: AccountId,
+        chain_type: ChainType,
         asset: String,
         amount: U128,
         recipient: String,
         memo: String,
+        tx_hash: String,
     );
     fn on_proof_verified(
         &mut self,
-        sub_intent_id: U128,
+        sub_intent_id: U64,
         payload: [u8; 32],
+        unsigned_tx: Option<Vec<u8>>,
         path: String,
         transition_chain_type: ChainType,
+        recipient: String,
+        transition_memo: String,
+        payment_chain_type: ChainType,
+        tx_hash: String,
     );
-    fn on_transition_verified(&mut self, sub_intent_id: U128, tx_hash: String);
-    fn on_signed(&mut self, id: u64, chain_type: ChainType, payload: [u8; 32]) -> String;
+    fn on_transition_verified(&mut self, sub_intent_id: U64, tx_hash: String);
+    fn on_signed(&mut self, target: SignTarget, chain_type: ChainType, payload: Vec<u8>, path: String) -> String;
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Intent {
+    #[serde(with = "u64_json")]
     pub id: u64,
     pub maker: AccountId,
     pub src_asset: String,
     pub src_amount: u128,
+    /// Amount taken by sub-intents, including fills whose signature is still
+    /// pending or failed; only unwinding a sub-intent gives it back.
     pub filled_amount: u128,
     pub dst_asset: String,
     pub dst_amount: u128,
     pub status: IntentStatus,
+    /// Block timestamp (ns) at which the intent stops being fillable. `None` = no expiry.
+    pub expires_at: Option<u64>,
+    /// Smallest fill accepted, except one that takes the whole remainder.
+    pub min_fill_amount: u128,
+    /// Only a single fill of the full remaining amount is accepted.
+    pub all_or_nothing: bool,
+    /// Block timestamp (ns) the intent was cancelled or expired.
+    pub closed_at: Option<u64>,
+    /// Only these accounts may take the intent or match it as solver.
+    /// `None` leaves it open to everyone.
+    pub allowed_takers: Option<Vec<AccountId>>,
+    /// Front-end credited with the intent; earns `referral_bps` of the
+    /// protocol fee on each of its fills.
+    pub referrer: Option<AccountId>,
+    /// Idempotency key the maker created the intent with.
+    pub client_id: Option<String>,
+    /// Intent this one replaced through `replace_intent`.
+    #[serde(with = "u64_json::option")]
+    pub replaced_from: Option<u64>,
+}
+
+impl Intent {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| now >= t)
+    }
+
+    /// `dst_asset` owed for `amount` of `src_asset` at the intent's limit
+    /// price. Rounded up so fractional units never go against the maker.
+    pub fn dst_for(&self, amount: u128) -> u128 {
+        amount
+            .checked_mul(self.dst_amount)
+            .expect("amount overflow")
+            .div_ceil(self.src_amount)
+    }
+
+    /// Panics unless a fill of `amount` respects the maker's fill flags.
+    pub fn assert_fill_allowed(&self, amount: u128) {
+        if let Err(error) = self.check_fill_allowed(amount) {
+            error.panic();
+        }
+    }
+
+    pub fn assert_taker_allowed(&self, taker: &AccountId) {
+        if let Err(error) = self.check_taker_allowed(taker) {
+            error.panic();
+        }
+    }
+
+    pub fn check_taker_allowed(&self, taker: &AccountId) -> Result<(), OrderbookError> {
+        match &self.allowed_takers {
+            Some(allowed) if !allowed.contains(taker) => Err(OrderbookError::TakerNotAllowed {
+                taker: taker.clone(),
+                intent_id: self.id,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_fill_allowed(&self, amount: u128) -> Result<(), OrderbookError> {
+        let remaining = self.src_amount - self.filled_amount;
+        if self.all_or_nothing && amount != remaining {
+            return Err(OrderbookError::AllOrNothing { intent_id: self.id, required: remaining });
+        }
+        if amount < self.min_fill_amount && amount != remaining {
+            return Err(OrderbookError::BelowMinFill {
+                intent_id: self.id,
+                amount,
+                min: self.min_fill_amount,
+            });
+        }
+        Ok(())
+    }
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SubIntent {
+    #[serde(with = "u64_json")]
     pub id: u64,
+    #[serde(with = "u64_json")]
     pub parent_intent_id: u64,
     pub taker: AccountId,
     pub amount: u128,
-    pub status: IntentStatus,
+    pub status: SubIntentStatus,
+    /// Parent's `dst_asset` the taker locked in `take_intent`; paid to the
+    /// maker when the transition completes. Zero for batch-matched fills.
+    pub escrow: u128,
+    /// Block timestamp (ns) after which a take still in `Taken` can be
+    /// expired. `None` for batch-matched fills.
+    pub expires_at: Option<u64>,
+    /// NEAR (yocto) the solver posted in `batch_match_intents`. Returned once
+    /// the transition is proven, slashed to the maker if it never is.
+    pub bond: u128,
+    /// Block timestamp (ns) the sub-intent reached a final status.
+    pub closed_at: Option<u64>,
+    /// External transaction that proved the transition, once `Completed`.
+    pub transition_tx_hash: Option<String>,
+    /// Batch match that created it; `None` for `take_intent` fills.
+    #[serde(with = "u64_json::option")]
+    pub batch_id: Option<u64>,
 }
 
+/// Lifecycle of an intent: open until it is filled or closed.
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub enum IntentStatus {
     Open,
+    /// Fully matched; its sub-intents carry on settling.
     Filled,
+    Cancelled,
+    Expired,
+    /// Closed by the owner through `force_cancel_intent`.
+    ForceCancelled,
+}
+
+impl IntentStatus {
+    /// No further transitions are possible from this status.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            IntentStatus::Cancelled | IntentStatus::Expired | IntentStatus::ForceCancelled
+        )
+    }
+}
+
+/// Lifecycle of a single fill, from the take (or batch match) through
+/// signing to the proven transition.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SubIntentStatus {
     Taken,
+    /// Payment proof or MPC signature in flight.
     Verifying,
+    /// Signed; waiting for the transition to be proven.
     Settled,
     TransitionVerifying,
     Completed,
+    /// Signed, but the transition was not proven before its deadline.
+    Failed,
+    Cancelled,
+    /// Only ever counted: expired takes are deleted.
+    Expired,
+    /// Closed by the owner through `force_cancel_intent` / `force_cancel_sub_intent`.
+    ForceCancelled,
+    /// Matched by `batch_match_intents_deferred`; its sign request waits in
+    /// the queue. Last so earlier variants keep their encoding.
+    AwaitingSign,
+}
+
+impl SubIntentStatus {
+    /// No further transitions are possible from this status.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            SubIntentStatus::Completed
+                | SubIntentStatus::Cancelled
+                | SubIntentStatus::Expired
+                | SubIntentStatus::Failed
+                | SubIntentStatus::ForceCancelled
+        )
+    }
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub struct TransitionExpectation {
+    #[serde(with = "u64_json")]
     pub sub_intent_id: u64,
     pub chain_type: ChainType,
     pub expected_asset: String,
     pub expected_amount: u128,
     pub expected_memo: String,
+    /// External address the transition must pay; this, not the prover's
+    /// word, is what the light client checks.
+    pub expected_recipient: String,
+    /// Block timestamp (ns) by which the transition must be proven. Set when
+    /// MPC returns the signature; `None` while signing is still pending.
+    pub transition_deadline: Option<u64>,
+    /// Unsigned transition transaction the solver submitted with the match.
+    pub unsigned_tx: Option<Vec<u8>>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
@@ -129,6 +524,18 @@ pub enum ChainType {
     SOL,
 }
 
+impl ChainType {
+    /// Symbol of the chain's native coin, the asset its transaction fees
+    /// are charged in.
+    pub fn native_asset(&self) -> &'static str {
+        match self {
+            ChainType::BTC => "BTC",
+            ChainType::ETH => "ETH",
+            ChainType::SOL => "SOL",
+        }
+    }
+}
+
 /// Tracks a pending withdrawal so we can refund on MPC sign failure.
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
@@ -136,12 +543,228 @@ pub struct PendingWithdrawal {
     pub user: AccountId,
     pub asset: String,
     pub amount: u128,
+    /// Fee debited on top of `amount` in the chain's native coin: the gas of
+    /// an ETH transaction, a BTC transaction's fee, a SOL signature fee.
+    pub gas_fee: u128,
+    /// External-chain recipient the signed transaction pays out to.
+    pub destination: String,
+    pub chain_type: ChainType,
+    /// Nonce of the user's withdrawal address on ETH; 0 on other chains.
+    pub nonce: u64,
+    /// Hex of the payload sent to MPC, computed by the contract: a hash, or
+    /// the message itself for SOL.
+    pub payload: String,
+    /// Block timestamp (ns) of the request.
+    pub requested_at: u64,
+}
+
+/// Sign request of a deferred batch match, waiting for `process_sign_queue`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SignJob {
+    pub payload: [u8; 32],
+    pub path: String,
+    pub chain_type: ChainType,
+    pub scheme: SignatureScheme,
+    /// Share of the solver's attached deposit this request forwards.
+    pub deposit: u128,
+}
+
+/// What a sub-intent's transition is signed as, fixed when it is matched or
+/// proven. `retry_settlement` signs exactly this again.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SettlementRequest {
+    pub payload: [u8; 32],
+    pub path: String,
+    pub chain_type: ChainType,
+    pub scheme: SignatureScheme,
+    pub recipient: String,
+    /// Unsigned transition transaction `payload` is the hash of. Always
+    /// set for ETH; other chains' solvers may submit one.
+    #[serde(default)]
+    pub unsigned_tx: Option<Vec<u8>>,
+}
+
+impl SettlementRequest {
+    /// Payload of a request the V0 migration backfilled, until the maker
+    /// approves a transaction for it.
+    pub const UNSET_PAYLOAD: [u8; 32] = [0; 32];
+}
+
+/// One withdrawal of a `batch_withdraw`; the fields are those of `withdraw`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawItem {
+    pub asset: String,
+    pub amount: U128,
+    pub destination: String,
+    pub unsigned_tx: String,
+    pub path: String,
+    pub chain_type: ChainType,
+    #[serde(default)]
+    pub scheme: SignatureScheme,
+}
+
+/// One intent of a `make_intents`; the fields are those of `make_intent`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentSpec {
+    pub src_asset: String,
+    pub src_amount: U128,
+    pub dst_asset: String,
+    pub dst_amount: U128,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub min_fill_amount: Option<U128>,
+    #[serde(default)]
+    pub all_or_nothing: Option<bool>,
+    #[serde(default)]
+    pub allowed_takers: Option<Vec<AccountId>>,
+    #[serde(default)]
+    pub referrer: Option<AccountId>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+}
+
+/// Data of the `intent_created` event.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentCreatedEvent {
+    pub id: U64,
+    pub maker: AccountId,
+    pub src_asset: String,
+    pub src_amount: U128,
+    pub dst_asset: String,
+    pub dst_amount: U128,
+}
+
+/// One fill of an intent, kept after its sub-intent settles or is removed.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FillRecord {
+    #[serde(with = "u64_json")]
+    pub sub_intent_id: u64,
+    pub taker: AccountId,
+    /// `src_asset` taken from the intent.
+    pub fill_amount: u128,
+    /// `dst_asset` owed to the maker for it.
+    pub get_amount: u128,
+    pub timestamp: u64,
+}
+
+/// Maker proceeds from a batch match, held until the sub-intent's
+/// transition proof is verified.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowEntry {
+    pub maker: AccountId,
+    pub asset: String,
+    pub amount: u128,
+    /// Protocol fee withheld from the proceeds, paid to the fee collector on release.
+    pub fee: u128,
+    /// Intent referrer, if any, and its share of `fee`.
+    pub referrer: Option<AccountId>,
+    pub referral_fee: u128,
+    /// Part of `fee` returned to the maker on release.
+    pub maker_rebate: u128,
+    /// Block timestamp (ns) after which `claim_escrow_timeout` may unwind the fill.
+    pub claimable_after: u64,
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+/// For a light-client verification: enough for the light client to hand
+/// an ETH proof to its prover contract and read the answer.
+pub const LIGHT_CLIENT_GAS: Gas = Gas::from_tgas(100);
+
+/// Default time a matched fill has to complete its transition (24h).
+pub const DEFAULT_ESCROW_TIMEOUT_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Default time a taker has to submit a take before it can be expired (1h).
+pub const DEFAULT_SUB_INTENT_TIMEOUT_NS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Default time a signed transition has to be proven before the solver's
+/// bond can be slashed (2h).
+pub const DEFAULT_TRANSITION_TIMEOUT_NS: u64 = 2 * 60 * 60 * 1_000_000_000;
+
+/// Default bond per sub-intent a solver attaches to `batch_match_intents` (0.1 NEAR).
+pub const DEFAULT_TRANSITION_BOND: u128 = 100_000_000_000_000_000_000_000;
+
+/// Default signing deposit per sub-intent `batch_match_intents` requires on
+/// top of the bond (1 yoctoNEAR, the least the MPC signer accepts).
+pub const DEFAULT_MIN_SIGN_DEPOSIT: u128 = 1;
+
+/// Default minimum NEAR a solver locks in `register_solver` (5 NEAR).
+pub const DEFAULT_MIN_SOLVER_BOND: u128 = 5_000_000_000_000_000_000_000_000;
+
+/// Default number of `Open` intents one maker may have at a time.
+pub const DEFAULT_MAX_OPEN_INTENTS_PER_ACCOUNT: u64 = 100;
+
+/// Highest protocol fee the owner can set, in basis points (1%).
+pub const MAX_FEE_BPS: u16 = 100;
+
+/// Most accounts an intent's `allowed_takers` may list.
+pub const MAX_ALLOWED_TAKERS: usize = 16;
+
+/// Longest asset symbol accepted by `make_intent`, in bytes.
+pub const MAX_ASSET_LEN: usize = 32;
+
+/// Longest `client_id` accepted by `make_intent`, in bytes.
+pub const MAX_CLIENT_ID_LEN: usize = 64;
+
+/// Most matches `batch_match_intents` signs in one call (gas limit).
+pub const MAX_BATCH_MATCHES: usize = 6;
+
+/// Most matches `batch_match_intents_deferred` accepts; their sign requests
+/// are sent later, by `process_sign_queue`.
+pub const MAX_DEFERRED_BATCH_MATCHES: usize = 20;
+
+/// Most queued sign requests one `process_sign_queue` call sends; each
+/// costs about 65 Tgas with its callback and action fees.
+pub const MAX_QUEUED_SIGNS_PER_CALL: u64 = 4;
+
+/// Most counter-intents one `match_best` call fills; with the intent's own
+/// leg the batch stays within `MAX_DEFERRED_BATCH_MATCHES`.
+pub const MAX_MATCH_BEST_COUNTERPARTIES: u32 = 8;
+
+/// Resting intents `match_best` reads from the opposite side of the book.
+/// Each costs one storage read, so the scan stays at a few Tgas however
+/// deep the book is. The book is read best price first, so only intents
+/// priced behind the cap are not considered.
+pub const MAX_MATCH_BEST_SCAN: usize = 32;
+
+/// Largest `unsigned_tx` a match may carry.
+pub const MAX_TRANSITION_TX_LEN: usize = 2048;
+
+/// Most intents one `make_intents` call creates.
+pub const MAX_BATCH_INTENTS: usize = 10;
+
+/// Most withdrawals one `batch_withdraw` may sign (gas limit).
+pub const MAX_BATCH_WITHDRAW_ITEMS: usize = 4;
+
+/// ETH chain id withdrawals are signed for until the owner changes it (Sepolia).
+pub const DEFAULT_ETH_CHAIN_ID: u64 = 11_155_111;
+
+/// Most wei a withdrawal's `gas_limit * max_fee_per_gas` may reach until the
+/// owner changes it (0.01 ETH).
+pub const DEFAULT_MAX_WITHDRAWAL_GAS_FEE: u128 = 10_000_000_000_000_000;
+
+/// Layout version of the `Orderbook` state this code reads and writes.
+/// Bump it, and add a variant to `VersionedOrderbook`, whenever a field of
+/// a released layout changes.
+pub const STATE_VERSION: u8 = 1;
+
+/// Default time a final intent or sub-intent is kept before `cleanup` may
+/// remove it (7 days).
+pub const DEFAULT_RECORD_RETENTION_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Shortest timelock between `stage_upgrade` and `apply_upgrade` (~1h).
+pub const MIN_UPGRADE_DELAY_BLOCKS: u64 = 3_600;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct MatchParams {
-    pub intent_id: U128,
+    pub intent_id: U64,
     pub fill_amount: U128,
     pub get_amount: U128,
     /// Hash of the external-chain transaction to be MPC-signed.
@@ -150,20 +773,176 @@ pub struct MatchParams {
     pub path: String,
     /// Which chain the transition (outbound transfer) targets.
     pub transition_chain_type: ChainType,
+    /// Signature scheme for the transition transaction.
+    #[serde(default)]
+    pub scheme: SignatureScheme,
+    /// Maker's address on `transition_chain_type` the transition pays out to.
+    /// Must be the maker's registered receive address there.
+    pub recipient: String,
+    /// Asset the transition moves; must be the intent's `src_asset`.
+    pub transition_asset: String,
+    /// The unsigned transition transaction, at most `MAX_TRANSITION_TX_LEN`
+    /// bytes, so the relayer can broadcast it without keeping its own copy.
+    /// On ETH, `payload` must be its keccak256 hash.
+    #[serde(default)]
+    pub unsigned_tx: Option<Vec<u8>>,
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Orderbook {
     pub owner: AccountId,
+    /// Account proposed by the owner, until it calls `accept_ownership`.
+    pub pending_owner: Option<AccountId>,
+    /// Default MPC signer, used for chains without an entry in `chain_signers`.
     pub mpc_contract: AccountId,
+    /// Per-chain MPC signer overrides.
+    pub chain_signers: UnorderedMap<ChainType, AccountId>,
+    /// The only derivation path transitions on each chain may be signed under.
+    pub treasury_paths: UnorderedMap<ChainType, String>,
     pub light_client_contract: AccountId,
+    /// Blocks a proven deposit must sit below the light client's finalized
+    /// height before it is credited; chains without an entry need none.
+    pub min_confirmations: UnorderedMap<ChainType, u64>,
+    /// Delay (blocks) before owner config changes take effect, see `config.rs`.
+    pub config_delay_blocks: u64,
+    pub pending_config_changes: Vec<PendingConfigChange>,
+    pub staged_upgrade: Option<StagedUpgrade>,
+    /// Chain id that ETH withdrawal transactions must commit to.
+    pub eth_chain_id: u64,
+    /// Cap on the gas fee (wei) of an ETH withdrawal transaction.
+    pub max_withdrawal_gas_fee: u128,
+    /// Fee on batch-matched maker proceeds, in basis points.
+    pub fee_bps: u16,
+    /// Account whose internal balance receives protocol fees.
+    pub fee_collector: AccountId,
+    /// Protocol fees credited to the fee collector so far, per asset.
+    pub accrued_fees: UnorderedMap<String, u128>,
+    /// Share of the protocol fee paid to an intent's referrer, in basis points.
+    pub referral_bps: u16,
+    /// Referral fees credited so far, per referrer and asset.
+    pub referral_earnings: UnorderedMap<AccountId, Vec<(String, u128)>>,
+    /// Part of `fee_bps` paid back to makers, see `rebate.rs`.
+    pub maker_rebate_bps: u16,
+    /// Maker rebates credited so far, per asset.
+    pub maker_rebates: UnorderedMap<String, u128>,
     pub balances: UnorderedMap<AccountId, UnorderedMap<String, u128>>,
     pub intents: UnorderedMap<u64, Intent>,
+    /// Ids of intents currently in `Open` status (may include expired ones).
+    pub open_intent_ids: UnorderedSet<u64>,
+    /// Open intents per market direction, keyed by `book_key`, best price
+    /// first.
+    pub open_intents_by_pair: LookupMap<String, TreeMap<BookEntry, ()>>,
+    /// Intent ids per maker, in creation order.
+    pub intents_by_maker: UnorderedMap<AccountId, Vector<u64>>,
+    /// Number of `Open` intents per maker, kept in step with `open_intent_ids`.
+    pub open_intent_counts: UnorderedMap<AccountId, u64>,
+    /// Open intent per `(maker, client_id)`, so a retried `make_intent`
+    /// returns the intent it already created.
+    pub client_ids: LookupMap<(AccountId, String), u64>,
+    pub max_open_intents_per_account: u64,
     pub sub_intents: UnorderedMap<u64, SubIntent>,
     pub transition_expectations: UnorderedMap<u64, TransitionExpectation>,
     pub pending_withdrawals: UnorderedMap<u64, PendingWithdrawal>,
-    pub next_id: u64,
+    /// Pending withdrawal ids per user.
+    pub pending_withdrawals_by_user: UnorderedMap<AccountId, UnorderedSet<u64>>,
+    pub escrowed_credits: UnorderedMap<u64, EscrowEntry>,
+    /// Sub-intent ids per parent intent, in creation order. Ids stay after
+    /// their record is deleted; readers skip them.
+    pub sub_intents_by_parent: UnorderedMap<u64, Vector<u64>>,
+    /// Sub-intent ids per taker, in creation order, kept the same way.
+    pub sub_intents_by_taker: UnorderedMap<AccountId, Vector<u64>>,
+    /// Fill history per intent, in fill order.
+    pub intent_fills: UnorderedMap<u64, Vector<FillRecord>>,
+    pub stats: Stats,
+    /// Matched source volume per asset, see `get_asset_volume`.
+    pub asset_volume: UnorderedMap<String, u128>,
+    /// Per asset, everything deposits have credited; see `check_solvency`.
+    pub total_credited: UnorderedMap<String, u128>,
+    /// Per asset, everything signed withdrawals have paid out.
+    pub total_debited: UnorderedMap<String, u128>,
+    /// Outcome of records removed by `cleanup`.
+    pub tombstones: UnorderedMap<(RecordKind, u64), Tombstone>,
+    pub record_retention_ns: u64,
+    pub escrow_timeout_ns: u64,
+    /// Lifetime of a take before `expire_sub_intent` may unwind it.
+    pub sub_intent_timeout_ns: u64,
+    /// Time a signed transition has before `claim_transition_timeout` applies.
+    pub transition_timeout_ns: u64,
+    /// Bond (yocto NEAR) held per batch-matched sub-intent.
+    pub transition_bond: u128,
+    /// Deposit per sub-intent `batch_match_intents` must forward to the signer.
+    pub min_sign_deposit: u128,
+    /// Last MPC signature per sign target, until pruned.
+    pub signatures: UnorderedMap<SignTarget, StoredSignature>,
+    /// Queued sign requests of deferred batch matches, by sub-intent id.
+    pub pending_signs: UnorderedMap<u64, SignJob>,
+    /// Transition sign parameters of each sub-intent, by sub-intent id.
+    pub settlement_requests: UnorderedMap<u64, SettlementRequest>,
+    /// MPC root public key (`x || y`) deposit addresses are derived from, per chain.
+    pub mpc_root_keys: LookupMap<ChainType, [u8; 64]>,
+    /// When set, user-facing mutating methods are refused.
+    pub paused: bool,
+    /// Markets halted by the owner, keyed by `pair_key`.
+    pub paused_pairs: UnorderedSet<String>,
+    /// Most volume of an asset that may be matched per volume window.
+    pub volume_caps: UnorderedMap<String, u128>,
+    /// Volume matched per asset in its latest window.
+    pub matched_volume: UnorderedMap<String, VolumeWindow>,
+    pub volume_window_blocks: u64,
+    /// Most each user may withdraw per asset and window.
+    pub withdrawal_limits: UnorderedMap<String, u128>,
+    /// Withdrawals counted against each user's limit, per asset.
+    pub withdrawal_usage: LookupMap<(AccountId, String), WindowUsage>,
+    /// Accounts the owner has barred from depositing, trading and withdrawing.
+    pub blocked_accounts: UnorderedSet<AccountId>,
+    /// Per-account storage staking, see `storage.rs`.
+    pub storage_accounts: UnorderedMap<AccountId, StorageAccount>,
+    /// External `(chain, tx_hash)` pairs already credited by `verify_mpc_deposit`.
+    pub consumed_deposits: UnorderedSet<(ChainType, String)>,
+    /// External `(chain, tx_hash)` pairs that already proved a transition.
+    pub consumed_transitions: UnorderedSet<(ChainType, String)>,
+    /// External `(chain, tx_hash)` pairs already accepted as a taker payment.
+    pub consumed_payments: UnorderedSet<(ChainType, String)>,
+    /// Registered assets by upper-cased symbol, see `assets.rs`.
+    pub assets: UnorderedMap<String, AssetInfo>,
+    /// MPC deposit address per user and chain, set by the owner.
+    pub deposit_addresses: UnorderedMap<(AccountId, ChainType), String>,
+    /// Credited deposits by id and each user's deposit ids, see `deposits.rs`.
+    pub deposits: LookupMap<u64, DepositRecord>,
+    pub deposits_by_user: LookupMap<AccountId, Vector<u64>>,
+    /// Every withdrawal by id, kept after it is signed or refunded, and each
+    /// user's withdrawal ids; see `withdrawals.rs`.
+    pub withdrawals: LookupMap<u64, WithdrawalRecord>,
+    pub withdrawals_by_user: LookupMap<AccountId, Vector<u64>>,
+    /// Where each maker receives transition payouts, per chain.
+    pub receive_addresses: UnorderedMap<(AccountId, ChainType), String>,
+    /// Per-asset surplus the most recent batch left for its solver.
+    pub last_batch_surplus: Vec<(String, u128)>,
+    /// Registered solvers and their bonds (yocto NEAR), see `solver.rs`.
+    pub solvers: UnorderedMap<AccountId, u128>,
+    pub min_solver_bond: u128,
+    /// When set, only registered solvers may match or retry settlements.
+    pub solver_allowlist_enabled: bool,
+    pub next_intent_id: u64,
+    pub next_sub_intent_id: u64,
+    pub next_withdrawal_id: u64,
+    pub next_deposit_id: u64,
+    /// Nonce for the next call that signs transitions, see `memo.rs`.
+    pub next_transition_nonce: u64,
+    /// Caps on argument sizes, see `input_limits.rs`.
+    pub input_limits: InputLimits,
+    /// Executed batch matches, see `batches.rs`.
+    pub next_batch_id: u64,
+    pub batches: LookupMap<u64, BatchRecord>,
+    pub batches_by_solver: LookupMap<AccountId, Vector<u64>>,
+    /// Batch surplus awaiting delivery of the batch, see `batches.rs`.
+    pub held_surplus: UnorderedMap<u64, HeldSurplus>,
+    /// Repriced transition transactions solvers proposed, by sub-intent id,
+    /// until the maker or the owner approves one.
+    pub settlement_proposals: LookupMap<u64, Vec<u8>>,
+    /// Nonce the next ETH withdrawal of each user must carry.
+    pub withdrawal_nonces: LookupMap<AccountId, u64>,
 }
 
 impl ContractState for Orderbook {}
@@ -172,16 +951,93 @@ impl ContractState for Orderbook {}
 impl Orderbook {
     #[init]
     pub fn new(mpc_contract: AccountId, light_client_contract: AccountId) -> Self {
+        migration::write_state_version();
         Self {
             owner: env::predecessor_account_id(),
+            pending_owner: None,
             mpc_contract,
+            chain_signers: UnorderedMap::new(b"c"),
+            treasury_paths: UnorderedMap::new(b"T"),
+            min_confirmations: UnorderedMap::new(b"H"),
             light_client_contract,
+            config_delay_blocks: 0,
+            pending_config_changes: Vec::new(),
+            staged_upgrade: None,
+            eth_chain_id: DEFAULT_ETH_CHAIN_ID,
+            max_withdrawal_gas_fee: DEFAULT_MAX_WITHDRAWAL_GAS_FEE,
+            fee_bps: 0,
+            fee_collector: env::predecessor_account_id(),
+            accrued_fees: UnorderedMap::new(b"f"),
+            referral_bps: 0,
+            referral_earnings: UnorderedMap::new(b"R"),
+            maker_rebate_bps: 0,
+            maker_rebates: UnorderedMap::new(b"L"),
             balances: UnorderedMap::new(b"b"),
             intents: UnorderedMap::new(b"i"),
+            open_intent_ids: UnorderedSet::new(b"o"),
+            open_intents_by_pair: LookupMap::new(b"A"),
+            intents_by_maker: UnorderedMap::new(b"m"),
+            open_intent_counts: UnorderedMap::new(b"j"),
+            client_ids: LookupMap::new(b"G"),
+            max_open_intents_per_account: DEFAULT_MAX_OPEN_INTENTS_PER_ACCOUNT,
             sub_intents: UnorderedMap::new(b"s"),
             transition_expectations: UnorderedMap::new(b"x"),
             pending_withdrawals: UnorderedMap::new(b"w"),
-            next_id: 0,
+            pending_withdrawals_by_user: UnorderedMap::new(b"u"),
+            escrowed_credits: UnorderedMap::new(b"e"),
+            sub_intents_by_parent: UnorderedMap::new(b"p"),
+            sub_intents_by_taker: UnorderedMap::new(b"t"),
+            intent_fills: UnorderedMap::new(b"h"),
+            stats: Stats::default(),
+            asset_volume: UnorderedMap::new(b"k"),
+            total_credited: UnorderedMap::new(b"C"),
+            total_debited: UnorderedMap::new(b"D"),
+            tombstones: UnorderedMap::new(b"l"),
+            record_retention_ns: DEFAULT_RECORD_RETENTION_NS,
+            escrow_timeout_ns: DEFAULT_ESCROW_TIMEOUT_NS,
+            sub_intent_timeout_ns: DEFAULT_SUB_INTENT_TIMEOUT_NS,
+            transition_timeout_ns: DEFAULT_TRANSITION_TIMEOUT_NS,
+            transition_bond: DEFAULT_TRANSITION_BOND,
+            min_sign_deposit: DEFAULT_MIN_SIGN_DEPOSIT,
+            signatures: UnorderedMap::new(b"g"),
+            pending_signs: UnorderedMap::new(b"Q"),
+            settlement_requests: UnorderedMap::new(b"U"),
+            mpc_root_keys: LookupMap::new(b"K"),
+            paused: false,
+            paused_pairs: UnorderedSet::new(b"q"),
+            volume_caps: UnorderedMap::new(b"V"),
+            matched_volume: UnorderedMap::new(b"Y"),
+            volume_window_blocks: DEFAULT_VOLUME_WINDOW_BLOCKS,
+            withdrawal_limits: UnorderedMap::new(b"W"),
+            withdrawal_usage: LookupMap::new(b"X"),
+            blocked_accounts: UnorderedSet::new(b"B"),
+            storage_accounts: UnorderedMap::new(b"r"),
+            consumed_deposits: UnorderedSet::new(b"d"),
+            consumed_transitions: UnorderedSet::new(b"z"),
+            consumed_payments: UnorderedSet::new(b"P"),
+            assets: UnorderedMap::new(b"n"),
+            deposit_addresses: UnorderedMap::new(b"a"),
+            deposits: LookupMap::new(b"E"),
+            deposits_by_user: LookupMap::new(b"F"),
+            withdrawals: LookupMap::new(b"I"),
+            withdrawals_by_user: LookupMap::new(b"J"),
+            receive_addresses: UnorderedMap::new(b"y"),
+            last_batch_surplus: Vec::new(),
+            solvers: UnorderedMap::new(b"v"),
+            min_solver_bond: DEFAULT_MIN_SOLVER_BOND,
+            solver_allowlist_enabled: false,
+            next_intent_id: 0,
+            next_sub_intent_id: 0,
+            next_withdrawal_id: 0,
+            next_deposit_id: 0,
+            next_transition_nonce: 0,
+            input_limits: InputLimits::default(),
+            next_batch_id: 0,
+            batches: LookupMap::new(b"M"),
+            batches_by_solver: LookupMap::new(b"N"),
+            held_surplus: UnorderedMap::new(b"S"),
+            settlement_proposals: LookupMap::new(b"Z"),
+            withdrawal_nonces: LookupMap::new(b"0"),
         }
     }
 
@@ -197,14 +1053,15 @@ impl Orderbook {
             self.owner,
             "Only owner can call deposit_for"
         );
+        let initial_usage = env::storage_usage();
         let amount: u128 = amount.into();
-        let mut user_balances = self.balances.get(&user).unwrap_or_else(|| {
-            UnorderedMap::new(format!("b{}", user).as_bytes())
-        });
-        let current = user_balances.get(&asset).unwrap_or(0);
-        user_balances.insert(&asset, &(current + amount));
-        self.balances.insert(&user, &user_balances);
-        env::log_str(&format!("Deposited {} {} for {}", amount, asset, user));
+        let (asset, info) = self.resolve_asset(&asset);
+        self.internal_credit(&user, &asset, amount, BalanceChangeReason::Deposit, None);
+        self.stats.total_deposits += 1;
+        self.record_credit(&asset, amount);
+        let deposit_id = self.record_deposit(&user, DepositSource::Admin, info.chain_type, &asset, amount, None, None);
+        self.settle_storage(&user, initial_usage);
+        env::log_str(&format!("Deposited {} {} for {} (deposit #{})", amount, asset, user, deposit_id));
     }
 
     /// Verify an external-chain deposit to MPC address via light client, then credit balance.
@@ -218,114 +1075,1262 @@ impl Orderbook {
         recipient: String,
         memo: String,
         proof_data: Vec<u8>,
+        tx_hash: String,
     ) -> Promise {
+        self.assert_not_paused();
+        self.assert_not_blocked(&user);
+        // Checked before paying for the light-client call.
+        assert!(!proof_data.is_empty(), "Proof data is empty");
+        if let Err(error) = self
+            .check_proof_data(&proof_data)
+            .and_then(|_| self.check_fields(&[("recipient", &recipient), ("memo", &memo), ("tx_hash", &tx_hash)]))
+        {
+            error.panic();
+        }
+        let (asset, info) = self.resolve_asset(&asset);
+        assert_eq!(info.chain_type, chain_type, "Asset {} is not on {:?}", asset, chain_type);
+        assert!(
+            amount.0 >= info.min_deposit,
+            "Deposit below minimum {} {}",
+            info.min_deposit,
+            asset
+        );
         let expected_memo = format!("mpc:deposit:{}:{}", user, asset);
         assert_eq!(memo, expected_memo, "memo mismatch");
-
+        let deposit_address = self
+            .deposit_addresses
+            .get(&(user.clone(), chain_type.clone()))
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("No deposit address registered for {} on {:?}", user, chain_type))
+            });
+        assert_eq!(recipient, deposit_address, "Recipient does not match registered deposit address");
+        self.assert_deposit_not_consumed(&chain_type, &tx_hash);
+
+        // The light client ties the proof to `tx_hash`, which is the hash
+        // we record as consumed, and reports the heights the confirmation
+        // check needs. It also spends the proof on its side once it has
+        // the confirmations, so it cannot be replayed elsewhere.
+        let min_confirmations = self.get_min_confirmations(chain_type.clone());
+        let expected_asset_kind = self.expected_asset_kind(&asset);
         ext_light_client::ext(self.light_client_contract.clone())
-            .with_static_gas(Gas::from_tgas(50))
-            .verify_payment_proof(
-                chain_type,
+            .with_static_gas(LIGHT_CLIENT_GAS)
+            .consume_payment_proof(
+                chain_type.clone(),
                 proof_data,
                 recipient.clone(),
                 asset.clone(),
                 amount,
                 memo.clone(),
+                tx_hash.clone(),
+                expected_asset_kind,
+                min_confirmations,
             )
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_static_gas(Gas::from_tgas(30))
-                    .on_mpc_deposit_verified(user, asset, amount, recipient, memo),
+                    .on_mpc_deposit_verified(user, chain_type, asset, amount, recipient, memo, tx_hash),
             )
     }
 
+    /// Credits a proven deposit. A rejected one returns `credited: false`
+    /// with the reason and logs `deposit_rejected` instead of panicking, so
+    /// the user sees why and no state is touched.
     #[private]
     pub fn on_mpc_deposit_verified(
         &mut self,
         user: AccountId,
+        chain_type: ChainType,
         asset: String,
         amount: U128,
         recipient: String,
         memo: String,
-        #[callback_result] verify_result: Result<bool, PromiseError>,
-    ) -> String {
-        let is_valid = verify_result.unwrap_or(false);
-        if !is_valid {
-            env::panic_str("MPC deposit proof invalid");
+        tx_hash: String,
+        #[callback_result] verify_result: Result<VerificationResult, PromiseError>,
+    ) -> DepositOutcome {
+        let checked = verify_result
+            .map_err(|_| OrderbookError::DepositProofInvalid { reason: None })
+            .and_then(|verification| match verification.outcome {
+                VerifyOutcome::Valid => Ok(verification),
+                VerifyOutcome::Invalid(reason) => Err(OrderbookError::DepositProofInvalid { reason: Some(reason) }),
+            })
+            .and_then(|verification| self.check_confirmations(&chain_type, &verification))
+            // Re-checked here: two submissions of the same proof can both
+            // pass the check in `verify_mpc_deposit` before either callback runs.
+            .and_then(|_| self.check_deposit_not_consumed(&chain_type, &tx_hash));
+        if let Err(error) = checked {
+            return Self::reject_deposit(user, chain_type, asset, amount, tx_hash, error);
         }
-        self.internal_transfer(user.clone(), asset.clone(), amount.0);
+        self.consumed_deposits.insert(&(chain_type.clone(), tx_hash.clone()));
+        self.internal_credit(&user, &asset, amount.0, BalanceChangeReason::MpcDeposit, None);
+        self.stats.total_deposits += 1;
+        self.record_credit(&asset, amount.0);
+        let deposit_id = self.record_deposit(
+            &user,
+            DepositSource::Mpc,
+            chain_type,
+            &asset,
+            amount.0,
+            Some(tx_hash.clone()),
+            Some(recipient.clone()),
+        );
         env::log_str(&format!(
-            "MPC_DEPOSIT_VERIFIED:user={},asset={},amount={},recipient={},memo={}",
-            user, asset, amount.0, recipient, memo
+            "MPC_DEPOSIT_VERIFIED:deposit_id={},user={},asset={},amount={},recipient={},memo={},tx_hash={}",
+            deposit_id, user, asset, amount.0, recipient, memo, tx_hash
         ));
-        "MpcDepositCredited".to_string()
+        DepositOutcome { credited: true, reason: None }
     }
 
-    // ========================================================================
-    // 2. Make Intent
-    // ========================================================================
+    /// Owner records the MPC-controlled address `user` deposits to on
+    /// `chain_type`. `verify_mpc_deposit` only credits payments to it.
+    pub fn register_deposit_address(&mut self, user: AccountId, chain_type: ChainType, address: String) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can register deposit addresses"
+        );
+        self.deposit_addresses.insert(&(user, chain_type), &address);
+    }
 
-    pub fn make_intent(&mut self, src_asset: String, src_amount: U128, dst_asset: String, dst_amount: U128) -> U128 {
-        let src_amount: u128 = src_amount.into();
-        let dst_amount: u128 = dst_amount.into();
-        let maker = env::predecessor_account_id();
-        let mut user_balances = self.balances.get(&maker).expect("User not found");
-        let current = user_balances.get(&src_asset).unwrap_or(0);
-        assert!(current >= src_amount, "Insufficient balance");
+    pub fn get_deposit_address(&self, user: AccountId, chain_type: ChainType) -> Option<String> {
+        self.deposit_addresses.get(&(user, chain_type))
+    }
+
+    pub fn is_deposit_consumed(&self, chain_type: ChainType, tx_hash: String) -> bool {
+        self.consumed_deposits.contains(&(chain_type, tx_hash))
+    }
 
-        user_balances.insert(&src_asset, &(current - src_amount));
-        self.balances.insert(&maker, &user_balances);
+    pub fn is_transition_consumed(&self, chain_type: ChainType, tx_hash: String) -> bool {
+        self.consumed_transitions.contains(&(chain_type, tx_hash))
+    }
 
-        let id = self.next_id;
-        self.next_id += 1;
+    pub fn is_payment_consumed(&self, chain_type: ChainType, tx_hash: String) -> bool {
+        self.consumed_payments.contains(&(chain_type, tx_hash))
+    }
 
-        let intent = Intent {
-            id,
-            maker: maker.clone(),
+    // ========================================================================
+    // 2. Make / Cancel Intent
+    // ========================================================================
+
+    #[handle_result]
+    pub fn make_intent(
+        &mut self,
+        src_asset: String,
+        src_amount: U128,
+        dst_asset: String,
+        dst_amount: U128,
+        expires_at: Option<u64>,
+        min_fill_amount: Option<U128>,
+        all_or_nothing: Option<bool>,
+        allowed_takers: Option<Vec<AccountId>>,
+        referrer: Option<AccountId>,
+        client_id: Option<String>,
+    ) -> Result<U64, OrderbookError> {
+        self.check_not_paused()?;
+        let maker = env::predecessor_account_id();
+        self.check_not_blocked(&maker)?;
+        let initial_usage = env::storage_usage();
+        let spec = IntentSpec {
             src_asset,
             src_amount,
-            filled_amount: 0,
             dst_asset,
             dst_amount,
-            status: IntentStatus::Open,
+            expires_at,
+            min_fill_amount,
+            all_or_nothing,
+            allowed_takers,
+            referrer,
+            client_id,
         };
-        self.intents.insert(&id, &intent);
-        env::log_str(&format!("Intent #{} created", id));
-        U128(id.into())
+        let id = self.create_intent(&maker, spec, None)?;
+        self.try_settle_storage(&maker, initial_usage)?;
+        Ok(U64(id))
     }
 
-    // ========================================================================
-    // 3. Take Intent (single taker, no batch)
-    // ========================================================================
-
-    pub fn take_intent(&mut self, intent_id: U128, amount: U128) -> U128 {
-        let intent_id: u64 = intent_id.0 as u64;
-        let amount: u128 = amount.into();
-        let taker = env::predecessor_account_id();
-        let mut intent = self.intents.get(&intent_id).expect("Intent not found");
-        assert_ne!(intent.status, IntentStatus::Filled, "Intent already filled");
+    /// Create several intents in one call, e.g. quotes on both sides of a
+    /// few pairs. The summed `src_amount` of each asset must be covered by
+    /// the maker's balance before anything is locked, and any invalid spec
+    /// fails the whole call, so either every intent is created or none.
+    /// Returns the intent ids in spec order.
+    #[handle_result]
+    pub fn make_intents(&mut self, specs: Vec<IntentSpec>) -> Result<Vec<U64>, OrderbookError> {
+        self.check_not_paused()?;
+        let invalid = |reason: String| OrderbookError::InvalidIntent { reason };
+        if specs.is_empty() {
+            return Err(invalid("No intents in batch".to_string()));
+        }
+        if specs.len() > MAX_BATCH_INTENTS {
+            return Err(invalid(format!("Max {} intents per batch", MAX_BATCH_INTENTS)));
+        }
+        let maker = env::predecessor_account_id();
+        self.check_not_blocked(&maker)?;
+        let initial_usage = env::storage_usage();
+
+        let mut needed: Vec<(String, u128)> = Vec::new();
+        for spec in &specs {
+            let retried = spec
+                .client_id
+                .as_ref()
+                .is_some_and(|client_id| self.client_ids.contains_key(&(maker.clone(), client_id.clone())));
+            if retried {
+                continue;
+            }
+            let (asset, _) = self.lookup_asset(&spec.src_asset)?;
+            match needed.iter_mut().find(|(a, _)| *a == asset) {
+                Some((_, total)) => {
+                    *total = total
+                        .checked_add(spec.src_amount.0)
+                        .ok_or_else(|| invalid("src_amount overflow".to_string()))?;
+                }
+                None => needed.push((asset, spec.src_amount.0)),
+            }
+        }
+        for (asset, total) in &needed {
+            self.check_balance(&maker, asset, *total)?;
+        }
 
-        let remaining = intent.src_amount - intent.filled_amount;
-        assert!(amount <= remaining, "Amount exceeds remaining balance");
+        let ids = specs
+            .into_iter()
+            .map(|spec| self.create_intent(&maker, spec, None).map(U64))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.try_settle_storage(&maker, initial_usage)?;
+        Ok(ids)
+    }
 
-        intent.filled_amount += amount;
+    /// Validate `spec`, lock its `src_amount` and store the intent. Pause,
+    /// block and storage checks are the caller's.
+    fn create_intent(
+        &mut self,
+        maker: &AccountId,
+        spec: IntentSpec,
+        replaced_from: Option<u64>,
+    ) -> Result<u64, OrderbookError> {
+        let invalid = |reason: &str| OrderbookError::InvalidIntent { reason: reason.to_string() };
+        let IntentSpec {
+            src_asset,
+            src_amount,
+            dst_asset,
+            dst_amount,
+            expires_at,
+            min_fill_amount,
+            all_or_nothing,
+            allowed_takers,
+            referrer,
+            client_id,
+        } = spec;
+        let src_amount: u128 = src_amount.into();
+        let dst_amount: u128 = dst_amount.into();
+        if let Some(client_id) = &client_id {
+            if client_id.is_empty() || client_id.len() > MAX_CLIENT_ID_LEN {
+                return Err(OrderbookError::InvalidIntent {
+                    reason: format!("client_id must be 1 to {} bytes", MAX_CLIENT_ID_LEN),
+                });
+            }
+            // A retry of a call that already went through: nothing is locked twice.
+            if let Some(id) = self.client_ids.get(&(maker.clone(), client_id.clone())) {
+                return Ok(id);
+            }
+        }
+        if src_amount == 0 {
+            return Err(invalid("src_amount is zero"));
+        }
+        if dst_amount == 0 {
+            return Err(invalid("dst_amount is zero"));
+        }
+        Self::check_valid_asset(&src_asset)?;
+        Self::check_valid_asset(&dst_asset)?;
+        let (src_asset, _) = self.lookup_asset(&src_asset)?;
+        let (dst_asset, _) = self.lookup_asset(&dst_asset)?;
+        if src_asset == dst_asset {
+            return Err(invalid("src_asset equals dst_asset"));
+        }
+        self.check_pair_not_paused(&src_asset, &dst_asset)?;
+        self.check_min_intent_size(&src_asset, src_amount)?;
+        let min_fill_amount = min_fill_amount.map_or(0, |a| a.0);
+        if min_fill_amount > src_amount {
+            return Err(invalid("min_fill_amount exceeds src_amount"));
+        }
+        Self::check_valid_allowed_takers(&allowed_takers)?;
+        if referrer.as_ref() == Some(maker) {
+            return Err(OrderbookError::SelfReferral);
+        }
+        if expires_at.is_some_and(|t| t <= env::block_timestamp()) {
+            return Err(OrderbookError::ExpiryInPast);
+        }
+        let open = self.open_intent_counts.get(maker).unwrap_or(0);
+        if open >= self.max_open_intents_per_account {
+            return Err(OrderbookError::OpenIntentLimit { limit: self.max_open_intents_per_account });
+        }
+        self.check_balance(maker, &src_asset, src_amount)?;
+
+        let id = self.next_intent_id;
+        self.next_intent_id += 1;
+        self.internal_debit(maker, &src_asset, src_amount, BalanceChangeReason::IntentLocked, Some(id));
+        self.stats.total_intents_created += 1;
+
+        let intent = Intent {
+            id,
+            maker: maker.clone(),
+            src_asset,
+            src_amount,
+            filled_amount: 0,
+            dst_asset,
+            dst_amount,
+            status: IntentStatus::Open,
+            expires_at,
+            min_fill_amount,
+            all_or_nothing: all_or_nothing.unwrap_or(false),
+            closed_at: None,
+            allowed_takers,
+            referrer,
+            client_id,
+            replaced_from,
+        };
+        if let Some(client_id) = &intent.client_id {
+            self.client_ids.insert(&(maker.clone(), client_id.clone()), &id);
+        }
+        self.save_intent(&intent);
+        self.index_intent(maker, id);
+        env::log_str(&format!("Intent #{} created", id));
+        crate::ledger::log_event(
+            "intent_created",
+            IntentCreatedEvent {
+                id: U64(id),
+                maker: maker.clone(),
+                src_asset: intent.src_asset,
+                src_amount: U128(src_amount),
+                dst_asset: intent.dst_asset,
+                dst_amount: U128(dst_amount),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Persist an intent and keep `open_intent_ids` and the pair index in
+    /// step with its status.
+    fn save_intent(&mut self, intent: &Intent) {
+        self.intents.insert(&intent.id, intent);
+        let changed = if intent.status == IntentStatus::Open {
+            self.open_intent_ids.insert(&intent.id)
+        } else {
+            self.open_intent_ids.remove(&intent.id)
+        };
+        if changed {
+            let count = self.open_intent_counts.get(&intent.maker).unwrap_or(0);
+            if intent.status == IntentStatus::Open {
+                self.index_open_intent(intent);
+                self.stats.open_intents += 1;
+                self.open_intent_counts.insert(&intent.maker, &(count + 1));
+            } else {
+                self.unindex_open_intent(intent);
+                if let Some(client_id) = &intent.client_id {
+                    self.client_ids.remove(&(intent.maker.clone(), client_id.clone()));
+                }
+                self.stats.open_intents = self.stats.open_intents.saturating_sub(1);
+                if count > 1 {
+                    self.open_intent_counts.insert(&intent.maker, &(count - 1));
+                } else {
+                    self.open_intent_counts.remove(&intent.maker);
+                }
+            }
+        }
+    }
+
+    fn index_intent(&mut self, maker: &AccountId, intent_id: u64) {
+        let mut ids = self.intents_by_maker.get(maker).unwrap_or_else(|| {
+            Vector::new(format!("m{}", maker).as_bytes())
+        });
+        ids.push(&intent_id);
+        self.intents_by_maker.insert(maker, &ids);
+    }
+
+    /// Validate a withdrawal, deduct it from `user` and record it as
+    /// pending. Returns its id and the payload to sign.
+    fn queue_withdrawal(&mut self, user: &AccountId, item: &WithdrawItem) -> Result<(u64, Vec<u8>), OrderbookError> {
+        self.check_not_blocked(user)?;
+        self.check_path_len(&item.path)?;
+        self.check_fields(&[("destination", &item.destination)])?;
+        let amount: u128 = item.amount.into();
+        let (asset, info) = self.lookup_asset(&item.asset)?;
+        let (chain_type, destination) = (&item.chain_type, &item.destination);
+        Self::check_destination(chain_type, destination)?;
+        Self::check_user_path(user, chain_type, &item.path)?;
+        let (nonce, payload, gas_fee) = self.withdrawal_payload(user, &asset, &info, amount, item)?;
+        let gas_asset = chain_type.native_asset();
+        if asset == gas_asset {
+            let need = amount.checked_add(gas_fee).ok_or_else(|| OrderbookError::AmountOverflow { asset: asset.clone() })?;
+            self.check_balance(user, &asset, need)?;
+        } else {
+            self.check_balance(user, &asset, amount)?;
+            self.check_balance(user, gas_asset, gas_fee)?;
+        }
+        self.consume_withdrawal_quota(user, &asset, amount)?;
+
+        // Track pending withdrawal so we can refund on MPC failure
+        let wd_id = self.next_withdrawal_id;
+        self.next_withdrawal_id += 1;
+        self.internal_debit(user, &asset, amount, BalanceChangeReason::Withdrawal, Some(wd_id));
+        if gas_fee > 0 {
+            self.internal_debit(user, gas_asset, gas_fee, BalanceChangeReason::Withdrawal, Some(wd_id));
+        }
+        if *chain_type == ChainType::ETH {
+            self.withdrawal_nonces.insert(user, &(nonce + 1));
+        }
+        let wd = PendingWithdrawal {
+            user: user.clone(),
+            asset: asset.clone(),
+            amount,
+            gas_fee,
+            destination: destination.clone(),
+            chain_type: chain_type.clone(),
+            nonce,
+            payload: hex::encode(&payload),
+            requested_at: env::block_timestamp(),
+        };
+        self.pending_withdrawals.insert(&wd_id, &wd);
+        self.record_withdrawal(wd_id, &wd);
+        let mut by_user = self
+            .pending_withdrawals_by_user
+            .get(user)
+            .unwrap_or_else(|| UnorderedSet::new(format!("u{}", user).as_bytes()));
+        by_user.insert(&wd_id);
+        self.pending_withdrawals_by_user.insert(user, &by_user);
+
+        env::log_str(&format!(
+            "WITHDRAWAL_REQUESTED:wd_id={},user={},asset={},amount={},gas_fee={},chain={:?},destination={}",
+            wd_id, user, asset, amount, gas_fee, chain_type, destination
+        ));
+        Ok((wd_id, payload))
+    }
+
+    /// Check that `item.unsigned_tx` pays exactly `amount` of `asset` to
+    /// `item.destination` and return its nonce, the payload to sign and
+    /// the fee charged in the chain's native coin. Native ETH, registered
+    /// ERC-20 tokens, native BTC and native SOL are supported.
+    fn withdrawal_payload(
+        &self,
+        user: &AccountId,
+        asset: &str,
+        info: &AssetInfo,
+        amount: u128,
+        item: &WithdrawItem,
+    ) -> Result<(u64, Vec<u8>, u128), OrderbookError> {
+        let chain_type = &item.chain_type;
+        let unsupported = || OrderbookError::UnsupportedWithdrawal {
+            asset: asset.to_string(),
+            chain_type: chain_type.clone(),
+        };
+        if info.chain_type != *chain_type {
+            return Err(unsupported());
+        }
+        let raw = hex::decode(item.unsigned_tx.strip_prefix("0x").unwrap_or(&item.unsigned_tx))
+            .map_err(|_| OrderbookError::invalid_tx("not hex"))?;
+        let native = asset == chain_type.native_asset();
+        match (chain_type, &info.token_contract) {
+            (ChainType::ETH, Some(token)) => {
+                let (nonce, payload, fee) = self.eth_withdrawal_payload(user, Some(eth::parse_address(token)), amount, item, &raw)?;
+                Ok((nonce, payload.to_vec(), fee))
+            }
+            (ChainType::ETH, None) if native => {
+                let (nonce, payload, fee) = self.eth_withdrawal_payload(user, None, amount, item, &raw)?;
+                Ok((nonce, payload.to_vec(), fee))
+            }
+            (ChainType::BTC, None) if native => {
+                let (payload, fee) = Self::btc_withdrawal_payload(amount, item, &raw)?;
+                Ok((0, payload.to_vec(), fee))
+            }
+            (ChainType::SOL, None) if native => {
+                let fee = Self::check_sol_withdrawal(amount, item, &raw)?;
+                Ok((0, raw, fee))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// Check an EIP-1559 transaction from `user`'s next nonce, within the
+    /// gas fee cap, paying native ETH or a transfer of `token`. Returns its
+    /// nonce, signing hash and gas fee.
+    fn eth_withdrawal_payload(
+        &self,
+        user: &AccountId,
+        token: Option<[u8; 20]>,
+        amount: u128,
+        item: &WithdrawItem,
+        raw: &[u8],
+    ) -> Result<(u64, [u8; 32], u128), OrderbookError> {
+        let tx = eth::parse_unsigned_eip1559(raw).map_err(|reason| OrderbookError::invalid_tx(&format!("malformed tx: {}", reason)))?;
+        if tx.chain_id != self.eth_chain_id {
+            return Err(OrderbookError::invalid_tx("chain id does not match"));
+        }
+        let expected_nonce = self.withdrawal_nonces.get(user).unwrap_or(0);
+        if tx.nonce != expected_nonce {
+            return Err(OrderbookError::WrongWithdrawalNonce { expected: expected_nonce, got: tx.nonce });
+        }
+        let gas_fee = (tx.gas_limit as u128)
+            .checked_mul(tx.max_fee_per_gas)
+            .filter(|fee| *fee <= self.max_withdrawal_gas_fee)
+            .ok_or(OrderbookError::WithdrawalGasFeeTooHigh { max: self.max_withdrawal_gas_fee })?;
+        let recipient = eth::parse_address(&item.destination);
+        match token {
+            None => {
+                if tx.to != recipient {
+                    return Err(OrderbookError::invalid_tx("recipient does not match destination"));
+                }
+                if tx.value != amount {
+                    return Err(OrderbookError::invalid_tx("value does not match withdrawal amount"));
+                }
+                if !tx.data.is_empty() {
+                    return Err(OrderbookError::invalid_tx("data must be empty"));
+                }
+            }
+            Some(token) => {
+                if tx.to != token {
+                    return Err(OrderbookError::invalid_tx("recipient is not the token contract"));
+                }
+                if tx.value != 0 {
+                    return Err(OrderbookError::invalid_tx("value must be zero for a token transfer"));
+                }
+                if tx.data != eth::erc20_transfer_data(&recipient, amount) {
+                    return Err(OrderbookError::invalid_tx("data is not a transfer of the withdrawal amount to destination"));
+                }
+            }
+        }
+        Ok((tx.nonce, tx.signing_hash, gas_fee))
+    }
+
+    /// Check a PSBT whose first output pays `amount` sats to the
+    /// destination and whose only other output, if any, returns the change
+    /// to the spent input's script. Returns its sighash and the fee, which
+    /// is everything else the input holds.
+    fn btc_withdrawal_payload(amount: u128, item: &WithdrawItem, raw: &[u8]) -> Result<([u8; 32], u128), OrderbookError> {
+        let tx = btc::parse_withdrawal_psbt(raw).map_err(|reason| OrderbookError::invalid_tx(&format!("malformed tx: {}", reason)))?;
+        let destination = btc::address_script(&item.destination).ok_or_else(|| OrderbookError::invalid_tx("destination is not a valid address"))?;
+        let (payment, change) = match tx.outputs.as_slice() {
+            [payment] => (payment, None),
+            [payment, change] => (payment, Some(change)),
+            _ => return Err(OrderbookError::invalid_tx("expected a payment and at most one change output")),
+        };
+        if payment.script != destination {
+            return Err(OrderbookError::invalid_tx("recipient does not match destination"));
+        }
+        if u128::from(payment.value) != amount {
+            return Err(OrderbookError::invalid_tx("value does not match withdrawal amount"));
+        }
+        if change.is_some_and(|change| change.script != tx.input_script) {
+            return Err(OrderbookError::invalid_tx("change must return to the spent input"));
+        }
+        let spent = tx.outputs.iter().map(|output| u128::from(output.value)).sum::<u128>();
+        let fee = u128::from(tx.input_value)
+            .checked_sub(spent)
+            .ok_or_else(|| OrderbookError::invalid_tx("outputs exceed the input"))?;
+        Ok((tx.sighash, fee))
+    }
+
+    /// Check a message transferring `amount` lamports to the destination.
+    /// Returns the fee; the message itself is what MPC signs.
+    fn check_sol_withdrawal(amount: u128, item: &WithdrawItem, raw: &[u8]) -> Result<u128, OrderbookError> {
+        let transfer = sol::parse_transfer_message(raw).map_err(|reason| OrderbookError::invalid_tx(&format!("malformed tx: {}", reason)))?;
+        if btc::base58_decode(&item.destination).as_deref() != Some(&transfer.to[..]) {
+            return Err(OrderbookError::invalid_tx("recipient does not match destination"));
+        }
+        if u128::from(transfer.lamports) != amount {
+            return Err(OrderbookError::invalid_tx("value does not match withdrawal amount"));
+        }
+        Ok(sol::LAMPORTS_PER_SIGNATURE)
+    }
+
+    fn assert_valid_destination(chain_type: &ChainType, destination: &str) {
+        if let Err(error) = Self::check_destination(chain_type, destination) {
+            error.panic();
+        }
+    }
+
+    /// Basic address shape check for `chain_type`; checksums are left to
+    /// the transaction the address ends up in.
+    pub(crate) fn check_destination(chain_type: &ChainType, destination: &str) -> Result<(), OrderbookError> {
+        const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+        const BECH32: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+        let is_base58 = |s: &str| s.chars().all(|c| BASE58.contains(c));
+        let valid = match chain_type {
+            ChainType::ETH => destination
+                .strip_prefix("0x")
+                .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit())),
+            ChainType::SOL => (32..=44).contains(&destination.len()) && is_base58(destination),
+            ChainType::BTC => {
+                let lower = destination.to_lowercase();
+                let bech32 = ["bc1", "tb1", "bcrt1"].iter().find_map(|hrp| lower.strip_prefix(hrp));
+                match bech32 {
+                    Some(data) => {
+                        (destination == lower || destination == destination.to_uppercase())
+                            && (14..=74).contains(&destination.len())
+                            && data.chars().all(|c| BECH32.contains(c))
+                    }
+                    None => {
+                        (26..=35).contains(&destination.len())
+                            && destination.starts_with(['1', '2', '3', 'm', 'n'])
+                            && is_base58(destination)
+                    }
+                }
+            }
+        };
+        if !valid {
+            return Err(OrderbookError::InvalidDestination {
+                chain_type: chain_type.clone(),
+                destination: destination.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Parse `unsigned_tx` as a repricing of the sub-intent's matched
+    /// transition transaction: only its gas fields may change, and it must
+    /// still pay the filled amount of the intent's source asset to the
+    /// matched recipient.
+    fn check_repriced_settlement_tx(&self, sub: &SubIntent, unsigned_tx: &[u8]) -> eth::UnsignedEthTx {
+        let request = self
+            .settlement_requests
+            .get(&sub.id)
+            .unwrap_or_else(|| env::panic_str(&format!("No settlement request for Sub-Intent {}", sub.id)));
+        assert_eq!(request.chain_type, ChainType::ETH, "Only ETH settlement transactions can be repriced");
+        let parse = |tx: &[u8]| {
+            eth::parse_unsigned_eip1559(tx)
+                .unwrap_or_else(|reason| env::panic_str(&format!("Invalid ETH transaction: {}", reason)))
+        };
+        match request.unsigned_tx.as_ref() {
+            Some(matched) => assert!(
+                parse(unsigned_tx).differs_only_in_gas(&parse(matched)),
+                "Only the gas fields of a settlement transaction may change"
+            ),
+            // Backfilled by the V0 migration: there is nothing to compare
+            // against, so the transaction only has to pay the fill.
+            None if request.payload == SettlementRequest::UNSET_PAYLOAD => {}
+            None => env::panic_str(&format!("Sub-Intent {} was matched without an unsigned transaction", sub.id)),
+        }
+        let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+        // The retry signs under the memo the sub-intent was first signed with
+        let memo = self
+            .transition_expectations
+            .get(&sub.id)
+            .unwrap_or_else(|| env::panic_str(&format!("No transition expectation for Sub-Intent {}", sub.id)))
+            .expected_memo;
+        let recipient = self.settlement_recipient(&parent, &request);
+        self.check_eth_transition_tx(&parent.src_asset, &recipient, sub.amount, &memo, unsigned_tx)
+            .unwrap_or_else(|error| error.panic())
+    }
+
+    /// Where the transition of `request` pays. A request the V0 migration
+    /// backfilled pays the maker's current receive address, which they may
+    /// only have registered since.
+    fn settlement_recipient(&self, parent: &Intent, request: &SettlementRequest) -> String {
+        if request.payload != SettlementRequest::UNSET_PAYLOAD {
+            return request.recipient.clone();
+        }
+        self.check_has_receive_address(&parent.maker, &request.chain_type)
+            .unwrap_or_else(|error| error.panic());
+        self.receive_addresses.get(&(parent.maker.clone(), request.chain_type.clone())).unwrap()
+    }
+
+    /// An ETH transition is signed over the keccak256 hash of its
+    /// transaction, which must be given and pay `amount` of the intent's
+    /// src asset to `recipient` under `memo`.
+    pub(crate) fn check_eth_transition(
+        &self,
+        intent: &Intent,
+        recipient: &str,
+        amount: u128,
+        memo: &str,
+        payload: &[u8; 32],
+        unsigned_tx: Option<&Vec<u8>>,
+    ) -> Result<(), OrderbookError> {
+        let tx = unsigned_tx
+            .filter(|tx| env::keccak256_array(tx) == *payload)
+            .ok_or(OrderbookError::TransitionPayloadMismatch { intent_id: intent.id })?;
+        self.check_eth_transition_tx(&intent.src_asset, recipient, amount, memo, tx).map(drop)
+    }
+
+    /// An ETH transition transaction must be for the configured chain id
+    /// and pay exactly `amount` of `asset` to `recipient`, carrying `memo`
+    /// the way the light client reads it: as the whole calldata of a native
+    /// transfer, or appended to the `transfer` calldata of an ERC-20 one.
+    /// Matches and repriced retries are both checked here.
+    pub(crate) fn check_eth_transition_tx(
+        &self,
+        asset: &str,
+        recipient: &str,
+        amount: u128,
+        memo: &str,
+        unsigned_tx: &[u8],
+    ) -> Result<eth::UnsignedEthTx, OrderbookError> {
+        let invalid = |reason: &str| OrderbookError::InvalidTransitionTx { reason: reason.to_string() };
+        let tx = eth::parse_unsigned_eip1559(unsigned_tx).map_err(|reason| invalid(&reason))?;
+        if tx.chain_id != self.eth_chain_id {
+            return Err(invalid("chain id does not match"));
+        }
+        let info = self.assets.get(&asset.to_string()).ok_or_else(|| OrderbookError::UnknownAsset { asset: asset.to_string() })?;
+        if info.chain_type != ChainType::ETH {
+            return Err(invalid(&format!("asset {} is not on ETH", asset)));
+        }
+        let recipient = eth::parse_address(recipient);
+        let pays_expected = match &info.token_contract {
+            None => tx.to == recipient && tx.value == amount,
+            Some(token) => {
+                tx.to == eth::parse_address(token)
+                    && tx.value == 0
+                    && tx.data.starts_with(&eth::erc20_transfer_data(&recipient, amount))
+            }
+        };
+        if !pays_expected {
+            return Err(invalid("does not pay the expected recipient, asset and amount"));
+        }
+        let calldata_memo = match info.token_contract {
+            None => &tx.data[..],
+            Some(_) => &tx.data[eth::ERC20_TRANSFER_DATA_LEN..],
+        };
+        if calldata_memo != memo.as_bytes() {
+            return Err(invalid(&format!("does not carry the memo {}", memo)));
+        }
+        Ok(tx)
+    }
+
+    fn assert_payment_not_consumed(&self, chain_type: &ChainType, tx_hash: &str) {
+        if let Err(error) = self.check_payment_not_consumed(chain_type, tx_hash) {
+            error.panic();
+        }
+    }
+
+    fn check_payment_not_consumed(&self, chain_type: &ChainType, tx_hash: &str) -> Result<(), OrderbookError> {
+        if self.consumed_payments.contains(&(chain_type.clone(), tx_hash.to_string())) {
+            return Err(OrderbookError::PaymentConsumed { tx_hash: tx_hash.to_string() });
+        }
+        Ok(())
+    }
+
+    fn assert_deposit_not_consumed(&self, chain_type: &ChainType, tx_hash: &str) {
+        if let Err(error) = self.check_deposit_not_consumed(chain_type, tx_hash) {
+            error.panic();
+        }
+    }
+
+    fn check_deposit_not_consumed(&self, chain_type: &ChainType, tx_hash: &str) -> Result<(), OrderbookError> {
+        if self.consumed_deposits.contains(&(chain_type.clone(), tx_hash.to_string())) {
+            return Err(OrderbookError::DepositConsumed { tx_hash: tx_hash.to_string() });
+        }
+        Ok(())
+    }
+
+    fn assert_valid_allowed_takers(allowed_takers: &Option<Vec<AccountId>>) {
+        if let Err(error) = Self::check_valid_allowed_takers(allowed_takers) {
+            error.panic();
+        }
+    }
+
+    fn check_valid_allowed_takers(allowed_takers: &Option<Vec<AccountId>>) -> Result<(), OrderbookError> {
+        let Some(allowed) = allowed_takers else {
+            return Ok(());
+        };
+        if allowed.is_empty() {
+            return Err(OrderbookError::InvalidIntent { reason: "allowed_takers is empty".to_string() });
+        }
+        if allowed.len() > MAX_ALLOWED_TAKERS {
+            return Err(OrderbookError::InvalidIntent {
+                reason: format!("more than {} allowed takers", MAX_ALLOWED_TAKERS),
+            });
+        }
+        Ok(())
+    }
+
+    /// Maker replaces the taker restriction of an open intent; `None` makes
+    /// it public.
+    pub fn set_allowed_takers(&mut self, intent_id: U64, allowed_takers: Option<Vec<AccountId>>) {
+        let intent_id: u64 = intent_id.0;
+        let mut intent = self.intents.get(&intent_id).expect("Intent not found");
+        assert_eq!(
+            intent.maker,
+            env::predecessor_account_id(),
+            "Only the maker can edit allowed takers"
+        );
+        assert_eq!(intent.status, IntentStatus::Open, "Intent {} not open", intent_id);
+        Self::assert_valid_allowed_takers(&allowed_takers);
+
+        let initial_usage = env::storage_usage();
+        intent.allowed_takers = allowed_takers;
+        self.save_intent(&intent);
+        self.settle_storage(&intent.maker, initial_usage);
+        env::log_str(&format!(
+            "INTENT_TAKERS_UPDATED:intent_id={},allowed_takers={}",
+            intent_id,
+            intent.allowed_takers.as_ref().map_or(0, |a| a.len())
+        ));
+    }
+
+    /// Why the light client did not accept a proof, for logs and panics.
+    fn rejection_reason(outcome: &Result<VerifyOutcome, PromiseError>) -> String {
+        match outcome {
+            Ok(VerifyOutcome::Invalid(reason)) => format!("{:?}", reason),
+            Ok(VerifyOutcome::Valid) => "Valid".to_string(),
+            Err(_) => "PromiseFailed".to_string(),
+        }
+    }
+
+    pub(crate) fn assert_valid_asset(asset: &str) {
+        if let Err(error) = Self::check_valid_asset(asset) {
+            error.panic();
+        }
+    }
+
+    fn check_valid_asset(asset: &str) -> Result<(), OrderbookError> {
+        if asset.is_empty() {
+            return Err(OrderbookError::InvalidIntent { reason: "empty asset".to_string() });
+        }
+        if asset.len() > MAX_ASSET_LEN {
+            return Err(OrderbookError::InvalidIntent {
+                reason: format!("asset longer than {} bytes", MAX_ASSET_LEN),
+            });
+        }
+        Ok(())
+    }
+
+    /// Maker cancels an open intent and gets the unfilled remainder back.
+    /// Refused while any sub-intent of this intent is still being settled.
+    /// Requires 1 yoctoNEAR, so only a full access key can cancel.
+    #[payable]
+    pub fn cancel_intent(&mut self, intent_id: U64) {
+        assert_one_yocto();
+        let intent_id: u64 = intent_id.0;
+        let mut intent = self.intents.get(&intent_id).expect("Intent not found");
+        assert_eq!(
+            intent.maker,
+            env::predecessor_account_id(),
+            "Only the maker can cancel the intent"
+        );
+        assert_eq!(intent.status, IntentStatus::Open, "Intent {} not open", intent_id);
+        assert!(
+            !self.has_in_flight_sub_intents(intent_id),
+            "Intent {} has sub-intents still settling",
+            intent_id
+        );
+
+        let initial_usage = env::storage_usage();
+        let refund = intent.src_amount - intent.filled_amount;
+        intent.status = IntentStatus::Cancelled;
+        intent.closed_at = Some(env::block_timestamp());
+        self.save_intent(&intent);
+        self.internal_credit(&intent.maker, &intent.src_asset, refund, BalanceChangeReason::IntentRefund, Some(intent.id));
+        self.settle_storage(&intent.maker, initial_usage);
+        env::log_str(&format!(
+            "Intent #{} cancelled, refunded {} {} to {}",
+            intent_id, refund, intent.src_asset, intent.maker
+        ));
+    }
+
+    /// Maker swaps an open intent for one with new amounts in a single call:
+    /// the old intent is cancelled and its remainder refunded, then the new
+    /// `src_amount` is locked, so the maker never holds neither. Expiry, fill
+    /// flags, allowed takers and referrer carry over; the new intent links
+    /// back through `replaced_from`. Same rules as `cancel_intent` for who
+    /// may call it and when.
+    #[payable]
+    #[handle_result]
+    pub fn replace_intent(
+        &mut self,
+        intent_id: U64,
+        new_src_amount: U128,
+        new_dst_amount: U128,
+    ) -> Result<U64, OrderbookError> {
+        assert_one_yocto();
+        self.check_not_paused()?;
+        let maker = env::predecessor_account_id();
+        self.check_not_blocked(&maker)?;
+        let intent_id: u64 = intent_id.0;
+        let mut intent = self
+            .intents
+            .get(&intent_id)
+            .ok_or(OrderbookError::IntentNotFound { id: intent_id })?;
+        if intent.maker != maker {
+            return Err(OrderbookError::NotAuthorized {
+                account: maker,
+                action: format!("replace Intent {}", intent_id),
+            });
+        }
+        if intent.status != IntentStatus::Open {
+            return Err(OrderbookError::IntentNotOpen { id: intent_id });
+        }
+        if self.has_in_flight_sub_intents(intent_id) {
+            return Err(OrderbookError::IntentSettling { id: intent_id });
+        }
+        if intent.is_expired(env::block_timestamp()) {
+            return Err(OrderbookError::IntentExpired { id: intent_id });
+        }
+
+        // Everything `create_intent` could reject is checked before the old
+        // intent is touched, so a failed replacement leaves it as it was.
+        let invalid = |reason: &str| OrderbookError::InvalidIntent { reason: reason.to_string() };
+        if new_src_amount.0 == 0 {
+            return Err(invalid("src_amount is zero"));
+        }
+        if new_dst_amount.0 == 0 {
+            return Err(invalid("dst_amount is zero"));
+        }
+        if intent.min_fill_amount > new_src_amount.0 {
+            return Err(invalid("min_fill_amount exceeds src_amount"));
+        }
+        self.check_pair_not_paused(&intent.src_asset, &intent.dst_asset)?;
+        self.check_min_intent_size(&intent.src_asset, new_src_amount.0)?;
+        let refund = intent.src_amount - intent.filled_amount;
+        if new_src_amount.0 > refund {
+            self.check_balance(&maker, &intent.src_asset, new_src_amount.0 - refund)?;
+        }
+
+        let initial_usage = env::storage_usage();
+        intent.status = IntentStatus::Cancelled;
+        intent.closed_at = Some(env::block_timestamp());
+        self.save_intent(&intent);
+        self.internal_credit(&maker, &intent.src_asset, refund, BalanceChangeReason::IntentRefund, Some(intent_id));
+        let spec = IntentSpec {
+            src_asset: intent.src_asset,
+            src_amount: new_src_amount,
+            dst_asset: intent.dst_asset,
+            dst_amount: new_dst_amount,
+            expires_at: intent.expires_at,
+            min_fill_amount: Some(U128(intent.min_fill_amount)),
+            all_or_nothing: Some(intent.all_or_nothing),
+            allowed_takers: intent.allowed_takers,
+            referrer: intent.referrer,
+            client_id: None,
+        };
+        let id = self.create_intent(&maker, spec, Some(intent_id))?;
+        self.try_settle_storage(&maker, initial_usage)?;
+        env::log_str(&format!("Intent #{} replaced by #{}", intent_id, id));
+        Ok(U64(id))
+    }
+
+    /// Anyone may expire an open intent past its `expires_at`; the unfilled
+    /// remainder goes back to the maker.
+    pub fn expire_intent(&mut self, intent_id: U64) {
+        let intent_id: u64 = intent_id.0;
+        let mut intent = self.intents.get(&intent_id).expect("Intent not found");
+        assert_eq!(intent.status, IntentStatus::Open, "Intent {} not open", intent_id);
+        assert!(
+            intent.is_expired(env::block_timestamp()),
+            "Intent {} has not expired yet",
+            intent_id
+        );
+
+        let initial_usage = env::storage_usage();
+        let refund = intent.src_amount - intent.filled_amount;
+        intent.status = IntentStatus::Expired;
+        intent.closed_at = Some(env::block_timestamp());
+        self.save_intent(&intent);
+        self.internal_credit(&intent.maker, &intent.src_asset, refund, BalanceChangeReason::IntentRefund, Some(intent.id));
+        self.settle_storage(&intent.maker, initial_usage);
+        env::log_str(&format!(
+            "Intent #{} expired, refunded {} {} to {}",
+            intent_id, refund, intent.src_asset, intent.maker
+        ));
+    }
+
+    /// True if any sub-intent of `intent_id` is mid-settlement (Verifying/Settled).
+    fn has_in_flight_sub_intents(&self, intent_id: u64) -> bool {
+        self.sub_intents_by_parent
+            .get(&intent_id)
+            .into_iter()
+            .flat_map(|ids| ids.to_vec())
+            .filter_map(|id| self.sub_intents.get(&id))
+            .any(|sub| {
+                matches!(
+                    sub.status,
+                    SubIntentStatus::Verifying | SubIntentStatus::Settled | SubIntentStatus::AwaitingSign
+                )
+            })
+    }
+
+    /// Drop a settled or refunded withdrawal from `user`'s pending set,
+    /// and the set itself once it is empty.
+    fn remove_pending_withdrawal_index(&mut self, user: &AccountId, wd_id: u64) {
+        let Some(mut by_user) = self.pending_withdrawals_by_user.get(user) else {
+            return;
+        };
+        by_user.remove(&wd_id);
+        if by_user.is_empty() {
+            self.pending_withdrawals_by_user.remove(user);
+        } else {
+            self.pending_withdrawals_by_user.insert(user, &by_user);
+        }
+    }
+
+    /// Delete a sub-intent record. Its id stays in the parent and taker
+    /// index vectors, which skip deleted ids, so pages never shift.
+    fn remove_sub_intent(&mut self, sub: &SubIntent) {
+        self.sub_intents.remove(&sub.id);
+    }
+
+    /// Return a sub-intent's fill to its parent intent, or to the maker's
+    /// balance if the parent is no longer open.
+    fn unwind_fill(&mut self, sub: &SubIntent) {
+        let mut parent = self
+            .intents
+            .get(&sub.parent_intent_id)
+            .expect("Parent intent not found");
+        parent.filled_amount -= sub.amount;
+        self.remove_volume(&parent.src_asset, sub.amount);
+        self.forfeit_surplus(sub);
+        match parent.status {
+            IntentStatus::Open | IntentStatus::Filled => parent.status = IntentStatus::Open,
+            _ => self.internal_credit(
+                &parent.maker,
+                &parent.src_asset,
+                sub.amount,
+                BalanceChangeReason::FillUnwound,
+                Some(sub.id),
+            ),
+        }
+        self.save_intent(&parent);
+    }
+
+    /// Credit the taker's locked counter-asset back to them. The caller
+    /// persists `sub`.
+    fn refund_taker_escrow(&mut self, sub: &mut SubIntent) {
+        if sub.escrow == 0 {
+            return;
+        }
+        let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+        self.internal_credit(&sub.taker, &parent.dst_asset, sub.escrow, BalanceChangeReason::TakerEscrowRefund, Some(sub.id));
+        sub.escrow = 0;
+    }
+
+    fn internal_force_cancel_sub_intent(&mut self, sub_intent_id: u64, refund_maker: bool, reason: &str) {
+        let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
+        // A timed-out transition leaves its escrows for the owner to settle
+        let resolving = sub.status == SubIntentStatus::Failed && self.holds_escrow(&sub);
+        assert!(
+            !sub.status.is_final() || resolving,
+            "Sub-Intent {} is already final",
+            sub_intent_id
+        );
+        let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+
+        self.transition_expectations.remove(&sub_intent_id);
+        self.signatures.remove(&SignTarget::SubIntent(sub_intent_id));
+        let solver = sub.taker.clone();
+        if let Some(job) = self.pending_signs.remove(&sub_intent_id) {
+            Promise::new(solver.clone()).transfer(NearToken::from_yoctonear(job.deposit)).detach();
+        }
+        self.pay_out_bond(&mut sub, &solver);
+        let proceeds = self.escrowed_credits.remove(&sub_intent_id);
+        if refund_maker {
+            self.refund_taker_escrow(&mut sub);
+        } else {
+            if sub.escrow > 0 {
+                self.internal_credit(
+                    &parent.maker,
+                    &parent.dst_asset,
+                    sub.escrow,
+                    BalanceChangeReason::TakerEscrowReleased,
+                    Some(sub_intent_id),
+                );
+                sub.escrow = 0;
+            }
+            if let Some(escrow) = proceeds {
+                self.release_escrow(sub_intent_id, escrow);
+            }
+            self.settle_surplus(&sub);
+        }
+        if !resolving {
+            sub.status = SubIntentStatus::ForceCancelled;
+            sub.closed_at = Some(env::block_timestamp());
+            self.record_sub_intent_outcome(&sub.status);
+        }
+        self.sub_intents.insert(&sub_intent_id, &sub);
+        if refund_maker {
+            self.unwind_fill(&sub);
+        }
+
+        env::log_str(&format!(
+            "SUB_INTENT_FORCE_CANCELLED:sub_intent_id={},intent_id={},admin={},refund_maker={},reason={}",
+            sub_intent_id, sub.parent_intent_id, self.owner, refund_maker, reason
+        ));
+    }
+
+    /// Pay out a delivered fill's proceeds: the maker gets `amount` and its
+    /// rebate, the fee collector and the referrer their shares of `fee`.
+    fn release_escrow(&mut self, sub_intent_id: u64, escrow: EscrowEntry) {
+        self.internal_credit(
+            &escrow.maker,
+            &escrow.asset,
+            escrow.amount,
+            BalanceChangeReason::EscrowReleased,
+            Some(sub_intent_id),
+        );
+        env::log_str(&format!(
+            "ESCROW_RELEASED:sub_intent_id={},maker={},asset={},amount={}",
+            sub_intent_id, escrow.maker, escrow.asset, escrow.amount
+        ));
+        if escrow.maker_rebate > 0 {
+            self.pay_maker_rebate(sub_intent_id, &escrow.maker, &escrow.asset, escrow.maker_rebate);
+        }
+        if escrow.fee > escrow.maker_rebate {
+            let protocol_fee = escrow.fee - escrow.maker_rebate - escrow.referral_fee;
+            let collector = self.fee_collector.clone();
+            self.internal_credit(&collector, &escrow.asset, protocol_fee, BalanceChangeReason::ProtocolFee, Some(sub_intent_id));
+            let accrued = self.accrued_fees.get(&escrow.asset).unwrap_or(0);
+            self.accrued_fees.insert(&escrow.asset, &(accrued + protocol_fee));
+        }
+        if let Some(referrer) = escrow.referrer.filter(|_| escrow.referral_fee > 0) {
+            self.pay_referral(sub_intent_id, &referrer, &escrow.asset, escrow.referral_fee);
+        }
+    }
+
+    /// True while the taker's escrow or the maker's proceeds are still held
+    /// for the sub-intent.
+    pub(crate) fn holds_escrow(&self, sub: &SubIntent) -> bool {
+        sub.escrow > 0 || self.escrowed_credits.get(&sub.id).is_some()
+    }
+
+    /// Send the sub-intent's bond to `to`. The caller persists `sub`.
+    fn pay_out_bond(&mut self, sub: &mut SubIntent, to: &AccountId) {
+        if sub.bond == 0 {
+            return;
+        }
+        Promise::new(to.clone()).transfer(NearToken::from_yoctonear(sub.bond)).detach();
+        sub.bond = 0;
+    }
+
+    /// Record what the transition must deliver, under `memo`, and ask MPC
+    /// to sign it.
+    fn request_transition_signature(&mut self, sub_intent_id: u64, request: SettlementRequest, memo: String) -> Promise {
+        self.settlement_requests.insert(&sub_intent_id, &request);
+        let SettlementRequest { payload, path, chain_type: transition_chain_type, scheme, recipient, unsigned_tx } = request;
+        let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
+        sub.status = SubIntentStatus::Verifying;
+        self.sub_intents.insert(&sub_intent_id, &sub);
+        let parent = self
+            .intents
+            .get(&sub.parent_intent_id)
+            .expect("Parent intent not found");
+        let expectation = TransitionExpectation {
+            sub_intent_id,
+            chain_type: transition_chain_type.clone(),
+            expected_asset: parent.src_asset.clone(),
+            expected_amount: sub.amount,
+            expected_memo: memo,
+            expected_recipient: recipient,
+            transition_deadline: None,
+            unsigned_tx,
+        };
+        self.transition_expectations.insert(&sub_intent_id, &expectation);
+
+        let request = SignRequest::new(&payload, path.clone(), scheme);
+
+        ext_signer::ext(self.signer_for(&transition_chain_type))
+            .with_attached_deposit(env::attached_deposit())
+            .with_static_gas(Gas::from_tgas(50))
+            .sign(request)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(30))
+                    .on_signed(SignTarget::SubIntent(sub_intent_id), transition_chain_type, payload.to_vec(), path),
+            )
+    }
+
+    /// Append `sub` to its parent's fill history and matched volume.
+    fn record_fill(&mut self, intent: &Intent, sub: &SubIntent, get_amount: u128) {
+        self.add_volume(&intent.src_asset, sub.amount);
+        let intent_id = intent.id;
+        let mut fills = self
+            .intent_fills
+            .get(&intent_id)
+            .unwrap_or_else(|| Vector::new(format!("h{}", intent_id).as_bytes()));
+        fills.push(&FillRecord {
+            sub_intent_id: sub.id,
+            taker: sub.taker.clone(),
+            fill_amount: sub.amount,
+            get_amount,
+            timestamp: env::block_timestamp(),
+        });
+        self.intent_fills.insert(&intent_id, &fills);
+    }
+
+    /// Store a new sub-intent and append it to the parent and taker indices.
+    fn insert_sub_intent(&mut self, sub: &SubIntent) {
+        self.sub_intents.insert(&sub.id, sub);
+
+        let mut by_parent = self.sub_intents_by_parent.get(&sub.parent_intent_id).unwrap_or_else(|| {
+            Vector::new(format!("p{}", sub.parent_intent_id).as_bytes())
+        });
+        by_parent.push(&sub.id);
+        self.sub_intents_by_parent.insert(&sub.parent_intent_id, &by_parent);
+
+        let mut by_taker = self.sub_intents_by_taker.get(&sub.taker).unwrap_or_else(|| {
+            Vector::new(format!("t{}", sub.taker).as_bytes())
+        });
+        by_taker.push(&sub.id);
+        self.sub_intents_by_taker.insert(&sub.taker, &by_taker);
+    }
+
+    // ========================================================================
+    // 3. Take Intent (single taker, no batch)
+    // ========================================================================
+
+    #[handle_result]
+    pub fn take_intent(&mut self, intent_id: U64, amount: U128) -> Result<U64, OrderbookError> {
+        self.check_not_paused()?;
+        let intent_id: u64 = intent_id.0;
+        let amount: u128 = amount.into();
+        let taker = env::predecessor_account_id();
+        self.check_not_blocked(&taker)?;
+        let initial_usage = env::storage_usage();
+        let mut intent = self
+            .intents
+            .get(&intent_id)
+            .ok_or(OrderbookError::IntentNotFound { id: intent_id })?;
+        self.check_not_blocked(&intent.maker)?;
+        match intent.status {
+            IntentStatus::Open => {}
+            IntentStatus::Filled => return Err(OrderbookError::IntentFilled { id: intent_id }),
+            _ => return Err(OrderbookError::IntentNotOpen { id: intent_id }),
+        }
+        if intent.is_expired(env::block_timestamp()) {
+            return Err(OrderbookError::IntentExpired { id: intent_id });
+        }
+        self.check_pair_not_paused(&intent.src_asset, &intent.dst_asset)?;
+        intent.check_taker_allowed(&taker)?;
+
+        let remaining = intent.src_amount - intent.filled_amount;
+        if amount > remaining {
+            return Err(OrderbookError::ExceedsRemaining { intent_id });
+        }
+        intent.check_fill_allowed(amount)?;
+
+        // The taker locks the counter-asset up front.
+        let escrow = intent.dst_for(amount);
+        self.check_balance(&taker, &intent.dst_asset, escrow)?;
+        self.internal_debit(&taker, &intent.dst_asset, escrow, BalanceChangeReason::TakerEscrowLocked, Some(intent_id));
+
+        intent.filled_amount += amount;
         if intent.filled_amount == intent.src_amount {
             intent.status = IntentStatus::Filled;
         }
-        self.intents.insert(&intent_id, &intent);
+        self.save_intent(&intent);
 
-        let sub_id = self.next_id;
-        self.next_id += 1;
+        let sub_id = self.next_sub_intent_id;
+        self.next_sub_intent_id += 1;
 
         let sub_intent = SubIntent {
             id: sub_id,
             parent_intent_id: intent_id,
             taker: taker.clone(),
             amount,
-            status: IntentStatus::Taken,
+            status: SubIntentStatus::Taken,
+            escrow,
+            expires_at: Some(env::block_timestamp() + self.sub_intent_timeout_ns),
+            bond: 0,
+            closed_at: None,
+            transition_tx_hash: None,
+            batch_id: None,
         };
-        self.sub_intents.insert(&sub_id, &sub_intent);
-        U128(sub_id.into())
+        self.insert_sub_intent(&sub_intent);
+        self.record_fill(&intent, &sub_intent, escrow);
+        self.try_settle_storage(&taker, initial_usage)?;
+        Ok(U64(sub_id))
     }
 
     // ========================================================================
@@ -335,58 +2340,192 @@ impl Orderbook {
     /// Solver submits a batch of matches. After validation, the contract
     /// automatically calls MPC to sign the corresponding external-chain
     /// transactions. No separate `settle` call is needed.
+    ///
+    /// Returns the created sub-intent ids, in the same order as `matches`,
+    /// or none if the batch would exceed a volume cap (see
+    /// `set_volume_cap`); the attached deposit is then refunded.
     #[payable]
-    pub fn batch_match_intents(&mut self, matches: Vec<MatchParams>) {
-        assert!(matches.len() >= 2, "At least 2 intents required");
-        assert!(matches.len() <= 6, "Max 6 intents per batch (gas limit)");
+    #[handle_result]
+    pub fn batch_match_intents(&mut self, matches: Vec<MatchParams>) -> Result<Vec<U64>, OrderbookError> {
         let solver = env::predecessor_account_id();
+        let (sub_ids, deposit_per_sign) =
+            self.apply_batch(&solver, &matches, MAX_BATCH_MATCHES, SubIntentStatus::Verifying)?;
 
-        let mut asset_balance: HashMap<String, i128> = HashMap::new();
-        let mut sub_ids: Vec<u64> = Vec::new();
+        // ---- Auto-trigger MPC signing for all sub-intents ----
+        for (m, &sub_id) in matches.iter().zip(&sub_ids) {
+            let request = SignRequest::new(&m.payload, m.path.clone(), m.scheme);
 
-        for m in &matches {
-            let intent_id: u64 = m.intent_id.0 as u64;
-            let fill_amount: u128 = m.fill_amount.into();
-            let get_amount: u128 = m.get_amount.into();
+            // Each promise chain executes independently once created.
+            // We detach them so NEAR doesn't try to return a joint promise.
+            ext_signer::ext(self.signer_for(&m.transition_chain_type))
+                .with_attached_deposit(NearToken::from_yoctonear(deposit_per_sign))
+                .with_static_gas(Gas::from_tgas(30))
+                .sign(request)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(Gas::from_tgas(15))
+                        .on_signed(
+                            SignTarget::SubIntent(sub_id),
+                            m.transition_chain_type.clone(),
+                            m.payload.to_vec(),
+                            m.path.clone(),
+                        ),
+                )
+                .detach();
+        }
 
-            let mut intent = self.intents.get(&intent_id).expect("Intent not found");
-            assert_eq!(intent.status, IntentStatus::Open, "Intent {} not open", intent_id);
+        Ok(sub_ids.into_iter().map(U64).collect())
+    }
 
-            let remaining_src = intent.src_amount - intent.filled_amount;
-            assert!(fill_amount <= remaining_src, "Fill amount exceeds remaining balance for Intent {}", intent_id);
+    /// Same as `batch_match_intents`, for up to `MAX_DEFERRED_BATCH_MATCHES`
+    /// matches: everything is validated and matched atomically, but the
+    /// sign requests are queued instead of sent. The sub-intents stay
+    /// `AwaitingSign` until `process_sign_queue` sends their request with the
+    /// deposit share attached here.
+    #[payable]
+    pub fn batch_match_intents_deferred(&mut self, matches: Vec<MatchParams>) -> Vec<U64> {
+        let solver = env::predecessor_account_id();
+        self.apply_deferred_batch(&solver, matches)
+    }
 
-            // Price Check: get_amount / fill_amount >= dst_amount / src_amount
-            let lhs = (get_amount as u128) * (intent.src_amount as u128);
-            let rhs = (fill_amount as u128) * (intent.dst_amount as u128);
-            assert!(lhs >= rhs, "Price mismatch for Intent {}: Get {} < Required", intent_id, get_amount);
+    /// `batch_match_intents_deferred` on behalf of `solver`.
+    pub(crate) fn apply_deferred_batch(&mut self, solver: &AccountId, matches: Vec<MatchParams>) -> Vec<U64> {
+        let (sub_ids, deposit_per_sign) = self
+            .apply_batch(solver, &matches, MAX_DEFERRED_BATCH_MATCHES, SubIntentStatus::AwaitingSign)
+            .unwrap_or_else(|error| error.panic());
+        if sub_ids.is_empty() {
+            return Vec::new();
+        }
+        for (m, sub_id) in matches.into_iter().zip(&sub_ids) {
+            self.pending_signs.insert(
+                sub_id,
+                &SignJob {
+                    payload: m.payload,
+                    path: m.path,
+                    chain_type: m.transition_chain_type,
+                    scheme: m.scheme,
+                    deposit: deposit_per_sign,
+                },
+            );
+        }
+        env::log_str(&format!("SIGNS_QUEUED:solver={},count={}", solver, sub_ids.len()));
+        sub_ids.into_iter().map(U64).collect()
+    }
 
-            // Asset supply/demand tracking
-            let src = &intent.src_asset;
-            let supply = *asset_balance.get(src).unwrap_or(&0);
-            asset_balance.insert(src.clone(), supply + fill_amount as i128);
+    /// Send up to `limit` (capped at `MAX_QUEUED_SIGNS_PER_CALL`) queued sign
+    /// requests of deferred batches. Anyone may call this: each request
+    /// carries the deposit its solver attached. Returns how many were sent.
+    pub fn process_sign_queue(&mut self, limit: u64) -> u32 {
+        let take = limit.min(MAX_QUEUED_SIGNS_PER_CALL) as usize;
+        let sub_ids: Vec<u64> = self.pending_signs.keys().take(take).collect();
+        let mut sent = 0u32;
+        for sub_id in sub_ids {
+            let job = self.pending_signs.remove(&sub_id).expect("Sign job not found");
+            let Some(mut sub) = self.sub_intents.get(&sub_id) else {
+                continue;
+            };
+            if sub.status != SubIntentStatus::AwaitingSign {
+                continue;
+            }
+            sub.status = SubIntentStatus::Verifying;
+            self.sub_intents.insert(&sub_id, &sub);
 
-            let dst = &intent.dst_asset;
-            let demand = *asset_balance.get(dst).unwrap_or(&0);
-            asset_balance.insert(dst.clone(), demand - get_amount as i128);
+            let request = SignRequest::new(&job.payload, job.path.clone(), job.scheme);
+            ext_signer::ext(self.signer_for(&job.chain_type))
+                .with_attached_deposit(NearToken::from_yoctonear(job.deposit))
+                .with_static_gas(Gas::from_tgas(30))
+                .sign(request)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(Gas::from_tgas(15))
+                        .on_signed(SignTarget::SubIntent(sub_id), job.chain_type, job.payload.to_vec(), job.path),
+                )
+                .detach();
+            sent += 1;
+        }
+        if sent > 0 {
+            env::log_str(&format!("SIGNS_PROCESSED:count={},remaining={}", sent, self.pending_signs.len()));
+        }
+        sent
+    }
+
+    /// Queued sign request of a deferred batch match, if any.
+    pub fn get_sign_job(&self, sub_intent_id: U64) -> Option<SignJob> {
+        self.pending_signs.get(&(sub_intent_id.0))
+    }
+
+    /// Number of queued sign requests.
+    pub fn get_pending_sign_count(&self) -> u64 {
+        self.pending_signs.len()
+    }
+
+    /// Validate `matches` (at most `max_matches`) and the attached deposit,
+    /// then fill the intents and create their sub-intents in `status`.
+    /// Returns the sub-intent ids and the deposit each sign request gets;
+    /// no ids if the circuit breaker turned the batch away.
+    fn apply_batch(
+        &mut self,
+        solver: &AccountId,
+        matches: &[MatchParams],
+        max_matches: usize,
+        status: SubIntentStatus,
+    ) -> Result<(Vec<u64>, u128), OrderbookError> {
+        // Status, amounts, price and solvency; shared with `simulate_batch_match`
+        let checked = self.check_batch(solver, matches, max_matches);
+        if let Some(error) = checked.errors.first() {
+            return Err(error.clone());
+        }
+        // Checked before any state changes, so an under-funded batch fails
+        // without side effects. The deposit covers the transition bond and
+        // the signing deposit of every sub-intent.
+        let n = matches.len() as u128;
+        let required = (self.transition_bond + self.min_sign_deposit) * n;
+        let attached = env::attached_deposit().as_yoctonear();
+        if attached < required {
+            return Err(OrderbookError::InsufficientDeposit { required, attached });
+        }
+        // A batch over a volume cap changes nothing and gets its deposit back
+        if !self.record_matched_volume(solver, &checked.net) {
+            Promise::new(solver.clone())
+                .transfer(NearToken::from_yoctonear(attached))
+                .detach();
+            return Ok((Vec::new(), 0));
+        }
+
+        let nonce = self.take_transition_nonce();
+        let batch_id = self.take_batch_id();
+        let mut sub_ids: Vec<u64> = Vec::new();
+        for m in matches {
+            let intent_id: u64 = m.intent_id.0;
+            let fill_amount: u128 = m.fill_amount.into();
+            let get_amount: u128 = m.get_amount.into();
+            let mut intent = self.intents.get(&intent_id).expect("Intent not found");
 
             // Update intent state
             intent.filled_amount += fill_amount;
             if intent.filled_amount == intent.src_amount {
                 intent.status = IntentStatus::Filled;
             }
-            self.intents.insert(&intent_id, &intent);
+            self.save_intent(&intent);
 
-            // Create sub-intent (starts as Verifying since we go straight to MPC)
-            let sub_id = self.next_id;
-            self.next_id += 1;
+            // Create sub-intent (Verifying, or AwaitingSign until its job is processed)
+            let sub_id = self.next_sub_intent_id;
+            self.next_sub_intent_id += 1;
             let sub_intent = SubIntent {
                 id: sub_id,
                 parent_intent_id: intent_id,
                 taker: solver.clone(),
                 amount: fill_amount,
-                status: IntentStatus::Verifying,
+                status: status.clone(),
+                escrow: 0,
+                expires_at: None,
+                bond: self.transition_bond,
+                closed_at: None,
+                transition_tx_hash: None,
+                batch_id: Some(batch_id),
             };
-            self.sub_intents.insert(&sub_id, &sub_intent);
+            self.insert_sub_intent(&sub_intent);
+            self.record_fill(&intent, &sub_intent, get_amount);
             sub_ids.push(sub_id);
 
             // Record transition expectation
@@ -395,12 +2534,45 @@ impl Orderbook {
                 chain_type: m.transition_chain_type.clone(),
                 expected_asset: intent.src_asset.clone(),
                 expected_amount: fill_amount,
-                expected_memo: format!("transition:sub:{}", sub_id),
+                expected_memo: Self::transition_memo(sub_id, nonce),
+                expected_recipient: m.recipient.clone(),
+                transition_deadline: None,
+                unsigned_tx: m.unsigned_tx.clone(),
             };
             self.transition_expectations.insert(&sub_id, &expectation);
+            self.settlement_requests.insert(
+                &sub_id,
+                &SettlementRequest {
+                    payload: m.payload,
+                    path: m.path.clone(),
+                    chain_type: m.transition_chain_type.clone(),
+                    scheme: m.scheme,
+                    recipient: m.recipient.clone(),
+                    unsigned_tx: m.unsigned_tx.clone(),
+                },
+            );
 
-            // Credit maker with what they bought
-            self.internal_transfer(intent.maker.clone(), intent.dst_asset.clone(), get_amount);
+            // Hold the maker's proceeds until the transition is proven. The
+            // fee is rounded down, so it never exceeds `get_amount`.
+            let fee = get_amount * self.fee_bps as u128 / 10_000;
+            let maker_rebate = self.maker_rebate(get_amount, fee);
+            let referral_fee = match intent.referrer {
+                Some(_) => (fee - maker_rebate) * self.referral_bps as u128 / 10_000,
+                None => 0,
+            };
+            self.escrowed_credits.insert(
+                &sub_id,
+                &EscrowEntry {
+                    maker: intent.maker.clone(),
+                    asset: intent.dst_asset.clone(),
+                    amount: get_amount - fee,
+                    fee,
+                    referrer: intent.referrer.clone(),
+                    referral_fee,
+                    maker_rebate,
+                    claimable_after: env::block_timestamp() + self.escrow_timeout_ns,
+                },
+            );
 
             env::log_str(&format!(
                 "Matched Intent #{}: filled {}, got {}, sub_intent #{}",
@@ -408,56 +2580,37 @@ impl Orderbook {
             ));
         }
 
-        // Verify solvency (conservation of mass)
-        for (asset, net) in asset_balance.iter() {
-            assert!(
-                *net >= 0,
-                "Insufficient supply for asset {}: deficit {}",
-                asset,
-                -*net
-            );
-        }
-
-        env::log_str("Batch Match Executed Successfully");
+        self.record_batch(batch_id, solver, &sub_ids, attached);
 
-        // ---- Auto-trigger MPC signing for all sub-intents ----
-        let n = sub_ids.len() as u128;
-        let deposit_per_sign = if n > 0 {
-            env::attached_deposit().as_yoctonear() / n
-        } else {
-            0
-        };
+        // Whatever supply the makers' limits leave over is the solver's,
+        // once every fill of the batch is delivered
+        let surplus: Vec<(String, u128)> = checked
+            .net
+            .into_iter()
+            .map(|net| (net.asset, net.supply.0 - net.demand.0))
+            .filter(|(_, amount)| *amount > 0)
+            .collect();
+        self.hold_surplus(batch_id, solver, &surplus, sub_ids.len() as u64);
+        self.last_batch_surplus = surplus;
 
-        for (i, m) in matches.iter().enumerate() {
-            let sub_id = sub_ids[i];
-            let request = SignRequest {
-                payload: m.payload,
-                path: m.path.clone(),
-                key_version: 0,
-            };
+        env::log_str("Batch Match Executed Successfully");
 
-            // Each promise chain executes independently once created.
-            // We detach them so NEAR doesn't try to return a joint promise.
-            ext_signer::ext(self.mpc_contract.clone())
-                .with_attached_deposit(NearToken::from_yoctonear(deposit_per_sign))
-                .with_static_gas(Gas::from_tgas(30))
-                .sign(request)
-                .then(
-                    ext_self::ext(env::current_account_id())
-                        .with_static_gas(Gas::from_tgas(15))
-                        .on_signed(sub_id, m.transition_chain_type.clone(), m.payload),
-                )
+        // The solver's bonds are held back; the rest pays for signing, and
+        // what does not divide evenly goes back to the solver.
+        let bonds = self.transition_bond * n;
+        let deposit_per_sign = (attached - bonds) / n;
+        let unused = attached - bonds - deposit_per_sign * n;
+        if unused > 0 {
+            Promise::new(solver.clone())
+                .transfer(NearToken::from_yoctonear(unused))
                 .detach();
         }
+        Ok((sub_ids, deposit_per_sign))
     }
 
-    fn internal_transfer(&mut self, user: AccountId, asset: String, amount: u128) {
-        let mut bals = self.balances.get(&user).unwrap_or_else(|| {
-            UnorderedMap::new(format!("b{}", user).as_bytes())
-        });
-        let cur = bals.get(&asset).unwrap_or(0);
-        bals.insert(&asset, &(cur + amount));
-        self.balances.insert(&user, &bals);
+    /// MPC signer responsible for `chain_type`.
+    fn signer_for(&self, chain_type: &ChainType) -> AccountId {
+        self.chain_signers.get(chain_type).unwrap_or_else(|| self.mpc_contract.clone())
     }
 
     // ========================================================================
@@ -465,252 +2618,467 @@ impl Orderbook {
     // ========================================================================
 
     /// If MPC signing failed during batch_match and sub-intent rolled back to
-    /// Taken, the original solver (taker) can retry.
+    /// Taken, the original solver (taker) can retry. The transition is signed
+    /// exactly as requested before; see `replace_settlement_payload`.
     #[payable]
-    pub fn retry_settlement(
-        &mut self,
-        sub_intent_id: U128,
-        payload: [u8; 32],
-        path: String,
-        transition_chain_type: ChainType,
-    ) -> Promise {
-        let sub_intent_id: u64 = sub_intent_id.0 as u64;
+    #[handle_result]
+    pub fn retry_settlement(&mut self, sub_intent_id: U64) -> Result<Promise, OrderbookError> {
+        self.check_not_paused()?;
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let sub = self
+            .sub_intents
+            .get(&sub_intent_id)
+            .ok_or(OrderbookError::SubIntentNotFound { id: sub_intent_id })?;
+        if sub.status != SubIntentStatus::Taken {
+            return Err(OrderbookError::SubIntentNotTaken { id: sub_intent_id });
+        }
+        let caller = env::predecessor_account_id();
+        if sub.taker != caller {
+            return Err(OrderbookError::NotAuthorized {
+                account: caller,
+                action: format!("retry settlement of Sub-Intent {}", sub_intent_id),
+            });
+        }
+        self.check_registered_solver(&sub.taker)?;
+        let request = self
+            .settlement_requests
+            .get(&sub_intent_id)
+            .ok_or(OrderbookError::NoSettlementRequest { id: sub_intent_id })?;
+        if request.payload == SettlementRequest::UNSET_PAYLOAD {
+            return Err(OrderbookError::SettlementPayloadUnset { id: sub_intent_id });
+        }
+        self.check_treasury_path(&request.chain_type, &request.path)?;
+        let parent = self
+            .intents
+            .get(&sub.parent_intent_id)
+            .ok_or(OrderbookError::IntentNotFound { id: sub.parent_intent_id })?;
+        self.check_transition_recipient(&parent.maker, &request.chain_type, &request.recipient)?;
+
+        // The payload is the one first signed, so is the memo it carries.
+        let memo = match self.transition_expectations.get(&sub_intent_id) {
+            Some(expectation) => expectation.expected_memo,
+            None => Self::transition_memo(sub_intent_id, self.take_transition_nonce()),
+        };
+        Ok(self.request_transition_signature(sub_intent_id, request, memo))
+    }
+
+    /// The solver of a rolled-back sub-intent proposes its transition
+    /// transaction repriced, e.g. after ETH gas moved. Only the gas fields
+    /// may differ from the transaction it was matched with; the maker or
+    /// the owner then approves it with `replace_settlement_payload`.
+    pub fn propose_settlement_tx(&mut self, sub_intent_id: U64, unsigned_tx: Vec<u8>) {
+        let sub_intent_id: u64 = sub_intent_id.0;
         let sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
-        assert_eq!(sub.status, IntentStatus::Taken, "Sub-Intent must be in Taken state to retry");
-        assert_eq!(
+        assert_eq!(sub.status, SubIntentStatus::Taken, "Sub-Intent must be in Taken state to replace its payload");
+        assert_eq!(env::predecessor_account_id(), sub.taker, "Only the solver can propose a settlement transaction");
+        assert!(unsigned_tx.len() <= MAX_TRANSITION_TX_LEN, "Settlement transaction is too large");
+        let tx = self.check_repriced_settlement_tx(&sub, &unsigned_tx);
+        self.settlement_proposals.insert(&sub_intent_id, &unsigned_tx);
+        env::log_str(&format!(
+            "SETTLEMENT_TX_PROPOSED:sub_intent_id={},solver={},payload={}",
+            sub_intent_id,
             sub.taker,
-            env::predecessor_account_id(),
-            "Only the solver who matched can retry settlement"
-        );
-
-        // Move to Verifying
-        let mut sub_mut = sub.clone();
-        sub_mut.status = IntentStatus::Verifying;
-        self.sub_intents.insert(&sub_intent_id, &sub_mut);
+            hex::encode(tx.signing_hash)
+        ));
+    }
 
+    /// The maker or the owner approves the solver's proposed transaction,
+    /// naming its hash as `payload`. The retry then signs it; path, chain
+    /// and recipient stay as matched.
+    pub fn replace_settlement_payload(&mut self, sub_intent_id: U64, payload: [u8; 32]) {
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
+        assert_eq!(sub.status, SubIntentStatus::Taken, "Sub-Intent must be in Taken state to replace its payload");
         let parent = self
             .intents
             .get(&sub.parent_intent_id)
             .expect("Parent intent not found");
-
-        let expectation = TransitionExpectation {
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == parent.maker || caller == self.owner,
+            "Only the maker or the owner can replace a settlement payload"
+        );
+        let unsigned_tx = self
+            .settlement_proposals
+            .get(&sub_intent_id)
+            .unwrap_or_else(|| env::panic_str(&format!("No settlement transaction proposed for Sub-Intent {}", sub_intent_id)));
+        // Checked again: the sub-intent may have been rolled back since
+        let tx = self.check_repriced_settlement_tx(&sub, &unsigned_tx);
+        assert_eq!(tx.signing_hash, payload, "Payload does not match the proposed settlement transaction");
+        let mut request = self.settlement_requests.get(&sub_intent_id).expect("No settlement request");
+        request.recipient = self.settlement_recipient(&parent, &request);
+        request.payload = payload;
+        request.unsigned_tx = Some(unsigned_tx);
+        self.settlement_requests.insert(&sub_intent_id, &request);
+        self.settlement_proposals.remove(&sub_intent_id);
+        env::log_str(&format!(
+            "SETTLEMENT_PAYLOAD_REPLACED:sub_intent_id={},by={},payload={}",
             sub_intent_id,
-            chain_type: transition_chain_type.clone(),
-            expected_asset: parent.src_asset.clone(),
-            expected_amount: sub.amount,
-            expected_memo: format!("transition:sub:{}", sub_intent_id),
-        };
-        self.transition_expectations
-            .insert(&sub_intent_id, &expectation);
-
-        let request = SignRequest {
-            payload,
-            path,
-            key_version: 0,
-        };
+            caller,
+            hex::encode(payload)
+        ));
+    }
 
-        ext_signer::ext(self.mpc_contract.clone())
-            .with_attached_deposit(env::attached_deposit())
-            .with_static_gas(Gas::from_tgas(50))
-            .sign(request)
-            .then(
-                ext_self::ext(env::current_account_id())
-                    .with_static_gas(Gas::from_tgas(30))
-                    .on_signed(sub_intent_id, transition_chain_type, payload),
-            )
+    pub fn get_settlement_request(&self, sub_intent_id: U64) -> Option<SettlementRequest> {
+        self.settlement_requests.get(&(sub_intent_id.0))
     }
 
     // ========================================================================
     // 6. Submit Payment Proof (full ZK path, for future use)
     // ========================================================================
 
+    /// Request the transition signature for a take. An escrowed take has
+    /// no payment to prove, so its transition must be an ETH transaction
+    /// the contract checks; a take of an intent selling BTC or SOL can only
+    /// be cancelled or expire.
     #[payable]
+    #[handle_result]
     pub fn submit_payment_proof(
         &mut self,
-        sub_intent_id: U128,
+        sub_intent_id: U64,
         proof_data: Vec<u8>,
         payload: [u8; 32],
+        unsigned_tx: Option<Vec<u8>>,
         path: String,
         payment_chain_type: ChainType,
         transition_chain_type: ChainType,
         recipient: String,
         memo: String,
-    ) -> Promise {
-        let sub_intent_id: u64 = sub_intent_id.0 as u64;
-        let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
-        assert_eq!(sub.status, IntentStatus::Taken, "Sub-Intent is not in Taken state");
+        transition_recipient: String,
+        tx_hash: String,
+    ) -> Result<Promise, OrderbookError> {
+        self.check_not_paused()?;
+        self.check_proof_data(&proof_data)?;
+        self.check_path_len(&path)?;
+        self.check_fields(&[
+            ("recipient", &recipient),
+            ("memo", &memo),
+            ("transition_recipient", &transition_recipient),
+            ("tx_hash", &tx_hash),
+        ])?;
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let mut sub = self
+            .sub_intents
+            .get(&sub_intent_id)
+            .ok_or(OrderbookError::SubIntentNotFound { id: sub_intent_id })?;
+        if sub.status != SubIntentStatus::Taken {
+            return Err(OrderbookError::SubIntentNotTaken { id: sub_intent_id });
+        }
+        if sub.expires_at.is_some_and(|t| env::block_timestamp() >= t) {
+            return Err(OrderbookError::SubIntentExpired { id: sub_intent_id });
+        }
         let parent = self
             .intents
             .get(&sub.parent_intent_id)
-            .expect("Parent intent not found");
-        let expected_amount = sub
-            .amount
-            .checked_mul(parent.dst_amount)
-            .expect("amount overflow")
-            / parent.src_amount;
+            .ok_or(OrderbookError::IntentNotFound { id: sub.parent_intent_id })?;
+        assert!(parent.src_amount > 0, "Parent intent has zero src_amount");
+        let expected_amount = parent.dst_for(sub.amount);
         let expected_asset = parent.dst_asset.clone();
         let expected_memo = format!("sub:{}", sub_intent_id);
-        assert_eq!(memo, expected_memo, "memo mismatch");
+        if memo != expected_memo {
+            return Err(OrderbookError::MemoMismatch { expected: expected_memo, got: memo });
+        }
+        self.check_transition_recipient(&parent.maker, &transition_chain_type, &transition_recipient)?;
+        self.check_treasury_path(&transition_chain_type, &path)?;
+        if let Some(tx) = unsigned_tx.as_ref().filter(|tx| tx.len() > MAX_TRANSITION_TX_LEN) {
+            return Err(OrderbookError::TransitionTxTooLarge { intent_id: parent.id, len: tx.len(), max: MAX_TRANSITION_TX_LEN });
+        }
+        // Checked as a match's transition is. An escrowed take has no
+        // payment proof behind it, so only a transaction the contract can
+        // check may be signed for it.
+        // The memo `get_next_take_memo` shows; its nonce is taken once every
+        // check has passed
+        let transition_memo = Self::transition_memo(sub_intent_id, self.next_transition_nonce);
+        if transition_chain_type == ChainType::ETH {
+            self.check_eth_transition(&parent, &transition_recipient, sub.amount, &transition_memo, &payload, unsigned_tx.as_ref())?;
+        } else if sub.escrow > 0 {
+            return Err(OrderbookError::UnsupportedTransitionChain { chain_type: transition_chain_type });
+        }
+        if sub.escrow == 0 {
+            self.check_payment_not_consumed(&payment_chain_type, &tx_hash)?;
+        }
+        self.take_transition_nonce();
 
-        sub.status = IntentStatus::Verifying;
+        sub.status = SubIntentStatus::Verifying;
         self.sub_intents.insert(&sub_intent_id, &sub);
 
-        ext_light_client::ext(self.light_client_contract.clone())
-            .with_static_gas(Gas::from_tgas(50))
-            .verify_payment_proof(
-                payment_chain_type,
+        // Escrowed takes already hold the maker's payment, so there is no
+        // external payment to prove (and `tx_hash` is unused); go straight
+        // to signing the transition.
+        if sub.escrow > 0 {
+            return Ok(self.request_transition_signature(
+                sub_intent_id,
+                SettlementRequest {
+                    payload,
+                    path,
+                    scheme: SignatureScheme::for_chain(&transition_chain_type),
+                    chain_type: transition_chain_type,
+                    recipient: transition_recipient,
+                    unsigned_tx,
+                },
+                transition_memo,
+            ));
+        }
+
+        // `on_proof_verified` takes any valid proof, so the light client
+        // must spend it whatever its depth.
+        let expected_asset_kind = self.expected_asset_kind(&expected_asset);
+        Ok(ext_light_client::ext(self.light_client_contract.clone())
+            .with_static_gas(LIGHT_CLIENT_GAS)
+            .consume_payment_proof(
+                payment_chain_type.clone(),
                 proof_data,
                 recipient,
                 expected_asset,
                 U128(expected_amount),
                 memo,
+                tx_hash.clone(),
+                expected_asset_kind,
+                0,
             )
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_static_gas(Gas::from_tgas(80))
                     .with_attached_deposit(env::attached_deposit())
                     .on_proof_verified(
-                        U128(sub_intent_id.into()),
+                        U64(sub_intent_id),
                         payload,
+                        unsigned_tx,
                         path,
                         transition_chain_type,
+                        transition_recipient,
+                        transition_memo,
+                        payment_chain_type,
+                        tx_hash,
                     ),
-            )
+            ))
     }
 
     #[private]
     #[payable]
     pub fn on_proof_verified(
         &mut self,
-        sub_intent_id: U128,
+        sub_intent_id: U64,
         payload: [u8; 32],
+        unsigned_tx: Option<Vec<u8>>,
         path: String,
         transition_chain_type: ChainType,
-        #[callback_result] verify_result: Result<bool, PromiseError>,
-    ) -> Promise {
-        let is_valid = verify_result.unwrap_or(false);
-        let sub_intent_id_u64: u64 = sub_intent_id.0 as u64;
-
-        if is_valid {
-            let mut sub = self.sub_intents.get(&sub_intent_id_u64).unwrap();
-            sub.status = IntentStatus::Verifying;
-            self.sub_intents.insert(&sub_intent_id_u64, &sub);
-            let parent = self
-                .intents
-                .get(&sub.parent_intent_id)
-                .expect("Parent intent not found");
-            let expectation = TransitionExpectation {
-                sub_intent_id: sub_intent_id_u64,
-                chain_type: transition_chain_type.clone(),
-                expected_asset: parent.src_asset.clone(),
-                expected_amount: sub.amount,
-                expected_memo: format!("transition:sub:{}", sub_intent_id_u64),
-            };
-            self.transition_expectations
-                .insert(&sub_intent_id_u64, &expectation);
-
-            let request = SignRequest {
-                payload,
-                path,
-                key_version: 0,
-            };
+        recipient: String,
+        transition_memo: String,
+        payment_chain_type: ChainType,
+        tx_hash: String,
+        #[callback_result] verify_result: Result<VerificationResult, PromiseError>,
+    ) -> Promise {
+        let outcome = verify_result.map(|verification| verification.outcome);
+        let sub_intent_id_u64: u64 = sub_intent_id.0;
+        let sub = self.sub_intents.get(&sub_intent_id_u64).expect("Sub-Intent not found");
+        assert_eq!(
+            sub.status,
+            SubIntentStatus::Verifying,
+            "Sub-Intent {} is no longer awaiting its proof",
+            sub_intent_id_u64
+        );
 
-            ext_signer::ext(self.mpc_contract.clone())
-                .with_attached_deposit(env::attached_deposit())
-                .with_static_gas(Gas::from_tgas(50))
-                .sign(request)
-                .then(
-                    ext_self::ext(env::current_account_id())
-                        .with_static_gas(Gas::from_tgas(30))
-                        .on_signed(sub_intent_id.0 as u64, transition_chain_type, payload),
-                )
+        if matches!(outcome, Ok(VerifyOutcome::Valid)) {
+            // Re-checked here: another sub-intent may have presented the
+            // same payment while this proof was in flight.
+            self.assert_payment_not_consumed(&payment_chain_type, &tx_hash);
+            self.consumed_payments.insert(&(payment_chain_type, tx_hash));
+            self.request_transition_signature(
+                sub_intent_id_u64,
+                SettlementRequest {
+                    payload,
+                    path,
+                    scheme: SignatureScheme::for_chain(&transition_chain_type),
+                    chain_type: transition_chain_type,
+                    recipient,
+                    unsigned_tx,
+                },
+                transition_memo,
+            )
         } else {
-            env::panic_str("Invalid Proof");
+            env::panic_str(&format!("Invalid Proof: {}", Self::rejection_reason(&outcome)));
         }
     }
 
+    /// Taker backs out of an escrowed take before it settles. The fill
+    /// returns to the parent intent and the escrow to the taker. Requires
+    /// 1 yoctoNEAR.
+    #[payable]
+    pub fn cancel_sub_intent(&mut self, sub_intent_id: U64) {
+        assert_one_yocto();
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
+        assert_eq!(
+            sub.taker,
+            env::predecessor_account_id(),
+            "Only the taker can cancel the sub-intent"
+        );
+        assert_eq!(sub.status, SubIntentStatus::Taken, "Sub-Intent is not in Taken state");
+        assert!(sub.escrow > 0, "Sub-Intent {} has no taker escrow", sub_intent_id);
+
+        sub.status = SubIntentStatus::Cancelled;
+        sub.closed_at = Some(env::block_timestamp());
+        self.refund_taker_escrow(&mut sub);
+        self.sub_intents.insert(&sub_intent_id, &sub);
+        self.record_sub_intent_outcome(&sub.status);
+        self.unwind_fill(&sub);
+        env::log_str(&format!(
+            "SUB_INTENT_CANCELLED:sub_intent_id={},intent_id={}",
+            sub_intent_id, sub.parent_intent_id
+        ));
+    }
+
+    /// Anyone may expire a take that was never submitted. The fill returns
+    /// to the parent intent, the escrow to the taker, and the sub-intent
+    /// record is deleted.
+    pub fn expire_sub_intent(&mut self, sub_intent_id: U64) {
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
+        assert_eq!(sub.status, SubIntentStatus::Taken, "Sub-Intent is not in Taken state");
+        assert!(
+            sub.expires_at.is_some_and(|t| env::block_timestamp() >= t),
+            "Sub-Intent {} has not expired yet",
+            sub_intent_id
+        );
+
+        let initial_usage = env::storage_usage();
+        self.refund_taker_escrow(&mut sub);
+        self.unwind_fill(&sub);
+        self.remove_sub_intent(&sub);
+        self.record_sub_intent_outcome(&SubIntentStatus::Expired);
+        self.settle_storage(&sub.taker, initial_usage);
+        env::log_str(&format!(
+            "SUB_INTENT_EXPIRED:sub_intent_id={},intent_id={},restored={}",
+            sub_intent_id, sub.parent_intent_id, sub.amount
+        ));
+    }
+
     // ========================================================================
     // 7. Withdraw (with refund on MPC failure)
     // ========================================================================
 
+    /// Withdraw `amount` of `asset` to `destination` on `chain_type`.
+    /// `unsigned_tx` is the hex-serialized transaction to be signed; the
+    /// contract checks that it pays exactly this withdrawal and derives the
+    /// MPC payload from it. Native ETH and ERC-20 tokens with a registered
+    /// contract are supported so far. Returns the withdrawal id; the
+    /// signature arrives later through `on_signed`.
+    /// The attached deposit pays the MPC signing fee and must be non-zero,
+    /// which also keeps function-call access keys from withdrawing.
     #[payable]
+    #[handle_result]
     pub fn withdraw(
         &mut self,
         asset: String,
         amount: U128,
-        payload: [u8; 32],
+        destination: String,
+        unsigned_tx: String,
         path: String,
         chain_type: ChainType,
-    ) -> Promise {
-        let amount: u128 = amount.into();
-        let user = env::predecessor_account_id();
-        let mut user_balances = self.balances.get(&user).expect("User balance not found");
-        let current = user_balances.get(&asset).unwrap_or(0);
-        assert!(current >= amount, "Insufficient funds to withdraw");
-
-        // Deduct balance
-        user_balances.insert(&asset, &(current - amount));
-        self.balances.insert(&user, &user_balances);
-
-        // Track pending withdrawal so we can refund on MPC failure
-        let wd_id = self.next_id;
-        self.next_id += 1;
-        self.pending_withdrawals.insert(
-            &wd_id,
-            &PendingWithdrawal {
-                user: user.clone(),
-                asset: asset.clone(),
-                amount,
-            },
-        );
-
-        env::log_str(&format!("Withdrawing {} {} for user {} (wd_id={})", amount, asset, user, wd_id));
-
-        let request = SignRequest {
-            payload,
-            path,
-            key_version: 0,
-        };
+        scheme: SignatureScheme,
+    ) -> Result<U64, OrderbookError> {
+        self.check_not_paused()?;
+        if env::attached_deposit().is_zero() {
+            return Err(OrderbookError::InsufficientDeposit { required: 1, attached: 0 });
+        }
+        let item = WithdrawItem { asset, amount, destination, unsigned_tx, path, chain_type, scheme };
+        let (wd_id, payload) = self.queue_withdrawal(&env::predecessor_account_id(), &item)?;
+        let request = SignRequest::new(&payload, item.path.clone(), item.scheme);
 
-        ext_signer::ext(self.mpc_contract.clone())
+        ext_signer::ext(self.signer_for(&item.chain_type))
             .with_attached_deposit(env::attached_deposit())
             .with_static_gas(Gas::from_tgas(50))
             .sign(request)
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_static_gas(Gas::from_tgas(30))
-                    .on_signed(wd_id, chain_type, payload),
+                    .on_signed(SignTarget::Withdrawal(wd_id), item.chain_type, payload, item.path),
             )
+            .detach();
+        Ok(U64(wd_id))
+    }
+
+    /// Several withdrawals in one call, e.g. every asset received from a
+    /// ring trade. Each item is checked and deducted as in `withdraw` before
+    /// any signature is requested, so one bad item rejects the whole call.
+    /// The attached deposit is split evenly between the signing requests.
+    /// Each item becomes its own pending withdrawal: a failed signature
+    /// refunds only that item. Returns the withdrawal ids in item order.
+    #[payable]
+    pub fn batch_withdraw(&mut self, items: Vec<WithdrawItem>) -> Vec<U64> {
+        self.assert_not_paused();
+        assert!(!items.is_empty(), "No withdrawals in batch");
+        assert!(
+            items.len() <= MAX_BATCH_WITHDRAW_ITEMS,
+            "Max {} withdrawals per batch (gas limit)",
+            MAX_BATCH_WITHDRAW_ITEMS
+        );
+        let n = items.len() as u128;
+        let deposit_per_sign = env::attached_deposit().as_yoctonear() / n;
+        assert!(
+            deposit_per_sign > 0,
+            "Requires attached deposit of at least 1 yoctoNEAR per withdrawal"
+        );
+
+        let user = env::predecessor_account_id();
+        let queued: Vec<(u64, Vec<u8>)> = items
+            .iter()
+            .map(|item| self.queue_withdrawal(&user, item).unwrap_or_else(|error| error.panic()))
+            .collect();
+        for (item, (wd_id, payload)) in items.into_iter().zip(&queued) {
+            let request = SignRequest::new(payload, item.path.clone(), item.scheme);
+            ext_signer::ext(self.signer_for(&item.chain_type))
+                .with_attached_deposit(NearToken::from_yoctonear(deposit_per_sign))
+                .with_static_gas(Gas::from_tgas(30))
+                .sign(request)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(Gas::from_tgas(15))
+                        .on_signed(SignTarget::Withdrawal(*wd_id), item.chain_type, payload.clone(), item.path),
+                )
+                .detach();
+        }
+        queued.into_iter().map(|(wd_id, _)| U64(wd_id)).collect()
     }
 
     // ========================================================================
     // 8. Transition Verification
     // ========================================================================
 
+    /// Prove the transition of a settled sub-intent. The light client is
+    /// asked about the recipient recorded when the transition was signed.
     #[payable]
     pub fn verify_transition_completion(
         &mut self,
-        sub_intent_id: U128,
+        sub_intent_id: U64,
         proof_data: Vec<u8>,
-        recipient: String,
         tx_hash: String,
     ) -> Promise {
-        let sub_intent_id: u64 = sub_intent_id.0 as u64;
+        if let Err(error) = self.check_proof_data(&proof_data).and_then(|_| self.check_fields(&[("tx_hash", &tx_hash)])) {
+            error.panic();
+        }
+        let sub_intent_id: u64 = sub_intent_id.0;
         let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
-        assert_eq!(sub.status, IntentStatus::Settled, "Sub-Intent is not ready for transition verification");
+        assert_eq!(sub.status, SubIntentStatus::Settled, "Sub-Intent is not ready for transition verification");
         let expectation = self
             .transition_expectations
             .get(&sub_intent_id)
             .expect("Transition expectation not found");
-        sub.status = IntentStatus::TransitionVerifying;
+        assert!(
+            !self.consumed_transitions.contains(&(expectation.chain_type.clone(), tx_hash.clone())),
+            "Transition tx {} already proved another sub-intent",
+            tx_hash
+        );
+        sub.status = SubIntentStatus::TransitionVerifying;
         self.sub_intents.insert(&sub_intent_id, &sub);
 
         ext_light_client::ext(self.light_client_contract.clone())
-            .with_static_gas(Gas::from_tgas(50))
+            .with_static_gas(LIGHT_CLIENT_GAS)
             .verify_transition_proof(
                 expectation.chain_type.clone(),
                 proof_data,
-                recipient,
+                expectation.expected_recipient.clone(),
                 expectation.expected_asset.clone(),
                 U128(expectation.expected_amount),
                 expectation.expected_memo.clone(),
@@ -719,34 +3087,345 @@ impl Orderbook {
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_static_gas(Gas::from_tgas(40))
-                    .on_transition_verified(U128(sub_intent_id.into()), tx_hash),
+                    .on_transition_verified(U64(sub_intent_id), tx_hash),
             )
     }
 
     #[private]
     pub fn on_transition_verified(
         &mut self,
-        sub_intent_id: U128,
+        sub_intent_id: U64,
         tx_hash: String,
-        #[callback_result] verify_result: Result<bool, PromiseError>,
+        #[callback_result] verify_result: Result<VerifyOutcome, PromiseError>,
     ) -> String {
-        let id = sub_intent_id.0 as u64;
-        let is_valid = verify_result.unwrap_or(false);
+        let id = sub_intent_id.0;
+        let is_valid = matches!(verify_result, Ok(VerifyOutcome::Valid));
         let mut sub = self.sub_intents.get(&id).expect("Sub-Intent not found");
-        if is_valid {
-            sub.status = IntentStatus::Completed;
+        if sub.status == SubIntentStatus::ForceCancelled {
+            // Closed by the owner while the proof was in flight
+            return "ForceCancelled".to_string();
+        }
+        // Another sub-intent may have been proven with the same tx while
+        // this proof was in flight.
+        let consumed_key = self
+            .transition_expectations
+            .get(&id)
+            .map(|expectation| (expectation.chain_type, tx_hash.clone()));
+        let replayed = consumed_key
+            .as_ref()
+            .is_some_and(|key| self.consumed_transitions.contains(key));
+        if is_valid && !replayed {
+            if let Some(key) = &consumed_key {
+                self.consumed_transitions.insert(key);
+            }
+            sub.status = SubIntentStatus::Completed;
+            sub.closed_at = Some(env::block_timestamp());
+            sub.transition_tx_hash = Some(tx_hash.clone());
             self.sub_intents.insert(&id, &sub);
+            self.record_sub_intent_outcome(&sub.status);
             self.transition_expectations.remove(&id);
+            self.signatures.remove(&SignTarget::SubIntent(id));
+            if sub.escrow > 0 {
+                let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+                self.internal_credit(&parent.maker, &parent.dst_asset, sub.escrow, BalanceChangeReason::TakerEscrowReleased, Some(id));
+                env::log_str(&format!(
+                    "TAKER_ESCROW_RELEASED:sub_intent_id={},maker={},asset={},amount={}",
+                    id, parent.maker, parent.dst_asset, sub.escrow
+                ));
+                sub.escrow = 0;
+                self.sub_intents.insert(&id, &sub);
+            }
+            let solver = sub.taker.clone();
+            self.pay_out_bond(&mut sub, &solver);
+            self.sub_intents.insert(&id, &sub);
+            if let Some(escrow) = self.escrowed_credits.remove(&id) {
+                self.release_escrow(id, escrow);
+            }
+            self.settle_surplus(&sub);
             env::log_str(&format!("TRANSITION_VERIFIED:sub_intent_id={},tx_hash={}", id, tx_hash));
             "TransitionVerified".to_string()
         } else {
-            sub.status = IntentStatus::Settled;
+            sub.status = SubIntentStatus::Settled;
             self.sub_intents.insert(&id, &sub);
-            env::log_str(&format!("TRANSITION_VERIFY_FAILED:sub_intent_id={}", id));
+            let reason = if replayed { "Replayed".to_string() } else { Self::rejection_reason(&verify_result) };
+            env::log_str(&format!("TRANSITION_VERIFY_FAILED:sub_intent_id={},reason={}", id, reason));
             "TransitionVerifyFailed".to_string()
         }
     }
 
+    /// Unwind a matched fill whose signing never completed in time: the
+    /// escrowed proceeds are dropped and the maker's source funds return to
+    /// the parent intent (or to the maker's balance if it is no longer open).
+    /// Only a `Taken` sub-intent, which has no signed transaction out, is
+    /// unwound; a signed one is failed by `claim_transition_timeout`.
+    #[handle_result]
+    pub fn claim_escrow_timeout(&mut self, sub_intent_id: U64) -> Result<(), OrderbookError> {
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let escrow = self
+            .escrowed_credits
+            .get(&sub_intent_id)
+            .ok_or(OrderbookError::NoEscrow { id: sub_intent_id })?;
+        if env::block_timestamp() < escrow.claimable_after {
+            return Err(OrderbookError::EscrowTimeoutNotReached {
+                id: sub_intent_id,
+                claimable_after: escrow.claimable_after,
+            });
+        }
+        let mut sub = self
+            .sub_intents
+            .get(&sub_intent_id)
+            .ok_or(OrderbookError::SubIntentNotFound { id: sub_intent_id })?;
+        if sub.status != SubIntentStatus::Taken {
+            return Err(OrderbookError::SubIntentNotTaken { id: sub_intent_id });
+        }
+
+        self.escrowed_credits.remove(&sub_intent_id);
+        self.transition_expectations.remove(&sub_intent_id);
+        // A failed sign is not the solver's fault
+        let taker = sub.taker.clone();
+        self.pay_out_bond(&mut sub, &taker);
+        sub.status = SubIntentStatus::Cancelled;
+        sub.closed_at = Some(env::block_timestamp());
+        self.sub_intents.insert(&sub_intent_id, &sub);
+        self.record_sub_intent_outcome(&sub.status);
+
+        self.unwind_fill(&sub);
+
+        env::log_str(&format!(
+            "ESCROW_TIMEOUT_CLAIMED:sub_intent_id={},intent_id={},restored={}",
+            sub_intent_id, sub.parent_intent_id, sub.amount
+        ));
+        Ok(())
+    }
+
+    /// Fail a signed sub-intent whose transition was not proven by its
+    /// deadline. Anyone may call it: the solver's bond goes to the maker and
+    /// the sub-intent can no longer be verified. The signed transaction is
+    /// public and may still land, so the fill is not unwound and the
+    /// taker's escrow and the maker's proceeds stay held until the owner
+    /// resolves them with `force_cancel_sub_intent`.
+    pub fn claim_transition_timeout(&mut self, sub_intent_id: U64) {
+        let sub_intent_id: u64 = sub_intent_id.0;
+        let mut sub = self.sub_intents.get(&sub_intent_id).expect("Sub-Intent not found");
+        assert_eq!(sub.status, SubIntentStatus::Settled, "Sub-Intent is not awaiting its transition");
+        let deadline = self
+            .transition_expectations
+            .get(&sub_intent_id)
+            .and_then(|e| e.transition_deadline)
+            .expect("Sub-Intent has no transition deadline");
+        assert!(env::block_timestamp() >= deadline, "Transition deadline not reached");
+
+        let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+        let slashed = sub.bond;
+        self.pay_out_bond(&mut sub, &parent.maker);
+        self.transition_expectations.remove(&sub_intent_id);
+        self.signatures.remove(&SignTarget::SubIntent(sub_intent_id));
+        sub.status = SubIntentStatus::Failed;
+        sub.closed_at = Some(env::block_timestamp());
+        self.sub_intents.insert(&sub_intent_id, &sub);
+        self.record_sub_intent_outcome(&sub.status);
+
+        env::log_str(&format!(
+            "TRANSITION_TIMEOUT_CLAIMED:sub_intent_id={},solver={},maker={},slashed={}",
+            sub_intent_id, sub.taker, parent.maker, slashed
+        ));
+    }
+
+    /// Owner escape hatch for a sub-intent stuck in any non-final state, or
+    /// failed by `claim_transition_timeout` with its escrows still held.
+    /// Its expectation, signature and escrows are cleared, the solver's bond
+    /// is returned and a taker's escrow refunded. With `refund_maker` the
+    /// fill is unwound as in `claim_escrow_timeout`; without it the transfer
+    /// is taken as delivered and the maker receives the escrowed proceeds.
+    pub fn force_cancel_sub_intent(&mut self, sub_intent_id: U64, refund_maker: bool, reason: String) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can force-cancel");
+        self.internal_force_cancel_sub_intent(sub_intent_id.0, refund_maker, &reason);
+    }
+
+    /// Owner closes an intent regardless of state. Live sub-intents are
+    /// force-cancelled with their fills unwound, then everything not already
+    /// delivered goes back to the maker.
+    pub fn force_cancel_intent(&mut self, intent_id: U64, reason: String) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can force-cancel");
+        let intent_id: u64 = intent_id.0;
+        let intent = self.intents.get(&intent_id).expect("Intent not found");
+        assert!(!intent.status.is_final(), "Intent {} is already final", intent_id);
+
+        let sub_ids = self.sub_intents_by_parent.get(&intent_id).map(|ids| ids.to_vec()).unwrap_or_default();
+        for sub_id in sub_ids {
+            if self.sub_intents.get(&sub_id).is_some_and(|sub| !sub.status.is_final()) {
+                self.internal_force_cancel_sub_intent(sub_id, true, &reason);
+            }
+        }
+
+        let mut intent = self.intents.get(&intent_id).expect("Intent not found");
+        let refund = intent.src_amount - intent.filled_amount;
+        intent.status = IntentStatus::ForceCancelled;
+        intent.closed_at = Some(env::block_timestamp());
+        self.save_intent(&intent);
+        self.internal_credit(&intent.maker, &intent.src_asset, refund, BalanceChangeReason::IntentRefund, Some(intent.id));
+        env::log_str(&format!(
+            "INTENT_FORCE_CANCELLED:intent_id={},admin={},refunded={},reason={}",
+            intent_id, self.owner, refund, reason
+        ));
+    }
+
+    pub fn set_transition_timeout(&mut self, timeout_ns: u64) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set transition timeout"
+        );
+        self.transition_timeout_ns = timeout_ns;
+    }
+
+    /// Applies to matches made after the change; existing bonds keep their amount.
+    pub fn set_transition_bond(&mut self, bond: U128) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set transition bond"
+        );
+        self.transition_bond = bond.0;
+    }
+
+    pub fn set_min_sign_deposit(&mut self, amount: U128) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set sign deposit"
+        );
+        self.min_sign_deposit = amount.0;
+    }
+
+    pub fn set_max_open_intents_per_account(&mut self, max: u64) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set the open intent limit"
+        );
+        self.max_open_intents_per_account = max;
+    }
+
+    pub fn set_sub_intent_timeout(&mut self, timeout_ns: u64) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set sub-intent timeout"
+        );
+        self.sub_intent_timeout_ns = timeout_ns;
+    }
+
+    pub fn set_escrow_timeout(&mut self, timeout_ns: u64) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set escrow timeout"
+        );
+        self.escrow_timeout_ns = timeout_ns;
+    }
+
+    /// First step of an ownership transfer. A newer proposal replaces any
+    /// earlier one.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can propose a new owner");
+        env::log_str(&format!("OWNERSHIP_PROPOSED:owner={},proposed={}", self.owner, new_owner));
+        self.pending_owner = Some(new_owner);
+    }
+
+    pub fn cancel_owner_proposal(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can cancel the proposal");
+        assert!(self.pending_owner.take().is_some(), "No pending owner");
+    }
+
+    /// Second step: the proposed account takes over.
+    pub fn accept_ownership(&mut self) {
+        let caller = env::predecessor_account_id();
+        assert_eq!(
+            self.pending_owner.as_ref(),
+            Some(&caller),
+            "Only the proposed owner can accept ownership"
+        );
+        let previous = std::mem::replace(&mut self.owner, caller);
+        self.pending_owner = None;
+        env::log_str(&format!(
+            "OWNERSHIP_TRANSFERRED:previous={},owner={}",
+            previous, self.owner
+        ));
+    }
+
+    /// Halt user-facing entry points. Callbacks keep running so in-flight
+    /// promises can still settle or refund.
+    pub fn pause(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can pause");
+        self.paused = true;
+        env::log_str("CONTRACT_PAUSED");
+    }
+
+    pub fn unpause(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can unpause");
+        assert!(!self.is_migrating(), "Finish the migration with migrate_batch first");
+        self.paused = false;
+        env::log_str("CONTRACT_UNPAUSED");
+    }
+
+    fn assert_not_paused(&self) {
+        if let Err(error) = self.check_not_paused() {
+            error.panic();
+        }
+    }
+
+    pub(crate) fn check_not_paused(&self) -> Result<(), OrderbookError> {
+        if self.paused {
+            return Err(OrderbookError::ContractPaused);
+        }
+        Ok(())
+    }
+
+    /// Halt or resume one market. Applies to both orderings of the pair.
+    pub fn set_pair_paused(&mut self, src_asset: String, dst_asset: String, paused: bool) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can pause pairs");
+        let pair = Self::pair_key(&src_asset, &dst_asset);
+        if paused {
+            self.paused_pairs.insert(&pair);
+        } else {
+            self.paused_pairs.remove(&pair);
+        }
+        env::log_str(&format!("PAIR_PAUSED:pair={},paused={}", pair, paused));
+    }
+
+    pub fn get_paused_pairs(&self) -> Vec<String> {
+        self.paused_pairs.to_vec()
+    }
+
+    /// Order-independent key for a market, e.g. "ETH/SOL".
+    fn pair_key(a: &str, b: &str) -> String {
+        let (a, b) = (a.to_uppercase(), b.to_uppercase());
+        if a <= b {
+            format!("{}/{}", a, b)
+        } else {
+            format!("{}/{}", b, a)
+        }
+    }
+
+    pub(crate) fn check_pair_not_paused(&self, src_asset: &str, dst_asset: &str) -> Result<(), OrderbookError> {
+        let pair = Self::pair_key(src_asset, dst_asset);
+        if self.paused_pairs.contains(&pair) {
+            return Err(OrderbookError::PairPaused { pair });
+        }
+        Ok(())
+    }
+
+    /// Owner drops a stored signature that is no longer needed.
+    pub fn remove_signature(&mut self, target: SignTarget) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can remove signatures"
+        );
+        self.signatures.remove(&target);
+    }
+
     // ========================================================================
     // 9. MPC Sign Callback (shared by batch_match, retry, withdraw)
     // ========================================================================
@@ -754,57 +3433,180 @@ impl Orderbook {
     #[private]
     pub fn on_signed(
         &mut self,
-        id: u64,
+        target: SignTarget,
         chain_type: ChainType,
-        payload: [u8; 32],
+        payload: Vec<u8>,
+        path: String,
         #[callback_result] call_result: Result<SignResult, PromiseError>,
     ) -> String {
-        match call_result {
-            Ok(res) => {
-                // Sub-intent settlement flow
-                if let Some(mut sub) = self.sub_intents.get(&id) {
-                    if sub.status == IntentStatus::Verifying {
-                        sub.status = IntentStatus::Settled;
+        let id = target.id();
+        // A signature that does not check out is handled like a failed call
+        let verified = match call_result {
+            Ok(res) => match self.check_signature(&chain_type, &payload, &path, &res) {
+                Ok(public_key) => Ok((res, public_key)),
+                Err(reason) => {
+                    env::log_str(&format!("SIGNATURE_REJECTED:target={:?},reason={}", target, reason));
+                    Err(SignFailureReason::InvalidSignature)
+                }
+            },
+            Err(_) => Err(SignFailureReason::PromiseFailed),
+        };
+        match verified {
+            Ok((res, public_key)) => {
+                let (transition_memo, destination, asset, unsigned_tx) = match target {
+                    // Sub-intent settlement flow
+                    SignTarget::SubIntent(id) => {
+                        let mut sub = match self.sub_intents.get(&id) {
+                            Some(sub) if sub.status == SubIntentStatus::Verifying => sub,
+                            // Closed while signing: record nothing and emit no
+                            // signature, so the relayer has nothing to broadcast.
+                            other => {
+                                crate::ledger::log_event(
+                                    "signed_after_cancel",
+                                    SignedAfterCancelEvent {
+                                        sub_intent_id: U64(id),
+                                        chain_type,
+                                        status: other.map(|sub| sub.status),
+                                    },
+                                );
+                                return "SignedAfterCancel".to_string();
+                            }
+                        };
+                        sub.status = SubIntentStatus::Settled;
                         self.sub_intents.insert(&id, &sub);
+                        if let Some(mut expectation) = self.transition_expectations.get(&id) {
+                            expectation.transition_deadline = Some(env::block_timestamp() + self.transition_timeout_ns);
+                            self.transition_expectations.insert(&id, &expectation);
+                        }
+                        // The memo the light client will expect
+                        match self.transition_expectations.get(&id) {
+                            Some(expectation) => (
+                                expectation.expected_memo,
+                                Some(expectation.expected_recipient),
+                                Some(expectation.expected_asset),
+                                expectation.unsigned_tx.map(hex::encode),
+                            ),
+                            None => (String::new(), None, None, None),
+                        }
                     }
-                }
-                // Withdrawal flow — just clean up tracking
-                if self.pending_withdrawals.get(&id).is_some() {
-                    self.pending_withdrawals.remove(&id);
-                }
+                    // Withdrawal flow — clean up tracking, keep the record
+                    SignTarget::Withdrawal(id) => {
+                        let wd = self.pending_withdrawals.remove(&id);
+                        if let Some(wd) = &wd {
+                            self.remove_pending_withdrawal_index(&wd.user, id);
+                            self.close_withdrawal(id, WithdrawalStatus::Signed);
+                            self.stats.total_withdrawals += 1;
+                            self.record_debit(&wd.asset, wd.amount);
+                            self.record_debit(wd.chain_type.native_asset(), wd.gas_fee);
+                        }
+                        let (destination, asset) = wd.map(|wd| (wd.destination, wd.asset)).unzip();
+                        (format!("withdraw:{}", id), destination, asset, None)
+                    }
+                };
 
-                env::log_str(&format!("Operation {} Signed Trustlessly!", id));
+                env::log_str(&format!("Operation {:?} Signed Trustlessly!", target));
+
+                self.signatures.insert(
+                    &target,
+                    &StoredSignature {
+                        target,
+                        chain_type: chain_type.clone(),
+                        payload: hex::encode(&payload),
+                        signature: res.clone(),
+                    },
+                );
 
                 // Emit standard event for Relayer
+                let scheme = res.scheme();
+                let (big_r, s, recovery_id, signature) = match res {
+                    SignResult::Secp256k1 { big_r, s, recovery_id } => {
+                        (Some(big_r.affine_point), Some(s.scalar), Some(recovery_id), None)
+                    }
+                    SignResult::Ed25519 { signature } => (None, None, None, Some(hex::encode(signature))),
+                };
+                let batch_id = match target {
+                    SignTarget::SubIntent(id) => self.sub_intents.get(&id).and_then(|sub| sub.batch_id).map(U64),
+                    SignTarget::Withdrawal(_) => None,
+                };
                 let event = SignatureEvent {
-                    sub_intent_id: id,
+                    sub_intent_id: U64(id),
+                    target,
                     chain_type,
                     payload: hex::encode(payload),
-                    big_r: res.big_r.affine_point,
-                    s: res.s.scalar,
-                    recovery_id: res.recovery_id,
-                    transition_memo: format!("transition:sub:{}", id),
+                    scheme,
+                    big_r,
+                    s,
+                    recovery_id,
+                    signature,
+                    public_key,
+                    transition_memo,
+                    destination,
+                    asset,
+                    unsigned_tx,
+                    batch_id,
                 };
                 let event_json = near_sdk::serde_json::to_string(&event).unwrap();
                 env::log_str(&format!("EVENT_JSON:{}", event_json));
 
                 "Success".to_string()
             }
-            Err(_) => {
-                // Sub-intent rollback
-                if let Some(mut sub) = self.sub_intents.get(&id) {
-                    sub.status = IntentStatus::Taken;
-                    self.sub_intents.insert(&id, &sub);
-                    self.transition_expectations.remove(&id);
-                }
-                // Withdrawal refund
-                if let Some(wd) = self.pending_withdrawals.get(&id) {
-                    self.internal_transfer(wd.user.clone(), wd.asset.clone(), wd.amount);
-                    self.pending_withdrawals.remove(&id);
-                    env::log_str(&format!(
-                        "WITHDRAW_REFUNDED:user={},asset={},amount={}",
-                        wd.user, wd.asset, wd.amount
-                    ));
+            Err(reason) => {
+                self.stats.sign_failure_count += 1;
+                match target {
+                    // Sub-intent rollback (only if this sign request is still the live one)
+                    SignTarget::SubIntent(id) => {
+                        if let Some(mut sub) = self
+                            .sub_intents
+                            .get(&id)
+                            .filter(|sub| sub.status == SubIntentStatus::Verifying)
+                        {
+                            // The expectation stays: a repriced retry must
+                            // carry the same memo
+                            sub.status = SubIntentStatus::Taken;
+                            self.sub_intents.insert(&id, &sub);
+                        }
+                        crate::ledger::log_event(
+                            "sub_intent_sign_failed",
+                            SubIntentSignFailedEvent { sub_intent_id: U64(id), chain_type, reason },
+                        );
+                    }
+                    // Withdrawal refund
+                    SignTarget::Withdrawal(id) => {
+                        if let Some(wd) = self.pending_withdrawals.remove(&id) {
+                            self.remove_pending_withdrawal_index(&wd.user, id);
+                            self.close_withdrawal(id, WithdrawalStatus::Refunded);
+                            self.release_withdrawal_quota(&wd.user, &wd.asset, wd.amount, wd.requested_at);
+                            self.internal_credit(&wd.user, &wd.asset, wd.amount, BalanceChangeReason::WithdrawalRefund, Some(id));
+                            if wd.gas_fee > 0 {
+                                self.internal_credit(
+                                    &wd.user,
+                                    wd.chain_type.native_asset(),
+                                    wd.gas_fee,
+                                    BalanceChangeReason::WithdrawalRefund,
+                                    Some(id),
+                                );
+                            }
+                            // The nonce was never used on chain, so hand it back
+                            // unless a later withdrawal has already taken the next one.
+                            if wd.chain_type == ChainType::ETH && self.withdrawal_nonces.get(&wd.user) == Some(wd.nonce + 1) {
+                                self.withdrawal_nonces.insert(&wd.user, &wd.nonce);
+                            }
+                            env::log_str(&format!(
+                                "WITHDRAW_REFUNDED:user={},asset={},amount={},gas_fee={}",
+                                wd.user, wd.asset, wd.amount, wd.gas_fee
+                            ));
+                            crate::ledger::log_event(
+                                "withdrawal_sign_failed",
+                                WithdrawalSignFailedEvent {
+                                    wd_id: U64(id),
+                                    user: wd.user,
+                                    asset: wd.asset,
+                                    amount: U128(wd.amount),
+                                    reason,
+                                },
+                            );
+                        }
+                    }
                 }
                 "Failed".to_string()
             }
@@ -815,34 +3617,132 @@ impl Orderbook {
     // Views
     // ========================================================================
 
-    pub fn get_intent(&self, id: U128) -> Option<Intent> {
-        self.intents.get(&(id.0 as u64))
+    pub fn get_intent(&self, id: U64) -> Option<Intent> {
+        self.intents.get(&(id.0))
+    }
+
+    pub fn get_sub_intent(&self, id: U64) -> Option<SubIntent> {
+        self.sub_intents.get(&(id.0))
+    }
+
+    pub fn get_transition_expectation(&self, id: U64) -> Option<TransitionExpectation> {
+        self.transition_expectations.get(&(id.0))
+    }
+
+    pub fn get_pending_withdrawal(&self, id: U64) -> Option<PendingWithdrawal> {
+        self.pending_withdrawals.get(&(id.0))
+    }
+
+    /// `user`'s withdrawals still waiting for a signature, oldest first.
+    pub fn get_pending_withdrawals(&self, user: AccountId) -> Vec<(U64, PendingWithdrawal)> {
+        let mut ids = self.pending_withdrawals_by_user.get(&user).map(|ids| ids.to_vec()).unwrap_or_default();
+        ids.sort_unstable();
+        ids.into_iter()
+            .filter_map(|id| self.pending_withdrawals.get(&id).map(|wd| (U64(id), wd)))
+            .collect()
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
     }
 
-    pub fn get_sub_intent(&self, id: U128) -> Option<SubIntent> {
-        self.sub_intents.get(&(id.0 as u64))
+    pub fn get_signature(&self, target: SignTarget) -> Option<StoredSignature> {
+        self.signatures.get(&target)
     }
 
-    pub fn get_transition_expectation(&self, id: U128) -> Option<TransitionExpectation> {
-        self.transition_expectations.get(&(id.0 as u64))
+    /// Surplus the most recent `batch_match_intents` left for its solver.
+    pub fn get_last_batch_surplus(&self) -> Vec<(String, U128)> {
+        self.last_batch_surplus
+            .iter()
+            .map(|(asset, amount)| (asset.clone(), U128(*amount)))
+            .collect()
+    }
+
+    /// Total protocol fees credited to the fee collector in `asset`.
+    pub fn get_accrued_fees(&self, asset: String) -> U128 {
+        U128(self.accrued_fees.get(&asset).unwrap_or(0))
+    }
+
+    pub fn get_escrow(&self, sub_intent_id: U64) -> Option<EscrowEntry> {
+        self.escrowed_credits.get(&(sub_intent_id.0))
     }
 
     pub fn get_open_intents(&self, from_index: U128, limit: u64) -> Vec<Intent> {
         let from_index = from_index.0 as u64;
-        let keys = self.intents.keys_as_vector();
-        (from_index..std::cmp::min(from_index + limit, keys.len()))
+        let now = env::block_timestamp();
+        let ids = self.open_intent_ids.as_vector();
+        (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
             .filter_map(|index| {
-                let id = keys.get(index).unwrap();
-                let intent = self.intents.get(&id).unwrap();
-                if intent.status == IntentStatus::Open {
-                    Some(intent)
-                } else {
+                let intent = self.intents.get(&ids.get(index).unwrap()).unwrap();
+                if intent.is_expired(now) {
                     None
+                } else {
+                    Some(intent)
                 }
             })
             .collect()
     }
 
+    /// How many of the maker's intents count against the open-intent cap.
+    pub fn get_open_intent_count(&self, maker: AccountId) -> u64 {
+        self.open_intent_counts.get(&maker).unwrap_or(0)
+    }
+
+    /// Page through a maker's intents in creation order. The page window is
+    /// applied before the status filter, as in `get_open_intents`.
+    pub fn get_intents_by_maker(
+        &self,
+        maker: AccountId,
+        status: Option<IntentStatus>,
+        from_index: U128,
+        limit: u64,
+    ) -> Vec<Intent> {
+        let Some(ids) = self.intents_by_maker.get(&maker) else {
+            return vec![];
+        };
+        let from_index = from_index.0 as u64;
+        (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
+            .filter_map(|index| self.intents.get(&ids.get(index).unwrap()))
+            .filter(|intent| status.as_ref().is_none_or(|s| &intent.status == s))
+            .collect()
+    }
+
+    pub fn get_sub_intents_by_parent(&self, intent_id: U64, from_index: U128, limit: u64) -> Vec<SubIntent> {
+        self.page_sub_intents(self.sub_intents_by_parent.get(&(intent_id.0)), from_index.0 as u64, limit)
+    }
+
+    pub fn get_sub_intents_by_taker(&self, taker: AccountId, from_index: U128, limit: u64) -> Vec<SubIntent> {
+        self.page_sub_intents(self.sub_intents_by_taker.get(&taker), from_index.0 as u64, limit)
+    }
+
+    pub fn get_intent_fills(&self, intent_id: U64, from_index: U128, limit: u64) -> Vec<FillRecord> {
+        let Some(fills) = self.intent_fills.get(&(intent_id.0)) else {
+            return vec![];
+        };
+        let from_index = from_index.0 as u64;
+        (from_index..std::cmp::min(from_index.saturating_add(limit), fills.len()))
+            .filter_map(|index| fills.get(index))
+            .collect()
+    }
+
+    /// Indices are append-only, so a page at a given offset never shifts.
+    fn page_sub_intents(&self, ids: Option<Vector<u64>>, from_index: u64, limit: u64) -> Vec<SubIntent> {
+        let Some(ids) = ids else {
+            return vec![];
+        };
+        (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
+            .filter_map(|index| self.sub_intents.get(&ids.get(index).unwrap()))
+            .collect()
+    }
+
     pub fn get_balance(&self, user: AccountId, asset: String) -> U128 {
         self.balances
             .get(&user)
@@ -850,24 +3750,112 @@ impl Orderbook {
             .unwrap_or(0)
             .into()
     }
+
+    /// Page through every asset balance held by `user`.
+    pub fn get_balances(&self, user: AccountId, from_index: u64, limit: u64) -> Vec<(String, U128)> {
+        self.balances
+            .get(&user)
+            .map(|b: UnorderedMap<String, u128>| {
+                b.iter()
+                    .skip(from_index as usize)
+                    .take(limit as usize)
+                    .map(|(asset, amount)| (asset, U128(amount)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_assets(&self, user: AccountId) -> Vec<String> {
+        self.balances
+            .get(&user)
+            .map(|b: UnorderedMap<String, u128>| b.keys().collect())
+            .unwrap_or_default()
+    }
 }
 
+mod assets;
+mod batches;
+mod btc;
+mod circuit_breaker;
+mod cleanup;
+mod compliance;
+mod config;
+mod deposits;
+mod depth;
+mod derivation;
+mod errors;
+mod eth;
+mod input_limits;
+mod ledger;
+mod matching;
+mod memo;
+mod migration;
+mod rebate;
+mod recipients;
+mod referral;
+mod simulation;
+mod sol;
+mod solvency;
+mod solver;
+mod stats;
+mod storage;
+mod upgrade;
+mod withdrawal_limits;
+mod withdrawals;
+pub use assets::AssetInfo;
+pub use batches::{BatchRecord, HeldSurplus};
+pub use circuit_breaker::{CircuitBreakerEvent, VolumeWindow, VolumeWindowView, DEFAULT_VOLUME_WINDOW_BLOCKS};
+pub use cleanup::{RecordKind, RecordStatus, Tombstone};
+pub use config::{ConfigChange, ContractConfig, PendingConfigChange};
+pub use deposits::{DepositOutcome, DepositRecord, DepositSource};
+pub use depth::{BookEntry, DepthLevel};
+pub use derivation::{deposit_path, user_path};
+pub use errors::OrderbookError;
+pub use input_limits::{InputLimits, DEFAULT_MAX_FIELD_LEN, DEFAULT_MAX_PATH_LEN, DEFAULT_MAX_PROOF_DATA_LEN};
+pub use ledger::{BalanceChangeReason, BalanceChangedEvent};
+pub use matching::PlannedFill;
+pub use memo::TransitionMemo;
+pub use rebate::FeeConfig;
+pub use simulation::{AssetNet, BatchSimulation, MatchVerdict};
+pub use solvency::SolvencyReport;
+pub use solver::SolverInfo;
+pub use stats::Stats;
+pub use storage::{StorageAccount, StorageBalance};
+pub use upgrade::StagedUpgrade;
+pub use withdrawal_limits::{WindowUsage, WithdrawalQuota, WITHDRAWAL_WINDOW_NS};
+pub use withdrawals::{WithdrawalRecord, WithdrawalStatus};
+
 #[cfg(test)]
 mod tests;
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(crate = "near_sdk::serde")]
-pub struct SignResult {
-    pub big_r: AffinePoint,
-    pub s: Scalar,
-    pub recovery_id: u8,
+/// MPC signer response, tagged by `scheme`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde", tag = "scheme")]
+pub enum SignResult {
+    Secp256k1 {
+        big_r: AffinePoint,
+        s: Scalar,
+        recovery_id: u8,
+    },
+    Ed25519 {
+        signature: Vec<u8>,
+    },
+}
+
+impl SignResult {
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            SignResult::Secp256k1 { .. } => SignatureScheme::Secp256k1,
+            SignResult::Ed25519 { .. } => SignatureScheme::Ed25519,
+        }
+    }
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub struct AffinePoint {
     pub affine_point: String,
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Scalar {
     pub scalar: String,
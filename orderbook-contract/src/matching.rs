@@ -0,0 +1,148 @@
+//! On-chain counterparty search for simple two-sided flow. `match_best`
+//! fills an intent against the opposite side of its market in price-time
+//! order and hands the result to the deferred batch path, so the fills go
+//! through the same checks as `batch_match_intents_deferred` and their sign
+//! requests wait in the queue for `process_sign_queue`.
+
+use crate::depth::cmp_ratio;
+use crate::*;
+use std::cmp::Ordering;
+
+/// One leg of the batch `match_best` would submit.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PlannedFill {
+    pub intent_id: U64,
+    pub fill_amount: U128,
+    pub get_amount: U128,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Fill `intent_id` against up to `max_counterparties` (capped at
+    /// `MAX_MATCH_BEST_COUNTERPARTIES`) crossing intents of the opposite
+    /// side, best price first, oldest first within a price. Each counter
+    /// fill is made at the counter-intent's limit price.
+    ///
+    /// `legs` settle the batch in the order `preview_match_best` lists it:
+    /// the intent itself first, then its counterparties. Returns the
+    /// sub-intent ids in that order, or nothing (refunding the deposit) if
+    /// no counter-intent crosses.
+    #[payable]
+    pub fn match_best(&mut self, intent_id: U64, max_counterparties: u32, legs: Vec<SettlementRequest>) -> Vec<U64> {
+        let solver = env::predecessor_account_id();
+        let plan = self.plan_best_match(&solver, intent_id.0, max_counterparties);
+        if plan.is_empty() {
+            let attached = env::attached_deposit();
+            if !attached.is_zero() {
+                Promise::new(solver).transfer(attached).detach();
+            }
+            return Vec::new();
+        }
+        assert!(
+            legs.len() == plan.len(),
+            "Expected {} settlement legs, got {}",
+            plan.len(),
+            legs.len()
+        );
+        let matches = plan
+            .into_iter()
+            .zip(legs)
+            .map(|(fill, leg)| MatchParams {
+                transition_asset: self.intents.get(&fill.intent_id.0).map(|i| i.src_asset).unwrap_or_default(),
+                unsigned_tx: leg.unsigned_tx,
+                intent_id: fill.intent_id,
+                fill_amount: fill.fill_amount,
+                get_amount: fill.get_amount,
+                payload: leg.payload,
+                path: leg.path,
+                transition_chain_type: leg.chain_type,
+                scheme: leg.scheme,
+                recipient: leg.recipient,
+            })
+            .collect();
+        self.apply_deferred_batch(&solver, matches)
+    }
+
+    /// Fills `match_best` would make if `solver` called it now; empty if no
+    /// counter-intent crosses.
+    pub fn preview_match_best(&self, solver: AccountId, intent_id: U64, max_counterparties: u32) -> Vec<PlannedFill> {
+        self.plan_best_match(&solver, intent_id.0, max_counterparties)
+    }
+}
+
+impl Orderbook {
+    fn plan_best_match(&self, solver: &AccountId, intent_id: u64, max_counterparties: u32) -> Vec<PlannedFill> {
+        let intent = self.intents.get(&intent_id).expect("Intent not found");
+        assert_eq!(intent.status, IntentStatus::Open, "Intent {} not open", intent_id);
+        let Some(book) = self
+            .open_intents_by_pair
+            .get(&Self::book_key(&intent.dst_asset, &intent.src_asset))
+        else {
+            return Vec::new();
+        };
+        let now = env::block_timestamp();
+        // The book is sorted: least of our src_asset asked per unit first,
+        // then oldest
+        let candidates = book
+            .iter()
+            .take(MAX_MATCH_BEST_SCAN)
+            .filter_map(|(entry, ())| self.intents.get(&entry.intent_id))
+            .filter(|counter| {
+                !counter.is_expired(now)
+                    && counter.check_taker_allowed(solver).is_ok()
+                    && self.check_not_blocked(&counter.maker).is_ok()
+            });
+
+        let max_counterparties = max_counterparties.min(MAX_MATCH_BEST_COUNTERPARTIES) as usize;
+        let mut left = intent.src_amount - intent.filled_amount;
+        let (mut filled, mut received) = (0u128, 0u128);
+        let mut fills = Vec::new();
+        for counter in candidates {
+            if fills.len() == max_counterparties || left == 0 {
+                break;
+            }
+            // Crossed while the counter gives at least our limit:
+            // counter.src / counter.dst >= intent.dst / intent.src
+            if cmp_ratio(intent.dst_amount, intent.src_amount, counter.src_amount, counter.dst_amount)
+                == Ordering::Greater
+            {
+                break;
+            }
+            let remaining = counter.src_amount - counter.filled_amount;
+            let amount = if counter.dst_for(remaining) <= left {
+                remaining
+            } else {
+                left.checked_mul(counter.src_amount).expect("amount overflow") / counter.dst_amount
+            };
+            if amount == 0 || counter.check_fill_allowed(amount).is_err() {
+                continue;
+            }
+            let cost = counter.dst_for(amount);
+            // `dst_for` rounds up for the counter, which can push a small
+            // fill just past our own limit
+            let gets = amount.checked_mul(intent.src_amount).expect("amount overflow");
+            if gets < cost.checked_mul(intent.dst_amount).expect("amount overflow") {
+                continue;
+            }
+            left -= cost;
+            filled += cost;
+            received += amount;
+            fills.push(PlannedFill {
+                intent_id: U64(counter.id),
+                fill_amount: U128(amount),
+                get_amount: U128(cost),
+            });
+        }
+        if fills.is_empty() || intent.check_fill_allowed(filled).is_err() {
+            return Vec::new();
+        }
+        let mut plan = vec![PlannedFill {
+            intent_id: U64(intent_id),
+            fill_amount: U128(filled),
+            get_amount: U128(received),
+        }];
+        plan.extend(fills);
+        plan
+    }
+}
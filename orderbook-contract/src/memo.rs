@@ -0,0 +1,96 @@
+//! Memo a transition transaction must carry. It names this contract and a
+//! nonce, so a proof for sub-intent `n` of one deployment (or of one signing
+//! attempt) cannot be replayed for sub-intent `n` of another.
+
+use crate::*;
+use std::fmt;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum TransitionMemo {
+    /// `transition:sub:{sub_intent_id}`, only found on expectations
+    /// migrated from V0.
+    V0 { sub_intent_id: u64 },
+    /// `transition:{contract}:{sub_intent_id}:{nonce}`.
+    V1 {
+        contract: AccountId,
+        sub_intent_id: u64,
+        nonce: u64,
+    },
+}
+
+impl TransitionMemo {
+    pub fn parse(memo: &str) -> Option<Self> {
+        let parts: Vec<&str> = memo.split(':').collect();
+        match parts.as_slice() {
+            ["transition", "sub", id] => Some(Self::V0 {
+                sub_intent_id: id.parse().ok()?,
+            }),
+            ["transition", contract, id, nonce] => Some(Self::V1 {
+                contract: contract.parse().ok()?,
+                sub_intent_id: id.parse().ok()?,
+                nonce: nonce.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn sub_intent_id(&self) -> u64 {
+        match self {
+            Self::V0 { sub_intent_id } | Self::V1 { sub_intent_id, .. } => *sub_intent_id,
+        }
+    }
+}
+
+impl fmt::Display for TransitionMemo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V0 { sub_intent_id } => write!(f, "transition:sub:{}", sub_intent_id),
+            Self::V1 {
+                contract,
+                sub_intent_id,
+                nonce,
+            } => write!(f, "transition:{}:{}:{}", contract, sub_intent_id, nonce),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Memos the transitions of the next batch of `count` matches (at most
+    /// `MAX_DEFERRED_BATCH_MATCHES`) must carry, in match order. Any call that
+    /// creates a sub-intent or takes a nonce first changes them.
+    pub fn get_next_batch_memos(&self, count: u32) -> Vec<String> {
+        (0..(count as usize).min(MAX_DEFERRED_BATCH_MATCHES)).map(|index| self.next_batch_memo(index)).collect()
+    }
+
+    /// Memo the transition of take `sub_intent_id` must carry if it is
+    /// submitted with `submit_payment_proof` before any other call takes a
+    /// nonce.
+    pub fn get_next_take_memo(&self, sub_intent_id: U64) -> String {
+        Self::transition_memo(sub_intent_id.0, self.next_transition_nonce)
+    }
+}
+
+impl Orderbook {
+    /// Memo of the `index`th match of the next batch: the sub-intent it
+    /// creates and the nonce `apply_batch` takes.
+    pub(crate) fn next_batch_memo(&self, index: usize) -> String {
+        Self::transition_memo(self.next_sub_intent_id + index as u64, self.next_transition_nonce)
+    }
+
+    /// Reserve a nonce for the transitions signed by one call.
+    pub(crate) fn take_transition_nonce(&mut self) -> u64 {
+        let nonce = self.next_transition_nonce;
+        self.next_transition_nonce += 1;
+        nonce
+    }
+
+    pub(crate) fn transition_memo(sub_intent_id: u64, nonce: u64) -> String {
+        TransitionMemo::V1 {
+            contract: env::current_account_id(),
+            sub_intent_id,
+            nonce,
+        }
+        .to_string()
+    }
+}
@@ -0,0 +1,616 @@
+//! State migration from earlier deployed layouts.
+//!
+//! The layout of the root state is recorded under its own storage key (see
+//! `STATE_VERSION`), and `migrate` upgrades from whichever version it finds.
+//!
+//! `OrderbookV0` is the layout that shipped before intents, sub-intents and
+//! withdrawals got independent id counters. Its types are kept here only so
+//! the old borsh state can be read back during the migration.
+//!
+//! Rewriting every V0 record can take more gas than one call has, so
+//! `migrate` only swaps in the new root and leaves the contract paused.
+//! The owner then calls `migrate_batch` until it reports the migration
+//! complete; the progress is kept under its own storage key in between.
+//!
+//! A type stored in the root state can only change together with a
+//! `STATE_VERSION` bump: freeze its last released shape here first, then
+//! convert it in the new version's `From` impl.
+
+use crate::*;
+
+/// The status enum intents and sub-intents shared in V0. Only the
+/// variant order matters: it is what the stored borsh encodes.
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Debug)]
+pub(crate) enum LegacyStatus {
+    Open,
+    Filled,
+    Taken,
+    Verifying,
+    Settled,
+    TransitionVerifying,
+    Completed,
+    Cancelled,
+    Expired,
+    Failed,
+    ForceCancelled,
+}
+
+impl From<LegacyStatus> for IntentStatus {
+    fn from(status: LegacyStatus) -> Self {
+        match status {
+            LegacyStatus::Open => IntentStatus::Open,
+            LegacyStatus::Filled => IntentStatus::Filled,
+            LegacyStatus::Cancelled => IntentStatus::Cancelled,
+            LegacyStatus::Expired => IntentStatus::Expired,
+            LegacyStatus::ForceCancelled => IntentStatus::ForceCancelled,
+            status => env::panic_str(&format!("Intent cannot be {:?}", status)),
+        }
+    }
+}
+
+impl From<LegacyStatus> for SubIntentStatus {
+    fn from(status: LegacyStatus) -> Self {
+        match status {
+            LegacyStatus::Taken => SubIntentStatus::Taken,
+            LegacyStatus::Verifying => SubIntentStatus::Verifying,
+            LegacyStatus::Settled => SubIntentStatus::Settled,
+            LegacyStatus::TransitionVerifying => SubIntentStatus::TransitionVerifying,
+            LegacyStatus::Completed => SubIntentStatus::Completed,
+            LegacyStatus::Cancelled => SubIntentStatus::Cancelled,
+            LegacyStatus::Expired => SubIntentStatus::Expired,
+            LegacyStatus::Failed => SubIntentStatus::Failed,
+            LegacyStatus::ForceCancelled => SubIntentStatus::ForceCancelled,
+            status => env::panic_str(&format!("Sub-Intent cannot be {:?}", status)),
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct IntentV0 {
+    pub id: u64,
+    pub maker: AccountId,
+    pub src_asset: String,
+    pub src_amount: u128,
+    pub filled_amount: u128,
+    pub dst_asset: String,
+    pub dst_amount: u128,
+    pub status: LegacyStatus,
+}
+
+impl From<IntentV0> for Intent {
+    fn from(v0: IntentV0) -> Self {
+        Intent {
+            id: v0.id,
+            maker: v0.maker,
+            src_asset: v0.src_asset,
+            src_amount: v0.src_amount,
+            filled_amount: v0.filled_amount,
+            dst_asset: v0.dst_asset,
+            dst_amount: v0.dst_amount,
+            status: v0.status.into(),
+            expires_at: None,
+            min_fill_amount: 0,
+            all_or_nothing: false,
+            closed_at: None,
+            allowed_takers: None,
+            referrer: None,
+            client_id: None,
+            replaced_from: None,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct SubIntentV0 {
+    pub id: u64,
+    pub parent_intent_id: u64,
+    pub taker: AccountId,
+    pub amount: u128,
+    pub status: LegacyStatus,
+}
+
+impl From<SubIntentV0> for SubIntent {
+    /// V0 takes locked nothing; they keep settling via payment proofs.
+    /// Their expiry clock starts at migration.
+    fn from(v0: SubIntentV0) -> Self {
+        SubIntent {
+            id: v0.id,
+            parent_intent_id: v0.parent_intent_id,
+            taker: v0.taker,
+            amount: v0.amount,
+            status: v0.status.into(),
+            escrow: 0,
+            expires_at: Some(env::block_timestamp() + DEFAULT_SUB_INTENT_TIMEOUT_NS),
+            bond: 0,
+            closed_at: None,
+            transition_tx_hash: None,
+            batch_id: None,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct TransitionExpectationV0 {
+    pub sub_intent_id: u64,
+    pub chain_type: ChainType,
+    pub expected_asset: String,
+    pub expected_amount: u128,
+    pub expected_memo: String,
+}
+
+impl From<TransitionExpectationV0> for TransitionExpectation {
+    /// V0 signatures carry no deadline; their fills can still be unwound
+    /// through `claim_escrow_timeout`.
+    fn from(v0: TransitionExpectationV0) -> Self {
+        TransitionExpectation {
+            sub_intent_id: v0.sub_intent_id,
+            chain_type: v0.chain_type,
+            expected_asset: v0.expected_asset,
+            expected_amount: v0.expected_amount,
+            // Still `TransitionMemo::V0`, which the signed transaction carries.
+            expected_memo: v0.expected_memo,
+            // V0 let the prover name the recipient, so none was recorded; a
+            // proof against the empty address cannot pass.
+            expected_recipient: String::new(),
+            transition_deadline: None,
+            unsigned_tx: None,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct PendingWithdrawalV0 {
+    pub user: AccountId,
+    pub asset: String,
+    pub amount: u128,
+}
+
+impl From<PendingWithdrawalV0> for PendingWithdrawal {
+    /// V0 withdrawals signed a caller-supplied hash, so there is no
+    /// destination or payload to carry over; only the refund fields matter.
+    fn from(v0: PendingWithdrawalV0) -> Self {
+        PendingWithdrawal {
+            user: v0.user,
+            asset: v0.asset,
+            amount: v0.amount,
+            gas_fee: 0,
+            destination: String::new(),
+            chain_type: ChainType::ETH,
+            nonce: 0,
+            payload: String::new(),
+            requested_at: 0,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct OrderbookV0 {
+    pub owner: AccountId,
+    pub mpc_contract: AccountId,
+    pub light_client_contract: AccountId,
+    pub balances: UnorderedMap<AccountId, UnorderedMap<String, u128>>,
+    pub intents: UnorderedMap<u64, IntentV0>,
+    pub sub_intents: UnorderedMap<u64, SubIntentV0>,
+    pub transition_expectations: UnorderedMap<u64, TransitionExpectationV0>,
+    pub pending_withdrawals: UnorderedMap<u64, PendingWithdrawalV0>,
+    /// Single counter shared by intents, sub-intents and withdrawals.
+    pub next_id: u64,
+}
+
+/// Storage key holding the layout version of the root state. State written
+/// before versioning has no such key and is always the V0 layout.
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// Storage key holding the `MigrationCursor` of a V0 migration. It only
+/// exists while one is under way.
+const MIGRATION_CURSOR_KEY: &[u8] = b"MIGRATION_CURSOR";
+
+/// The maps a V0 migration walks, in order: the V0 record maps it
+/// re-encodes, then the balances it credits.
+#[derive(BorshDeserialize, BorshSerialize, PartialEq, Clone, Copy, Debug)]
+enum MigrationStage {
+    Intents,
+    Withdrawals,
+    Expectations,
+    SubIntents,
+    Balances,
+}
+
+/// Next record `migrate_batch` migrates: its position in the storage
+/// order of the stage's map.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct MigrationCursor {
+    stage: MigrationStage,
+    index: u64,
+}
+
+fn read_cursor() -> Option<MigrationCursor> {
+    env::storage_read(MIGRATION_CURSOR_KEY)
+        .map(|bytes| borsh::from_slice(&bytes).expect("Migration cursor deserializes"))
+}
+
+fn write_cursor(cursor: &MigrationCursor) {
+    env::storage_write(MIGRATION_CURSOR_KEY, &borsh::to_vec(cursor).expect("Migration cursor serializes"));
+}
+
+/// Root state in any layout `migrate` knows how to upgrade.
+pub(crate) enum VersionedOrderbook {
+    V0(Box<OrderbookV0>),
+    /// The current layout (`STATE_VERSION` 1).
+    V1(Box<Orderbook>),
+}
+
+impl VersionedOrderbook {
+    /// Read the root state using the layout its version key names.
+    fn read() -> Self {
+        match stored_state_version() {
+            0 => Self::V0(Box::new(env::state_read().expect("No V0 state to migrate"))),
+            1 => Self::V1(Box::new(env::state_read().expect("No V1 state to migrate"))),
+            version => env::panic_str(&format!("Unknown state version {}", version)),
+        }
+    }
+}
+
+impl From<VersionedOrderbook> for Orderbook {
+    fn from(state: VersionedOrderbook) -> Self {
+        match state {
+            VersionedOrderbook::V0(old) => Orderbook::from(*old),
+            VersionedOrderbook::V1(current) => *current,
+        }
+    }
+}
+
+/// View a collection handle as one over a different element type. Legacy
+/// collections encode only their prefixes and length, so the handle itself
+/// is the same whatever the elements are.
+fn reinterpret<From: BorshSerialize, To: BorshDeserialize>(collection: &From) -> To {
+    borsh::from_slice(&borsh::to_vec(collection).expect("Collection serializes"))
+        .expect("Collection layout matches")
+}
+
+/// Replace a value without decoding the one it overwrites, which is still in
+/// the old encoding.
+pub(crate) fn overwrite<K: BorshSerialize, V: BorshSerialize, E: BorshSerialize>(
+    map: &mut UnorderedMap<K, V>,
+    key: &K,
+    value: &E,
+) {
+    map.insert_raw(
+        &borsh::to_vec(key).expect("Key serializes"),
+        &borsh::to_vec(value).expect("Value serializes"),
+    );
+}
+
+/// The `index`th entry of `map`, decoded in its V0 encoding.
+fn legacy_entry<V, Legacy>(map: &UnorderedMap<u64, V>, index: u64) -> (u64, Legacy)
+where
+    V: BorshSerialize + BorshDeserialize,
+    Legacy: BorshSerialize + BorshDeserialize,
+{
+    let legacy: UnorderedMap<u64, Legacy> = reinterpret(map);
+    let key = legacy.keys_as_vector().get(index).expect("Migrated index is in range");
+    let value = legacy.values_as_vector().get(index).expect("Migrated index is in range");
+    (key, value)
+}
+
+/// Append `id` to the index `Vector` kept for `key` under `{prefix}{key}`.
+fn push_index<K: BorshSerialize + BorshDeserialize + std::fmt::Display>(
+    index: &mut UnorderedMap<K, Vector<u64>>,
+    prefix: char,
+    key: &K,
+    id: u64,
+) {
+    let mut ids = index
+        .get(key)
+        .unwrap_or_else(|| Vector::new(format!("{}{}", prefix, key).as_bytes()));
+    ids.push(&id);
+    index.insert(key, &ids);
+}
+
+impl Orderbook {
+    /// Re-encode the `index`th V0 record of `stage` and add it to the
+    /// indices, stats and totals V0 did not keep. Whatever the state owes
+    /// is taken as credited, since no earlier layout tracked deposit totals.
+    fn migrate_record(&mut self, stage: MigrationStage, index: u64) {
+        match stage {
+            MigrationStage::Intents => {
+                let (id, intent): (u64, IntentV0) = legacy_entry(&self.intents, index);
+                let intent = Intent::from(intent);
+                push_index(&mut self.intents_by_maker, 'm', &intent.maker, id);
+                if intent.status == IntentStatus::Open {
+                    self.open_intent_ids.insert(&id);
+                    let count = self.open_intent_counts.get(&intent.maker).unwrap_or(0);
+                    self.open_intent_counts.insert(&intent.maker, &(count + 1));
+                    self.stats.open_intents += 1;
+                    self.record_credit(&intent.src_asset, intent.src_amount - intent.filled_amount);
+                    self.index_open_intent(&intent);
+                }
+                self.stats.total_intents_created += 1;
+                overwrite(&mut self.intents, &id, &intent);
+            }
+            MigrationStage::Withdrawals => {
+                let (id, wd): (u64, PendingWithdrawalV0) = legacy_entry(&self.pending_withdrawals, index);
+                let wd = PendingWithdrawal::from(wd);
+                let mut by_user = self
+                    .pending_withdrawals_by_user
+                    .get(&wd.user)
+                    .unwrap_or_else(|| UnorderedSet::new(format!("u{}", wd.user).as_bytes()));
+                by_user.insert(&id);
+                self.pending_withdrawals_by_user.insert(&wd.user, &by_user);
+                // Only still-pending withdrawals get a record; finished V0
+                // ones left no trace.
+                self.withdrawals.insert(&id, &WithdrawalRecord::pending(id, &wd));
+                let mut ids = self
+                    .withdrawals_by_user
+                    .get(&wd.user)
+                    .unwrap_or_else(|| Vector::new(format!("J{}", wd.user).as_bytes()));
+                ids.push(&id);
+                self.withdrawals_by_user.insert(&wd.user, &ids);
+                // V0 withdrawals paid no gas fee.
+                self.record_credit(&wd.asset, wd.amount);
+                overwrite(&mut self.pending_withdrawals, &id, &wd);
+            }
+            MigrationStage::Expectations => {
+                let (id, expectation): (u64, TransitionExpectationV0) =
+                    legacy_entry(&self.transition_expectations, index);
+                overwrite(&mut self.transition_expectations, &id, &TransitionExpectation::from(expectation));
+            }
+            MigrationStage::SubIntents => {
+                let (id, sub): (u64, SubIntentV0) = legacy_entry(&self.sub_intents, index);
+                let sub = SubIntent::from(sub);
+                match sub.status {
+                    SubIntentStatus::Completed => self.stats.sub_intents_completed += 1,
+                    SubIntentStatus::Cancelled => self.stats.sub_intents_cancelled += 1,
+                    _ => {}
+                }
+                push_index(&mut self.sub_intents_by_parent, 'p', &sub.parent_intent_id, id);
+                push_index(&mut self.sub_intents_by_taker, 't', &sub.taker, id);
+                // A `Verifying` take is being signed; if that fails it falls
+                // back to `Taken`, and the retry needs a request as well.
+                if matches!(sub.status, SubIntentStatus::Taken | SubIntentStatus::Verifying) {
+                    let request = self.backfilled_settlement_request(&sub);
+                    self.settlement_requests.insert(&id, &request);
+                    // The payload in flight carries the memo of its V0
+                    // expectation, so that one is kept.
+                    if sub.status == SubIntentStatus::Taken || self.transition_expectations.get(&id).is_none() {
+                        let expectation = self.backfilled_expectation(&sub, &request);
+                        self.transition_expectations.insert(&id, &expectation);
+                    }
+                }
+                overwrite(&mut self.sub_intents, &id, &sub);
+            }
+            // V0 takes locked no escrow, so balances are all that is left.
+            MigrationStage::Balances => {
+                let user_balances = self.balances.values_as_vector().get(index).expect("Migrated index is in range");
+                for (asset, amount) in user_balances.iter().filter(|(_, amount)| *amount > 0) {
+                    self.record_credit(&asset, amount);
+                }
+            }
+        }
+    }
+
+    /// V0 kept nothing of what a take is signed as, so `retry_settlement`
+    /// would have nothing to sign. The request names the chain of the
+    /// parent's src asset, its treasury path and, if the maker registered
+    /// one, their receive address; its payload stays unset until the maker
+    /// approves a transaction with `replace_settlement_payload`.
+    fn backfilled_settlement_request(&self, sub: &SubIntent) -> SettlementRequest {
+        let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+        let chain_type = self
+            .assets
+            .get(&parent.src_asset)
+            .unwrap_or_else(|| env::panic_str(&format!("Register asset {} before migrating its sub-intents", parent.src_asset)))
+            .chain_type;
+        let path = self
+            .treasury_paths
+            .get(&chain_type)
+            .unwrap_or_else(|| env::panic_str(&format!("Set the {:?} treasury path before migrating sub-intents", chain_type)));
+        SettlementRequest {
+            payload: SettlementRequest::UNSET_PAYLOAD,
+            path,
+            scheme: SignatureScheme::for_chain(&chain_type),
+            recipient: self.receive_addresses.get(&(parent.maker, chain_type.clone())).unwrap_or_default(),
+            chain_type,
+            unsigned_tx: None,
+        }
+    }
+
+    /// What the transition of a backfilled request must deliver. Its memo
+    /// is fixed now, so the solver can put it in the transaction they
+    /// propose before the retry signs it.
+    fn backfilled_expectation(&mut self, sub: &SubIntent, request: &SettlementRequest) -> TransitionExpectation {
+        let parent = self.intents.get(&sub.parent_intent_id).expect("Parent intent not found");
+        TransitionExpectation {
+            sub_intent_id: sub.id,
+            chain_type: request.chain_type.clone(),
+            expected_asset: parent.src_asset,
+            expected_amount: sub.amount,
+            expected_memo: Self::transition_memo(sub.id, self.take_transition_nonce()),
+            expected_recipient: request.recipient.clone(),
+            transition_deadline: None,
+            unsigned_tx: None,
+        }
+    }
+
+    /// Whether a V0 migration still has records to rewrite or credit.
+    pub(crate) fn is_migrating(&self) -> bool {
+        env::storage_has_key(MIGRATION_CURSOR_KEY)
+    }
+}
+
+fn stored_state_version() -> u8 {
+    env::storage_read(STATE_VERSION_KEY).map_or(0, |bytes| bytes[0])
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Upgrade the stored state to the current layout, whichever version it
+    /// was written in. Already-current state is returned unchanged, so
+    /// calling it again after an upgrade is harmless. V0 state still needs
+    /// `migrate_batch` afterwards.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = Orderbook::from(VersionedOrderbook::read());
+        write_state_version();
+        contract
+    }
+
+    /// Migrate up to `limit` more V0 records or account balances, in the
+    /// storage order of each map. Returns true once none are left; until
+    /// then the contract stays paused.
+    pub fn migrate_batch(&mut self, limit: u32) -> bool {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can migrate");
+        let Some(mut cursor) = read_cursor() else {
+            return true;
+        };
+        let mut budget = u64::from(limit);
+        while budget > 0 {
+            let len = match cursor.stage {
+                MigrationStage::Intents => self.intents.len(),
+                MigrationStage::Withdrawals => self.pending_withdrawals.len(),
+                MigrationStage::Expectations => self.transition_expectations.len(),
+                MigrationStage::SubIntents => self.sub_intents.len(),
+                MigrationStage::Balances => self.balances.len(),
+            };
+            if cursor.index < len {
+                self.migrate_record(cursor.stage, cursor.index);
+                cursor.index += 1;
+                budget -= 1;
+            } else {
+                cursor = MigrationCursor {
+                    stage: match cursor.stage {
+                        MigrationStage::Intents => MigrationStage::Withdrawals,
+                        MigrationStage::Withdrawals => MigrationStage::Expectations,
+                        MigrationStage::Expectations => MigrationStage::SubIntents,
+                        MigrationStage::SubIntents => MigrationStage::Balances,
+                        MigrationStage::Balances => {
+                            env::storage_remove(MIGRATION_CURSOR_KEY);
+                            env::log_str("MIGRATION_COMPLETE");
+                            return true;
+                        }
+                    },
+                    index: 0,
+                };
+            }
+        }
+        write_cursor(&cursor);
+        false
+    }
+
+    pub fn get_state_version(&self) -> u8 {
+        stored_state_version()
+    }
+}
+
+impl From<OrderbookV0> for Orderbook {
+    /// The V0 record maps are taken over in place, still in their V0
+    /// encoding, and the contract is left paused; `migrate_batch` rewrites
+    /// the records and builds the indices. Ids are preserved as-is.
+    fn from(old: OrderbookV0) -> Self {
+        write_cursor(&MigrationCursor { stage: MigrationStage::Intents, index: 0 });
+
+        // Existing ids were all drawn from `next_id`, so starting every new
+        // counter there guarantees no collisions in any of the maps.
+        Self {
+            owner: old.owner.clone(),
+            pending_owner: None,
+            // The single V0 signer becomes the default for every chain.
+            mpc_contract: old.mpc_contract,
+            chain_signers: UnorderedMap::new(b"c"),
+            treasury_paths: UnorderedMap::new(b"T"),
+            min_confirmations: UnorderedMap::new(b"H"),
+            light_client_contract: old.light_client_contract,
+            config_delay_blocks: 0,
+            pending_config_changes: Vec::new(),
+            staged_upgrade: None,
+            eth_chain_id: DEFAULT_ETH_CHAIN_ID,
+            max_withdrawal_gas_fee: DEFAULT_MAX_WITHDRAWAL_GAS_FEE,
+            fee_bps: 0,
+            fee_collector: old.owner,
+            accrued_fees: UnorderedMap::new(b"f"),
+            referral_bps: 0,
+            referral_earnings: UnorderedMap::new(b"R"),
+            maker_rebate_bps: 0,
+            maker_rebates: UnorderedMap::new(b"L"),
+            balances: old.balances,
+            intents: reinterpret(&old.intents),
+            open_intent_ids: UnorderedSet::new(b"o"),
+            open_intents_by_pair: LookupMap::new(b"A"),
+            intents_by_maker: UnorderedMap::new(b"m"),
+            open_intent_counts: UnorderedMap::new(b"j"),
+            client_ids: LookupMap::new(b"G"),
+            max_open_intents_per_account: DEFAULT_MAX_OPEN_INTENTS_PER_ACCOUNT,
+            sub_intents: reinterpret(&old.sub_intents),
+            transition_expectations: reinterpret(&old.transition_expectations),
+            pending_withdrawals: reinterpret(&old.pending_withdrawals),
+            pending_withdrawals_by_user: UnorderedMap::new(b"u"),
+            escrowed_credits: UnorderedMap::new(b"e"),
+            sub_intents_by_parent: UnorderedMap::new(b"p"),
+            sub_intents_by_taker: UnorderedMap::new(b"t"),
+            // V0 kept no fill amounts, so history starts at migration.
+            intent_fills: UnorderedMap::new(b"h"),
+            // Deposits, withdrawals and volume were never tracked in V0 and
+            // start from zero; the rest is counted as records are rewritten.
+            stats: Stats::default(),
+            asset_volume: UnorderedMap::new(b"k"),
+            total_credited: UnorderedMap::new(b"C"),
+            total_debited: UnorderedMap::new(b"D"),
+            tombstones: UnorderedMap::new(b"l"),
+            record_retention_ns: DEFAULT_RECORD_RETENTION_NS,
+            escrow_timeout_ns: DEFAULT_ESCROW_TIMEOUT_NS,
+            sub_intent_timeout_ns: DEFAULT_SUB_INTENT_TIMEOUT_NS,
+            transition_timeout_ns: DEFAULT_TRANSITION_TIMEOUT_NS,
+            transition_bond: DEFAULT_TRANSITION_BOND,
+            min_sign_deposit: DEFAULT_MIN_SIGN_DEPOSIT,
+            signatures: UnorderedMap::new(b"g"),
+            pending_signs: UnorderedMap::new(b"Q"),
+            // Backfilled for V0 takes as their sub-intents are rewritten.
+            settlement_requests: UnorderedMap::new(b"U"),
+            mpc_root_keys: LookupMap::new(b"K"),
+            // Until `migrate_batch` has rewritten every record.
+            paused: true,
+            paused_pairs: UnorderedSet::new(b"q"),
+            volume_caps: UnorderedMap::new(b"V"),
+            matched_volume: UnorderedMap::new(b"Y"),
+            volume_window_blocks: DEFAULT_VOLUME_WINDOW_BLOCKS,
+            withdrawal_limits: UnorderedMap::new(b"W"),
+            withdrawal_usage: LookupMap::new(b"X"),
+            blocked_accounts: UnorderedSet::new(b"B"),
+            storage_accounts: UnorderedMap::new(b"r"),
+            consumed_deposits: UnorderedSet::new(b"d"),
+            // V0 recorded no proven transitions; replay protection starts here.
+            consumed_transitions: UnorderedSet::new(b"z"),
+            consumed_payments: UnorderedSet::new(b"P"),
+            // Empty: the owner registers assets before trading resumes.
+            assets: UnorderedMap::new(b"n"),
+            deposit_addresses: UnorderedMap::new(b"a"),
+            // V0 kept no deposit records; history starts here.
+            deposits: LookupMap::new(b"E"),
+            deposits_by_user: LookupMap::new(b"F"),
+            withdrawals: LookupMap::new(b"I"),
+            withdrawals_by_user: LookupMap::new(b"J"),
+            receive_addresses: UnorderedMap::new(b"y"),
+            last_batch_surplus: Vec::new(),
+            solvers: UnorderedMap::new(b"v"),
+            min_solver_bond: DEFAULT_MIN_SOLVER_BOND,
+            solver_allowlist_enabled: false,
+            next_intent_id: old.next_id,
+            next_sub_intent_id: old.next_id,
+            next_withdrawal_id: old.next_id,
+            next_deposit_id: 0,
+            next_transition_nonce: 0,
+            input_limits: InputLimits::default(),
+            next_batch_id: 0,
+            batches: LookupMap::new(b"M"),
+            batches_by_solver: LookupMap::new(b"N"),
+            held_surplus: UnorderedMap::new(b"S"),
+            settlement_proposals: LookupMap::new(b"Z"),
+            withdrawal_nonces: LookupMap::new(b"0"),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Maker rebates. A resting intent filled by a solver batch gets
+//! `maker_rebate_bps` of its proceeds back out of the protocol fee, paid
+//! with the rest of the proceeds when the fill's escrow is released.
+
+use crate::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeConfig {
+    /// Gross fee on batch-matched maker proceeds.
+    pub fee_bps: u16,
+    /// Part of `fee_bps` handed back to the maker.
+    pub maker_rebate_bps: u16,
+    /// Referrers' share of what is left of the fee after the rebate.
+    pub referral_bps: u16,
+    pub fee_collector: AccountId,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn get_fee_config(&self) -> FeeConfig {
+        FeeConfig {
+            fee_bps: self.fee_bps,
+            maker_rebate_bps: self.maker_rebate_bps,
+            referral_bps: self.referral_bps,
+            fee_collector: self.fee_collector.clone(),
+        }
+    }
+
+    /// Total rebates credited to makers in `asset`.
+    pub fn get_maker_rebates(&self, asset: String) -> U128 {
+        U128(self.maker_rebates.get(&asset).unwrap_or(0))
+    }
+}
+
+impl Orderbook {
+    /// Rebate on a fill of `get_amount`, rounded down and capped at `fee` in
+    /// case a fee cut took effect after the rebate was set.
+    pub(crate) fn maker_rebate(&self, get_amount: u128, fee: u128) -> u128 {
+        std::cmp::min(get_amount * self.maker_rebate_bps as u128 / 10_000, fee)
+    }
+
+    pub(crate) fn pay_maker_rebate(&mut self, sub_intent_id: u64, maker: &AccountId, asset: &str, amount: u128) {
+        self.internal_credit(maker, asset, amount, BalanceChangeReason::MakerRebate, Some(sub_intent_id));
+        let paid = self.maker_rebates.get(&asset.to_string()).unwrap_or(0);
+        self.maker_rebates.insert(&asset.to_string(), &(paid + amount));
+        env::log_str(&format!(
+            "MAKER_REBATE_PAID:sub_intent_id={},maker={},asset={},amount={}",
+            sub_intent_id, maker, asset, amount
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+//! External receive addresses. A maker registers where transitions paying
+//! them must land on each chain; every fill needs one for its transition
+//! chain, and the recipient named for it has to be that address. The
+//! recipient is copied into the transition expectation, so changing the
+//! address later does not move fills already matched.
+
+use crate::*;
+
+#[near_bindgen]
+impl Orderbook {
+    /// Register the caller's receive address on `chain_type`, replacing any
+    /// previous one. `None` removes it.
+    pub fn set_receive_address(&mut self, chain_type: ChainType, address: Option<String>) {
+        let initial_usage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+        let key = (account_id.clone(), chain_type.clone());
+        match &address {
+            Some(address) => {
+                Self::assert_valid_destination(&chain_type, address);
+                self.receive_addresses.insert(&key, address);
+            }
+            None => {
+                self.receive_addresses.remove(&key);
+            }
+        }
+        self.settle_storage(&account_id, initial_usage);
+        env::log_str(&format!(
+            "RECEIVE_ADDRESS_SET:account={},chain={:?},address={}",
+            account_id,
+            chain_type,
+            address.unwrap_or_default()
+        ));
+    }
+
+    pub fn get_receive_address(&self, account_id: AccountId, chain_type: ChainType) -> Option<String> {
+        self.receive_addresses.get(&(account_id, chain_type))
+    }
+}
+
+impl Orderbook {
+    /// `maker` must have registered a receive address on `chain_type`.
+    pub(crate) fn check_has_receive_address(&self, maker: &AccountId, chain_type: &ChainType) -> Result<(), OrderbookError> {
+        if self.receive_addresses.get(&(maker.clone(), chain_type.clone())).is_none() {
+            return Err(OrderbookError::NoReceiveAddress { maker: maker.clone(), chain_type: chain_type.clone() });
+        }
+        Ok(())
+    }
+
+    /// `recipient` must be a valid address on `chain_type` and the same
+    /// address as the receive address `maker` registered there.
+    pub(crate) fn check_transition_recipient(
+        &self,
+        maker: &AccountId,
+        chain_type: &ChainType,
+        recipient: &str,
+    ) -> Result<(), OrderbookError> {
+        self.check_has_receive_address(maker, chain_type)?;
+        Self::check_destination(chain_type, recipient)?;
+        let registered = self.receive_addresses.get(&(maker.clone(), chain_type.clone())).unwrap();
+        if Self::normalize_destination(chain_type, &registered) != Self::normalize_destination(chain_type, recipient) {
+            return Err(OrderbookError::RecipientMismatch {
+                recipient: recipient.to_string(),
+                chain_type: chain_type.clone(),
+                maker: maker.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Canonical spelling of a valid address, so two spellings of the same
+    /// address compare equal: hex and bech32 are case-insensitive, base58
+    /// is not.
+    fn normalize_destination(chain_type: &ChainType, destination: &str) -> String {
+        let lower = destination.to_lowercase();
+        match chain_type {
+            ChainType::ETH => lower,
+            ChainType::BTC if ["bc1", "tb1", "bcrt1"].iter().any(|hrp| lower.starts_with(hrp)) => lower,
+            ChainType::SOL | ChainType::BTC => destination.to_string(),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Referral share of the protocol fee. An intent may name the front-end
+//! that brought it in; when one of its fills releases its escrow, the
+//! referrer's cut of the fee is credited to the referrer's balance instead
+//! of the fee collector's.
+
+use crate::*;
+
+#[near_bindgen]
+impl Orderbook {
+    /// Referral fees `account` has been credited so far, per asset.
+    pub fn get_referral_earnings(&self, account: AccountId) -> Vec<(String, U128)> {
+        self.referral_earnings
+            .get(&account)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(asset, amount)| (asset, U128(amount)))
+            .collect()
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn pay_referral(&mut self, sub_intent_id: u64, referrer: &AccountId, asset: &str, amount: u128) {
+        self.internal_credit(referrer, asset, amount, BalanceChangeReason::ReferralFee, Some(sub_intent_id));
+        let mut earnings = self.referral_earnings.get(referrer).unwrap_or_default();
+        match earnings.iter_mut().find(|(a, _)| a == asset) {
+            Some((_, earned)) => *earned += amount,
+            None => earnings.push((asset.to_string(), amount)),
+        }
+        self.referral_earnings.insert(referrer, &earnings);
+        env::log_str(&format!(
+            "REFERRAL_PAID:sub_intent_id={},referrer={},asset={},amount={}",
+            sub_intent_id, referrer, asset, amount
+        ));
+    }
+}
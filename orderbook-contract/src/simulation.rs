@@ -0,0 +1,225 @@
+//! Dry run of `batch_match_intents`. Both go through `check_batch`, so a
+//! batch the simulation accepts is one the real call accepts against the
+//! same state (apart from the attached transition bond, which a view cannot
+//! see, and the volume caps of the circuit breaker).
+
+use crate::*;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchVerdict {
+    pub intent_id: U64,
+    /// First check this match fails, if any.
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetNet {
+    pub asset: String,
+    /// Sum of `fill_amount` over matches selling the asset.
+    pub supply: U128,
+    /// Sum of `get_amount` over matches buying it.
+    pub demand: U128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchSimulation {
+    /// Every failed check, in the order `batch_match_intents` runs them; the
+    /// first is the error the real call would fail with.
+    pub errors: Vec<String>,
+    pub matches: Vec<MatchVerdict>,
+    /// Per-asset totals over the matches that passed, sorted by asset.
+    pub net: Vec<AssetNet>,
+}
+
+impl BatchSimulation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// `check_batch` result, with the typed errors `apply_batch` returns.
+pub(crate) struct CheckedBatch {
+    pub errors: Vec<OrderbookError>,
+    pub matches: Vec<MatchVerdict>,
+    pub net: Vec<AssetNet>,
+}
+
+impl From<CheckedBatch> for BatchSimulation {
+    fn from(checked: CheckedBatch) -> Self {
+        BatchSimulation {
+            errors: checked.errors.iter().map(ToString::to_string).collect(),
+            matches: checked.matches,
+            net: checked.net,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Validate `matches` as if `solver` submitted them, without changing
+    /// any state.
+    pub fn simulate_batch_match(&self, solver: AccountId, matches: Vec<MatchParams>) -> BatchSimulation {
+        self.check_batch(&solver, &matches, MAX_BATCH_MATCHES).into()
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn check_batch(&self, solver: &AccountId, matches: &[MatchParams], max_matches: usize) -> CheckedBatch {
+        let mut errors = Vec::new();
+        if let Err(error) = self.check_not_paused() {
+            errors.push(error);
+        }
+        if matches.len() < 2 {
+            errors.push(OrderbookError::TooFewMatches);
+        }
+        if matches.len() > max_matches {
+            errors.push(OrderbookError::TooManyMatches { max: max_matches });
+        }
+        if let Err(error) = self.check_registered_solver(solver) {
+            errors.push(error);
+        }
+        if let Err(error) = self.check_not_blocked(solver) {
+            errors.push(error);
+        }
+
+        // Each intent may appear at most once per batch
+        let mut seen: HashSet<u64> = HashSet::new();
+        for m in matches {
+            let intent_id = m.intent_id.0;
+            if !seen.insert(intent_id) {
+                errors.push(OrderbookError::DuplicateIntent { id: intent_id });
+            }
+        }
+
+        // Per-asset totals; kept unsigned so large amounts can't wrap
+        let mut asset_supply: BTreeMap<String, u128> = BTreeMap::new();
+        let mut asset_demand: BTreeMap<String, u128> = BTreeMap::new();
+        let mut verdicts = Vec::new();
+        for (index, m) in matches.iter().enumerate() {
+            let error = self
+                .check_match(solver, m, &self.next_batch_memo(index), &mut asset_supply, &mut asset_demand)
+                .err();
+            verdicts.push(MatchVerdict {
+                intent_id: m.intent_id,
+                error: error.as_ref().map(ToString::to_string),
+            });
+            errors.extend(error);
+        }
+
+        // Verify solvency (conservation of mass)
+        for (asset, demand) in &asset_demand {
+            let supply = asset_supply.get(asset).copied().unwrap_or(0);
+            if supply < *demand {
+                errors.push(OrderbookError::InsufficientSupply {
+                    asset: asset.clone(),
+                    deficit: demand - supply,
+                });
+            }
+        }
+
+        let mut net: BTreeMap<String, AssetNet> = BTreeMap::new();
+        for (asset, supply) in asset_supply {
+            net.entry(asset.clone())
+                .or_insert_with(|| AssetNet { asset, supply: U128(0), demand: U128(0) })
+                .supply = U128(supply);
+        }
+        for (asset, demand) in asset_demand {
+            net.entry(asset.clone())
+                .or_insert_with(|| AssetNet { asset, supply: U128(0), demand: U128(0) })
+                .demand = U128(demand);
+        }
+
+        CheckedBatch {
+            errors,
+            matches: verdicts,
+            net: net.into_values().collect(),
+        }
+    }
+
+    /// Check one match, whose transition is signed under `memo`, against
+    /// its intent and, if it passes, add it to the per-asset totals.
+    fn check_match(
+        &self,
+        solver: &AccountId,
+        m: &MatchParams,
+        memo: &str,
+        asset_supply: &mut BTreeMap<String, u128>,
+        asset_demand: &mut BTreeMap<String, u128>,
+    ) -> Result<(), OrderbookError> {
+        let intent_id: u64 = m.intent_id.0;
+        let fill_amount: u128 = m.fill_amount.into();
+        let get_amount: u128 = m.get_amount.into();
+
+        let intent = self
+            .intents
+            .get(&intent_id)
+            .ok_or(OrderbookError::IntentNotFound { id: intent_id })?;
+        if intent.status != IntentStatus::Open {
+            return Err(OrderbookError::IntentNotOpen { id: intent_id });
+        }
+        if intent.is_expired(env::block_timestamp()) {
+            return Err(OrderbookError::IntentExpired { id: intent_id });
+        }
+        self.check_pair_not_paused(&intent.src_asset, &intent.dst_asset)?;
+        self.check_not_blocked(&intent.maker)?;
+        intent.check_taker_allowed(solver)?;
+        self.check_path_len(&m.path)?;
+        self.check_fields(&[("recipient", &m.recipient)])?;
+        self.check_transition_recipient(&intent.maker, &m.transition_chain_type, &m.recipient)?;
+        if m.transition_asset.to_uppercase() != intent.src_asset {
+            return Err(OrderbookError::TransitionAssetMismatch {
+                intent_id,
+                expected: intent.src_asset,
+                got: m.transition_asset.clone(),
+            });
+        }
+        if let Some(tx) = m.unsigned_tx.as_ref().filter(|tx| tx.len() > MAX_TRANSITION_TX_LEN) {
+            return Err(OrderbookError::TransitionTxTooLarge { intent_id, len: tx.len(), max: MAX_TRANSITION_TX_LEN });
+        }
+        // An ETH transition is signed over the keccak256 hash of its tx,
+        // which must pay the fill to the maker under the sub-intent's memo.
+        // BTC and SOL payloads cannot be derived from a checked tx yet, and
+        // would be signed blind under the treasury path.
+        if m.transition_chain_type != ChainType::ETH {
+            return Err(OrderbookError::UnsupportedTransitionChain { chain_type: m.transition_chain_type.clone() });
+        }
+        self.check_eth_transition(&intent, &m.recipient, fill_amount, memo, &m.payload, m.unsigned_tx.as_ref())?;
+        self.check_treasury_path(&m.transition_chain_type, &m.path)?;
+
+        let remaining_src = intent.src_amount - intent.filled_amount;
+        if fill_amount > remaining_src {
+            return Err(OrderbookError::ExceedsRemaining { intent_id });
+        }
+        intent.check_fill_allowed(fill_amount)?;
+        // Only the fill that empties the intent may be smaller than the
+        // asset's minimum, so batches leave no dust sub-intents.
+        let min_fill = self.min_intent_size(&intent.src_asset);
+        if fill_amount < min_fill && fill_amount != remaining_src {
+            return Err(OrderbookError::BelowMinFill { intent_id, amount: fill_amount, min: min_fill });
+        }
+
+        // Price Check: get_amount / fill_amount >= dst_amount / src_amount
+        let overflow = OrderbookError::PriceOverflow { intent_id };
+        let lhs = get_amount.checked_mul(intent.src_amount).ok_or(overflow.clone())?;
+        let rhs = fill_amount.checked_mul(intent.dst_amount).ok_or(overflow)?;
+        if lhs < rhs {
+            return Err(OrderbookError::PriceMismatch { intent_id, get_amount });
+        }
+
+        let supply = asset_supply.get(&intent.src_asset).copied().unwrap_or(0);
+        let supply = supply
+            .checked_add(fill_amount)
+            .ok_or_else(|| OrderbookError::AmountOverflow { asset: intent.src_asset.clone() })?;
+        let demand = asset_demand.get(&intent.dst_asset).copied().unwrap_or(0);
+        let demand = demand
+            .checked_add(get_amount)
+            .ok_or_else(|| OrderbookError::AmountOverflow { asset: intent.dst_asset.clone() })?;
+        asset_supply.insert(intent.src_asset, supply);
+        asset_demand.insert(intent.dst_asset, demand);
+        Ok(())
+    }
+}
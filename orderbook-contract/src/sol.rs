@@ -0,0 +1,98 @@
+//! Minimal parsing of unsigned Solana withdrawals so `withdraw` can check
+//! what MPC is asked to sign instead of trusting caller-supplied bytes.
+//!
+//! A withdrawal is a legacy message with one signer, the sender, and a
+//! single System Program transfer. Ed25519 signs the message itself, so
+//! the message is the payload. With no compute budget instruction its fee
+//! is the base fee of one signature.
+
+/// `11111111111111111111111111111111`.
+const SYSTEM_PROGRAM: [u8; 32] = [0; 32];
+const SYSTEM_TRANSFER: [u8; 4] = [2, 0, 0, 0];
+
+/// Lamports a transaction pays per signature.
+pub(crate) const LAMPORTS_PER_SIGNATURE: u128 = 5_000;
+
+/// The fields of an unsigned transfer message it is checked against.
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnsignedSolTransfer {
+    pub to: [u8; 32],
+    pub lamports: u64,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).ok_or("truncated")?;
+        let slice = self.bytes.get(self.pos..end).ok_or("truncated")?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// "Compact-u16": seven bits a byte, low first, at most three bytes.
+    fn compact_len(&mut self) -> Result<usize, String> {
+        let mut value = 0usize;
+        for shift in [0, 7, 14] {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return u16::try_from(value).map(usize::from).map_err(|_| "length out of range".to_string());
+            }
+        }
+        Err("length out of range".to_string())
+    }
+
+    fn compact_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.compact_len()?;
+        self.take(len)
+    }
+}
+
+/// Parse a serialized legacy message holding exactly one lamport transfer
+/// signed by its sender. The error says what is malformed or not allowed.
+pub(crate) fn parse_transfer_message(message: &[u8]) -> Result<UnsignedSolTransfer, String> {
+    let mut reader = Reader { bytes: message, pos: 0 };
+    let required_signatures = reader.byte()?;
+    if required_signatures & 0x80 != 0 {
+        return Err("only legacy messages are supported".to_string());
+    }
+    if required_signatures != 1 {
+        return Err("expected a single signer".to_string());
+    }
+    reader.take(2)?;
+    let keys = (0..reader.compact_len()?)
+        .map(|_| reader.array::<32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    reader.take(32)?;
+    if reader.compact_len()? != 1 {
+        return Err("expected a single instruction".to_string());
+    }
+    let program = *keys.get(usize::from(reader.byte()?)).ok_or("unknown program account")?;
+    let accounts = reader.compact_bytes()?;
+    let data = reader.compact_bytes()?;
+    if reader.pos != message.len() {
+        return Err("trailing bytes after the message".to_string());
+    }
+    if program != SYSTEM_PROGRAM || data.len() != 12 || data[..4] != SYSTEM_TRANSFER {
+        return Err("instruction is not a System Program transfer".to_string());
+    }
+    let [0, to] = accounts else {
+        return Err("transfer must be from the signer to one account".to_string());
+    };
+    Ok(UnsignedSolTransfer {
+        to: *keys.get(usize::from(*to)).ok_or("unknown recipient account")?,
+        lamports: u64::from_le_bytes(data[4..].try_into().unwrap()),
+    })
+}
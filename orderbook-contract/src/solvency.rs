@@ -0,0 +1,104 @@
+//! Per-asset solvency check. Deposits credit an asset and signed
+//! withdrawals debit it; everything in between only moves value between
+//! balances, intents and escrows, so what the contract owes should never
+//! exceed what came in and has not gone out again.
+
+use crate::*;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolvencyReport {
+    pub asset: String,
+    /// User balances, open intent remainders, taker escrows, escrowed
+    /// maker proceeds (fees included) and pending withdrawals.
+    pub liabilities: U128,
+    /// Sum of all deposits credited.
+    pub credited: U128,
+    /// Sum of all withdrawals whose signature was produced.
+    pub debited: U128,
+    /// `liabilities + debited <= credited`.
+    pub ok: bool,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Walks every balance, open intent, sub-intent escrow and pending
+    /// withdrawal; meant for monitoring, not for calls from other contracts.
+    pub fn check_solvency(&self, asset: String) -> SolvencyReport {
+        let asset = asset.to_uppercase();
+        let liabilities = self.liabilities().remove(&asset).unwrap_or(0);
+        let credited = self.total_credited.get(&asset).unwrap_or(0);
+        let debited = self.total_debited.get(&asset).unwrap_or(0);
+        SolvencyReport {
+            asset,
+            liabilities: U128(liabilities),
+            credited: U128(credited),
+            debited: U128(debited),
+            ok: liabilities.saturating_add(debited) <= credited,
+        }
+    }
+
+    pub fn assert_solvency(&mut self, asset: String) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can assert solvency");
+        let report = self.check_solvency(asset);
+        assert!(
+            report.ok,
+            "Insolvent in {}: liabilities {} + debited {} exceed credited {}",
+            report.asset,
+            report.liabilities.0,
+            report.debited.0,
+            report.credited.0
+        );
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn record_credit(&mut self, asset: &str, amount: u128) {
+        let credited = self.total_credited.get(&asset.to_string()).unwrap_or(0);
+        self.total_credited.insert(&asset.to_string(), &(credited + amount));
+    }
+
+    pub(crate) fn record_debit(&mut self, asset: &str, amount: u128) {
+        let debited = self.total_debited.get(&asset.to_string()).unwrap_or(0);
+        self.total_debited.insert(&asset.to_string(), &(debited + amount));
+    }
+
+    /// Everything owed to accounts, summed per asset.
+    pub(crate) fn liabilities(&self) -> BTreeMap<String, u128> {
+        let mut owed: BTreeMap<String, u128> = BTreeMap::new();
+        let mut add = |asset: &String, amount: u128| {
+            if amount > 0 {
+                *owed.entry(asset.clone()).or_insert(0) += amount;
+            }
+        };
+        for user_balances in self.balances.values() {
+            for (asset, amount) in user_balances.iter() {
+                add(&asset, amount);
+            }
+        }
+        for id in self.open_intent_ids.iter() {
+            if let Some(intent) = self.intents.get(&id) {
+                add(&intent.src_asset, intent.src_amount - intent.filled_amount);
+            }
+        }
+        for sub in self.sub_intents.values().filter(|sub| sub.escrow > 0) {
+            if let Some(parent) = self.intents.get(&sub.parent_intent_id) {
+                add(&parent.dst_asset, sub.escrow);
+            }
+        }
+        for escrow in self.escrowed_credits.values() {
+            add(&escrow.asset, escrow.amount + escrow.fee);
+        }
+        for held in self.held_surplus.values() {
+            for (asset, amount) in &held.amounts {
+                add(asset, amount.0);
+            }
+        }
+        for wd in self.pending_withdrawals.values() {
+            add(&wd.asset, wd.amount);
+            add(&wd.chain_type.native_asset().to_string(), wd.gas_fee);
+        }
+        owed
+    }
+}
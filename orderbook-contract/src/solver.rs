@@ -0,0 +1,130 @@
+//! Solver registry. Solvers lock a NEAR bond to register; when the owner
+//! turns the allowlist on, only registered solvers may match and retry
+//! settlements.
+
+use crate::*;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolverInfo {
+    pub account_id: AccountId,
+    pub bond: U128,
+    /// Sub-intents the solver still has to settle (`Verifying`, `Settled`
+    /// or `TransitionVerifying`).
+    pub active_sub_intents: u64,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Register the caller as a solver, or top up an existing bond. The
+    /// resulting bond must be at least `min_solver_bond`.
+    #[payable]
+    pub fn register_solver(&mut self) -> SolverInfo {
+        self.assert_not_paused();
+        let initial_usage = env::storage_usage();
+        let solver = env::predecessor_account_id();
+        let bond = self.solvers.get(&solver).unwrap_or(0) + env::attached_deposit().as_yoctonear();
+        assert!(
+            bond >= self.min_solver_bond,
+            "Solver bond must be at least {}",
+            self.min_solver_bond
+        );
+        self.solvers.insert(&solver, &bond);
+        self.settle_storage(&solver, initial_usage);
+        env::log_str(&format!("SOLVER_REGISTERED:solver={},bond={}", solver, bond));
+        self.solver_info(solver, bond)
+    }
+
+    /// Leave the registry and get the bond back. Refused while any of the
+    /// caller's settlements are still pending. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn unregister_solver(&mut self) {
+        assert_one_yocto();
+        let initial_usage = env::storage_usage();
+        let solver = env::predecessor_account_id();
+        let bond = self.solvers.get(&solver).expect("Solver not registered");
+        let active = self.active_sub_intent_count(&solver);
+        assert_eq!(active, 0, "Solver has {} pending settlements", active);
+
+        self.solvers.remove(&solver);
+        self.settle_storage(&solver, initial_usage);
+        if bond > 0 {
+            Promise::new(solver.clone()).transfer(NearToken::from_yoctonear(bond)).detach();
+        }
+        env::log_str(&format!("SOLVER_UNREGISTERED:solver={},bond={}", solver, bond));
+    }
+
+    /// Applies to new registrations and top-ups; existing bonds are kept.
+    pub fn set_min_solver_bond(&mut self, amount: U128) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can set the minimum solver bond"
+        );
+        self.min_solver_bond = amount.0;
+    }
+
+    pub fn set_solver_allowlist(&mut self, enabled: bool) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only owner can toggle the solver allowlist"
+        );
+        self.solver_allowlist_enabled = enabled;
+        env::log_str(&format!("SOLVER_ALLOWLIST:enabled={}", enabled));
+    }
+
+    pub fn get_solver(&self, account_id: AccountId) -> Option<SolverInfo> {
+        self.solvers.get(&account_id).map(|bond| self.solver_info(account_id, bond))
+    }
+
+    pub fn get_solvers(&self, from_index: u64, limit: u64) -> Vec<SolverInfo> {
+        self.solvers
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(account_id, bond)| self.solver_info(account_id, bond))
+            .collect()
+    }
+
+    pub fn is_solver_allowlist_enabled(&self) -> bool {
+        self.solver_allowlist_enabled
+    }
+}
+
+impl Orderbook {
+    /// Only checked while the allowlist is enabled.
+    pub(crate) fn check_registered_solver(&self, account_id: &AccountId) -> Result<(), OrderbookError> {
+        if self.solver_allowlist_enabled && self.solvers.get(account_id).is_none() {
+            return Err(OrderbookError::SolverNotRegistered { account: account_id.clone() });
+        }
+        Ok(())
+    }
+
+    fn active_sub_intent_count(&self, account_id: &AccountId) -> u64 {
+        self.sub_intents_by_taker
+            .get(account_id)
+            .into_iter()
+            .flat_map(|ids| ids.to_vec())
+            .filter_map(|id| self.sub_intents.get(&id))
+            .filter(|sub| {
+                matches!(
+                    sub.status,
+                    SubIntentStatus::Verifying
+                        | SubIntentStatus::Settled
+                        | SubIntentStatus::TransitionVerifying
+                        | SubIntentStatus::AwaitingSign
+                )
+            })
+            .count() as u64
+    }
+
+    fn solver_info(&self, account_id: AccountId, bond: u128) -> SolverInfo {
+        let active_sub_intents = self.active_sub_intent_count(&account_id);
+        SolverInfo {
+            account_id,
+            bond: U128(bond),
+            active_sub_intents,
+        }
+    }
+}
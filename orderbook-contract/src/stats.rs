@@ -0,0 +1,62 @@
+//! Market-wide counters for dashboards, updated alongside each state
+//! transition so `get_stats` stays a single cheap read.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stats {
+    pub total_intents_created: u64,
+    pub open_intents: u64,
+    /// Sub-intents by terminal status. Expired takes are deleted, so they
+    /// are only visible here.
+    pub sub_intents_completed: u64,
+    pub sub_intents_cancelled: u64,
+    pub sub_intents_expired: u64,
+    pub sub_intents_failed: u64,
+    pub sub_intents_force_cancelled: u64,
+    /// Credited deposits (`deposit_for` and verified MPC deposits).
+    pub total_deposits: u64,
+    /// Withdrawals whose signature was produced; refunded ones are not counted.
+    pub total_withdrawals: u64,
+    /// `on_signed` callbacks that failed, for sub-intents and withdrawals.
+    pub sign_failure_count: u64,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn get_stats(&self) -> Stats {
+        self.stats.clone()
+    }
+
+    /// Source amount of `asset` currently matched across all fills. Fills
+    /// that are unwound are taken back out.
+    pub fn get_asset_volume(&self, asset: String) -> U128 {
+        U128(self.asset_volume.get(&asset.to_uppercase()).unwrap_or(0))
+    }
+}
+
+impl Orderbook {
+    pub(crate) fn add_volume(&mut self, asset: &str, amount: u128) {
+        let volume = self.asset_volume.get(&asset.to_string()).unwrap_or(0);
+        self.asset_volume.insert(&asset.to_string(), &(volume + amount));
+    }
+
+    pub(crate) fn remove_volume(&mut self, asset: &str, amount: u128) {
+        let volume = self.asset_volume.get(&asset.to_string()).unwrap_or(0);
+        self.asset_volume.insert(&asset.to_string(), &volume.saturating_sub(amount));
+    }
+
+    /// Count a sub-intent reaching `status`.
+    pub(crate) fn record_sub_intent_outcome(&mut self, status: &SubIntentStatus) {
+        let counter = match status {
+            SubIntentStatus::Completed => &mut self.stats.sub_intents_completed,
+            SubIntentStatus::Cancelled => &mut self.stats.sub_intents_cancelled,
+            SubIntentStatus::Expired => &mut self.stats.sub_intents_expired,
+            SubIntentStatus::Failed => &mut self.stats.sub_intents_failed,
+            SubIntentStatus::ForceCancelled => &mut self.stats.sub_intents_force_cancelled,
+            _ => return,
+        };
+        *counter += 1;
+    }
+}
@@ -0,0 +1,125 @@
+//! Storage staking. Callers prepay NEAR for the bytes their records occupy;
+//! state-creating methods charge that balance and freed bytes are credited
+//! back.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct StorageAccount {
+    /// Total yoctoNEAR deposited for storage.
+    pub deposit: u128,
+    /// Bytes currently attributed to the account.
+    pub bytes_used: u64,
+}
+
+impl StorageAccount {
+    fn locked(&self) -> u128 {
+        self.bytes_used as u128 * env::storage_byte_cost().as_yoctonear()
+    }
+
+    fn available(&self) -> u128 {
+        self.deposit.saturating_sub(self.locked())
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+impl From<&StorageAccount> for StorageBalance {
+    fn from(account: &StorageAccount) -> Self {
+        StorageBalance {
+            total: U128(account.deposit),
+            available: U128(account.available()),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Add NEAR to the storage balance of `account_id` (defaults to caller).
+    /// The first deposit also pays for the account's own storage entry.
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>) -> StorageBalance {
+        let initial_usage = env::storage_usage();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let mut account = self.storage_accounts.get(&account_id).unwrap_or_default();
+        account.deposit += env::attached_deposit().as_yoctonear();
+        self.storage_accounts.insert(&account_id, &account);
+        self.settle_storage(&account_id, initial_usage);
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Withdraw unlocked storage balance back to the caller. `None` withdraws
+    /// everything that is not covering bytes in use. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.storage_accounts.get(&account_id).expect("Storage not registered");
+        let available = account.available();
+        let amount = amount.map(|a| a.0).unwrap_or(available);
+        assert!(amount <= available, "Storage withdraw exceeds available balance {}", available);
+
+        account.deposit -= amount;
+        self.storage_accounts.insert(&account_id, &account);
+        if amount > 0 {
+            Promise::new(account_id).transfer(NearToken::from_yoctonear(amount)).detach();
+        }
+        StorageBalance::from(&account)
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.get(&account_id).map(|a| StorageBalance::from(&a))
+    }
+}
+
+impl Orderbook {
+    /// Attribute the storage change since `initial_usage` to `account_id`.
+    /// Growth must be covered by the account's deposit; shrinkage is
+    /// credited back to accounts that are registered.
+    pub(crate) fn settle_storage(&mut self, account_id: &AccountId, initial_usage: u64) {
+        if let Err(error) = self.try_settle_storage(account_id, initial_usage) {
+            error.panic();
+        }
+    }
+
+    /// `settle_storage` for entry points that return `OrderbookError`. On
+    /// an error nothing is recorded; the failed call rolls back the rest.
+    pub(crate) fn try_settle_storage(&mut self, account_id: &AccountId, initial_usage: u64) -> Result<(), OrderbookError> {
+        let usage = env::storage_usage();
+        if usage > initial_usage {
+            let mut account = self
+                .storage_accounts
+                .get(account_id)
+                .ok_or_else(|| OrderbookError::StorageNotRegistered { account: account_id.clone() })?;
+            account.bytes_used += usage - initial_usage;
+            if account.deposit < account.locked() {
+                return Err(OrderbookError::InsufficientStorage { account: account_id.clone() });
+            }
+            self.storage_accounts.insert(account_id, &account);
+        } else if let Some(mut account) = self.storage_accounts.get(account_id) {
+            account.bytes_used = account.bytes_used.saturating_sub(initial_usage - usage);
+            self.storage_accounts.insert(account_id, &account);
+        }
+        Ok(())
+    }
+
+    /// Drop `bytes` from the account's attributed usage together with the
+    /// deposit that was locked for them, e.g. when someone else is paid for
+    /// freeing them. Returns the yoctoNEAR taken.
+    pub(crate) fn release_storage(&mut self, account_id: &AccountId, bytes: u64) -> u128 {
+        let Some(mut account) = self.storage_accounts.get(account_id) else {
+            return 0;
+        };
+        let bytes = bytes.min(account.bytes_used);
+        let amount = bytes as u128 * env::storage_byte_cost().as_yoctonear();
+        account.bytes_used -= bytes;
+        account.deposit = account.deposit.saturating_sub(amount);
+        self.storage_accounts.insert(account_id, &account);
+        amount
+    }
+}
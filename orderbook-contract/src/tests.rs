@@ -2,6 +2,7 @@ use crate::*;
 use near_sdk::test_utils::{accounts, VMContextBuilder};
 use near_sdk::{testing_env, AccountId, NearToken, Gas};
 use near_sdk::json_types::U128;
+use crate::migration::LegacyStatus;
 use std::str::FromStr;
 
 // ============================================================================
@@ -17,6 +18,19 @@ fn user_charlie() -> AccountId { AccountId::from_str("charlie.testnet").unwrap()
 fn user_dave() -> AccountId { AccountId::from_str("dave.testnet").unwrap() }
 fn u(v: u128) -> U128 { U128(v) }
 
+/// Light-client verdict on a payment proof included at the finalized height.
+fn verified(valid: bool) -> Result<VerificationResult, PromiseError> {
+    Ok(VerificationResult { outcome: transition_verified(valid).unwrap(), block_height: 100, finalized_height: 100 })
+}
+
+/// A light client verdict; a rejection reports `AmountMismatch`.
+fn transition_verified(valid: bool) -> Result<VerifyOutcome, PromiseError> {
+    Ok(match valid {
+        true => VerifyOutcome::Valid,
+        false => VerifyOutcome::Invalid(VerifyError::AmountMismatch),
+    })
+}
+
 fn get_context(predecessor: AccountId, deposit: NearToken) -> VMContextBuilder {
     let mut builder = VMContextBuilder::new();
     builder
@@ -32,47 +46,263 @@ fn get_context(predecessor: AccountId, deposit: NearToken) -> VMContextBuilder {
 fn new_contract() -> (Orderbook, VMContextBuilder) {
     let context = get_context(orderbook_contract(), NearToken::from_near(0));
     testing_env!(context.build());
-    let contract = Orderbook::new(mpc_contract(), light_client_contract());
+    let mut contract = Orderbook::new(mpc_contract(), light_client_contract());
+    register_test_assets(&mut contract);
     (contract, context)
 }
 
+/// Register every asset the tests trade. Caller must be the owner.
+fn register_test_assets(contract: &mut Orderbook) {
+    for chain_type in [ChainType::ETH, ChainType::SOL, ChainType::BTC] {
+        contract.set_treasury_path(chain_type, Some("default/path".to_string()));
+    }
+    contract.register_asset("ETH".to_string(), ChainType::ETH, 18, u(0));
+    contract.register_asset("USDC".to_string(), ChainType::ETH, 6, u(0));
+    contract.register_asset("SOL".to_string(), ChainType::SOL, 9, u(0));
+    contract.register_asset("BTC".to_string(), ChainType::BTC, 8, u(0));
+    // SOL and BTC bridged to ETH: batches only sign ETH transitions, so
+    // the tests that match them trade these instead.
+    contract.register_asset("WSOL".to_string(), ChainType::ETH, 9, u(0));
+    contract.register_asset("WBTC".to_string(), ChainType::ETH, 8, u(0));
+    for symbol in ["A", "B", "C"] {
+        contract.register_asset(symbol.to_string(), ChainType::ETH, 18, u(0));
+    }
+}
+
 fn mock_sig() -> SignResult {
-    SignResult {
+    SignResult::Secp256k1 {
         big_r: AffinePoint { affine_point: "mock_r".to_string() },
         s: Scalar { scalar: "mock_s".to_string() },
         recovery_id: 1,
     }
 }
 
+fn mock_ed25519_sig() -> SignResult {
+    SignResult::Ed25519 { signature: vec![7u8; 64] }
+}
+
+/// The withdrawal path of the current caller on `chain_type`.
+fn own_path(chain_type: &ChainType) -> String {
+    user_path(&near_sdk::env::predecessor_account_id(), chain_type)
+}
+
+/// Fill in the `transition_asset` that `mp` leaves empty with the src_asset
+/// of each match's intent.
+fn bind_assets(contract: &Orderbook, matches: Vec<MatchParams>) -> Vec<MatchParams> {
+    matches.into_iter().enumerate().map(|(index, m)| bind_transition(contract, m, index)).collect()
+}
+
+/// Fill in the transition a default match implies: the intent's src asset,
+/// settled on that asset's chain, and on ETH a transaction paying the fill
+/// to the recipient under the memo of the `index`th match of the next
+/// batch, with its hash as payload.
+fn bind_transition(contract: &Orderbook, m: MatchParams, index: usize) -> MatchParams {
+    let Some(intent) = contract.get_intent(m.intent_id).filter(|_| m.transition_asset.is_empty()) else {
+        return m;
+    };
+    let chain = contract.get_asset(intent.src_asset.clone()).map_or(ChainType::ETH, |info| info.chain_type);
+    let mut m = MatchParams { transition_asset: intent.src_asset, ..m };
+    if m.transition_chain_type != ChainType::ETH || m.unsigned_tx.is_some() {
+        return m;
+    }
+    if chain != ChainType::ETH {
+        let recipient = if m.recipient == ETH_DEST { dest_for(&chain).to_string() } else { m.recipient };
+        return MatchParams {
+            recipient,
+            scheme: SignatureScheme::for_chain(&chain),
+            transition_chain_type: chain,
+            ..m
+        };
+    }
+    let memo = contract.next_batch_memo(index);
+    let tx = eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, &m.recipient, m.fill_amount.0, memo.as_bytes());
+    let tx = hex::decode(tx.trim_start_matches("0x")).unwrap();
+    m.payload = env::keccak256_array(&tx);
+    m.unsigned_tx = Some(tx);
+    m
+}
+
 /// Build MatchParams with default signing fields.
-fn mp(intent_id: U128, fill: u128, get: u128) -> MatchParams {
+fn mp(intent_id: U64, fill: u128, get: u128) -> MatchParams {
     MatchParams {
+        transition_asset: String::new(),
+        unsigned_tx: None,
         intent_id,
         fill_amount: u(fill),
         get_amount: u(get),
         payload: [1u8; 32],
         path: "default/path".to_string(),
         transition_chain_type: ChainType::ETH,
+        scheme: SignatureScheme::Secp256k1,
+        recipient: ETH_DEST.to_string(),
     }
 }
 
-fn mp_with_chain(intent_id: U128, fill: u128, get: u128, chain: ChainType) -> MatchParams {
+fn mp_with_chain(intent_id: U64, fill: u128, get: u128, chain: ChainType) -> MatchParams {
     MatchParams {
+        transition_asset: String::new(),
+        unsigned_tx: None,
         intent_id,
         fill_amount: u(fill),
         get_amount: u(get),
         payload: [1u8; 32],
         path: "default/path".to_string(),
-        transition_chain_type: chain,
+        recipient: dest_for(&chain).to_string(),
+        transition_chain_type: chain.clone(),
+        scheme: SignatureScheme::for_chain(&chain),
+    }
+}
+
+/// Withdrawal destination used by the ETH withdraw tests.
+const ETH_DEST: &str = "0x00000000000000000000000000000000000000aa";
+/// Well-formed Solana address, for withdrawals that must get past the
+/// destination check.
+const SOL_DEST: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+const BTC_DEST: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+fn dest_for(chain: &ChainType) -> &'static str {
+    match chain {
+        ChainType::BTC => BTC_DEST,
+        ChainType::ETH => ETH_DEST,
+        ChainType::SOL => SOL_DEST,
+    }
+}
+
+fn rlp_encode(prefix: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = if payload.len() <= 55 {
+        vec![prefix + payload.len() as u8]
+    } else {
+        let len = payload.len().to_be_bytes();
+        let len: Vec<u8> = len.iter().copied().skip_while(|b| *b == 0).collect();
+        let mut header = vec![prefix + 55 + len.len() as u8];
+        header.extend(len);
+        header
+    };
+    out.extend_from_slice(payload);
+    out
+}
+
+fn rlp_str(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    rlp_encode(0x80, bytes)
+}
+
+fn rlp_uint(v: u128) -> Vec<u8> {
+    let bytes: Vec<u8> = v.to_be_bytes().iter().copied().skip_while(|b| *b == 0).collect();
+    rlp_str(&bytes)
+}
+
+/// Hex of an unsigned EIP-1559 transaction sending `value` wei to `to`.
+/// Free of gas, so withdrawals built from it debit only their amount.
+fn eth_tx_with(chain_id: u64, nonce: u64, to: &str, value: u128, data: &[u8]) -> String {
+    eth_tx_priced(chain_id, nonce, to, value, data, 0)
+}
+
+fn eth_tx_priced(chain_id: u64, nonce: u64, to: &str, value: u128, data: &[u8], max_fee_per_gas: u128) -> String {
+    let fields = [
+        rlp_uint(chain_id as u128),
+        rlp_uint(nonce as u128),
+        rlp_uint(1_000_000_000),
+        rlp_uint(max_fee_per_gas),
+        rlp_uint(21_000),
+        rlp_str(&hex::decode(to.trim_start_matches("0x")).unwrap()),
+        rlp_uint(value),
+        rlp_str(data),
+        rlp_encode(0xc0, &[]),
+    ]
+    .concat();
+    let mut tx = vec![0x02];
+    tx.extend(rlp_encode(0xc0, &fields));
+    format!("0x{}", hex::encode(tx))
+}
+
+fn eth_tx(value: u128) -> String {
+    eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, value, &[])
+}
+
+/// Script of the P2WPKH output BTC withdrawals in these tests spend.
+const BTC_INPUT_SCRIPT: [u8; 22] = [
+    0x00, 0x14, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+    0x11, 0x11, 0x11,
+];
+
+/// Hex of a PSBT spending `input_value` sats from `BTC_INPUT_SCRIPT` to
+/// `outputs`, given as `(script, sats)`.
+fn btc_psbt(input_value: u64, outputs: &[(&[u8], u64)]) -> String {
+    let mut tx = vec![2, 0, 0, 0, 1];
+    tx.extend([0x42; 32]);
+    tx.extend([0, 0, 0, 0, 0, 0xfd, 0xff, 0xff, 0xff]);
+    tx.push(outputs.len() as u8);
+    for (script, value) in outputs {
+        tx.extend(value.to_le_bytes());
+        tx.push(script.len() as u8);
+        tx.extend(*script);
+    }
+    tx.extend([0, 0, 0, 0]);
+
+    let mut psbt = b"psbt\xff".to_vec();
+    psbt.extend([1, 0, tx.len() as u8]);
+    psbt.extend(&tx);
+    psbt.push(0);
+    psbt.extend([1, 1, 8 + 1 + BTC_INPUT_SCRIPT.len() as u8]);
+    psbt.extend(input_value.to_le_bytes());
+    psbt.push(BTC_INPUT_SCRIPT.len() as u8);
+    psbt.extend(BTC_INPUT_SCRIPT);
+    psbt.push(0);
+    psbt.extend(std::iter::repeat_n(0, outputs.len()));
+    hex::encode(psbt)
+}
+
+fn btc_dest_script() -> Vec<u8> {
+    crate::btc::address_script(BTC_DEST).unwrap()
+}
+
+/// Hex of a legacy SOL message transferring `lamports` from the signer to
+/// `to`.
+fn sol_transfer(to: &str, lamports: u64) -> String {
+    let mut message = vec![1, 0, 1, 3];
+    message.extend([0x05; 32]);
+    message.extend(crate::btc::base58_decode(to).unwrap());
+    message.extend([0; 32]);
+    message.extend([0x09; 32]);
+    message.extend([1, 2, 2, 0, 1, 12, 2, 0, 0, 0]);
+    message.extend(lamports.to_le_bytes());
+    hex::encode(message)
+}
+
+/// Give `account` a storage balance large enough for any test scenario, and
+/// the `dest_for` receive address on every chain so it can be matched.
+fn register_storage(contract: &mut Orderbook, context: &mut VMContextBuilder, account: &AccountId) {
+    if contract.storage_balance_of(account.clone()).is_some() {
+        return;
+    }
+    testing_env!(context.predecessor_account_id(account.clone()).attached_deposit(NearToken::from_near(1)).build());
+    contract.storage_deposit(None);
+    testing_env!(context.attached_deposit(NearToken::from_near(0)).build());
+    for chain_type in [ChainType::ETH, ChainType::SOL, ChainType::BTC] {
+        contract.set_receive_address(chain_type.clone(), Some(dest_for(&chain_type).to_string()));
     }
 }
 
-/// Owner deposits for a user. Caller must have set predecessor to owner beforehand.
+/// Owner deposits for a user, registering the user's storage first.
 fn owner_deposit(contract: &mut Orderbook, context: &mut VMContextBuilder, user: &AccountId, asset: &str, amount: u128) {
+    register_storage(contract, context, user);
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
     contract.deposit_for(user.clone(), asset.to_string(), u(amount));
 }
 
+/// Drive sub-intents through a successful sign and transition proof so their
+/// escrowed maker proceeds are released.
+fn release_escrows(contract: &mut Orderbook, context: &mut VMContextBuilder, sub_ids: &[u64]) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    for &id in sub_ids {
+        contract.on_signed(SignTarget::SubIntent(id), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+        contract.on_transition_verified(U64(id), format!("tx-{}", id), transition_verified(true));
+    }
+}
+
 // ============================================================================
 // 1. DEPOSIT TESTS
 // ============================================================================
@@ -128,25 +358,29 @@ fn test_deposit_via_mpc_verification_callback() {
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
     let user = user_alice();
     let result = contract.on_mpc_deposit_verified(
-        user.clone(), "SOL".to_string(), U128(500),
+        user.clone(), ChainType::SOL, "SOL".to_string(), U128(500),
         "mpc-sol-addr".to_string(),
         format!("mpc:deposit:{}:SOL", user),
-        Ok(true),
+        "dep-tx-1".to_string(),
+        verified(true),
     );
-    assert_eq!(result, "MpcDepositCredited");
+    assert_eq!(result, DepositOutcome { credited: true, reason: None });
     assert_eq!(contract.get_balance(user, "SOL".to_string()), u(500));
 }
 
 #[test]
-#[should_panic(expected = "MPC deposit proof invalid")]
 fn test_deposit_via_mpc_verification_rejected() {
     let (mut contract, mut context) = new_contract();
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
-    contract.on_mpc_deposit_verified(
-        user_alice(), "SOL".to_string(), U128(500),
+    let outcome = contract.on_mpc_deposit_verified(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
         "addr".to_string(), "mpc:deposit:x:SOL".to_string(),
-        Ok(false),
+        "dep-tx-2".to_string(),
+        verified(false),
     );
+    assert_eq!(outcome, DepositOutcome { credited: false, reason: Some("MPC deposit proof invalid: AmountMismatch".to_string()) });
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+    assert!(!contract.is_deposit_consumed(ChainType::SOL, "dep-tx-2".to_string()));
 }
 
 // ============================================================================
@@ -159,7 +393,7 @@ fn test_make_intent_basic() {
     owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
 
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let id = contract.make_intent("SOL".to_string(), u(500), "ETH".to_string(), u(100));
+    let id = contract.make_intent("SOL".to_string(), u(500), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     let intent = contract.get_intent(id).unwrap();
     assert_eq!(intent.maker, user_alice());
@@ -170,20 +404,20 @@ fn test_make_intent_basic() {
 }
 
 #[test]
-#[should_panic(expected = "Insufficient balance")]
 fn test_make_intent_insufficient_balance() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    contract.make_intent("SOL".to_string(), u(200), "ETH".to_string(), u(50));
+    let error = contract.make_intent("SOL".to_string(), u(200), "ETH".to_string(), u(50), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientBalance { asset: "SOL".to_string(), have: 100, need: 200 });
 }
 
 #[test]
-#[should_panic(expected = "User not found")]
 fn test_make_intent_no_deposit() {
     let (mut contract, mut context) = new_contract();
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(50));
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(50), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientBalance { asset: "SOL".to_string(), have: 0, need: 100 });
 }
 
 #[test]
@@ -191,12 +425,102 @@ fn test_make_multiple_intents_same_user() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let id1 = contract.make_intent("SOL".to_string(), u(300), "ETH".to_string(), u(30));
-    let id2 = contract.make_intent("SOL".to_string(), u(400), "BTC".to_string(), u(1));
+    let id1 = contract.make_intent("SOL".to_string(), u(300), "ETH".to_string(), u(30), None, None, None, None, None, None).unwrap();
+    let id2 = contract.make_intent("SOL".to_string(), u(400), "BTC".to_string(), u(1), None, None, None, None, None, None).unwrap();
     assert_ne!(id1.0, id2.0);
     assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(300));
 }
 
+#[test]
+fn test_make_intent_zero_src_amount_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(0), "ETH".to_string(), u(50), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "src_amount is zero".to_string() });
+}
+
+#[test]
+fn test_make_intent_zero_dst_amount_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(0), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "dst_amount is zero".to_string() });
+}
+
+#[test]
+fn test_make_intent_same_asset_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "src_asset equals dst_asset".to_string() });
+}
+
+#[test]
+fn test_make_intent_empty_asset_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "".to_string(), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "empty asset".to_string() });
+}
+
+#[test]
+fn test_make_intent_asset_too_long_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "X".repeat(MAX_ASSET_LEN + 1), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "asset longer than 32 bytes".to_string() });
+}
+
+#[test]
+#[should_panic(expected = "Parent intent has zero src_amount")]
+fn test_submit_payment_proof_zero_src_amount_panics() {
+    let (mut contract, mut context) = new_contract();
+    // Zero-amount intents can no longer be created; seed one as older state could hold it
+    contract.intents.insert(&0, &Intent {
+        id: 0,
+        maker: user_alice(),
+        src_asset: "SOL".to_string(),
+        src_amount: 0,
+        filled_amount: 0,
+        dst_asset: "ETH".to_string(),
+        dst_amount: 100,
+        status: IntentStatus::Open,
+        expires_at: None,
+        min_fill_amount: 0,
+        all_or_nothing: false,
+        closed_at: None,
+        allowed_takers: None,
+        referrer: None,
+        client_id: None,
+        replaced_from: None,
+    });
+    contract.sub_intents.insert(&0, &SubIntent {
+        id: 0,
+        parent_intent_id: 0,
+        taker: solver_bob(),
+        amount: 10,
+        status: SubIntentStatus::Taken,
+        escrow: 0,
+        expires_at: None,
+        bond: 0,
+        closed_at: None,
+        transition_tx_hash: None,
+        batch_id: None,
+    });
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = contract.submit_payment_proof(
+        U64(0), vec![], [0u8; 32], None, "default/path".to_string(),
+        ChainType::SOL, ChainType::ETH, "recipient".to_string(), "sub:0".to_string(),
+        ETH_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).unwrap();
+}
+
 // ============================================================================
 // 3. TAKE INTENT TESTS
 // ============================================================================
@@ -204,52 +528,64 @@ fn test_make_multiple_intents_same_user() {
 #[test]
 fn test_take_intent_partial() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
     owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 1000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000));
+    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
 
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    let sub_id = contract.take_intent(intent_id, u(30));
+    let sub_id = contract.take_intent(intent_id, u(30)).unwrap();
 
     let intent = contract.get_intent(intent_id).unwrap();
     assert_eq!(intent.filled_amount, 30);
     assert_eq!(intent.status, IntentStatus::Open);
-    assert_eq!(contract.get_sub_intent(sub_id).unwrap().status, IntentStatus::Taken);
+    assert_eq!(contract.get_sub_intent(sub_id).unwrap().status, SubIntentStatus::Taken);
 }
 
 #[test]
 fn test_take_intent_full() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
     owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 1000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000));
+    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    contract.take_intent(intent_id, u(100));
+    contract.take_intent(intent_id, u(100)).unwrap();
     assert_eq!(contract.get_intent(intent_id).unwrap().status, IntentStatus::Filled);
 }
 
 #[test]
-#[should_panic(expected = "Amount exceeds remaining balance")]
 fn test_take_intent_exceeds_remaining() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
     owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 2000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000));
+    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    contract.take_intent(intent_id, u(60));
-    contract.take_intent(intent_id, u(50));
+    contract.take_intent(intent_id, u(60)).unwrap();
+    let error = contract.take_intent(intent_id, u(50)).unwrap_err();
+    assert_eq!(error, OrderbookError::ExceedsRemaining { intent_id: 0 });
 }
 
 #[test]
-#[should_panic(expected = "Intent already filled")]
 fn test_take_intent_already_filled() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
     owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 2000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000));
+    let intent_id = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    contract.take_intent(intent_id, u(100));
-    contract.take_intent(intent_id, u(1));
+    contract.take_intent(intent_id, u(100)).unwrap();
+    let error = contract.take_intent(intent_id, u(1)).unwrap_err();
+    assert_eq!(error, OrderbookError::IntentFilled { id: 0 });
 }
 
 // ============================================================================
@@ -262,23 +598,28 @@ fn test_batch_match_simple_swap() {
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &alice, "WSOL", 100);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id1 = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id1 = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id2 = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100));
+    let id2 = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 100), mp(id2, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 100)])).unwrap();
+
+    // Proceeds are escrowed until the transitions are proven
+    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(0));
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().amount, 100);
+    release_escrows(&mut contract, &mut context, &[0, 1]);
 
     assert_eq!(contract.get_balance(alice, "ETH".to_string()), u(100));
-    assert_eq!(contract.get_balance(bob, "SOL".to_string()), u(100));
+    assert_eq!(contract.get_balance(bob, "WSOL".to_string()), u(100));
     assert_eq!(contract.get_intent(id1).unwrap().status, IntentStatus::Filled);
 }
 
@@ -292,18 +633,19 @@ fn test_batch_match_partial_fill() {
     owner_deposit(&mut contract, &mut context, &bob, "B", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100));
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id2 = contract.make_intent("B".to_string(), u(50), "A".to_string(), u(50));
+    let id2 = contract.make_intent("B".to_string(), u(50), "A".to_string(), u(50), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 50, 50), mp(id2, 50, 50)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 50, 50), mp(id2, 50, 50)])).unwrap();
 
-    assert_eq!(contract.get_balance(alice, "B".to_string()), u(50));
+    let escrow = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow.maker, escrow.asset, escrow.amount), (alice, "B".to_string(), 50));
     let i1 = contract.get_intent(id1).unwrap();
     assert_eq!(i1.filled_amount, 50);
     assert_eq!(i1.status, IntentStatus::Open); // Partial
@@ -316,27 +658,59 @@ fn test_batch_match_3way_ring() {
     let bob = solver_bob();
     let charlie = user_charlie();
 
-    owner_deposit(&mut contract, &mut context, &alice, "BTC", 100);
+    owner_deposit(&mut contract, &mut context, &alice, "WBTC", 100);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 1000);
-    owner_deposit(&mut contract, &mut context, &charlie, "SOL", 500);
+    owner_deposit(&mut contract, &mut context, &charlie, "WSOL", 500);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id1 = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(1000));
+    let id1 = contract.make_intent("WBTC".to_string(), u(100), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id2 = contract.make_intent("ETH".to_string(), u(1000), "SOL".to_string(), u(500));
+    let id2 = contract.make_intent("ETH".to_string(), u(1000), "WSOL".to_string(), u(500), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(charlie.clone()).build());
-    let id3 = contract.make_intent("SOL".to_string(), u(500), "BTC".to_string(), u(100));
+    let id3 = contract.make_intent("WSOL".to_string(), u(500), "WBTC".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let sub_ids = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 1000), mp(id2, 1000, 500), mp(id3, 500, 100)])).unwrap();
+
+    assert_eq!(sub_ids, vec![U64(0), U64(1), U64(2)]);
+    for (sub_id, parent) in sub_ids.iter().zip([id1, id2, id3]) {
+        assert_eq!(contract.get_sub_intent(*sub_id).unwrap().parent_intent_id, parent.0);
+    }
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().maker, alice);
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().amount, 1000);
+    assert_eq!(contract.get_escrow(U64(1)).unwrap().maker, bob);
+    assert_eq!(contract.get_escrow(U64(1)).unwrap().amount, 500);
+    assert_eq!(contract.get_escrow(U64(2)).unwrap().maker, charlie);
+    assert_eq!(contract.get_escrow(U64(2)).unwrap().amount, 100);
+}
+
+#[test]
+fn test_batch_match_returns_sub_intent_ids_in_match_order() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 200);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 150);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("A".to_string(), u(200), "B".to_string(), u(200), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    // An earlier take bumps the sub-intent counter so ids don't start at 0
+    contract.take_intent(id1, u(50)).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 1000), mp(id2, 1000, 500), mp(id3, 500, 100)]);
+    let sub_ids = contract.batch_match_intents(bind_assets(&contract, vec![mp(id2, 100, 100), mp(id1, 100, 100)])).unwrap();
 
-    assert_eq!(contract.get_balance(alice, "ETH".to_string()), u(1000));
-    assert_eq!(contract.get_balance(bob, "SOL".to_string()), u(500));
-    assert_eq!(contract.get_balance(charlie, "BTC".to_string()), u(100));
+    assert_eq!(sub_ids, vec![U64(1), U64(2)]);
+    assert_eq!(contract.get_sub_intent(sub_ids[0]).unwrap().parent_intent_id, id2.0);
+    assert_eq!(contract.get_sub_intent(sub_ids[1]).unwrap().parent_intent_id, id1.0);
 }
 
 #[test]
@@ -349,73 +723,72 @@ fn test_batch_match_sub_intents_start_as_verifying() {
     owner_deposit(&mut contract, &mut context, &bob, "B", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100));
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100));
+    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
-    // IDs: id1=0, id2=1, sub for id1=2, sub for id2=3
+    // Intent ids: 0, 1. Sub-intent ids: 0, 1 (independent counter)
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 100), mp(id2, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 100)])).unwrap();
 
     // Sub-intents start as Verifying (MPC sign auto-triggered)
-    assert_eq!(contract.get_sub_intent(u(2)).unwrap().status, IntentStatus::Verifying);
-    assert_eq!(contract.get_sub_intent(u(3)).unwrap().status, IntentStatus::Verifying);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Verifying);
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().status, SubIntentStatus::Verifying);
 
     // Transition expectations recorded
-    assert!(contract.get_transition_expectation(u(2)).is_some());
-    assert!(contract.get_transition_expectation(u(3)).is_some());
+    assert!(contract.get_transition_expectation(U64(0)).is_some());
+    assert!(contract.get_transition_expectation(U64(1)).is_some());
 }
 
 #[test]
-#[should_panic(expected = "At least 2 intents required")]
-fn test_batch_match_single_intent_panics() {
+fn test_batch_match_single_intent_fails() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100));
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 100)]);
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::TooFewMatches);
 }
 
 #[test]
-#[should_panic(expected = "Insufficient supply for asset")]
-fn test_batch_match_insolvent_panics() {
+fn test_batch_match_insolvent_fails() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
     owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 100);
 
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100));
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100));
+    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 100), mp(id2, 100, 110)]);
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 110)])).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientSupply { asset: "A".to_string(), deficit: 10 });
 }
 
 #[test]
-#[should_panic(expected = "Price mismatch")]
-fn test_batch_match_bad_price_panics() {
+fn test_batch_match_bad_price_fails() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
     owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 100);
 
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100));
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100));
+    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
@@ -423,7 +796,70 @@ fn test_batch_match_bad_price_panics() {
         .build()
     );
     // Give Alice only 90 B — worse than her 1:1 price
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 90), mp(id2, 100, 100)]);
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 90), mp(id2, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::PriceMismatch { intent_id: 0, get_amount: 90 });
+}
+
+#[test]
+fn test_batch_match_duplicate_intent_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    // Each entry alone fits the 100-unit intent; together they overfill it
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 60, 60), mp(id1, 60, 60)])).unwrap_err();
+    assert_eq!(error, OrderbookError::DuplicateIntent { id: 0 });
+}
+
+#[test]
+fn test_batch_match_demand_overflow_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 1);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "C", 1);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("A".to_string(), u(1), "B".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("C".to_string(), u(1), "B".to_string(), u(1), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    // Each leg is above i128::MAX; their sum overflows u128
+    let huge = i128::MAX as u128 + 1;
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 1, huge), mp(id2, 1, huge)])).unwrap_err();
+    assert_eq!(error, OrderbookError::AmountOverflow { asset: "B".to_string() });
+}
+
+#[test]
+fn test_batch_match_amount_above_i128_max_is_not_solvent() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 1);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("B".to_string(), u(1), "A".to_string(), u(1), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    // Cast to i128 this demand would wrap negative and look like extra supply
+    let huge = i128::MAX as u128 + 1;
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 1), mp(id2, 1, huge)])).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientSupply { asset: "A".to_string(), deficit: huge - 100 });
 }
 
 // ============================================================================
@@ -433,25 +869,27 @@ fn test_batch_match_bad_price_panics() {
 #[test]
 fn test_full_lifecycle_2party() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
     let alice = user_alice();
     let bob = solver_bob();
 
     // 1. Deposit
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
     contract.on_mpc_deposit_verified(
-        alice.clone(), "SOL".to_string(), U128(1000),
-        "alice-mpc".to_string(), format!("mpc:deposit:{}:SOL", alice), Ok(true),
+        alice.clone(), ChainType::ETH, "WSOL".to_string(), U128(1000),
+        "alice-mpc".to_string(), format!("mpc:deposit:{}:WSOL", alice), "dep-tx-3".to_string(), verified(true),
     );
     contract.on_mpc_deposit_verified(
-        bob.clone(), "ETH".to_string(), U128(500),
-        "bob-mpc".to_string(), format!("mpc:deposit:{}:ETH", bob), Ok(true),
+        bob.clone(), ChainType::ETH, "ETH".to_string(), U128(500),
+        "bob-mpc".to_string(), format!("mpc:deposit:{}:ETH", bob), "dep-tx-4".to_string(), verified(true),
     );
 
     // 2. Make intents
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(1000), "ETH".to_string(), u(500));
+    let id_a = contract.make_intent("WSOL".to_string(), u(1000), "ETH".to_string(), u(500), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(500), "SOL".to_string(), u(1000));
+    let id_b = contract.make_intent("ETH".to_string(), u(500), "WSOL".to_string(), u(1000), None, None, None, None, None, None).unwrap();
 
     // 3. Batch match (auto-triggers MPC)
     testing_env!(context
@@ -459,47 +897,55 @@ fn test_full_lifecycle_2party() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![
-        mp_with_chain(id_a, 1000, 500, ChainType::SOL),
+    contract.batch_match_intents(bind_assets(&contract, vec![
+        mp_with_chain(id_a, 1000, 500, ChainType::ETH),
         mp_with_chain(id_b, 500, 1000, ChainType::ETH),
-    ]);
+    ])).unwrap();
 
-    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(500));
-    assert_eq!(contract.get_balance(bob.clone(), "SOL".to_string()), u(1000));
+    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(0));
+    assert_eq!(contract.get_balance(bob.clone(), "WSOL".to_string()), u(0));
 
-    let sub_a = u(2);
-    let sub_b = u(3);
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Verifying);
+    let sub_a = U64(0);
+    let sub_b = U64(1);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Verifying);
 
     // 4. MPC sign callbacks
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let r = contract.on_signed(2, ChainType::SOL, [1u8; 32], Ok(mock_sig()));
+    let r = contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
     assert_eq!(r, "Success");
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(3, ChainType::ETH, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
 
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Settled);
-    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, IntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, SubIntentStatus::Settled);
 
     // 5. Transition verify
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_a, vec![1], "addr-a".to_string(), "tx-a".to_string());
+    let _ = contract.verify_transition_completion(sub_a, vec![1], "tx-a".to_string());
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_b, vec![1], "addr-b".to_string(), "tx-b".to_string());
+    let _ = contract.verify_transition_completion(sub_b, vec![1], "tx-b".to_string());
 
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_a, "tx-a".to_string(), Ok(true));
+    contract.on_transition_verified(sub_a, "tx-a".to_string(), transition_verified(true));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_b, "tx-b".to_string(), Ok(true));
+    contract.on_transition_verified(sub_b, "tx-b".to_string(), transition_verified(true));
 
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Completed);
-    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, IntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, SubIntentStatus::Completed);
     assert!(contract.get_transition_expectation(sub_a).is_none());
+
+    // Maker proceeds only appear once the transitions are verified
+    assert_eq!(contract.get_balance(alice, "ETH".to_string()), u(500));
+    assert_eq!(contract.get_balance(bob, "WSOL".to_string()), u(1000));
+    assert!(contract.get_escrow(sub_a).is_none());
 }
 
 #[test]
 fn test_full_lifecycle_3party_sol_eth() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    register_storage(&mut contract, &mut context, &user_charlie());
     let alice = user_alice();
     let bob = solver_bob();
     let solver = user_charlie();
@@ -513,17 +959,17 @@ fn test_full_lifecycle_3party_sol_eth() {
 
     // Deposits
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
-    contract.on_mpc_deposit_verified(alice.clone(), "SOL".to_string(), U128(alice_sol), "a".to_string(), format!("mpc:deposit:{}:SOL", alice), Ok(true));
-    contract.on_mpc_deposit_verified(bob.clone(), "ETH".to_string(), U128(bob_eth), "b".to_string(), format!("mpc:deposit:{}:ETH", bob), Ok(true));
-    contract.on_mpc_deposit_verified(solver.clone(), "SOL".to_string(), U128(solver_sol), "s".to_string(), format!("mpc:deposit:{}:SOL", solver), Ok(true));
+    contract.on_mpc_deposit_verified(alice.clone(), ChainType::ETH, "WSOL".to_string(), U128(alice_sol), "a".to_string(), format!("mpc:deposit:{}:WSOL", alice), "dep-tx-5".to_string(), verified(true));
+    contract.on_mpc_deposit_verified(bob.clone(), ChainType::ETH, "ETH".to_string(), U128(bob_eth), "b".to_string(), format!("mpc:deposit:{}:ETH", bob), "dep-tx-6".to_string(), verified(true));
+    contract.on_mpc_deposit_verified(solver.clone(), ChainType::ETH, "WSOL".to_string(), U128(solver_sol), "s".to_string(), format!("mpc:deposit:{}:WSOL", solver), "dep-tx-7".to_string(), verified(true));
 
     // Intents
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(alice_sol), "ETH".to_string(), u(alice_want_eth));
+    let id_a = contract.make_intent("WSOL".to_string(), u(alice_sol), "ETH".to_string(), u(alice_want_eth), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(bob_eth), "SOL".to_string(), u(bob_want_sol));
+    let id_b = contract.make_intent("ETH".to_string(), u(bob_eth), "WSOL".to_string(), u(bob_want_sol), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(solver.clone()).build());
-    let id_s = contract.make_intent("SOL".to_string(), u(solver_sol), "ETH".to_string(), u(solver_want_eth));
+    let id_s = contract.make_intent("WSOL".to_string(), u(solver_sol), "ETH".to_string(), u(solver_want_eth), None, None, None, None, None, None).unwrap();
 
     // Batch match
     testing_env!(context
@@ -531,55 +977,58 @@ fn test_full_lifecycle_3party_sol_eth() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![
-        mp_with_chain(id_a, alice_sol, alice_want_eth, ChainType::SOL),
+    contract.batch_match_intents(bind_assets(&contract, vec![
+        mp_with_chain(id_a, alice_sol, alice_want_eth, ChainType::ETH),
         mp_with_chain(id_b, bob_eth, bob_want_sol, ChainType::ETH),
-        mp_with_chain(id_s, solver_sol, solver_want_eth, ChainType::SOL),
-    ]);
+        mp_with_chain(id_s, solver_sol, solver_want_eth, ChainType::ETH),
+    ])).unwrap();
 
     // Conservation check
     assert_eq!(alice_sol + solver_sol, bob_want_sol);
     assert_eq!(bob_eth, alice_want_eth + solver_want_eth);
 
-    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(alice_want_eth));
-    assert_eq!(contract.get_balance(bob.clone(), "SOL".to_string()), u(bob_want_sol));
-    assert_eq!(contract.get_balance(solver.clone(), "ETH".to_string()), u(solver_want_eth));
+    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(0));
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().amount, alice_want_eth);
 
-    // Sub-intents: 0,1,2 = intents, 3,4,5 = sub-intents
-    let sub_a = u(3);
-    let sub_b = u(4);
-    let sub_s = u(5);
+    // Sub-intents have their own counter: 0,1,2
+    let sub_a = U64(0);
+    let sub_b = U64(1);
+    let sub_s = U64(2);
 
     // MPC sign callbacks
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(3, ChainType::SOL, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(4, ChainType::ETH, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(5, ChainType::SOL, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(2), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
 
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Settled);
-    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, IntentStatus::Settled);
-    assert_eq!(contract.get_sub_intent(sub_s).unwrap().status, IntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_s).unwrap().status, SubIntentStatus::Settled);
 
     // Transition verify
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_a, vec![1], "a".to_string(), "tx-a".to_string());
+    let _ = contract.verify_transition_completion(sub_a, vec![1], "tx-a".to_string());
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_b, vec![1], "b".to_string(), "tx-b".to_string());
+    let _ = contract.verify_transition_completion(sub_b, vec![1], "tx-b".to_string());
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_s, vec![1], "s".to_string(), "tx-s".to_string());
+    let _ = contract.verify_transition_completion(sub_s, vec![1], "tx-s".to_string());
 
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_a, "tx-a".to_string(), Ok(true));
+    contract.on_transition_verified(sub_a, "tx-a".to_string(), transition_verified(true));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_b, "tx-b".to_string(), Ok(true));
+    contract.on_transition_verified(sub_b, "tx-b".to_string(), transition_verified(true));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_s, "tx-s".to_string(), Ok(true));
+    contract.on_transition_verified(sub_s, "tx-s".to_string(), transition_verified(true));
+
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, SubIntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_s).unwrap().status, SubIntentStatus::Completed);
 
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Completed);
-    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, IntentStatus::Completed);
-    assert_eq!(contract.get_sub_intent(sub_s).unwrap().status, IntentStatus::Completed);
+    assert_eq!(contract.get_balance(alice, "ETH".to_string()), u(alice_want_eth));
+    assert_eq!(contract.get_balance(bob, "WSOL".to_string()), u(bob_want_sol));
+    assert_eq!(contract.get_balance(solver, "ETH".to_string()), u(solver_want_eth));
 }
 
 // ============================================================================
@@ -592,32 +1041,32 @@ fn test_mpc_sign_failure_rollback_to_taken() {
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &alice, "WSOL", 100);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100));
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
 
-    let sub_a = u(2);
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Verifying);
+    let sub_a = U64(0);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Verifying);
 
     // MPC sign FAILS
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let res = contract.on_signed(2, ChainType::ETH, [1u8; 32], Err(near_sdk::PromiseError::Failed));
+    let res = contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
     assert_eq!(res, "Failed");
 
-    // Rolled back to Taken (can retry)
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Taken);
-    assert!(contract.get_transition_expectation(sub_a).is_none());
+    // Rolled back to Taken (can retry), still expecting the same transition
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Taken);
+    assert!(contract.get_transition_expectation(sub_a).is_some());
 }
 
 #[test]
@@ -626,13 +1075,13 @@ fn test_retry_settlement_after_failure() {
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &alice, "WSOL", 100);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100));
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     // batch_match is called by owner (or solver in production)
     testing_env!(context
@@ -640,14 +1089,14 @@ fn test_retry_settlement_after_failure() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
 
-    let sub_a = u(2);
+    let sub_a = U64(0);
 
     // MPC sign fails
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(2, ChainType::ETH, [1u8; 32], Err(near_sdk::PromiseError::Failed));
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Taken);
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Taken);
 
     // Retry — taker is orderbook_contract() (set as solver during batch_match)
     testing_env!(context
@@ -656,40 +1105,39 @@ fn test_retry_settlement_after_failure() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.retry_settlement(sub_a, [2u8; 32], "sol/1".to_string(), ChainType::SOL);
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Verifying);
+    let _ = contract.retry_settlement(sub_a).unwrap();
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Verifying);
 
     // MPC sign succeeds this time
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(2, ChainType::SOL, [2u8; 32], Ok(mock_sig()));
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Settled);
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![2u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Settled);
 }
 
 #[test]
-#[should_panic(expected = "Only the solver who matched can retry")]
 fn test_retry_settlement_wrong_caller() {
     let (mut contract, mut context) = new_contract();
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &alice, "WSOL", 100);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100));
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
 
     // MPC fails
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(2, ChainType::ETH, [1u8; 32], Err(near_sdk::PromiseError::Failed));
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
 
     // Alice (not the solver) tries to retry — should fail
     testing_env!(context
@@ -697,7 +1145,8 @@ fn test_retry_settlement_wrong_caller() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.retry_settlement(u(2), [2u8; 32], "sol/1".to_string(), ChainType::SOL);
+    let error = contract.retry_settlement(U64(0)).err();
+    assert_eq!(error, Some(OrderbookError::NotAuthorized { account: user_alice(), action: "retry settlement of Sub-Intent 0".to_string() }));
 }
 
 // ============================================================================
@@ -710,37 +1159,37 @@ fn test_transition_verify_failure_rollback() {
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &alice, "WSOL", 100);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 100);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100));
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
 
-    let sub_a = u(2);
+    let sub_a = U64(0);
 
     // MPC sign succeeds
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(2, ChainType::ETH, [1u8; 32], Ok(mock_sig()));
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Settled);
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Settled);
 
     // Transition verify
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_a, vec![1], "addr".to_string(), "tx".to_string());
+    let _ = contract.verify_transition_completion(sub_a, vec![1], "tx".to_string());
 
     // Transition verify FAILS
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let res = contract.on_transition_verified(sub_a, "tx".to_string(), Ok(false));
+    let res = contract.on_transition_verified(sub_a, "tx".to_string(), transition_verified(false));
     assert_eq!(res, "TransitionVerifyFailed");
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Settled); // Can retry
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Settled); // Can retry
 }
 
 // ============================================================================
@@ -757,12 +1206,11 @@ fn test_withdraw_deducts_balance() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.withdraw("ETH".to_string(), u(1000), [9u8; 32], "eth/alice".to_string(), ChainType::ETH);
+    contract.withdraw("ETH".to_string(), u(1000), ETH_DEST.to_string(), eth_tx(1000), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
     assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(9000));
 }
 
 #[test]
-#[should_panic(expected = "Insufficient funds to withdraw")]
 fn test_withdraw_insufficient_balance() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
@@ -771,7 +1219,8 @@ fn test_withdraw_insufficient_balance() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.withdraw("ETH".to_string(), u(200), [0u8; 32], "eth/a".to_string(), ChainType::ETH);
+    let error = contract.withdraw("ETH".to_string(), u(200), ETH_DEST.to_string(), eth_tx(200), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientBalance { asset: "ETH".to_string(), have: 100, need: 200 });
 }
 
 #[test]
@@ -784,18 +1233,22 @@ fn test_withdraw_mpc_success_cleans_up() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.withdraw("ETH".to_string(), u(50), [9u8; 32], "eth/a".to_string(), ChainType::ETH);
+    contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
 
-    // wd_id = next_id - 1. After 0 intents, wd_id = 0
+    // First withdrawal gets wd_id 0
     let wd_id = 0u64;
     assert!(contract.pending_withdrawals.get(&wd_id).is_some());
+    assert_eq!(contract.get_withdrawal(U64(wd_id)).unwrap().status, WithdrawalStatus::PendingSign);
 
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let res = contract.on_signed(wd_id, ChainType::ETH, [9u8; 32], Ok(mock_sig()));
+    let res = contract.on_signed(SignTarget::Withdrawal(wd_id), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
     assert_eq!(res, "Success");
 
-    // Pending withdrawal cleaned up
+    // Pending withdrawal cleaned up, its record kept
     assert!(contract.pending_withdrawals.get(&wd_id).is_none());
+    let record = contract.get_withdrawal(U64(wd_id)).unwrap();
+    assert_eq!(record.status, WithdrawalStatus::Signed);
+    assert!(record.closed_at.is_some());
     // Balance stays deducted
     assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(50));
 }
@@ -810,21 +1263,23 @@ fn test_withdraw_mpc_failure_refunds() {
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.withdraw("ETH".to_string(), u(50), [9u8; 32], "eth/a".to_string(), ChainType::ETH);
+    contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
 
     // Balance deducted to 50
     assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(50));
 
     // MPC sign FAILS
     let wd_id = 0u64;
+    assert_eq!(contract.get_withdrawal(U64(wd_id)).unwrap().status, WithdrawalStatus::PendingSign);
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let res = contract.on_signed(wd_id, ChainType::ETH, [9u8; 32], Err(near_sdk::PromiseError::Failed));
+    let res = contract.on_signed(SignTarget::Withdrawal(wd_id), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
     assert_eq!(res, "Failed");
 
     // Balance REFUNDED to 100
     assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
-    // Pending withdrawal cleaned up
+    // Pending withdrawal cleaned up, its record kept
     assert!(contract.pending_withdrawals.get(&wd_id).is_none());
+    assert_eq!(contract.get_withdrawal(U64(wd_id)).unwrap().status, WithdrawalStatus::Refunded);
 }
 
 // ============================================================================
@@ -837,23 +1292,104 @@ fn test_get_open_intents_pagination() {
     owner_deposit(&mut contract, &mut context, &user_alice(), "A", 1000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
     for _ in 0..5 {
-        contract.make_intent("A".to_string(), u(10), "B".to_string(), u(10));
+        contract.make_intent("A".to_string(), u(10), "B".to_string(), u(10), None, None, None, None, None, None).unwrap();
     }
     assert_eq!(contract.get_open_intents(u(0), 3).len(), 3);
     assert_eq!(contract.get_open_intents(u(3), 3).len(), 2);
     assert_eq!(contract.get_open_intents(u(0), 100).len(), 5);
 }
 
+#[test]
+fn test_get_open_intents_skips_filled_history() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_max_open_intents_per_account(1000);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 10_000);
+    for account in [user_alice(), solver_bob()] {
+        testing_env!(context.predecessor_account_id(account).attached_deposit(NearToken::from_near(10)).build());
+        contract.storage_deposit(None);
+    }
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 10_000);
+    testing_env!(context.attached_deposit(NearToken::from_near(0)).build());
+    // Fresh env per call so the mock's per-receipt log and gas limits aren't hit
+    let mut ids: Vec<U64> = Vec::new();
+    for i in 0..1000 {
+        if i % 25 == 0 {
+            testing_env!(context.predecessor_account_id(user_alice()).build());
+        }
+        ids.push(contract.make_intent("A".to_string(), u(10), "B".to_string(), u(10), None, None, None, None, None, None).unwrap());
+    }
+    for (i, id) in ids[..990].iter().enumerate() {
+        if i % 25 == 0 {
+            testing_env!(context.predecessor_account_id(solver_bob()).build());
+        }
+        contract.take_intent(*id, u(10)).unwrap();
+    }
+
+    let page = contract.get_open_intents(u(0), 10);
+    assert_eq!(page.len(), 10);
+    assert!(page.iter().all(|i| i.id >= 990 && i.status == IntentStatus::Open));
+    assert!(contract.get_open_intents(u(10), 10).is_empty());
+}
+
+#[test]
+fn test_open_intent_index_follows_status() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.block_timestamp(1_000).build());
+    let (id_a, id_b) = setup_matched_pair(&mut contract, &mut context);
+    assert!(contract.get_open_intents(u(0), 10).is_empty());
+
+    // Escrow timeout puts the parent back on the book
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_ESCROW_TIMEOUT_NS).build());
+    contract.claim_escrow_timeout(U64(0)).unwrap();
+    let open: Vec<u64> = contract.get_open_intents(u(0), 10).iter().map(|i| i.id).collect();
+    assert_eq!(open, vec![id_a.0]);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id_a);
+    assert!(contract.get_open_intents(u(0), 10).is_empty());
+    assert_eq!(contract.get_intent(id_b).unwrap().status, IntentStatus::Filled);
+}
+
 #[test]
 fn test_get_balance_nonexistent() {
     let (contract, _) = new_contract();
     assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
 }
 
+#[test]
+fn test_get_balances_all_assets() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 20);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 3);
+
+    assert_eq!(
+        contract.get_balances(user_alice(), 0, 10),
+        vec![
+            ("SOL".to_string(), u(100)),
+            ("ETH".to_string(), u(20)),
+            ("BTC".to_string(), u(3)),
+        ]
+    );
+    assert_eq!(contract.get_balances(user_alice(), 1, 1), vec![("ETH".to_string(), u(20))]);
+    assert_eq!(contract.get_assets(user_alice()), vec!["SOL", "ETH", "BTC"]);
+}
+
+#[test]
+fn test_get_balances_nonexistent() {
+    let (contract, _) = new_contract();
+    assert!(contract.get_balances(user_alice(), 0, 10).is_empty());
+    assert!(contract.get_assets(user_alice()).is_empty());
+}
+
 #[test]
 fn test_get_intent_nonexistent() {
     let (contract, _) = new_contract();
-    assert!(contract.get_intent(u(999)).is_none());
+    assert!(contract.get_intent(U64(999)).is_none());
 }
 
 // ============================================================================
@@ -866,39 +1402,41 @@ fn test_multi_round_trading() {
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 200);
+    owner_deposit(&mut contract, &mut context, &alice, "WSOL", 200);
     owner_deposit(&mut contract, &mut context, &bob, "ETH", 200);
 
     // Round 1
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id1 = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id1 = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id2 = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100));
+    let id2 = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id1, 100, 100), mp(id2, 100, 100)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 100)])).unwrap();
+    release_escrows(&mut contract, &mut context, &[0, 1]);
 
     assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(100));
-    assert_eq!(contract.get_balance(bob.clone(), "SOL".to_string()), u(100));
+    assert_eq!(contract.get_balance(bob.clone(), "WSOL".to_string()), u(100));
 
     // Round 2: trade what they got
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id3 = contract.make_intent("ETH".to_string(), u(50), "SOL".to_string(), u(50));
+    let id3 = contract.make_intent("ETH".to_string(), u(50), "WSOL".to_string(), u(50), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id4 = contract.make_intent("SOL".to_string(), u(50), "ETH".to_string(), u(50));
+    let id4 = contract.make_intent("WSOL".to_string(), u(50), "ETH".to_string(), u(50), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![mp(id3, 50, 50), mp(id4, 50, 50)]);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id3, 50, 50), mp(id4, 50, 50)])).unwrap();
+    release_escrows(&mut contract, &mut context, &[2, 3]);
 
-    assert_eq!(contract.get_balance(alice.clone(), "SOL".to_string()), u(150));
+    assert_eq!(contract.get_balance(alice.clone(), "WSOL".to_string()), u(150));
     assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(50));
 }
 
@@ -915,31 +1453,32 @@ fn test_4party_complex_ring() {
     let dave = user_dave();
 
     owner_deposit(&mut contract, &mut context, &alice, "USDC", 100);
-    owner_deposit(&mut contract, &mut context, &bob, "BTC", 1);
+    owner_deposit(&mut contract, &mut context, &bob, "WBTC", 1);
     owner_deposit(&mut contract, &mut context, &charlie, "ETH", 10);
-    owner_deposit(&mut contract, &mut context, &dave, "SOL", 1000);
+    owner_deposit(&mut contract, &mut context, &dave, "WSOL", 1000);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id1 = contract.make_intent("USDC".to_string(), u(100), "BTC".to_string(), u(1));
+    let id1 = contract.make_intent("USDC".to_string(), u(100), "WBTC".to_string(), u(1), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id2 = contract.make_intent("BTC".to_string(), u(1), "ETH".to_string(), u(10));
+    let id2 = contract.make_intent("WBTC".to_string(), u(1), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(charlie.clone()).build());
-    let id3 = contract.make_intent("ETH".to_string(), u(10), "SOL".to_string(), u(1000));
+    let id3 = contract.make_intent("ETH".to_string(), u(10), "WSOL".to_string(), u(1000), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(dave.clone()).build());
-    let id4 = contract.make_intent("SOL".to_string(), u(1000), "USDC".to_string(), u(100));
+    let id4 = contract.make_intent("WSOL".to_string(), u(1000), "USDC".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![
+    contract.batch_match_intents(bind_assets(&contract, vec![
         mp(id1, 100, 1), mp(id2, 1, 10), mp(id3, 10, 1000), mp(id4, 1000, 100),
-    ]);
+    ])).unwrap();
+    release_escrows(&mut contract, &mut context, &[0, 1, 2, 3]);
 
-    assert_eq!(contract.get_balance(alice, "BTC".to_string()), u(1));
+    assert_eq!(contract.get_balance(alice, "WBTC".to_string()), u(1));
     assert_eq!(contract.get_balance(bob, "ETH".to_string()), u(10));
-    assert_eq!(contract.get_balance(charlie, "SOL".to_string()), u(1000));
+    assert_eq!(contract.get_balance(charlie, "WSOL".to_string()), u(1000));
     assert_eq!(contract.get_balance(dave, "USDC".to_string()), u(100));
 }
 
@@ -950,45 +1489,47 @@ fn test_4party_complex_ring() {
 #[test]
 fn test_end_to_end_with_withdraw() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
     let alice = user_alice();
     let bob = solver_bob();
 
     // Deposit
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
-    contract.on_mpc_deposit_verified(alice.clone(), "SOL".to_string(), U128(1000), "a".to_string(), format!("mpc:deposit:{}:SOL", alice), Ok(true));
-    contract.on_mpc_deposit_verified(bob.clone(), "ETH".to_string(), U128(500), "b".to_string(), format!("mpc:deposit:{}:ETH", bob), Ok(true));
+    contract.on_mpc_deposit_verified(alice.clone(), ChainType::ETH, "WSOL".to_string(), U128(1000), "a".to_string(), format!("mpc:deposit:{}:WSOL", alice), "dep-tx-8".to_string(), verified(true));
+    contract.on_mpc_deposit_verified(bob.clone(), ChainType::ETH, "ETH".to_string(), U128(500), "b".to_string(), format!("mpc:deposit:{}:ETH", bob), "dep-tx-9".to_string(), verified(true));
 
     // Make & match
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(1000), "ETH".to_string(), u(500));
+    let id_a = contract.make_intent("WSOL".to_string(), u(1000), "ETH".to_string(), u(500), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let id_b = contract.make_intent("ETH".to_string(), u(500), "SOL".to_string(), u(1000));
+    let id_b = contract.make_intent("ETH".to_string(), u(500), "WSOL".to_string(), u(1000), None, None, None, None, None, None).unwrap();
 
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![
-        mp_with_chain(id_a, 1000, 500, ChainType::SOL),
+    contract.batch_match_intents(bind_assets(&contract, vec![
+        mp_with_chain(id_a, 1000, 500, ChainType::ETH),
         mp_with_chain(id_b, 500, 1000, ChainType::ETH),
-    ]);
+    ])).unwrap();
 
     // MPC sign
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(2, ChainType::SOL, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(3, ChainType::ETH, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
 
     // Transition verify
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(u(2), vec![1], "a".to_string(), "tx-a".to_string());
+    let _ = contract.verify_transition_completion(U64(0), vec![1], "tx-a".to_string());
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(u(3), vec![1], "b".to_string(), "tx-b".to_string());
+    let _ = contract.verify_transition_completion(U64(1), vec![1], "tx-b".to_string());
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(u(2), "tx-a".to_string(), Ok(true));
+    contract.on_transition_verified(U64(0), "tx-a".to_string(), transition_verified(true));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(u(3), "tx-b".to_string(), Ok(true));
+    contract.on_transition_verified(U64(1), "tx-b".to_string(), transition_verified(true));
 
     // Alice withdraws ETH
     assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(500));
@@ -998,13 +1539,13 @@ fn test_end_to_end_with_withdraw() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.withdraw("ETH".to_string(), u(500), [5u8; 32], "eth/a".to_string(), ChainType::ETH);
+    contract.withdraw("ETH".to_string(), u(500), ETH_DEST.to_string(), eth_tx(500), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
     assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(0));
 
     // MPC sign for withdraw succeeds
-    // wd_id = 4 (next_id after 0,1,2,3 used by intents+sub_intents)
+    // wd_id = 0 (withdrawals have their own counter)
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(4, ChainType::ETH, [5u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![5u8; 32], "default/path".to_string(), Ok(mock_sig()));
     assert_eq!(contract.get_balance(alice, "ETH".to_string()), u(0));
 }
 
@@ -1017,9 +1558,9 @@ fn test_id_monotonic_increment() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "A", 10000);
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let mut last_id = 0u128;
+    let mut last_id = 0u64;
     for i in 0..10 {
-        let id = contract.make_intent("A".to_string(), u(1), "B".to_string(), u(1));
+        let id = contract.make_intent("A".to_string(), u(1), "B".to_string(), u(1), None, None, None, None, None, None).unwrap();
         if i > 0 { assert!(id.0 > last_id); }
         last_id = id.0;
     }
@@ -1035,55 +1576,52 @@ fn test_submit_payment_proof_memo_check() {
     let alice = user_alice();
     let bob = solver_bob();
 
-    owner_deposit(&mut contract, &mut context, &alice, "SOL", 1000);
-    owner_deposit(&mut contract, &mut context, &bob, "ETH", 500);
+    owner_deposit(&mut contract, &mut context, &alice, "ETH", 1000);
+    owner_deposit(&mut contract, &mut context, &bob, "SOL", 1000);
 
     testing_env!(context.predecessor_account_id(alice.clone()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(1000), "ETH".to_string(), u(500));
+    let id_a = contract.make_intent("ETH".to_string(), u(1000), "SOL".to_string(), u(500), None, None, None, None, None, None).unwrap();
     testing_env!(context.predecessor_account_id(bob.clone()).build());
-    let _id_b = contract.make_intent("ETH".to_string(), u(500), "SOL".to_string(), u(1000));
+    let _id_b = contract.make_intent("SOL".to_string(), u(500), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
 
     // Use take_intent to create a sub-intent in Taken state (for submit_payment_proof)
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    let sub_a = contract.take_intent(id_a, u(1000));
+    let sub_a = contract.take_intent(id_a, u(1000)).unwrap();
 
     testing_env!(context
         .predecessor_account_id(solver_bob())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.submit_payment_proof(
-        sub_a, vec![1, 2, 3], [0u8; 32],
-        "sol/transfer".to_string(), ChainType::ETH, ChainType::SOL,
-        "recipient-addr".to_string(),
-        format!("sub:{}", sub_a.0),
-    );
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Verifying);
+    let _ = submit_escrowed_take(&mut contract, sub_a).unwrap();
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Verifying);
 }
 
 #[test]
-#[should_panic(expected = "memo mismatch")]
 fn test_submit_payment_proof_wrong_memo() {
     let (mut contract, mut context) = new_contract();
     owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
     owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
 
     testing_env!(context.predecessor_account_id(user_alice()).build());
-    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100));
+    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
 
     testing_env!(context.predecessor_account_id(solver_bob()).build());
-    let sub_a = contract.take_intent(id_a, u(100));
+    let sub_a = contract.take_intent(id_a, u(100)).unwrap();
 
     testing_env!(context
         .predecessor_account_id(solver_bob())
         .attached_deposit(NearToken::from_near(1))
         .build()
     );
-    let _ = contract.submit_payment_proof(
-        sub_a, vec![1], [0u8; 32],
-        "sol/transfer".to_string(), ChainType::ETH, ChainType::SOL,
+    let error = contract.submit_payment_proof(
+        sub_a, vec![1], [0u8; 32], None,
+        "default/path".to_string(), ChainType::ETH, ChainType::SOL,
         "recipient".to_string(), "wrong_memo".to_string(),
-    );
+        SOL_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).err();
+    assert_eq!(error, Some(OrderbookError::MemoMismatch { expected: "sub:0".to_string(), got: "wrong_memo".to_string() }));
 }
 
 // ============================================================================
@@ -1101,7 +1639,7 @@ fn test_verify_mpc_deposit_wrong_memo() {
     );
     let _ = contract.verify_mpc_deposit(
         user_alice(), ChainType::ETH, "ETH".to_string(),
-        U128(100), "recipient".to_string(), "bad_memo".to_string(), vec![1],
+        U128(100), "recipient".to_string(), "bad_memo".to_string(), vec![1], "tx".to_string(),
     );
 }
 
@@ -1114,6 +1652,9 @@ fn test_verify_mpc_deposit_wrong_memo() {
 #[test]
 fn test_complete_e2e_simulation() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    register_storage(&mut contract, &mut context, &user_charlie());
     let alice = user_alice();
     let bob = solver_bob();
     let charlie = user_charlie();
@@ -1129,53 +1670,57 @@ fn test_complete_e2e_simulation() {
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
     let result = contract.on_mpc_deposit_verified(
         alice.clone(),
-        "SOL".to_string(),
+        ChainType::ETH,
+        "WSOL".to_string(),
         U128(2_000_000_000),  // 2 SOL (in lamports)
         "mpc-sol-address-alice".to_string(),
-        format!("mpc:deposit:{}:SOL", alice),
-        Ok(true),
+        format!("mpc:deposit:{}:WSOL", alice),
+        "dep-tx-10".to_string(),
+        verified(true),
     );
-    assert_eq!(result, "MpcDepositCredited");
+    assert_eq!(result, DepositOutcome { credited: true, reason: None });
     assert_eq!(
-        contract.get_balance(alice.clone(), "SOL".to_string()),
+        contract.get_balance(alice.clone(), "WSOL".to_string()),
         u(2_000_000_000)
     );
 
     // Bob deposits 100 ETH (via MPC deposit verification)
     let result = contract.on_mpc_deposit_verified(
         bob.clone(),
+        ChainType::ETH,
         "ETH".to_string(),
         U128(100_000_000_000_000_000), // 0.1 ETH (in wei)
         "mpc-eth-address-bob".to_string(),
         format!("mpc:deposit:{}:ETH", bob),
-        Ok(true),
+        "dep-tx-11".to_string(),
+        verified(true),
     );
-    assert_eq!(result, "MpcDepositCredited");
+    assert_eq!(result, DepositOutcome { credited: true, reason: None });
     assert_eq!(
         contract.get_balance(bob.clone(), "ETH".to_string()),
         u(100_000_000_000_000_000)
     );
 
     // Charlie deposits 3000 SOL (via admin direct deposit, for testing)
-    owner_deposit(&mut contract, &mut context, &charlie, "SOL", 3_000_000_000);
+    owner_deposit(&mut contract, &mut context, &charlie, "WSOL", 3_000_000_000);
     assert_eq!(
-        contract.get_balance(charlie.clone(), "SOL".to_string()),
+        contract.get_balance(charlie.clone(), "WSOL".to_string()),
         u(3_000_000_000)
     );
 
     // Verify: invalid MPC deposit proof should be rejected
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
-    let rejected = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        contract.on_mpc_deposit_verified(
-            alice.clone(),
-            "SOL".to_string(),
-            U128(999),
-            "addr".to_string(),
-            format!("mpc:deposit:{}:SOL", alice),
-            Ok(false), // verification failed
-        );
-    }));
-    assert!(rejected.is_err(), "Invalid proof should be rejected");
+    let rejected = contract.on_mpc_deposit_verified(
+        alice.clone(),
+        ChainType::ETH,
+        "WSOL".to_string(),
+        U128(999),
+        "addr".to_string(),
+        format!("mpc:deposit:{}:WSOL", alice),
+        "dep-tx-12".to_string(),
+        verified(false), // verification failed
+    );
+    assert!(!rejected.credited, "Invalid proof should be rejected");
 
     // ================================================================
     // Phase 2: Create exchange intent (Make Intent)
@@ -1187,14 +1732,20 @@ fn test_complete_e2e_simulation() {
     // Alice: sell 1 SOL, buy 0.05 ETH
     testing_env!(context.predecessor_account_id(alice.clone()).build());
     let intent_alice = contract.make_intent(
-        "SOL".to_string(),
+        "WSOL".to_string(),
         u(1_000_000_000),                // 1 SOL
         "ETH".to_string(),
         u(50_000_000_000_000_000),       // 0.05 ETH
-    );
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ).unwrap();
     // Alice's SOL balance should decrease by 1 SOL
     assert_eq!(
-        contract.get_balance(alice.clone(), "SOL".to_string()),
+        contract.get_balance(alice.clone(), "WSOL".to_string()),
         u(1_000_000_000) // remaining 1 SOL
     );
     let intent_a = contract.get_intent(intent_alice).unwrap();
@@ -1207,9 +1758,15 @@ fn test_complete_e2e_simulation() {
     let intent_bob = contract.make_intent(
         "ETH".to_string(),
         u(50_000_000_000_000_000),       // 0.05 ETH
-        "SOL".to_string(),
+        "WSOL".to_string(),
         u(1_000_000_000),                // 1 SOL
-    );
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ).unwrap();
     assert_eq!(
         contract.get_balance(bob.clone(), "ETH".to_string()),
         u(50_000_000_000_000_000) // remaining 0.05 ETH
@@ -1218,13 +1775,19 @@ fn test_complete_e2e_simulation() {
     // Charlie: sell 2 SOL, buy 0.1 ETH (this order has no match yet)
     testing_env!(context.predecessor_account_id(charlie.clone()).build());
     let intent_charlie = contract.make_intent(
-        "SOL".to_string(),
+        "WSOL".to_string(),
         u(2_000_000_000),                // 2 SOL
         "ETH".to_string(),
         u(100_000_000_000_000_000),      // 0.1 ETH — but Bob only has 0.05 ETH left
-    );
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ).unwrap();
     assert_eq!(
-        contract.get_balance(charlie.clone(), "SOL".to_string()),
+        contract.get_balance(charlie.clone(), "WSOL".to_string()),
         u(1_000_000_000) // remaining 1 SOL
     );
 
@@ -1248,20 +1811,18 @@ fn test_complete_e2e_simulation() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![
-        mp_with_chain(intent_alice, 1_000_000_000, 50_000_000_000_000_000, ChainType::SOL),
+    contract.batch_match_intents(bind_assets(&contract, vec![
+        mp_with_chain(intent_alice, 1_000_000_000, 50_000_000_000_000_000, ChainType::ETH),
         mp_with_chain(intent_bob, 50_000_000_000_000_000, 1_000_000_000, ChainType::ETH),
-    ]);
+    ])).unwrap();
 
-    // Verify: Alice gets 0.05 ETH, Bob gets 1 SOL (logical balance)
+    // Verify: Alice's 0.05 ETH and Bob's 1 SOL are escrowed, not yet spendable
     assert_eq!(
         contract.get_balance(alice.clone(), "ETH".to_string()),
-        u(50_000_000_000_000_000)
-    );
-    assert_eq!(
-        contract.get_balance(bob.clone(), "SOL".to_string()),
-        u(1_000_000_000)
+        u(0)
     );
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().amount, 50_000_000_000_000_000);
+    assert_eq!(contract.get_escrow(U64(1)).unwrap().amount, 1_000_000_000);
 
     // Verify: Intent status becomes Filled
     assert_eq!(
@@ -1274,21 +1835,21 @@ fn test_complete_e2e_simulation() {
     );
 
     // Verify: SubIntent created and in Verifying status (MPC sign triggered)
-    // intent_alice=0, intent_bob=1, intent_charlie=2 → sub_alice=3, sub_bob=4
-    let sub_alice = u(3);
-    let sub_bob = u(4);
+    // intent_alice=0, intent_bob=1, intent_charlie=2 → sub_alice=0, sub_bob=1
+    let sub_alice = U64(0);
+    let sub_bob = U64(1);
     assert_eq!(
         contract.get_sub_intent(sub_alice).unwrap().status,
-        IntentStatus::Verifying
+        SubIntentStatus::Verifying
     );
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Verifying
+        SubIntentStatus::Verifying
     );
 
     // Verify: TransitionExpectation recorded
     let exp_alice = contract.get_transition_expectation(sub_alice).unwrap();
-    assert_eq!(exp_alice.chain_type, ChainType::SOL);
+    assert_eq!(exp_alice.chain_type, ChainType::ETH);
     assert_eq!(exp_alice.expected_amount, 1_000_000_000);
 
     let exp_bob = contract.get_transition_expectation(sub_bob).unwrap();
@@ -1304,7 +1865,7 @@ fn test_complete_e2e_simulation() {
     // Open Intents should only have Charlie's
     let open_intents = contract.get_open_intents(u(0), 100);
     assert_eq!(open_intents.len(), 1);
-    assert_eq!(open_intents[0].id, intent_charlie.0 as u64);
+    assert_eq!(open_intents[0].id, intent_charlie.0);
 
     // ================================================================
     // Phase 4: MPC sign callback
@@ -1320,23 +1881,25 @@ fn test_complete_e2e_simulation() {
         .build()
     );
     let sign_result = contract.on_signed(
-        3, // sub_alice id
-        ChainType::SOL,
-        [1u8; 32],
+        SignTarget::SubIntent(0), // sub_alice id
+        ChainType::ETH,
+        vec![1u8; 32],
+        "default/path".to_string(),
         Ok(mock_sig()),
     );
     assert_eq!(sign_result, "Success");
     assert_eq!(
         contract.get_sub_intent(sub_alice).unwrap().status,
-        IntentStatus::Settled
+        SubIntentStatus::Settled
     );
 
     // Bob's sub-intent: MPC sign fails
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
     let sign_result = contract.on_signed(
-        4, // sub_bob id
+        SignTarget::SubIntent(1), // sub_bob id
         ChainType::ETH,
-        [1u8; 32],
+        vec![1u8; 32],
+        "default/path".to_string(),
         Err(near_sdk::PromiseError::Failed), // sign failed
     );
     assert_eq!(sign_result, "Failed");
@@ -1344,10 +1907,10 @@ fn test_complete_e2e_simulation() {
     // Verify: Bob's sub-intent rolled back to Taken status, can retry
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Taken
+        SubIntentStatus::Taken
     );
-    // TransitionExpectation cleared
-    assert!(contract.get_transition_expectation(sub_bob).is_none());
+    // TransitionExpectation kept for the retry
+    assert!(contract.get_transition_expectation(sub_bob).is_some());
 
     // ================================================================
     // Phase 5: Retry settlement (Retry Settlement)
@@ -1361,15 +1924,10 @@ fn test_complete_e2e_simulation() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.retry_settlement(
-        sub_bob,
-        [2u8; 32],                    // new payload
-        "eth/retry".to_string(),      // new derivation path
-        ChainType::ETH,
-    );
+    let _ = contract.retry_settlement(sub_bob).unwrap();
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Verifying
+        SubIntentStatus::Verifying
     );
     // TransitionExpectation re-recorded
     assert!(contract.get_transition_expectation(sub_bob).is_some());
@@ -1380,11 +1938,11 @@ fn test_complete_e2e_simulation() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let sign_result = contract.on_signed(4, ChainType::ETH, [2u8; 32], Ok(mock_sig()));
+    let sign_result = contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![2u8; 32], "default/path".to_string(), Ok(mock_sig()));
     assert_eq!(sign_result, "Success");
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Settled
+        SubIntentStatus::Settled
     );
 
     // ================================================================
@@ -1405,13 +1963,12 @@ fn test_complete_e2e_simulation() {
     let _ = contract.verify_transition_completion(
         sub_alice,
         vec![1, 2, 3], // proof_data
-        "alice-sol-external-addr".to_string(),
         "0xabc123_sol_tx_hash".to_string(),
     );
     // Status becomes TransitionVerifying
     assert_eq!(
         contract.get_sub_intent(sub_alice).unwrap().status,
-        IntentStatus::TransitionVerifying
+        SubIntentStatus::TransitionVerifying
     );
 
     // Light Client verification success callback
@@ -1423,15 +1980,19 @@ fn test_complete_e2e_simulation() {
     let result = contract.on_transition_verified(
         sub_alice,
         "0xabc123_sol_tx_hash".to_string(),
-        Ok(true),
+        transition_verified(true),
     );
     assert_eq!(result, "TransitionVerified");
     assert_eq!(
         contract.get_sub_intent(sub_alice).unwrap().status,
-        IntentStatus::Completed
+        SubIntentStatus::Completed
     );
-    // TransitionExpectation cleared
+    // TransitionExpectation cleared, escrow released to Alice
     assert!(contract.get_transition_expectation(sub_alice).is_none());
+    assert_eq!(
+        contract.get_balance(alice.clone(), "ETH".to_string()),
+        u(50_000_000_000_000_000)
+    );
 
     // --- Bob's transition verify: first attempt fails ---
     testing_env!(context
@@ -1442,7 +2003,6 @@ fn test_complete_e2e_simulation() {
     let _ = contract.verify_transition_completion(
         sub_bob,
         vec![4, 5, 6],
-        "bob-eth-external-addr".to_string(),
         "0xdef456_eth_tx_hash".to_string(),
     );
 
@@ -1455,13 +2015,13 @@ fn test_complete_e2e_simulation() {
     let result = contract.on_transition_verified(
         sub_bob,
         "0xdef456_eth_tx_hash".to_string(),
-        Ok(false), // verification failed
+        transition_verified(false), // verification failed
     );
     assert_eq!(result, "TransitionVerifyFailed");
     // Roll back to Settled status, can resubmit proof
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Settled
+        SubIntentStatus::Settled
     );
 
     // --- Bob's transition verify: second attempt succeeds ---
@@ -1473,7 +2033,6 @@ fn test_complete_e2e_simulation() {
     let _ = contract.verify_transition_completion(
         sub_bob,
         vec![7, 8, 9], // new proof
-        "bob-eth-external-addr".to_string(),
         "0xdef456_eth_tx_hash_v2".to_string(),
     );
 
@@ -1485,22 +2044,23 @@ fn test_complete_e2e_simulation() {
     let result = contract.on_transition_verified(
         sub_bob,
         "0xdef456_eth_tx_hash_v2".to_string(),
-        Ok(true),
+        transition_verified(true),
     );
     assert_eq!(result, "TransitionVerified");
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Completed
+        SubIntentStatus::Completed
     );
 
     // ================================================================
     // Phase 7: Withdraw
     //   After trade completes, user can withdraw logical balance to external chain.
-    //   Scenario: Alice withdraws 0.05 ETH she received; Bob withdraws 1 SOL but gets refund on MPC failure.
+    //   Scenario: Alice withdraws 0.05 ETH she received; the first MPC sign fails and
+    //   is refunded, the retry succeeds with the same nonce. Bob's SOL withdrawal is
+    //   rejected, since SOL transactions are not validated yet, and keeps his balance.
     // ================================================================
     println!("=== Phase 7: Withdraw ===");
 
-    // --- Alice withdraws 0.05 ETH: success flow ---
     assert_eq!(
         contract.get_balance(alice.clone(), "ETH".to_string()),
         u(50_000_000_000_000_000)
@@ -1512,112 +2072,80 @@ fn test_complete_e2e_simulation() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.withdraw(
+    contract.withdraw(
         "ETH".to_string(),
         u(50_000_000_000_000_000),
-        [10u8; 32],
-        "eth/alice-withdraw".to_string(),
+        ETH_DEST.to_string(),
+        eth_tx(50_000_000_000_000_000),
+        own_path(&ChainType::ETH),
         ChainType::ETH,
-    );
+        SignatureScheme::Secp256k1,
+    ).unwrap();
     // Balance immediately deducted
     assert_eq!(
         contract.get_balance(alice.clone(), "ETH".to_string()),
         u(0)
     );
 
-    // MPC sign succeeds -> withdraw complete
-    // wd_id = 5 (IDs 0,1,2=intents, 3,4=sub-intents, 5=withdrawal)
-    let alice_wd_id = 5u64;
-    assert!(contract.pending_withdrawals.get(&alice_wd_id).is_some());
-
-    testing_env!(context
-        .predecessor_account_id(orderbook_contract())
-        .prepaid_gas(Gas::from_tgas(300))
-        .build()
-    );
-    let result = contract.on_signed(alice_wd_id, ChainType::ETH, [10u8; 32], Ok(mock_sig()));
-    assert_eq!(result, "Success");
-    // PendingWithdrawal cleared, balance unchanged (already deducted)
-    assert!(contract.pending_withdrawals.get(&alice_wd_id).is_none());
-    assert_eq!(
-        contract.get_balance(alice.clone(), "ETH".to_string()),
-        u(0)
-    );
-
-    // --- Bob withdraws 1 SOL: auto-refund on MPC failure ---
-    assert_eq!(
-        contract.get_balance(bob.clone(), "SOL".to_string()),
-        u(1_000_000_000)
-    );
-
-    testing_env!(context
-        .predecessor_account_id(bob.clone())
-        .attached_deposit(NearToken::from_near(1))
-        .prepaid_gas(Gas::from_tgas(300))
-        .build()
-    );
-    let _ = contract.withdraw(
-        "SOL".to_string(),
-        u(1_000_000_000),
-        [11u8; 32],
-        "sol/bob-withdraw".to_string(),
-        ChainType::SOL,
-    );
-    // Balance immediately deducted
-    assert_eq!(
-        contract.get_balance(bob.clone(), "SOL".to_string()),
-        u(0)
-    );
-
     // MPC sign fails -> auto refund
-    let bob_wd_id = 6u64;
+    // wd_id = 0 (withdrawals have their own counter)
+    let alice_wd_id = 0u64;
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
     let result = contract.on_signed(
-        bob_wd_id,
-        ChainType::SOL,
-        [11u8; 32],
+        SignTarget::Withdrawal(alice_wd_id),
+        ChainType::ETH,
+        vec![10u8; 32],
+        "default/path".to_string(),
         Err(near_sdk::PromiseError::Failed),
     );
     assert_eq!(result, "Failed");
     // Balance refunded
     assert_eq!(
-        contract.get_balance(bob.clone(), "SOL".to_string()),
-        u(1_000_000_000)
+        contract.get_balance(alice.clone(), "ETH".to_string()),
+        u(50_000_000_000_000_000)
     );
-    assert!(contract.pending_withdrawals.get(&bob_wd_id).is_none());
+    assert!(contract.pending_withdrawals.get(&alice_wd_id).is_none());
 
-    // Bob retries withdraw, this time succeeds
+    // Alice retries withdraw, this time succeeds
     testing_env!(context
-        .predecessor_account_id(bob.clone())
+        .predecessor_account_id(alice.clone())
         .attached_deposit(NearToken::from_near(1))
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.withdraw(
-        "SOL".to_string(),
-        u(1_000_000_000),
-        [12u8; 32],
-        "sol/bob-withdraw-retry".to_string(),
-        ChainType::SOL,
-    );
+    contract.withdraw(
+        "ETH".to_string(),
+        u(50_000_000_000_000_000),
+        ETH_DEST.to_string(),
+        eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, 50_000_000_000_000_000, &[]),
+        own_path(&ChainType::ETH),
+        ChainType::ETH,
+        SignatureScheme::Secp256k1,
+    ).unwrap();
 
-    let bob_wd_id_2 = 7u64;
+    let alice_wd_id_2 = 1u64;
+    assert!(contract.pending_withdrawals.get(&alice_wd_id_2).is_some());
     testing_env!(context
         .predecessor_account_id(orderbook_contract())
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let result = contract.on_signed(bob_wd_id_2, ChainType::SOL, [12u8; 32], Ok(mock_sig()));
+    let result = contract.on_signed(SignTarget::Withdrawal(alice_wd_id_2), ChainType::ETH, vec![11u8; 32], "default/path".to_string(), Ok(mock_sig()));
     assert_eq!(result, "Success");
+    // PendingWithdrawal cleared, balance unchanged (already deducted)
+    assert!(contract.pending_withdrawals.get(&alice_wd_id_2).is_none());
     assert_eq!(
-        contract.get_balance(bob.clone(), "SOL".to_string()),
+        contract.get_balance(alice.clone(), "ETH".to_string()),
         u(0)
     );
 
+    // --- Bob keeps his WSOL, which has no token contract to withdraw through ---
+    assert!(contract.get_pending_withdrawals(bob.clone()).is_empty());
+
     // ================================================================
     // Phase 8: Final state verification
     //   Confirm all data consistent: balances settled, Intent/SubIntent status correct.
@@ -1626,7 +2154,7 @@ fn test_complete_e2e_simulation() {
 
     // Alice: SOL remaining 1 SOL (started 2 SOL, 1 SOL frozen in order), ETH fully withdrawn
     assert_eq!(
-        contract.get_balance(alice.clone(), "SOL".to_string()),
+        contract.get_balance(alice.clone(), "WSOL".to_string()),
         u(1_000_000_000)
     );
     assert_eq!(
@@ -1634,14 +2162,14 @@ fn test_complete_e2e_simulation() {
         u(0)
     );
 
-    // Bob: ETH remaining 0.05 ETH (started 0.1 ETH, 0.05 ETH frozen in order), SOL fully withdrawn
+    // Bob: ETH remaining 0.05 ETH (started 0.1 ETH, 0.05 ETH frozen in order), 1 WSOL received
     assert_eq!(
         contract.get_balance(bob.clone(), "ETH".to_string()),
         u(50_000_000_000_000_000)
     );
     assert_eq!(
-        contract.get_balance(bob.clone(), "SOL".to_string()),
-        u(0)
+        contract.get_balance(bob.clone(), "WSOL".to_string()),
+        u(1_000_000_000)
     );
 
     // Charlie: order still Open, SOL partially frozen
@@ -1650,18 +2178,18 @@ fn test_complete_e2e_simulation() {
         IntentStatus::Open
     );
     assert_eq!(
-        contract.get_balance(charlie.clone(), "SOL".to_string()),
+        contract.get_balance(charlie.clone(), "WSOL".to_string()),
         u(1_000_000_000) // 3 SOL - 2 SOL (frozen in order) = 1 SOL
     );
 
     // All SubIntents Completed
     assert_eq!(
         contract.get_sub_intent(sub_alice).unwrap().status,
-        IntentStatus::Completed
+        SubIntentStatus::Completed
     );
     assert_eq!(
         contract.get_sub_intent(sub_bob).unwrap().status,
-        IntentStatus::Completed
+        SubIntentStatus::Completed
     );
 
     // No leftover TransitionExpectation
@@ -1670,21 +2198,23 @@ fn test_complete_e2e_simulation() {
 
     // No leftover PendingWithdrawal
     assert!(contract.pending_withdrawals.get(&alice_wd_id).is_none());
-    assert!(contract.pending_withdrawals.get(&bob_wd_id).is_none());
-    assert!(contract.pending_withdrawals.get(&bob_wd_id_2).is_none());
+    assert!(contract.pending_withdrawals.get(&alice_wd_id_2).is_none());
 
     println!("=== Complete end-to-end simulation test passed! ===");
 }
 
 // ============================================================================
 // 17. 3-party ring match + full flow test
-//     Scenario: Alice(BTC->ETH), Bob(ETH->SOL), Charlie(SOL->BTC)
-//     Forms BTC -> ETH -> SOL -> BTC ring trade
+//     Scenario: Alice(WBTC->ETH), Bob(ETH->WSOL), Charlie(WSOL->WBTC)
+//     Forms WBTC -> ETH -> WSOL -> WBTC ring trade
 // ============================================================================
 
 #[test]
 fn test_complete_3party_ring_e2e() {
     let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    register_storage(&mut contract, &mut context, &user_charlie());
     let alice = user_alice();
     let bob = solver_bob();
     let charlie = user_charlie();
@@ -1692,42 +2222,63 @@ fn test_complete_3party_ring_e2e() {
     // --- Deposits ---
     testing_env!(context.predecessor_account_id(orderbook_contract()).build());
     contract.on_mpc_deposit_verified(
-        alice.clone(), "BTC".to_string(), U128(100_000_000), // 1 BTC in satoshis
+        alice.clone(), ChainType::ETH, "WBTC".to_string(), U128(100_000_000), // 1 BTC in satoshis
         "mpc-btc-alice".to_string(),
         format!("mpc:deposit:{}:BTC", alice),
-        Ok(true),
+        "dep-tx-13".to_string(),
+        verified(true),
     );
     contract.on_mpc_deposit_verified(
-        bob.clone(), "ETH".to_string(), U128(10_000_000_000_000_000_000), // 10 ETH in wei
+        bob.clone(), ChainType::ETH, "ETH".to_string(), U128(10_000_000_000_000_000_000), // 10 ETH in wei
         "mpc-eth-bob".to_string(),
         format!("mpc:deposit:{}:ETH", bob),
-        Ok(true),
+        "dep-tx-14".to_string(),
+        verified(true),
     );
     contract.on_mpc_deposit_verified(
-        charlie.clone(), "SOL".to_string(), U128(500_000_000_000), // 500 SOL in lamports
+        charlie.clone(), ChainType::ETH, "WSOL".to_string(), U128(500_000_000_000), // 500 SOL in lamports
         "mpc-sol-charlie".to_string(),
-        format!("mpc:deposit:{}:SOL", charlie),
-        Ok(true),
+        format!("mpc:deposit:{}:WSOL", charlie),
+        "dep-tx-15".to_string(),
+        verified(true),
     );
 
     // --- Place orders ---
     testing_env!(context.predecessor_account_id(alice.clone()).build());
     let id_a = contract.make_intent(
-        "BTC".to_string(), u(100_000_000),
+        "WBTC".to_string(), u(100_000_000),
         "ETH".to_string(), u(10_000_000_000_000_000_000),
-    );
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ).unwrap();
 
     testing_env!(context.predecessor_account_id(bob.clone()).build());
     let id_b = contract.make_intent(
         "ETH".to_string(), u(10_000_000_000_000_000_000),
-        "SOL".to_string(), u(500_000_000_000),
-    );
+        "WSOL".to_string(), u(500_000_000_000),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ).unwrap();
 
     testing_env!(context.predecessor_account_id(charlie.clone()).build());
     let id_c = contract.make_intent(
-        "SOL".to_string(), u(500_000_000_000),
-        "BTC".to_string(), u(100_000_000),
-    );
+        "WSOL".to_string(), u(500_000_000_000),
+        "WBTC".to_string(), u(100_000_000),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ).unwrap();
 
     // --- 3-party ring match ---
     testing_env!(context
@@ -1736,90 +2287,7715 @@ fn test_complete_3party_ring_e2e() {
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.batch_match_intents(vec![
-        mp_with_chain(id_a, 100_000_000, 10_000_000_000_000_000_000, ChainType::BTC),
+    contract.batch_match_intents(bind_assets(&contract, vec![
+        mp_with_chain(id_a, 100_000_000, 10_000_000_000_000_000_000, ChainType::ETH),
         mp_with_chain(id_b, 10_000_000_000_000_000_000, 500_000_000_000, ChainType::ETH),
-        mp_with_chain(id_c, 500_000_000_000, 100_000_000, ChainType::SOL),
-    ]);
+        mp_with_chain(id_c, 500_000_000_000, 100_000_000, ChainType::ETH),
+    ])).unwrap();
 
-    // Verify logical balance swap correct (ring conservation)
-    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(10_000_000_000_000_000_000));
-    assert_eq!(contract.get_balance(bob.clone(), "SOL".to_string()), u(500_000_000_000));
-    assert_eq!(contract.get_balance(charlie.clone(), "BTC".to_string()), u(100_000_000));
+    // Verify proceeds escrowed per sub-intent (ring conservation)
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().amount, 10_000_000_000_000_000_000);
+    assert_eq!(contract.get_escrow(U64(1)).unwrap().amount, 500_000_000_000);
+    assert_eq!(contract.get_escrow(U64(2)).unwrap().amount, 100_000_000);
 
-    // sub_intents: id_a=0, id_b=1, id_c=2 → sub_a=3, sub_b=4, sub_c=5
-    let sub_a = u(3);
-    let sub_b = u(4);
-    let sub_c = u(5);
+    // sub_intents have their own counter: sub_a=0, sub_b=1, sub_c=2
+    let sub_a = U64(0);
+    let sub_b = U64(1);
+    let sub_c = U64(2);
 
     // --- All MPC signs succeed ---
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(3, ChainType::BTC, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(4, ChainType::ETH, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(5, ChainType::SOL, [1u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(2), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
 
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Settled);
-    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, IntentStatus::Settled);
-    assert_eq!(contract.get_sub_intent(sub_c).unwrap().status, IntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(contract.get_sub_intent(sub_c).unwrap().status, SubIntentStatus::Settled);
 
     // --- All transition verifications ---
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_a, vec![1], "addr-a".to_string(), "tx-btc".to_string());
+    let _ = contract.verify_transition_completion(sub_a, vec![1], "tx-btc".to_string());
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_b, vec![1], "addr-b".to_string(), "tx-eth".to_string());
+    let _ = contract.verify_transition_completion(sub_b, vec![1], "tx-eth".to_string());
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    let _ = contract.verify_transition_completion(sub_c, vec![1], "addr-c".to_string(), "tx-sol".to_string());
+    let _ = contract.verify_transition_completion(sub_c, vec![1], "tx-sol".to_string());
 
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_a, "tx-btc".to_string(), Ok(true));
+    contract.on_transition_verified(sub_a, "tx-btc".to_string(), transition_verified(true));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_b, "tx-eth".to_string(), Ok(true));
+    contract.on_transition_verified(sub_b, "tx-eth".to_string(), transition_verified(true));
     testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_transition_verified(sub_c, "tx-sol".to_string(), Ok(true));
+    contract.on_transition_verified(sub_c, "tx-sol".to_string(), transition_verified(true));
 
     // All Completed
-    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, IntentStatus::Completed);
-    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, IntentStatus::Completed);
-    assert_eq!(contract.get_sub_intent(sub_c).unwrap().status, IntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_a).unwrap().status, SubIntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_b).unwrap().status, SubIntentStatus::Completed);
+    assert_eq!(contract.get_sub_intent(sub_c).unwrap().status, SubIntentStatus::Completed);
+    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(10_000_000_000_000_000_000));
+    assert_eq!(contract.get_balance(bob.clone(), "WSOL".to_string()), u(500_000_000_000));
+    assert_eq!(contract.get_balance(charlie.clone(), "WBTC".to_string()), u(100_000_000));
 
-    // --- All parties withdraw ---
-    // Alice withdraws 10 ETH
+    // --- Alice withdraws 10 ETH ---
     testing_env!(context
         .predecessor_account_id(alice.clone())
         .attached_deposit(NearToken::from_near(1))
         .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.withdraw("ETH".to_string(), u(10_000_000_000_000_000_000), [20u8; 32], "eth/a".to_string(), ChainType::ETH);
+    contract.withdraw("ETH".to_string(), u(10_000_000_000_000_000_000), ETH_DEST.to_string(), eth_tx(10_000_000_000_000_000_000), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(6, ChainType::ETH, [20u8; 32], Ok(mock_sig()));
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![20u8; 32], "default/path".to_string(), Ok(mock_sig()));
     assert_eq!(contract.get_balance(alice, "ETH".to_string()), u(0));
 
-    // Bob withdraws 500 SOL
+    println!("=== 3-party ring match full flow test passed! ===");
+}
+
+// ============================================================================
+// 18. CANCEL INTENT
+// ============================================================================
+
+#[test]
+fn test_cancel_intent_before_any_fill() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(400), "ETH".to_string(), u(40), None, None, None, None, None, None).unwrap();
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(600));
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::Cancelled);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(1000));
+    assert!(contract.get_open_intents(u(0), 100).is_empty());
+}
+
+#[test]
+fn test_cancel_intent_after_partial_fill_refunds_remainder() {
+    let (mut contract, mut context) = new_contract();
+    let alice = user_alice();
+    let bob = solver_bob();
+
+    owner_deposit(&mut contract, &mut context, &alice, "A", 100);
+    owner_deposit(&mut contract, &mut context, &bob, "B", 100);
+
+    testing_env!(context.predecessor_account_id(alice.clone()).build());
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(bob.clone()).build());
+    let id2 = contract.make_intent("B".to_string(), u(40), "A".to_string(), u(40), None, None, None, None, None, None).unwrap();
+
     testing_env!(context
-        .predecessor_account_id(bob.clone())
+        .predecessor_account_id(orderbook_contract())
         .attached_deposit(NearToken::from_near(1))
-        .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.withdraw("SOL".to_string(), u(500_000_000_000), [21u8; 32], "sol/b".to_string(), ChainType::SOL);
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 40, 40), mp(id2, 40, 40)])).unwrap();
+
+    // Drive Alice's sub-intent (id 0) to Completed so nothing is in flight.
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(7, ChainType::SOL, [21u8; 32], Ok(mock_sig()));
-    assert_eq!(contract.get_balance(bob, "SOL".to_string()), u(0));
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    contract.on_transition_verified(U64(0), "tx".to_string(), transition_verified(true));
+
+    testing_env!(context.predecessor_account_id(alice.clone()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id1);
+
+    let intent = contract.get_intent(id1).unwrap();
+    assert_eq!(intent.status, IntentStatus::Cancelled);
+    assert_eq!(intent.filled_amount, 40);
+    assert_eq!(contract.get_balance(alice.clone(), "A".to_string()), u(60));
+    assert_eq!(contract.get_balance(alice, "B".to_string()), u(40));
+}
+
+#[test]
+#[should_panic(expected = "not open")]
+fn test_cancel_intent_twice_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+    contract.cancel_intent(id);
+}
+
+#[test]
+#[should_panic(expected = "Only the maker can cancel the intent")]
+fn test_cancel_intent_not_maker_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+}
+
+#[test]
+#[should_panic(expected = "has sub-intents still settling")]
+fn test_cancel_intent_with_sub_intent_in_flight_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 100);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("B".to_string(), u(50), "A".to_string(), u(50), None, None, None, None, None, None).unwrap();
 
-    // Charlie withdraws 1 BTC
     testing_env!(context
-        .predecessor_account_id(charlie.clone())
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 50, 50), mp(id2, 50, 50)])).unwrap();
+
+    // Sub-intent for id1 is still Verifying
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id1);
+}
+
+#[test]
+fn test_take_cancelled_intent_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.take_intent(id, u(10)).unwrap_err();
+    assert_eq!(error, OrderbookError::IntentNotOpen { id: 0 });
+}
+
+// ============================================================================
+// 19. INTENT EXPIRY
+// ============================================================================
+
+#[test]
+fn test_intent_expiry_boundary_on_take() {
+    let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 10);
+    testing_env!(context.predecessor_account_id(user_alice()).block_timestamp(1_000).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), Some(2_000), None, None, None, None, None).unwrap();
+
+    // One nanosecond before expiry is still fillable
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_999).build());
+    contract.take_intent(id, u(10)).unwrap();
+
+    // Exactly at expiry the intent is no longer fillable
+    testing_env!(context.block_timestamp(2_000).build());
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.take_intent(id, u(10)).unwrap();
+    }));
+    assert!(res.is_err(), "take at expiry timestamp must fail");
+}
+
+#[test]
+fn test_batch_match_rejects_expired_intent() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "A", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "B", 100);
+
+    testing_env!(context.predecessor_account_id(user_alice()).block_timestamp(1_000).build());
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), Some(5_000), None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .block_timestamp(5_000)
+        .build()
+    );
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::IntentExpired { id: 0 });
+}
+
+#[test]
+fn test_expire_intent_refunds_unfilled_remainder() {
+    let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    register_storage(&mut contract, &mut context, &user_charlie());
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 10);
+    testing_env!(context.predecessor_account_id(user_alice()).block_timestamp(1_000).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), Some(2_000), None, None, None, None, None).unwrap();
+    assert_eq!(contract.get_open_intents(u(0), 10).len(), 1);
+
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_500).build());
+    contract.take_intent(id, u(30)).unwrap();
+
+    // Past expiry: hidden from the open list, and anyone can expire it
+    testing_env!(context.predecessor_account_id(user_charlie()).block_timestamp(2_500).build());
+    assert!(contract.get_open_intents(u(0), 10).is_empty());
+    contract.expire_intent(id);
+
+    let intent = contract.get_intent(id).unwrap();
+    assert_eq!(intent.status, IntentStatus::Expired);
+    assert_eq!(intent.filled_amount, 30);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(70));
+}
+
+#[test]
+#[should_panic(expected = "has not expired yet")]
+fn test_expire_intent_without_expiry_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).block_timestamp(1_000).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    testing_env!(context.block_timestamp(u64::MAX).build());
+    assert_eq!(contract.get_open_intents(u(0), 10).len(), 1);
+    contract.expire_intent(id);
+}
+
+#[test]
+fn test_make_intent_with_past_expiry_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).block_timestamp(1_000).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), Some(1_000), None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::ExpiryInPast);
+}
+
+// ============================================================================
+// 20. INDEPENDENT ID COUNTERS & STATE MIGRATION
+// ============================================================================
+
+/// Two intents matched by `orderbook_contract()`, leaving sub-intents 0 and 1 Verifying.
+fn setup_matched_pair(contract: &mut Orderbook, context: &mut VMContextBuilder) -> (U64, U64) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 100);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
+    (id_a, id_b)
+}
+
+#[test]
+fn test_id_counters_are_independent() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_matched_pair(&mut contract, &mut context);
+    assert_eq!((id_a, id_b), (U64(0), U64(1)));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().parent_intent_id, 0);
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().parent_intent_id, 1);
+
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!(contract.get_pending_withdrawal(U64(0)).unwrap().amount, 10);
+    assert_eq!(contract.next_intent_id, 2);
+    assert_eq!(contract.next_sub_intent_id, 2);
+    assert_eq!(contract.next_withdrawal_id, 1);
+}
+
+#[test]
+fn test_withdrawal_failure_does_not_touch_sub_intent_with_same_id() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
         .attached_deposit(NearToken::from_near(1))
-        .prepaid_gas(Gas::from_tgas(300))
         .build()
     );
-    let _ = contract.withdraw("BTC".to_string(), u(100_000_000), [22u8; 32], "btc/c".to_string(), ChainType::BTC);
+    contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+
+    // Sub-intent 0 and withdrawal 0 share a numeric id; fail the withdrawal.
     testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
-    contract.on_signed(8, ChainType::BTC, [22u8; 32], Ok(mock_sig()));
-    assert_eq!(contract.get_balance(charlie, "BTC".to_string()), u(0));
+    let res = contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![3u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(res, "Failed");
 
-    println!("=== 3-party ring match full flow test passed! ===");
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(10));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Verifying);
+    assert!(contract.get_transition_expectation(U64(0)).is_some());
+}
+
+#[test]
+fn test_sub_intent_success_does_not_clear_withdrawal_with_same_id() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+    assert!(contract.get_pending_withdrawal(U64(0)).is_some());
+}
+
+/// Write a V0 root state in which Alice has 400 SOL and an open intent
+/// selling 600 `src_asset` for 60 `dst_asset`, 100 of it taken by Bob and
+/// left at `status`. A `Verifying` take also has the expectation V0 wrote
+/// when it was signed.
+fn write_v0_take(context: &mut VMContextBuilder, src_asset: &str, dst_asset: &str, status: LegacyStatus) {
+    use crate::migration::{IntentV0, OrderbookV0, SubIntentV0, TransitionExpectationV0};
+
+    // No `new_contract`: its asset registry would already occupy storage.
+    *context = get_context(orderbook_contract(), NearToken::from_near(0));
+    testing_env!(context.build());
+    let mut balances: UnorderedMap<AccountId, UnorderedMap<String, u128>> = UnorderedMap::new(b"b");
+    let mut alice_bal = UnorderedMap::new(format!("b{}", user_alice()).as_bytes());
+    alice_bal.insert(&"SOL".to_string(), &400u128);
+    balances.insert(&user_alice(), &alice_bal);
+
+    let mut intents = UnorderedMap::new(b"i");
+    intents.insert(&0u64, &IntentV0 {
+        id: 0,
+        maker: user_alice(),
+        src_asset: src_asset.to_string(),
+        src_amount: 600,
+        filled_amount: 100,
+        dst_asset: dst_asset.to_string(),
+        dst_amount: 60,
+        status: LegacyStatus::Open,
+    });
+    let mut sub_intents = UnorderedMap::new(b"s");
+    sub_intents.insert(&1u64, &SubIntentV0 {
+        id: 1,
+        parent_intent_id: 0,
+        taker: solver_bob(),
+        amount: 100,
+        status: status.clone(),
+    });
+    let mut transition_expectations = UnorderedMap::new(b"x");
+    if status == LegacyStatus::Verifying {
+        transition_expectations.insert(&1u64, &TransitionExpectationV0 {
+            sub_intent_id: 1,
+            chain_type: ChainType::ETH,
+            expected_asset: src_asset.to_string(),
+            expected_amount: 100,
+            expected_memo: "transition:sub:1".to_string(),
+        });
+    }
+
+    let old = OrderbookV0 {
+        owner: orderbook_contract(),
+        mpc_contract: mpc_contract(),
+        light_client_contract: light_client_contract(),
+        balances,
+        intents,
+        sub_intents,
+        transition_expectations,
+        pending_withdrawals: UnorderedMap::new(b"w"),
+        next_id: 2,
+    };
+    env::state_write(&old);
+}
+
+/// Migrate the stored V0 state, registering the test assets before
+/// the records are rewritten, and reopen the contract.
+fn migrate_v0(context: &mut VMContextBuilder) -> Orderbook {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let mut contract = Orderbook::migrate();
+    register_test_assets(&mut contract);
+    assert!(contract.migrate_batch(100));
+    contract.unpause();
+    contract
+}
+
+#[test]
+fn test_migrate_from_v0_layout() {
+    let mut context = get_context(orderbook_contract(), NearToken::from_near(0));
+    write_v0_take(&mut context, "SOL", "ETH", LegacyStatus::Taken);
+    let mut contract = migrate_v0(&mut context);
+
+    let intent = contract.get_intent(U64(0)).unwrap();
+    assert_eq!(intent.src_amount, 600);
+    assert_eq!(intent.filled_amount, 100);
+    assert_eq!(intent.expires_at, None);
+    assert_eq!(intent.min_fill_amount, 0);
+    assert!(!intent.all_or_nothing);
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().taker, solver_bob());
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(400));
+    assert_eq!(contract.get_open_intents(u(0), 10).len(), 1);
+    assert_eq!(contract.get_sub_intents_by_parent(U64(0), u(0), 10)[0].id, 1);
+    assert_eq!(contract.get_sub_intents_by_taker(solver_bob(), u(0), 10)[0].id, 1);
+    assert_eq!(contract.get_intents_by_maker(user_alice(), None, u(0), 10).len(), 1);
+    assert_eq!(contract.get_chain_signer(ChainType::BTC), mpc_contract());
+    assert_eq!(contract.get_stats().total_intents_created, 1);
+    // Balance plus the open remainder is taken as credited
+    let solvency = contract.check_solvency("SOL".to_string());
+    assert_eq!((solvency.liabilities, solvency.credited), (u(900), u(900)));
+
+    // New ids continue past everything the shared counter handed out.
+    // Migrated accounts have no storage balance until they register.
+    register_storage(&mut contract, &mut context, &user_alice());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    assert_eq!(id, U64(2));
+    assert_eq!(contract.next_sub_intent_id, 2);
+    assert_eq!(contract.next_withdrawal_id, 2);
+    assert_eq!(contract.get_state_version(), STATE_VERSION);
+}
+
+#[test]
+fn test_migrate_v0_resumes_across_batches() {
+    let mut context = get_context(orderbook_contract(), NearToken::from_near(0));
+    write_v0_take(&mut context, "SOL", "ETH", LegacyStatus::Taken);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let mut contract = Orderbook::migrate();
+    register_test_assets(&mut contract);
+    assert_eq!(contract.get_state_version(), STATE_VERSION);
+
+    // One record per call: the intent, the sub-intent, then Alice's balances
+    assert!(!contract.migrate_batch(1));
+    assert_eq!(contract.get_intent(U64(0)).unwrap().filled_amount, 100);
+    assert_eq!(contract.get_open_intents(u(0), 10).len(), 1);
+    assert_eq!(contract.total_credited.get(&"SOL".to_string()), Some(500));
+    assert!(!contract.migrate_batch(1));
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().status, SubIntentStatus::Taken);
+    assert!(!contract.migrate_batch(1));
+    assert_eq!(contract.check_solvency("SOL".to_string()).credited, u(900));
+    assert!(!near_sdk::test_utils::get_logs().contains(&"MIGRATION_COMPLETE".to_string()));
+    assert!(contract.migrate_batch(1));
+    assert!(near_sdk::test_utils::get_logs().contains(&"MIGRATION_COMPLETE".to_string()));
+    // Nothing left to do
+    assert!(contract.migrate_batch(1));
+    assert_eq!(contract.get_stats().total_intents_created, 1);
+}
+
+#[test]
+fn test_contract_stays_paused_until_migration_completes() {
+    let mut context = get_context(orderbook_contract(), NearToken::from_near(0));
+    write_v0_take(&mut context, "SOL", "ETH", LegacyStatus::Taken);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let mut contract = Orderbook::migrate();
+    register_test_assets(&mut contract);
+    assert!(contract.is_paused());
+
+    let early = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.unpause()));
+    assert!(early.is_err(), "unpause must wait for the migration");
+    assert!(contract.migrate_batch(10));
+    contract.unpause();
+    assert!(!contract.is_paused());
+}
+
+#[test]
+#[should_panic(expected = "Register asset SOL before migrating its sub-intents")]
+fn test_migrate_v0_take_needs_registered_asset() {
+    let mut context = get_context(orderbook_contract(), NearToken::from_near(0));
+    write_v0_take(&mut context, "SOL", "ETH", LegacyStatus::Taken);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let mut contract = Orderbook::migrate();
+    contract.migrate_batch(10);
+}
+
+#[test]
+fn test_migrated_v0_take_retries_once_its_transaction_is_approved() {
+    let mut context = get_context(orderbook_contract(), NearToken::from_near(0));
+    write_v0_take(&mut context, "ETH", "SOL", LegacyStatus::Taken);
+    let mut contract = migrate_v0(&mut context);
+    let request = contract.get_settlement_request(U64(1)).unwrap();
+    assert_eq!(request.payload, SettlementRequest::UNSET_PAYLOAD);
+    assert_eq!((request.chain_type, request.path, request.recipient), (ChainType::ETH, "default/path".to_string(), String::new()));
+
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let error = contract.retry_settlement(U64(1)).err();
+    assert_eq!(error, Some(OrderbookError::SettlementPayloadUnset { id: 1 }));
+
+    // Alice registers where she is paid; Bob proposes a transaction paying it
+    register_storage(&mut contract, &mut context, &user_alice());
+    let tx = settlement_tx(100, 5, &sub_memo(&contract, U64(1)));
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.propose_settlement_tx(U64(1), tx.clone());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.replace_settlement_payload(U64(1), env::keccak256_array(&tx));
+    let request = contract.get_settlement_request(U64(1)).unwrap();
+    assert_eq!(request.recipient, ETH_DEST);
+    assert_eq!(request.unsigned_tx, Some(tx));
+
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let _ = contract.retry_settlement(U64(1)).unwrap();
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().status, SubIntentStatus::Verifying);
+}
+
+#[test]
+fn test_migrated_v0_verifying_take_keeps_its_memo_and_can_retry() {
+    let mut context = get_context(orderbook_contract(), NearToken::from_near(0));
+    write_v0_take(&mut context, "ETH", "SOL", LegacyStatus::Verifying);
+    let mut contract = migrate_v0(&mut context);
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().status, SubIntentStatus::Verifying);
+    let request = contract.get_settlement_request(U64(1)).unwrap();
+    assert_eq!((request.payload, request.chain_type), (SettlementRequest::UNSET_PAYLOAD, ChainType::ETH));
+    // The payload being signed carries the V0 memo
+    assert_eq!(sub_memo(&contract, U64(1)), "transition:sub:1");
+    assert_eq!(contract.next_transition_nonce, 0);
+
+    // Its signature fails, and the take is retried like any other
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().status, SubIntentStatus::Taken);
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let error = contract.retry_settlement(U64(1)).err();
+    assert_eq!(error, Some(OrderbookError::SettlementPayloadUnset { id: 1 }));
+}
+
+#[test]
+fn test_sub_intent_failure_does_not_refund_withdrawal_with_same_id() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+
+    // Sub-intent rolled back, withdrawal untouched and not refunded
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Taken);
+    assert!(contract.get_pending_withdrawal(U64(0)).is_some());
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+}
+
+#[test]
+fn test_stale_sign_failure_does_not_roll_back_settled_sub_intent() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+    assert!(contract.get_transition_expectation(U64(0)).is_some());
+}
+
+// ============================================================================
+// 21. MAKER PROCEEDS ESCROW
+// ============================================================================
+
+#[test]
+fn test_escrow_released_only_after_transition_verified() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+
+    // Failed transition proof keeps the escrow in place
+    contract.on_transition_verified(U64(0), "tx".to_string(), transition_verified(false));
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+    assert!(contract.get_escrow(U64(0)).is_some());
+
+    contract.on_transition_verified(U64(0), "tx".to_string(), transition_verified(true));
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+    assert!(contract.get_escrow(U64(0)).is_none());
+}
+
+#[test]
+fn test_claim_escrow_timeout_unfills_intent() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.block_timestamp(1_000).build());
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    // Signing failed, so no transaction is out and the fill can be unwound
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+
+    let deadline = contract.get_escrow(U64(0)).unwrap().claimable_after;
+    assert_eq!(deadline, 1_000 + DEFAULT_ESCROW_TIMEOUT_NS);
+    testing_env!(context.predecessor_account_id(user_charlie()).block_timestamp(deadline).build());
+    contract.claim_escrow_timeout(U64(0)).unwrap();
+
+    let intent = contract.get_intent(id_a).unwrap();
+    assert_eq!(intent.status, IntentStatus::Open);
+    assert_eq!(intent.filled_amount, 0);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Cancelled);
+    assert!(contract.get_escrow(U64(0)).is_none());
+    assert!(contract.get_transition_expectation(U64(0)).is_none());
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+
+    // Maker can now take the restored funds back
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id_a);
+    assert_eq!(contract.get_balance(user_alice(), "WSOL".to_string()), u(100));
+}
+
+#[test]
+fn test_claim_escrow_timeout_before_deadline_fails() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.block_timestamp(1_000).build());
+    setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_ESCROW_TIMEOUT_NS - 1).build());
+    let error = contract.claim_escrow_timeout(U64(0)).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::EscrowTimeoutNotReached { id: 0, claimable_after: 1_000 + DEFAULT_ESCROW_TIMEOUT_NS }
+    );
+}
+
+#[test]
+fn test_claim_escrow_timeout_while_signing_fails() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.block_timestamp(1_000).build());
+    setup_matched_pair(&mut contract, &mut context);
+    // Sub-intent still Verifying: a signature may yet be produced
+    testing_env!(context.block_timestamp(u64::MAX).build());
+    let error = contract.claim_escrow_timeout(U64(0)).unwrap_err();
+    assert_eq!(error, OrderbookError::SubIntentNotTaken { id: 0 });
+}
+
+#[test]
+fn test_claim_escrow_timeout_after_signing_fails() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    testing_env!(context.block_timestamp(u64::MAX).build());
+    // A signed sub-intent is only failed by claim_transition_timeout
+    let error = contract.claim_escrow_timeout(U64(0)).unwrap_err();
+    assert_eq!(error, OrderbookError::SubIntentNotTaken { id: 0 });
+}
+
+#[test]
+fn test_set_escrow_timeout_applies_to_new_matches() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_timestamp(500).build());
+    contract.set_escrow_timeout(1_000);
+    setup_matched_pair(&mut contract, &mut context);
+    assert_eq!(contract.get_escrow(U64(1)).unwrap().claimable_after, 1_500);
+}
+
+// ============================================================================
+// 22. SUB-INTENT INDEX VIEWS
+// ============================================================================
+
+#[test]
+fn test_get_sub_intents_by_parent_paginates() {
+    let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    register_storage(&mut contract, &mut context, &user_charlie());
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 30);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 10);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    for _ in 0..3 {
+        contract.take_intent(id, u(10)).unwrap();
+    }
+
+    let page1 = contract.get_sub_intents_by_parent(id, u(0), 2);
+    assert_eq!(page1.iter().map(|s| s.id).collect::<Vec<_>>(), vec![0, 1]);
+
+    // New sub-intents land after the existing ones, so offsets stay valid
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.take_intent(id, u(10)).unwrap();
+    let page2 = contract.get_sub_intents_by_parent(id, u(2), 2);
+    assert_eq!(page2.iter().map(|s| s.id).collect::<Vec<_>>(), vec![2, 3]);
+    assert!(contract.get_sub_intents_by_parent(id, u(4), 2).is_empty());
+    assert!(contract.get_sub_intents_by_parent(U64(99), u(0), 2).is_empty());
+}
+
+#[test]
+fn test_get_sub_intents_by_taker() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    // Batch matches record the submitting solver as taker
+    let subs = contract.get_sub_intents_by_taker(orderbook_contract(), u(0), 10);
+    assert_eq!(subs.iter().map(|s| s.parent_intent_id).collect::<Vec<_>>(), vec![0, 1]);
+    assert!(contract.get_sub_intents_by_taker(solver_bob(), u(0), 10).is_empty());
+
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "WSOL", 50);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "BTC", 1);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id = contract.make_intent("WSOL".to_string(), u(50), "BTC".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(50)).unwrap();
+
+    let subs = contract.get_sub_intents_by_taker(solver_bob(), u(0), 10);
+    assert_eq!(subs.len(), 1);
+    assert_eq!(subs[0].id, sub.0);
+}
+
+// ============================================================================
+// 23. INTENTS BY MAKER
+// ============================================================================
+
+#[test]
+fn test_get_intents_by_maker_with_status_filter() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 300);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 300);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let a1 = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    let a2 = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    let a3 = contract.make_intent("WSOL".to_string(), u(100), "BTC".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let b1 = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    let b2 = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(a1, 100, 100), mp(b1, 100, 100)])).unwrap();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(a3);
+
+    let ids = |intents: Vec<Intent>| intents.iter().map(|i| U64(i.id)).collect::<Vec<_>>();
+    assert_eq!(ids(contract.get_intents_by_maker(user_alice(), None, u(0), 10)), vec![a1, a2, a3]);
+    assert_eq!(
+        ids(contract.get_intents_by_maker(user_alice(), Some(IntentStatus::Open), u(0), 10)),
+        vec![a2]
+    );
+    assert_eq!(
+        ids(contract.get_intents_by_maker(user_alice(), Some(IntentStatus::Filled), u(0), 10)),
+        vec![a1]
+    );
+    assert_eq!(
+        ids(contract.get_intents_by_maker(user_alice(), Some(IntentStatus::Cancelled), u(0), 10)),
+        vec![a3]
+    );
+    assert_eq!(
+        ids(contract.get_intents_by_maker(solver_bob(), Some(IntentStatus::Open), u(0), 10)),
+        vec![b2]
+    );
+    assert_eq!(ids(contract.get_intents_by_maker(solver_bob(), None, u(1), 10)), vec![b2]);
+    assert!(contract.get_intents_by_maker(user_charlie(), None, u(0), 10).is_empty());
+}
+
+// ============================================================================
+// 24. STORAGE STAKING
+// ============================================================================
+
+#[test]
+#[should_panic(expected = "Storage not registered for")]
+fn test_deposit_for_unregistered_user_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.deposit_for(user_alice(), "SOL".to_string(), u(100));
+}
+
+#[test]
+fn test_storage_deposit_and_balance_of() {
+    let (mut contract, mut context) = new_contract();
+    assert!(contract.storage_balance_of(user_alice()).is_none());
+
+    // Anyone can fund another account's storage
+    testing_env!(context.predecessor_account_id(solver_bob()).attached_deposit(NearToken::from_near(1)).build());
+    let balance = contract.storage_deposit(Some(user_alice()));
+    assert_eq!(balance.total, u(NearToken::from_near(1).as_yoctonear()));
+    assert!(balance.available.0 < balance.total.0);
+    assert_eq!(contract.storage_balance_of(user_alice()), Some(balance));
+    assert!(contract.storage_balance_of(solver_bob()).is_none());
+}
+
+#[test]
+fn test_make_intent_underfunded_storage_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+
+    // Pull out everything not already locked, leaving nothing for new records
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    let balance = contract.storage_withdraw(None);
+    assert_eq!(balance.available, u(0));
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientStorage { account: user_alice() });
+}
+
+#[test]
+fn test_take_intent_underfunded_storage_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    // Bytes already in use stay covered, a sub-intent record is not
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(solver_bob()).attached_deposit(NearToken::from_near(0)).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.storage_withdraw(None);
+    let error = contract.take_intent(id, u(50)).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientStorage { account: solver_bob() });
+}
+
+#[test]
+fn test_storage_credited_back_after_cancel() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    let before_intent = contract.storage_balance_of(user_alice()).unwrap().available.0;
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    let after_make = contract.storage_balance_of(user_alice()).unwrap().available.0;
+    assert!(after_make < before_intent);
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+    let after_cancel = contract.storage_balance_of(user_alice()).unwrap().available.0;
+    assert!(after_cancel > after_make);
+}
+
+#[test]
+#[should_panic(expected = "Storage withdraw exceeds available balance")]
+fn test_storage_withdraw_locked_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.storage_withdraw(Some(u(NearToken::from_near(1).as_yoctonear())));
+}
+
+// ============================================================================
+// 25. STORED SIGNATURES
+// ============================================================================
+
+#[test]
+fn test_signature_stored_until_transition_verified() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+
+    let stored = contract.get_signature(SignTarget::SubIntent(0)).unwrap();
+    assert_eq!(stored.target, SignTarget::SubIntent(0));
+    assert_eq!(stored.chain_type, ChainType::ETH);
+    assert_eq!(stored.payload, hex::encode([1u8; 32]));
+    assert_eq!(stored.signature, mock_sig());
+    assert!(contract.get_signature(SignTarget::Withdrawal(0)).is_none());
+
+    // A failed proof leaves it available for rebroadcast
+    contract.on_transition_verified(U64(0), "tx".to_string(), transition_verified(false));
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_some());
+
+    contract.on_transition_verified(U64(0), "tx".to_string(), transition_verified(true));
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_none());
+}
+
+#[test]
+fn test_failed_sign_stores_nothing() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_none());
+}
+
+#[test]
+fn test_owner_removes_withdrawal_signature() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(
+        contract.get_signature(SignTarget::Withdrawal(0)).unwrap().payload,
+        hex::encode([9u8; 32])
+    );
+
+    contract.remove_signature(SignTarget::Withdrawal(0));
+    assert!(contract.get_signature(SignTarget::Withdrawal(0)).is_none());
+}
+
+#[test]
+#[should_panic(expected = "Only owner can remove signatures")]
+fn test_remove_signature_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.remove_signature(SignTarget::SubIntent(0));
+}
+
+// ============================================================================
+// 26. PAUSE
+// ============================================================================
+
+fn paused_contract() -> (Orderbook, VMContextBuilder) {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.pause();
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    (contract, context)
+}
+
+#[test]
+fn test_pause_unpause() {
+    let (mut contract, mut context) = new_contract();
+    assert!(!contract.is_paused());
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.pause();
+    assert!(contract.is_paused());
+    contract.unpause();
+    assert!(!contract.is_paused());
+}
+
+#[test]
+#[should_panic(expected = "Only owner can pause")]
+fn test_pause_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.pause();
+}
+
+#[test]
+fn test_paused_make_intent() {
+    let (mut contract, _) = paused_contract();
+    let error = contract.make_intent("SOL".to_string(), u(10), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::ContractPaused);
+}
+
+#[test]
+fn test_paused_take_intent() {
+    let (mut contract, _) = paused_contract();
+    let error = contract.take_intent(U64(0), u(10)).unwrap_err();
+    assert_eq!(error, OrderbookError::ContractPaused);
+}
+
+#[test]
+fn test_paused_batch_match() {
+    let (mut contract, _) = paused_contract();
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(U64(0), 10, 10), mp(U64(1), 10, 10)])).unwrap_err();
+    assert_eq!(error, OrderbookError::ContractPaused);
+}
+
+#[test]
+fn test_paused_submit_payment_proof() {
+    let (mut contract, _) = paused_contract();
+    let error = contract.submit_payment_proof(
+        U64(0), vec![], [0u8; 32], None, "default/path".to_string(),
+        ChainType::SOL, ChainType::ETH, "recipient".to_string(), "sub:0".to_string(),
+        ETH_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).err();
+    assert_eq!(error, Some(OrderbookError::ContractPaused));
+}
+
+#[test]
+fn test_paused_withdraw() {
+    let (mut contract, _) = paused_contract();
+    let error = contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap_err();
+    assert_eq!(error, OrderbookError::ContractPaused);
+}
+
+#[test]
+fn test_paused_retry_settlement() {
+    let (mut contract, _) = paused_contract();
+    let error = contract.retry_settlement(U64(0)).err();
+    assert_eq!(error, Some(OrderbookError::ContractPaused));
+}
+
+#[test]
+#[should_panic(expected = "Contract is paused")]
+fn test_paused_verify_mpc_deposit() {
+    let (mut contract, _) = paused_contract();
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), u(10), "addr".to_string(),
+        format!("mpc:deposit:{}:SOL", user_alice()), vec![], "tx".to_string(),
+    );
+}
+
+#[test]
+fn test_paused_withdraw_failure_still_refunds() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(60), ETH_DEST.to_string(), eth_tx(60), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(40));
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.pause();
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+    assert!(contract.get_pending_withdrawal(U64(0)).is_none());
+}
+
+// ============================================================================
+// 27. OWNERSHIP TRANSFER
+// ============================================================================
+
+#[test]
+fn test_ownership_transfer_two_step() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.propose_owner(user_dave());
+    // A newer proposal overwrites the previous one
+    contract.propose_owner(user_charlie());
+    assert_eq!(contract.get_pending_owner(), Some(user_charlie()));
+    assert_eq!(contract.get_owner(), orderbook_contract());
+
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.accept_ownership();
+    assert_eq!(contract.get_owner(), user_charlie());
+    assert_eq!(contract.get_pending_owner(), None);
+
+    // New owner holds the admin rights
+    contract.pause();
+    assert!(contract.is_paused());
+}
+
+#[test]
+#[should_panic(expected = "Only the proposed owner can accept ownership")]
+fn test_accept_ownership_unauthorized_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.propose_owner(user_charlie());
+    testing_env!(context.predecessor_account_id(user_dave()).build());
+    contract.accept_ownership();
+}
+
+#[test]
+#[should_panic(expected = "Only the proposed owner can accept ownership")]
+fn test_cancelled_owner_proposal_cannot_be_accepted() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.propose_owner(user_charlie());
+    contract.cancel_owner_proposal();
+    assert_eq!(contract.get_pending_owner(), None);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.accept_ownership();
+}
+
+#[test]
+#[should_panic(expected = "Only owner can propose a new owner")]
+fn test_propose_owner_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.propose_owner(user_alice());
+}
+
+// ============================================================================
+// 28. CONFIG SETTERS
+// ============================================================================
+
+#[test]
+fn test_set_mpc_contract_without_delay() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_mpc_contract(user_dave());
+    assert_eq!(
+        contract.get_config(),
+        ContractConfig {
+            owner: orderbook_contract(),
+            mpc_contract: user_dave(),
+            light_client_contract: light_client_contract(),
+            eth_chain_id: DEFAULT_ETH_CHAIN_ID,
+            max_withdrawal_gas_fee: U128(DEFAULT_MAX_WITHDRAWAL_GAS_FEE),
+            fee_bps: 0,
+            fee_collector: orderbook_contract(),
+            referral_bps: 0,
+            config_delay_blocks: 0,
+        }
+    );
+}
+
+#[test]
+fn test_config_change_waits_for_delay() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(100).build());
+    contract.set_config_delay(10);
+    contract.set_light_client_contract(user_dave());
+    assert_eq!(contract.get_config().light_client_contract, light_client_contract());
+    assert_eq!(contract.get_pending_config_changes()[0].effective_height, 110);
+
+    testing_env!(context.predecessor_account_id(user_charlie()).block_height(109).build());
+    assert_eq!(contract.apply_config_changes(), 0);
+    assert_eq!(contract.get_config().light_client_contract, light_client_contract());
+
+    testing_env!(context.block_height(110).build());
+    assert_eq!(contract.apply_config_changes(), 1);
+    assert_eq!(contract.get_config().light_client_contract, user_dave());
+    assert!(contract.get_pending_config_changes().is_empty());
+}
+
+#[test]
+fn test_lowering_config_delay_is_delayed_and_cancelable() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(100).build());
+    contract.set_config_delay(10);
+    contract.set_config_delay(0);
+    assert_eq!(contract.get_config().config_delay_blocks, 10);
+
+    contract.cancel_config_changes();
+    testing_env!(context.block_height(200).build());
+    assert_eq!(contract.apply_config_changes(), 0);
+    assert_eq!(contract.get_config().config_delay_blocks, 10);
+}
+
+#[test]
+#[should_panic(expected = "Only owner can change config")]
+fn test_set_mpc_contract_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_mpc_contract(user_alice());
+}
+
+#[test]
+fn test_mpc_contract_change_keeps_inflight_withdraw_refund() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(60), ETH_DEST.to_string(), eth_tx(60), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_mpc_contract(user_dave());
+
+    // Callback for the promise sent to the old signer still refunds
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+}
+
+// ============================================================================
+// 29. PER-CHAIN SIGNERS
+// ============================================================================
+
+fn signer_receivers() -> Vec<AccountId> {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .map(|r| r.receiver_id)
+        .filter(|id| *id != orderbook_contract())
+        .collect()
+}
+
+#[test]
+fn test_chain_signers_route_by_chain() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_chain_signer(ChainType::SOL, Some(user_dave()));
+    assert_eq!(contract.get_chain_signer(ChainType::SOL), user_dave());
+    assert_eq!(contract.get_chain_signer(ChainType::ETH), mpc_contract());
+
+    proven_sol_take(&mut contract, &mut context);
+    assert_eq!(signer_receivers(), vec![user_dave()]);
+}
+
+#[test]
+fn test_withdraw_uses_chain_signer_and_reset_falls_back() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_chain_signer(ChainType::ETH, Some(user_charlie()));
+
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!(signer_receivers(), vec![user_charlie()]);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(0)).build());
+    contract.set_chain_signer(ChainType::ETH, None);
+    assert_eq!(contract.get_chain_signer(ChainType::ETH), mpc_contract());
+}
+
+// ============================================================================
+// 30. SIGNATURE SCHEMES
+// ============================================================================
+
+/// JSON args of every `sign` call created in the current context.
+fn sign_request_args() -> Vec<near_sdk::serde_json::Value> {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .filter_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, args, .. } if method_name == b"sign" => {
+                Some(near_sdk::serde_json::from_slice(&args).unwrap())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn signature_event() -> near_sdk::serde_json::Value {
+    let log = near_sdk::test_utils::get_logs()
+        .into_iter()
+        .find_map(|l| l.strip_prefix("EVENT_JSON:").map(str::to_string))
+        .expect("No signature event");
+    near_sdk::serde_json::from_str(&log).unwrap()
+}
+
+#[test]
+fn test_batch_match_signs_with_ecdsa() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let matches = bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]);
+    let eth_payload = hex::encode(matches[1].payload);
+    contract.batch_match_intents(matches).unwrap();
+
+    let args = sign_request_args();
+    assert_eq!(args[0]["request"]["domain_id"], 0);
+    assert_eq!(args[1]["request"]["payload_v2"]["Ecdsa"], eth_payload.as_str());
+    assert_eq!(args[1]["request"]["domain_id"], 0);
+}
+
+#[test]
+fn test_proven_sol_take_signs_with_eddsa() {
+    let (mut contract, mut context) = new_contract();
+    proven_sol_take(&mut contract, &mut context);
+    let args = sign_request_args();
+    assert_eq!(args[0]["request"]["payload_v2"]["Eddsa"], hex::encode([0u8; 32]));
+    assert_eq!(args[0]["request"]["domain_id"], 1);
+}
+
+#[test]
+fn test_on_signed_secp256k1_event() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+
+    let event = signature_event();
+    assert_eq!(event["scheme"], "Secp256k1");
+    assert_eq!(event["big_r"], "mock_r");
+    assert_eq!(event["s"], "mock_s");
+    assert_eq!(event["recovery_id"], 1);
+    assert!(event.get("signature").is_none());
+}
+
+#[test]
+fn test_on_signed_ed25519_event() {
+    let (mut contract, mut context) = new_contract();
+    proven_sol_take(&mut contract, &mut context);
+
+    let result = contract.on_signed(SignTarget::SubIntent(0), ChainType::SOL, vec![0u8; 32], "default/path".to_string(), Ok(mock_ed25519_sig()));
+    assert_eq!(result, "Success");
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+
+    let event = signature_event();
+    assert_eq!(event["scheme"], "Ed25519");
+    assert_eq!(event["signature"], hex::encode([7u8; 64]).as_str());
+    assert!(event.get("big_r").is_none());
+    assert!(event.get("recovery_id").is_none());
+    assert_eq!(
+        contract.get_signature(SignTarget::SubIntent(0)).unwrap().signature,
+        mock_ed25519_sig()
+    );
+}
+
+#[test]
+fn test_sign_result_parses_signer_responses() {
+    let secp: SignResult = near_sdk::serde_json::from_str(
+        r#"{"scheme":"Secp256k1","big_r":{"affine_point":"02ab"},"s":{"scalar":"cd"},"recovery_id":0}"#,
+    )
+    .unwrap();
+    assert_eq!(secp.scheme(), SignatureScheme::Secp256k1);
+
+    let sig: Vec<u8> = (0..64).collect();
+    let ed: SignResult = near_sdk::serde_json::from_str(&format!(
+        r#"{{"scheme":"Ed25519","signature":{:?}}}"#,
+        sig
+    ))
+    .unwrap();
+    assert_eq!(ed, SignResult::Ed25519 { signature: sig });
+}
+
+// ============================================================================
+// 31. WITHDRAWAL PAYLOAD BINDING
+// ============================================================================
+
+fn alice_with_eth(amount: u128) -> (Orderbook, VMContextBuilder) {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", amount);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    (contract, context)
+}
+
+#[test]
+fn test_withdraw_signs_hash_of_submitted_tx() {
+    let (mut contract, _) = alice_with_eth(100);
+    let tx = eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, 60, &[]);
+    contract.withdraw(
+        "ETH".to_string(), u(60), ETH_DEST.to_string(), tx.clone(),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap();
+
+    let expected = hex::encode(env::keccak256_array(hex::decode(&tx[2..]).unwrap()));
+    assert_eq!(sign_request_args()[0]["request"]["payload_v2"]["Ecdsa"], expected.as_str());
+
+    let wd = contract.get_pending_withdrawal(U64(0)).unwrap();
+    assert_eq!(wd.amount, 60);
+    assert_eq!(wd.destination, ETH_DEST);
+    assert_eq!(wd.chain_type, ChainType::ETH);
+    assert_eq!(wd.nonce, 0);
+    assert_eq!(wd.payload, expected);
+    assert_eq!(contract.get_withdrawal_nonce(user_alice()), U64(1));
+}
+
+#[test]
+fn test_withdraw_tx_value_above_amount_fails() {
+    let (mut contract, _) = alice_with_eth(100);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(1), ETH_DEST.to_string(), eth_tx(1_000_000_000_000_000_000),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransaction { reason: "value does not match withdrawal amount".to_string() });
+}
+
+#[test]
+fn test_withdraw_tx_recipient_mismatch_fails() {
+    let (mut contract, _) = alice_with_eth(100);
+    let tx = eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, "0x00000000000000000000000000000000000000bb", 50, &[]);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), tx,
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransaction { reason: "recipient does not match destination".to_string() });
+}
+
+#[test]
+fn test_withdraw_tx_with_calldata_fails() {
+    let (mut contract, _) = alice_with_eth(100);
+    // ERC-20 transfer selector: would move tokens instead of the checked value.
+    let tx = eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, 50, &[0xa9, 0x05, 0x9c, 0xbb]);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), tx,
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransaction { reason: "data must be empty".to_string() });
+}
+
+#[test]
+fn test_withdraw_tx_wrong_chain_id_fails() {
+    let (mut contract, _) = alice_with_eth(100);
+    let tx = eth_tx_with(1, 0, ETH_DEST, 50, &[]);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), tx,
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransaction { reason: "chain id does not match".to_string() });
+}
+
+#[test]
+fn test_owner_sets_eth_chain_id() {
+    let (mut contract, mut context) = alice_with_eth(100);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_eth_chain_id(1);
+    assert_eq!(contract.get_config().eth_chain_id, 1);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx_with(1, 0, ETH_DEST, 50, &[]),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap();
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(50));
+}
+
+#[test]
+fn test_withdraw_legacy_tx_fails() {
+    let (mut contract, _) = alice_with_eth(100);
+    let tx = eth_tx(50).replacen("0x02", "0x01", 1);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), tx,
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::InvalidTransaction {
+            reason: "malformed tx: only EIP-1559 (type 2) transactions are supported".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_withdraw_truncated_tx_fails() {
+    let (mut contract, _) = alice_with_eth(100);
+    let tx = eth_tx(50);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), tx[..tx.len() - 4].to_string(),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransaction { reason: "malformed tx: truncated RLP item".to_string() });
+    // Nothing was deducted
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+}
+
+#[test]
+fn test_withdraw_other_asset_on_eth_fails() {
+    let (mut contract, mut context) = alice_with_eth(100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "USDC", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.withdraw(
+        "USDC".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::UnsupportedWithdrawal { asset: "USDC".to_string(), chain_type: ChainType::ETH });
+}
+
+#[test]
+fn test_withdraw_sol_signs_transfer_message() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100_000);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let message = sol_transfer(SOL_DEST, 50_000);
+    contract.withdraw(
+        "SOL".to_string(), u(50_000), SOL_DEST.to_string(), message.clone(),
+        own_path(&ChainType::SOL), ChainType::SOL, SignatureScheme::Ed25519,
+    ).unwrap();
+
+    // Ed25519 signs the message itself, and its signature fee is charged in SOL
+    assert_eq!(sign_request_args()[0]["request"]["payload_v2"]["Eddsa"], message.as_str());
+    let wd = contract.get_pending_withdrawal(U64(0)).unwrap();
+    assert_eq!(wd.payload, message);
+    assert_eq!(wd.gas_fee, 5_000);
+    assert_eq!(wd.nonce, 0);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(45_000));
+    assert_eq!(contract.get_withdrawal_nonce(user_alice()), U64(0));
+}
+
+#[test]
+fn test_withdraw_sol_message_must_pay_destination_amount() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100_000);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let other = "11111111111111111111111111111112";
+    for (message, reason) in [
+        (sol_transfer(SOL_DEST, 90_000), "value does not match withdrawal amount"),
+        (sol_transfer(other, 50_000), "recipient does not match destination"),
+        ("00".to_string(), "malformed tx: expected a single signer"),
+    ] {
+        let error = contract.withdraw(
+            "SOL".to_string(), u(50_000), SOL_DEST.to_string(), message,
+            own_path(&ChainType::SOL), ChainType::SOL, SignatureScheme::Ed25519,
+        ).unwrap_err();
+        assert_eq!(error, OrderbookError::InvalidTransaction { reason: reason.to_string() });
+    }
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(100_000));
+}
+
+#[test]
+fn test_withdraw_btc_signs_sighash_and_charges_fee() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 1_000);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let psbt = btc_psbt(5_000, &[(&btc_dest_script(), 600), (&BTC_INPUT_SCRIPT, 4_300)]);
+    contract.withdraw(
+        "BTC".to_string(), u(600), BTC_DEST.to_string(), psbt.clone(),
+        own_path(&ChainType::BTC), ChainType::BTC, SignatureScheme::Secp256k1,
+    ).unwrap();
+
+    let expected = crate::btc::parse_withdrawal_psbt(&hex::decode(&psbt).unwrap()).unwrap().sighash;
+    assert_eq!(sign_request_args()[0]["request"]["payload_v2"]["Ecdsa"], hex::encode(expected).as_str());
+    let wd = contract.get_pending_withdrawal(U64(0)).unwrap();
+    assert_eq!(wd.gas_fee, 100);
+    // The 100 sats the input leaves to miners come out of Alice's balance
+    assert_eq!(contract.get_balance(user_alice(), "BTC".to_string()), u(300));
+
+    // A failed signature refunds the fee with the amount
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(
+        SignTarget::Withdrawal(0), ChainType::BTC, expected.to_vec(), own_path(&ChainType::BTC),
+        Err(PromiseError::Failed),
+    );
+    assert_eq!(contract.get_balance(user_alice(), "BTC".to_string()), u(1_000));
+}
+
+#[test]
+fn test_withdraw_btc_outputs_must_pay_destination_and_change() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 10_000);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let dest = btc_dest_script();
+    let elsewhere = crate::btc::address_script("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+    for (psbt, reason) in [
+        (btc_psbt(5_000, &[(&dest, 700)]), "value does not match withdrawal amount"),
+        (btc_psbt(5_000, &[(&elsewhere, 600)]), "recipient does not match destination"),
+        (btc_psbt(5_000, &[(&dest, 600), (&elsewhere, 4_300)]), "change must return to the spent input"),
+        (btc_psbt(500, &[(&dest, 600)]), "outputs exceed the input"),
+        (btc_psbt(5_000, &[]), "expected a payment and at most one change output"),
+        ("00".to_string(), "malformed tx: truncated"),
+    ] {
+        let error = contract.withdraw(
+            "BTC".to_string(), u(600), BTC_DEST.to_string(), psbt,
+            own_path(&ChainType::BTC), ChainType::BTC, SignatureScheme::Secp256k1,
+        ).unwrap_err();
+        assert_eq!(error, OrderbookError::InvalidTransaction { reason: reason.to_string() });
+    }
+    assert_eq!(contract.get_balance(user_alice(), "BTC".to_string()), u(10_000));
+}
+
+#[test]
+fn test_btc_sighash_matches_bip143_vector() {
+    // BIP143 "Native P2WPKH" example: input 1 spends 6 BTC
+    let tx = hex::decode(
+        "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc\
+         89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db\
+         99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000",
+    )
+    .unwrap();
+    let tx = crate::btc::decode_unsigned(&tx).unwrap();
+    let key_hash: [u8; 20] = hex::decode("1d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap().try_into().unwrap();
+    assert_eq!(
+        hex::encode(crate::btc::bip143_sighash(&tx, 1, &key_hash, 600_000_000)),
+        "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+    );
+}
+
+#[test]
+fn test_withdraw_asset_on_another_chain_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 1_000);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.withdraw(
+        "BTC".to_string(), u(600), SOL_DEST.to_string(), sol_transfer(SOL_DEST, 600),
+        own_path(&ChainType::SOL), ChainType::SOL, SignatureScheme::Ed25519,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::UnsupportedWithdrawal { asset: "BTC".to_string(), chain_type: ChainType::SOL });
+}
+
+/// Alice holds 100 USDC, registered as an ERC-20 token, and 1 ETH for gas.
+fn alice_with_usdc() -> (Orderbook, VMContextBuilder) {
+    let (mut contract, mut context) = alice_with_eth(1_000_000_000_000_000_000);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_token_contract("USDC".to_string(), Some(USDC_CONTRACT.to_string()));
+    owner_deposit(&mut contract, &mut context, &user_alice(), "USDC", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    (contract, context)
+}
+
+fn usdc_transfer_tx(amount: u128) -> String {
+    let data = crate::eth::erc20_transfer_data(&crate::eth::parse_address(ETH_DEST), amount);
+    eth_tx_priced(DEFAULT_ETH_CHAIN_ID, 0, USDC_CONTRACT, 0, &data, 1_000_000_000)
+}
+
+#[test]
+fn test_withdraw_erc20_signs_token_transfer() {
+    let (mut contract, _) = alice_with_usdc();
+    let tx = usdc_transfer_tx(40);
+    contract.withdraw(
+        "USDC".to_string(), u(40), ETH_DEST.to_string(), tx.clone(),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap();
+
+    let expected = hex::encode(env::keccak256_array(hex::decode(&tx[2..]).unwrap()));
+    assert_eq!(sign_request_args()[0]["request"]["payload_v2"]["Ecdsa"], expected.as_str());
+    assert_eq!(contract.get_balance(user_alice(), "USDC".to_string()), u(60));
+    // Gas is paid in ETH
+    let gas_fee = 21_000 * 1_000_000_000;
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(1_000_000_000_000_000_000 - gas_fee));
+    assert_eq!(contract.get_withdrawal(U64(0)).unwrap().gas_fee, u(gas_fee));
+}
+
+#[test]
+fn test_withdraw_erc20_rejects_native_transfer() {
+    let (mut contract, _) = alice_with_usdc();
+    let error = contract.withdraw(
+        "USDC".to_string(), u(40), ETH_DEST.to_string(), eth_tx(40),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransaction { reason: "recipient is not the token contract".to_string() });
+}
+
+#[test]
+fn test_withdraw_erc20_rejects_other_amount_in_data() {
+    let (mut contract, _) = alice_with_usdc();
+    let error = contract.withdraw(
+        "USDC".to_string(), u(40), ETH_DEST.to_string(), usdc_transfer_tx(41),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::InvalidTransaction { reason: "data is not a transfer of the withdrawal amount to destination".to_string() }
+    );
+}
+
+#[test]
+fn test_withdraw_erc20_needs_eth_for_gas() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_token_contract("USDC".to_string(), Some(USDC_CONTRACT.to_string()));
+    owner_deposit(&mut contract, &mut context, &user_alice(), "USDC", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.withdraw(
+        "USDC".to_string(), u(40), ETH_DEST.to_string(), usdc_transfer_tx(40),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::InsufficientBalance { asset: "ETH".to_string(), have: 0, need: 21_000 * 1_000_000_000 }
+    );
+}
+
+#[test]
+fn test_withdraw_debits_and_refunds_gas_fee() {
+    let (mut contract, mut context) = alice_with_eth(1_000_000_000_000_000_000);
+    let tx = eth_tx_priced(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, 100, &[], 2_000_000_000);
+    contract.withdraw(
+        "ETH".to_string(), u(100), ETH_DEST.to_string(), tx,
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap();
+    let gas_fee = 21_000 * 2_000_000_000;
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(1_000_000_000_000_000_000 - 100 - gas_fee));
+    assert_eq!(contract.get_pending_withdrawal(U64(0)).unwrap().gas_fee, gas_fee);
+    assert_eq!(contract.get_withdrawal_nonce(user_alice()), U64(1));
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(1_000_000_000_000_000_000));
+    assert_eq!(contract.get_withdrawal_nonce(user_alice()), U64(0));
+}
+
+#[test]
+fn test_withdraw_needs_balance_for_amount_and_gas() {
+    let (mut contract, _) = alice_with_eth(100);
+    let tx = eth_tx_priced(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, 100, &[], 1);
+    let error = contract.withdraw(
+        "ETH".to_string(), u(100), ETH_DEST.to_string(), tx,
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientBalance { asset: "ETH".to_string(), have: 100, need: 21_100 });
+}
+
+#[test]
+fn test_withdraw_gas_fee_above_cap_fails() {
+    let (mut contract, mut context) = alice_with_eth(1_000_000_000_000_000_000);
+    // 21_000 gas at 1_000 gwei is 0.021 ETH, above the 0.01 ETH default
+    let tx = eth_tx_priced(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, 100, &[], 1_000_000_000_000);
+    let item = WithdrawItem { unsigned_tx: tx, ..withdraw_item(100, 0) };
+    let error = contract
+        .withdraw(item.asset.clone(), item.amount, item.destination.clone(), item.unsigned_tx.clone(), item.path.clone(), item.chain_type.clone(), item.scheme)
+        .unwrap_err();
+    assert_eq!(error, OrderbookError::WithdrawalGasFeeTooHigh { max: DEFAULT_MAX_WITHDRAWAL_GAS_FEE });
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_max_withdrawal_gas_fee(u(21_000 * 1_000_000_000_000));
+    assert_eq!(contract.get_config().max_withdrawal_gas_fee, u(21_000 * 1_000_000_000_000));
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw(item.asset, item.amount, item.destination, item.unsigned_tx, item.path, item.chain_type, item.scheme).unwrap();
+}
+
+#[test]
+fn test_withdraw_requires_next_nonce() {
+    let (mut contract, _) = alice_with_eth(100);
+    let item = withdraw_item(30, 1);
+    let error = contract
+        .withdraw(item.asset, item.amount, item.destination, item.unsigned_tx, item.path, item.chain_type, item.scheme)
+        .unwrap_err();
+    assert_eq!(error, OrderbookError::WrongWithdrawalNonce { expected: 0, got: 1 });
+
+    let item = withdraw_item(30, 0);
+    contract.withdraw(item.asset, item.amount, item.destination, item.unsigned_tx, item.path, item.chain_type, item.scheme).unwrap();
+    // The same nonce cannot be signed twice
+    let item = withdraw_item(30, 0);
+    let error = contract
+        .withdraw(item.asset, item.amount, item.destination, item.unsigned_tx, item.path, item.chain_type, item.scheme)
+        .unwrap_err();
+    assert_eq!(error, OrderbookError::WrongWithdrawalNonce { expected: 1, got: 0 });
+}
+
+// ============================================================================
+// 32. DEPOSIT REPLAY PROTECTION
+// ============================================================================
+
+fn credit_deposit(contract: &mut Orderbook, chain_type: ChainType, tx_hash: &str) -> DepositOutcome {
+    contract.on_mpc_deposit_verified(
+        user_alice(), chain_type, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        tx_hash.to_string(), verified(true),
+    )
+}
+
+#[test]
+fn test_deposit_replay_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    assert!(credit_deposit(&mut contract, ChainType::SOL, "sol-tx-1").credited);
+    assert!(near_sdk::test_utils::get_logs().iter().any(|l| l.ends_with("tx_hash=sol-tx-1")));
+    assert!(contract.is_deposit_consumed(ChainType::SOL, "sol-tx-1".to_string()));
+
+    let replay = credit_deposit(&mut contract, ChainType::SOL, "sol-tx-1");
+    assert_eq!(replay.reason, Some("Deposit tx sol-tx-1 already credited".to_string()), "Replayed deposit should be rejected");
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(500));
+}
+
+#[test]
+#[should_panic(expected = "Deposit tx sol-tx-1 already credited")]
+fn test_verify_mpc_deposit_rejects_consumed_tx() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    credit_deposit(&mut contract, ChainType::SOL, "sol-tx-1");
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mpc-sol-addr".to_string());
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        vec![1], "sol-tx-1".to_string(),
+    );
+}
+
+#[test]
+fn test_deposit_tx_hash_is_scoped_per_chain() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    credit_deposit(&mut contract, ChainType::SOL, "same-hash");
+    credit_deposit(&mut contract, ChainType::ETH, "same-hash");
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(1000));
+    assert!(!contract.is_deposit_consumed(ChainType::BTC, "same-hash".to_string()));
+}
+
+// ============================================================================
+// 33. DEPOSIT ADDRESS REGISTRY
+// ============================================================================
+
+fn verify_alice_sol_deposit(contract: &mut Orderbook, recipient: &str) {
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        recipient.to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        vec![1], "sol-tx-1".to_string(),
+    );
+}
+
+#[test]
+fn test_verify_mpc_deposit_to_registered_address() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "alice-sol-addr".to_string());
+    assert_eq!(
+        contract.get_deposit_address(user_alice(), ChainType::SOL),
+        Some("alice-sol-addr".to_string())
+    );
+    assert_eq!(contract.get_deposit_address(user_alice(), ChainType::ETH), None);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    verify_alice_sol_deposit(&mut contract, "alice-sol-addr");
+    let receivers: Vec<AccountId> = near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .map(|r| r.receiver_id)
+        .collect();
+    assert!(receivers.contains(&light_client_contract()));
+}
+
+#[test]
+#[should_panic(expected = "Recipient does not match registered deposit address")]
+fn test_verify_mpc_deposit_to_other_address_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "alice-sol-addr".to_string());
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    verify_alice_sol_deposit(&mut contract, "someone-elses-addr");
+}
+
+#[test]
+#[should_panic(expected = "No deposit address registered for")]
+fn test_verify_mpc_deposit_unregistered_user_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::ETH, "0xalice".to_string());
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    verify_alice_sol_deposit(&mut contract, "alice-sol-addr");
+}
+
+#[test]
+#[should_panic(expected = "Only owner can register deposit addresses")]
+fn test_register_deposit_address_not_owner() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mine".to_string());
+}
+
+// ============================================================================
+// 34. TAKER ESCROW
+// ============================================================================
+
+/// Alice offers 300 SOL for 100 ETH; Bob holds 100 ETH to take with.
+fn setup_take(contract: &mut Orderbook, context: &mut VMContextBuilder) -> U64 {
+    owner_deposit(contract, context, &user_alice(), "SOL", 300);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent("SOL".to_string(), u(300), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap()
+}
+
+/// Alice sells 300 ETH for 100 SOL; Bob holds the SOL to escrow a take.
+fn setup_eth_take(contract: &mut Orderbook, context: &mut VMContextBuilder) -> U64 {
+    owner_deposit(contract, context, &user_alice(), "ETH", 300);
+    owner_deposit(contract, context, &solver_bob(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent("ETH".to_string(), u(300), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap()
+}
+
+/// Bob submits his escrowed take with an ETH transition paying the fill to Alice.
+fn submit_escrowed_take(contract: &mut Orderbook, sub: U64) -> Result<Promise, OrderbookError> {
+    let tx = settlement_tx(contract.get_sub_intent(sub).unwrap().amount, 0, &contract.get_next_take_memo(sub));
+    contract.submit_payment_proof(
+        sub, vec![], env::keccak256_array(&tx), Some(tx), "default/path".to_string(),
+        ChainType::SOL, ChainType::ETH, String::new(), format!("sub:{}", sub.0),
+        ETH_DEST.to_string(),
+        "pay-tx".to_string(),
+    )
+}
+
+#[test]
+fn test_take_intent_locks_escrow_rounded_up() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(10)).unwrap();
+
+    // 10 * 100 / 300 = 3.33.., rounded up in the maker's favour
+    assert_eq!(contract.get_sub_intent(sub).unwrap().escrow, 4);
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(96));
+}
+
+#[test]
+fn test_take_intent_without_counter_asset_fails() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "BTC", 1);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let error = contract.take_intent(id, u(300)).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientBalance { asset: "ETH".to_string(), have: 0, need: 100 });
+}
+
+#[test]
+fn test_escrowed_take_settles_without_payment_proof() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_eth_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(300)).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let memo = contract.get_next_take_memo(sub);
+    let _ = submit_escrowed_take(&mut contract, sub).unwrap();
+    // Straight to the signer: the light client is not consulted
+    assert_eq!(signer_receivers(), vec![mpc_contract()]);
+    assert_eq!(contract.get_sub_intent(sub).unwrap().status, SubIntentStatus::Verifying);
+    assert_eq!(contract.get_transition_expectation(sub).unwrap().expected_memo, memo);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(0)).build());
+    let payload = env::keccak256_array(settlement_tx(300, 0, &memo));
+    contract.on_signed(SignTarget::SubIntent(sub.0), ChainType::ETH, payload.to_vec(), "default/path".to_string(), Ok(mock_sig()));
+    // Escrow is held until the transition is proven
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+
+    contract.on_transition_verified(sub, "eth-tx".to_string(), transition_verified(true));
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(100));
+    assert_eq!(contract.get_balance(solver_bob(), "SOL".to_string()), u(0));
+    assert_eq!(contract.get_sub_intent(sub).unwrap().escrow, 0);
+}
+
+#[test]
+fn test_escrowed_take_requires_a_transition_tx_paying_the_fill() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_eth_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(300)).unwrap();
+    let submit = |contract: &mut Orderbook, payload: [u8; 32], tx: Option<Vec<u8>>, chain: ChainType, recipient: &str| {
+        contract.submit_payment_proof(
+            sub, vec![], payload, tx, "default/path".to_string(), ChainType::ETH, chain,
+            String::new(), format!("sub:{}", sub.0), recipient.to_string(), "pay-tx".to_string(),
+        ).err()
+    };
+
+    let error = submit(&mut contract, [4u8; 32], None, ChainType::ETH, ETH_DEST);
+    assert_eq!(error, Some(OrderbookError::TransitionPayloadMismatch { intent_id: id.0 }));
+    let memo = contract.get_next_take_memo(sub);
+    let short = settlement_tx(299, 0, &memo);
+    let error = submit(&mut contract, [4u8; 32], Some(short.clone()), ChainType::ETH, ETH_DEST);
+    assert_eq!(error, Some(OrderbookError::TransitionPayloadMismatch { intent_id: id.0 }));
+    let error = submit(&mut contract, env::keccak256_array(&short), Some(short), ChainType::ETH, ETH_DEST);
+    assert_eq!(error, Some(OrderbookError::InvalidTransitionTx { reason: "does not pay the expected recipient, asset and amount".to_string() }));
+    let unmarked = settlement_tx(300, 0, "");
+    let error = submit(&mut contract, env::keccak256_array(&unmarked), Some(unmarked), ChainType::ETH, ETH_DEST);
+    assert_eq!(error, Some(OrderbookError::InvalidTransitionTx { reason: format!("does not carry the memo {}", memo) }));
+    let error = submit(&mut contract, [4u8; 32], None, ChainType::SOL, SOL_DEST);
+    assert_eq!(error, Some(OrderbookError::UnsupportedTransitionChain { chain_type: ChainType::SOL }));
+    assert_eq!(contract.get_sub_intent(sub).unwrap().status, SubIntentStatus::Taken);
+}
+
+#[test]
+fn test_escrowed_take_of_sol_can_only_be_cancelled() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(300)).unwrap();
+    let submit = |contract: &mut Orderbook, payload: [u8; 32], tx: Option<Vec<u8>>, chain: ChainType, recipient: &str| {
+        contract.submit_payment_proof(
+            sub, vec![], payload, tx, "default/path".to_string(), ChainType::ETH, chain,
+            String::new(), format!("sub:{}", sub.0), recipient.to_string(), "pay-tx".to_string(),
+        ).err()
+    };
+
+    let error = submit(&mut contract, [4u8; 32], None, ChainType::SOL, SOL_DEST);
+    assert_eq!(error, Some(OrderbookError::UnsupportedTransitionChain { chain_type: ChainType::SOL }));
+    let tx = settlement_tx(300, 0, &contract.get_next_take_memo(sub));
+    let error = submit(&mut contract, env::keccak256_array(&tx), Some(tx), ChainType::ETH, ETH_DEST);
+    assert_eq!(error, Some(OrderbookError::InvalidTransitionTx { reason: "asset SOL is not on ETH".to_string() }));
+    assert_eq!(contract.get_sub_intent(sub).unwrap().status, SubIntentStatus::Taken);
+    assert!(sign_request_args().is_empty());
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(sub);
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(100));
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::Open);
+}
+
+#[test]
+fn test_cancel_sub_intent_returns_escrow_and_refills_parent() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(300)).unwrap();
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::Filled);
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(sub);
+    let sub = contract.get_sub_intent(sub).unwrap();
+    assert_eq!(sub.status, SubIntentStatus::Cancelled);
+    assert_eq!(sub.escrow, 0);
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(100));
+    let intent = contract.get_intent(id).unwrap();
+    assert_eq!(intent.status, IntentStatus::Open);
+    assert_eq!(intent.filled_amount, 0);
+}
+
+#[test]
+fn test_cancel_sub_intent_after_maker_cancelled_refunds_maker() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(150)).unwrap();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(150));
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(sub);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(300));
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(100));
+}
+
+#[test]
+#[should_panic(expected = "Only the taker can cancel the sub-intent")]
+fn test_cancel_sub_intent_not_taker_panics() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(sub);
+}
+
+#[test]
+#[should_panic(expected = "Sub-Intent is not in Taken state")]
+fn test_cancel_sub_intent_after_submission_panics() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_eth_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    let _ = submit_escrowed_take(&mut contract, sub).unwrap();
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(sub);
+}
+
+// ============================================================================
+// 35. SUB-INTENT EXPIRY
+// ============================================================================
+
+#[test]
+fn test_expire_sub_intent_restores_fill() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let kept = contract.take_intent(id, u(90)).unwrap();
+    let stale = contract.take_intent(id, u(210)).unwrap();
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::Filled);
+    assert_eq!(
+        contract.get_sub_intent(stale).unwrap().expires_at,
+        Some(1_000 + DEFAULT_SUB_INTENT_TIMEOUT_NS)
+    );
+
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_SUB_INTENT_TIMEOUT_NS)
+        .build()
+    );
+    contract.expire_sub_intent(stale);
+
+    let intent = contract.get_intent(id).unwrap();
+    assert_eq!(intent.filled_amount, 90);
+    assert_eq!(intent.status, IntentStatus::Open);
+    assert!(contract.get_sub_intent(stale).is_none());
+    assert_eq!(contract.get_sub_intent(kept).unwrap().status, SubIntentStatus::Taken);
+    assert_eq!(
+        contract.get_sub_intents_by_parent(id, u(0), 10).iter().map(|s| s.id).collect::<Vec<_>>(),
+        vec![kept.0]
+    );
+    // 210 SOL of the take escrowed 70 ETH, now back with the taker
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(70));
+    assert!(near_sdk::test_utils::get_logs().iter().any(|l| l.starts_with("SUB_INTENT_EXPIRED")));
+
+    let again = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.expire_sub_intent(stale);
+    }));
+    assert!(again.is_err(), "second expiry must fail");
+}
+
+#[test]
+fn test_expired_sub_intent_keeps_index_offsets() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let stale = contract.take_intent(id, u(30)).unwrap();
+    let second = contract.take_intent(id, u(30)).unwrap();
+    let third = contract.take_intent(id, u(30)).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_SUB_INTENT_TIMEOUT_NS)
+        .build()
+    );
+    contract.expire_sub_intent(stale);
+
+    // The deleted record is skipped, but later sub-intents keep their offsets
+    let page = contract.get_sub_intents_by_parent(id, u(2), 10);
+    assert_eq!(page.iter().map(|s| s.id).collect::<Vec<_>>(), vec![third.0]);
+    let page = contract.get_sub_intents_by_taker(solver_bob(), u(1), 1);
+    assert_eq!(page.iter().map(|s| s.id).collect::<Vec<_>>(), vec![second.0]);
+}
+
+#[test]
+#[should_panic(expected = "has not expired yet")]
+fn test_expire_sub_intent_before_timeout_panics() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    testing_env!(context.block_timestamp(DEFAULT_SUB_INTENT_TIMEOUT_NS).build());
+    contract.expire_sub_intent(sub);
+}
+
+#[test]
+#[should_panic(expected = "Sub-Intent is not in Taken state")]
+fn test_expire_submitted_sub_intent_panics() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_eth_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    let _ = submit_escrowed_take(&mut contract, sub).unwrap();
+    testing_env!(context.block_timestamp(DEFAULT_SUB_INTENT_TIMEOUT_NS * 2).build());
+    contract.expire_sub_intent(sub);
+}
+
+#[test]
+fn test_submit_expired_sub_intent_fails() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_sub_intent_timeout(500);
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    assert_eq!(contract.get_sub_intent(sub).unwrap().expires_at, Some(1_500));
+
+    testing_env!(context.block_timestamp(1_500).build());
+    let error = contract.submit_payment_proof(
+        sub, vec![], [4u8; 32], None, "default/path".to_string(),
+        ChainType::ETH, ChainType::SOL, String::new(), format!("sub:{}", sub.0),
+        SOL_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).err();
+    assert_eq!(error, Some(OrderbookError::SubIntentExpired { id: 0 }));
+}
+
+// ============================================================================
+// 36. TRANSITION DEADLINE & SOLVER BOND
+// ============================================================================
+
+/// Matched pair with sub-intent 0 signed at `signed_at`.
+fn setup_signed_pair(contract: &mut Orderbook, context: &mut VMContextBuilder, signed_at: u64) -> U64 {
+    let (id_a, _) = setup_matched_pair(contract, context);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .block_timestamp(signed_at)
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    id_a
+}
+
+fn transfers_to(account: &AccountId) -> Vec<u128> {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .filter(|r| &r.receiver_id == account)
+        .flat_map(|r| r.actions)
+        .filter_map(|a| match a {
+            near_sdk::mock::MockAction::Transfer { deposit, .. } => Some(deposit.as_yoctonear()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_batch_match_holds_bond_per_sub_intent() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().bond, DEFAULT_TRANSITION_BOND);
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().bond, DEFAULT_TRANSITION_BOND);
+    assert_eq!(contract.get_transition_expectation(U64(0)).unwrap().transition_deadline, None);
+}
+
+#[test]
+fn test_batch_match_without_bond_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_yoctonear(2 * DEFAULT_TRANSITION_BOND - 1))
+        .build()
+    );
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientDeposit { required: 2 * (DEFAULT_TRANSITION_BOND + DEFAULT_MIN_SIGN_DEPOSIT), attached: 2 * DEFAULT_TRANSITION_BOND - 1 });
+}
+
+#[test]
+fn test_signing_stamps_transition_deadline() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    assert_eq!(
+        contract.get_transition_expectation(U64(0)).unwrap().transition_deadline,
+        Some(1_000 + DEFAULT_TRANSITION_TIMEOUT_NS)
+    );
+}
+
+#[test]
+#[should_panic(expected = "Transition deadline not reached")]
+fn test_claim_transition_timeout_before_deadline_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_TRANSITION_TIMEOUT_NS - 1)
+        .build()
+    );
+    contract.claim_transition_timeout(U64(0));
+}
+
+#[test]
+#[should_panic(expected = "Sub-Intent has no transition deadline")]
+fn test_claim_transition_timeout_while_signing_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    // Still Verifying: no signature yet, so no deadline either
+    let mut sub = contract.sub_intents.get(&0).unwrap();
+    sub.status = SubIntentStatus::Settled;
+    contract.sub_intents.insert(&0, &sub);
+    testing_env!(context.block_timestamp(u64::MAX).build());
+    contract.claim_transition_timeout(U64(0));
+}
+
+#[test]
+fn test_claim_transition_timeout_slashes_bond_to_maker() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = setup_signed_pair(&mut contract, &mut context, 1_000);
+
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_TRANSITION_TIMEOUT_NS)
+        .build()
+    );
+    contract.claim_transition_timeout(U64(0));
+
+    assert_eq!(transfers_to(&user_alice()), vec![DEFAULT_TRANSITION_BOND]);
+    let sub = contract.get_sub_intent(U64(0)).unwrap();
+    assert_eq!(sub.status, SubIntentStatus::Failed);
+    assert_eq!(sub.bond, 0);
+    assert!(contract.get_transition_expectation(U64(0)).is_none());
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_none());
+    // The signed transaction may still land, so the fill stands
+    assert!(contract.get_escrow(U64(0)).is_some());
+    let intent = contract.get_intent(id_a).unwrap();
+    assert_eq!(intent.status, IntentStatus::Filled);
+    assert_eq!(intent.filled_amount, 100);
+    assert!(near_sdk::test_utils::get_logs()
+        .iter()
+        .any(|l| l.starts_with("TRANSITION_TIMEOUT_CLAIMED:sub_intent_id=0")));
+
+    let again = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.claim_transition_timeout(U64(0));
+    }));
+    assert!(again.is_err(), "second claim must fail");
+}
+
+#[test]
+fn test_claim_transition_timeout_leaves_maker_balance() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    let balances = |contract: &Orderbook| {
+        (
+            contract.get_balance(user_alice(), "WSOL".to_string()),
+            contract.get_balance(user_alice(), "ETH".to_string()),
+        )
+    };
+    let before = balances(&contract);
+
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_TRANSITION_TIMEOUT_NS)
+        .build()
+    );
+    contract.claim_transition_timeout(U64(0));
+
+    assert_eq!(balances(&contract), before);
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(0));
+}
+
+#[test]
+fn test_force_cancel_settles_timed_out_escrow() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_TRANSITION_TIMEOUT_NS)
+        .build()
+    );
+    contract.claim_transition_timeout(U64(0));
+
+    // Held proceeds keep the record from being cleaned up
+    testing_env!(context.block_timestamp(u64::MAX).build());
+    let cleanup = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.cleanup(vec![U64(0)], RecordKind::SubIntent);
+    }));
+    assert!(cleanup.is_err(), "held escrow must block cleanup");
+
+    // The owner confirms delivery and releases the proceeds to the maker
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.force_cancel_sub_intent(U64(0), false, "delivered".to_string());
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+    assert!(contract.get_escrow(U64(0)).is_none());
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Failed);
+    assert_eq!(contract.get_stats().sub_intents_failed, 1);
+}
+
+#[test]
+#[should_panic(expected = "Sub-Intent is not ready for transition verification")]
+fn test_verify_transition_after_timeout_claim_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(1_000 + DEFAULT_TRANSITION_TIMEOUT_NS)
+        .build()
+    );
+    contract.claim_transition_timeout(U64(0));
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = contract.verify_transition_completion(U64(0), vec![], "0xlate".to_string());
+}
+
+#[test]
+fn test_verified_transition_returns_bond_to_solver() {
+    let (mut contract, mut context) = new_contract();
+    setup_signed_pair(&mut contract, &mut context, 1_000);
+    contract.on_transition_verified(U64(0), "tx".to_string(), transition_verified(true));
+    // Solver is the owner account in setup_matched_pair
+    assert_eq!(transfers_to(&orderbook_contract()), vec![DEFAULT_TRANSITION_BOND]);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().bond, 0);
+}
+
+// ============================================================================
+// 37. SOLVER REGISTRY
+// ============================================================================
+
+fn register_solver(contract: &mut Orderbook, context: &mut VMContextBuilder, solver: &AccountId) {
+    register_storage(contract, context, solver);
+    testing_env!(context
+        .predecessor_account_id(solver.clone())
+        .attached_deposit(NearToken::from_yoctonear(DEFAULT_MIN_SOLVER_BOND))
+        .build()
+    );
+    contract.register_solver();
+    testing_env!(context.attached_deposit(NearToken::from_near(0)).build());
+}
+
+/// Two crossing intents matched by `solver`; returns the sub-intent ids.
+fn match_pair_as(
+    contract: &mut Orderbook,
+    context: &mut VMContextBuilder,
+    solver: &AccountId,
+) -> Result<Vec<U64>, OrderbookError> {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(solver.clone())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]))
+}
+
+#[test]
+fn test_register_solver_records_bond() {
+    let (mut contract, mut context) = new_contract();
+    register_solver(&mut contract, &mut context, &solver_bob());
+
+    let info = contract.get_solver(solver_bob()).unwrap();
+    assert_eq!(info.bond, u(DEFAULT_MIN_SOLVER_BOND));
+    assert_eq!(info.active_sub_intents, 0);
+    assert_eq!(contract.get_solvers(0, 10), vec![info]);
+    assert!(contract.get_solver(user_charlie()).is_none());
+}
+
+#[test]
+#[should_panic(expected = "Solver bond must be at least")]
+fn test_register_solver_below_min_bond_panics() {
+    let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &solver_bob());
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_yoctonear(DEFAULT_MIN_SOLVER_BOND - 1))
+        .build()
+    );
+    contract.register_solver();
+}
+
+#[test]
+fn test_batch_match_by_unregistered_solver_fails() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_solver_allowlist(true);
+    let error = match_pair_as(&mut contract, &mut context, &solver_bob()).unwrap_err();
+    assert_eq!(error, OrderbookError::SolverNotRegistered { account: solver_bob() });
+}
+
+#[test]
+fn test_registered_solver_matches_with_allowlist() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_solver_allowlist(true);
+    register_solver(&mut contract, &mut context, &solver_bob());
+
+    let subs = match_pair_as(&mut contract, &mut context, &solver_bob()).unwrap();
+    assert_eq!(subs.len(), 2);
+    assert_eq!(contract.get_solver(solver_bob()).unwrap().active_sub_intents, 2);
+}
+
+#[test]
+fn test_retry_settlement_after_unregister_fails() {
+    let (mut contract, mut context) = new_contract();
+    register_solver(&mut contract, &mut context, &solver_bob());
+    match_pair_as(&mut contract, &mut context, &solver_bob()).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    for id in 0..2 {
+        contract.on_signed(SignTarget::SubIntent(id), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    }
+    contract.set_solver_allowlist(true);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.unregister_solver();
+
+    let error = contract.retry_settlement(U64(0)).err();
+    assert_eq!(error, Some(OrderbookError::SolverNotRegistered { account: solver_bob() }));
+}
+
+#[test]
+#[should_panic(expected = "Solver has 2 pending settlements")]
+fn test_unregister_solver_with_pending_settlements_panics() {
+    let (mut contract, mut context) = new_contract();
+    register_solver(&mut contract, &mut context, &solver_bob());
+    match_pair_as(&mut contract, &mut context, &solver_bob()).unwrap();
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.unregister_solver();
+}
+
+#[test]
+fn test_unregister_solver_returns_bond_after_settlement() {
+    let (mut contract, mut context) = new_contract();
+    register_solver(&mut contract, &mut context, &solver_bob());
+    match_pair_as(&mut contract, &mut context, &solver_bob()).unwrap();
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_solver(solver_bob()).unwrap().active_sub_intents, 0);
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.unregister_solver();
+    assert!(contract.get_solver(solver_bob()).is_none());
+    assert_eq!(transfers_to(&solver_bob()), vec![DEFAULT_MIN_SOLVER_BOND]);
+}
+
+// ============================================================================
+// 38. PROTOCOL FEE
+// ============================================================================
+
+/// Alice sells 1000 SOL for 999 ETH, Bob the reverse; both fully matched.
+fn setup_fee_match(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 1000);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 999);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(1000), "ETH".to_string(), u(999), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(999), "WSOL".to_string(), u(1000), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 1000, 999), mp(id_b, 999, 1000)])).unwrap();
+}
+
+#[test]
+fn test_fee_rounds_down_and_accrues_to_collector() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(30);
+    contract.set_fee_collector(user_dave());
+    setup_fee_match(&mut contract, &mut context);
+
+    // 999 * 30 / 10_000 = 2.997 -> 2; 1000 * 30 / 10_000 = 3
+    let escrow_a = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow_a.amount, escrow_a.fee), (997, 2));
+    let escrow_b = contract.get_escrow(U64(1)).unwrap();
+    assert_eq!((escrow_b.amount, escrow_b.fee), (997, 3));
+    // Nothing accrues until the transitions are proven
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(0));
+
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(997));
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(997));
+    assert_eq!(contract.get_balance(user_dave(), "ETH".to_string()), u(2));
+    assert_eq!(contract.get_balance(user_dave(), "WSOL".to_string()), u(3));
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(2));
+    assert_eq!(contract.get_accrued_fees("WSOL".to_string()), u(3));
+}
+
+#[test]
+fn test_fee_on_tiny_fill_is_zero() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(MAX_FEE_BPS);
+    setup_matched_pair(&mut contract, &mut context);
+    // 100 * 100 / 10_000 = 1; never more than get_amount
+    let escrow = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow.amount, escrow.fee), (99, 1));
+}
+
+#[test]
+fn test_zero_fee_leaves_proceeds_untouched() {
+    let (mut contract, mut context) = new_contract();
+    setup_fee_match(&mut contract, &mut context);
+    let escrow = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow.amount, escrow.fee), (999, 0));
+
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(999));
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(0));
+}
+
+#[test]
+fn test_unwound_fill_pays_no_fee() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_timestamp(1_000).build());
+    contract.set_fee_bps(30);
+    setup_fee_match(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_ESCROW_TIMEOUT_NS).build());
+    contract.claim_escrow_timeout(U64(0)).unwrap();
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(0));
+    assert_eq!(contract.get_balance(orderbook_contract(), "ETH".to_string()), u(0));
+}
+
+#[test]
+#[should_panic(expected = "Fee exceeds maximum of 100 bps")]
+fn test_set_fee_above_cap_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(MAX_FEE_BPS + 1);
+}
+
+#[test]
+#[should_panic(expected = "Only owner can change config")]
+fn test_set_fee_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_fee_bps(10);
+}
+
+// ============================================================================
+// 39. SOLVER SURPLUS
+// ============================================================================
+
+#[test]
+fn test_batch_surplus_credited_to_solver() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 100);
+    // Alice asks 1:1; Charlie only wants 90 WSOL for his 100 ETH
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_c = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(90), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_c, 100, 90)])).unwrap();
+
+    // Held until both fills are delivered
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(0));
+    assert_eq!(contract.get_last_batch_surplus(), vec![("WSOL".to_string(), u(10))]);
+    assert!(near_sdk::test_utils::get_logs()
+        .contains(&format!("SOLVER_SURPLUS_HELD:batch_id=0,solver={},asset=WSOL,amount=10", solver_bob())));
+
+    release_escrows(&mut contract, &mut context, &[0]);
+    assert_eq!(contract.get_held_surplus(U64(0)).unwrap().outstanding, 1);
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(0));
+
+    release_escrows(&mut contract, &mut context, &[1]);
+    assert!(contract.get_held_surplus(U64(0)).is_none());
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(10));
+    assert_eq!(contract.get_balance(solver_bob(), "ETH".to_string()), u(0));
+    assert!(near_sdk::test_utils::get_logs()
+        .contains(&format!("SOLVER_SURPLUS:solver={},asset=WSOL,amount=10", solver_bob())));
+}
+
+/// The batch of `test_batch_surplus_credited_to_solver`: Bob keeps 10 SOL.
+fn setup_surplus_batch(contract: &mut Orderbook, context: &mut VMContextBuilder) -> (U64, U64) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_c = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(90), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 100, 100), mp(id_c, 100, 90)])).unwrap();
+    (id_a, id_c)
+}
+
+#[test]
+fn test_unwound_batch_forfeits_surplus() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_surplus_batch(&mut contract, &mut context);
+    release_escrows(&mut contract, &mut context, &[1]);
+
+    // Alice's fill never signs and is unwound after the escrow timeout
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    let deadline = contract.get_escrow(U64(0)).unwrap().claimable_after;
+    testing_env!(context.predecessor_account_id(user_dave()).block_timestamp(deadline).build());
+    contract.claim_escrow_timeout(U64(0)).unwrap();
+
+    assert!(contract.get_held_surplus(U64(0)).is_none());
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 0);
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(0));
+    assert!(near_sdk::test_utils::get_logs().contains(&"SOLVER_SURPLUS_FORFEITED:batch_id=0,sub_intent_id=0".to_string()));
+}
+
+#[test]
+fn test_force_cancel_with_refund_forfeits_surplus() {
+    let (mut contract, mut context) = new_contract();
+    setup_surplus_batch(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.force_cancel_sub_intent(U64(1), true, "stuck".to_string());
+    release_escrows(&mut contract, &mut context, &[0]);
+
+    assert!(contract.get_held_surplus(U64(0)).is_none());
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(0));
+}
+
+#[test]
+fn test_force_cancel_without_refund_counts_as_delivered() {
+    let (mut contract, mut context) = new_contract();
+    setup_surplus_batch(&mut contract, &mut context);
+    release_escrows(&mut contract, &mut context, &[0]);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.force_cancel_sub_intent(U64(1), false, "delivered off-chain".to_string());
+
+    assert!(contract.get_held_surplus(U64(0)).is_none());
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(10));
+}
+
+#[test]
+fn test_balanced_batch_has_no_surplus() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    assert!(contract.get_last_batch_surplus().is_empty());
+    assert!(!near_sdk::test_utils::get_logs().iter().any(|l| l.starts_with("SOLVER_SURPLUS")));
+}
+
+// ============================================================================
+// 40. FILL FLAGS
+// ============================================================================
+
+/// Alice offers 300 SOL for 100 ETH with the given fill flags; Bob holds 100 ETH.
+fn setup_flagged(contract: &mut Orderbook, context: &mut VMContextBuilder, min_fill: u128, aon: bool) -> U64 {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 300);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent("WSOL".to_string(), u(300), "ETH".to_string(), u(100), None, Some(u(min_fill)), Some(aon), None, None, None).unwrap()
+}
+
+#[test]
+fn test_fill_flags_exposed_in_open_intents() {
+    let (mut contract, mut context) = new_contract();
+    setup_flagged(&mut contract, &mut context, 30, true);
+    let open = contract.get_open_intents(u(0), 10);
+    assert_eq!(open[0].min_fill_amount, 30);
+    assert!(open[0].all_or_nothing);
+}
+
+#[test]
+fn test_partial_take_of_aon_intent_fails() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_flagged(&mut contract, &mut context, 0, true);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.take_intent(id, u(150)).unwrap_err();
+    assert_eq!(error, OrderbookError::AllOrNothing { intent_id: 0, required: 300 });
+}
+
+#[test]
+fn test_partial_batch_fill_of_aon_intent_fails() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = setup_flagged(&mut contract, &mut context, 0, true);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(50), "WSOL".to_string(), u(150), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 150, 50), mp(id_b, 50, 150)])).unwrap_err();
+    assert_eq!(error, OrderbookError::AllOrNothing { intent_id: 0, required: 300 });
+}
+
+#[test]
+fn test_full_take_of_aon_intent_passes() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_flagged(&mut contract, &mut context, 0, true);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.take_intent(id, u(300)).unwrap();
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::Filled);
+}
+
+#[test]
+fn test_fill_of_exactly_min_fill_passes() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_flagged(&mut contract, &mut context, 30, false);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.take_intent(id, u(30)).unwrap();
+    assert_eq!(contract.get_intent(id).unwrap().filled_amount, 30);
+}
+
+#[test]
+fn test_fill_below_min_fill_fails() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_flagged(&mut contract, &mut context, 30, false);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.take_intent(id, u(29)).unwrap_err();
+    assert_eq!(error, OrderbookError::BelowMinFill { intent_id: 0, amount: 29, min: 30 });
+}
+
+#[test]
+fn test_remainder_below_min_fill_can_be_taken() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_flagged(&mut contract, &mut context, 30, false);
+    // Rounding up both escrows takes one ETH more than the 100 Bob holds
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 1);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.take_intent(id, u(280)).unwrap();
+    contract.take_intent(id, u(20)).unwrap();
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::Filled);
+}
+
+#[test]
+#[should_panic(expected = "min_fill_amount exceeds src_amount")]
+fn test_make_intent_min_fill_above_amount_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_flagged(&mut contract, &mut context, 301, false);
+}
+
+// ============================================================================
+// 41. FORCE CANCEL
+// ============================================================================
+
+#[test]
+fn test_force_cancel_stuck_verifying_sub_intent_refunds_maker() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    // The MPC callback never arrives: sub-intent 0 stays Verifying
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Verifying);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.force_cancel_sub_intent(U64(0), true, "mpc callback lost".to_string());
+
+    let sub = contract.get_sub_intent(U64(0)).unwrap();
+    assert_eq!(sub.status, SubIntentStatus::ForceCancelled);
+    assert_eq!(sub.bond, 0);
+    assert!(contract.get_transition_expectation(U64(0)).is_none());
+    assert!(contract.get_escrow(U64(0)).is_none());
+    let intent = contract.get_intent(id_a).unwrap();
+    assert_eq!(intent.status, IntentStatus::Open);
+    assert_eq!(intent.filled_amount, 0);
+    assert!(near_sdk::test_utils::get_logs().contains(&format!(
+        "SUB_INTENT_FORCE_CANCELLED:sub_intent_id=0,intent_id=0,admin={},refund_maker=true,reason=mpc callback lost",
+        orderbook_contract()
+    )));
+
+    // A late signature no longer moves it and is not handed to relayers
+    let result =
+        contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(result, "SignedAfterCancel");
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::ForceCancelled);
+    let logs = near_sdk::test_utils::get_logs();
+    assert!(logs.iter().any(|log| log.contains("\"event\":\"signed_after_cancel\"")
+        && log.contains("\"sub_intent_id\":\"0\"")
+        && log.contains("\"status\":\"ForceCancelled\"")));
+    assert!(!logs.iter().any(|log| log.contains("Signed Trustlessly")));
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_none());
+
+    // Maker reclaims the full amount
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id_a);
+    assert_eq!(contract.get_balance(user_alice(), "WSOL".to_string()), u(100));
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+}
+
+#[test]
+fn test_force_cancel_sub_intent_without_refund_releases_proceeds() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    contract.force_cancel_sub_intent(U64(0), false, "delivered off-chain".to_string());
+
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_none());
+}
+
+#[test]
+fn test_force_cancel_sub_intent_without_refund_routes_fee() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(30);
+    contract.set_fee_collector(user_dave());
+    setup_fee_match(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    contract.force_cancel_sub_intent(U64(0), false, "delivered off-chain".to_string());
+
+    // The maker gets the net amount; the fee goes to the collector as on release
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(997));
+    assert_eq!(contract.get_balance(user_dave(), "ETH".to_string()), u(2));
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(2));
+}
+
+#[test]
+fn test_force_cancel_intent_unwinds_live_sub_intents() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_eth_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let stuck = contract.take_intent(id, u(90)).unwrap();
+    let _ = submit_escrowed_take(&mut contract, stuck).unwrap();
+    let taken = contract.take_intent(id, u(30)).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.force_cancel_intent(id, "abandoned".to_string());
+
+    assert_eq!(contract.get_intent(id).unwrap().status, IntentStatus::ForceCancelled);
+    assert_eq!(contract.get_sub_intent(stuck).unwrap().status, SubIntentStatus::ForceCancelled);
+    assert_eq!(contract.get_sub_intent(taken).unwrap().status, SubIntentStatus::ForceCancelled);
+    assert!(contract.get_open_intents(u(0), 10).is_empty());
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(300));
+    assert_eq!(contract.get_balance(solver_bob(), "SOL".to_string()), u(100));
+    assert!(near_sdk::test_utils::get_logs()
+        .iter()
+        .any(|l| l.starts_with("INTENT_FORCE_CANCELLED:intent_id=0") && l.ends_with("reason=abandoned")));
+}
+
+#[test]
+#[should_panic(expected = "is already final")]
+fn test_force_cancel_completed_sub_intent_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    release_escrows(&mut contract, &mut context, &[0]);
+    contract.force_cancel_sub_intent(U64(0), true, "late".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Only owner can force-cancel")]
+fn test_force_cancel_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.force_cancel_intent(id_a, "mine".to_string());
+}
+
+// ============================================================================
+// 42. ASSET REGISTRY
+// ============================================================================
+
+fn disable_asset(contract: &mut Orderbook, context: &mut VMContextBuilder, symbol: &str) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_enabled(symbol.to_string(), false);
+}
+
+#[test]
+fn test_register_asset_normalizes_symbol() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_asset("wBtc".to_string(), ChainType::ETH, 8, u(1_000));
+
+    let info = contract.get_asset("WBTC".to_string()).unwrap();
+    assert_eq!(info, AssetInfo { chain_type: ChainType::ETH, decimals: 8, min_deposit: 1_000, enabled: true, min_intent_size: 0, token_contract: None });
+    assert_eq!(contract.get_asset("wbtc".to_string()), Some(info));
+    assert!(contract.get_registered_assets(0, 100).iter().any(|(symbol, _)| symbol == "WBTC"));
+}
+
+#[test]
+fn test_mixed_case_assets_share_one_balance() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "eth", 10);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "Eth", 5);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(15));
+    assert_eq!(contract.get_assets(user_alice()), vec!["ETH"]);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("eth".to_string(), u(15), "sol".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    let intent = contract.get_intent(id).unwrap();
+    assert_eq!((intent.src_asset.as_str(), intent.dst_asset.as_str()), ("ETH", "SOL"));
+}
+
+#[test]
+fn test_make_intent_same_asset_different_case_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "sol".to_string(), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "src_asset equals dst_asset".to_string() });
+}
+
+#[test]
+fn test_verify_mpc_deposit_normalizes_asset() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "alice-sol-addr".to_string());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    // The memo commits to the registry symbol, not the caller's spelling
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "sol".to_string(), U128(500),
+        "alice-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        vec![1], "sol-tx-1".to_string(),
+    );
+}
+
+#[test]
+fn test_withdraw_normalizes_asset() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw("eth".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!(contract.get_pending_withdrawal(U64(0)).unwrap().asset, "ETH");
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+}
+
+#[test]
+#[should_panic(expected = "Unknown asset DOGE")]
+fn test_deposit_unknown_asset_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "DOGE", 10);
+}
+
+#[test]
+#[should_panic(expected = "Asset ETH is disabled")]
+fn test_deposit_for_disabled_asset_panics() {
+    let (mut contract, mut context) = new_contract();
+    disable_asset(&mut contract, &mut context, "eth");
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+}
+
+#[test]
+fn test_make_intent_disabled_asset_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    disable_asset(&mut contract, &mut context, "SOL");
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("ETH".to_string(), u(10), "SOL".to_string(), u(1), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::AssetDisabled { asset: "SOL".to_string() });
+}
+
+#[test]
+#[should_panic(expected = "Asset SOL is disabled")]
+fn test_verify_mpc_deposit_disabled_asset_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "alice-sol-addr".to_string());
+    disable_asset(&mut contract, &mut context, "SOL");
+    verify_alice_sol_deposit(&mut contract, "alice-sol-addr");
+}
+
+#[test]
+fn test_withdraw_disabled_asset_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 10);
+    disable_asset(&mut contract, &mut context, "ETH");
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.withdraw("ETH".to_string(), u(10), ETH_DEST.to_string(), eth_tx(10), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap_err();
+    assert_eq!(error, OrderbookError::AssetDisabled { asset: "ETH".to_string() });
+}
+
+#[test]
+#[should_panic(expected = "Asset SOL is not on ETH")]
+fn test_verify_mpc_deposit_wrong_chain_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::ETH, "SOL".to_string(), U128(500),
+        "addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        vec![1], "tx-1".to_string(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Deposit below minimum 1000 SOL")]
+fn test_verify_mpc_deposit_below_minimum_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_asset("SOL".to_string(), ChainType::SOL, 9, u(1_000));
+    verify_alice_sol_deposit(&mut contract, "addr");
+}
+
+#[test]
+#[should_panic(expected = "Only owner can manage assets")]
+fn test_register_asset_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.register_asset("DOGE".to_string(), ChainType::ETH, 8, u(0));
+}
+
+// ============================================================================
+// 43. PAIR PAUSE
+// ============================================================================
+
+fn pause_sol_eth(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_pair_paused("sol".to_string(), "ETH".to_string(), true);
+}
+
+#[test]
+fn test_pause_pair_blocks_both_orderings_only() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 100);
+    pause_sol_eth(&mut contract, &mut context);
+    assert_eq!(contract.get_paused_pairs(), vec!["ETH/SOL".to_string()]);
+    assert!(near_sdk::test_utils::get_logs().contains(&"PAIR_PAUSED:pair=ETH/SOL,paused=true".to_string()));
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    for (src, dst) in [("SOL", "ETH"), ("ETH", "SOL")] {
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.make_intent(src.to_string(), u(10), dst.to_string(), u(10), None, None, None, None, None, None).unwrap();
+        }));
+        assert!(res.is_err(), "{}/{} must be paused", src, dst);
+    }
+    contract.make_intent("BTC".to_string(), u(10), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    contract.make_intent("ETH".to_string(), u(10), "BTC".to_string(), u(10), None, None, None, None, None, None).unwrap();
+}
+
+#[test]
+fn test_take_intent_on_paused_pair_fails() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    pause_sol_eth(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.take_intent(id, u(30)).unwrap_err();
+    assert_eq!(error, OrderbookError::PairPaused { pair: "ETH/SOL".to_string() });
+}
+
+#[test]
+fn test_batch_with_paused_leg_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    pause_sol_eth(&mut contract, &mut context);
+    testing_env!(context.attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::PairPaused { pair: "ETH/SOL".to_string() });
+}
+
+#[test]
+fn test_unpause_pair_resumes_trading() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    pause_sol_eth(&mut contract, &mut context);
+    contract.set_pair_paused("ETH".to_string(), "SOL".to_string(), false);
+    assert!(contract.get_paused_pairs().is_empty());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "Only owner can pause pairs")]
+fn test_set_pair_paused_not_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_pair_paused("SOL".to_string(), "ETH".to_string(), true);
+}
+
+// ============================================================================
+// 44. OPEN INTENT CAP
+// ============================================================================
+
+#[test]
+fn test_open_intent_cap_frees_slot_on_fill() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_max_open_intents_per_account(2);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 300);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    assert_eq!(contract.get_open_intent_count(user_alice()), 2);
+    let capped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    }));
+    assert!(capped.is_err(), "third intent must hit the cap");
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
+    assert_eq!(contract.get_open_intent_count(user_alice()), 1);
+    assert_eq!(contract.get_open_intent_count(solver_bob()), 0);
+
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(0)).build());
+    contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    assert_eq!(contract.get_open_intent_count(user_alice()), 2);
+}
+
+#[test]
+fn test_open_intent_cap_fails() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_max_open_intents_per_account(1);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 200);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::OpenIntentLimit { limit: 1 });
+}
+
+#[test]
+fn test_open_intent_count_tracks_cancel_and_unwind() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.block_timestamp(1_000).build());
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    assert_eq!(contract.get_open_intent_count(user_alice()), 0);
+
+    // Unwinding the fill reopens the intent and takes the slot back
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_ESCROW_TIMEOUT_NS).build());
+    contract.claim_escrow_timeout(U64(0)).unwrap();
+    assert_eq!(contract.get_open_intent_count(user_alice()), 1);
+
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id_a);
+    assert_eq!(contract.get_open_intent_count(user_alice()), 0);
+}
+
+// ============================================================================
+// 45. PRO-RATA ROUNDING
+// ============================================================================
+
+/// Seed a 3 SOL : 10 ETH intent with an unescrowed (pre-escrow layout) take
+/// of `amount`, as only those still go through an external payment proof,
+/// and Alice's SOL receive address.
+fn seed_unescrowed_take(contract: &mut Orderbook, amount: u128) {
+    contract.intents.insert(&0, &Intent {
+        id: 0,
+        maker: user_alice(),
+        src_asset: "SOL".to_string(),
+        src_amount: 3,
+        filled_amount: amount,
+        dst_asset: "ETH".to_string(),
+        dst_amount: 10,
+        status: IntentStatus::Open,
+        expires_at: None,
+        min_fill_amount: 0,
+        all_or_nothing: false,
+        closed_at: None,
+        allowed_takers: None,
+        referrer: None,
+        client_id: None,
+        replaced_from: None,
+    });
+    contract.sub_intents.insert(&0, &SubIntent {
+        id: 0,
+        parent_intent_id: 0,
+        taker: solver_bob(),
+        amount,
+        status: SubIntentStatus::Taken,
+        escrow: 0,
+        expires_at: None,
+        bond: 0,
+        closed_at: None,
+        transition_tx_hash: None,
+        batch_id: None,
+    });
+    contract.receive_addresses.insert(&(user_alice(), ChainType::SOL), &SOL_DEST.to_string());
+}
+
+/// `expected_amount` of the `consume_payment_proof` call created in the current context.
+fn payment_proof_expected_amount() -> String {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .find_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, args, .. }
+                if method_name == b"consume_payment_proof" =>
+            {
+                let args: near_sdk::serde_json::Value = near_sdk::serde_json::from_slice(&args).unwrap();
+                Some(args["expected_amount"].as_str().unwrap().to_string())
+            }
+            _ => None,
+        })
+        .expect("No consume_payment_proof call")
+}
+
+#[test]
+fn test_payment_proof_expected_amount_rounds_up() {
+    let (mut contract, mut context) = new_contract();
+    seed_unescrowed_take(&mut contract, 1);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = contract.submit_payment_proof(
+        U64(0), vec![], [0u8; 32], None, "default/path".to_string(),
+        ChainType::ETH, ChainType::SOL, "recipient".to_string(), "sub:0".to_string(),
+        SOL_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).unwrap();
+    // 1 * 10 / 3 = 3.33: the taker must pay 4, not 3
+    assert_eq!(payment_proof_expected_amount(), "4");
+}
+
+#[test]
+fn test_payment_proof_expected_amount_exact_ratio_unchanged() {
+    let (mut contract, mut context) = new_contract();
+    seed_unescrowed_take(&mut contract, 3);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = contract.submit_payment_proof(
+        U64(0), vec![], [0u8; 32], None, "default/path".to_string(),
+        ChainType::ETH, ChainType::SOL, "recipient".to_string(), "sub:0".to_string(),
+        SOL_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).unwrap();
+    assert_eq!(payment_proof_expected_amount(), "10");
+}
+
+#[test]
+fn test_take_escrow_uses_same_rounding() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 3);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 10);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(3), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(1)).unwrap();
+    assert_eq!(contract.get_sub_intent(sub).unwrap().escrow, 4);
+}
+
+// ============================================================================
+// 46. FILL HISTORY
+// ============================================================================
+
+#[test]
+fn test_intent_fills_records_partial_takes_in_order() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let first = contract.take_intent(id, u(90)).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(2_000).build());
+    let second = contract.take_intent(id, u(10)).unwrap();
+
+    let fills = contract.get_intent_fills(id, u(0), 10);
+    assert_eq!(fills, vec![
+        FillRecord { sub_intent_id: first.0, taker: solver_bob(), fill_amount: 90, get_amount: 30, timestamp: 1_000 },
+        FillRecord { sub_intent_id: second.0, taker: solver_bob(), fill_amount: 10, get_amount: 4, timestamp: 2_000 },
+    ]);
+    assert_eq!(contract.get_intent_fills(id, u(1), 10).len(), 1);
+    assert!(contract.get_intent_fills(U64(99), u(0), 10).is_empty());
+}
+
+#[test]
+fn test_intent_fills_survive_expired_sub_intent() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_SUB_INTENT_TIMEOUT_NS).build());
+    contract.expire_sub_intent(sub);
+
+    assert!(contract.get_sub_intent(sub).is_none());
+    let fills = contract.get_intent_fills(id, u(0), 10);
+    assert_eq!(fills.len(), 1);
+    assert_eq!((fills[0].sub_intent_id, fills[0].fill_amount), (sub.0, 30));
+}
+
+#[test]
+fn test_batch_match_records_fills() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_matched_pair(&mut contract, &mut context);
+    let fills_a = contract.get_intent_fills(id_a, u(0), 10);
+    assert_eq!(fills_a.len(), 1);
+    assert_eq!((fills_a[0].taker.clone(), fills_a[0].fill_amount, fills_a[0].get_amount), (orderbook_contract(), 100, 100));
+    assert_eq!(contract.get_intent_fills(id_b, u(0), 10).len(), 1);
+}
+
+// ============================================================================
+// 47. CONTRACT STATS
+// ============================================================================
+
+#[test]
+fn test_stats_follow_two_party_lifecycle() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+
+    let stats = contract.get_stats();
+    assert_eq!(
+        (stats.total_intents_created, stats.open_intents, stats.total_deposits),
+        (2, 0, 2)
+    );
+    assert_eq!(contract.get_asset_volume("wsol".to_string()), u(100));
+    assert_eq!(contract.get_asset_volume("ETH".to_string()), u(100));
+
+    // A failed sign leaves the fill in place and counts nothing
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(contract.get_stats().sub_intents_failed, 0);
+    assert_eq!(contract.get_asset_volume("WSOL".to_string()), u(100));
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    let _ = contract.retry_settlement(U64(0)).unwrap();
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    let stats = contract.get_stats();
+    assert_eq!(stats.sub_intents_completed, 2);
+    assert_eq!(stats.open_intents, 0);
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!(contract.get_stats().total_withdrawals, 0);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(contract.get_stats().total_withdrawals, 1);
+}
+
+#[test]
+fn test_stats_transition_timeout_keeps_volume() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).block_timestamp(0).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    contract.on_transition_verified(U64(0), "tx-0".to_string(), transition_verified(false));
+    assert_eq!(contract.get_stats(), Stats { total_intents_created: 2, total_deposits: 2, ..Stats::default() });
+
+    testing_env!(context.predecessor_account_id(user_charlie()).block_timestamp(DEFAULT_TRANSITION_TIMEOUT_NS).build());
+    contract.claim_transition_timeout(U64(0));
+
+    let stats = contract.get_stats();
+    assert_eq!((stats.sub_intents_failed, stats.open_intents), (1, 0));
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+    assert_eq!(contract.get_asset_volume("WSOL".to_string()), u(100));
+    assert_eq!(contract.get_asset_volume("ETH".to_string()), u(100));
+}
+
+#[test]
+fn test_stats_count_expired_and_cancelled_takes() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(1_000).build());
+    let stale = contract.take_intent(id, u(30)).unwrap();
+    let backed_out = contract.take_intent(id, u(60)).unwrap();
+    assert_eq!(contract.get_asset_volume("SOL".to_string()), u(90));
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(backed_out);
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_SUB_INTENT_TIMEOUT_NS).build());
+    contract.expire_sub_intent(stale);
+
+    let stats = contract.get_stats();
+    assert_eq!((stats.sub_intents_cancelled, stats.sub_intents_expired), (1, 1));
+    assert_eq!(stats.open_intents, 1);
+    assert_eq!(contract.get_asset_volume("SOL".to_string()), u(0));
+}
+
+#[test]
+fn test_stats_refunded_withdrawal_not_counted() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(contract.get_stats().total_withdrawals, 0);
+    assert_eq!(contract.get_stats().total_deposits, 1);
+}
+
+// ============================================================================
+// 48. STATE VERSIONING
+// ============================================================================
+
+#[test]
+fn test_new_contract_records_state_version() {
+    let (contract, _) = new_contract();
+    assert_eq!(contract.get_state_version(), STATE_VERSION);
+}
+
+#[test]
+fn test_migrate_current_layout_keeps_state() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "BTC", 7);
+    env::state_write(&contract);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let contract = Orderbook::migrate();
+
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 100);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Verifying);
+    assert_eq!(contract.get_balance(user_charlie(), "BTC".to_string()), u(7));
+    assert_eq!(contract.get_stats().total_intents_created, 2);
+    assert_eq!(contract.next_intent_id, 2);
+    assert_eq!(contract.get_state_version(), STATE_VERSION);
+}
+
+#[test]
+#[should_panic(expected = "Unknown state version 9")]
+fn test_migrate_unknown_state_version_panics() {
+    let (contract, mut context) = new_contract();
+    env::state_write(&contract);
+    env::storage_write(b"STATE_VERSION", &[9]);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    Orderbook::migrate();
+}
+
+// ============================================================================
+// 49. CODE UPGRADE
+// ============================================================================
+
+const NEW_CODE: &[u8] = b"\0asm new orderbook code";
+
+fn stage_new_code(contract: &mut Orderbook, context: &mut VMContextBuilder) -> String {
+    let code_hash = hex::encode(env::sha256(NEW_CODE));
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(100).build());
+    contract.stage_upgrade(code_hash.clone());
+    code_hash
+}
+
+#[test]
+fn test_upgrade_cycle_deploys_code_and_migrates() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    let code_hash = stage_new_code(&mut contract, &mut context);
+    assert_eq!(
+        contract.get_staged_upgrade(),
+        Some(StagedUpgrade { code_hash, effective_height: 100 + MIN_UPGRADE_DELAY_BLOCKS })
+    );
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .block_height(100 + MIN_UPGRADE_DELAY_BLOCKS)
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    let _ = contract.apply_upgrade(NEW_CODE.to_vec());
+    assert!(contract.get_staged_upgrade().is_none());
+
+    let actions: Vec<_> = near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .filter(|r| r.receiver_id == orderbook_contract())
+        .flat_map(|r| r.actions)
+        .collect();
+    assert!(actions.iter().any(|a| matches!(
+        a,
+        near_sdk::mock::MockAction::DeployContract { code, .. } if code == NEW_CODE
+    )));
+    assert!(actions.iter().any(|a| matches!(
+        a,
+        near_sdk::mock::MockAction::FunctionCallWeight { method_name, .. } if method_name == b"migrate"
+    )));
+
+    // The new code's `migrate` picks up the stored state
+    env::state_write(&contract);
+    let contract = Orderbook::migrate();
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 100);
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(0));
+}
+
+#[test]
+#[should_panic(expected = "Upgrade timelock not reached")]
+fn test_apply_upgrade_before_timelock_panics() {
+    let (mut contract, mut context) = new_contract();
+    stage_new_code(&mut contract, &mut context);
+    testing_env!(context.block_height(100 + MIN_UPGRADE_DELAY_BLOCKS - 1).build());
+    let _ = contract.apply_upgrade(NEW_CODE.to_vec());
+}
+
+#[test]
+#[should_panic(expected = "Code hash does not match staged upgrade")]
+fn test_apply_upgrade_with_other_code_panics() {
+    let (mut contract, mut context) = new_contract();
+    stage_new_code(&mut contract, &mut context);
+    testing_env!(context.block_height(100 + MIN_UPGRADE_DELAY_BLOCKS).build());
+    let _ = contract.apply_upgrade(b"\0asm something else".to_vec());
+}
+
+#[test]
+fn test_upgrade_delay_follows_longer_config_delay() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(0).build());
+    contract.set_config_delay(MIN_UPGRADE_DELAY_BLOCKS * 2);
+    let code_hash = hex::encode(env::sha256(NEW_CODE));
+    testing_env!(context.block_height(100).build());
+    contract.stage_upgrade(code_hash);
+    assert_eq!(contract.get_staged_upgrade().unwrap().effective_height, 100 + MIN_UPGRADE_DELAY_BLOCKS * 2);
+}
+
+#[test]
+#[should_panic(expected = "Only owner can upgrade")]
+fn test_stage_upgrade_non_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.stage_upgrade(hex::encode(env::sha256(NEW_CODE)));
+}
+
+#[test]
+#[should_panic(expected = "No staged upgrade")]
+fn test_cancelled_upgrade_cannot_be_applied() {
+    let (mut contract, mut context) = new_contract();
+    stage_new_code(&mut contract, &mut context);
+    contract.cancel_upgrade();
+    testing_env!(context.block_height(100 + MIN_UPGRADE_DELAY_BLOCKS).build());
+    let _ = contract.apply_upgrade(NEW_CODE.to_vec());
+}
+
+// ============================================================================
+// 50. RECORD CLEANUP
+// ============================================================================
+
+/// Match and complete both legs at timestamp 0, then move past retention.
+fn setup_completed_pair(contract: &mut Orderbook, context: &mut VMContextBuilder) -> (U64, U64) {
+    let ids = setup_matched_pair(contract, context);
+    testing_env!(context.block_timestamp(0).build());
+    release_escrows(contract, context, &[0, 1]);
+    testing_env!(context
+        .predecessor_account_id(user_charlie())
+        .block_timestamp(DEFAULT_RECORD_RETENTION_NS)
+        .build()
+    );
+    ids
+}
+
+#[test]
+fn test_cleanup_completed_sub_intent_leaves_tombstone() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_completed_pair(&mut contract, &mut context);
+
+    assert_eq!(contract.cleanup(vec![U64(0)], RecordKind::SubIntent), 1);
+    assert!(contract.get_sub_intent(U64(0)).is_none());
+    assert!(contract.get_sub_intents_by_parent(id_a, u(0), 10).is_empty());
+    assert_eq!(
+        contract.get_tombstone(RecordKind::SubIntent, U64(0)),
+        Some(Tombstone { status: RecordStatus::SubIntent(SubIntentStatus::Completed), closed_at: Some(0) })
+    );
+    // Fill history outlives the sub-intent
+    assert_eq!(contract.get_intent_fills(id_a, u(0), 10).len(), 1);
+    let refunds = transfers_to(&user_charlie());
+    assert_eq!(refunds.len(), 1);
+    assert!(refunds[0] > 0);
+}
+
+#[test]
+#[should_panic(expected = "Sub-Intent 0 is not final")]
+fn test_cleanup_settled_sub_intent_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    testing_env!(context.predecessor_account_id(user_charlie()).block_timestamp(DEFAULT_RECORD_RETENTION_NS).build());
+    contract.cleanup(vec![U64(0)], RecordKind::SubIntent);
+}
+
+#[test]
+fn test_double_cleanup_is_noop() {
+    let (mut contract, mut context) = new_contract();
+    setup_completed_pair(&mut contract, &mut context);
+    contract.cleanup(vec![U64(0)], RecordKind::SubIntent);
+
+    testing_env!(context.build());
+    assert_eq!(contract.cleanup(vec![U64(0), U64(99)], RecordKind::SubIntent), 0);
+    assert!(transfers_to(&user_charlie()).is_empty());
+    assert_eq!(contract.get_tombstone(RecordKind::SubIntent, U64(0)).unwrap().status, RecordStatus::SubIntent(SubIntentStatus::Completed));
+}
+
+#[test]
+#[should_panic(expected = "Record 0 is within its retention period")]
+fn test_cleanup_within_retention_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_completed_pair(&mut contract, &mut context);
+    testing_env!(context.block_timestamp(DEFAULT_RECORD_RETENTION_NS - 1).build());
+    contract.cleanup(vec![U64(0)], RecordKind::SubIntent);
+}
+
+#[test]
+fn test_cleanup_filled_intent_after_its_sub_intents() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_completed_pair(&mut contract, &mut context);
+    let early = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.cleanup(vec![id_a], RecordKind::Intent)
+    }));
+    assert!(early.is_err());
+
+    contract.cleanup(vec![U64(0)], RecordKind::SubIntent);
+    assert_eq!(contract.cleanup(vec![id_a], RecordKind::Intent), 1);
+    assert!(contract.get_intent(id_a).is_none());
+    assert!(contract.get_intent_fills(id_a, u(0), 10).is_empty());
+    assert_eq!(
+        contract.get_tombstone(RecordKind::Intent, id_a),
+        Some(Tombstone { status: RecordStatus::Intent(IntentStatus::Filled), closed_at: None })
+    );
+}
+
+#[test]
+fn test_cleanup_take_charges_taker_storage_deposit() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).block_timestamp(0).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_sub_intent(sub);
+    let before = contract.storage_balance_of(solver_bob()).unwrap();
+
+    testing_env!(context.predecessor_account_id(user_charlie()).block_timestamp(DEFAULT_RECORD_RETENTION_NS).build());
+    contract.cleanup(vec![sub], RecordKind::SubIntent);
+
+    let refund = transfers_to(&user_charlie())[0];
+    let after = contract.storage_balance_of(solver_bob()).unwrap();
+    assert_eq!(after.total.0, before.total.0 - refund);
+    assert_eq!(after.available, before.available);
+}
+
+#[test]
+#[should_panic(expected = "Intent 0 is not final")]
+fn test_cleanup_open_intent_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_take(&mut contract, &mut context);
+    contract.cleanup(vec![U64(0)], RecordKind::Intent);
+}
+
+// ============================================================================
+// 51. BATCH SIMULATION
+// ============================================================================
+
+/// Alice sells 100 A for 100 B, Bob sells 100 B for 100 A.
+fn setup_ab_pair(contract: &mut Orderbook, context: &mut VMContextBuilder) -> (U64, U64) {
+    owner_deposit(contract, context, &user_alice(), "A", 100);
+    owner_deposit(contract, context, &solver_bob(), "B", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("A".to_string(), u(100), "B".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("B".to_string(), u(100), "A".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    (id1, id2)
+}
+
+/// Simulate `matches`, then submit them for real and check the call fails
+/// with the simulation's first error. Returns the simulation.
+fn assert_rejected_like_simulation(
+    contract: &mut Orderbook,
+    context: &mut VMContextBuilder,
+    matches: Vec<MatchParams>,
+) -> BatchSimulation {
+    let matches = bind_assets(contract, matches);
+    let simulation = contract.simulate_batch_match(orderbook_contract(), matches.clone());
+    assert!(!simulation.is_valid());
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let error = contract
+        .batch_match_intents(matches)
+        .expect_err("batch_match_intents accepted a batch the simulation rejected");
+    assert_eq!(error.to_string(), simulation.errors[0]);
+    simulation
+}
+
+#[test]
+fn test_simulate_valid_batch_reports_net_without_mutating() {
+    let (mut contract, mut context) = new_contract();
+    let (id1, id2) = setup_ab_pair(&mut contract, &mut context);
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 100)]));
+
+    assert!(simulation.is_valid());
+    assert!(simulation.matches.iter().all(|v| v.error.is_none()));
+    assert_eq!(simulation.net, vec![
+        AssetNet { asset: "A".to_string(), supply: u(100), demand: u(100) },
+        AssetNet { asset: "B".to_string(), supply: u(100), demand: u(100) },
+    ]);
+    assert_eq!(contract.get_intent(id1).unwrap().filled_amount, 0);
+    assert!(contract.get_sub_intent(U64(0)).is_none());
+}
+
+#[test]
+fn test_simulate_rejects_insolvent_batch_like_real_call() {
+    let (mut contract, mut context) = new_contract();
+    let (id1, id2) = setup_ab_pair(&mut contract, &mut context);
+    let simulation = assert_rejected_like_simulation(
+        &mut contract, &mut context, vec![mp(id1, 100, 100), mp(id2, 100, 110)],
+    );
+    assert_eq!(simulation.errors, vec!["Insufficient supply for asset A: deficit 10".to_string()]);
+    assert!(simulation.matches.iter().all(|v| v.error.is_none()));
+}
+
+#[test]
+fn test_simulate_rejects_bad_price_like_real_call() {
+    let (mut contract, mut context) = new_contract();
+    let (id1, id2) = setup_ab_pair(&mut contract, &mut context);
+    let simulation = assert_rejected_like_simulation(
+        &mut contract, &mut context, vec![mp(id1, 100, 90), mp(id2, 100, 100)],
+    );
+    assert_eq!(simulation.matches[0].error.as_deref(), Some("Price mismatch for Intent 0: Get 90 < Required"));
+    assert_eq!(simulation.matches[1].error, None);
+}
+
+#[test]
+fn test_simulate_rejects_same_fixtures_as_real_call() {
+    let (mut contract, mut context) = new_contract();
+    let (id1, id2) = setup_ab_pair(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "C", 2);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id3 = contract.make_intent("C".to_string(), u(1), "B".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    let id4 = contract.make_intent("C".to_string(), u(1), "B".to_string(), u(1), None, None, None, None, None, None).unwrap();
+    let huge = i128::MAX as u128 + 1;
+
+    // Rejected batches leave state untouched, so every fixture runs on the same book
+    let fixtures = vec![
+        vec![mp(id1, 100, 100)],
+        vec![mp(id1, 60, 60), mp(id1, 60, 60)],
+        vec![mp(id1, 101, 101), mp(id2, 100, 100)],
+        vec![mp(id1, 100, 100), mp(id2, 100, 100), mp(U64(99), 1, 1)],
+        vec![mp(id1, 100, 1), mp(id2, 1, huge)],
+        vec![mp(id3, 1, huge), mp(id4, 1, huge)],
+    ];
+    for fixture in fixtures {
+        assert_rejected_like_simulation(&mut contract, &mut context, fixture);
+    }
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, vec![mp(id3, 1, huge), mp(id4, 1, huge)]));
+    assert_eq!(simulation.matches[1].error.as_deref(), Some("Amount overflow for asset B"));
+    assert_eq!(contract.get_intent(id1).unwrap().filled_amount, 0);
+}
+
+#[test]
+fn test_simulate_reports_unregistered_solver_and_pause() {
+    let (mut contract, mut context) = new_contract();
+    let (id1, id2) = setup_ab_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_solver_allowlist(true);
+    contract.pause();
+
+    let simulation = contract.simulate_batch_match(user_charlie(), bind_assets(&contract, vec![mp(id1, 100, 100), mp(id2, 100, 100)]));
+    assert_eq!(simulation.errors, vec![
+        "Contract is paused".to_string(),
+        format!("{} is not a registered solver", user_charlie()),
+    ]);
+}
+
+// ============================================================================
+// 52. ALLOWED TAKERS
+// ============================================================================
+
+/// Alice's SOL → ETH intent restricted to `allowed`, and Bob's public ETH → SOL.
+fn setup_restricted_pair(
+    contract: &mut Orderbook,
+    context: &mut VMContextBuilder,
+    allowed: Vec<AccountId>,
+) -> (U64, U64) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, Some(allowed), None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    (id_a, id_b)
+}
+
+#[test]
+fn test_allowed_solver_can_match_restricted_intent() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_restricted_pair(&mut contract, &mut context, vec![orderbook_contract()]);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let subs = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
+    assert_eq!(subs.len(), 2);
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+}
+
+#[test]
+fn test_disallowed_solver_batch_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_restricted_pair(&mut contract, &mut context, vec![user_charlie()]);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::TakerNotAllowed { taker: orderbook_contract(), intent_id: 0 });
+}
+
+#[test]
+fn test_disallowed_taker_cannot_take() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_restricted_pair(&mut contract, &mut context, vec![user_charlie()]);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.take_intent(id_a, u(10)).unwrap_err();
+    assert_eq!(error, OrderbookError::TakerNotAllowed { taker: solver_bob(), intent_id: 0 });
+}
+
+#[test]
+fn test_maker_can_clear_taker_restriction() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_restricted_pair(&mut contract, &mut context, vec![user_charlie()]);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_allowed_takers(id_a, None);
+    assert_eq!(contract.get_intent(id_a).unwrap().allowed_takers, None);
+
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 10);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id_a, u(10)).unwrap();
+    assert_eq!(contract.get_sub_intent(sub).unwrap().taker, solver_bob());
+}
+
+#[test]
+#[should_panic(expected = "Only the maker can edit allowed takers")]
+fn test_non_maker_cannot_edit_allowed_takers() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_restricted_pair(&mut contract, &mut context, vec![user_charlie()]);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.set_allowed_takers(id_a, None);
+}
+
+#[test]
+fn test_allowed_takers_list_is_capped() {
+    let (mut contract, mut context) = new_contract();
+    let allowed = (0..=MAX_ALLOWED_TAKERS)
+        .map(|i| AccountId::from_str(&format!("taker{}.testnet", i)).unwrap())
+        .collect();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract
+        .make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, Some(allowed), None, None)
+        .unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "more than 16 allowed takers".to_string() });
+}
+
+// ============================================================================
+// 53. PENDING WITHDRAWALS PER USER
+// ============================================================================
+
+#[test]
+fn test_pending_withdrawals_listed_per_user() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let first = contract.withdraw("ETH".to_string(), u(30), ETH_DEST.to_string(), eth_tx(30), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    let second = contract.withdraw("ETH".to_string(), u(20), ETH_DEST.to_string(), withdraw_item(20, 1).unsigned_tx, own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!((first, second), (U64(0), U64(1)));
+
+    let pending = contract.get_pending_withdrawals(user_alice());
+    assert_eq!(pending.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![first, second]);
+    assert_eq!(pending[0].1.amount, 30);
+    assert_eq!(pending[1].1.amount, 20);
+    assert!(contract.get_pending_withdrawals(solver_bob()).is_empty());
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(first.0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+
+    let pending = contract.get_pending_withdrawals(user_alice());
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].0, second);
+    assert!(contract.get_pending_withdrawal(first).is_none());
+}
+
+#[test]
+fn test_refunded_withdrawal_leaves_user_index() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context
+        .predecessor_account_id(user_alice())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let wd_id = contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    assert_eq!(contract.get_pending_withdrawals(user_alice()).len(), 1);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(wd_id.0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+
+    assert!(contract.get_pending_withdrawals(user_alice()).is_empty());
+    assert!(contract.pending_withdrawals_by_user.get(&user_alice()).is_none());
+}
+
+// ============================================================================
+// 54. ONE YOCTO CONFIRMATION
+// ============================================================================
+
+#[test]
+fn test_withdraw_without_deposit_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(0)).build());
+    let error = contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientDeposit { required: 1, attached: 0 });
+}
+
+#[test]
+#[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+fn test_cancel_intent_without_deposit_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(0)).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(10), None, None, None, None, None, None).unwrap();
+    contract.cancel_intent(id);
+}
+
+#[test]
+#[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+fn test_cancel_sub_intent_without_deposit_panics() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).attached_deposit(NearToken::from_near(0)).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    contract.cancel_sub_intent(sub);
+}
+
+#[test]
+#[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+fn test_storage_withdraw_without_deposit_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(0)).build());
+    contract.storage_withdraw(None);
+}
+
+#[test]
+#[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+fn test_unregister_solver_without_deposit_panics() {
+    let (mut contract, mut context) = new_contract();
+    register_solver(&mut contract, &mut context, &solver_bob());
+    testing_env!(context.predecessor_account_id(solver_bob()).attached_deposit(NearToken::from_near(0)).build());
+    contract.unregister_solver();
+}
+
+// ============================================================================
+// 55. WITHDRAWAL DESTINATION
+// ============================================================================
+
+fn withdraw_to(
+    contract: &mut Orderbook,
+    context: &mut VMContextBuilder,
+    chain_type: ChainType,
+    destination: &str,
+) -> Result<U64, OrderbookError> {
+    owner_deposit(contract, context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw(
+        "ETH".to_string(), u(50), destination.to_string(), eth_tx(50),
+        own_path(&chain_type), chain_type, SignatureScheme::Secp256k1,
+    )
+}
+
+#[test]
+fn test_malformed_destinations_rejected_per_chain() {
+    let (mut contract, mut context) = new_contract();
+    let cases = [
+        (ChainType::ETH, "00000000000000000000000000000000000000aa"),
+        (ChainType::ETH, "0x00000000000000000000000000000000000000a"),
+        (ChainType::ETH, "0x00000000000000000000000000000000000000zz"),
+        (ChainType::SOL, "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB40"),
+        (ChainType::SOL, "short"),
+        (ChainType::BTC, "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdb"),
+        (ChainType::BTC, "bc1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ"),
+        (ChainType::BTC, "4J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"),
+        (ChainType::BTC, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN0"),
+    ];
+    for (chain_type, destination) in cases {
+        let error = withdraw_to(&mut contract, &mut context, chain_type.clone(), destination).unwrap_err();
+        assert_eq!(error, OrderbookError::InvalidDestination { chain_type, destination: destination.to_string() });
+    }
+}
+
+#[test]
+fn test_well_formed_destinations_pass_the_format_check() {
+    let (mut contract, mut context) = new_contract();
+    let cases = [
+        (ChainType::SOL, SOL_DEST),
+        (ChainType::BTC, "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"),
+        (ChainType::BTC, "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"),
+        (ChainType::BTC, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"),
+        (ChainType::BTC, "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"),
+    ];
+    for (chain_type, destination) in cases {
+        let error = withdraw_to(&mut contract, &mut context, chain_type.clone(), destination).unwrap_err();
+        assert_eq!(error, OrderbookError::UnsupportedWithdrawal { asset: "ETH".to_string(), chain_type });
+    }
+}
+
+#[test]
+fn test_withdrawal_events_carry_destination() {
+    let (mut contract, mut context) = new_contract();
+    withdraw_to(&mut contract, &mut context, ChainType::ETH, ETH_DEST).unwrap();
+    assert!(near_sdk::test_utils::get_logs().contains(&format!(
+        "WITHDRAWAL_REQUESTED:wd_id=0,user={},asset=ETH,amount=50,gas_fee=0,chain=ETH,destination={}",
+        user_alice(),
+        ETH_DEST
+    )));
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    let event = signature_event();
+    assert_eq!(event["transition_memo"], "withdraw:0");
+    assert_eq!(event["destination"], ETH_DEST);
+}
+
+#[test]
+fn test_sub_intent_signature_event_names_recipient_and_asset() {
+    let (mut contract, mut context) = new_contract();
+    match_pair_as(&mut contract, &mut context, &orderbook_contract()).unwrap();
+    let tx = contract.get_transition_expectation(U64(0)).unwrap().unsigned_tx.unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    let event = signature_event();
+    assert_eq!(event["destination"], ETH_DEST);
+    assert_eq!(event["asset"], "WSOL");
+    assert_eq!(event["unsigned_tx"], hex::encode(&tx));
+}
+
+// ============================================================================
+// 56. TRANSITION RECIPIENT
+// ============================================================================
+
+const ALICE_ETH: &str = "0x00000000000000000000000000000000000000a1";
+
+/// `expected_recipient` of the `verify_transition_proof` call created in the current context.
+fn transition_proof_recipient() -> String {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .find_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, args, .. }
+                if method_name == b"verify_transition_proof" =>
+            {
+                let args: near_sdk::serde_json::Value = near_sdk::serde_json::from_slice(&args).unwrap();
+                Some(args["expected_recipient"].as_str().unwrap().to_string())
+            }
+            _ => None,
+        })
+        .expect("No verify_transition_proof call")
+}
+
+/// Alice (ETH → SOL, settled on ETH) registered `ALICE_ETH`; Charlie
+/// (SOL → ETH) keeps the `dest_for` addresses.
+fn setup_recipient_pair(contract: &mut Orderbook, context: &mut VMContextBuilder) -> (U64, U64) {
+    owner_deposit(contract, context, &user_alice(), "ETH", 100);
+    owner_deposit(contract, context, &user_charlie(), "WSOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_receive_address(ChainType::ETH, Some(ALICE_ETH.to_string()));
+    let id_a = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    (id_a, id_b)
+}
+
+#[test]
+fn test_stored_recipient_is_sent_to_light_client() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_recipient_pair(&mut contract, &mut context);
+    let alice_match = MatchParams { recipient: ALICE_ETH.to_string(), ..mp(id_a, 100, 100) };
+    let subs = contract.batch_match_intents(bind_assets(&contract, vec![alice_match, mp(id_b, 100, 100)])).unwrap();
+    assert_eq!(contract.get_transition_expectation(subs[0]).unwrap().expected_recipient, ALICE_ETH);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(subs[0].0 as u64), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    testing_env!(context.predecessor_account_id(solver_bob()).prepaid_gas(Gas::from_tgas(300)).build());
+    let _ = contract.verify_transition_completion(subs[0], vec![1], "tx-a".to_string());
+    assert_eq!(transition_proof_recipient(), ALICE_ETH);
+}
+
+#[test]
+fn test_match_with_mismatched_recipient_fails() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_recipient_pair(&mut contract, &mut context);
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::RecipientMismatch { recipient: ETH_DEST.to_string(), chain_type: ChainType::ETH, maker: user_alice() });
+}
+
+#[test]
+fn test_simulation_reports_mismatched_recipient() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_recipient_pair(&mut contract, &mut context);
+    let sim = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]));
+    assert_eq!(
+        sim.matches[0].error.as_deref(),
+        Some(format!("Recipient {} does not match the ETH receive address of {}", ETH_DEST, user_alice()).as_str())
+    );
+    assert_eq!(sim.matches[1].error, None);
+}
+
+#[test]
+fn test_match_with_malformed_recipient_fails() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_recipient_pair(&mut contract, &mut context);
+    let alice_match = MatchParams { recipient: ALICE_ETH.to_string(), ..mp(id_a, 100, 100) };
+    let charlie_match = MatchParams { recipient: "not-a-sol-address".to_string(), ..mp_with_chain(id_b, 100, 100, ChainType::SOL) };
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![alice_match, charlie_match])).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidDestination { chain_type: ChainType::SOL, destination: "not-a-sol-address".to_string() });
+}
+
+#[test]
+fn test_retry_with_mismatched_recipient_fails() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_recipient_pair(&mut contract, &mut context);
+    let alice_match = MatchParams { recipient: ALICE_ETH.to_string(), ..mp(id_a, 100, 100) };
+    let subs = contract.batch_match_intents(bind_assets(&contract, vec![alice_match, mp(id_b, 100, 100)])).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(subs[0].0 as u64), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    // Alice moves her receive address while the sub-intent waits for a retry
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_receive_address(ChainType::ETH, Some(ETH_DEST.to_string()));
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let error = contract.retry_settlement(subs[0]).err();
+    assert_eq!(error, Some(OrderbookError::RecipientMismatch { recipient: ALICE_ETH.to_string(), chain_type: ChainType::ETH, maker: user_alice() }));
+}
+
+#[test]
+fn test_recipient_matches_in_any_hex_case() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = setup_recipient_pair(&mut contract, &mut context);
+    // Checksum casing spells the same ETH address
+    let checksummed = format!("0x{}", ALICE_ETH[2..].to_uppercase());
+    let alice_match = MatchParams { recipient: checksummed.clone(), ..mp(id_a, 100, 100) };
+    let subs = contract.batch_match_intents(bind_assets(&contract, vec![alice_match, mp(id_b, 100, 100)])).unwrap();
+    assert_eq!(contract.get_transition_expectation(subs[0]).unwrap().expected_recipient, checksummed);
+}
+
+#[test]
+fn test_payment_proof_without_receive_address_fails() {
+    let (mut contract, mut context) = new_contract();
+    seed_unescrowed_take(&mut contract, 1);
+    contract.receive_addresses.remove(&(user_alice(), ChainType::SOL));
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.submit_payment_proof(
+        U64(0), vec![], [0u8; 32], None, "default/path".to_string(),
+        ChainType::ETH, ChainType::SOL, "recipient".to_string(), "sub:0".to_string(),
+        SOL_DEST.to_string(),
+        "pay-tx".to_string(),
+    ).err();
+    assert_eq!(error, Some(OrderbookError::NoReceiveAddress { maker: user_alice(), chain_type: ChainType::SOL }));
+}
+
+#[test]
+fn test_receive_address_can_be_replaced_and_removed() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_receive_address(ChainType::SOL, Some(SOL_DEST.to_string()));
+    assert_eq!(contract.get_receive_address(user_alice(), ChainType::SOL), Some(SOL_DEST.to_string()));
+    assert_eq!(contract.get_receive_address(user_dave(), ChainType::SOL), None);
+    contract.set_receive_address(ChainType::SOL, None);
+    assert_eq!(contract.get_receive_address(user_alice(), ChainType::SOL), None);
+}
+
+#[test]
+#[should_panic(expected = "Invalid BTC destination bc1-bad")]
+fn test_malformed_receive_address_panics() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_receive_address(ChainType::BTC, Some("bc1-bad".to_string()));
+}
+
+// ============================================================================
+// 57. TRANSITION TX REPLAY PROTECTION
+// ============================================================================
+
+/// Two identical SOL → ETH fills (subs 0 and 2) and their ETH → SOL
+/// counterparts, all signed and awaiting transition proofs.
+fn setup_twin_settled_subs(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 200);
+    owner_deposit(contract, context, &user_charlie(), "ETH", 200);
+    let mut ids = vec![];
+    for _ in 0..2 {
+        testing_env!(context.predecessor_account_id(user_alice()).build());
+        ids.push(contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap());
+        testing_env!(context.predecessor_account_id(user_charlie()).build());
+        ids.push(contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap());
+    }
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, ids.into_iter().map(|id| mp(id, 100, 100)).collect())).unwrap();
+    for id in 0..4 {
+        contract.on_signed(SignTarget::SubIntent(id), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    }
+}
+
+fn submit_transition(contract: &mut Orderbook, context: &mut VMContextBuilder, sub: U64, tx_hash: &str) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    let _ = contract.verify_transition_completion(sub, vec![1], tx_hash.to_string());
+}
+
+#[test]
+#[should_panic(expected = "Transition tx 0xsame already proved another sub-intent")]
+fn test_transition_tx_cannot_prove_two_sub_intents() {
+    let (mut contract, mut context) = new_contract();
+    setup_twin_settled_subs(&mut contract, &mut context);
+    submit_transition(&mut contract, &mut context, U64(0), "0xsame");
+    contract.on_transition_verified(U64(0), "0xsame".to_string(), transition_verified(true));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().transition_tx_hash, Some("0xsame".to_string()));
+    assert!(contract.is_transition_consumed(ChainType::ETH, "0xsame".to_string()));
+    assert!(!contract.is_transition_consumed(ChainType::SOL, "0xsame".to_string()));
+
+    submit_transition(&mut contract, &mut context, U64(2), "0xsame");
+}
+
+#[test]
+fn test_in_flight_replay_is_rejected_in_callback() {
+    let (mut contract, mut context) = new_contract();
+    setup_twin_settled_subs(&mut contract, &mut context);
+    submit_transition(&mut contract, &mut context, U64(0), "0xsame");
+    submit_transition(&mut contract, &mut context, U64(2), "0xsame");
+
+    assert_eq!(contract.on_transition_verified(U64(0), "0xsame".to_string(), transition_verified(true)), "TransitionVerified");
+    assert_eq!(contract.on_transition_verified(U64(2), "0xsame".to_string(), transition_verified(true)), "TransitionVerifyFailed");
+    let sub = contract.get_sub_intent(U64(2)).unwrap();
+    assert_eq!(sub.status, SubIntentStatus::Settled);
+    assert_eq!(sub.transition_tx_hash, None);
+
+    // A distinct transaction still settles it
+    submit_transition(&mut contract, &mut context, U64(2), "0xother");
+    assert_eq!(contract.on_transition_verified(U64(2), "0xother".to_string(), transition_verified(true)), "TransitionVerified");
+    assert_eq!(contract.get_sub_intent(U64(2)).unwrap().transition_tx_hash, Some("0xother".to_string()));
+}
+
+#[test]
+fn test_failed_transition_proof_does_not_consume_tx() {
+    let (mut contract, mut context) = new_contract();
+    setup_twin_settled_subs(&mut contract, &mut context);
+    submit_transition(&mut contract, &mut context, U64(0), "0xsame");
+    contract.on_transition_verified(U64(0), "0xsame".to_string(), transition_verified(false));
+    assert!(!contract.is_transition_consumed(ChainType::ETH, "0xsame".to_string()));
+
+    submit_transition(&mut contract, &mut context, U64(2), "0xsame");
+    assert_eq!(contract.on_transition_verified(U64(2), "0xsame".to_string(), transition_verified(true)), "TransitionVerified");
+}
+
+// ============================================================================
+// 58. PAYMENT PROOF REPLAY PROTECTION
+// ============================================================================
+
+/// Two unescrowed takes of 1 SOL each on the seeded intent (subs 0 and 1).
+fn seed_twin_unescrowed_takes(contract: &mut Orderbook) {
+    seed_unescrowed_take(contract, 2);
+    let mut sub = contract.sub_intents.get(&0).unwrap();
+    sub.amount = 1;
+    contract.sub_intents.insert(&0, &sub);
+    contract.sub_intents.insert(&1, &SubIntent { id: 1, ..sub });
+}
+
+fn submit_payment(contract: &mut Orderbook, sub_intent_id: u64, tx_hash: &str) -> Result<Promise, OrderbookError> {
+    contract.submit_payment_proof(
+        U64(sub_intent_id), vec![], [0u8; 32], None, "default/path".to_string(),
+        ChainType::ETH, ChainType::SOL, "recipient".to_string(), format!("sub:{}", sub_intent_id),
+        SOL_DEST.to_string(), tx_hash.to_string(),
+    )
+}
+
+fn payment_proved(contract: &mut Orderbook, sub_intent_id: u64, tx_hash: &str) -> Promise {
+    contract.on_proof_verified(
+        U64(sub_intent_id), [0u8; 32], None, "default/path".to_string(), ChainType::SOL, SOL_DEST.to_string(),
+        Orderbook::transition_memo(sub_intent_id, 0), ChainType::ETH, tx_hash.to_string(), verified(true),
+    )
+}
+
+/// Seed an unescrowed take of SOL and prove its payment, which requests
+/// the SOL transition signature. Leaves the owner as the caller.
+fn proven_sol_take(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    seed_unescrowed_take(contract, 3);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(contract, 0, "pay-1").unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let _ = payment_proved(contract, 0, "pay-1");
+}
+
+fn light_client_calls() -> Vec<Vec<u8>> {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .filter_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, .. } => Some(method_name),
+            _ => None,
+        })
+        .filter(|m| m.starts_with(b"verify_") || m.starts_with(b"consume_"))
+        .collect()
+}
+
+#[test]
+fn test_payment_tx_hash_is_sent_to_light_client() {
+    let (mut contract, mut context) = new_contract();
+    seed_twin_unescrowed_takes(&mut contract);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 0, "pay-1").unwrap();
+    let args: near_sdk::serde_json::Value = near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .find_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, args, .. }
+                if method_name == b"consume_payment_proof" =>
+            {
+                Some(near_sdk::serde_json::from_slice(&args).unwrap())
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(args["expected_tx_hash"], "pay-1");
+}
+
+#[test]
+fn test_replayed_payment_rejected_before_any_promise() {
+    let (mut contract, mut context) = new_contract();
+    seed_twin_unescrowed_takes(&mut contract);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 0, "pay-1").unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let _ = payment_proved(&mut contract, 0, "pay-1");
+    assert!(contract.is_payment_consumed(ChainType::ETH, "pay-1".to_string()));
+
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = submit_payment(&mut contract, 1, "pay-1").err();
+    assert_eq!(error, Some(OrderbookError::PaymentConsumed { tx_hash: "pay-1".to_string() }));
+    assert!(light_client_calls().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Payment tx pay-1 already used")]
+fn test_in_flight_payment_replay_rejected_in_callback() {
+    let (mut contract, mut context) = new_contract();
+    seed_twin_unescrowed_takes(&mut contract);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 0, "pay-1").unwrap();
+    // A separate transaction, with its own gas
+    testing_env!(context.build());
+    let _ = submit_payment(&mut contract, 1, "pay-1").unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let _ = payment_proved(&mut contract, 0, "pay-1");
+    let _ = payment_proved(&mut contract, 1, "pay-1");
+}
+
+#[test]
+fn test_escrowed_take_does_not_consume_payment_hash() {
+    let (mut contract, mut context) = new_contract();
+    let id = setup_eth_take(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id, u(30)).unwrap();
+    let _ = submit_escrowed_take(&mut contract, sub).unwrap();
+    assert_eq!(contract.get_sub_intent(sub).unwrap().status, SubIntentStatus::Verifying);
+    assert!(!contract.is_payment_consumed(ChainType::SOL, "pay-tx".to_string()));
+}
+
+// ============================================================================
+// 59. TRANSITION MEMO
+// ============================================================================
+
+#[test]
+fn test_signature_event_memo_is_the_stored_expectation_memo() {
+    let (mut contract, mut context) = new_contract();
+    let subs = match_pair_as(&mut contract, &mut context, &orderbook_contract()).unwrap();
+    let contract_id = near_sdk::env::current_account_id();
+    for (i, sub) in subs.iter().enumerate() {
+        // Both fills were signed by the same batch, so they share its nonce
+        let memo = contract.get_transition_expectation(*sub).unwrap().expected_memo;
+        assert_eq!(memo, format!("transition:{}:{}:0", contract_id, i));
+
+        testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+        contract.on_signed(SignTarget::SubIntent(sub.0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+        assert_eq!(signature_event()["transition_memo"].as_str().unwrap().as_bytes(), memo.as_bytes());
+    }
+}
+
+#[test]
+fn test_retry_signs_under_the_first_memo() {
+    let (mut contract, mut context) = new_contract();
+    let subs = match_pair_as(&mut contract, &mut context, &orderbook_contract()).unwrap();
+    let first = contract.get_transition_expectation(subs[0]).unwrap().expected_memo;
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    let _ = contract.retry_settlement(subs[0]).unwrap();
+    let retried = contract.get_transition_expectation(subs[0]).unwrap().expected_memo;
+
+    assert_eq!(retried, first);
+    assert_eq!(
+        TransitionMemo::parse(&retried),
+        Some(TransitionMemo::V1 { contract: near_sdk::env::current_account_id(), sub_intent_id: 0, nonce: 0 })
+    );
+}
+
+#[test]
+fn test_transition_memo_parser_round_trips() {
+    for memo in ["transition:sub:7", "transition:orderbook.testnet:7:42"] {
+        let parsed = TransitionMemo::parse(memo).unwrap();
+        assert_eq!(parsed.sub_intent_id(), 7);
+        assert_eq!(parsed.to_string(), memo);
+    }
+    assert_eq!(TransitionMemo::parse("transition:sub:7"), Some(TransitionMemo::V0 { sub_intent_id: 7 }));
+    for memo in ["withdraw:0", "transition:sub:x", "transition:orderbook.testnet:7", "transition:a:1:2:3", ""] {
+        assert_eq!(TransitionMemo::parse(memo), None, "{}", memo);
+    }
+}
+
+// ============================================================================
+// 60. INTENT / SUB-INTENT STATUS SPLIT
+// ============================================================================
+
+#[test]
+#[should_panic(expected = "Sub-Intent cannot be Open")]
+fn test_legacy_status_rejects_intent_status_on_sub_intent() {
+    use crate::migration::LegacyStatus;
+    let _: SubIntentStatus = LegacyStatus::Open.into();
+}
+
+#[test]
+fn test_intent_and_sub_intent_lifecycles() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_matched_pair(&mut contract, &mut context);
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Verifying);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+
+    let _ = contract.verify_transition_completion(U64(0), vec![1], "tx-a".to_string());
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::TransitionVerifying);
+
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_transition_verified(U64(0), "tx-a".to_string(), transition_verified(true));
+    let sub = contract.get_sub_intent(U64(0)).unwrap();
+    assert_eq!(sub.status, SubIntentStatus::Completed);
+    assert!(sub.status.is_final());
+    // A filled intent stays open to cleanup only through its sub-intents
+    assert!(!contract.get_intent(id_a).unwrap().status.is_final());
+}
+
+#[test]
+fn test_status_json_uses_split_names() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, _) = setup_completed_pair(&mut contract, &mut context);
+    contract.cleanup(vec![U64(0)], RecordKind::SubIntent);
+
+    let intent = near_sdk::serde_json::to_value(contract.get_intent(id_a).unwrap()).unwrap();
+    assert_eq!(intent["status"], "Filled");
+    let sub = near_sdk::serde_json::to_value(contract.get_sub_intent(U64(1)).unwrap()).unwrap();
+    assert_eq!(sub["status"], "Completed");
+    let tombstone = near_sdk::serde_json::to_value(contract.get_tombstone(RecordKind::SubIntent, U64(0))).unwrap();
+    assert_eq!(tombstone["status"], "Completed");
+}
+
+// ============================================================================
+// 61. SOLVENCY
+// ============================================================================
+
+fn assert_solvent(contract: &Orderbook, asset: &str, liabilities: u128, credited: u128, debited: u128) {
+    let report = contract.check_solvency(asset.to_string());
+    assert_eq!(
+        report,
+        SolvencyReport {
+            asset: asset.to_string(),
+            liabilities: u(liabilities),
+            credited: u(credited),
+            debited: u(debited),
+            ok: true,
+        }
+    );
+}
+
+#[test]
+fn test_solvency_holds_through_full_lifecycle() {
+    let (mut contract, mut context) = new_contract();
+    register_storage(&mut contract, &mut context, &user_alice());
+    register_storage(&mut contract, &mut context, &solver_bob());
+    let alice = user_alice();
+    let bob = solver_bob();
+    assert_solvent(&contract, "WSOL", 0, 0, 0);
+
+    // 1. Deposit
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_mpc_deposit_verified(
+        alice.clone(), ChainType::ETH, "WSOL".to_string(), U128(1000),
+        "alice-mpc".to_string(), format!("mpc:deposit:{}:WSOL", alice), "dep-tx-3".to_string(), verified(true),
+    );
+    contract.on_mpc_deposit_verified(
+        bob.clone(), ChainType::ETH, "ETH".to_string(), U128(500),
+        "bob-mpc".to_string(), format!("mpc:deposit:{}:ETH", bob), "dep-tx-4".to_string(), verified(true),
+    );
+    assert_solvent(&contract, "WSOL", 1000, 1000, 0);
+    assert_solvent(&contract, "ETH", 500, 500, 0);
+
+    // 2. Make intents: balances move into the open intents
+    testing_env!(context.predecessor_account_id(alice.clone()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(1000), "ETH".to_string(), u(500), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(bob.clone()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(500), "WSOL".to_string(), u(1000), None, None, None, None, None, None).unwrap();
+    assert_eq!(contract.get_balance(alice.clone(), "WSOL".to_string()), u(0));
+    assert_solvent(&contract, "WSOL", 1000, 1000, 0);
+    assert_solvent(&contract, "ETH", 500, 500, 0);
+
+    // 3. Batch match: the fills become escrowed proceeds
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, vec![
+        mp_with_chain(id_a, 1000, 500, ChainType::ETH),
+        mp_with_chain(id_b, 500, 1000, ChainType::ETH),
+    ])).unwrap();
+    assert!(contract.get_escrow(U64(0)).is_some());
+    assert_solvent(&contract, "WSOL", 1000, 1000, 0);
+    assert_solvent(&contract, "ETH", 500, 500, 0);
+
+    // 4. MPC signatures
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_solvent(&contract, "WSOL", 1000, 1000, 0);
+
+    // 5. Transition verification releases the escrows to the makers
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_transition_verified(U64(0), "tx-a".to_string(), transition_verified(true));
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_transition_verified(U64(1), "tx-b".to_string(), transition_verified(true));
+    assert_eq!(contract.get_balance(alice.clone(), "ETH".to_string()), u(500));
+    assert_solvent(&contract, "WSOL", 1000, 1000, 0);
+    assert_solvent(&contract, "ETH", 500, 500, 0);
+
+    // 6. Withdrawal: pending while signing, debited once signed
+    testing_env!(context.predecessor_account_id(alice.clone()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw(
+        "ETH".to_string(), u(200), ETH_DEST.to_string(), eth_tx(200),
+        own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap();
+    assert_solvent(&contract, "ETH", 500, 500, 0);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_solvent(&contract, "ETH", 300, 500, 200);
+
+    // 7. A SOL withdrawal books its signature fee in SOL, not ETH
+    owner_deposit(&mut contract, &mut context, &alice, "SOL", 100_000);
+    testing_env!(context.predecessor_account_id(alice.clone()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw(
+        "SOL".to_string(), u(50_000), SOL_DEST.to_string(), sol_transfer(SOL_DEST, 50_000),
+        own_path(&ChainType::SOL), ChainType::SOL, SignatureScheme::Ed25519,
+    ).unwrap();
+    assert_eq!(contract.get_pending_withdrawal(U64(1)).unwrap().gas_fee, 5_000);
+    assert_solvent(&contract, "SOL", 100_000, 100_000, 0);
+    assert_solvent(&contract, "ETH", 300, 500, 200);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(1), ChainType::SOL, vec![9u8; 32], "default/path".to_string(), Ok(mock_ed25519_sig()));
+    assert_solvent(&contract, "SOL", 45_000, 100_000, 55_000);
+}
+
+#[test]
+fn test_refunded_withdrawal_is_not_debited() {
+    let (mut contract, mut context) = new_contract();
+    withdraw_to(&mut contract, &mut context, ChainType::ETH, ETH_DEST).unwrap();
+    assert_solvent(&contract, "ETH", 100, 100, 0);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_solvent(&contract, "ETH", 100, 100, 0);
+}
+
+#[test]
+fn test_taker_escrow_counts_as_liability() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 60);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(50), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.take_intent(id, u(100)).unwrap();
+
+    assert_eq!(contract.get_balance(user_charlie(), "ETH".to_string()), u(10));
+    // The taken SOL is owed to the taker off-chain, so only the escrow remains
+    assert_solvent(&contract, "ETH", 160, 160, 0);
+    assert!(contract.check_solvency("WSOL".to_string()).ok);
+}
+
+#[test]
+#[should_panic(expected = "Insolvent in ETH: liabilities 101 + debited 0 exceed credited 100")]
+fn test_assert_solvency_panics_when_liabilities_exceed_credits() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    contract.internal_credit(&user_charlie(), "ETH", 1, BalanceChangeReason::Deposit, None);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.assert_solvency("eth".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Only owner can assert solvency")]
+fn test_assert_solvency_non_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.assert_solvency("ETH".to_string());
+}
+
+// ============================================================================
+// 62. BATCH WITHDRAW
+// ============================================================================
+
+fn withdraw_item(amount: u128, nonce: u64) -> WithdrawItem {
+    WithdrawItem {
+        asset: "ETH".to_string(),
+        amount: u(amount),
+        destination: ETH_DEST.to_string(),
+        unsigned_tx: eth_tx_with(DEFAULT_ETH_CHAIN_ID, nonce, ETH_DEST, amount, &[]),
+        path: own_path(&ChainType::ETH),
+        chain_type: ChainType::ETH,
+        scheme: SignatureScheme::Secp256k1,
+    }
+}
+
+fn sign_deposits() -> Vec<u128> {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .filter_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, attached_deposit, .. } if method_name == b"sign" => {
+                Some(attached_deposit.as_yoctonear())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_batch_withdraw_failure_refunds_only_its_item() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_yoctonear(10)).build());
+    let ids = contract.batch_withdraw(vec![withdraw_item(30, 0), withdraw_item(20, 1)]);
+    assert_eq!(ids, vec![U64(0), U64(1)]);
+    assert_eq!(sign_deposits(), vec![5, 5]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(50));
+    assert_eq!(contract.get_pending_withdrawals(user_alice()).len(), 2);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(1), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+
+    // Only the failed 20 comes back
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(70));
+    assert!(contract.get_pending_withdrawals(user_alice()).is_empty());
+    assert!(contract.get_signature(SignTarget::Withdrawal(0)).is_some());
+    assert_eq!(contract.get_stats().total_withdrawals, 1);
+}
+
+#[test]
+#[should_panic(expected = "Insufficient ETH balance: have 40, need 60")]
+fn test_batch_withdraw_checks_items_against_remaining_balance() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_yoctonear(2)).build());
+    contract.batch_withdraw(vec![withdraw_item(60, 0), withdraw_item(60, 1)]);
+}
+
+#[test]
+#[should_panic(expected = "Max 4 withdrawals per batch (gas limit)")]
+fn test_batch_withdraw_item_limit() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_withdraw((0..5).map(|n| withdraw_item(1, n)).collect());
+}
+
+#[test]
+#[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR per withdrawal")]
+fn test_batch_withdraw_needs_deposit_per_item() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.batch_withdraw(vec![withdraw_item(30, 0), withdraw_item(20, 1)]);
+}
+
+// ============================================================================
+// 63. REFERRAL FEE SHARE
+// ============================================================================
+
+/// As `setup_fee_match`, but 10_000 each way and Alice's intent referred
+/// by Charlie.
+fn setup_referred_match(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 10_000);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 10_000);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent(
+        "WSOL".to_string(), u(10_000), "ETH".to_string(), u(10_000), None, None, None, None, Some(user_charlie()),
+        None,
+    ).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(10_000), "WSOL".to_string(), u(10_000), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 10_000, 10_000), mp(id_b, 10_000, 10_000)])).unwrap();
+}
+
+#[test]
+fn test_referrer_gets_its_share_of_the_fee() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(MAX_FEE_BPS);
+    contract.set_fee_collector(user_dave());
+    contract.set_referral_bps(2_500);
+    setup_referred_match(&mut contract, &mut context);
+
+    // 10_000 * 100 / 10_000 = 100 fee; 100 * 2_500 / 10_000 = 25 to Charlie
+    let escrow = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow.amount, escrow.fee, escrow.referral_fee), (9_900, 100, 25));
+    assert_eq!(escrow.referrer, Some(user_charlie()));
+
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(9_900));
+    assert_eq!(contract.get_balance(user_charlie(), "ETH".to_string()), u(25));
+    assert_eq!(contract.get_balance(user_dave(), "ETH".to_string()), u(75));
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(75));
+    assert_eq!(contract.get_referral_earnings(user_charlie()), vec![("ETH".to_string(), u(25))]);
+    // Bob's intent has no referrer: the collector keeps the whole fee
+    assert_eq!(contract.get_balance(user_dave(), "WSOL".to_string()), u(100));
+    assert_eq!(contract.get_accrued_fees("WSOL".to_string()), u(100));
+
+    let paid: Vec<String> = near_sdk::test_utils::get_logs()
+        .into_iter()
+        .filter(|l| l.starts_with("REFERRAL_PAID:"))
+        .collect();
+    assert_eq!(
+        paid,
+        vec![format!("REFERRAL_PAID:sub_intent_id=0,referrer={},asset=ETH,amount=25", user_charlie())]
+    );
+}
+
+#[test]
+fn test_referral_without_fee_pays_nothing() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_referral_bps(2_500);
+    setup_referred_match(&mut contract, &mut context);
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(10_000));
+    assert_eq!(contract.get_balance(user_charlie(), "ETH".to_string()), u(0));
+    assert!(contract.get_referral_earnings(user_charlie()).is_empty());
+    assert!(!near_sdk::test_utils::get_logs().iter().any(|l| l.starts_with("REFERRAL_PAID:")));
+}
+
+#[test]
+fn test_referral_share_defaults_to_zero() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(MAX_FEE_BPS);
+    contract.set_fee_collector(user_dave());
+    setup_referred_match(&mut contract, &mut context);
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+
+    assert_eq!(contract.get_balance(user_dave(), "ETH".to_string()), u(100));
+    assert!(contract.get_referral_earnings(user_charlie()).is_empty());
+}
+
+#[test]
+fn test_self_referral_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, Some(user_alice()), None).unwrap_err();
+    assert_eq!(error, OrderbookError::SelfReferral);
+}
+
+#[test]
+#[should_panic(expected = "Referral share exceeds 10000 bps")]
+fn test_referral_share_above_whole_fee_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_referral_bps(10_001);
+}
+
+// ============================================================================
+// 64. BATCH SIGNING DEPOSIT
+// ============================================================================
+
+/// Alice sells 100 SOL for 100 ETH; Bob and Dave each sell her 50 ETH.
+fn setup_three_leg_intents(contract: &mut Orderbook, context: &mut VMContextBuilder) -> Vec<MatchParams> {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 50);
+    owner_deposit(contract, context, &user_dave(), "ETH", 50);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(50), "WSOL".to_string(), u(50), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_dave()).build());
+    let id_d = contract.make_intent("ETH".to_string(), u(50), "WSOL".to_string(), u(50), None, None, None, None, None, None).unwrap();
+    vec![mp(id_a, 100, 100), mp(id_b, 50, 50), mp(id_d, 50, 50)]
+}
+
+#[test]
+fn test_batch_match_refunds_undivided_deposit() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_three_leg_intents(&mut contract, &mut context);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, matches)).unwrap();
+
+    // (1 NEAR - 3 bonds) / 3 leaves 1 yoctoNEAR over
+    let per_sign = (NearToken::from_near(1).as_yoctonear() - 3 * DEFAULT_TRANSITION_BOND) / 3;
+    assert_eq!(sign_deposits(), vec![per_sign; 3]);
+    assert_eq!(transfers_to(&orderbook_contract()), vec![1]);
+}
+
+#[test]
+fn test_batch_match_with_even_deposit_refunds_nothing() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_three_leg_intents(&mut contract, &mut context);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_yoctonear(3 * DEFAULT_TRANSITION_BOND + 30))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(&contract, matches)).unwrap();
+    assert_eq!(sign_deposits(), vec![10; 3]);
+    assert!(transfers_to(&orderbook_contract()).is_empty());
+}
+
+#[test]
+fn test_underfunded_batch_rejected_before_any_state_change() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_three_leg_intents(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_min_sign_deposit(u(5));
+    testing_env!(context
+        .attached_deposit(NearToken::from_yoctonear(3 * (DEFAULT_TRANSITION_BOND + 5) - 1))
+        .build()
+    );
+    let error = contract.batch_match_intents(bind_assets(&contract, matches.clone())).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::InsufficientDeposit {
+            required: 3 * (DEFAULT_TRANSITION_BOND + 5),
+            attached: 3 * (DEFAULT_TRANSITION_BOND + 5) - 1,
+        }
+    );
+    assert_eq!(contract.get_intent(matches[0].intent_id).unwrap().filled_amount, 0);
+    assert_eq!(contract.next_sub_intent_id, 0);
+    assert_eq!(contract.next_transition_nonce, 0);
+    assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Only owner can set sign deposit")]
+fn test_set_min_sign_deposit_non_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_min_sign_deposit(u(5));
+}
+
+// ============================================================================
+// 65. DEFERRED BATCH MATCHING
+// ============================================================================
+
+/// Alice sells `legs * 100` SOL for ETH; Bob fills her with `legs` intents of 100 ETH.
+fn setup_wide_batch(contract: &mut Orderbook, context: &mut VMContextBuilder, legs: u128) -> Vec<MatchParams> {
+    owner_deposit(contract, context, &user_alice(), "WSOL", legs * 100);
+    owner_deposit(contract, context, &solver_bob(), "ETH", legs * 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a =
+        contract.make_intent("WSOL".to_string(), u(legs * 100), "ETH".to_string(), u(legs * 100), None, None, None, None, None, None).unwrap();
+    let mut matches = vec![mp(id_a, legs * 100, legs * 100)];
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    for _ in 0..legs {
+        let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+        matches.push(mp(id_b, 100, 100));
+    }
+    matches
+}
+
+fn batch_deposit(subs: u128) -> NearToken {
+    NearToken::from_yoctonear(subs * (DEFAULT_TRANSITION_BOND + 10))
+}
+
+#[test]
+fn test_deferred_batch_queues_signs_and_drains_in_chunks() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_wide_batch(&mut contract, &mut context, 9);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(batch_deposit(10))
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    let sub_ids = contract.batch_match_intents_deferred(bind_assets(&contract, matches.clone()));
+    assert_eq!(sub_ids.len(), 10);
+    assert!(sign_deposits().is_empty());
+    assert_eq!(contract.get_pending_sign_count(), 10);
+    for (m, id) in matches.iter().zip(&sub_ids) {
+        assert_eq!(contract.get_sub_intent(*id).unwrap().status, SubIntentStatus::AwaitingSign);
+        assert_eq!(contract.get_intent(m.intent_id).unwrap().status, IntentStatus::Filled);
+        assert_eq!(contract.get_sign_job(*id).unwrap().deposit, 10);
+    }
+
+    // Anyone drains the queue, at most four requests per call
+    testing_env!(context.predecessor_account_id(user_charlie()).attached_deposit(NearToken::from_near(0)).build());
+    assert_eq!(contract.process_sign_queue(50), 4);
+    assert_eq!(sign_deposits(), vec![10; 4]);
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    assert_eq!(contract.process_sign_queue(4), 4);
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    assert_eq!(contract.process_sign_queue(50), 2);
+    assert_eq!(sign_deposits(), vec![10; 2]);
+    assert_eq!(contract.get_pending_sign_count(), 0);
+    for id in &sub_ids {
+        assert_eq!(contract.get_sub_intent(*id).unwrap().status, SubIntentStatus::Verifying);
+    }
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    assert_eq!(contract.process_sign_queue(50), 0);
+}
+
+#[test]
+fn test_deferred_batch_respects_its_own_limit() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_wide_batch(&mut contract, &mut context, 20);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(batch_deposit(21))
+        .build()
+    );
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.batch_match_intents_deferred(bind_assets(&contract, matches.clone()));
+    }));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("Max 20 intents per batch (gas limit)"));
+    assert_eq!(contract.get_pending_sign_count(), 0);
+}
+
+#[test]
+fn test_immediate_batch_still_capped_at_six() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_wide_batch(&mut contract, &mut context, 6);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(batch_deposit(7))
+        .build()
+    );
+    let error = contract.batch_match_intents(bind_assets(&contract, matches)).unwrap_err();
+    assert_eq!(error, OrderbookError::TooManyMatches { max: 6 });
+}
+
+#[test]
+fn test_force_cancel_refunds_queued_sign_deposit() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_wide_batch(&mut contract, &mut context, 1);
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(batch_deposit(2))
+        .build()
+    );
+    let sub_ids = contract.batch_match_intents_deferred(bind_assets(&contract, matches));
+
+    testing_env!(context.attached_deposit(NearToken::from_near(0)).build());
+    contract.force_cancel_sub_intent(sub_ids[0], true, "stuck".to_string());
+    assert!(contract.get_sign_job(sub_ids[0]).is_none());
+    assert_eq!(transfers_to(&orderbook_contract()), vec![10, DEFAULT_TRANSITION_BOND]);
+
+    // The cancelled leg's job is gone; the other one is still sent
+    testing_env!(context.build());
+    assert_eq!(contract.process_sign_queue(6), 1);
+    assert_eq!(contract.get_sub_intent(sub_ids[1]).unwrap().status, SubIntentStatus::Verifying);
+}
+
+// ============================================================================
+// 66. MPC-DERIVED DEPOSIT ADDRESSES
+// ============================================================================
+
+/// The generator point: the public key of private key 1.
+const KEY_G: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+const KEY_G_UNCOMPRESSED: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+                                  483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+/// -G, the public key of private key n - 1; its y is odd.
+const KEY_MINUS_G: &str = "0379be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+#[test]
+fn test_public_key_addresses_match_known_vectors() {
+    use crate::derivation::{btc_p2wpkh_address, eth_address, parse_public_key};
+    let _ = new_contract();
+    let g = parse_public_key(KEY_G).unwrap();
+    assert_eq!(eth_address(&g), "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+    // BIP173's P2WPKH example
+    assert_eq!(btc_p2wpkh_address(&g), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+}
+
+#[test]
+fn test_derive_deposit_address_matches_reference_vectors() {
+    let (mut contract, mut context) = new_contract();
+    contract.set_mpc_root_key(ChainType::ETH, KEY_G.to_string());
+    contract.set_mpc_root_key(ChainType::BTC, KEY_G.to_string());
+    assert_eq!(deposit_path(&user_alice(), &ChainType::ETH), "deposit/eugene/ETH");
+
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    assert_eq!(
+        contract.derive_deposit_address(user_alice(), ChainType::ETH),
+        "0x04cf2652d61eb22320ea0e1d881b6cb986a05a22"
+    );
+    assert_eq!(
+        contract.derive_deposit_address(user_dave(), ChainType::ETH),
+        "0x9286223fe32c557ead59a4a87011c5f0b09481a9"
+    );
+    assert_eq!(
+        contract.derive_deposit_address(user_alice(), ChainType::BTC),
+        "bc1qv2ye8gpua76p78thfvkfhl3uy9dpuenn6xff39"
+    );
+}
+
+#[test]
+fn test_derive_deposit_address_from_odd_root_key() {
+    let (mut contract, _) = new_contract();
+    contract.set_mpc_root_key(ChainType::ETH, KEY_MINUS_G.to_string());
+    contract.set_mpc_root_key(ChainType::BTC, format!("0x{}", KEY_MINUS_G));
+    assert_eq!(
+        contract.derive_deposit_address(user_alice(), ChainType::ETH),
+        "0xbf033fc5314e05544f6f4b367631df97c5d7679b"
+    );
+    assert_eq!(
+        contract.derive_deposit_address(user_alice(), ChainType::BTC),
+        "bc1qsufm2mpwxnaq5u69jj2ns0yy0uf4j0w9w76s88"
+    );
+}
+
+#[test]
+fn test_mpc_root_key_accepts_uncompressed_and_rejects_off_curve() {
+    let (mut contract, _) = new_contract();
+    contract.set_mpc_root_key(ChainType::ETH, KEY_G_UNCOMPRESSED.to_string());
+    assert_eq!(contract.get_mpc_root_key(ChainType::ETH).unwrap(), KEY_G_UNCOMPRESSED);
+    contract.set_mpc_root_key(ChainType::BTC, KEY_G.to_string());
+    assert_eq!(contract.get_mpc_root_key(ChainType::BTC).unwrap(), KEY_G_UNCOMPRESSED);
+
+    let off_curve = format!("{}9", &KEY_G_UNCOMPRESSED[..129]);
+    for key in [off_curve.as_str(), "04", "", "05ab", &KEY_G[..64]] {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.set_mpc_root_key(ChainType::ETH, key.to_string());
+        }));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("Invalid secp256k1 public key"), "{}: {}", key, message);
+    }
+}
+
+#[test]
+#[should_panic(expected = "No MPC root key set for ETH")]
+fn test_derive_deposit_address_without_root_key_panics() {
+    let (contract, _) = new_contract();
+    contract.derive_deposit_address(user_alice(), ChainType::ETH);
+}
+
+#[test]
+#[should_panic(expected = "SOL deposit addresses need Ed25519 key derivation")]
+fn test_derive_sol_deposit_address_panics() {
+    let (mut contract, _) = new_contract();
+    contract.set_mpc_root_key(ChainType::SOL, KEY_G.to_string());
+}
+
+#[test]
+#[should_panic(expected = "Only owner can set MPC root keys")]
+fn test_set_mpc_root_key_non_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_mpc_root_key(ChainType::ETH, KEY_G.to_string());
+}
+
+// ============================================================================
+// 67. SIGNATURE VERIFICATION
+// ============================================================================
+
+/// Signature over `[1u8; 32]` by the key MPC derives from `KEY_G` for this
+/// contract under "default/path".
+fn derived_key_sig() -> SignResult {
+    SignResult::Secp256k1 {
+        big_r: AffinePoint {
+            affine_point: "03264640c2113e5710429dd274d5a511bd810f69d7b83662c389507ee7b9c5051f".to_string(),
+        },
+        s: Scalar { scalar: "7008e0876b9e7206b7197dd1dfbbbe15cfdbec5c6496eaed71fb5e5d72c88687".to_string() },
+        recovery_id: 1,
+    }
+}
+
+const DERIVED_KEY: &str = "04fcc53fc11dffab7e0f24b9ca8a485f3b7fddbb3e1044a8c808afa4994eb5817340907a0fa3e3377fef1d18151f41acec374f040e5b8c8105d52a2352751b45a3";
+
+fn sign_matched_pair_with(contract: &mut Orderbook, context: &mut VMContextBuilder, sig: SignResult) -> String {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_mpc_root_key(ChainType::ETH, KEY_G.to_string());
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(sig))
+}
+
+#[test]
+fn test_signature_from_derived_key_is_accepted() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    assert_eq!(sign_matched_pair_with(&mut contract, &mut context, derived_key_sig()), "Success");
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(signature_event()["public_key"], DERIVED_KEY);
+}
+
+#[test]
+fn test_corrupted_signature_rolls_back_sub_intent() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    let mut sig = derived_key_sig();
+    if let SignResult::Secp256k1 { s, .. } = &mut sig {
+        s.scalar.replace_range(62.., "00");
+    }
+    assert_eq!(sign_matched_pair_with(&mut contract, &mut context, sig), "Failed");
+
+    let sub = contract.get_sub_intent(U64(0)).unwrap();
+    assert_eq!(sub.status, SubIntentStatus::Taken);
+    assert!(contract.get_signature(SignTarget::SubIntent(0)).is_none());
+    let logs = near_sdk::test_utils::get_logs();
+    assert!(logs.iter().any(|l| l.starts_with("SIGNATURE_REJECTED:target=SubIntent(0),reason=Signature recovers to 04")));
+    assert!(!logs.iter().any(|l| l.starts_with("EVENT_JSON:") && !l.contains("\"sub_intent_sign_failed\"")));
+}
+
+#[test]
+fn test_signature_from_other_path_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    // Valid signature, but by the key derived under "other/path"
+    let sig = SignResult::Secp256k1 {
+        big_r: AffinePoint {
+            affine_point: "0220839e28771736fbfad2af9e7085bd0f0d932ccd30b7c51772a3268a8a1bbb82".to_string(),
+        },
+        s: Scalar { scalar: "7c0a57707d8357cf513cfeb1283018ba743f52abd4e71b8fbfcb7117d8455af4".to_string() },
+        recovery_id: 0,
+    };
+    assert_eq!(sign_matched_pair_with(&mut contract, &mut context, sig), "Failed");
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Taken);
+    assert!(near_sdk::test_utils::get_logs()
+        .iter()
+        .any(|l| l.ends_with("not the key derived for path default/path")));
+}
+
+#[test]
+fn test_unparseable_signature_rejected_once_root_key_is_set() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    assert_eq!(sign_matched_pair_with(&mut contract, &mut context, mock_sig()), "Failed");
+    assert!(near_sdk::test_utils::get_logs()
+        .iter()
+        .any(|l| l.ends_with("reason=Signature does not recover to a public key")));
+}
+
+#[test]
+fn test_signature_without_root_key_reports_recovered_key() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    let r = contract.on_signed(
+        SignTarget::SubIntent(0),
+        ChainType::ETH,
+        vec![1u8; 32],
+        "default/path".to_string(),
+        Ok(derived_key_sig()),
+    );
+    assert_eq!(r, "Success");
+    assert_eq!(signature_event()["public_key"], DERIVED_KEY);
+}
+
+#[test]
+fn test_rejected_withdrawal_signature_refunds_user() {
+    let (mut contract, mut context) = new_contract();
+    contract.set_mpc_root_key(ChainType::ETH, KEY_G.to_string());
+    withdraw_to(&mut contract, &mut context, ChainType::ETH, ETH_DEST).unwrap();
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(50));
+    let wd_id = U64(0);
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let r = contract.on_signed(
+        SignTarget::Withdrawal(wd_id.0),
+        ChainType::ETH,
+        vec![1u8; 32],
+        "default/path".to_string(),
+        Ok(mock_sig()),
+    );
+    assert_eq!(r, "Failed");
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+    assert!(contract.get_pending_withdrawal(wd_id).is_none());
+}
+
+// ============================================================================
+// 68. DERIVATION PATH VALIDATION
+// ============================================================================
+
+#[test]
+fn test_withdraw_with_foreign_path_fails() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50),
+        user_path(&user_dave(), &ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::WrongWithdrawalPath { expected: user_path(&user_alice(), &ChainType::ETH), got: user_path(&user_dave(), &ChainType::ETH) });
+}
+
+#[test]
+fn test_batch_withdraw_with_foreign_path_signs_nothing() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let mut items = vec![withdraw_item(30, 0), withdraw_item(20, 1)];
+    items[1].path = "treasury/eth".to_string();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.batch_withdraw(items.clone());
+    }));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("Withdrawal path must be user/eugene/ETH, got treasury/eth"));
+    assert!(sign_deposits().is_empty());
+}
+
+#[test]
+fn test_batch_match_with_foreign_path_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    let mut matches = setup_three_leg_intents(&mut contract, &mut context);
+    matches[2].path = user_path(&user_dave(), &ChainType::ETH);
+
+    let matches = bind_assets(&contract, matches);
+    let simulation = contract.simulate_batch_match(orderbook_contract(), matches.clone());
+    assert_eq!(simulation.errors[0], "Path user/dave.testnet/ETH is not the ETH treasury path");
+    assert_eq!(simulation.matches[2].error.as_deref(), Some("Path user/dave.testnet/ETH is not the ETH treasury path"));
+
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    let error = contract.batch_match_intents(matches.clone()).unwrap_err();
+    assert_eq!(error.to_string(), "Path user/dave.testnet/ETH is not the ETH treasury path");
+    assert_eq!(contract.next_sub_intent_id, 0);
+    assert!(sign_deposits().is_empty());
+}
+
+#[test]
+fn test_batch_match_follows_configured_treasury_path() {
+    let (mut contract, mut context) = new_contract();
+    let mut matches = setup_three_leg_intents(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_treasury_path(ChainType::ETH, Some("treasury/eth".to_string()));
+    assert_eq!(contract.get_treasury_path(ChainType::ETH), Some("treasury/eth".to_string()));
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, matches.clone()));
+    let errors: Vec<_> = simulation.matches.iter().map(|m| m.error.as_deref()).collect();
+    let wrong_path = Some("Path default/path is not the ETH treasury path");
+    assert_eq!(errors, [wrong_path; 3]);
+
+    for m in matches.iter_mut() {
+        m.path = "treasury/eth".to_string();
+    }
+    testing_env!(context.attached_deposit(NearToken::from_near(1)).build());
+    assert_eq!(contract.batch_match_intents(bind_assets(&contract, matches)).unwrap().len(), 3);
+}
+
+#[test]
+fn test_batch_match_without_treasury_path_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    let matches = setup_three_leg_intents(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_treasury_path(ChainType::ETH, None);
+    assert_eq!(contract.get_treasury_path(ChainType::ETH), None);
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, matches));
+    let errors: Vec<_> = simulation.matches.iter().map(|m| m.error.as_deref()).collect();
+    assert_eq!(errors, [Some("No treasury path configured for ETH"); 3]);
+}
+
+#[test]
+fn test_retry_settlement_after_treasury_path_change_fails() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(
+        SignTarget::SubIntent(0),
+        ChainType::ETH,
+        vec![1u8; 32],
+        "default/path".to_string(),
+        Err(near_sdk::PromiseError::Failed),
+    );
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Taken);
+    contract.set_treasury_path(ChainType::ETH, Some("treasury/sol".to_string()));
+
+    testing_env!(context.attached_deposit(NearToken::from_near(1)).prepaid_gas(Gas::from_tgas(300)).build());
+    let error = contract.retry_settlement(U64(0)).err();
+    assert_eq!(error, Some(OrderbookError::NotTreasuryPath { chain_type: ChainType::ETH, path: "default/path".to_string() }));
+}
+
+#[test]
+#[should_panic(expected = "Only owner can change config")]
+fn test_set_treasury_path_non_owner_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_treasury_path(ChainType::ETH, Some("evil/path".to_string()));
+}
+
+// ============================================================================
+// 69. SETTLEMENT RETRY PARAMETERS
+// ============================================================================
+
+/// Match Alice and Bob with Alice's transition signed over `[9u8; 32]`, then
+/// fail that signature so her sub-intent is back in `Taken`.
+fn setup_failed_settlement(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
+    testing_env!(context.attached_deposit(NearToken::from_near(0)).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(
+        SignTarget::SubIntent(0),
+        ChainType::ETH,
+        vec![9u8; 32],
+        "default/path".to_string(),
+        Err(near_sdk::PromiseError::Failed),
+    );
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Taken);
+}
+
+fn retry_as_solver(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    let _ = contract.retry_settlement(U64(0)).unwrap();
+}
+
+#[test]
+fn test_retry_reuses_stored_settlement_request() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_settlement(&mut contract, &mut context);
+    let request = contract.get_settlement_request(U64(0)).unwrap();
+    let tx = request.unsigned_tx.clone().unwrap();
+    assert_eq!(
+        request,
+        SettlementRequest {
+            payload: env::keccak256_array(&tx),
+            path: "default/path".to_string(),
+            chain_type: ChainType::ETH,
+            scheme: SignatureScheme::Secp256k1,
+            recipient: ETH_DEST.to_string(),
+            unsigned_tx: Some(tx),
+        }
+    );
+
+    retry_as_solver(&mut contract, &mut context);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Verifying);
+    let args = sign_request_args();
+    assert_eq!(args.len(), 1);
+    assert_eq!(args[0]["request"]["payload_v2"]["Ecdsa"], hex::encode(request.payload));
+    assert_eq!(args[0]["request"]["path"], "default/path");
+    let expectation = contract.get_transition_expectation(U64(0)).unwrap();
+    assert_eq!((expectation.chain_type, expectation.expected_recipient), (ChainType::ETH, ETH_DEST.to_string()));
+}
+
+/// Alice's 100 ETH → SOL intent matched by Bob (sub 0) with the unsigned
+/// ETH transaction paying her, then rolled back by a failed sign.
+fn setup_failed_eth_settlement(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    owner_deposit(contract, context, &user_alice(), "ETH", 100);
+    owner_deposit(contract, context, &solver_bob(), "WSOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.attached_deposit(NearToken::from_near(1)).build());
+    let tx = settlement_tx(100, 20_000_000_000, &contract.next_batch_memo(0));
+    let alice_match = MatchParams { payload: env::keccak256_array(&tx), unsigned_tx: Some(tx), ..mp(id_a, 100, 100) };
+    contract.batch_match_intents(bind_assets(contract, vec![alice_match, mp(id_b, 100, 100)])).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(0))
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(PromiseError::Failed));
+}
+
+/// Alice's settlement transaction paying `value` under `memo` at
+/// `max_fee_per_gas`.
+fn settlement_tx(value: u128, max_fee_per_gas: u128, memo: &str) -> Vec<u8> {
+    let tx = eth_tx_priced(DEFAULT_ETH_CHAIN_ID, 0, ETH_DEST, value, memo.as_bytes(), max_fee_per_gas);
+    hex::decode(tx.trim_start_matches("0x")).unwrap()
+}
+
+/// Memo the transition of `sub` is expected to carry.
+fn sub_memo(contract: &Orderbook, sub: U64) -> String {
+    contract.get_transition_expectation(sub).unwrap().expected_memo
+}
+
+fn propose_as_solver(contract: &mut Orderbook, context: &mut VMContextBuilder, tx: Vec<u8>) {
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.propose_settlement_tx(U64(0), tx);
+}
+
+#[test]
+fn test_maker_approves_repriced_settlement_tx() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_eth_settlement(&mut contract, &mut context);
+    let repriced = settlement_tx(100, 30_000_000_000, &sub_memo(&contract, U64(0)));
+    let payload = env::keccak256_array(&repriced);
+    propose_as_solver(&mut contract, &mut context, repriced.clone());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.replace_settlement_payload(U64(0), payload);
+
+    let request = contract.get_settlement_request(U64(0)).unwrap();
+    assert_eq!((request.payload, request.path.as_str()), (payload, "default/path"));
+    assert_eq!(request.unsigned_tx, Some(repriced.clone()));
+    testing_env!(context
+        .predecessor_account_id(solver_bob())
+        .attached_deposit(NearToken::from_near(1))
+        .prepaid_gas(Gas::from_tgas(300))
+        .build()
+    );
+    let _ = contract.retry_settlement(U64(0)).unwrap();
+    assert_eq!(sign_request_args()[0]["request"]["payload_v2"]["Ecdsa"], hex::encode(payload));
+    assert_eq!(contract.get_transition_expectation(U64(0)).unwrap().unsigned_tx, Some(repriced));
+}
+
+#[test]
+fn test_owner_may_replace_settlement_payload() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_eth_settlement(&mut contract, &mut context);
+    let repriced = settlement_tx(100, 30_000_000_000, &sub_memo(&contract, U64(0)));
+    let payload = env::keccak256_array(&repriced);
+    propose_as_solver(&mut contract, &mut context, repriced);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.replace_settlement_payload(U64(0), payload);
+    assert_eq!(contract.get_settlement_request(U64(0)).unwrap().payload, payload);
+}
+
+#[test]
+#[should_panic(expected = "Only the maker or the owner can replace a settlement payload")]
+fn test_solver_cannot_replace_settlement_payload() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_eth_settlement(&mut contract, &mut context);
+    let repriced = settlement_tx(100, 30_000_000_000, &sub_memo(&contract, U64(0)));
+    let payload = env::keccak256_array(&repriced);
+    propose_as_solver(&mut contract, &mut context, repriced);
+    contract.replace_settlement_payload(U64(0), payload);
+}
+
+#[test]
+#[should_panic(expected = "No settlement transaction proposed for Sub-Intent 0")]
+fn test_replace_payload_without_proposal_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_eth_settlement(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.replace_settlement_payload(U64(0), [7u8; 32]);
+}
+
+#[test]
+#[should_panic(expected = "Payload does not match the proposed settlement transaction")]
+fn test_replace_payload_with_other_hash_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_eth_settlement(&mut contract, &mut context);
+    let repriced = settlement_tx(100, 30_000_000_000, &sub_memo(&contract, U64(0)));
+    propose_as_solver(&mut contract, &mut context, repriced);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.replace_settlement_payload(U64(0), [7u8; 32]);
+}
+
+#[test]
+#[should_panic(expected = "Only the gas fields of a settlement transaction may change")]
+fn test_repriced_tx_changing_value_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_failed_eth_settlement(&mut contract, &mut context);
+    let repriced = settlement_tx(99, 30_000_000_000, &sub_memo(&contract, U64(0)));
+    propose_as_solver(&mut contract, &mut context, repriced);
+}
+
+#[test]
+#[should_panic(expected = "Sub-Intent must be in Taken state to replace its payload")]
+fn test_replace_payload_while_signing_panics() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.replace_settlement_payload(U64(0), [7u8; 32]);
+}
+
+// ============================================================================
+// 70. BALANCE CHANGE EVENTS
+// ============================================================================
+
+/// Append the `balance_changed` entries logged since the last
+/// `testing_env!` to `events`.
+fn collect_balance_events(events: &mut Vec<near_sdk::serde_json::Value>) {
+    for log in near_sdk::test_utils::get_logs() {
+        let Some(json) = log.strip_prefix("EVENT_JSON:") else { continue };
+        let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(json).unwrap();
+        if event["event"] == "balance_changed" {
+            events.extend(event["data"].as_array().unwrap().iter().cloned());
+        }
+    }
+}
+
+#[test]
+fn test_deposit_emits_balance_changed_event() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 50);
+
+    let log = near_sdk::test_utils::get_logs()
+        .into_iter()
+        .find_map(|l| l.strip_prefix("EVENT_JSON:").map(str::to_string))
+        .unwrap();
+    assert_eq!(
+        near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(&log).unwrap(),
+        near_sdk::serde_json::json!({
+            "standard": "orderbook",
+            "version": "1.0.0",
+            "event": "balance_changed",
+            "data": [{
+                "user": user_alice(),
+                "asset": "ETH",
+                "delta": "50",
+                "reason": "deposit",
+                "related_id": null,
+                "balance": "150",
+            }],
+        })
+    );
+}
+
+#[test]
+fn test_debits_carry_negative_delta_and_related_id() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(60), "ETH".to_string(), u(60), None, None, None, None, None, None).unwrap();
+    let mut events = Vec::new();
+    collect_balance_events(&mut events);
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        (&events[0]["delta"], &events[0]["reason"], &events[0]["related_id"], &events[0]["balance"]),
+        (&near_sdk::serde_json::json!("-60"), &near_sdk::serde_json::json!("intent_locked"), &near_sdk::serde_json::json!(id.0.to_string()), &near_sdk::serde_json::json!("40"))
+    );
+
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw("ETH".to_string(), u(30), ETH_DEST.to_string(), eth_tx(30), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    let mut events = Vec::new();
+    collect_balance_events(&mut events);
+    assert_eq!(events.len(), 1);
+    assert_eq!((&events[0]["reason"], &events[0]["related_id"]), (&near_sdk::serde_json::json!("withdrawal"), &near_sdk::serde_json::json!("0")));
+    assert_eq!((&events[0]["delta"], &events[0]["balance"]), (&near_sdk::serde_json::json!("-30"), &near_sdk::serde_json::json!("70")));
+}
+
+#[test]
+fn test_balance_events_reconcile_across_lifecycle() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(MAX_FEE_BPS);
+    contract.set_fee_collector(user_dave());
+    contract.set_referral_bps(2_500);
+    let mut events = Vec::new();
+
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 10_000);
+    collect_balance_events(&mut events);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 10_000);
+    collect_balance_events(&mut events);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent(
+        "WSOL".to_string(), u(10_000), "ETH".to_string(), u(10_000), None, None, None, None, Some(user_charlie()),
+        None,
+    ).unwrap();
+    collect_balance_events(&mut events);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(4_000), "WSOL".to_string(), u(4_000), None, None, None, None, None, None).unwrap();
+    collect_balance_events(&mut events);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 4_000, 4_000), mp(id_b, 4_000, 4_000)])).unwrap();
+    collect_balance_events(&mut events);
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    collect_balance_events(&mut events);
+
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id_a);
+    collect_balance_events(&mut events);
+
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw("ETH".to_string(), u(1_000), ETH_DEST.to_string(), eth_tx(1_000), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    collect_balance_events(&mut events);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], own_path(&ChainType::ETH), Err(near_sdk::PromiseError::Failed));
+    collect_balance_events(&mut events);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw("ETH".to_string(), u(500), ETH_DEST.to_string(), eth_tx(500), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    collect_balance_events(&mut events);
+
+    // Each event's balance is the running sum of its account's deltas
+    let mut running: std::collections::HashMap<(String, String), i128> = std::collections::HashMap::new();
+    for event in &events {
+        let key = (event["user"].as_str().unwrap().to_string(), event["asset"].as_str().unwrap().to_string());
+        let delta: i128 = event["delta"].as_str().unwrap().parse().unwrap();
+        let balance = running.entry(key).or_insert(0);
+        *balance += delta;
+        assert_eq!(event["balance"].as_str().unwrap(), balance.to_string(), "{}", event);
+    }
+    for ((user, asset), balance) in &running {
+        assert_eq!(contract.get_balance(user.parse().unwrap(), asset.clone()).0 as i128, *balance, "{} {}", user, asset);
+    }
+    assert_eq!(running.len(), 7);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(3_460));
+    assert_eq!(contract.get_balance(user_alice(), "WSOL".to_string()), u(6_000));
+
+    let alice_reasons: Vec<&str> = events
+        .iter()
+        .filter(|e| e["user"] == user_alice().as_str())
+        .map(|e| e["reason"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        alice_reasons,
+        vec!["deposit", "intent_locked", "escrow_released", "intent_refund", "withdrawal", "withdrawal_refund", "withdrawal"]
+    );
+    let reasons: std::collections::HashSet<&str> = events.iter().map(|e| e["reason"].as_str().unwrap()).collect();
+    assert!(reasons.contains("protocol_fee") && reasons.contains("referral_fee"));
+}
+
+// ============================================================================
+// 71. ORDER-BOOK DEPTH
+// ============================================================================
+
+fn depth_level(num: u128, den: u128, total: u128, count: u32) -> DepthLevel {
+    DepthLevel { price_num: u(num), price_den: u(den), total_remaining: u(total), intent_count: count }
+}
+
+/// Alice and Dave sell SOL at 2 ETH, Charlie at 1.5 ETH.
+fn setup_sol_book(contract: &mut Orderbook, context: &mut VMContextBuilder) -> Vec<U64> {
+    let makers = [(user_alice(), 100, 200), (user_dave(), 50, 100), (user_charlie(), 30, 45)];
+    let mut ids = Vec::new();
+    for (maker, src, dst) in makers {
+        owner_deposit(contract, context, &maker, "SOL", src);
+        testing_env!(context.predecessor_account_id(maker).build());
+        ids.push(contract.make_intent("SOL".to_string(), u(src), "ETH".to_string(), u(dst), None, None, None, None, None, None).unwrap());
+    }
+    ids
+}
+
+#[test]
+fn test_depth_aggregates_intents_by_price() {
+    let (mut contract, mut context) = new_contract();
+    setup_sol_book(&mut contract, &mut context);
+    assert_eq!(
+        contract.get_depth("sol".to_string(), "ETH".to_string(), 10),
+        vec![depth_level(3, 2, 30, 1), depth_level(2, 1, 150, 2)]
+    );
+    assert_eq!(contract.get_depth("SOL".to_string(), "ETH".to_string(), 1), vec![depth_level(3, 2, 30, 1)]);
+    // The other side of the market is empty
+    assert!(contract.get_depth("ETH".to_string(), "SOL".to_string(), 10).is_empty());
+}
+
+#[test]
+fn test_depth_tracks_fills_and_closed_intents() {
+    let (mut contract, mut context) = new_contract();
+    let ids = setup_sol_book(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.take_intent(ids[0], u(40)).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(ids[2]);
+
+    assert_eq!(
+        contract.get_depth("SOL".to_string(), "ETH".to_string(), 10),
+        vec![depth_level(2, 1, 110, 2)]
+    );
+}
+
+#[test]
+fn test_depth_skips_expired_intents() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).block_timestamp(0).build());
+    contract.make_intent("SOL".to_string(), u(60), "ETH".to_string(), u(60), Some(1_000), None, None, None, None, None).unwrap();
+    contract.make_intent("SOL".to_string(), u(40), "ETH".to_string(), u(80), None, None, None, None, None, None).unwrap();
+    testing_env!(context.block_timestamp(1_000).build());
+    assert_eq!(
+        contract.get_depth("SOL".to_string(), "ETH".to_string(), 10),
+        vec![depth_level(2, 1, 40, 1)]
+    );
+}
+
+// ============================================================================
+// 72. ON-CHAIN BEST MATCH
+// ============================================================================
+
+fn settlement_leg() -> SettlementRequest {
+    SettlementRequest {
+        payload: [1u8; 32],
+        path: "default/path".to_string(),
+        chain_type: ChainType::ETH,
+        scheme: SignatureScheme::Secp256k1,
+        recipient: ETH_DEST.to_string(),
+        unsigned_tx: None,
+    }
+}
+
+fn planned(intent_id: U64, fill: u128, get: u128) -> PlannedFill {
+    PlannedFill { intent_id, fill_amount: u(fill), get_amount: u(get) }
+}
+
+/// `maker` offers `src` of `src_asset` for `dst` of `dst_asset`.
+fn offer(contract: &mut Orderbook, context: &mut VMContextBuilder, maker: AccountId, src_asset: &str, src: u128, dst_asset: &str, dst: u128) -> U64 {
+    owner_deposit(contract, context, &maker, src_asset, src);
+    testing_env!(context.predecessor_account_id(maker).build());
+    contract.make_intent(src_asset.to_string(), u(src), dst_asset.to_string(), u(dst), None, None, None, None, None, None).unwrap()
+}
+
+fn match_best_as_solver(contract: &mut Orderbook, context: &mut VMContextBuilder, intent_id: U64, max: u32, legs: usize) -> Vec<U64> {
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    // Each planned fill gets the leg a default match for it would carry
+    let plan = contract.preview_match_best(orderbook_contract(), intent_id, max);
+    let legs = (0..legs)
+        .map(|i| match plan.get(i) {
+            Some(fill) => {
+                let m = bind_transition(contract, mp(fill.intent_id, fill.fill_amount.0, fill.get_amount.0), i);
+                SettlementRequest {
+                    payload: m.payload,
+                    path: m.path,
+                    chain_type: m.transition_chain_type,
+                    scheme: m.scheme,
+                    recipient: m.recipient,
+                    unsigned_tx: m.unsigned_tx,
+                }
+            }
+            None => settlement_leg(),
+        })
+        .collect();
+    contract.match_best(intent_id, max, legs)
+}
+
+#[test]
+fn test_match_best_fills_crossing_counter_intent() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = offer(&mut contract, &mut context, user_alice(), "WSOL", 100, "ETH", 100);
+    let id_b = offer(&mut contract, &mut context, solver_bob(), "ETH", 100, "WSOL", 100);
+
+    let sub_ids = match_best_as_solver(&mut contract, &mut context, id_a, 4, 2);
+    assert_eq!(sub_ids, vec![U64(0), U64(1)]);
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+    assert_eq!(contract.get_intent(id_b).unwrap().status, IntentStatus::Filled);
+    assert_eq!(contract.get_sub_intent(U64(1)).unwrap().parent_intent_id, 1);
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::AwaitingSign);
+    assert_eq!(contract.get_pending_sign_count(), 2);
+    assert_eq!(contract.get_escrow(U64(0)).unwrap().amount, 100);
+}
+
+#[test]
+fn test_match_best_takes_best_price_first() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = offer(&mut contract, &mut context, user_alice(), "WSOL", 200, "ETH", 200);
+    let id_dave = offer(&mut contract, &mut context, user_dave(), "ETH", 100, "WSOL", 100);
+    let id_charlie = offer(&mut contract, &mut context, user_charlie(), "ETH", 100, "WSOL", 80);
+    let id_bob = offer(&mut contract, &mut context, solver_bob(), "ETH", 100, "WSOL", 100);
+
+    // Charlie asks least; Dave and Bob tie on price and Dave was first
+    assert_eq!(
+        contract.preview_match_best(orderbook_contract(), id_a, 2),
+        vec![planned(id_a, 180, 200), planned(id_charlie, 100, 80), planned(id_dave, 100, 100)]
+    );
+    assert_eq!(
+        contract.preview_match_best(orderbook_contract(), id_a, 1),
+        vec![planned(id_a, 80, 100), planned(id_charlie, 100, 80)]
+    );
+
+    assert_eq!(match_best_as_solver(&mut contract, &mut context, id_a, 1, 2).len(), 2);
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 80);
+    assert_eq!(contract.get_intent(id_charlie).unwrap().status, IntentStatus::Filled);
+    assert_eq!(contract.get_intent(id_dave).unwrap().filled_amount, 0);
+    assert_eq!(contract.get_intent(id_bob).unwrap().filled_amount, 0);
+}
+
+#[test]
+fn test_match_best_reads_the_book_in_price_order() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = offer(&mut contract, &mut context, user_alice(), "SOL", 100, "ETH", 100);
+    for _ in 0..MAX_MATCH_BEST_SCAN {
+        offer(&mut contract, &mut context, user_dave(), "ETH", 100, "SOL", 100);
+    }
+    // Placed after a full scan's worth of worse offers
+    let id_charlie = offer(&mut contract, &mut context, user_charlie(), "ETH", 100, "SOL", 80);
+
+    assert_eq!(
+        contract.preview_match_best(orderbook_contract(), id_a, 1),
+        vec![planned(id_a, 80, 100), planned(id_charlie, 100, 80)]
+    );
+    let depth = contract.get_depth("ETH".to_string(), "SOL".to_string(), 1);
+    assert_eq!((depth[0].price_num, depth[0].price_den), (u(4), u(5)));
+}
+
+#[test]
+fn test_match_best_without_cross_returns_empty() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = offer(&mut contract, &mut context, user_alice(), "SOL", 100, "ETH", 200);
+    offer(&mut contract, &mut context, solver_bob(), "ETH", 100, "SOL", 100);
+
+    assert!(contract.preview_match_best(orderbook_contract(), id_a, 4).is_empty());
+    assert!(match_best_as_solver(&mut contract, &mut context, id_a, 4, 0).is_empty());
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 0);
+    assert_eq!(contract.get_pending_sign_count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Expected 2 settlement legs, got 1")]
+fn test_match_best_needs_a_leg_per_fill() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = offer(&mut contract, &mut context, user_alice(), "SOL", 100, "ETH", 100);
+    offer(&mut contract, &mut context, solver_bob(), "ETH", 100, "SOL", 100);
+    match_best_as_solver(&mut contract, &mut context, id_a, 4, 1);
+}
+
+// ============================================================================
+// 73. ACCOUNT BLOCKLIST
+// ============================================================================
+
+fn block(contract: &mut Orderbook, context: &mut VMContextBuilder, account: &AccountId) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.block_account(account.clone());
+}
+
+#[test]
+fn test_block_and_unblock_account_log_events() {
+    let (mut contract, mut context) = new_contract();
+    block(&mut contract, &mut context, &user_alice());
+    assert!(contract.is_blocked(user_alice()));
+    assert_eq!(near_sdk::test_utils::get_logs(), vec![format!("ACCOUNT_BLOCKED:account={}", user_alice())]);
+
+    contract.unblock_account(user_alice());
+    assert!(!contract.is_blocked(user_alice()));
+    assert_eq!(near_sdk::test_utils::get_logs()[1], format!("ACCOUNT_UNBLOCKED:account={}", user_alice()));
+}
+
+#[test]
+#[should_panic(expected = "Only owner can block accounts")]
+fn test_only_owner_can_block_accounts() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.block_account(solver_bob());
+}
+
+#[test]
+fn test_blocked_maker_cannot_make_intent() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    block(&mut contract, &mut context, &user_alice());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let error = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap_err();
+    assert_eq!(error, OrderbookError::AccountBlocked { account: user_alice() });
+}
+
+#[test]
+#[should_panic(expected = "Account eugene is blocked")]
+fn test_blocked_user_deposit_is_refused() {
+    let (mut contract, mut context) = new_contract();
+    block(&mut contract, &mut context, &user_alice());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), u(10), "addr".to_string(),
+        format!("mpc:deposit:{}:SOL", user_alice()), vec![], "tx".to_string(),
+    );
+}
+
+#[test]
+fn test_taking_from_blocked_maker_is_refused() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    block(&mut contract, &mut context, &user_alice());
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let error = contract.take_intent(id, u(50)).unwrap_err();
+    assert_eq!(error, OrderbookError::AccountBlocked { account: user_alice() });
+}
+
+#[test]
+fn test_blocked_solver_and_maker_fail_batch_checks() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_dave(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_dave()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    block(&mut contract, &mut context, &solver_bob());
+    block(&mut contract, &mut context, &user_dave());
+
+    let sim = contract.simulate_batch_match(solver_bob(), bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]));
+    assert_eq!(
+        sim.errors[..2],
+        [format!("Account {} is blocked", solver_bob()), "Account dave.testnet is blocked".to_string()]
+    );
+    assert_eq!(sim.matches[1].error, Some("Account dave.testnet is blocked".to_string()));
+}
+
+#[test]
+fn test_blocked_solver_batch_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    block(&mut contract, &mut context, &orderbook_contract());
+    testing_env!(context.attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::AccountBlocked { account: orderbook_contract() });
+}
+
+#[test]
+fn test_blocked_user_cannot_withdraw() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    block(&mut contract, &mut context, &user_alice());
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap_err();
+    assert_eq!(error, OrderbookError::AccountBlocked { account: user_alice() });
+}
+
+#[test]
+fn test_blocked_user_still_gets_in_flight_withdrawal_refund() {
+    let (mut contract, mut context) = new_contract();
+    withdraw_to(&mut contract, &mut context, ChainType::ETH, ETH_DEST).unwrap();
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(50));
+    block(&mut contract, &mut context, &user_alice());
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    let res = contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(res, "Failed");
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+}
+
+// ============================================================================
+// 74. WITHDRAWAL LIMITS
+// ============================================================================
+
+/// Limit Alice's ETH withdrawals to 60 per window and credit her 200 ETH at
+/// time 0.
+fn setup_withdrawal_limit(contract: &mut Orderbook, context: &mut VMContextBuilder) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_timestamp(0).build());
+    contract.set_withdrawal_limit("eth".to_string(), Some(u(60)));
+    owner_deposit(contract, context, &user_alice(), "ETH", 200);
+}
+
+fn withdraw_eth(contract: &mut Orderbook, context: &mut VMContextBuilder, amount: u128, nonce: u64) {
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    let item = withdraw_item(amount, nonce);
+    contract.withdraw(item.asset, item.amount, item.destination, item.unsigned_tx, item.path, item.chain_type, item.scheme).unwrap();
+}
+
+fn fail_withdrawal(contract: &mut Orderbook, context: &mut VMContextBuilder, wd_id: u64) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::Withdrawal(wd_id), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+}
+
+fn quota(limit: u128, remaining: u128, resets_at: Option<u64>) -> Option<WithdrawalQuota> {
+    Some(WithdrawalQuota { limit: u(limit), remaining: u(remaining), resets_at })
+}
+
+#[test]
+fn test_withdrawal_quota_counts_withdrawals() {
+    let (mut contract, mut context) = new_contract();
+    setup_withdrawal_limit(&mut contract, &mut context);
+    assert_eq!(contract.get_withdrawal_quota(user_alice(), "ETH".to_string()), quota(60, 60, None));
+    assert_eq!(contract.get_withdrawal_quota(user_alice(), "SOL".to_string()), None);
+
+    testing_env!(context.block_timestamp(1_000).build());
+    withdraw_eth(&mut contract, &mut context, 50, 0);
+    assert_eq!(
+        contract.get_withdrawal_quota(user_alice(), "ETH".to_string()),
+        quota(60, 10, Some(1_000 + WITHDRAWAL_WINDOW_NS))
+    );
+    // Other users have their own window
+    assert_eq!(contract.get_withdrawal_quota(solver_bob(), "ETH".to_string()), quota(60, 60, None));
+}
+
+#[test]
+fn test_withdrawal_over_limit_fails() {
+    let (mut contract, mut context) = new_contract();
+    setup_withdrawal_limit(&mut contract, &mut context);
+    withdraw_eth(&mut contract, &mut context, 50, 0);
+    let item = withdraw_item(20, 1);
+    let error = contract
+        .withdraw(item.asset, item.amount, item.destination, item.unsigned_tx, item.path, item.chain_type, item.scheme)
+        .unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::WithdrawalLimitExceeded { asset: "ETH".to_string(), remaining: 10, resets_at: WITHDRAWAL_WINDOW_NS }
+    );
+}
+
+#[test]
+#[should_panic(expected = "Withdrawal limit exceeded for ETH: 30 remaining")]
+fn test_batch_withdraw_counts_every_item() {
+    let (mut contract, mut context) = new_contract();
+    setup_withdrawal_limit(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_withdraw(vec![withdraw_item(30, 0), withdraw_item(40, 1)]);
+}
+
+#[test]
+fn test_refunded_withdrawal_returns_quota() {
+    let (mut contract, mut context) = new_contract();
+    setup_withdrawal_limit(&mut contract, &mut context);
+    withdraw_eth(&mut contract, &mut context, 60, 0);
+    assert_eq!(contract.get_withdrawal_quota(user_alice(), "ETH".to_string()).unwrap().remaining, u(0));
+
+    fail_withdrawal(&mut contract, &mut context, 0);
+    assert_eq!(contract.get_withdrawal_quota(user_alice(), "ETH".to_string()).unwrap().remaining, u(60));
+    // The refunded transaction was never sent, so its nonce is reused
+    withdraw_eth(&mut contract, &mut context, 60, 0);
+}
+
+#[test]
+fn test_withdrawal_window_resets_after_24_hours() {
+    let (mut contract, mut context) = new_contract();
+    setup_withdrawal_limit(&mut contract, &mut context);
+    withdraw_eth(&mut contract, &mut context, 60, 0);
+
+    testing_env!(context.block_timestamp(WITHDRAWAL_WINDOW_NS - 1).build());
+    assert_eq!(contract.get_withdrawal_quota(user_alice(), "ETH".to_string()).unwrap().remaining, u(0));
+    testing_env!(context.block_timestamp(WITHDRAWAL_WINDOW_NS).build());
+    assert_eq!(contract.get_withdrawal_quota(user_alice(), "ETH".to_string()), quota(60, 60, None));
+
+    withdraw_eth(&mut contract, &mut context, 40, 1);
+    // The first withdrawal belongs to the old window: its refund does not
+    // add to the new one
+    fail_withdrawal(&mut contract, &mut context, 0);
+    assert_eq!(
+        contract.get_withdrawal_quota(user_alice(), "ETH".to_string()),
+        quota(60, 20, Some(2 * WITHDRAWAL_WINDOW_NS))
+    );
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(160));
+}
+
+#[test]
+fn test_removing_withdrawal_limit() {
+    let (mut contract, mut context) = new_contract();
+    setup_withdrawal_limit(&mut contract, &mut context);
+    assert_eq!(contract.get_withdrawal_limit("ETH".to_string()), Some(u(60)));
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_withdrawal_limit("ETH".to_string(), None);
+    assert_eq!(contract.get_withdrawal_limit("ETH".to_string()), None);
+    withdraw_eth(&mut contract, &mut context, 150, 0);
+}
+
+// ============================================================================
+// 75. VOLUME CIRCUIT BREAKER
+// ============================================================================
+
+/// Cap matched SOL at 150 per window and open two crossing SOL/ETH pairs of
+/// 100 each. Returns the pairs' intent ids.
+fn setup_volume_cap(contract: &mut Orderbook, context: &mut VMContextBuilder) -> [(U64, U64); 2] {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(10).build());
+    contract.set_volume_cap("wsol".to_string(), Some(u(150)));
+    [(); 2].map(|_| {
+        let id_a = offer(contract, context, user_alice(), "WSOL", 100, "ETH", 100);
+        let id_b = offer(contract, context, solver_bob(), "ETH", 100, "WSOL", 100);
+        (id_a, id_b)
+    })
+}
+
+fn match_pair(contract: &mut Orderbook, context: &mut VMContextBuilder, (id_a, id_b): (U64, U64)) -> Vec<U64> {
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .build()
+    );
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap()
+}
+
+#[test]
+fn test_circuit_breaker_rejects_batch_over_volume_cap() {
+    let (mut contract, mut context) = new_contract();
+    let [first, second] = setup_volume_cap(&mut contract, &mut context);
+    assert_eq!(match_pair(&mut contract, &mut context, first).len(), 2);
+    assert_eq!(
+        contract.get_volume_window("WSOL".to_string()),
+        VolumeWindowView {
+            asset: "WSOL".to_string(),
+            window_start: 0,
+            window_end: DEFAULT_VOLUME_WINDOW_BLOCKS,
+            volume: u(100),
+            cap: Some(u(150)),
+        }
+    );
+
+    assert!(match_pair(&mut contract, &mut context, second).is_empty());
+    let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(
+        near_sdk::test_utils::get_logs()[0].strip_prefix("EVENT_JSON:").unwrap(),
+    )
+    .unwrap();
+    assert_eq!(event["event"], "circuit_breaker_tripped");
+    assert_eq!(
+        event["data"][0],
+        near_sdk::serde_json::json!({
+            "solver": orderbook_contract(),
+            "asset": "WSOL",
+            "cap": "150",
+            "volume": "100",
+            "batch_volume": "100",
+            "window_start": 0,
+        })
+    );
+    // Nothing was matched, and the whole deposit went back
+    assert_eq!(contract.get_intent(second.0).unwrap().filled_amount, 0);
+    assert_eq!(contract.get_volume_window("WSOL".to_string()).volume, u(100));
+    assert_eq!(transfers_to(&orderbook_contract()), vec![NearToken::from_near(1).as_yoctonear()]);
+}
+
+#[test]
+fn test_circuit_breaker_resets_in_next_window() {
+    let (mut contract, mut context) = new_contract();
+    let [first, second] = setup_volume_cap(&mut contract, &mut context);
+    match_pair(&mut contract, &mut context, first);
+
+    testing_env!(context.block_height(DEFAULT_VOLUME_WINDOW_BLOCKS).build());
+    assert_eq!(contract.get_volume_window("WSOL".to_string()).volume, u(0));
+    assert_eq!(match_pair(&mut contract, &mut context, second).len(), 2);
+    let window = contract.get_volume_window("WSOL".to_string());
+    assert_eq!((window.window_start, window.volume), (DEFAULT_VOLUME_WINDOW_BLOCKS, u(100)));
+}
+
+#[test]
+fn test_cleared_volume_cap_lets_batches_through() {
+    let (mut contract, mut context) = new_contract();
+    let [first, second] = setup_volume_cap(&mut contract, &mut context);
+    match_pair(&mut contract, &mut context, first);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_volume_cap("WSOL".to_string(), None);
+    assert_eq!(match_pair(&mut contract, &mut context, second).len(), 2);
+    // Uncapped assets are tracked too
+    assert_eq!(contract.get_volume_window("ETH".to_string()).volume, u(200));
+}
+
+#[test]
+fn test_circuit_breaker_applies_to_deferred_batches() {
+    let (mut contract, mut context) = new_contract();
+    let [first, second] = setup_volume_cap(&mut contract, &mut context);
+    match_pair(&mut contract, &mut context, first);
+    testing_env!(context.attached_deposit(NearToken::from_near(1)).build());
+    assert!(contract.batch_match_intents_deferred(bind_assets(&contract, vec![mp(second.0, 100, 100), mp(second.1, 100, 100)])).is_empty());
+    assert_eq!(contract.get_pending_sign_count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Volume window must be at least one block")]
+fn test_volume_window_cannot_be_zero() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_volume_window_blocks(0);
+}
+
+// ============================================================================
+// 76. DEPOSIT HISTORY
+// ============================================================================
+
+#[test]
+fn test_deposits_are_recorded_per_user() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_timestamp(1_000).build());
+    owner_deposit(&mut contract, &mut context, &user_alice(), "eth", 70);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_timestamp(2_000).build());
+    contract.on_mpc_deposit_verified(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(),
+        format!("mpc:deposit:{}:SOL", user_alice()),
+        "dep-tx-1".to_string(),
+        verified(true),
+    );
+
+    let admin = contract.get_deposit(U64(0)).unwrap();
+    assert_eq!(admin.user, user_alice());
+    assert_eq!(admin.source, DepositSource::Admin);
+    assert_eq!((admin.chain_type, admin.asset.as_str(), admin.amount), (ChainType::ETH, "ETH", u(70)));
+    assert_eq!((admin.tx_hash, admin.recipient), (None, None));
+
+    let mpc = contract.get_deposit(U64(1)).unwrap();
+    assert_eq!(
+        mpc,
+        DepositRecord {
+            id: 1,
+            user: user_alice(),
+            source: DepositSource::Mpc,
+            chain_type: ChainType::SOL,
+            asset: "SOL".to_string(),
+            amount: u(500),
+            tx_hash: Some("dep-tx-1".to_string()),
+            recipient: Some("mpc-sol-addr".to_string()),
+            timestamp: 2_000,
+        }
+    );
+
+    let history = contract.get_deposits_by_user(user_alice(), u(0), 10);
+    assert_eq!(history.iter().map(|d| d.id).collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(contract.get_deposits_by_user(user_alice(), u(1), 10), vec![mpc]);
+    assert!(contract.get_deposits_by_user(solver_bob(), u(0), 10).is_empty());
+    assert_eq!(contract.get_deposit(U64(2)), None);
+}
+
+#[test]
+fn test_rejected_mpc_deposit_leaves_no_record() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let outcome = contract.on_mpc_deposit_verified(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        "dep-tx-1".to_string(),
+        verified(false),
+    );
+    assert!(!outcome.credited);
+    assert_eq!(contract.next_deposit_id, 0);
+    assert!(contract.get_deposits_by_user(user_alice(), u(0), 10).is_empty());
+}
+
+// ============================================================================
+// 77. CLIENT IDEMPOTENCY KEYS
+// ============================================================================
+
+fn make_keyed(contract: &mut Orderbook, client_id: &str) -> Result<U64, OrderbookError> {
+    contract.make_intent(
+        "SOL".to_string(), u(300), "ETH".to_string(), u(30),
+        None, None, None, None, None,
+        Some(client_id.to_string()),
+    )
+}
+
+#[test]
+fn test_make_intent_same_client_id_returns_existing_intent() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+
+    let id = make_keyed(&mut contract, "order-1").unwrap();
+    let retried = make_keyed(&mut contract, "order-1").unwrap();
+
+    assert_eq!(retried, id);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(700));
+    assert_eq!(contract.get_open_intent_count(user_alice()), 1);
+    assert_eq!(contract.get_intent(id).unwrap().client_id, Some("order-1".to_string()));
+}
+
+#[test]
+fn test_make_intent_different_client_ids_create_two_intents() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "SOL", 1000);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+
+    let first = make_keyed(&mut contract, "order-1").unwrap();
+    let second = make_keyed(&mut contract, "order-2").unwrap();
+    assert_ne!(first, second);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(400));
+
+    // Keys are scoped per maker.
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let bobs = make_keyed(&mut contract, "order-1").unwrap();
+    assert_ne!(bobs, first);
+    assert_eq!(contract.get_intent(bobs).unwrap().maker, solver_bob());
+}
+
+#[test]
+fn test_client_id_is_released_when_intent_closes() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id = make_keyed(&mut contract, "order-1").unwrap();
+    assert_eq!(contract.client_ids.get(&(user_alice(), "order-1".to_string())), Some(id.0));
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.cancel_intent(id);
+    assert_eq!(contract.client_ids.get(&(user_alice(), "order-1".to_string())), None);
+
+    testing_env!(context.attached_deposit(NearToken::from_yoctonear(0)).build());
+    let reused = make_keyed(&mut contract, "order-1").unwrap();
+    assert_ne!(reused, id);
+    assert_eq!(contract.get_intent(reused).unwrap().status, IntentStatus::Open);
+}
+
+#[test]
+fn test_make_intent_rejects_oversized_client_id() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 1000);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+
+    let error = make_keyed(&mut contract, &"k".repeat(MAX_CLIENT_ID_LEN + 1)).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::InvalidIntent { reason: format!("client_id must be 1 to {} bytes", MAX_CLIENT_ID_LEN) }
+    );
+    assert_eq!(make_keyed(&mut contract, "").unwrap_err(), error);
+    assert!(make_keyed(&mut contract, &"k".repeat(MAX_CLIENT_ID_LEN)).is_ok());
+}
+
+// ============================================================================
+// 78. JSON IDS
+// ============================================================================
+
+#[test]
+fn test_record_ids_serialize_as_strings() {
+    let (mut contract, mut context) = new_contract();
+    let (_, id_b) = setup_matched_pair(&mut contract, &mut context);
+    let sub = U64(1);
+    fn json<T: Serialize>(value: T) -> near_sdk::serde_json::Result<near_sdk::serde_json::Value> {
+        near_sdk::serde_json::to_value(value)
+    }
+
+    let intent = json(contract.get_intent(id_b).unwrap()).unwrap();
+    assert_eq!(intent["id"], "1");
+    // The u128 bond does not fit a `Value`, so check the serialized text.
+    let sub_intent = near_sdk::serde_json::to_string(&contract.get_sub_intent(sub).unwrap()).unwrap();
+    assert!(sub_intent.starts_with(r#"{"id":"1","parent_intent_id":"1","#));
+    let fills = json(contract.get_intent_fills(id_b, u(0), 10)).unwrap();
+    assert_eq!(fills[0]["sub_intent_id"], "1");
+    let expectation = json(contract.get_transition_expectation(sub).unwrap()).unwrap();
+    assert_eq!(expectation["sub_intent_id"], "1");
+    assert_eq!(json(SignTarget::SubIntent(u64::MAX)).unwrap(), near_sdk::serde_json::json!({ "SubIntent": u64::MAX.to_string() }));
+
+    // Borsh is unchanged and JSON round-trips past 2^53.
+    let mut big = contract.get_intent(id_b).unwrap();
+    big.id = (1 << 53) + 1;
+    let parsed: Intent = near_sdk::serde_json::from_value(json(big.clone()).unwrap()).unwrap();
+    assert_eq!(parsed.id, big.id);
+    assert_eq!(borsh::to_vec(&big.id).unwrap(), borsh::to_vec(&big).unwrap()[..8].to_vec());
+}
+
+// ============================================================================
+// 79. DEPOSIT CONFIRMATIONS
+// ============================================================================
+
+fn credit_at_height(contract: &mut Orderbook, block_height: u64, tx_hash: &str) -> DepositOutcome {
+    contract.on_mpc_deposit_verified(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(),
+        format!("mpc:deposit:{}:SOL", user_alice()),
+        tx_hash.to_string(),
+        Ok(VerificationResult { outcome: VerifyOutcome::Valid, block_height, finalized_height: 1_000 }),
+    )
+}
+
+#[test]
+fn test_deposit_with_exact_min_confirmations_is_credited() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_min_confirmations(ChainType::SOL, 32);
+    assert_eq!(contract.get_min_confirmations(ChainType::SOL), 32);
+    assert_eq!(contract.get_min_confirmations(ChainType::ETH), 0);
+
+    assert!(credit_at_height(&mut contract, 968, "dep-tx-1").credited);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(500));
+}
+
+#[test]
+fn test_deposit_one_confirmation_short_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_min_confirmations(ChainType::SOL, 32);
+    let outcome = credit_at_height(&mut contract, 969, "dep-tx-1");
+    assert_eq!(outcome.reason, Some("Deposit at height 969 has 31 confirmations on SOL, 32 required".to_string()));
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+}
+
+#[test]
+fn test_deposit_confirmations_default_to_none_required() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    assert!(credit_at_height(&mut contract, 1_000, "dep-tx-1").credited);
+
+    // Setting 0 removes a requirement again.
+    contract.set_min_confirmations(ChainType::SOL, 5);
+    contract.set_min_confirmations(ChainType::SOL, 0);
+    assert_eq!(contract.get_min_confirmations(ChainType::SOL), 0);
+    assert!(credit_at_height(&mut contract, 1_000, "dep-tx-2").credited);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(1_000));
+}
+
+#[test]
+fn test_min_confirmations_waits_for_config_delay() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(10).build());
+    contract.set_config_delay(100);
+    contract.set_min_confirmations(ChainType::SOL, 32);
+    assert_eq!(contract.get_min_confirmations(ChainType::SOL), 0);
+
+    testing_env!(context.block_height(110).build());
+    contract.apply_config_changes();
+    assert_eq!(contract.get_min_confirmations(ChainType::SOL), 32);
+}
+
+// ============================================================================
+// 80. DEPOSIT OUTCOMES
+// ============================================================================
+
+#[test]
+fn test_rejected_deposit_emits_event_and_keeps_state() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_min_confirmations(ChainType::SOL, 32);
+
+    let outcome = credit_at_height(&mut contract, 990, "dep-tx-1");
+    assert!(!outcome.credited);
+    let log = near_sdk::test_utils::get_logs()
+        .into_iter()
+        .find_map(|l| l.strip_prefix("EVENT_JSON:").map(str::to_string))
+        .expect("No deposit_rejected event");
+    let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(&log).unwrap();
+    assert_eq!(event["event"], "deposit_rejected");
+    assert_eq!(event["data"][0]["tx_hash"], "dep-tx-1");
+    assert_eq!(event["data"][0]["amount"], "500");
+    assert_eq!(event["data"][0]["reason"], outcome.reason.unwrap().as_str());
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+    assert!(!contract.is_deposit_consumed(ChainType::SOL, "dep-tx-1".to_string()));
+
+    // The same tx can still be credited once it is deep enough.
+    assert!(credit_at_height(&mut contract, 960, "dep-tx-1").credited);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(500));
+}
+
+#[test]
+#[should_panic(expected = "Proof data is empty")]
+fn test_verify_mpc_deposit_rejects_empty_proof() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mpc-sol-addr".to_string());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        vec![], "sol-tx-1".to_string(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "proof_data is 16385 bytes, max 16384")]
+fn test_verify_mpc_deposit_rejects_oversized_proof() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mpc-sol-addr".to_string());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        vec![0; DEFAULT_MAX_PROOF_DATA_LEN as usize + 1], "sol-tx-1".to_string(),
+    );
+}
+
+// ============================================================================
+// 81. INTENT GROUPS
+// ============================================================================
+
+fn spec(src_asset: &str, src_amount: u128, dst_asset: &str, dst_amount: u128) -> IntentSpec {
+    IntentSpec {
+        src_asset: src_asset.to_string(),
+        src_amount: u(src_amount),
+        dst_asset: dst_asset.to_string(),
+        dst_amount: u(dst_amount),
+        expires_at: None,
+        min_fill_amount: None,
+        all_or_nothing: None,
+        allowed_takers: None,
+        referrer: None,
+        client_id: None,
+    }
+}
+
+#[test]
+fn test_make_intents_creates_all_in_order() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 50);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+
+    let ids = contract
+        .make_intents(vec![spec("ETH", 60, "SOL", 600), spec("SOL", 50, "ETH", 5), spec("ETH", 40, "BTC", 2)])
+        .unwrap();
+    assert_eq!(ids, vec![U64(0), U64(1), U64(2)]);
+    assert_eq!(contract.get_intent(U64(1)).unwrap().src_asset, "SOL");
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(0));
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+
+    let created = near_sdk::test_utils::get_logs()
+        .iter()
+        .filter_map(|l| l.strip_prefix("EVENT_JSON:"))
+        .map(|l| near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(l).unwrap())
+        .filter(|e| e["event"] == "intent_created")
+        .map(|e| e["data"][0]["id"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(created, vec!["0", "1", "2"]);
+}
+
+#[test]
+fn test_make_intents_checks_combined_balance_up_front() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 50);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+
+    // Each SOL spec fits on its own; together they need 60.
+    let result = contract.make_intents(vec![spec("ETH", 60, "SOL", 600), spec("SOL", 30, "ETH", 3), spec("SOL", 30, "ETH", 3)]);
+    assert_eq!(result.err(), Some(OrderbookError::InsufficientBalance { asset: "SOL".to_string(), have: 50, need: 60 }));
+    assert!(contract.get_intent(U64(0)).is_none());
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(50));
+}
+
+#[test]
+fn test_make_intents_rejects_oversized_batch() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let specs = vec![spec("ETH", 1, "SOL", 1); MAX_BATCH_INTENTS + 1];
+    assert_eq!(
+        contract.make_intents(specs).err(),
+        Some(OrderbookError::InvalidIntent { reason: "Max 10 intents per batch".to_string() })
+    );
+}
+
+// ============================================================================
+// 82. TRANSITION ASSET AND CALLDATA
+// ============================================================================
+
+#[test]
+fn test_batch_match_rejects_wrong_transition_asset() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let wrong = MatchParams { transition_asset: "ETH".to_string(), ..mp(id_a, 100, 100) };
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![wrong, mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(
+        error,
+        OrderbookError::TransitionAssetMismatch { intent_id: 0, expected: "SOL".to_string(), got: "ETH".to_string() }
+    );
+    assert_eq!(contract.next_sub_intent_id, 0);
+}
+
+#[test]
+fn test_batch_match_rejects_oversized_unsigned_tx() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    let big = MatchParams { unsigned_tx: Some(vec![0; MAX_TRANSITION_TX_LEN + 1]), ..mp(id_b, 100, 100) };
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, vec![mp(id_a, 100, 100), big]));
+    assert_eq!(simulation.matches[1].error.as_deref(), Some("Transition tx of intent 1 is 2049 bytes, max 2048"));
+}
+
+#[test]
+fn test_batch_match_rejects_payload_not_hashing_unsigned_tx() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "SOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let with_tx = MatchParams { unsigned_tx: Some(vec![0xab, 0xcd]), ..mp(id_a, 100, 100) };
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![with_tx, mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::TransitionPayloadMismatch { intent_id: 0 });
+}
+
+#[test]
+fn test_eth_match_requires_unsigned_tx() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = sol_eth_pair(&mut contract, &mut context);
+    let bare = MatchParams { transition_asset: "ETH".to_string(), ..mp(id_b, 100, 100) };
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, vec![mp(id_a, 100, 100), bare]));
+    assert_eq!(simulation.matches[1].error.as_deref(), Some("Payload of intent 1 is not the keccak256 hash of its transition tx"));
+}
+
+#[test]
+fn test_btc_match_with_foreign_payload_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "BTC", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("BTC".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_c = contract.make_intent("ETH".to_string(), u(100), "BTC".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    // Any 32 bytes, e.g. the sighash of a spend from the treasury elsewhere
+    let foreign = MatchParams { payload: [0xee; 32], ..mp_with_chain(id_a, 100, 100, ChainType::BTC) };
+    let matches = bind_assets(&contract, vec![foreign, mp(id_c, 100, 100)]);
+    let simulation = contract.simulate_batch_match(orderbook_contract(), matches.clone());
+    assert_eq!(simulation.matches[0].error.as_deref(), Some("Transitions on BTC are not supported; only ETH transitions can be checked"));
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.batch_match_intents(matches).unwrap_err();
+    assert_eq!(error, OrderbookError::UnsupportedTransitionChain { chain_type: ChainType::BTC });
+    assert_eq!(contract.next_sub_intent_id, 0);
+    assert!(sign_request_args().is_empty());
+}
+
+#[test]
+fn test_eth_match_tx_must_pay_fill_to_recipient() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = sol_eth_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let with_tx = |tx: Vec<u8>, m: MatchParams| MatchParams { payload: env::keccak256_array(&tx), unsigned_tx: Some(tx), ..m };
+    let cases = [
+        (with_tx(settlement_tx(99, 0, &contract.next_batch_memo(1)), mp(id_b, 100, 100)), "does not pay the expected recipient, asset and amount"),
+        (with_tx(hex::decode(&eth_tx_with(DEFAULT_ETH_CHAIN_ID, 0, ALICE_ETH, 100, &[])[2..]).unwrap(), mp(id_b, 100, 100)), "does not pay"),
+        (with_tx(hex::decode(&eth_tx_with(1, 0, ETH_DEST, 100, &[])[2..]).unwrap(), mp(id_b, 100, 100)), "chain id does not match"),
+        (with_tx(settlement_tx(100, 0, &contract.next_batch_memo(1))[..8].to_vec(), mp(id_b, 100, 100)), "truncated"),
+    ];
+    for (bad, reason) in cases {
+        let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), bad])).unwrap_err();
+        assert!(error.to_string().contains(reason), "{}", error);
+    }
+
+    // A SOL fill cannot settle with an ETH transaction, even one paying the amount
+    owner_deposit(&mut contract, &mut context, &user_dave(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_dave()).build());
+    let id_d = contract.make_intent("SOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let sol_on_eth = with_tx(settlement_tx(100, 0, &contract.next_batch_memo(0)), MatchParams { transition_asset: "SOL".to_string(), ..mp(id_d, 100, 100) });
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![sol_on_eth, mp(id_b, 100, 100)])).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidTransitionTx { reason: "asset SOL is not on ETH".to_string() });
+    assert_eq!(contract.next_sub_intent_id, 0);
+}
+
+#[test]
+fn test_unsigned_tx_is_stored_and_emitted() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let tx = settlement_tx(100, 20_000_000_000, &contract.next_batch_memo(1));
+    let with_tx = MatchParams { payload: env::keccak256_array(&tx), unsigned_tx: Some(tx.clone()), ..mp(id_b, 100, 100) };
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), with_tx])).unwrap();
+    let expectation = contract.get_transition_expectation(U64(1)).unwrap();
+    assert_eq!(expectation.expected_asset, "ETH");
+    assert_eq!(expectation.unsigned_tx, Some(tx.clone()));
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    let event = signature_event();
+    assert_eq!(event["unsigned_tx"], hex::encode(&tx));
+    assert_eq!(event["asset"], "ETH");
+}
+
+// ============================================================================
+// 83. WITHDRAWAL HISTORY
+// ============================================================================
+
+#[test]
+fn test_get_withdrawals_by_user_pages_in_order() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    for (nonce, amount) in [10, 20, 30].into_iter().enumerate() {
+        testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+        let tx = eth_tx_with(DEFAULT_ETH_CHAIN_ID, nonce as u64, ETH_DEST, amount, &[]);
+        contract.withdraw("ETH".to_string(), u(amount), ETH_DEST.to_string(), tx, own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+    }
+
+    let page = contract.get_withdrawals_by_user(user_alice(), u(1), 10);
+    assert_eq!(page.iter().map(|r| r.amount).collect::<Vec<_>>(), vec![u(20), u(30)]);
+    assert!(page.iter().all(|r| r.status == WithdrawalStatus::PendingSign && r.destination == ETH_DEST));
+    assert!(contract.get_withdrawals_by_user(user_dave(), u(0), 10).is_empty());
+    assert!(contract.get_withdrawal(U64(3)).is_none());
+}
+
+#[test]
+fn test_cleanup_removes_final_withdrawal_after_retention() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_near(1)).build());
+    contract.withdraw("ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50), own_path(&ChainType::ETH), ChainType::ETH, SignatureScheme::Secp256k1).unwrap();
+
+    let pending = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.cleanup(vec![U64(0)], RecordKind::Withdrawal);
+    }));
+    assert!(pending.is_err(), "A pending withdrawal must not be removed");
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_timestamp(1_000).build());
+    contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![9u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    testing_env!(context.block_timestamp(1_000 + DEFAULT_RECORD_RETENTION_NS).build());
+    assert_eq!(contract.cleanup(vec![U64(0)], RecordKind::Withdrawal), 1);
+
+    assert!(contract.get_withdrawal(U64(0)).is_none());
+    assert!(contract.get_withdrawals_by_user(user_alice(), u(0), 10).is_empty());
+    assert_eq!(
+        contract.get_tombstone(RecordKind::Withdrawal, U64(0)),
+        Some(Tombstone { status: RecordStatus::Withdrawal(WithdrawalStatus::Signed), closed_at: Some(1_000) })
+    );
+}
+
+// ============================================================================
+// 84. IN-FLIGHT FILLS HOLD THEIR LIQUIDITY
+// ============================================================================
+
+#[test]
+fn test_in_flight_fill_is_not_matched_again() {
+    let (mut contract, mut context) = new_contract();
+    owner_deposit(&mut contract, &mut context, &user_alice(), "WSOL", 100);
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 60);
+    owner_deposit(&mut contract, &mut context, &user_dave(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_c = contract.make_intent("ETH".to_string(), u(60), "WSOL".to_string(), u(60), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_dave()).build());
+    let id_d = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+
+    // First batch: 60 of Alice's 100 is in flight, still Open for the rest.
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 60, 60), mp(id_c, 60, 60)])).unwrap();
+    let intent = contract.get_intent(id_a).unwrap();
+    assert_eq!((intent.status, intent.filled_amount), (IntentStatus::Open, 60));
+
+    // Before on_signed resolves, a second batch can only reach the other 40.
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 50, 50), mp(id_d, 50, 50)])).unwrap_err();
+    assert_eq!(error, OrderbookError::ExceedsRemaining { intent_id: id_a.0 });
+
+    // The signature fails: the sub-intent returns to Taken and keeps its 60.
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Taken);
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 60);
+
+    // Another solver takes the free 40 in between.
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 40, 40), mp(id_d, 40, 40)])).unwrap();
+    let intent = contract.get_intent(id_a).unwrap();
+    assert_eq!((intent.status, intent.filled_amount), (IntentStatus::Filled, 100));
+
+    // The retry still has its liquidity and settles.
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).prepaid_gas(Gas::from_tgas(300)).build());
+    let _ = contract.retry_settlement(U64(0)).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+    assert_eq!(contract.get_intent(id_a).unwrap().filled_amount, 100);
+}
+
+// ============================================================================
+// 85. REQUIRED RECEIVE ADDRESSES
+// ============================================================================
+
+#[test]
+fn test_match_without_receive_address_fails() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = sol_eth_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.set_receive_address(ChainType::ETH, None);
+
+    let matches = vec![mp(id_a, 100, 100), mp(id_b, 100, 100)];
+    let simulation = contract.simulate_batch_match(orderbook_contract(), bind_assets(&contract, matches.clone()));
+    assert_eq!(simulation.matches[1].error.as_deref(), Some("Maker charlie.testnet has no ETH receive address"));
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.batch_match_intents(bind_assets(&contract, matches)).unwrap_err();
+    assert_eq!(error, OrderbookError::NoReceiveAddress { maker: user_charlie(), chain_type: ChainType::ETH });
+    assert_eq!(contract.next_sub_intent_id, 0);
+}
+
+#[test]
+fn test_expectation_keeps_recipient_after_address_change() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = sol_eth_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
+
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    contract.set_receive_address(ChainType::ETH, Some(ALICE_ETH.to_string()));
+    assert_eq!(contract.get_receive_address(user_charlie(), ChainType::ETH), Some(ALICE_ETH.to_string()));
+    assert_eq!(contract.get_transition_expectation(U64(1)).unwrap().expected_recipient, ETH_DEST);
+}
+
+/// Alice sells 100 SOL for ETH, Charlie 100 ETH for SOL; both have the
+/// default receive addresses.
+fn sol_eth_pair(contract: &mut Orderbook, context: &mut VMContextBuilder) -> (U64, U64) {
+    owner_deposit(contract, context, &user_alice(), "WSOL", 100);
+    owner_deposit(contract, context, &user_charlie(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("WSOL".to_string(), u(100), "ETH".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    (id_a, id_b)
+}
+
+// ============================================================================
+// 86. SIGN FAILURE EVENTS
+// ============================================================================
+
+/// `data` of the first `EVENT_JSON` log named `name` in the current context.
+fn event_data(name: &str) -> near_sdk::serde_json::Value {
+    near_sdk::test_utils::get_logs()
+        .iter()
+        .filter_map(|l| l.strip_prefix("EVENT_JSON:"))
+        .map(|l| near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(l).unwrap())
+        .find(|e| e["event"] == name)
+        .map(|e| e["data"][0].clone())
+        .unwrap_or_else(|| panic!("No {} event", name))
+}
+
+#[test]
+fn test_sub_intent_sign_failure_emits_event() {
+    let (mut contract, mut context) = new_contract();
+    setup_matched_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    let res = contract.on_signed(SignTarget::SubIntent(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Err(near_sdk::PromiseError::Failed));
+    assert_eq!(res, "Failed");
+
+    let data = event_data("sub_intent_sign_failed");
+    assert_eq!(data["sub_intent_id"], "0");
+    assert_eq!(data["chain_type"], "ETH");
+    assert_eq!(data["reason"], "promise_failed");
+    assert_eq!(contract.get_stats().sign_failure_count, 1);
+}
+
+#[test]
+fn test_withdrawal_invalid_signature_emits_event() {
+    let (mut contract, mut context) = new_contract();
+    contract.set_mpc_root_key(ChainType::ETH, KEY_G.to_string());
+    withdraw_to(&mut contract, &mut context, ChainType::ETH, ETH_DEST).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let res = contract.on_signed(SignTarget::Withdrawal(0), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(res, "Failed");
+
+    let data = event_data("withdrawal_sign_failed");
+    assert_eq!(data["wd_id"], "0");
+    assert_eq!(data["user"], user_alice().to_string());
+    assert_eq!(data["asset"], "ETH");
+    assert_eq!(data["amount"], "50");
+    assert_eq!(data["reason"], "invalid_signature");
+    assert_eq!(contract.get_stats().sign_failure_count, 1);
+}
+
+// ============================================================================
+// 87. INPUT SIZE LIMITS
+// ============================================================================
+
+fn over(max: u32) -> String {
+    "x".repeat(max as usize + 1)
+}
+
+/// Alice's ETH intent taken in full by Bob, ready for `submit_payment_proof`.
+fn taken_sub_intent(contract: &mut Orderbook, context: &mut VMContextBuilder) -> U64 {
+    owner_deposit(contract, context, &user_alice(), "ETH", 100);
+    owner_deposit(contract, context, &solver_bob(), "SOL", 100);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id_a = contract.make_intent("ETH".to_string(), u(100), "SOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub = contract.take_intent(id_a, u(100)).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).attached_deposit(NearToken::from_near(1)).build());
+    sub
+}
+
+fn submit_proof(contract: &mut Orderbook, sub: U64, proof_data: Vec<u8>, path: String, tx_hash: String) -> Result<Promise, OrderbookError> {
+    let tx = settlement_tx(100, 0, &contract.get_next_take_memo(sub));
+    contract.submit_payment_proof(
+        sub, proof_data, env::keccak256_array(&tx), Some(tx), path, ChainType::SOL, ChainType::ETH,
+        "recipient".to_string(), format!("sub:{}", sub.0), ETH_DEST.to_string(), tx_hash,
+    )
+}
+
+#[test]
+fn test_submit_payment_proof_rejects_inputs_over_limits() {
+    let (mut contract, mut context) = new_contract();
+    let sub = taken_sub_intent(&mut contract, &mut context);
+
+    let error = submit_proof(&mut contract, sub, vec![0; DEFAULT_MAX_PROOF_DATA_LEN as usize + 1], "default/path".to_string(), "pay-tx".to_string()).err();
+    assert_eq!(error, Some(OrderbookError::InputTooLong { field: "proof_data".to_string(), len: 16385, max: DEFAULT_MAX_PROOF_DATA_LEN }));
+    let error = submit_proof(&mut contract, sub, vec![1], over(DEFAULT_MAX_PATH_LEN), "pay-tx".to_string()).err();
+    assert_eq!(error, Some(OrderbookError::InputTooLong { field: "path".to_string(), len: 129, max: DEFAULT_MAX_PATH_LEN }));
+    let error = submit_proof(&mut contract, sub, vec![1], "default/path".to_string(), over(DEFAULT_MAX_FIELD_LEN)).err();
+    assert_eq!(error, Some(OrderbookError::InputTooLong { field: "tx_hash".to_string(), len: 257, max: DEFAULT_MAX_FIELD_LEN }));
+    assert_eq!(contract.get_sub_intent(sub).unwrap().status, SubIntentStatus::Taken);
+
+    // Exactly at the limits is accepted.
+    let _ = submit_proof(&mut contract, sub, vec![0; DEFAULT_MAX_PROOF_DATA_LEN as usize], "default/path".to_string(), "h".repeat(256)).unwrap();
+    assert_eq!(contract.get_sub_intent(sub).unwrap().status, SubIntentStatus::Verifying);
+}
+
+#[test]
+#[should_panic(expected = "memo is 257 bytes, max 256")]
+fn test_verify_mpc_deposit_rejects_long_memo() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mpc-sol-addr".to_string());
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), over(DEFAULT_MAX_FIELD_LEN), vec![1], "sol-tx-1".to_string(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "proof_data is 16385 bytes, max 16384")]
+fn test_verify_transition_completion_rejects_oversized_proof() {
+    let (mut contract, _context) = new_contract();
+    let _ = contract.verify_transition_completion(U64(0), vec![0; DEFAULT_MAX_PROOF_DATA_LEN as usize + 1], "tx".to_string());
+}
+
+#[test]
+#[should_panic(expected = "tx_hash is 257 bytes, max 256")]
+fn test_verify_transition_completion_rejects_long_tx_hash() {
+    let (mut contract, _context) = new_contract();
+    let _ = contract.verify_transition_completion(U64(0), vec![1], over(DEFAULT_MAX_FIELD_LEN));
+}
+
+#[test]
+fn test_withdraw_rejects_long_destination_and_path() {
+    let (mut contract, mut context) = new_contract();
+    let error = withdraw_to(&mut contract, &mut context, ChainType::ETH, &over(DEFAULT_MAX_FIELD_LEN)).unwrap_err();
+    assert_eq!(error, OrderbookError::InputTooLong { field: "destination".to_string(), len: 257, max: DEFAULT_MAX_FIELD_LEN });
+
+    let error = contract.withdraw(
+        "ETH".to_string(), u(50), ETH_DEST.to_string(), eth_tx(50),
+        over(DEFAULT_MAX_PATH_LEN), ChainType::ETH, SignatureScheme::Secp256k1,
+    ).unwrap_err();
+    assert_eq!(error, OrderbookError::InputTooLong { field: "path".to_string(), len: 129, max: DEFAULT_MAX_PATH_LEN });
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(100));
+}
+
+#[test]
+fn test_batch_match_rejects_long_path_and_recipient() {
+    let (mut contract, mut context) = new_contract();
+    let (id_a, id_b) = sol_eth_pair(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+
+    let mut long_path = mp(id_b, 100, 100);
+    long_path.path = over(DEFAULT_MAX_PATH_LEN);
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), long_path])).unwrap_err();
+    assert_eq!(error, OrderbookError::InputTooLong { field: "path".to_string(), len: 129, max: DEFAULT_MAX_PATH_LEN });
+
+    let mut matches = bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)]);
+    matches[0].recipient = over(DEFAULT_MAX_FIELD_LEN);
+    let error = contract.batch_match_intents(matches).unwrap_err();
+    assert_eq!(error, OrderbookError::InputTooLong { field: "recipient".to_string(), len: 257, max: DEFAULT_MAX_FIELD_LEN });
+    assert_eq!(contract.next_sub_intent_id, 0);
+}
+
+#[test]
+fn test_input_limits_change_after_config_delay() {
+    let (mut contract, mut context) = new_contract();
+    let limits = InputLimits { max_proof_data_len: 4, max_path_len: 8, max_field_len: 16 };
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(100).build());
+    contract.set_config_delay(10);
+    contract.set_input_limits(limits);
+    assert_eq!(contract.get_input_limits(), InputLimits::default());
+
+    testing_env!(context.block_height(110).build());
+    assert_eq!(contract.apply_config_changes(), 1);
+    assert_eq!(contract.get_input_limits(), limits);
+
+    let sub = taken_sub_intent(&mut contract, &mut context);
+    let error = submit_proof(&mut contract, sub, vec![0; 5], "default".to_string(), "pay-tx".to_string()).err();
+    assert_eq!(error, Some(OrderbookError::InputTooLong { field: "proof_data".to_string(), len: 5, max: 4 }));
+}
+
+#[test]
+#[should_panic(expected = "Only owner can change config")]
+fn test_set_input_limits_owner_only() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_input_limits(InputLimits::default());
+}
+
+#[test]
+#[should_panic(expected = "Input limits must be positive")]
+fn test_set_input_limits_rejects_zero() {
+    let (mut contract, _context) = new_contract();
+    contract.set_input_limits(InputLimits { max_proof_data_len: 0, ..InputLimits::default() });
+}
+
+// ============================================================================
+// 88. MAKER REBATES
+// ============================================================================
+
+#[test]
+fn test_maker_rebate_rounds_down_and_comes_out_of_the_fee() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(30);
+    contract.set_maker_rebate_bps(20);
+    contract.set_fee_collector(user_dave());
+    setup_fee_match(&mut contract, &mut context);
+
+    // 999 * 20 / 10_000 = 1.998 -> 1 of a fee of 2; 1000 * 20 / 10_000 = 2 of 3
+    let escrow_a = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow_a.amount, escrow_a.fee, escrow_a.maker_rebate), (997, 2, 1));
+    let escrow_b = contract.get_escrow(U64(1)).unwrap();
+    assert_eq!((escrow_b.amount, escrow_b.fee, escrow_b.maker_rebate), (997, 3, 2));
+    assert_eq!(contract.get_maker_rebates("ETH".to_string()), u(0));
+
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(998));
+    assert_eq!(contract.get_balance(solver_bob(), "WSOL".to_string()), u(999));
+    assert_eq!(contract.get_balance(user_dave(), "ETH".to_string()), u(1));
+    assert_eq!(contract.get_balance(user_dave(), "WSOL".to_string()), u(1));
+    for (asset, gross) in [("ETH", 2), ("WSOL", 3)] {
+        let collected = contract.get_accrued_fees(asset.to_string()).0;
+        let rebates = contract.get_maker_rebates(asset.to_string()).0;
+        assert_eq!(collected + rebates, gross);
+    }
+}
+
+#[test]
+fn test_maker_rebate_is_taken_before_the_referral_share() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(MAX_FEE_BPS);
+    contract.set_maker_rebate_bps(40);
+    contract.set_fee_collector(user_dave());
+    contract.set_referral_bps(2_500);
+    setup_referred_match(&mut contract, &mut context);
+
+    // 100 fee: 40 rebate, then 25% of the remaining 60 to Charlie
+    let escrow = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow.fee, escrow.maker_rebate, escrow.referral_fee), (100, 40, 15));
+
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(9_940));
+    assert_eq!(contract.get_balance(user_charlie(), "ETH".to_string()), u(15));
+    assert_eq!(contract.get_accrued_fees("ETH".to_string()), u(45));
+    assert_eq!(contract.get_maker_rebates("ETH".to_string()), u(40));
+    assert!(near_sdk::test_utils::get_logs()
+        .contains(&format!("MAKER_REBATE_PAID:sub_intent_id=0,maker={},asset=ETH,amount=40", user_alice())));
+}
+
+#[test]
+fn test_zero_fee_pays_no_rebate() {
+    let (mut contract, mut context) = new_contract();
+    // Both changes pass their checks when scheduled, leaving a rebate above
+    // the fee once applied.
+    testing_env!(context.predecessor_account_id(orderbook_contract()).block_height(100).build());
+    contract.set_fee_bps(30);
+    contract.set_config_delay(10);
+    contract.set_maker_rebate_bps(30);
+    contract.set_fee_bps(0);
+    testing_env!(context.block_height(110).build());
+    assert_eq!(contract.apply_config_changes(), 2);
+    assert_eq!(contract.get_fee_config().maker_rebate_bps, 30);
+
+    setup_fee_match(&mut contract, &mut context);
+    let escrow = contract.get_escrow(U64(0)).unwrap();
+    assert_eq!((escrow.amount, escrow.fee, escrow.maker_rebate), (999, 0, 0));
+    release_escrows(&mut contract, &mut context, &[0, 1]);
+    assert_eq!(contract.get_balance(user_alice(), "ETH".to_string()), u(999));
+    assert_eq!(contract.get_maker_rebates("ETH".to_string()), u(0));
+}
+
+#[test]
+fn test_get_fee_config() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(50);
+    contract.set_maker_rebate_bps(10);
+    contract.set_referral_bps(1_000);
+    assert_eq!(
+        contract.get_fee_config(),
+        FeeConfig { fee_bps: 50, maker_rebate_bps: 10, referral_bps: 1_000, fee_collector: orderbook_contract() }
+    );
+}
+
+#[test]
+#[should_panic(expected = "Maker rebate exceeds fee of 30 bps")]
+fn test_maker_rebate_above_fee_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(30);
+    contract.set_maker_rebate_bps(31);
+}
+
+#[test]
+#[should_panic(expected = "Fee is below the maker rebate of 20 bps")]
+fn test_fee_below_maker_rebate_panics() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_fee_bps(30);
+    contract.set_maker_rebate_bps(20);
+    contract.set_fee_bps(10);
+}
+
+// ============================================================================
+// 89. INTENT REPLACEMENT
+// ============================================================================
+
+/// Alice holds 1000 SOL and quotes 400 of it for 400 ETH with some flags set.
+fn replaceable_intent(contract: &mut Orderbook, context: &mut VMContextBuilder) -> U64 {
+    owner_deposit(contract, context, &user_alice(), "SOL", 1000);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent(
+        "SOL".to_string(), u(400), "ETH".to_string(), u(400), Some(5_000), Some(u(50)), None,
+        Some(vec![solver_bob()]), Some(user_charlie()), None,
+    ).unwrap()
+}
+
+fn replace(contract: &mut Orderbook, context: &mut VMContextBuilder, id: U64, src: u128, dst: u128) -> Result<U64, OrderbookError> {
+    testing_env!(context.predecessor_account_id(user_alice()).attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.replace_intent(id, u(src), u(dst))
+}
+
+#[test]
+fn test_replace_intent_upsizes() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    let new = replace(&mut contract, &mut context, old, 900, 950).unwrap();
+
+    assert_eq!(contract.get_intent(old).unwrap().status, IntentStatus::Cancelled);
+    let intent = contract.get_intent(new).unwrap();
+    assert_eq!((intent.src_amount, intent.dst_amount, intent.status), (900, 950, IntentStatus::Open));
+    assert_eq!(intent.replaced_from, Some(old.0));
+    assert_eq!(intent.expires_at, Some(5_000));
+    assert_eq!(intent.min_fill_amount, 50);
+    assert_eq!(intent.allowed_takers, Some(vec![solver_bob()]));
+    assert_eq!(intent.referrer, Some(user_charlie()));
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(100));
+    assert!(near_sdk::test_utils::get_logs().contains(&format!("Intent #{} replaced by #{}", old.0, new.0)));
+}
+
+#[test]
+fn test_replace_intent_downsizes() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    let new = replace(&mut contract, &mut context, old, 100, 90).unwrap();
+    assert_eq!(contract.get_intent(new).unwrap().src_amount, 100);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(900));
+    assert_eq!(contract.get_open_intent_count(user_alice()), 1);
+}
+
+#[test]
+fn test_replace_partially_filled_intent_refunds_only_the_remainder() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    contract.take_intent(old, u(100)).unwrap();
+
+    // 300 left on the old intent plus 600 free covers exactly 900
+    replace(&mut contract, &mut context, old, 900, 900).unwrap();
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+}
+
+#[test]
+fn test_replace_intent_upsize_needs_balance_for_the_difference() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    let error = replace(&mut contract, &mut context, old, 1001, 1000).unwrap_err();
+    assert_eq!(error, OrderbookError::InsufficientBalance { asset: "SOL".to_string(), have: 600, need: 601 });
+
+    // Nothing changed: the old intent still holds its funds.
+    assert_eq!(contract.get_intent(old).unwrap().status, IntentStatus::Open);
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(600));
+    assert_eq!(contract.next_intent_id, 1);
+}
+
+#[test]
+fn test_replace_intent_rejects_amount_below_min_fill() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    let error = replace(&mut contract, &mut context, old, 40, 40).unwrap_err();
+    assert_eq!(error, OrderbookError::InvalidIntent { reason: "min_fill_amount exceeds src_amount".to_string() });
+    assert_eq!(contract.get_intent(old).unwrap().status, IntentStatus::Open);
+}
+
+#[test]
+fn test_replace_intent_by_other_account_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    testing_env!(context.predecessor_account_id(solver_bob()).attached_deposit(NearToken::from_yoctonear(1)).build());
+    let error = contract.replace_intent(old, u(100), u(100)).unwrap_err();
+    assert_eq!(error, OrderbookError::NotAuthorized { account: solver_bob(), action: "replace Intent 0".to_string() });
+    let error = replace(&mut contract, &mut context, U64(9), 100, 100).unwrap_err();
+    assert_eq!(error, OrderbookError::IntentNotFound { id: 9 });
+}
+
+#[test]
+fn test_replace_cancelled_intent_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    let new = replace(&mut contract, &mut context, old, 100, 100).unwrap();
+    assert_eq!(new, U64(1));
+    let error = replace(&mut contract, &mut context, old, 100, 100).unwrap_err();
+    assert_eq!(error, OrderbookError::IntentNotOpen { id: 0 });
+}
+
+#[test]
+fn test_replace_intent_with_settling_take_is_rejected() {
+    let (mut contract, mut context) = new_contract();
+    let old = replaceable_intent(&mut contract, &mut context);
+    owner_deposit(&mut contract, &mut context, &solver_bob(), "ETH", 100);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let sub_id = contract.take_intent(old, u(100)).unwrap();
+    // Its payment proof is being verified
+    let mut sub = contract.sub_intents.get(&sub_id.0).unwrap();
+    sub.status = SubIntentStatus::Verifying;
+    contract.sub_intents.insert(&sub_id.0, &sub);
+
+    let error = replace(&mut contract, &mut context, old, 100, 100).unwrap_err();
+    assert_eq!(error, OrderbookError::IntentSettling { id: 0 });
+    assert_eq!(contract.get_intent(old).unwrap().status, IntentStatus::Open);
+}
+
+// ============================================================================
+// 90. BATCH RECORDS
+// ============================================================================
+
+/// Alice BTC -> ETH, Bob ETH -> SOL, Charlie SOL -> BTC, matched as one ring
+/// by the owner at `timestamp`.
+fn match_ring(contract: &mut Orderbook, context: &mut VMContextBuilder, timestamp: u64) -> Vec<U64> {
+    owner_deposit(contract, context, &user_alice(), "WBTC", 100);
+    owner_deposit(contract, context, &solver_bob(), "ETH", 1000);
+    owner_deposit(contract, context, &user_charlie(), "WSOL", 500);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let id1 = contract.make_intent("WBTC".to_string(), u(100), "ETH".to_string(), u(1000), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let id2 = contract.make_intent("ETH".to_string(), u(1000), "WSOL".to_string(), u(500), None, None, None, None, None, None).unwrap();
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id3 = contract.make_intent("WSOL".to_string(), u(500), "WBTC".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    testing_env!(context
+        .predecessor_account_id(orderbook_contract())
+        .attached_deposit(NearToken::from_near(1))
+        .block_timestamp(timestamp)
+        .build());
+    contract.batch_match_intents(bind_assets(contract, vec![mp(id1, 100, 1000), mp(id2, 1000, 500), mp(id3, 500, 100)])).unwrap()
+}
+
+#[test]
+fn test_ring_batch_is_recorded_once() {
+    let (mut contract, mut context) = new_contract();
+    let sub_ids = match_ring(&mut contract, &mut context, 7_000);
+
+    let batch = contract.get_batch(U64(0)).unwrap();
+    assert_eq!(
+        batch,
+        BatchRecord {
+            id: 0,
+            solver: orderbook_contract(),
+            sub_intent_ids: sub_ids.clone(),
+            timestamp: 7_000,
+            attached_deposit: U128(NearToken::from_near(1).as_yoctonear()),
+        }
+    );
+    for sub_id in sub_ids {
+        assert_eq!(contract.get_sub_intent(sub_id).unwrap().batch_id, Some(0));
+    }
+    assert!(contract.get_batch(U64(1)).is_none());
+}
+
+#[test]
+fn test_batches_by_solver_pages_in_order() {
+    let (mut contract, mut context) = new_contract();
+    match_ring(&mut contract, &mut context, 1_000);
+    let second = match_ring(&mut contract, &mut context, 2_000);
+
+    let batches = contract.get_batches_by_solver(orderbook_contract(), U128(0), 10);
+    assert_eq!(batches.iter().map(|b| b.id).collect::<Vec<_>>(), vec![0, 1]);
+    let page = contract.get_batches_by_solver(orderbook_contract(), U128(1), 10);
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].sub_intent_ids, second);
+    assert!(contract.get_batches_by_solver(user_alice(), U128(0), 10).is_empty());
+}
+
+#[test]
+fn test_signature_event_names_the_batch() {
+    let (mut contract, mut context) = new_contract();
+    match_ring(&mut contract, &mut context, 1_000);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.on_signed(SignTarget::SubIntent(1), ChainType::ETH, vec![1u8; 32], "default/path".to_string(), Ok(mock_sig()));
+    assert_eq!(signature_event()["batch_id"], "0");
+}
+
+#[test]
+fn test_taken_sub_intent_has_no_batch() {
+    let (mut contract, mut context) = new_contract();
+    let sub = taken_sub_intent(&mut contract, &mut context);
+    assert_eq!(contract.get_sub_intent(sub).unwrap().batch_id, None);
+    assert_eq!(contract.next_batch_id, 0);
+}
+
+// ============================================================================
+// 91. MINIMUM INTENT SIZE
+// ============================================================================
+
+fn make_wsol_intent(contract: &mut Orderbook, context: &mut VMContextBuilder, amount: u128) -> Result<U64, OrderbookError> {
+    owner_deposit(contract, context, &user_alice(), "WSOL", amount);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.make_intent("WSOL".to_string(), u(amount), "ETH".to_string(), u(amount), None, None, None, None, None, None)
+}
+
+fn set_wsol_min(contract: &mut Orderbook, context: &mut VMContextBuilder, min: u128) {
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_min_intent_size("wsol".to_string(), u(min));
+}
+
+#[test]
+fn test_make_intent_at_min_size_boundary() {
+    let (mut contract, mut context) = new_contract();
+    set_wsol_min(&mut contract, &mut context, 100);
+    assert_eq!(contract.get_min_intent_size("WSOL".to_string()), u(100));
+    assert_eq!(contract.get_asset("WSOL".to_string()).unwrap().min_intent_size, 100);
+
+    let error = make_wsol_intent(&mut contract, &mut context, 99).unwrap_err();
+    assert_eq!(error, OrderbookError::BelowMinIntentSize { asset: "WSOL".to_string(), amount: 99, min: 100 });
+    make_wsol_intent(&mut contract, &mut context, 100).unwrap();
+    // Only the sold asset's minimum applies
+    assert_eq!(contract.get_min_intent_size("ETH".to_string()), u(0));
+}
+
+#[test]
+fn test_batch_fill_below_min_size_fails() {
+    let (mut contract, mut context) = new_contract();
+    set_wsol_min(&mut contract, &mut context, 100);
+    let id_a = make_wsol_intent(&mut contract, &mut context, 1000).unwrap();
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 99);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(99), "WSOL".to_string(), u(99), None, None, None, None, None, None).unwrap();
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    let error = contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 99, 99), mp(id_b, 99, 99)])).unwrap_err();
+    assert_eq!(error, OrderbookError::BelowMinFill { intent_id: id_a.0, amount: 99, min: 100 });
+}
+
+#[test]
+fn test_final_fill_below_min_size_is_allowed() {
+    let (mut contract, mut context) = new_contract();
+    let id_a = make_wsol_intent(&mut contract, &mut context, 150).unwrap();
+    owner_deposit(&mut contract, &mut context, &user_charlie(), "ETH", 150);
+    testing_env!(context.predecessor_account_id(user_charlie()).build());
+    let id_b = contract.make_intent("ETH".to_string(), u(100), "WSOL".to_string(), u(100), None, None, None, None, None, None).unwrap();
+    let id_c = contract.make_intent("ETH".to_string(), u(50), "WSOL".to_string(), u(50), None, None, None, None, None, None).unwrap();
+    set_wsol_min(&mut contract, &mut context, 100);
+
+    // A fill of exactly the minimum, then the 50 left over closes the intent
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 100, 100), mp(id_b, 100, 100)])).unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).attached_deposit(NearToken::from_near(1)).build());
+    contract.batch_match_intents(bind_assets(&contract, vec![mp(id_a, 50, 50), mp(id_c, 50, 50)])).unwrap();
+    assert_eq!(contract.get_intent(id_a).unwrap().status, IntentStatus::Filled);
+}
+
+#[test]
+fn test_reregistering_asset_keeps_min_size() {
+    let (mut contract, mut context) = new_contract();
+    set_wsol_min(&mut contract, &mut context, 100);
+    contract.register_asset("WSOL".to_string(), ChainType::ETH, 9, u(5));
+    assert_eq!(contract.get_min_intent_size("WSOL".to_string()), u(100));
+}
+
+#[test]
+#[should_panic(expected = "Only owner can manage assets")]
+fn test_set_min_intent_size_owner_only() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_min_intent_size("SOL".to_string(), u(100));
+}
+
+// ============================================================================
+// 92. CONSUMING PROOFS AT THE LIGHT CLIENT
+// ============================================================================
+
+/// Arguments of the `consume_payment_proof` call created in the current context.
+fn consume_call_args() -> near_sdk::serde_json::Value {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|r| r.actions)
+        .find_map(|a| match a {
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, args, .. }
+                if method_name == b"consume_payment_proof" =>
+            {
+                Some(near_sdk::serde_json::from_slice(&args).unwrap())
+            }
+            _ => None,
+        })
+        .expect("No consume_payment_proof call")
+}
+
+#[test]
+fn test_deposit_proof_is_consumed_with_the_chain_confirmations() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mpc-sol-addr".to_string());
+    contract.set_min_confirmations(ChainType::SOL, 32);
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    let _ = contract.verify_mpc_deposit(
+        user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+        "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+        b"proof".to_vec(), "sol-tx-1".to_string(),
+    );
+    let args = consume_call_args();
+    assert_eq!(args["expected_tx_hash"], "sol-tx-1");
+    assert_eq!(args["expected_memo"], format!("mpc:deposit:{}:SOL", user_alice()));
+    assert_eq!(args["min_confirmations"], 32);
+}
+
+#[test]
+fn test_payment_proof_is_consumed_at_any_depth() {
+    let (mut contract, mut context) = new_contract();
+    seed_twin_unescrowed_takes(&mut contract);
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_min_confirmations(ChainType::ETH, 12);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 0, "pay-1").unwrap();
+    let args = consume_call_args();
+    assert_eq!(args["expected_tx_hash"], "pay-1");
+    assert_eq!(args["min_confirmations"], 0);
+}
+
+// ============================================================================
+// 93. NATIVE AND ERC-20 ASSET KINDS
+// ============================================================================
+
+const USDC_CONTRACT: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+#[test]
+fn test_set_asset_token_contract() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_token_contract("usdc".to_string(), Some(USDC_CONTRACT.to_string()));
+    let lower = USDC_CONTRACT.to_lowercase();
+    assert_eq!(contract.get_asset("USDC".to_string()).unwrap().token_contract, Some(lower.clone()));
+    assert_eq!(contract.expected_asset_kind("USDC"), Some(AssetKind::Erc20 { contract: lower.clone() }));
+    assert_eq!(contract.expected_asset_kind("ETH"), Some(AssetKind::Native));
+    assert_eq!(contract.expected_asset_kind("SOL"), None);
+
+    // Re-registering keeps the contract; `None` makes it native again
+    contract.register_asset("USDC".to_string(), ChainType::ETH, 6, u(10));
+    assert_eq!(contract.get_asset("USDC".to_string()).unwrap().token_contract, Some(lower));
+    contract.set_asset_token_contract("USDC".to_string(), None);
+    assert_eq!(contract.expected_asset_kind("USDC"), Some(AssetKind::Native));
+}
+
+#[test]
+#[should_panic(expected = "Token contracts are for ETH assets")]
+fn test_set_asset_token_contract_eth_only() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_token_contract("SOL".to_string(), Some(USDC_CONTRACT.to_string()));
+}
+
+#[test]
+#[should_panic(expected = "Token contract must be a 20-byte hex address")]
+fn test_set_asset_token_contract_rejects_bad_address() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_token_contract("USDC".to_string(), Some("0x1234".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "Only owner can manage assets")]
+fn test_set_asset_token_contract_owner_only() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(user_alice()).build());
+    contract.set_asset_token_contract("USDC".to_string(), Some(USDC_CONTRACT.to_string()));
+}
+
+#[test]
+fn test_deposit_proof_carries_expected_asset_kind() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.register_deposit_address(user_alice(), ChainType::ETH, "0xdeposit".to_string());
+    contract.register_deposit_address(user_alice(), ChainType::SOL, "mpc-sol-addr".to_string());
+    contract.set_asset_token_contract("USDC".to_string(), Some(USDC_CONTRACT.to_string()));
+    let deposit = |contract: &mut Orderbook, chain_type: ChainType, asset: &str, recipient: &str| {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(orderbook_contract())
+            .predecessor_account_id(user_alice())
+            .build());
+        let _ = contract.verify_mpc_deposit(
+            user_alice(), chain_type, asset.to_string(), U128(500), recipient.to_string(),
+            format!("mpc:deposit:{}:{}", user_alice(), asset), b"proof".to_vec(), format!("{}-tx", asset),
+        );
+        consume_call_args()["expected_asset_kind"].clone()
+    };
+    assert_eq!(deposit(&mut contract, ChainType::ETH, "ETH", "0xdeposit"), near_sdk::serde_json::json!("Native"));
+    assert_eq!(
+        deposit(&mut contract, ChainType::ETH, "USDC", "0xdeposit"),
+        near_sdk::serde_json::json!({ "Erc20": { "contract": USDC_CONTRACT.to_lowercase() } })
+    );
+    assert!(deposit(&mut contract, ChainType::SOL, "SOL", "mpc-sol-addr").is_null());
+}
+
+#[test]
+fn test_payment_proof_carries_token_contract_of_dst_asset() {
+    let (mut contract, mut context) = new_contract();
+    seed_twin_unescrowed_takes(&mut contract);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 0, "pay-1").unwrap();
+    assert_eq!(consume_call_args()["expected_asset_kind"], near_sdk::serde_json::json!("Native"));
+
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    contract.set_asset_token_contract("USDC".to_string(), Some(USDC_CONTRACT.to_string()));
+    let parent_id = contract.sub_intents.get(&1).unwrap().parent_intent_id;
+    let mut parent = contract.intents.get(&parent_id).unwrap();
+    parent.dst_asset = "USDC".to_string();
+    contract.intents.insert(&parent_id, &parent);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 1, "pay-2").unwrap();
+    let args = consume_call_args();
+    assert_eq!(args["expected_asset"], "USDC");
+    assert_eq!(args["expected_asset_kind"], near_sdk::serde_json::json!({ "Erc20": { "contract": USDC_CONTRACT.to_lowercase() } }));
+}
+
+// ============================================================================
+// 94. LIGHT CLIENT REJECTION REASONS
+// ============================================================================
+
+fn rejected_with(reason: VerifyError) -> Result<VerificationResult, PromiseError> {
+    Ok(VerificationResult { outcome: VerifyOutcome::Invalid(reason), block_height: 90, finalized_height: 100 })
+}
+
+#[test]
+fn test_rejected_deposit_reports_light_client_reason() {
+    let (mut contract, mut context) = new_contract();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).build());
+    let deposit = |contract: &mut Orderbook, result| {
+        contract.on_mpc_deposit_verified(
+            user_alice(), ChainType::SOL, "SOL".to_string(), U128(500),
+            "mpc-sol-addr".to_string(), format!("mpc:deposit:{}:SOL", user_alice()),
+            "dep-tx-1".to_string(), result,
+        )
+    };
+    let outcome = deposit(&mut contract, rejected_with(VerifyError::NotFinalized { proof_height: 90, finalized: 80 }));
+    assert_eq!(
+        outcome.reason.as_deref(),
+        Some("MPC deposit proof invalid: NotFinalized { proof_height: 90, finalized: 80 }")
+    );
+    assert!(near_sdk::test_utils::get_logs().iter().any(|l| l.contains("MPC deposit proof invalid: NotFinalized")));
+
+    // A failed light client call has no reason to report
+    let outcome = deposit(&mut contract, Err(PromiseError::Failed));
+    assert_eq!(outcome, DepositOutcome { credited: false, reason: Some("MPC deposit proof invalid".to_string()) });
+    assert_eq!(contract.get_balance(user_alice(), "SOL".to_string()), u(0));
+}
+
+#[test]
+#[should_panic(expected = "Invalid Proof: MemoMismatch")]
+fn test_rejected_payment_proof_panics_with_reason() {
+    let (mut contract, mut context) = new_contract();
+    seed_twin_unescrowed_takes(&mut contract);
+    testing_env!(context.predecessor_account_id(solver_bob()).build());
+    let _ = submit_payment(&mut contract, 0, "pay-1").unwrap();
+    testing_env!(context.predecessor_account_id(orderbook_contract()).prepaid_gas(Gas::from_tgas(300)).build());
+    let _ = contract.on_proof_verified(
+        U64(0), [0u8; 32], None, "default/path".to_string(), ChainType::SOL, SOL_DEST.to_string(),
+        Orderbook::transition_memo(0, 0), ChainType::ETH, "pay-1".to_string(), rejected_with(VerifyError::MemoMismatch),
+    );
+}
+
+#[test]
+fn test_rejected_transition_logs_reason() {
+    let (mut contract, mut context) = new_contract();
+    setup_twin_settled_subs(&mut contract, &mut context);
+    submit_transition(&mut contract, &mut context, U64(0), "0xsame");
+    let result = contract.on_transition_verified(
+        U64(0), "0xsame".to_string(), Ok(VerifyOutcome::Invalid(VerifyError::RecipientMismatch)),
+    );
+    assert_eq!(result, "TransitionVerifyFailed");
+    assert!(near_sdk::test_utils::get_logs()
+        .contains(&"TRANSITION_VERIFY_FAILED:sub_intent_id=0,reason=RecipientMismatch".to_string()));
+    assert_eq!(contract.get_sub_intent(U64(0)).unwrap().status, SubIntentStatus::Settled);
+
+    submit_transition(&mut contract, &mut context, U64(0), "0xsame");
+    contract.on_transition_verified(U64(0), "0xsame".to_string(), Err(PromiseError::Failed));
+    assert!(near_sdk::test_utils::get_logs()
+        .contains(&"TRANSITION_VERIFY_FAILED:sub_intent_id=0,reason=PromiseFailed".to_string()));
 }
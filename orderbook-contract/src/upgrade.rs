@@ -0,0 +1,67 @@
+//! Owner-driven code upgrades behind a timelock. The owner first stages the
+//! sha256 of the new wasm; once the delay has passed, `apply_upgrade` with
+//! the matching code redeploys this account and runs `migrate`.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StagedUpgrade {
+    /// Hex-encoded sha256 of the wasm `apply_upgrade` will accept.
+    pub code_hash: String,
+    /// Block height from which `apply_upgrade` may run.
+    pub effective_height: u64,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Stage the code to upgrade to. The delay is `config_delay_blocks`, but
+    /// never less than `MIN_UPGRADE_DELAY_BLOCKS`. A newer stage replaces
+    /// the previous one and restarts the delay.
+    pub fn stage_upgrade(&mut self, code_hash: String) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can upgrade");
+        let hash = hex::decode(&code_hash).unwrap_or_else(|_| env::panic_str("Invalid code hash: not hex"));
+        assert_eq!(hash.len(), 32, "Invalid code hash: expected 32 bytes");
+
+        let effective_height = env::block_height() + self.config_delay_blocks.max(MIN_UPGRADE_DELAY_BLOCKS);
+        env::log_str(&format!(
+            "UPGRADE_STAGED:code_hash={},effective_height={}",
+            code_hash.to_lowercase(),
+            effective_height
+        ));
+        self.staged_upgrade = Some(StagedUpgrade {
+            code_hash: code_hash.to_lowercase(),
+            effective_height,
+        });
+    }
+
+    pub fn cancel_upgrade(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can upgrade");
+        let staged = self.staged_upgrade.take().expect("No staged upgrade");
+        env::log_str(&format!("UPGRADE_CANCELLED:code_hash={}", staged.code_hash));
+    }
+
+    /// Deploy the staged code and call `migrate` on it. `code` is borsh
+    /// encoded so the wasm is not inflated into a JSON byte array.
+    pub fn apply_upgrade(&mut self, #[serializer(borsh)] code: Vec<u8>) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can upgrade");
+        let staged = self.staged_upgrade.clone().expect("No staged upgrade");
+        assert!(
+            env::block_height() >= staged.effective_height,
+            "Upgrade timelock not reached: effective at block {}",
+            staged.effective_height
+        );
+        let code_hash = hex::encode(env::sha256(&code));
+        assert_eq!(code_hash, staged.code_hash, "Code hash does not match staged upgrade");
+
+        self.staged_upgrade = None;
+        env::log_str(&format!("UPGRADE_APPLIED:code_hash={}", code_hash));
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_string(), vec![], NearToken::from_yoctonear(0), Gas::from_tgas(100))
+    }
+
+    pub fn get_staged_upgrade(&self) -> Option<StagedUpgrade> {
+        self.staged_upgrade.clone()
+    }
+}
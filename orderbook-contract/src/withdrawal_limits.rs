@@ -0,0 +1,103 @@
+//! Per-asset withdrawal caps. Each user may withdraw at most the asset's
+//! limit per `WITHDRAWAL_WINDOW_NS`, counted from their first withdrawal
+//! after the previous window ran out. A withdrawal refunded after a failed
+//! signature gives its amount back if its window is still the current one.
+
+use crate::*;
+
+/// Length of a withdrawal limit window: 24 hours.
+pub const WITHDRAWAL_WINDOW_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WindowUsage {
+    /// Block timestamp (ns) of the first withdrawal counted in the window.
+    pub window_start: u64,
+    pub used: u128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawalQuota {
+    pub limit: U128,
+    pub remaining: U128,
+    /// When the current window ends and `remaining` goes back to `limit`;
+    /// `None` if no window is running.
+    pub resets_at: Option<u64>,
+}
+
+#[near_bindgen]
+impl Orderbook {
+    /// Cap what each user may withdraw of `asset` per window; `None`
+    /// removes the cap. Usage already counted in running windows is kept.
+    pub fn set_withdrawal_limit(&mut self, asset: String, limit: Option<U128>) {
+        self.schedule_config_change(ConfigChange::WithdrawalLimit(asset.to_uppercase(), limit));
+    }
+
+    pub fn get_withdrawal_limit(&self, asset: String) -> Option<U128> {
+        self.withdrawal_limits.get(&asset.to_uppercase()).map(U128)
+    }
+
+    /// `None` if `asset` has no withdrawal limit.
+    pub fn get_withdrawal_quota(&self, user: AccountId, asset: String) -> Option<WithdrawalQuota> {
+        let asset = asset.to_uppercase();
+        let limit = self.withdrawal_limits.get(&asset)?;
+        let usage = self.current_window(&user, &asset);
+        Some(WithdrawalQuota {
+            limit: U128(limit),
+            remaining: U128(limit.saturating_sub(usage.as_ref().map_or(0, |u| u.used))),
+            resets_at: usage.map(|u| u.window_start + WITHDRAWAL_WINDOW_NS),
+        })
+    }
+}
+
+impl Orderbook {
+    /// Usage of `user`'s window for `asset`, if one is still running.
+    fn current_window(&self, user: &AccountId, asset: &str) -> Option<WindowUsage> {
+        let now = env::block_timestamp();
+        self.withdrawal_usage
+            .get(&(user.clone(), asset.to_string()))
+            .filter(|usage| now < usage.window_start + WITHDRAWAL_WINDOW_NS)
+    }
+
+    /// Count `amount` against `user`'s limit for `asset`, starting a new
+    /// window if none is running.
+    pub(crate) fn consume_withdrawal_quota(
+        &mut self,
+        user: &AccountId,
+        asset: &str,
+        amount: u128,
+    ) -> Result<(), OrderbookError> {
+        let Some(limit) = self.withdrawal_limits.get(&asset.to_string()) else {
+            return Ok(());
+        };
+        let mut usage = self.current_window(user, asset).unwrap_or(WindowUsage {
+            window_start: env::block_timestamp(),
+            used: 0,
+        });
+        let remaining = limit.saturating_sub(usage.used);
+        if amount > remaining {
+            return Err(OrderbookError::WithdrawalLimitExceeded {
+                asset: asset.to_string(),
+                remaining,
+                resets_at: usage.window_start + WITHDRAWAL_WINDOW_NS,
+            });
+        }
+        usage.used += amount;
+        self.withdrawal_usage.insert(&(user.clone(), asset.to_string()), &usage);
+        Ok(())
+    }
+
+    /// Give back the quota of a refunded withdrawal requested at
+    /// `requested_at`, unless its window has already ended.
+    pub(crate) fn release_withdrawal_quota(&mut self, user: &AccountId, asset: &str, amount: u128, requested_at: u64) {
+        let Some(mut usage) = self.current_window(user, asset) else {
+            return;
+        };
+        if requested_at < usage.window_start {
+            return;
+        }
+        usage.used = usage.used.saturating_sub(amount);
+        self.withdrawal_usage.insert(&(user.clone(), asset.to_string()), &usage);
+    }
+}
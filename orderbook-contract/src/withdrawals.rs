@@ -0,0 +1,111 @@
+//! Durable withdrawal history. `pending_withdrawals` only holds requests
+//! still waiting for MPC; every withdrawal also gets a `WithdrawalRecord`
+//! that outlives the signature or refund, so its outcome stays queryable
+//! until `cleanup` removes it after the retention period.
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum WithdrawalStatus {
+    /// Debited and waiting for the MPC signature.
+    PendingSign,
+    /// Signed; the signature is kept under `SignTarget::Withdrawal(id)`.
+    Signed,
+    /// MPC failed and the amount was credited back.
+    Refunded,
+    /// Withdrawn before it was signed. No entry point cancels withdrawals
+    /// yet, so no record is in this state.
+    Cancelled,
+}
+
+impl WithdrawalStatus {
+    pub fn is_final(&self) -> bool {
+        *self != WithdrawalStatus::PendingSign
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawalRecord {
+    #[serde(with = "crate::u64_json")]
+    pub id: u64,
+    pub user: AccountId,
+    pub asset: String,
+    pub amount: U128,
+    /// ETH (wei) charged for the transaction's gas.
+    pub gas_fee: U128,
+    pub destination: String,
+    pub chain_type: ChainType,
+    pub status: WithdrawalStatus,
+    /// Hex of the hash sent to MPC; with `Signed`, the signature is at
+    /// `get_signature(SignTarget::Withdrawal(id))`.
+    pub payload: String,
+    /// Block timestamps (ns) of the request and of the signature or refund.
+    pub requested_at: u64,
+    pub closed_at: Option<u64>,
+}
+
+impl WithdrawalRecord {
+    pub(crate) fn pending(id: u64, wd: &PendingWithdrawal) -> Self {
+        WithdrawalRecord {
+            id,
+            user: wd.user.clone(),
+            asset: wd.asset.clone(),
+            amount: U128(wd.amount),
+            gas_fee: U128(wd.gas_fee),
+            destination: wd.destination.clone(),
+            chain_type: wd.chain_type.clone(),
+            status: WithdrawalStatus::PendingSign,
+            payload: wd.payload.clone(),
+            requested_at: wd.requested_at,
+            closed_at: None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Orderbook {
+    pub fn get_withdrawal(&self, withdrawal_id: U64) -> Option<WithdrawalRecord> {
+        self.withdrawals.get(&(withdrawal_id.0))
+    }
+
+    /// Nonce `user`'s next ETH withdrawal transaction must carry.
+    pub fn get_withdrawal_nonce(&self, user: AccountId) -> U64 {
+        U64(self.withdrawal_nonces.get(&user).unwrap_or(0))
+    }
+
+    /// A user's withdrawals, oldest first. Records removed by `cleanup` are
+    /// skipped, so a page may hold fewer than `limit`.
+    pub fn get_withdrawals_by_user(&self, user: AccountId, from_index: U128, limit: u64) -> Vec<WithdrawalRecord> {
+        let Some(ids) = self.withdrawals_by_user.get(&user) else {
+            return vec![];
+        };
+        let from_index = from_index.0 as u64;
+        (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
+            .filter_map(|index| self.withdrawals.get(&ids.get(index).unwrap()))
+            .collect()
+    }
+}
+
+impl Orderbook {
+    /// Store the record of a withdrawal just queued as `wd`.
+    pub(crate) fn record_withdrawal(&mut self, id: u64, wd: &PendingWithdrawal) {
+        self.withdrawals.insert(&id, &WithdrawalRecord::pending(id, wd));
+        let mut ids = self
+            .withdrawals_by_user
+            .get(&wd.user)
+            .unwrap_or_else(|| Vector::new(format!("J{}", wd.user).as_bytes()));
+        ids.push(&id);
+        self.withdrawals_by_user.insert(&wd.user, &ids);
+    }
+
+    /// Move a withdrawal's record to the final `status`.
+    pub(crate) fn close_withdrawal(&mut self, id: u64, status: WithdrawalStatus) {
+        if let Some(mut record) = self.withdrawals.get(&id) {
+            record.status = status;
+            record.closed_at = Some(env::block_timestamp());
+            self.withdrawals.insert(&id, &record);
+        }
+    }
+}
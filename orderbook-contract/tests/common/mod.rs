@@ -0,0 +1,155 @@
+//! In-process network shared by the cross-contract tests.
+//!
+//! Each contract keeps its own storage, swapped in for the call being made,
+//! and each cross-contract call is replayed from the receipts the previous
+//! call created, with its JSON arguments.
+
+use std::collections::HashMap;
+
+use near_sdk::mock::MockAction;
+use near_sdk::serde::de::DeserializeOwned;
+use near_sdk::serde_json::{self, Value};
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{env, AccountId, Gas, NearToken, PromiseResult};
+
+/// A function call one contract made on another.
+pub struct Call {
+    pub receiver: AccountId,
+    pub method: String,
+    pub args: Value,
+}
+
+impl Call {
+    pub fn arg<T: DeserializeOwned>(&self, name: &str) -> T {
+        serde_json::from_value(self.args[name].clone()).unwrap()
+    }
+}
+
+/// Per-account storage, so the contracts don't see each other's keys.
+#[derive(Default)]
+pub struct Network {
+    storage: HashMap<AccountId, HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Network {
+    /// Runs `call` on `account` as called by `predecessor` with 1 yoctoNEAR
+    /// attached, with the results of the promises it is a callback of.
+    /// Returns its result and the calls it made.
+    pub fn run<R>(
+        &mut self,
+        account: AccountId,
+        predecessor: AccountId,
+        promise_results: Vec<PromiseResult>,
+        call: impl FnOnce() -> R,
+    ) -> (R, Vec<Call>) {
+        self.run_paying(account, predecessor, NearToken::from_yoctonear(1), promise_results, call)
+    }
+
+    /// `run` with `deposit` attached.
+    pub fn run_paying<R>(
+        &mut self,
+        account: AccountId,
+        predecessor: AccountId,
+        deposit: NearToken,
+        promise_results: Vec<PromiseResult>,
+        call: impl FnOnce() -> R,
+    ) -> (R, Vec<Call>) {
+        let context = VMContextBuilder::new()
+            .current_account_id(account.clone())
+            .signer_account_id(predecessor.clone())
+            .predecessor_account_id(predecessor)
+            .attached_deposit(deposit)
+            .prepaid_gas(Gas::from_tgas(300))
+            .build();
+        env::set_blockchain_interface(near_sdk::MockedBlockchain::new(
+            context,
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            promise_results,
+            self.storage.remove(&account).unwrap_or_default(),
+            Default::default(),
+            None,
+        ));
+        let result = call();
+        let calls = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                receipt.actions.into_iter().filter_map(move |action| match action {
+                    MockAction::FunctionCallWeight { method_name, args, .. } => Some(Call {
+                        receiver: receipt.receiver_id.clone(),
+                        method: String::from_utf8(method_name).unwrap(),
+                        args: serde_json::from_slice(&args).unwrap(),
+                    }),
+                    _ => None,
+                })
+            })
+            .collect();
+        let storage = near_sdk::mock::with_mocked_blockchain(|blockchain| blockchain.take_storage());
+        self.storage.insert(account, storage);
+        (result, calls)
+    }
+}
+
+pub fn returned<T: near_sdk::serde::Serialize>(value: &T) -> Vec<PromiseResult> {
+    vec![PromiseResult::Successful(serde_json::to_vec(value).unwrap())]
+}
+
+pub fn find<'a>(calls: &'a [Call], method: &str) -> &'a Call {
+    calls.iter().find(|call| call.method == method).unwrap_or_else(|| panic!("No {} call", method))
+}
+
+fn enc_len(offset: u8, len: usize) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    [vec![offset + 55 + bytes.len() as u8], bytes].concat()
+}
+
+pub fn enc_bytes(bytes: &[u8]) -> Vec<u8> {
+    match bytes {
+        [b] if *b < 0x80 => vec![*b],
+        _ => [enc_len(0x80, bytes.len()), bytes.to_vec()].concat(),
+    }
+}
+
+pub fn enc_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    [enc_len(0xc0, payload.len()), payload].concat()
+}
+
+pub fn enc_uint(value: u128) -> Vec<u8> {
+    let bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    enc_bytes(&bytes)
+}
+
+/// `address` as a 32-byte ABI word.
+pub fn word(address: &str) -> Vec<u8> {
+    [vec![0; 12], hex::decode(&address[2..]).unwrap()].concat()
+}
+
+/// A `Transfer` of `amount` of `token` to `to`.
+pub fn transfer_log(token: &str, to: &str, amount: u128) -> Vec<u8> {
+    let topics = [
+        enc_bytes(&env::keccak256(b"Transfer(address,address,uint256)")),
+        enc_bytes(&word("0x3333333333333333333333333333333333333333")),
+        enc_bytes(&word(to)),
+    ];
+    let amount = [vec![0; 16], amount.to_be_bytes().to_vec()].concat();
+    enc_list(&[enc_bytes(&hex::decode(&token[2..]).unwrap()), enc_list(&topics), enc_bytes(&amount)])
+}
+
+/// A successful typed receipt with `logs`.
+pub fn receipt(logs: &[Vec<u8>]) -> Vec<u8> {
+    [vec![0x02], enc_list(&[enc_uint(1), enc_uint(21_000), enc_bytes(&[0; 256]), enc_list(logs)])].concat()
+}
+
+/// A header at `height` with the given trie roots.
+pub fn header(height: u64, transactions_root: &[u8], receipts_root: &[u8]) -> Vec<u8> {
+    let mut fields = vec![enc_bytes(&[0; 32]); 8];
+    fields[4] = enc_bytes(transactions_root);
+    fields[5] = enc_bytes(receipts_root);
+    fields.push(enc_uint(height as u128));
+    fields.extend((0..7).map(|_| enc_uint(1)));
+    enc_list(&fields)
+}
@@ -0,0 +1,199 @@
+//! An ETH deposit proven end to end: the orderbook asks the light client,
+//! which hands the log to the mock prover, and the answers travel back
+//! through each contract's callback. The three contracts run in-process,
+//! on the `common` network.
+
+mod common;
+
+use common::{find, receipt, returned, transfer_log, Network};
+use light_client::{EthLogProof, LightClient, PaymentProofV1};
+use mock_prover::MockProver;
+use near_sdk::json_types::U128;
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId, PromiseOrValue};
+use orderbook_contract::{ChainType, DepositOutcome, Orderbook};
+
+const HEIGHT: u64 = 1_000;
+const RECIPIENT: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+const TOKEN: &str = "0x2222222222222222222222222222222222222222";
+
+fn orderbook() -> AccountId {
+    "orderbook.near".parse().unwrap()
+}
+
+fn light_client() -> AccountId {
+    "light-client.near".parse().unwrap()
+}
+
+fn prover() -> AccountId {
+    "prover.near".parse().unwrap()
+}
+
+fn alice() -> AccountId {
+    "alice.near".parse().unwrap()
+}
+
+/// A `Transfer` of 42 of `TOKEN` to `RECIPIENT`.
+fn usdc_transfer() -> Vec<u8> {
+    transfer_log(TOKEN, RECIPIENT, 42)
+}
+
+fn header() -> Vec<u8> {
+    common::header(HEIGHT, &[0; 32], &[0; 32])
+}
+
+/// The log as the 0th of receipt 1. The mock prover checks nothing, so
+/// there is no trie proof.
+fn log_proof() -> EthLogProof {
+    EthLogProof {
+        log_index: 0,
+        log_entry_data: usdc_transfer(),
+        receipt_index: 1,
+        receipt_data: receipt(&[usdc_transfer()]),
+        header_data: header(),
+        proof: vec![],
+    }
+}
+
+/// The tx hash an `EthLogProof` is presented under.
+fn log_id(receipt_index: u64, log_index: u64) -> String {
+    let preimage = [env::keccak256(header()), receipt_index.to_be_bytes().to_vec(), log_index.to_be_bytes().to_vec()].concat();
+    format!("0x{}", hex::encode(env::keccak256(&preimage)))
+}
+
+/// The light client finalized at `HEIGHT` with a prover set, and an
+/// orderbook taking USDC deposits from alice at `RECIPIENT`.
+fn deploy(network: &mut Network) -> (Orderbook, LightClient, MockProver) {
+    let (client, _) = network.run(light_client(), light_client(), vec![], || {
+        let mut client = LightClient::new(light_client());
+        client.add_updater(light_client());
+        client.report_finalized_height(light_client::ChainType::ETH, HEIGHT, hex::encode(env::keccak256(header())));
+        client.set_eth_token("USDC".to_string(), Some(TOKEN.to_string()));
+        client.set_eth_prover(Some(prover()));
+        client.add_consumer(orderbook());
+        client
+    });
+    let (contract, _) = network.run(orderbook(), orderbook(), vec![], || {
+        let mut contract = Orderbook::new("mpc.near".parse().unwrap(), light_client());
+        contract.register_asset("USDC".to_string(), ChainType::ETH, 6, U128(0));
+        contract.set_asset_token_contract("USDC".to_string(), Some(TOKEN.to_string()));
+        contract.register_deposit_address(alice(), ChainType::ETH, RECIPIENT.to_string());
+        contract
+    });
+    (contract, client, MockProver::default())
+}
+
+/// Alice proves a deposit of 42 USDC with `tx_hash`, following each call
+/// through. Returns the orderbook's outcome.
+fn deposit(network: &mut Network, contract: &mut Orderbook, client: &mut LightClient, prover_contract: &MockProver, tx_hash: &str) -> DepositOutcome {
+    let memo = format!("mpc:deposit:{}:USDC", alice());
+    let (_, calls) = network.run(orderbook(), alice(), vec![], || {
+        let _ = contract.verify_mpc_deposit(
+            alice(),
+            ChainType::ETH,
+            "USDC".to_string(),
+            U128(42),
+            RECIPIENT.to_string(),
+            memo.clone(),
+            light_client::encode_borsh(&PaymentProofV1::EthLog(log_proof())),
+            tx_hash.to_string(),
+        );
+    });
+    let consume = find(&calls, "consume_payment_proof");
+    assert_eq!(consume.receiver, light_client());
+    let credit = find(&calls, "on_mpc_deposit_verified");
+
+    // A promise is scheduled once dropped, inside the call.
+    let (verdict, calls) = network.run(light_client(), orderbook(), vec![], || {
+        match client.consume_payment_proof(
+            consume.arg("chain_type"),
+            consume.arg("proof_data"),
+            consume.arg("expected_recipient"),
+            consume.arg("expected_asset"),
+            consume.arg("expected_amount"),
+            consume.arg("expected_memo"),
+            consume.arg("expected_tx_hash"),
+            consume.arg("expected_asset_kind"),
+            consume.arg("min_confirmations"),
+        ) {
+            PromiseOrValue::Value(verification) => Some(verification),
+            PromiseOrValue::Promise(_) => None,
+        }
+    });
+    let verification = match verdict {
+        Some(verification) => verification,
+        None => {
+            let verify = find(&calls, "verify_log_entry");
+            assert_eq!(verify.receiver, prover());
+            let (proven, _) = network.run(prover(), light_client(), vec![], || {
+                prover_contract.verify_log_entry(
+                    verify.arg("log_index"),
+                    verify.arg("log_entry_data"),
+                    verify.arg("receipt_index"),
+                    verify.arg("receipt_data"),
+                    verify.arg("header_data"),
+                    verify.arg("proof"),
+                    verify.arg("skip_bridge_call"),
+                )
+            });
+            let consumed = find(&calls, "on_eth_log_consumed");
+            network
+                .run(light_client(), light_client(), returned(&proven), || {
+                    client.on_eth_log_consumed(
+                        Ok(proven),
+                        consumed.arg("block_height"),
+                        consumed.arg("finalized_height"),
+                        consumed.arg("key"),
+                        consumed.arg("tx_hash"),
+                        consumed.arg("min_confirmations"),
+                        consumed.arg("consumer"),
+                    )
+                })
+                .0
+        }
+    };
+
+    // Handed over as JSON, from the light client's types to the orderbook's.
+    let verification = serde_json::from_value(serde_json::to_value(&verification).unwrap()).unwrap();
+    network
+        .run(orderbook(), orderbook(), returned(&verification), || {
+            contract.on_mpc_deposit_verified(
+                credit.arg("user"),
+                credit.arg("chain_type"),
+                credit.arg("asset"),
+                credit.arg("amount"),
+                credit.arg("recipient"),
+                credit.arg("memo"),
+                credit.arg("tx_hash"),
+                Ok(verification),
+            )
+        })
+        .0
+}
+
+#[test]
+fn test_eth_deposit_is_proven_through_the_prover() {
+    let mut network = Network::default();
+    let (mut contract, mut client, prover_contract) = deploy(&mut network);
+    let outcome = deposit(&mut network, &mut contract, &mut client, &prover_contract, &log_id(1, 0));
+    assert_eq!(outcome, DepositOutcome { credited: true, reason: None });
+
+    let (balance, _) = network.run(orderbook(), alice(), vec![], || contract.get_balance(alice(), "USDC".to_string()));
+    assert_eq!(balance, U128(42));
+    let (consumed, _) = network.run(light_client(), alice(), vec![], || {
+        client.get_consumed_proof(light_client::ChainType::ETH, log_id(1, 0), String::new())
+    });
+    assert_eq!(consumed, Some(HEIGHT));
+}
+
+#[test]
+fn test_eth_deposit_under_another_log_id_never_reaches_the_prover() {
+    let mut network = Network::default();
+    let (mut contract, mut client, prover_contract) = deploy(&mut network);
+    let outcome = deposit(&mut network, &mut contract, &mut client, &prover_contract, &log_id(1, 1));
+    assert!(!outcome.credited);
+    assert!(outcome.reason.unwrap().contains("TxHashMismatch"));
+
+    let (balance, _) = network.run(orderbook(), alice(), vec![], || contract.get_balance(alice(), "USDC".to_string()));
+    assert_eq!(balance, U128(0));
+}
@@ -0,0 +1,265 @@
+//! ETH transitions proven end to end: the transactions the orderbook checks
+//! when a batch is matched are signed, mined, and proven to the light
+//! client against the sub-intents' expectations. The contracts run
+//! in-process, on the `common` network.
+
+mod common;
+
+use common::{enc_bytes, enc_list, enc_uint, find, receipt, returned, transfer_log, Network};
+use light_client::{AssetKind, EthPaymentProof, LightClient, VerifyOutcome};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, AccountId, NearToken, PromiseOrValue};
+use orderbook_contract::{
+    AffinePoint, ChainType, MatchParams, Orderbook, Scalar, SignResult, SignTarget, SignatureScheme, SubIntentStatus,
+    DEFAULT_ETH_CHAIN_ID,
+};
+
+const HEIGHT: u64 = 1_000;
+const TOKEN: &str = "0x2222222222222222222222222222222222222222";
+const ALICE_ETH: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+const BOB_ETH: &str = "0x00000000000000000000000000000000000000bb";
+const TREASURY_PATH: &str = "treasury/eth";
+
+fn orderbook() -> AccountId {
+    "orderbook.near".parse().unwrap()
+}
+
+fn light_client() -> AccountId {
+    "light-client.near".parse().unwrap()
+}
+
+fn alice() -> AccountId {
+    "alice.near".parse().unwrap()
+}
+
+fn bob() -> AccountId {
+    "bob.near".parse().unwrap()
+}
+
+fn solver() -> AccountId {
+    "solver.near".parse().unwrap()
+}
+
+/// An EIP-1559 transaction on the orderbook's chain, with its signature
+/// fields if `signed`.
+fn eip1559_tx(to: &str, value: u128, data: &[u8], signed: bool) -> Vec<u8> {
+    let mut fields = vec![
+        enc_uint(DEFAULT_ETH_CHAIN_ID as u128),
+        enc_uint(0),
+        enc_uint(1_000_000_000),
+        enc_uint(20_000_000_000),
+        enc_uint(60_000),
+        enc_bytes(&hex::decode(&to[2..]).unwrap()),
+        enc_uint(value),
+        enc_bytes(data),
+        enc_list(&[]),
+    ];
+    if signed {
+        fields.extend([enc_uint(1), enc_bytes(&[7; 32]), enc_bytes(&[9; 32])]);
+    }
+    [vec![0x02], enc_list(&fields)].concat()
+}
+
+/// `transfer(to, amount)` calldata followed by `memo`.
+fn transfer_calldata(to: &str, amount: u128, memo: &str) -> Vec<u8> {
+    let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+    data.extend(common::word(to));
+    data.extend([0; 16]);
+    data.extend(amount.to_be_bytes());
+    data.extend(memo.as_bytes());
+    data
+}
+
+/// Root of a trie holding `value` alone, under the key of index 0, and its
+/// proof.
+fn single_leaf_trie(value: &[u8]) -> ([u8; 32], Vec<String>) {
+    // rlp(0) = 0x80: nibbles 8, 0, an even-length leaf path
+    let leaf = enc_list(&[enc_bytes(&[0x20, 0x80]), enc_bytes(value)]);
+    (env::keccak256_array(&leaf), vec![hex::encode(leaf)])
+}
+
+/// The transition of one sub-intent, as the solver built it.
+struct Transition {
+    unsigned: Vec<u8>,
+    signed: Vec<u8>,
+    logs: Vec<Vec<u8>>,
+    asset_kind: AssetKind,
+}
+
+impl Transition {
+    fn new(to: &str, value: u128, data: &[u8], logs: Vec<Vec<u8>>, asset_kind: AssetKind) -> Self {
+        Transition {
+            unsigned: eip1559_tx(to, value, data, false),
+            signed: eip1559_tx(to, value, data, true),
+            logs,
+            asset_kind,
+        }
+    }
+
+    fn tx_hash(&self) -> String {
+        format!("0x{}", hex::encode(env::keccak256(&self.signed)))
+    }
+
+    /// The block at `height` holding only this transaction, and its proof.
+    fn mined(&self, height: u64) -> (Vec<u8>, EthPaymentProof) {
+        let (transactions_root, transaction_proof) = single_leaf_trie(&self.signed);
+        let receipt = receipt(&self.logs);
+        let (receipts_root, receipt_proof) = single_leaf_trie(&receipt);
+        let header = common::header(height, &transactions_root, &receipts_root);
+        let proof = EthPaymentProof {
+            header: hex::encode(&header),
+            receipt_index: 0,
+            receipt: hex::encode(&receipt),
+            receipt_proof,
+            transaction: hex::encode(&self.signed),
+            transaction_proof,
+            asset_kind: self.asset_kind.clone(),
+        };
+        (header, proof)
+    }
+}
+
+fn mock_sig() -> SignResult {
+    SignResult::Secp256k1 {
+        big_r: AffinePoint { affine_point: "mock_r".to_string() },
+        s: Scalar { scalar: "mock_s".to_string() },
+        recovery_id: 1,
+    }
+}
+
+/// A light client that knows the USDC token, and an orderbook where alice
+/// offers 42 USDC for 500 wei and bob the reverse, each paid at their
+/// registered ETH address.
+fn deploy(network: &mut Network) -> (Orderbook, LightClient) {
+    let (client, _) = network.run(light_client(), light_client(), vec![], || {
+        let mut client = LightClient::new(light_client());
+        client.add_updater(light_client());
+        client.set_eth_token("USDC".to_string(), Some(TOKEN.to_string()));
+        client
+    });
+    let (mut contract, _) = network.run(orderbook(), orderbook(), vec![], || {
+        let mut contract = Orderbook::new("mpc.near".parse().unwrap(), light_client());
+        contract.register_asset("ETH".to_string(), ChainType::ETH, 18, U128(0));
+        contract.register_asset("USDC".to_string(), ChainType::ETH, 6, U128(0));
+        contract.set_asset_token_contract("USDC".to_string(), Some(TOKEN.to_string()));
+        contract.set_treasury_path(ChainType::ETH, Some(TREASURY_PATH.to_string()));
+        contract
+    });
+    for (maker, address, src, src_amount, dst, dst_amount) in
+        [(alice(), ALICE_ETH, "USDC", 42, "ETH", 500), (bob(), BOB_ETH, "ETH", 500, "USDC", 42)]
+    {
+        network.run_paying(orderbook(), maker.clone(), NearToken::from_near(1), vec![], || {
+            contract.storage_deposit(None);
+        });
+        network.run(orderbook(), maker.clone(), vec![], || {
+            contract.set_receive_address(ChainType::ETH, Some(address.to_string()));
+        });
+        network.run(orderbook(), orderbook(), vec![], || {
+            contract.deposit_for(maker.clone(), src.to_string(), U128(src_amount));
+        });
+        network.run(orderbook(), maker, vec![], || {
+            contract
+                .make_intent(src.to_string(), U128(src_amount), dst.to_string(), U128(dst_amount), None, None, None, None, None, None)
+                .unwrap()
+        });
+    }
+    (contract, client)
+}
+
+/// The solver matches the two intents with `transitions` (alice's first),
+/// and MPC signs both. Returns the sub-intent ids.
+fn match_and_sign(network: &mut Network, contract: &mut Orderbook, transitions: &[Transition]) -> Vec<U64> {
+    let legs = [(0, 42, 500, "USDC", ALICE_ETH), (1, 500, 42, "ETH", BOB_ETH)];
+    let matches = legs
+        .iter()
+        .zip(transitions)
+        .map(|((intent_id, fill, get, asset, recipient), transition)| MatchParams {
+            intent_id: U64(*intent_id),
+            fill_amount: U128(*fill),
+            get_amount: U128(*get),
+            payload: env::keccak256_array(&transition.unsigned),
+            path: TREASURY_PATH.to_string(),
+            transition_chain_type: ChainType::ETH,
+            scheme: SignatureScheme::Secp256k1,
+            recipient: recipient.to_string(),
+            transition_asset: asset.to_string(),
+            unsigned_tx: Some(transition.unsigned.clone()),
+        })
+        .collect();
+    let (sub_ids, calls) = network.run_paying(orderbook(), solver(), NearToken::from_near(1), vec![], || {
+        contract.batch_match_intents(matches).unwrap()
+    });
+    for (sub_id, transition) in sub_ids.iter().zip(transitions) {
+        assert!(calls.iter().any(|call| call.method == "sign"));
+        network.run(orderbook(), orderbook(), returned(&mock_sig()), || {
+            contract.on_signed(
+                SignTarget::SubIntent(sub_id.0),
+                ChainType::ETH,
+                env::keccak256(&transition.unsigned),
+                TREASURY_PATH.to_string(),
+                Ok(mock_sig()),
+            )
+        });
+    }
+    sub_ids
+}
+
+/// `transition` is mined at `height`, which the light client finalizes,
+/// and proven for `sub_id`. Returns the light client's verdict.
+fn prove(network: &mut Network, contract: &mut Orderbook, client: &mut LightClient, sub_id: U64, transition: &Transition, height: u64) -> VerifyOutcome {
+    let (header, proof) = transition.mined(height);
+    network.run(light_client(), light_client(), vec![], || {
+        client.report_finalized_height(light_client::ChainType::ETH, height, hex::encode(env::keccak256(&header)));
+    });
+    let (_, calls) = network.run(orderbook(), solver(), vec![], || {
+        let _ = contract.verify_transition_completion(sub_id, light_client::encode_json(&proof), transition.tx_hash());
+    });
+    let verify = find(&calls, "verify_transition_proof");
+    assert_eq!(verify.receiver, light_client());
+    let (verdict, _) = network.run(light_client(), orderbook(), vec![], || {
+        match client.verify_transition_proof(
+            verify.arg("chain_type"),
+            verify.arg("proof_data"),
+            verify.arg("expected_recipient"),
+            verify.arg("expected_asset"),
+            verify.arg("expected_amount"),
+            verify.arg("expected_memo"),
+            verify.arg("expected_tx_hash"),
+        ) {
+            PromiseOrValue::Value(outcome) => outcome,
+            PromiseOrValue::Promise(_) => panic!("No prover is set"),
+        }
+    });
+    let callback = find(&calls, "on_transition_verified");
+    // Handed over as JSON, from the light client's types to the orderbook's.
+    let outcome = near_sdk::serde_json::from_value(near_sdk::serde_json::to_value(verdict).unwrap()).unwrap();
+    network.run(orderbook(), orderbook(), returned(&verdict), || {
+        contract.on_transition_verified(callback.arg("sub_intent_id"), callback.arg("tx_hash"), Ok(outcome))
+    });
+    verdict
+}
+
+#[test]
+fn test_checked_transitions_pass_the_light_client() {
+    let mut network = Network::default();
+    let (mut contract, mut client) = deploy(&mut network);
+    let (memos, _) = network.run(orderbook(), solver(), vec![], || contract.get_next_batch_memos(2));
+    let transitions = [
+        Transition::new(
+            TOKEN,
+            0,
+            &transfer_calldata(ALICE_ETH, 42, &memos[0]),
+            vec![transfer_log(TOKEN, ALICE_ETH, 42)],
+            AssetKind::Erc20 { contract: TOKEN.to_string() },
+        ),
+        Transition::new(BOB_ETH, 500, memos[1].as_bytes(), vec![], AssetKind::Native),
+    ];
+    let sub_ids = match_and_sign(&mut network, &mut contract, &transitions);
+
+    for (i, (sub_id, transition)) in sub_ids.iter().zip(&transitions).enumerate() {
+        let verdict = prove(&mut network, &mut contract, &mut client, *sub_id, transition, HEIGHT + i as u64);
+        assert_eq!(verdict, VerifyOutcome::Valid);
+        let (sub, _) = network.run(orderbook(), solver(), vec![], || contract.get_sub_intent(*sub_id).unwrap());
+        assert_eq!(sub.status, SubIntentStatus::Completed);
+    }
+}
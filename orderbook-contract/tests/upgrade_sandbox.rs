@@ -0,0 +1,113 @@
+//! A full code upgrade on a local sandbox node: the baseline wasm is
+//! deployed and initialized, the new wasm is staged by hash, the timelock is
+//! waited out and `apply_upgrade` redeploys the account and runs `migrate`.
+//! State written before the upgrade is read back through the new code.
+//!
+//! Both wasms are built from this crate for `wasm32-unknown-unknown`; the
+//! baseline can be pointed at an older release build with
+//! `ORDERBOOK_BASELINE_WASM`. The test needs that target installed and
+//! downloads a sandbox node, so it is ignored by default.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use near_sdk::json_types::U128;
+use near_workspaces::types::NearToken;
+use orderbook_contract::{ChainType, StagedUpgrade, MIN_UPGRADE_DELAY_BLOCKS, STATE_VERSION};
+use serde_json::json;
+
+fn build_wasm() -> anyhow::Result<Vec<u8>> {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "-p", "orderbook-contract", "--release", "--target", "wasm32-unknown-unknown"])
+        .current_dir(&manifest_dir)
+        .status()?;
+    anyhow::ensure!(status.success(), "wasm build failed");
+    let wasm = manifest_dir.join("../target/wasm32-unknown-unknown/release/orderbook_contract.wasm");
+    Ok(std::fs::read(wasm)?)
+}
+
+#[tokio::test]
+#[ignore = "needs the wasm32-unknown-unknown target and a sandbox node"]
+async fn test_staged_upgrade_on_sandbox() -> anyhow::Result<()> {
+    let new_wasm = build_wasm()?;
+    let baseline_wasm = match std::env::var("ORDERBOOK_BASELINE_WASM") {
+        Ok(path) => std::fs::read(path)?,
+        Err(_) => new_wasm.clone(),
+    };
+
+    let worker = near_workspaces::sandbox().await?;
+    let contract = worker.dev_deploy(&baseline_wasm).await?;
+    let alice = worker.dev_create_account().await?;
+
+    contract
+        .call("new")
+        .args_json(json!({ "mpc_contract": "mpc.test.near", "light_client_contract": "light-client.test.near" }))
+        .transact()
+        .await?
+        .into_result()?;
+    contract
+        .call("register_asset")
+        .args_json(json!({ "symbol": "ETH", "chain_type": ChainType::ETH, "decimals": 18, "min_deposit": U128(1) }))
+        .transact()
+        .await?
+        .into_result()?;
+    alice
+        .call(contract.id(), "storage_deposit")
+        .args_json(json!({ "account_id": null }))
+        .deposit(NearToken::from_near(1))
+        .transact()
+        .await?
+        .into_result()?;
+    contract
+        .call("deposit_for")
+        .args_json(json!({ "user": alice.id(), "asset": "ETH", "amount": U128(500) }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Stage the new code, then apply it only once the timelock has passed
+    let code_hash = hex::encode(near_sdk::env::sha256(&new_wasm));
+    contract
+        .call("stage_upgrade")
+        .args_json(json!({ "code_hash": code_hash }))
+        .transact()
+        .await?
+        .into_result()?;
+    let staged: StagedUpgrade = contract.view("get_staged_upgrade").await?.json::<Option<_>>()?.unwrap();
+    assert_eq!(staged.code_hash, code_hash);
+
+    let early = contract
+        .call("apply_upgrade")
+        .args_borsh(new_wasm.clone())
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(early.is_failure(), "upgrade applied before the timelock");
+
+    worker.fast_forward(MIN_UPGRADE_DELAY_BLOCKS + 1).await?;
+    contract
+        .call("apply_upgrade")
+        .args_borsh(new_wasm.clone())
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let account = contract.view_account().await?;
+    assert_eq!(account.code_hash.0.to_vec(), near_sdk::env::sha256(&new_wasm));
+    let staged: Option<StagedUpgrade> = contract.view("get_staged_upgrade").await?.json()?;
+    assert_eq!(staged, None);
+    let version: u8 = contract.view("get_state_version").await?.json()?;
+    assert_eq!(version, STATE_VERSION);
+    let balance: U128 = contract
+        .view("get_balance")
+        .args_json(json!({ "user": alice.id(), "asset": "ETH" }))
+        .await?
+        .json()?;
+    assert_eq!(balance, U128(500));
+    let owner: String = contract.view("get_owner").await?.json()?;
+    assert_eq!(owner, contract.id().to_string());
+
+    Ok(())
+}